@@ -48,6 +48,10 @@ fn benchmark_do_query(c: &mut Criterion) {
         b.iter(|| execute_query("select file.name, tags from frontmatter_data(\"benches/bench/\") where \"example\" in tags", None, None, None))
     });
 
+    c.bench_function("query with repeated fields", |b| {
+        b.iter(|| execute_query("select file.name from frontmatter_data(\"benches/bench/\") where file.created > \"2020\" and file.created < \"2100\" and \"example\" in tags and tags != \"other\"", None, None, None))
+    });
+
     let dir = PATH_TO_FILES.to_string();
     c.bench_function("query finding", |b| {
         b.iter(|| fetch_code_snippets(&dir, "krafna".to_string()))
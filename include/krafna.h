@@ -0,0 +1,24 @@
+#ifndef KRAFNA_H
+#define KRAFNA_H
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* Executes `query` and returns the result as a JSON array of objects, or NULL on error (see
+ * krafna_last_error). If `dir` is not NULL, it overrides FROM with FRONTMATTER_DATA(dir).
+ * The result has to be released with krafna_free_string. */
+char *krafna_execute_query(const char *query, const char *dir);
+
+/* Error message of the last failed krafna_execute_query on this thread, or NULL.
+ * The result has to be released with krafna_free_string. */
+char *krafna_last_error(void);
+
+/* Releases a string returned by krafna. NULL is ignored. */
+void krafna_free_string(char *s);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* KRAFNA_H */
@@ -1,5 +1,8 @@
 pub mod libs;
 
 // Re-export important items at the crate root
+pub use libs::cancellation::CancellationToken;
+pub use libs::data_fetcher::SourceProvider;
+pub use libs::engine::{Krafna, KrafnaConfig};
 pub use libs::parser::Query;
 pub use libs::peekable_deque::PeekableDeque;
@@ -0,0 +1,475 @@
+//! Aggregates in SELECT, e.g. `SELECT status, COUNT(*), MEDIAN(words) ... GROUP BY status`. A
+//! query with aggregates or GROUP BY returns one row per group, with the GROUP BY fields and the
+//! aggregates as columns named by their query text (`COUNT(*)`, `MEDIAN(words)`).
+
+use std::fmt::Display;
+use std::str::FromStr;
+
+use indexmap::IndexMap;
+
+use crate::libs::data_fetcher::pod::Pod;
+use crate::libs::executor::{
+    execute_function, field_value_to_pod, get_field_value, ExecutionContext,
+};
+use crate::libs::lexer::{tokenize, Token, TokenKind};
+use crate::libs::parser::{FieldValue, GroupByField};
+use crate::libs::peekable_deque::PeekableDeque;
+use crate::libs::suggestion::with_suggestion;
+
+pub const AGGREGATE_FUNCTIONS: [&str; 8] = [
+    "COUNT",
+    "SUM",
+    "AVG",
+    "MIN",
+    "MAX",
+    "MEDIAN",
+    "PERCENTILE",
+    "STDDEV",
+];
+
+/// Aggregate over the rows of a group. Values that are NULL (or, for the numeric ones, not
+/// numbers) are left out, like in SQL.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Aggregate {
+    /// `COUNT(*)` counts rows, `COUNT(field)` rows where `field` is not NULL
+    Count(Option<String>),
+    Sum(String),
+    Avg(String),
+    Min(String),
+    Max(String),
+    Median(String),
+    /// `PERCENTILE(field, 90)`, interpolating between the two closest values
+    Percentile(String, f64),
+    /// Sample standard deviation
+    Stddev(String),
+}
+
+impl Aggregate {
+    /// Field the aggregate is computed over, `None` for `COUNT(*)`.
+    pub fn field_name(&self) -> Option<&str> {
+        match self {
+            Aggregate::Count(field_name) => field_name.as_deref(),
+            Aggregate::Sum(field_name)
+            | Aggregate::Avg(field_name)
+            | Aggregate::Min(field_name)
+            | Aggregate::Max(field_name)
+            | Aggregate::Median(field_name)
+            | Aggregate::Percentile(field_name, _)
+            | Aggregate::Stddev(field_name) => Some(field_name),
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Aggregate::Count(_) => "COUNT",
+            Aggregate::Sum(_) => "SUM",
+            Aggregate::Avg(_) => "AVG",
+            Aggregate::Min(_) => "MIN",
+            Aggregate::Max(_) => "MAX",
+            Aggregate::Median(_) => "MEDIAN",
+            Aggregate::Percentile(_, _) => "PERCENTILE",
+            Aggregate::Stddev(_) => "STDDEV",
+        }
+    }
+
+    /// Whether the next tokens are an aggregate call, e.g. `count(` or `MEDIAN(`.
+    pub(crate) fn starts(tokens: &PeekableDeque<Token>) -> bool {
+        let is_aggregate = matches!(
+            tokens.peek().map(|token| &token.kind),
+            Some(TokenKind::Identifier(name))
+                if AGGREGATE_FUNCTIONS.contains(&name.to_uppercase().as_str())
+        );
+        is_aggregate
+            && matches!(
+                tokens.peek_nth(1),
+                Some(Token {
+                    kind: TokenKind::OpenedBracket,
+                    space_before: false,
+                    ..
+                })
+            )
+    }
+
+    /// Parses an aggregate call, call only when [`Aggregate::starts`].
+    pub(crate) fn parse(tokens: &mut PeekableDeque<Token>) -> Result<Self, String> {
+        let name = match next_kind(tokens) {
+            Some(TokenKind::Identifier(name)) => name.to_uppercase(),
+            _ => return Err("Expected an aggregate".to_string()),
+        };
+        tokens.next();
+
+        let field_name = match next_kind(tokens) {
+            Some(TokenKind::Symbol(symbol)) if symbol == "*" && name == "COUNT" => None,
+            Some(TokenKind::Identifier(field_name)) => Some(field_name),
+            Some(kind) => return Err(format!("{} expects a field name, found {}", name, kind)),
+            None => return Err(format!("{} expects a field name, found nothing", name)),
+        };
+
+        let mut percentile = None;
+        if name == "PERCENTILE" {
+            percentile = match (next_kind(tokens), next_kind(tokens)) {
+                (Some(TokenKind::Comma), Some(kind))
+                    if kind
+                        .as_f64()
+                        .is_some_and(|percentile| (0.0..=100.0).contains(&percentile)) =>
+                {
+                    kind.as_f64()
+                }
+                _ => {
+                    return Err(
+                        "PERCENTILE expects a field and a percentile from 0 to 100, e.g. PERCENTILE(words, 90)"
+                            .to_string(),
+                    )
+                }
+            };
+        }
+
+        match next_kind(tokens) {
+            Some(TokenKind::ClosedBracket) => {}
+            Some(kind) => return Err(format!("Expected ')' after {}, but found {}", name, kind)),
+            None => return Err(format!("Expected ')' after {}, but found nothing", name)),
+        }
+
+        let Some(field_name) = field_name else {
+            return Ok(Aggregate::Count(None));
+        };
+        Ok(match name.as_str() {
+            "COUNT" => Aggregate::Count(Some(field_name)),
+            "SUM" => Aggregate::Sum(field_name),
+            "AVG" => Aggregate::Avg(field_name),
+            "MIN" => Aggregate::Min(field_name),
+            "MAX" => Aggregate::Max(field_name),
+            "MEDIAN" => Aggregate::Median(field_name),
+            "PERCENTILE" => Aggregate::Percentile(field_name, percentile.unwrap_or_default()),
+            "STDDEV" => Aggregate::Stddev(field_name),
+            _ => {
+                return Err(with_suggestion(
+                    format!("Unknown aggregate: {}", name),
+                    &name,
+                    AGGREGATE_FUNCTIONS,
+                ))
+            }
+        })
+    }
+
+    /// Value of the aggregate over `rows`. Aggregates of no values are NULL, except COUNT which
+    /// is 0.
+    pub fn compute(&self, rows: &[&Pod]) -> FieldValue {
+        let values: Vec<FieldValue> = match self.field_name() {
+            Some(field_name) => rows
+                .iter()
+                .map(|row| get_field_value(field_name, row))
+                .filter(|value| *value != FieldValue::Null)
+                .collect(),
+            None => return FieldValue::Integer(rows.len() as i64),
+        };
+        let mut numbers: Vec<f64> = values.iter().filter_map(FieldValue::as_f64).collect();
+        numbers.sort_by(f64::total_cmp);
+
+        let number = match self {
+            Aggregate::Count(_) => return FieldValue::Integer(values.len() as i64),
+            Aggregate::Min(_) => {
+                return values
+                    .into_iter()
+                    .reduce(|a, b| if b < a { b } else { a })
+                    .unwrap_or(FieldValue::Null)
+            }
+            Aggregate::Max(_) => {
+                return values
+                    .into_iter()
+                    .reduce(|a, b| if b > a { b } else { a })
+                    .unwrap_or(FieldValue::Null)
+            }
+            Aggregate::Sum(_) if numbers.is_empty() => None,
+            // Sum of integers stays an integer, unless it overflows
+            Aggregate::Sum(_) if values.iter().all(|value| value.as_i64().is_some()) => {
+                let sum = values
+                    .iter()
+                    .filter_map(FieldValue::as_i64)
+                    .try_fold(0i64, i64::checked_add);
+                if let Some(sum) = sum {
+                    return FieldValue::Integer(sum);
+                }
+                Some(numbers.iter().sum())
+            }
+            Aggregate::Sum(_) => Some(numbers.iter().sum()),
+            Aggregate::Avg(_) => mean(&numbers),
+            Aggregate::Median(_) => percentile(&numbers, 50.0),
+            Aggregate::Percentile(_, p) => percentile(&numbers, *p),
+            Aggregate::Stddev(_) => stddev(&numbers),
+        };
+        number.map_or(FieldValue::Null, FieldValue::Float)
+    }
+}
+
+// Kind of the next token, moving past it
+fn next_kind(tokens: &mut PeekableDeque<Token>) -> Option<TokenKind> {
+    let kind = tokens.peek().map(|token| token.kind.clone());
+    tokens.next();
+    kind
+}
+
+fn mean(numbers: &[f64]) -> Option<f64> {
+    match numbers.len() {
+        0 => None,
+        n => Some(numbers.iter().sum::<f64>() / n as f64),
+    }
+}
+
+// `sorted` has to be sorted, values between two of them are interpolated
+fn percentile(sorted: &[f64], p: f64) -> Option<f64> {
+    let last = sorted.len().checked_sub(1)?;
+    let rank = p / 100.0 * last as f64;
+    let (below, above) = (rank.floor() as usize, rank.ceil() as usize);
+    Some(sorted[below] + (sorted[above] - sorted[below]) * (rank - below as f64))
+}
+
+fn stddev(numbers: &[f64]) -> Option<f64> {
+    if numbers.len() < 2 {
+        return None;
+    }
+    let mean = mean(numbers)?;
+    let squares: f64 = numbers.iter().map(|n| (n - mean).powi(2)).sum();
+    Some((squares / (numbers.len() - 1) as f64).sqrt())
+}
+
+impl Display for Aggregate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Aggregate::Percentile(field_name, p) => {
+                write!(f, "{}({}, {})", self.name(), field_name, p)
+            }
+            _ => write!(f, "{}({})", self.name(), self.field_name().unwrap_or("*")),
+        }
+    }
+}
+
+impl FromStr for Aggregate {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut tokens = PeekableDeque::from_iter(tokenize(s).map_err(|error| error.message)?);
+        if !Aggregate::starts(&tokens) {
+            return Err(format!("{} is not an aggregate", s));
+        }
+        let aggregate = Aggregate::parse(&mut tokens)?;
+        match tokens.peek() {
+            Some(token) => Err(format!("Unexpected token '{}' after {}", token, aggregate)),
+            None => Ok(aggregate),
+        }
+    }
+}
+
+/// Groups `data` by the values of the `group_by` fields and computes `aggregates` for every
+/// group, in the order the groups first appear. A group row has the group values and the
+/// aggregates under their column names (`status`, `COUNT(*)`). Without `group_by` all of the rows
+/// are one group, which is there even when `data` is empty.
+pub fn group_rows(
+    group_by: &[GroupByField],
+    aggregates: &[Aggregate],
+    data: &[Pod],
+    context: &ExecutionContext,
+) -> Vec<Pod> {
+    let mut groups: IndexMap<Vec<String>, (Vec<Pod>, Vec<&Pod>)> = IndexMap::new();
+    if group_by.is_empty() {
+        groups.insert(Vec::new(), (Vec::new(), Vec::new()));
+    }
+    for pod in data {
+        let values: Vec<Pod> = group_by
+            .iter()
+            .map(|group_by| group_value(group_by, pod, context))
+            .collect();
+        let key = values.iter().map(|value| format!("{:?}", value)).collect();
+        groups
+            .entry(key)
+            .or_insert((values, Vec::new()))
+            .1
+            .push(pod);
+    }
+
+    groups
+        .into_values()
+        .map(|(values, rows)| {
+            let mut row = Pod::new_hash();
+            for (group_by, value) in group_by.iter().zip(values) {
+                let _ = row.insert(group_by.field_name.clone(), value);
+            }
+            for aggregate in aggregates {
+                let value = field_value_to_pod(aggregate.compute(&rows));
+                let _ = row.insert(aggregate.to_string(), value);
+            }
+            row
+        })
+        .collect()
+}
+
+// Rows a function fails on (e.g. missing dates) are grouped under NULL
+fn group_value(group_by: &GroupByField, pod: &Pod, context: &ExecutionContext) -> Pod {
+    match &group_by.function {
+        Some(function) => execute_function(function, pod, context)
+            .map(field_value_to_pod)
+            .unwrap_or(Pod::Null),
+        None => pod
+            .nested_get(&group_by.field_name)
+            .cloned()
+            .unwrap_or(Pod::Null),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(fields: &[(&str, Pod)]) -> Pod {
+        let mut pod = Pod::new_hash();
+        for (name, value) in fields {
+            let _ = pod.insert(name.to_string(), value.clone());
+        }
+        pod
+    }
+
+    #[test]
+    fn test_parse_aggregate() {
+        assert_eq!(Ok(Aggregate::Count(None)), "count(*)".parse());
+        assert_eq!(
+            Ok(Aggregate::Percentile("words".to_string(), 90.0)),
+            "PERCENTILE(words, 90)".parse()
+        );
+        assert_eq!(
+            "PERCENTILE(words, 90)",
+            "percentile(words,90)"
+                .parse::<Aggregate>()
+                .unwrap()
+                .to_string()
+        );
+        assert_eq!(
+            "MEDIAN(file.size)",
+            Aggregate::Median("file.size".to_string()).to_string()
+        );
+        assert!("SUM(*)".parse::<Aggregate>().is_err());
+        assert!("PERCENTILE(words, 120)".parse::<Aggregate>().is_err());
+        assert!("PERCENTILE(words)".parse::<Aggregate>().is_err());
+        assert!("MEDIAN(words) x".parse::<Aggregate>().is_err());
+        assert!("title".parse::<Aggregate>().is_err());
+    }
+
+    #[test]
+    fn test_compute_aggregates() {
+        let rows: Vec<Pod> = [Pod::Integer(7), Pod::Integer(5), Pod::Float(6.0), Pod::Null]
+            .into_iter()
+            .map(|sleep| row(&[("sleep", sleep)]))
+            .chain([row(&[("sleep", Pod::String("n/a".to_string()))])])
+            .collect();
+        let rows: Vec<&Pod> = rows.iter().collect();
+        let sleep = || "sleep".to_string();
+
+        assert_eq!(
+            FieldValue::Integer(5),
+            Aggregate::Count(None).compute(&rows)
+        );
+        assert_eq!(
+            FieldValue::Integer(4),
+            Aggregate::Count(Some(sleep())).compute(&rows)
+        );
+        assert_eq!(
+            FieldValue::Float(18.0),
+            Aggregate::Sum(sleep()).compute(&rows)
+        );
+        assert_eq!(
+            FieldValue::Float(6.0),
+            Aggregate::Avg(sleep()).compute(&rows)
+        );
+        assert_eq!(
+            FieldValue::Float(6.0),
+            Aggregate::Median(sleep()).compute(&rows)
+        );
+        assert_eq!(
+            FieldValue::Float(6.5),
+            Aggregate::Percentile(sleep(), 75.0).compute(&rows)
+        );
+        assert_eq!(
+            FieldValue::Float(1.0),
+            Aggregate::Stddev(sleep()).compute(&rows)
+        );
+
+        // COUNT is an integer, and so is the SUM of only integers
+        assert!(matches!(
+            Aggregate::Count(None).compute(&rows),
+            FieldValue::Integer(5)
+        ));
+        assert!(matches!(
+            Aggregate::Sum(sleep()).compute(&rows[..2]),
+            FieldValue::Integer(12)
+        ));
+        assert!(matches!(
+            Aggregate::Sum(sleep()).compute(&rows),
+            FieldValue::Float(18.0)
+        ));
+
+        // MIN and MAX compare any values, e.g. dates
+        let dates: Vec<Pod> = ["2024-03-01", "2023-12-24"]
+            .iter()
+            .map(|date| row(&[("created", Pod::String(date.to_string()))]))
+            .collect();
+        let dates: Vec<&Pod> = dates.iter().collect();
+        assert_eq!(
+            FieldValue::String("2023-12-24".to_string()),
+            Aggregate::Min("created".to_string()).compute(&dates)
+        );
+        assert_eq!(
+            FieldValue::String("2024-03-01".to_string()),
+            Aggregate::Max("created".to_string()).compute(&dates)
+        );
+
+        let none: Vec<&Pod> = Vec::new();
+        assert_eq!(
+            FieldValue::Integer(0),
+            Aggregate::Count(None).compute(&none)
+        );
+        assert_eq!(FieldValue::Null, Aggregate::Avg(sleep()).compute(&none));
+        assert_eq!(
+            FieldValue::Null,
+            Aggregate::Stddev(sleep()).compute(&rows[..1])
+        );
+    }
+
+    #[test]
+    fn test_group_rows() {
+        let data = vec![
+            row(&[
+                ("status", Pod::String("done".to_string())),
+                ("words", Pod::Integer(10)),
+            ]),
+            row(&[
+                ("status", Pod::String("open".to_string())),
+                ("words", Pod::Integer(20)),
+            ]),
+            row(&[
+                ("status", Pod::String("done".to_string())),
+                ("words", Pod::Integer(30)),
+            ]),
+            row(&[("words", Pod::Integer(40))]),
+        ];
+        let aggregates = [Aggregate::Count(None), Aggregate::Avg("words".to_string())];
+
+        let context = ExecutionContext::default();
+        let status = [GroupByField::new("status".to_string())];
+        let rows: Vec<String> = group_rows(&status, &aggregates, &data, &context)
+            .iter()
+            .map(|row| row.to_string())
+            .collect();
+
+        assert_eq!(
+            vec![
+                r#"{"status":"done","COUNT(*)":2,"AVG(words)":20.0}"#,
+                r#"{"status":"open","COUNT(*)":1,"AVG(words)":20.0}"#,
+                r#"{"status":null,"COUNT(*)":1,"AVG(words)":40.0}"#,
+            ],
+            rows
+        );
+        assert_eq!(
+            r#"{"COUNT(*)":0,"AVG(words)":null}"#,
+            group_rows(&[], &aggregates, &[], &context)[0].to_string()
+        );
+    }
+}
@@ -0,0 +1,110 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Lets a running query be aborted, either explicitly with [`CancellationToken::cancel`] (e.g.
+/// from another thread) or by a timeout. Clones share the same cancelled flag.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+    deadline: Option<Instant>,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        CancellationToken::default()
+    }
+
+    pub fn with_timeout(timeout: Duration) -> Self {
+        CancellationToken {
+            cancelled: Arc::new(AtomicBool::new(false)),
+            deadline: Instant::now().checked_add(timeout),
+        }
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    /// Guard that cancels the token (and its clones) when it is dropped, e.g. together with the
+    /// future waiting for the query.
+    pub fn cancel_on_drop(&self) -> CancelOnDrop {
+        CancelOnDrop(self.clone())
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed) || self.is_timed_out()
+    }
+
+    fn is_timed_out(&self) -> bool {
+        self.deadline
+            .is_some_and(|deadline| Instant::now() >= deadline)
+    }
+
+    /// Returns an error if the query should stop, meant to be used with `?` between units of work.
+    pub fn check(&self) -> Result<(), String> {
+        if self.cancelled.load(Ordering::Relaxed) {
+            return Err("Query was cancelled!".to_string());
+        }
+        if self.is_timed_out() {
+            return Err("Query timed out!".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Cancels its token when dropped, see [`CancellationToken::cancel_on_drop`].
+#[derive(Debug)]
+pub struct CancelOnDrop(CancellationToken);
+
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        self.0.cancel();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_new_is_not_cancelled() {
+        let token = CancellationToken::new();
+
+        assert!(!token.is_cancelled());
+        assert_eq!(Ok(()), token.check());
+    }
+
+    #[test]
+    fn test_cancel_is_shared_between_clones() {
+        let token = CancellationToken::new();
+        let clone = token.clone();
+
+        clone.cancel();
+
+        assert!(token.is_cancelled());
+        assert_eq!(Err("Query was cancelled!".to_string()), token.check());
+    }
+
+    #[test]
+    fn test_cancel_on_drop() {
+        let token = CancellationToken::new();
+
+        let guard = token.cancel_on_drop();
+        assert!(!token.is_cancelled());
+        drop(guard);
+
+        assert!(token.is_cancelled());
+    }
+
+    #[test]
+    fn test_timeout() {
+        let token = CancellationToken::with_timeout(Duration::ZERO);
+
+        assert!(token.is_cancelled());
+        assert_eq!(Err("Query timed out!".to_string()), token.check());
+
+        let token = CancellationToken::with_timeout(Duration::from_secs(3600));
+        assert!(!token.is_cancelled());
+    }
+}
@@ -0,0 +1,223 @@
+//! Locale-aware order of strings for ORDER BY. Byte order sorts "Zebra" before "Ärger" and
+//! "apple" after "Zebra", a collation sorts them the way a dictionary of the locale would.
+//! Collations of locales need the `collation` feature, which bundles ICU's collation data, the
+//! [`BUILTIN_COLLATIONS`] are always available.
+
+use std::cmp::Ordering;
+use std::fmt::Debug;
+use std::iter::Peekable;
+use std::str::Chars;
+#[cfg(feature = "collation")]
+use std::sync::Arc;
+
+/// Collations that do not need a locale: `natural` compares the numbers in strings by their value,
+/// so "note2" comes before "note10", `nocase` ignores case and `natural-nocase` does both
+pub const BUILTIN_COLLATIONS: [&str; 3] = ["natural", "nocase", "natural-nocase"];
+
+#[derive(Clone)]
+pub struct Collation {
+    locale: String,
+    rules: Rules,
+}
+
+#[derive(Clone)]
+enum Rules {
+    Builtin {
+        natural: bool,
+        ignore_case: bool,
+    },
+    #[cfg(feature = "collation")]
+    Locale(Arc<icu_collator::Collator>),
+}
+
+impl Collation {
+    /// One of the [`BUILTIN_COLLATIONS`], or the collation of `locale`, a BCP 47 tag such as `de`,
+    /// `sv` or `de-u-co-phonebk`. Locales without rules of their own use the root collation, which
+    /// already orders accented letters next to their base letters.
+    pub fn new(locale: &str) -> Result<Self, String> {
+        let (natural, ignore_case) = match locale.to_lowercase().as_str() {
+            "natural" => (true, false),
+            "nocase" => (false, true),
+            "natural-nocase" => (true, true),
+            _ => return Collation::of_locale(locale),
+        };
+        Ok(Collation {
+            locale: locale.to_string(),
+            rules: Rules::Builtin {
+                natural,
+                ignore_case,
+            },
+        })
+    }
+
+    #[cfg(feature = "collation")]
+    fn of_locale(locale: &str) -> Result<Self, String> {
+        let data_locale: icu_provider::DataLocale = locale
+            .parse()
+            .map_err(|error| format!("Invalid collation locale {}: {}", locale, error))?;
+        let collator =
+            icu_collator::Collator::try_new(&data_locale, icu_collator::CollatorOptions::new())
+                .map_err(|error| format!("Collation {} is not available: {}", locale, error))?;
+
+        Ok(Collation {
+            locale: locale.to_string(),
+            rules: Rules::Locale(Arc::new(collator)),
+        })
+    }
+
+    #[cfg(not(feature = "collation"))]
+    fn of_locale(locale: &str) -> Result<Self, String> {
+        Err(format!(
+            "Collation {} needs krafna built with the `collation` feature",
+            locale
+        ))
+    }
+
+    pub fn locale(&self) -> &str {
+        &self.locale
+    }
+
+    pub fn compare(&self, a: &str, b: &str) -> Ordering {
+        match &self.rules {
+            Rules::Builtin {
+                natural,
+                ignore_case,
+            } => compare_builtin(a, b, *natural, *ignore_case),
+            #[cfg(feature = "collation")]
+            Rules::Locale(collator) => collator.compare(a, b),
+        }
+    }
+}
+
+// Strings that only differ in case or leading zeros are still ordered, by their bytes, so the
+// order does not depend on the order of the rows
+fn compare_builtin(a: &str, b: &str, natural: bool, ignore_case: bool) -> Ordering {
+    let mut a_chars = a.chars().peekable();
+    let mut b_chars = b.chars().peekable();
+    loop {
+        let ordering = match (a_chars.peek(), b_chars.peek()) {
+            (None, None) => return a.cmp(b),
+            (None, Some(_)) => Ordering::Less,
+            (Some(_), None) => Ordering::Greater,
+            (Some(x), Some(y)) if natural && x.is_ascii_digit() && y.is_ascii_digit() => {
+                compare_numbers(&take_digits(&mut a_chars), &take_digits(&mut b_chars))
+            }
+            (Some(&x), Some(&y)) => {
+                a_chars.next();
+                b_chars.next();
+                if ignore_case {
+                    x.to_lowercase().cmp(y.to_lowercase())
+                } else {
+                    x.cmp(&y)
+                }
+            }
+        };
+        if ordering.is_ne() {
+            return ordering;
+        }
+    }
+}
+
+fn take_digits(chars: &mut Peekable<Chars>) -> String {
+    let mut digits = String::new();
+    while let Some(digit) = chars.next_if(char::is_ascii_digit) {
+        digits.push(digit);
+    }
+    digits
+}
+
+// By value without parsing, so numbers of any length can be compared
+fn compare_numbers(a: &str, b: &str) -> Ordering {
+    let a = a.trim_start_matches('0');
+    let b = b.trim_start_matches('0');
+    a.len().cmp(&b.len()).then_with(|| a.cmp(b))
+}
+
+impl Debug for Collation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Collation")
+            .field("locale", &self.locale)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builtin_collations() {
+        let sorted = |collation: &str, words: &[&'static str]| {
+            let collation = Collation::new(collation).unwrap();
+            let mut words = words.to_vec();
+            words.sort_by(|a, b| collation.compare(a, b));
+            words
+        };
+        let notes = [
+            "note10.md",
+            "Note3.md",
+            "note2.md",
+            "note02.md",
+            "note1.md",
+            "notes.md",
+        ];
+
+        assert_eq!(
+            vec![
+                "Note3.md",
+                "note1.md",
+                "note02.md",
+                "note2.md",
+                "note10.md",
+                "notes.md"
+            ],
+            sorted("natural", &notes)
+        );
+        assert_eq!(
+            vec![
+                "note02.md",
+                "note1.md",
+                "note10.md",
+                "note2.md",
+                "Note3.md",
+                "notes.md"
+            ],
+            sorted("NOCASE", &notes)
+        );
+        assert_eq!(
+            vec![
+                "note1.md",
+                "note02.md",
+                "note2.md",
+                "Note3.md",
+                "note10.md",
+                "notes.md"
+            ],
+            sorted("natural-nocase", &notes)
+        );
+        // numbers longer than any integer
+        assert_eq!(
+            vec!["v99999999999999999999", "v100000000000000000000"],
+            sorted(
+                "natural",
+                &["v100000000000000000000", "v99999999999999999999"]
+            )
+        );
+    }
+
+    #[cfg(feature = "collation")]
+    #[test]
+    fn test_collation_orders_by_locale() {
+        let mut words = vec!["Zebra", "apple", "Ärger", "Apfel", "zoo"];
+        let german = Collation::new("de").unwrap();
+        words.sort_by(|a, b| german.compare(a, b));
+        assert_eq!(vec!["Apfel", "apple", "Ärger", "Zebra", "zoo"], words);
+
+        // Swedish sorts Ä after Z
+        let swedish = Collation::new("sv").unwrap();
+        words.sort_by(|a, b| swedish.compare(a, b));
+        assert_eq!(vec!["Apfel", "apple", "Zebra", "zoo", "Ärger"], words);
+
+        assert!(Collation::new("not a locale").is_err());
+    }
+}
@@ -0,0 +1,220 @@
+//! krafna's YAML config file, `config.yaml` in the OS config dir (e.g.
+//! `~/.config/krafna/config.yaml` on Linux) unless another one is given:
+//! ```yaml
+//! computed_fields:
+//!   is_project: "'project' IN tags"
+//!   created_year: DATE(file.created).year
+//! collation: de
+//! field_aliases:
+//!   deadline: due
+//! macros:
+//!   active: status != 'done' AND archived != true
+//! queries:
+//!   next-actions: SELECT title FROM FRONTMATTER_DATA('~/notes') WHERE status == 'next'
+//! ```
+
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use directories::ProjectDirs;
+use yaml_rust2::{Yaml, YamlLoader};
+
+use crate::libs::engine::KrafnaConfig;
+use crate::libs::suggestion::with_suggestion;
+
+pub const CONFIG_FILE_NAME: &str = "config.yaml";
+
+/// Top level keys of the config file
+const CONFIG_KEYS: [&str; 6] = [
+    "collation",
+    "computed_fields",
+    "field_aliases",
+    "macros",
+    "queries",
+    "vault",
+];
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ConfigFile {
+    /// See [`KrafnaConfig::collation`]
+    pub collation: Option<String>,
+    /// See [`KrafnaConfig::computed_fields`]
+    pub computed_fields: Vec<(String, String)>,
+    /// See [`KrafnaConfig::field_aliases`]
+    pub field_aliases: Vec<(String, String)>,
+    /// See [`KrafnaConfig::macros`]
+    pub macros: Vec<(String, String)>,
+    /// Saved queries as (name, query), they can use `:name` parameters
+    pub queries: Vec<(String, String)>,
+    /// See [`KrafnaConfig::vault`]
+    pub vault: Option<String>,
+}
+
+impl ConfigFile {
+    /// Where the config file is read from by default, `None` when there is no config dir.
+    pub fn default_path() -> Option<PathBuf> {
+        ProjectDirs::from("com", "7sedam7", "krafna")
+            .map(|proj_dirs| proj_dirs.config_dir().join(CONFIG_FILE_NAME))
+    }
+
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let content = fs::read_to_string(path)
+            .map_err(|error| format!("Could not read {}: {}", path.display(), error))?;
+        Ok(content
+            .parse()
+            .map_err(|error| format!("{}: {}", path.display(), error))?)
+    }
+
+    /// Text of the saved query `name`.
+    pub fn query(&self, name: &str) -> Result<&str, String> {
+        match self
+            .queries
+            .iter()
+            .find(|(query_name, _)| query_name == name)
+        {
+            Some((_, query)) => Ok(query),
+            None => Err(with_suggestion(
+                format!("Unknown saved query: {}", name),
+                name,
+                self.queries.iter().map(|(name, _)| name.as_str()),
+            )),
+        }
+    }
+
+    /// `config` with the settings of the config file on top.
+    pub fn apply(&self, config: KrafnaConfig) -> KrafnaConfig {
+        KrafnaConfig {
+            computed_fields: self.computed_fields.clone(),
+            field_aliases: self.field_aliases.clone(),
+            macros: self.macros.clone(),
+            collation: self.collation.clone().or(config.collation),
+            // --vault wins over the config file
+            vault: config.vault.or(self.vault.clone()),
+            ..config
+        }
+    }
+}
+
+impl FromStr for ConfigFile {
+    type Err = String;
+
+    fn from_str(yaml: &str) -> Result<Self, Self::Err> {
+        let docs = YamlLoader::load_from_str(yaml).map_err(|error| error.to_string())?;
+        let mut config = ConfigFile::default();
+        let settings = match docs.into_iter().next() {
+            Some(Yaml::Hash(settings)) => settings,
+            None | Some(Yaml::Null) => return Ok(config),
+            Some(_) => return Err("Expected key: value pairs".to_string()),
+        };
+
+        for (key, value) in settings {
+            let key = scalar(&key).ok_or("Keys must be strings")?;
+            match key.as_str() {
+                "collation" => {
+                    config.collation =
+                        Some(scalar(&value).ok_or(
+                            "collation must be a locale or natural, nocase, natural-nocase",
+                        )?)
+                }
+                "computed_fields" => config.computed_fields = string_pairs(&key, value)?,
+                "field_aliases" => config.field_aliases = string_pairs(&key, value)?,
+                "macros" => config.macros = string_pairs(&key, value)?,
+                "queries" => config.queries = string_pairs(&key, value)?,
+                "vault" => config.vault = Some(scalar(&value).ok_or("vault must be a directory")?),
+                _ => {
+                    return Err(with_suggestion(
+                        format!("Unknown config key: {}", key),
+                        &key,
+                        CONFIG_KEYS,
+                    ))
+                }
+            }
+        }
+
+        Ok(config)
+    }
+}
+
+// `name: text` pairs, in the order they are written
+fn string_pairs(key: &str, value: Yaml) -> Result<Vec<(String, String)>, String> {
+    let Yaml::Hash(hash) = value else {
+        return Err(format!("{} must be name: value pairs", key));
+    };
+    hash.iter()
+        .map(|(name, value)| match (scalar(name), scalar(value)) {
+            (Some(name), Some(value)) => Ok((name, value)),
+            _ => Err(format!("{} must be name: value pairs", key)),
+        })
+        .collect()
+}
+
+// YAML reads `a: true` or `a: 3` as bool and number, they are expressions all the same
+fn scalar(yaml: &Yaml) -> Option<String> {
+    match yaml {
+        Yaml::String(s) | Yaml::Real(s) => Some(s.clone()),
+        Yaml::Integer(i) => Some(i.to_string()),
+        Yaml::Boolean(b) => Some(b.to_string()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_config_file() {
+        let config: ConfigFile =
+            "computed_fields:\n  is_project: \"'project' IN tags\"\n  always: true\n"
+                .parse()
+                .unwrap();
+
+        assert_eq!(
+            vec![
+                ("is_project".to_string(), "'project' IN tags".to_string()),
+                ("always".to_string(), "true".to_string()),
+            ],
+            config.computed_fields
+        );
+        assert_eq!(ConfigFile::default(), "".parse().unwrap());
+        assert!(config.queries.is_empty());
+        assert_eq!(
+            vec![("deadline".to_string(), "due".to_string())],
+            "field_aliases:\n  deadline: due\n"
+                .parse::<ConfigFile>()
+                .unwrap()
+                .field_aliases
+        );
+        assert_eq!(
+            Some("~/notes".to_string()),
+            "vault: ~/notes".parse::<ConfigFile>().unwrap().vault
+        );
+        assert_eq!(
+            Err("Unknown config key: computed_field, did you mean 'computed_fields'?".to_string()),
+            "computed_field: {}".parse::<ConfigFile>()
+        );
+        assert_eq!(
+            Err("computed_fields must be name: value pairs".to_string()),
+            "computed_fields: [a]".parse::<ConfigFile>()
+        );
+    }
+
+    #[test]
+    fn test_saved_queries() {
+        let config: ConfigFile =
+            "queries:\n  next-actions: SELECT title FROM F('x') WHERE status == 'next'\n"
+                .parse()
+                .unwrap();
+
+        assert_eq!(
+            Ok("SELECT title FROM F('x') WHERE status == 'next'"),
+            config.query("next-actions")
+        );
+        assert_eq!(
+            Err("Unknown saved query: next-action, did you mean 'next-actions'?".to_string()),
+            config.query("next-action")
+        );
+    }
+}
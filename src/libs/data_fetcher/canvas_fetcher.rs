@@ -0,0 +1,201 @@
+use std::error::Error;
+use std::fs;
+
+use indexmap::IndexMap;
+
+use crate::libs::cancellation::CancellationToken;
+use crate::libs::data_fetcher::markdown_fetcher::{
+    find_files, get_file_info, markdown_path_arguments,
+};
+use crate::libs::data_fetcher::pod::Pod;
+use crate::libs::parser::FunctionArg;
+
+/// Area a canvas node takes, to find the groups it is in.
+struct Rect {
+    x: f64,
+    y: f64,
+    width: f64,
+    height: f64,
+}
+
+impl Rect {
+    fn of(node: &serde_json::Value) -> Rect {
+        let number = |key| node.get(key).and_then(|v| v.as_f64()).unwrap_or(0.0);
+        Rect {
+            x: number("x"),
+            y: number("y"),
+            width: number("width"),
+            height: number("height"),
+        }
+    }
+
+    fn contains(&self, other: &Rect) -> bool {
+        self.x <= other.x
+            && self.y <= other.y
+            && other.x + other.width <= self.x + self.width
+            && other.y + other.height <= self.y + self.height
+    }
+}
+
+/// Nodes and edges of the Obsidian canvases (`.canvas` files) in the paths of `args`, one row
+/// each with the canvas in `file` and what it is in `type` (text, file, link, group or edge).
+/// Nodes have the labels of the groups they are in, `group` is the innermost one.
+pub fn fetch_canvas_data(args: &[FunctionArg]) -> Result<Vec<Pod>, Box<dyn Error>> {
+    let dir_paths = markdown_path_arguments(args)?;
+    let (files, errors) = find_files(&dir_paths, "canvas", &CancellationToken::default())?;
+    if let Some(error) = errors.first() {
+        return Err(error.to_string().into());
+    }
+
+    let mut rows = Vec::new();
+    for path in files {
+        let content = fs::read_to_string(&path)
+            .map_err(|error| format!("Could not read {}: {}", path.display(), error))?;
+        let file_data = Pod::Hash(get_file_info(&path));
+        rows.extend(
+            canvas_to_pods(&content, &file_data)
+                .map_err(|error| format!("{}: {}", path.display(), error))?,
+        );
+    }
+    Ok(rows)
+}
+
+fn canvas_to_pods(content: &str, file_data: &Pod) -> Result<Vec<Pod>, String> {
+    let canvas: serde_json::Value =
+        serde_json::from_str(content).map_err(|error| format!("Invalid JSON: {}", error))?;
+    let items = |key| match canvas.get(key) {
+        Some(serde_json::Value::Array(items)) => Ok(items.as_slice()),
+        None | Some(serde_json::Value::Null) => Ok(&[][..]),
+        Some(_) => Err(format!("'{}' of the canvas is not an array", key)),
+    };
+    let nodes = items("nodes")?;
+    let edges = items("edges")?;
+
+    // Largest first, so the groups of a node are listed from the outermost one
+    let mut groups: Vec<(Rect, &serde_json::Value)> = nodes
+        .iter()
+        .filter(|node| node.get("type").and_then(|t| t.as_str()) == Some("group"))
+        .map(|node| (Rect::of(node), node))
+        .collect();
+    groups.sort_by(|(a, _), (b, _)| (b.width * b.height).total_cmp(&(a.width * a.height)));
+
+    let mut rows = Vec::new();
+    for node in nodes {
+        let mut row = canvas_row(rows.len() + 1, node, file_data)?;
+        for key in ["text", "file", "subpath", "url", "label"] {
+            if let Some(value) = node.get(key) {
+                // `file` is the canvas itself, like on the rows of the other sources
+                let name = if key == "file" { "path" } else { key };
+                row.insert(name.to_string(), Pod::from_json_value(value));
+            }
+        }
+        for key in ["x", "y", "width", "height"] {
+            row.insert(
+                key.to_string(),
+                node.get(key).map_or(Pod::Null, Pod::from_json_value),
+            );
+        }
+
+        let rect = Rect::of(node);
+        let labels: Vec<Pod> = groups
+            .iter()
+            .filter(|(group, group_node)| !std::ptr::eq(*group_node, node) && group.contains(&rect))
+            .filter_map(|(_, group_node)| group_node.get("label"))
+            .map(Pod::from_json_value)
+            .collect();
+        row.insert(
+            "group".to_string(),
+            labels.last().cloned().unwrap_or(Pod::Null),
+        );
+        row.insert("groups".to_string(), Pod::Array(labels));
+        rows.push(Pod::Hash(row));
+    }
+
+    for edge in edges {
+        let mut row = canvas_row(rows.len() + 1, edge, file_data)?;
+        row.insert("type".to_string(), Pod::String("edge".to_string()));
+        for (key, name) in [
+            ("fromNode", "from"),
+            ("toNode", "to"),
+            ("fromSide", "from_side"),
+            ("toSide", "to_side"),
+            ("label", "label"),
+        ] {
+            row.insert(
+                name.to_string(),
+                edge.get(key).map_or(Pod::Null, Pod::from_json_value),
+            );
+        }
+        rows.push(Pod::Hash(row));
+    }
+    Ok(rows)
+}
+
+// Fields every row has: the canvas, `ord` in it (from 1), `id`, `type` and `color`
+fn canvas_row(
+    ord: usize,
+    item: &serde_json::Value,
+    file_data: &Pod,
+) -> Result<IndexMap<String, Pod>, String> {
+    if !item.is_object() {
+        return Err(format!("Item {} of the canvas is not an object", ord));
+    }
+    let mut row = IndexMap::new();
+    row.insert("file".to_string(), file_data.clone());
+    row.insert("ord".to_string(), Pod::Integer(ord as i64));
+    for key in ["id", "type", "color"] {
+        row.insert(
+            key.to_string(),
+            item.get(key).map_or(Pod::Null, Pod::from_json_value),
+        );
+    }
+    Ok(row)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_canvas_to_pods() {
+        let content = r#"{
+            "nodes": [
+                {"id": "g1", "type": "group", "x": 0, "y": 0, "width": 1000, "height": 1000, "label": "Later"},
+                {"id": "g2", "type": "group", "x": 10, "y": 10, "width": 500, "height": 500, "label": "Soon"},
+                {"id": "n1", "type": "text", "x": 20, "y": 20, "width": 100, "height": 50, "text": "Call **Ana**"},
+                {"id": "n2", "type": "file", "x": 600, "y": 20, "width": 100, "height": 50, "file": "projects/roadmap.md", "color": "1"},
+                {"id": "n3", "type": "link", "x": 2000, "y": 0, "width": 100, "height": 50, "url": "https://example.com"}
+            ],
+            "edges": [
+                {"id": "e1", "fromNode": "n1", "fromSide": "right", "toNode": "n2", "label": "then"}
+            ]
+        }"#;
+
+        let rows: Vec<String> = canvas_to_pods(content, &Pod::Null)
+            .unwrap()
+            .iter()
+            .map(|row| row.to_json_value().to_string())
+            .collect();
+
+        assert_eq!(
+            vec![
+                r#"{"file":null,"ord":1,"id":"g1","type":"group","color":null,"label":"Later","x":0,"y":0,"width":1000,"height":1000,"group":null,"groups":[]}"#,
+                r#"{"file":null,"ord":2,"id":"g2","type":"group","color":null,"label":"Soon","x":10,"y":10,"width":500,"height":500,"group":"Later","groups":["Later"]}"#,
+                r#"{"file":null,"ord":3,"id":"n1","type":"text","color":null,"text":"Call **Ana**","x":20,"y":20,"width":100,"height":50,"group":"Soon","groups":["Later","Soon"]}"#,
+                r#"{"file":null,"ord":4,"id":"n2","type":"file","color":"1","path":"projects/roadmap.md","x":600,"y":20,"width":100,"height":50,"group":"Later","groups":["Later"]}"#,
+                r#"{"file":null,"ord":5,"id":"n3","type":"link","color":null,"url":"https://example.com","x":2000,"y":0,"width":100,"height":50,"group":null,"groups":[]}"#,
+                r#"{"file":null,"ord":6,"id":"e1","type":"edge","color":null,"from":"n1","to":"n2","from_side":"right","to_side":null,"label":"then"}"#,
+            ],
+            rows
+        );
+        assert!(canvas_to_pods("{}", &Pod::Null).unwrap().is_empty());
+        assert_eq!(
+            Err("'nodes' of the canvas is not an array".to_string()),
+            canvas_to_pods(r#"{"nodes": 1}"#, &Pod::Null).map(|_| ())
+        );
+        assert_eq!(
+            Err("Item 1 of the canvas is not an object".to_string()),
+            canvas_to_pods(r#"{"nodes": [1]}"#, &Pod::Null).map(|_| ())
+        );
+    }
+}
@@ -0,0 +1,212 @@
+use std::error::Error;
+use std::fs;
+
+use indexmap::IndexMap;
+
+use crate::libs::data_fetcher::pod::Pod;
+use crate::libs::parser::{FieldValue, FunctionArg};
+
+/// A value of a CSV record, quoted values are always strings.
+struct CsvValue {
+    text: String,
+    quoted: bool,
+}
+
+/// Rows of the CSV file in `args` (`CSV_DATA('~/exports/time.csv')`), with the names of its header
+/// line as fields. Numbers and `true`/`false` become numbers and booleans, empty values are null.
+pub fn fetch_csv_data(args: &[FunctionArg]) -> Result<Vec<Pod>, Box<dyn Error>> {
+    let path = match args {
+        [FunctionArg::FieldValue(FieldValue::String(path))] => {
+            shellexpand::tilde(path).into_owned()
+        }
+        _ => {
+            return Err(format!("CSV_DATA expects a file path, but found {:?}", args).into());
+        }
+    };
+    let content =
+        fs::read_to_string(&path).map_err(|error| format!("Could not read {}: {}", path, error))?;
+
+    Ok(csv_to_pods(&content).map_err(|error| format!("{}: {}", path, error))?)
+}
+
+fn csv_to_pods(content: &str) -> Result<Vec<Pod>, String> {
+    let content = content.strip_prefix('\u{feff}').unwrap_or(content);
+    let mut records = parse_csv(content)?.into_iter();
+    let Some((_, header)) = records.next() else {
+        return Ok(vec![]);
+    };
+    let names: Vec<String> = header
+        .into_iter()
+        .map(|name| name.text.trim().to_string())
+        .collect();
+
+    records
+        .map(|(line, record)| {
+            if record.len() > names.len() {
+                return Err(format!(
+                    "Line {} has {} values, but the header has {} names",
+                    line,
+                    record.len(),
+                    names.len()
+                ));
+            }
+            let mut values = record.into_iter();
+            // Missing values at the end of a short line are null
+            let row = names
+                .iter()
+                .map(|name| (name.clone(), values.next().map_or(Pod::Null, csv_value)))
+                .collect::<IndexMap<_, _>>();
+            Ok(Pod::Hash(row))
+        })
+        .collect()
+}
+
+fn csv_value(value: CsvValue) -> Pod {
+    if value.quoted {
+        return Pod::String(value.text);
+    }
+    typed_value(&value.text)
+}
+
+/// Number, boolean or null (when empty) the `text` of a value is, or the text itself.
+pub(crate) fn typed_value(text: &str) -> Pod {
+    let trimmed = text.trim();
+    // f64 parses "inf" and "NaN" as well
+    let is_number = trimmed.chars().any(|c| c.is_ascii_digit())
+        && trimmed
+            .chars()
+            .all(|c| c.is_ascii_digit() || matches!(c, '+' | '-' | '.' | 'e' | 'E'));
+    if trimmed.is_empty() {
+        Pod::Null
+    } else if let (true, Ok(integer)) = (is_number, trimmed.parse::<i64>()) {
+        Pod::Integer(integer)
+    } else if let (true, Ok(float)) = (is_number, trimmed.parse::<f64>()) {
+        Pod::Float(float)
+    } else if trimmed.eq_ignore_ascii_case("true") {
+        Pod::Boolean(true)
+    } else if trimmed.eq_ignore_ascii_case("false") {
+        Pod::Boolean(false)
+    } else {
+        Pod::String(text.to_string())
+    }
+}
+
+// Records with the line they start at. Values are separated by `,`, and quoted with `"` when they
+// have commas, line breaks or quotes (doubled) in them, as in RFC 4180. Empty lines are skipped.
+fn parse_csv(content: &str) -> Result<Vec<(usize, Vec<CsvValue>)>, String> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut value = CsvValue {
+        text: String::new(),
+        quoted: false,
+    };
+    let mut in_quotes = false;
+    let mut line = 1;
+    let mut record_line = 1;
+
+    let mut chars = content.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if in_quotes => {
+                if chars.peek() == Some(&'"') {
+                    chars.next();
+                    value.text.push('"');
+                } else {
+                    in_quotes = false;
+                }
+            }
+            '"' if value.text.is_empty() && !value.quoted => {
+                in_quotes = true;
+                value.quoted = true;
+            }
+            '\n' if in_quotes => {
+                line += 1;
+                value.text.push(c);
+            }
+            ',' if !in_quotes => {
+                record.push(std::mem::replace(
+                    &mut value,
+                    CsvValue {
+                        text: String::new(),
+                        quoted: false,
+                    },
+                ));
+            }
+            '\r' if !in_quotes && chars.peek() == Some(&'\n') => {}
+            '\n' => {
+                end_record(&mut records, &mut record, &mut value, record_line);
+                line += 1;
+                record_line = line;
+            }
+            _ => value.text.push(c),
+        }
+    }
+    if in_quotes {
+        return Err(format!(
+            "Line {} has a quoted value that is never closed",
+            record_line
+        ));
+    }
+    end_record(&mut records, &mut record, &mut value, record_line);
+
+    Ok(records)
+}
+
+fn end_record(
+    records: &mut Vec<(usize, Vec<CsvValue>)>,
+    record: &mut Vec<CsvValue>,
+    value: &mut CsvValue,
+    line: usize,
+) {
+    let value = std::mem::replace(
+        value,
+        CsvValue {
+            text: String::new(),
+            quoted: false,
+        },
+    );
+    if record.is_empty() && value.text.is_empty() && !value.quoted {
+        return;
+    }
+    record.push(value);
+    records.push((line, std::mem::take(record)));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_csv_to_pods() {
+        let content = "\u{feff}project, hours,billable,note\r\nkrafna,1.5,true,\"fix, test\"\r\nsite,2,FALSE,\"said \"\"hi\"\"\nand left\"\n\n\"007\",,x\nshort\n";
+
+        let rows: Vec<String> = csv_to_pods(content)
+            .unwrap()
+            .iter()
+            .map(|row| row.to_json_value().to_string())
+            .collect();
+
+        assert_eq!(
+            vec![
+                r#"{"project":"krafna","hours":1.5,"billable":true,"note":"fix, test"}"#,
+                r#"{"project":"site","hours":2,"billable":false,"note":"said \"hi\"\nand left"}"#,
+                r#"{"project":"007","hours":null,"billable":"x","note":null}"#,
+                r#"{"project":"short","hours":null,"billable":null,"note":null}"#,
+            ],
+            rows
+        );
+        assert!(csv_to_pods("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_csv_errors_name_the_line() {
+        assert_eq!(
+            Err("Line 3 has 3 values, but the header has 2 names".to_string()),
+            csv_to_pods("a,b\n1,2\n1,2,3\n").map(|_| ())
+        );
+        assert_eq!(
+            Err("Line 2 has a quoted value that is never closed".to_string()),
+            csv_to_pods("a\n\"open\nvalue\n").map(|_| ())
+        );
+    }
+}
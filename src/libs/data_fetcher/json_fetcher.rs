@@ -0,0 +1,90 @@
+use std::error::Error;
+use std::fs;
+
+use crate::libs::data_fetcher::pod::Pod;
+use crate::libs::parser::{FieldValue, FunctionArg};
+
+/// Rows of the JSON file in `args`, an array of objects (`JSON_DATA('~/exports/todoist.json')`).
+/// A second argument is the dotted key of the array in the file, e.g.
+/// `JSON_DATA('~/exports/readwise.json', 'results')`.
+pub fn fetch_json_data(args: &[FunctionArg]) -> Result<Vec<Pod>, Box<dyn Error>> {
+    let (path, key) = match args {
+        [FunctionArg::FieldValue(FieldValue::String(path))] => (path, None),
+        [FunctionArg::FieldValue(FieldValue::String(path)), FunctionArg::FieldValue(FieldValue::String(key))] => {
+            (path, Some(key.as_str()))
+        }
+        _ => {
+            return Err(format!(
+                "JSON_DATA expects a file path and optionally the key of the array in it, but found {:?}",
+                args
+            )
+            .into());
+        }
+    };
+    let path = shellexpand::tilde(path).into_owned();
+    let content =
+        fs::read_to_string(&path).map_err(|error| format!("Could not read {}: {}", path, error))?;
+
+    Ok(json_to_pods(&content, key).map_err(|error| format!("{}: {}", path, error))?)
+}
+
+fn json_to_pods(content: &str, key: Option<&str>) -> Result<Vec<Pod>, String> {
+    let value: serde_json::Value =
+        serde_json::from_str(content).map_err(|error| format!("Invalid JSON: {}", error))?;
+    let pod = Pod::from_json_value(&value);
+    let rows = match key {
+        Some(key) => pod
+            .nested_get(key)
+            .ok_or_else(|| format!("There is no '{}' in the JSON", key))?,
+        None => &pod,
+    };
+    let Pod::Array(rows) = rows else {
+        return Err(match key {
+            Some(key) => format!("'{}' is not an array of objects", key),
+            None => "Expected an array of objects, pass the key of the array in it as the second argument".to_string(),
+        });
+    };
+
+    rows.iter()
+        .enumerate()
+        .map(|(index, row)| match row {
+            Pod::Hash(_) => Ok(row.clone()),
+            _ => Err(format!("Item {} of the array is not an object", index)),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_json_to_pods() {
+        let content = r#"{"count": 2, "data": {"results": [{"title": "A", "tags": ["x"], "score": 1.5}, {"title": "B", "id": 18446744073709551615}]}}"#;
+
+        let rows: Vec<String> = json_to_pods(content, Some("data.results"))
+            .unwrap()
+            .iter()
+            .map(|row| row.to_json_value().to_string())
+            .collect();
+
+        assert_eq!(
+            vec![
+                r#"{"title":"A","tags":["x"],"score":1.5}"#,
+                r#"{"title":"B","id":1.8446744073709552e19}"#,
+            ],
+            rows
+        );
+        assert_eq!(1, json_to_pods(r#"[{"a": 1}]"#, None).unwrap().len());
+        assert_eq!(
+            Err("Item 1 of the array is not an object".to_string()),
+            json_to_pods(r#"[{"a": 1}, 2]"#, None).map(|_| ())
+        );
+        assert_eq!(
+            Err("'count' is not an array of objects".to_string()),
+            json_to_pods(content, Some("count")).map(|_| ())
+        );
+        assert!(json_to_pods(content, None).is_err());
+        assert!(json_to_pods("[", None).is_err());
+    }
+}
@@ -1,34 +1,52 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::error::Error;
 use std::fs;
 use std::fs::File;
-use std::io::{BufReader, BufWriter, Write};
-use std::path::PathBuf;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::io::{BufWriter, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Instant, SystemTime};
 
 use chrono::{DateTime, Utc};
 use directories::ProjectDirs;
-use gray_matter::{engine::YAML, Matter};
+use gray_matter::{engine::Engine, Matter};
+use indexmap::IndexMap;
 use pulldown_cmark::{Event, HeadingLevel, Options, Parser, Tag, TagEnd};
 use rayon::prelude::*;
+use regex::Regex;
 use serde::{Deserialize, Serialize};
-use walkdir::WalkDir;
+use yaml_rust2::{Yaml, YamlLoader};
 
+use crate::libs::cancellation::CancellationToken;
 use crate::libs::data_fetcher::pod::Pod;
+use crate::libs::data_fetcher::FileRows;
 use crate::libs::parser::{FieldValue, FunctionArg};
+use crate::libs::progress::{Progress, ProgressEvent, Stage};
 
 #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
 pub struct MarkdownFileInfo {
     modified: String,
     title: String,
     frontmatter: Pod,
-    code_blocks: Vec<String>,
+    code_blocks: Vec<Pod>,
     links: Vec<Pod>,
     tasks: Vec<Pod>,
+    paragraphs: Vec<Pod>,
+    /// `#tags` in the text of the note, they are in the `tags` of its frontmatter as well
+    body_tags: Vec<String>,
 }
 
-pub fn fetch_frontmatter_data(args: &[FunctionArg]) -> Result<Vec<Pod>, Box<dyn Error>> {
-    let dir_path = validate_and_fetch_markdown_path_argument(args)?;
-    let mdf_files_info = get_markdown_files_info(&dir_path)?;
+pub fn fetch_frontmatter_data(
+    args: &[FunctionArg],
+    cache: &MarkdownCache,
+    cancellation: &CancellationToken,
+    progress: &Progress,
+) -> Result<Vec<Pod>, Box<dyn Error>> {
+    let dir_paths = markdown_path_arguments(args)?;
+    let mdf_files_info = get_markdown_files_info(&dir_paths, cache, cancellation, progress)?;
 
     Ok(mdf_files_info
         .into_values()
@@ -36,19 +54,48 @@ pub fn fetch_frontmatter_data(args: &[FunctionArg]) -> Result<Vec<Pod>, Box<dyn
         .collect())
 }
 
-pub fn fetch_markdown_links(args: &[FunctionArg]) -> Result<Vec<Pod>, Box<dyn Error>> {
-    let dir_path = validate_and_fetch_markdown_path_argument(args)?;
-    let mdf_files_info = get_markdown_files_info(&dir_path)?;
+pub fn fetch_markdown_links(
+    args: &[FunctionArg],
+    cache: &MarkdownCache,
+    cancellation: &CancellationToken,
+    progress: &Progress,
+) -> Result<Vec<Pod>, Box<dyn Error>> {
+    let dir_paths = markdown_path_arguments(args)?;
+    let mdf_files_info = get_markdown_files_info(&dir_paths, cache, cancellation, progress)?;
 
     Ok(mdf_files_info
         .into_values()
-        .flat_map(|mdf_info| mdf_info.links)
+        .flat_map(|mdf_info| links_with_frontmatter(&mdf_info))
         .collect())
 }
 
-pub fn fetch_markdown_tasks(args: &[FunctionArg]) -> Result<Vec<Pod>, Box<dyn Error>> {
-    let dir_path = validate_and_fetch_markdown_path_argument(args)?;
-    let mdf_files_info = get_markdown_files_info(&dir_path)?;
+/// Links of the file with the frontmatter of the file under `frontmatter` (without `file`, it is
+/// on the link already), e.g. `WHERE 'project' IN frontmatter.tags`. Added when the links are
+/// fetched, so the cache has the frontmatter only once per file.
+fn links_with_frontmatter(mdf_info: &MarkdownFileInfo) -> Vec<Pod> {
+    let mut frontmatter = mdf_info.frontmatter.clone();
+    if let Pod::Hash(hash) = &mut frontmatter {
+        hash.shift_remove("file");
+    }
+    mdf_info
+        .links
+        .iter()
+        .map(|link| {
+            let mut link = link.clone();
+            let _ = link.insert("frontmatter".to_string(), frontmatter.clone());
+            link
+        })
+        .collect()
+}
+
+pub fn fetch_markdown_tasks(
+    args: &[FunctionArg],
+    cache: &MarkdownCache,
+    cancellation: &CancellationToken,
+    progress: &Progress,
+) -> Result<Vec<Pod>, Box<dyn Error>> {
+    let dir_paths = markdown_path_arguments(args)?;
+    let mdf_files_info = get_markdown_files_info(&dir_paths, cache, cancellation, progress)?;
 
     Ok(mdf_files_info
         .into_values()
@@ -56,9 +103,396 @@ pub fn fetch_markdown_tasks(args: &[FunctionArg]) -> Result<Vec<Pod>, Box<dyn Er
         .collect())
 }
 
+/// Fenced code blocks of the files, only those of the language in the last argument
+/// when there are two or more, see [`source_path_arguments`].
+pub fn fetch_markdown_code_blocks(
+    args: &[FunctionArg],
+    cache: &MarkdownCache,
+    cancellation: &CancellationToken,
+    progress: &Progress,
+) -> Result<Vec<Pod>, Box<dyn Error>> {
+    let (path_args, lang) = split_code_block_language(args)?;
+    let dir_paths = markdown_path_arguments(path_args)?;
+    let mdf_files_info = get_markdown_files_info(&dir_paths, cache, cancellation, progress)?;
+
+    Ok(mdf_files_info
+        .into_values()
+        .flat_map(|mdf_info| code_blocks_of(mdf_info.code_blocks, lang.as_deref()))
+        .collect())
+}
+
+/// Paragraphs and list items of the files, with the headings they are under and their `^id`.
+pub fn fetch_markdown_paragraphs(
+    args: &[FunctionArg],
+    cache: &MarkdownCache,
+    cancellation: &CancellationToken,
+    progress: &Progress,
+) -> Result<Vec<Pod>, Box<dyn Error>> {
+    let dir_paths = markdown_path_arguments(args)?;
+    let mdf_files_info = get_markdown_files_info(&dir_paths, cache, cancellation, progress)?;
+
+    Ok(mdf_files_info
+        .into_values()
+        .flat_map(|mdf_info| mdf_info.paragraphs)
+        .collect())
+}
+
+/// Every note with the `file.path`s of the notes linking to it (`backlinks`) and how many there
+/// are (`backlink_count`). Links are resolved like the `path` of MD_LINKS rows, among the files in
+/// `args`, and links of a note to itself are not counted.
+pub fn fetch_markdown_backlinks(
+    args: &[FunctionArg],
+    cache: &MarkdownCache,
+    cancellation: &CancellationToken,
+    progress: &Progress,
+) -> Result<Vec<Pod>, Box<dyn Error>> {
+    let dir_paths = markdown_path_arguments(args)?;
+    let mdf_files_info = get_markdown_files_info(&dir_paths, cache, cancellation, progress)?;
+
+    // Resolved again, files parsed at different times only know the paths of files parsed with them
+    let file_paths: Vec<String> = mdf_files_info.keys().cloned().collect();
+    let titles: HashMap<String, String> = mdf_files_info
+        .iter()
+        .map(|(file_path, mdf_info)| (mdf_info.title.clone(), file_path.clone()))
+        .collect();
+    let mut backlinks: HashMap<String, BTreeSet<&str>> = HashMap::new();
+    for (file_path, mdf_info) in &mdf_files_info {
+        for link in &mdf_info.links {
+            if link.nested_get("external") != Some(&Pod::Boolean(false)) {
+                continue;
+            }
+            let Some(Pod::String(url)) = link.nested_get("url") else {
+                continue;
+            };
+            match find_matching_path(url, &file_paths, &titles) {
+                Some(target) if target != *file_path => {
+                    backlinks.entry(target).or_default().insert(file_path);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    Ok(mdf_files_info
+        .iter()
+        .map(|(file_path, mdf_info)| {
+            let sources = backlinks.remove(file_path).unwrap_or_default();
+            let mut backlink_hm = IndexMap::new();
+            backlink_hm.insert(
+                "file".to_string(),
+                mdf_info
+                    .frontmatter
+                    .nested_get("file")
+                    .cloned()
+                    .unwrap_or(Pod::Null),
+            );
+            backlink_hm.insert(
+                "backlinks".to_string(),
+                Pod::Array(
+                    sources
+                        .iter()
+                        .map(|source| Pod::String(source.to_string()))
+                        .collect(),
+                ),
+            );
+            backlink_hm.insert(
+                "backlink_count".to_string(),
+                Pod::Integer(sources.len() as i64),
+            );
+            Pod::Hash(backlink_hm)
+        })
+        .collect())
+}
+
+/// Rows of the built-in `source` (FRONTMATTER_DATA, MD_LINKS, MD_TASKS, MD_CODE_BLOCKS or
+/// MD_PARAGRAPHS) for just the `changed` files of the directories in `args`, by file path, without
+/// walking the directories. Changed files are parsed again (and cached), removed ones come back
+/// without rows, changes to other files are ignored. `None` when a directory changed, as its files
+/// are not known without a walk, and for MD_BACKLINKS, whose rows depend on the other files.
+pub fn fetch_changed_rows(
+    source: &str,
+    args: &[FunctionArg],
+    changed: &[PathBuf],
+    cache: &MarkdownCache,
+    progress: &Progress,
+) -> Result<Option<FileRows>, Box<dyn Error>> {
+    let (args, lang) = match source {
+        "MD_CODE_BLOCKS" => split_code_block_language(args)?,
+        // Backlinks of a file change with the other files
+        "MD_BACKLINKS" => return Ok(None),
+        _ => (args, None),
+    };
+    let paths = MarkdownPaths::new(&markdown_path_arguments(args)?)?;
+    let mut changed_files = BTreeSet::new();
+    for (root, glob) in &paths.roots {
+        let mut cached_files = cache
+            .files
+            .lock()
+            .map_err(|_| "Markdown cache is poisoned")?;
+        let mdf_files_info = cached_files.get_or_insert_with(|| cache.load());
+        let root_files = changed_markdown_files(root, changed, |dir| {
+            mdf_files_info
+                .keys()
+                .any(|file_path| Path::new(file_path).starts_with(dir))
+        });
+        let Some(root_files) = root_files else {
+            return Ok(None);
+        };
+        changed_files.extend(
+            root_files
+                .into_iter()
+                .filter(|path| paths.includes(root, glob.as_ref(), path)),
+        );
+    }
+    if changed_files.is_empty() {
+        return Ok(Some(BTreeMap::new()));
+    }
+
+    let existing: Vec<PathBuf> = changed_files
+        .iter()
+        .filter(|path| path.is_file())
+        .cloned()
+        .collect();
+    let (parsed_files, parse_errors) = parse_files(
+        existing,
+        cache.max_file_size,
+        &CancellationToken::default(),
+        progress,
+    )?;
+
+    let mut cached_files = cache
+        .files
+        .lock()
+        .map_err(|_| "Markdown cache is poisoned")?;
+    let mdf_files_info = cached_files.get_or_insert_with(|| cache.load());
+    // Removed files, and files that fail to parse now, are left without rows
+    let mut rows: FileRows = changed_files
+        .iter()
+        .map(|path| (path.display().to_string(), Vec::new()))
+        .collect();
+    for file_path in rows.keys() {
+        mdf_files_info.remove(file_path);
+    }
+    for (file_path, mdf_info) in parsed_files {
+        rows.insert(
+            file_path.clone(),
+            source_rows(source, lang.as_deref(), &mdf_info),
+        );
+        mdf_files_info.insert(file_path, CachedFile::Parsed(mdf_info));
+    }
+    if cache.persist {
+        if let Ok(path) = cache.file_path() {
+            save_cache(&path, mdf_files_info);
+        }
+    }
+
+    if let Ok(mut cache_errors) = cache.errors.lock() {
+        cache_errors.retain(|error| !rows.contains_key(&error.path));
+        cache_errors.extend(parse_errors);
+        cache_errors.sort_by(|a, b| a.path.cmp(&b.path));
+    }
+
+    Ok(Some(rows))
+}
+
+fn source_rows(source: &str, lang: Option<&str>, mdf_info: &MarkdownFileInfo) -> Vec<Pod> {
+    match source {
+        "MD_LINKS" => links_with_frontmatter(mdf_info),
+        "MD_TASKS" => mdf_info.tasks.clone(),
+        "MD_CODE_BLOCKS" => code_blocks_of(mdf_info.code_blocks.clone(), lang),
+        "MD_PARAGRAPHS" => mdf_info.paragraphs.clone(),
+        _ => vec![mdf_info.frontmatter.clone()],
+    }
+}
+
+/// `changed` paths under `root` as walking `root` would find them, markdown files only. `None`
+/// when one of them is a directory, or was one (`is_known_dir`) before it got removed.
+fn changed_markdown_files<F>(
+    root: &Path,
+    changed: &[PathBuf],
+    is_known_dir: F,
+) -> Option<Vec<PathBuf>>
+where
+    F: Fn(&Path) -> bool,
+{
+    // Watchers report canonical paths for some platforms
+    let canonical_root = fs::canonicalize(root).ok();
+    let mut files = BTreeSet::new();
+    for changed_path in changed {
+        let relative = changed_path.strip_prefix(root).ok().or_else(|| {
+            canonical_root
+                .as_ref()
+                .and_then(|canonical_root| changed_path.strip_prefix(canonical_root).ok())
+        });
+        let Some(relative) = relative else {
+            continue;
+        };
+        let path = match relative.as_os_str().is_empty() {
+            true => root.to_path_buf(),
+            false => root.join(relative),
+        };
+
+        if path.is_dir() || (!path.exists() && is_known_dir(&path)) {
+            return None;
+        }
+        if path.extension().is_some_and(|extension| extension == "md") {
+            files.insert(path);
+        }
+    }
+    Some(files.into_iter().collect())
+}
+
+/// Paths a built-in source reads, e.g. both folders of
+/// `FRONTMATTER_DATA('~/vault/projects', '~/vault/areas')`. Paths can be globs (`*` is any part
+/// of a name, `**` any number of folders and `?` one character), e.g. `~/vault/**/dailies/*.md`,
+/// and the ones starting with `!` leave files out, e.g. `!templates`. `FRONTMATTER_DATA()` reads
+/// the current directory.
+pub fn markdown_path_arguments(args: &[FunctionArg]) -> Result<Vec<String>, Box<dyn Error>> {
+    if args.is_empty() {
+        return Ok(vec![".".to_string()]);
+    }
+    args.iter()
+        .map(|arg| match arg {
+            FunctionArg::FieldValue(FieldValue::String(path)) => Ok(path.clone()),
+            _ => Err(format!("Expected a string argument, but found {:?}", arg).into()),
+        })
+        .collect()
+}
+
+/// Arguments of the built-in source `name` that are paths, all but the language of MD_CODE_BLOCKS.
+pub fn source_path_arguments<'a>(name: &str, args: &'a [FunctionArg]) -> &'a [FunctionArg] {
+    match args.split_last() {
+        Some((_, paths)) if !paths.is_empty() && name.eq_ignore_ascii_case("MD_CODE_BLOCKS") => {
+            paths
+        }
+        _ => args,
+    }
+}
+
+// Path arguments and the language of MD_CODE_BLOCKS
+type CodeBlockArguments<'a> = (&'a [FunctionArg], Option<String>);
+
+// MD_CODE_BLOCKS('~/notes', 'bash') keeps the bash blocks, the language is last of two or more
+fn split_code_block_language(
+    args: &[FunctionArg],
+) -> Result<CodeBlockArguments<'_>, Box<dyn Error>> {
+    let paths = source_path_arguments("MD_CODE_BLOCKS", args);
+    match args.get(paths.len()) {
+        None => Ok((paths, None)),
+        Some(FunctionArg::FieldValue(FieldValue::String(lang))) => Ok((paths, Some(lang.clone()))),
+        Some(arg) => Err(format!("Expected a string language, but found {:?}", arg).into()),
+    }
+}
+
+fn code_blocks_of(code_blocks: Vec<Pod>, lang: Option<&str>) -> Vec<Pod> {
+    match lang {
+        None => code_blocks,
+        Some(lang) => code_blocks
+            .into_iter()
+            .filter(|code_block| {
+                matches!(code_block.nested_get("lang"), Some(Pod::String(block_lang)) if block_lang.eq_ignore_ascii_case(lang))
+            })
+            .collect(),
+    }
+}
+
+/// Folders (and files) a built-in source walks, e.g. to watch them for changes.
+pub fn markdown_roots(args: &[FunctionArg]) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let paths = MarkdownPaths::new(&markdown_path_arguments(args)?)?;
+    Ok(paths.roots.into_iter().map(|(root, _)| root).collect())
+}
+
+/// [`markdown_path_arguments`] split into what to walk and which of the found files to keep.
+struct MarkdownPaths {
+    /// Folder or file to walk, and the glob the paths below it have to match
+    roots: Vec<(PathBuf, Option<Regex>)>,
+    /// `!` paths, matched below the root a file is found in or as a whole path
+    exclude: Vec<Regex>,
+}
+
+impl MarkdownPaths {
+    fn new(paths: &[String]) -> Result<Self, String> {
+        let mut roots = Vec::new();
+        let mut exclude = Vec::new();
+        for path in paths {
+            if let Some(pattern) = path.strip_prefix('!') {
+                exclude.push(glob_regex(&shellexpand::tilde(pattern), true)?);
+                continue;
+            }
+            let path = shellexpand::tilde(path).into_owned();
+            let Some(glob_start) = path.find(['*', '?']) else {
+                roots.push((PathBuf::from(path), None));
+                continue;
+            };
+            // The folders in front of the first glob are walked
+            match path[..glob_start].rfind('/') {
+                Some(0) => roots.push((PathBuf::from("/"), Some(glob_regex(&path[1..], false)?))),
+                Some(slash) => roots.push((
+                    PathBuf::from(&path[..slash]),
+                    Some(glob_regex(&path[slash + 1..], false)?),
+                )),
+                None => roots.push((PathBuf::from("."), Some(glob_regex(&path, false)?))),
+            }
+        }
+        // Only exclusions, e.g. `FRONTMATTER_DATA('!templates')`, leave them out of the current
+        // directory
+        if roots.is_empty() {
+            roots.push((PathBuf::from("."), None));
+        }
+        Ok(MarkdownPaths { roots, exclude })
+    }
+
+    /// Whether `path`, found by walking `root`, is read.
+    fn includes(&self, root: &Path, glob: Option<&Regex>, path: &Path) -> bool {
+        let relative = path.strip_prefix(root).unwrap_or(path).to_string_lossy();
+        let whole = path.to_string_lossy();
+        glob.is_none_or(|glob| glob.is_match(&relative))
+            && !self
+                .exclude
+                .iter()
+                .any(|exclude| exclude.is_match(&relative) || exclude.is_match(&whole))
+    }
+}
+
+// Regex of a glob over `/` separated paths. A glob without a `/` matches in any folder when
+// `or_below`, which also matches everything below the paths it matches (so `templates` leaves
+// out the whole folder).
+fn glob_regex(glob: &str, or_below: bool) -> Result<Regex, String> {
+    let glob = glob.trim_start_matches("./").trim_end_matches('/');
+    let mut regex = String::from("^");
+    if or_below && !glob.contains('/') {
+        regex.push_str("(?:.*/)?");
+    }
+    let mut chars = glob.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                match chars.next_if_eq(&'/') {
+                    Some(_) => regex.push_str("(?:.*/)?"),
+                    None => regex.push_str(".*"),
+                }
+            }
+            '*' => regex.push_str("[^/]*"),
+            '?' => regex.push_str("[^/]"),
+            c => regex.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    if or_below {
+        regex.push_str("(?:/.*)?");
+    }
+    regex.push('$');
+    Regex::new(&regex).map_err(|error| format!("Invalid path pattern {}: {}", glob, error))
+}
+
+/// The path of a source that reads one directory.
 pub fn validate_and_fetch_markdown_path_argument(
     args: &[FunctionArg],
 ) -> Result<String, Box<dyn Error>> {
+    // `FRONTMATTER_DATA()` reads the current directory
+    if args.is_empty() {
+        return Ok(".".to_string());
+    }
     if args.len() != 1 {
         return Err(format!(
             "Incorret amount of arguments, 1 String expected, but {} arguments found!",
@@ -72,27 +506,223 @@ pub fn validate_and_fetch_markdown_path_argument(
     }
 }
 
-pub fn fetch_code_snippets(dir_path: &str, _lang: String) -> Result<Vec<String>, Box<dyn Error>> {
-    let mdf_files_info = get_markdown_files_info(dir_path)?;
+/// Contents of the `lang` code blocks of the markdown files in `dir_path`, on one line each.
+pub fn fetch_code_snippets(dir_path: &str, lang: String) -> Result<Vec<String>, Box<dyn Error>> {
+    code_snippets(dir_path, &lang, &MarkdownCache::default())
+}
+
+fn code_snippets(
+    dir_path: &str,
+    lang: &str,
+    cache: &MarkdownCache,
+) -> Result<Vec<String>, Box<dyn Error>> {
+    let mdf_files_info = get_markdown_files_info(
+        &[dir_path.to_string()],
+        cache,
+        &CancellationToken::default(),
+        &Progress::default(),
+    )?;
 
     Ok(mdf_files_info
         .into_values()
-        .flat_map(|mdf_info| mdf_info.code_blocks)
+        .flat_map(|mdf_info| code_blocks_of(mdf_info.code_blocks, Some(lang)))
+        .filter_map(|code_block| match code_block.nested_get("content") {
+            Some(Pod::String(code)) => Some(
+                code.chars()
+                    .map(|c| if c == '\n' { ' ' } else { c })
+                    .collect::<String>()
+                    .trim()
+                    .to_string(),
+            ),
+            _ => None,
+        })
         .collect())
 }
 
+/// Markdown file that could not be read or whose frontmatter is not valid YAML. It is left out of
+/// the results.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileError {
+    pub path: String,
+    pub error: String,
+}
+
+impl std::fmt::Display for FileError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.path, self.error)
+    }
+}
+
+/// Number of markdown files a FROM over `dir_paths` would read, without parsing any of them.
+pub fn count_markdown_files(dir_paths: &[String]) -> Result<usize, Box<dyn Error>> {
+    let (files, _) = get_markdown_files(dir_paths, &CancellationToken::default())?;
+    Ok(files.len())
+}
+
+/// Fingerprint of the markdown files a FROM over `dir_paths` reads (paths, sizes and
+/// modification times), it changes whenever one of them is added, removed or modified. Nothing
+/// is parsed.
+pub fn fingerprint_markdown_files(
+    dir_paths: &[String],
+    cancellation: &CancellationToken,
+) -> Result<u64, Box<dyn Error>> {
+    let (files, errors) = get_markdown_files(dir_paths, cancellation)?;
+    let metadata: Vec<Option<(u64, SystemTime)>> = files
+        .par_iter()
+        .map(|path| {
+            let metadata = fs::metadata(path).ok()?;
+            Some((metadata.len(), metadata.modified().ok()?))
+        })
+        .collect();
+
+    let mut hasher = DefaultHasher::new();
+    files.hash(&mut hasher);
+    metadata.hash(&mut hasher);
+    for error in errors {
+        error.path.hash(&mut hasher);
+    }
+    Ok(hasher.finish())
+}
+
+/// Default limit for the size of a markdown file, bigger ones are most likely not notes.
+pub const DEFAULT_MAX_FILE_SIZE: u64 = 10 * 1024 * 1024;
+
+/// How much of a file is checked for NUL bytes to tell binary files apart from text.
+const BINARY_SNIFF_LEN: usize = 8000;
+
+/// Parsed markdown files, kept in memory between queries and (optionally) persisted on disk so
+/// that only modified files are parsed again.
+#[derive(Debug)]
+pub struct MarkdownCache {
+    /// Cache file location, `None` uses the OS cache dir
+    path: Option<PathBuf>,
+    persist: bool,
+    /// Bigger files are skipped (and reported), `None` reads files of any size
+    max_file_size: Option<u64>,
+    files: Mutex<Option<HashMap<String, CachedFile>>>,
+    /// Files that failed to parse in the last fetch, they are not cached so they are retried
+    errors: Mutex<Vec<FileError>>,
+}
+
+impl Default for MarkdownCache {
+    fn default() -> Self {
+        MarkdownCache::new(None, true)
+    }
+}
+
+impl MarkdownCache {
+    pub fn new(path: Option<PathBuf>, persist: bool) -> Self {
+        MarkdownCache {
+            path,
+            persist,
+            max_file_size: Some(DEFAULT_MAX_FILE_SIZE),
+            files: Mutex::new(None),
+            errors: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn with_max_file_size(mut self, max_file_size: Option<u64>) -> Self {
+        self.max_file_size = max_file_size;
+        self
+    }
+
+    /// Files that could not be parsed by the last fetch, ordered by path.
+    pub fn errors(&self) -> Vec<FileError> {
+        self.errors
+            .lock()
+            .map(|errors| errors.clone())
+            .unwrap_or_default()
+    }
+
+    pub(crate) fn set_errors(&self, errors: Vec<FileError>) {
+        if let Ok(mut cache_errors) = self.errors.lock() {
+            *cache_errors = errors;
+        }
+    }
+
+    /// Whether a cache from a previous run exists (only changed files will be parsed).
+    pub fn exists(&self) -> bool {
+        if self.files.lock().is_ok_and(|files| files.is_some()) {
+            return true;
+        }
+        self.persist && self.file_path().is_ok_and(|path| path.exists())
+    }
+
+    /// Drops the in-memory copy, the next query will load it from disk again.
+    pub fn clear(&self) {
+        if let Ok(mut files) = self.files.lock() {
+            *files = None;
+        }
+    }
+
+    // Files of the cache file, or none when it is not persisted
+    fn load(&self) -> HashMap<String, CachedFile> {
+        match self.file_path() {
+            Ok(path) if self.persist => load_cache(&path),
+            _ => HashMap::new(),
+        }
+    }
+
+    fn file_path(&self) -> Result<PathBuf, Box<dyn Error>> {
+        match &self.path {
+            Some(path) => Ok(path.clone()),
+            None => get_cache_file_path(),
+        }
+    }
+}
+
+/// File in the [`MarkdownCache`]. Files loaded from the cache file stay serialized until a query
+/// reads them, so a big cache does not have to be deserialized as a whole on startup.
+#[derive(Debug, Clone)]
+enum CachedFile {
+    Parsed(MarkdownFileInfo),
+    Stored {
+        modified: String,
+        /// Content of the whole cache file, shared by all stored files
+        data: Arc<Vec<u8>>,
+        range: Range<usize>,
+    },
+}
+
+impl CachedFile {
+    fn modified(&self) -> &str {
+        match self {
+            CachedFile::Parsed(mdf_info) => &mdf_info.modified,
+            CachedFile::Stored { modified, .. } => modified,
+        }
+    }
+
+    fn materialize(&mut self) -> Result<&MarkdownFileInfo, bincode::Error> {
+        if let CachedFile::Stored { data, range, .. } = self {
+            *self = CachedFile::Parsed(bincode::deserialize(&data[range.clone()])?);
+        }
+        match self {
+            CachedFile::Parsed(mdf_info) => Ok(mdf_info),
+            CachedFile::Stored { .. } => unreachable!("stored files were just deserialized"),
+        }
+    }
+}
+
+/// Markdown files in `dir_path` by path, ordered so that rows come out the same on every run.
+#[tracing::instrument(level = "debug", skip_all, fields(dir = dir_paths.join(", ")))]
 fn get_markdown_files_info(
-    dir_path: &str,
-) -> Result<HashMap<String, MarkdownFileInfo>, Box<dyn Error>> {
-    let files = get_markdown_files(&shellexpand::tilde(dir_path).into_owned())?;
+    dir_paths: &[String],
+    cache: &MarkdownCache,
+    cancellation: &CancellationToken,
+    progress: &Progress,
+) -> Result<BTreeMap<String, MarkdownFileInfo>, Box<dyn Error>> {
+    let started = Instant::now();
+    let (files, mut errors) = get_markdown_files(dir_paths, cancellation)?;
+    progress.report(ProgressEvent::FilesDiscovered(files.len()));
+    progress.stage_finished(Stage::Walk, started, files.len());
 
     // Do caching of markdown files info
-    let mut mdf_files_info = load_cache();
-    if mdf_files_info.is_empty() {
-        let mdf_info = parse_files(files)?;
-        save_cache(&mdf_info);
-        return Ok(mdf_info);
-    }
+    let started = Instant::now();
+    let mut cached_files = cache
+        .files
+        .lock()
+        .map_err(|_| "Markdown cache is poisoned")?;
+    let mdf_files_info = cached_files.get_or_insert_with(|| cache.load());
 
     let file_paths: HashSet<String> = files
         .iter()
@@ -102,16 +732,18 @@ fn get_markdown_files_info(
     let files_to_parse: Vec<PathBuf> = files
         .into_iter()
         .filter(|file_path| {
-            let mdf_info = mdf_files_info.get(&file_path.display().to_string());
-            if mdf_info.is_none() {
+            let Some(cached_file) = mdf_files_info.get_mut(&file_path.display().to_string()) else {
                 return true;
-            }
+            };
             let metadata = fs::metadata(file_path);
             match metadata {
                 Ok(metadata) => {
                     if let Ok(modified_time) = metadata.modified() {
                         let modified = DateTime::<Utc>::from(modified_time).to_rfc3339();
-                        return mdf_info.unwrap().modified < modified;
+                        // Only files of the queried directory are deserialized, the ones that
+                        // can not be are parsed again
+                        return cached_file.modified() < modified.as_str()
+                            || cached_file.materialize().is_err();
                     }
                     true
                 }
@@ -119,23 +751,58 @@ fn get_markdown_files_info(
             }
         })
         .collect();
+    tracing::debug!(
+        files = file_paths.len(),
+        to_parse = files_to_parse.len(),
+        "checked markdown cache"
+    );
+    progress.stage_finished(
+        Stage::CacheLoad,
+        started,
+        file_paths.len() - files_to_parse.len(),
+    );
 
     if !files_to_parse.is_empty() {
-        let new_mdf_files_info = parse_files(files_to_parse)?;
+        let started = Instant::now();
+        let parsed = files_to_parse.len();
+        let (new_mdf_files_info, parse_errors) =
+            parse_files(files_to_parse, cache.max_file_size, cancellation, progress)?;
+        progress.stage_finished(Stage::Parse, started, parsed);
+        errors.extend(parse_errors);
         for (file_path, new_mdf_info) in new_mdf_files_info {
-            mdf_files_info.insert(file_path, new_mdf_info);
+            mdf_files_info.insert(file_path, CachedFile::Parsed(new_mdf_info));
+        }
+        if cache.persist {
+            if let Ok(path) = cache.file_path() {
+                save_cache(&path, mdf_files_info);
+            }
         }
-        save_cache(&mdf_files_info);
     }
 
-    // Filter out files that are not in the requestd directory
-    mdf_files_info.retain(|file_path, _| file_paths.contains(file_path));
+    if let Ok(mut cache_errors) = cache.errors.lock() {
+        errors.sort_by(|a, b| a.path.cmp(&b.path));
+        *cache_errors = errors;
+    }
 
-    Ok(mdf_files_info)
+    // Filter out files that are not in the requested directories
+    Ok(mdf_files_info
+        .iter()
+        .filter(|(file_path, _)| file_paths.contains(*file_path))
+        .filter_map(|(file_path, cached_file)| match cached_file {
+            CachedFile::Parsed(mdf_info) => Some((file_path.clone(), mdf_info.clone())),
+            CachedFile::Stored { .. } => None,
+        })
+        .collect())
 }
 
-static CACHE_FILE_PATH: &str = "markdown.cache";
+// Bump the version whenever files are parsed differently, so unmodified files are parsed again
+static CACHE_FILE_PATH: &str = "markdown.v8.cache";
 fn get_cache_file_path() -> Result<PathBuf, Box<dyn Error>> {
+    Ok(cache_dir()?.join(CACHE_FILE_PATH))
+}
+
+/// krafna's directory in the OS cache dir, created if it does not exist yet.
+pub(crate) fn cache_dir() -> Result<PathBuf, Box<dyn Error>> {
     let cache_dir = ProjectDirs::from("com", "7sedam7", "krafna")
         .map(|proj_dirs| proj_dirs.cache_dir().to_path_buf())
         .ok_or("Could not determine cache directory")?;
@@ -143,82 +810,273 @@ fn get_cache_file_path() -> Result<PathBuf, Box<dyn Error>> {
     // Create the directory if it doesn't exist
     fs::create_dir_all(&cache_dir)?;
 
-    Ok(cache_dir.join(CACHE_FILE_PATH))
+    Ok(cache_dir)
 }
 
-fn save_cache(mdf_info: &HashMap<String, MarkdownFileInfo>) {
-    let file_path = match get_cache_file_path() {
-        Ok(path) => path,
-        Err(_) => return,
+/// Entry of the index at the start of the cache file, the serialized files follow the index in
+/// the same order.
+#[derive(Serialize, Deserialize)]
+struct CacheIndexEntry {
+    path: String,
+    modified: String,
+    len: u64,
+}
+
+// Layout: index length (u64, little endian), index, serialized files
+#[tracing::instrument(level = "debug", skip_all, fields(files = mdf_info.len()))]
+fn save_cache(file_path: &PathBuf, mdf_info: &HashMap<String, CachedFile>) {
+    let mut index = Vec::new();
+    let mut blobs = Vec::new();
+    for (path, cached_file) in mdf_info {
+        let blob = match cached_file {
+            CachedFile::Parsed(mdf_info) => match bincode::serialize(mdf_info) {
+                Ok(blob) => blob,
+                Err(_) => continue,
+            },
+            // Never deserialized, the stored bytes are written back as they are
+            CachedFile::Stored { data, range, .. } => data[range.clone()].to_vec(),
+        };
+        index.push(CacheIndexEntry {
+            path: path.clone(),
+            modified: cached_file.modified().to_string(),
+            len: blob.len() as u64,
+        });
+        blobs.push(blob);
+    }
+    let Ok(index) = bincode::serialize(&index) else {
+        return;
     };
+
     let file = match File::create(file_path) {
         Ok(file) => file,
         Err(_) => return,
     };
     let mut writer = BufWriter::new(file);
-    if bincode::serialize_into(&mut writer, &mdf_info).is_ok() {
+    let written = writer
+        .write_all(&(index.len() as u64).to_le_bytes())
+        .and_then(|_| writer.write_all(&index))
+        .and_then(|_| blobs.iter().try_for_each(|blob| writer.write_all(blob)));
+    if written.is_ok() {
         let _ = writer.flush(); // Ensure all data is written to disk
     }
 }
 
-fn load_cache() -> HashMap<String, MarkdownFileInfo> {
-    let file_path = match get_cache_file_path() {
-        Ok(path) => path,
-        Err(e) => {
-            eprintln!("[LOAD MD CACHE] Error getting file path: {}", e);
-            return HashMap::new();
-        }
-    };
-    let file = match File::open(file_path) {
-        Ok(file) => file,
+#[tracing::instrument(level = "debug", skip_all)]
+fn load_cache(file_path: &PathBuf) -> HashMap<String, CachedFile> {
+    let data = match fs::read(file_path) {
+        Ok(data) => data,
+        // First run (or first run after the cache version changed)
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return HashMap::new(),
         Err(e) => {
             eprintln!("[LOAD MD CACHE] Error opening a file: {}", e);
             return HashMap::new();
         }
     };
-    let reader = BufReader::new(file);
-    bincode::deserialize_from::<BufReader<File>, HashMap<String, MarkdownFileInfo>>(reader)
-        .unwrap_or_else(|e| {
-            eprintln!("[LOAD MD CACHE] Error deserializing: {}", e);
-            HashMap::new()
-        })
+    read_cache_index(Arc::new(data)).unwrap_or_else(|e| {
+        eprintln!("[LOAD MD CACHE] Error deserializing: {}", e);
+        HashMap::new()
+    })
 }
 
-fn get_markdown_files(dir: &String) -> Result<Vec<PathBuf>, Box<dyn Error>> {
-    let mut markdown_files = Vec::new();
+// Only the index is deserialized, files are left as ranges of `data`
+fn read_cache_index(data: Arc<Vec<u8>>) -> Result<HashMap<String, CachedFile>, String> {
+    let index_len = data
+        .get(..8)
+        .and_then(|bytes| bytes.try_into().ok())
+        .map(u64::from_le_bytes)
+        .ok_or("Cache file is too short")? as usize;
+    let index_end = 8_usize
+        .checked_add(index_len)
+        .filter(|end| *end <= data.len())
+        .ok_or("Cache index is cut off")?;
+    let index: Vec<CacheIndexEntry> =
+        bincode::deserialize(&data[8..index_end]).map_err(|e| e.to_string())?;
+
+    let mut start = index_end;
+    let mut files = HashMap::new();
+    for entry in index {
+        let end = start
+            .checked_add(entry.len as usize)
+            .filter(|end| *end <= data.len())
+            .ok_or("Cached file is cut off")?;
+        files.insert(
+            entry.path,
+            CachedFile::Stored {
+                modified: entry.modified,
+                data: Arc::clone(&data),
+                range: start..end,
+            },
+        );
+        start = end;
+    }
+    Ok(files)
+}
 
-    for entry in WalkDir::new(dir)
-        .follow_links(true)
-        .into_iter()
-        .filter_map(|e| e.ok())
-    {
-        if entry.file_type().is_file() {
-            let path = entry.path();
-            if let Some(extension) = path.extension() {
-                if extension == "md" {
-                    markdown_files.push(path.to_path_buf());
+// Markdown files of all `dirs`, a file in several of them is read once
+fn get_markdown_files(
+    dirs: &[String],
+    cancellation: &CancellationToken,
+) -> Result<(Vec<PathBuf>, Vec<FileError>), Box<dyn Error>> {
+    find_files(dirs, "md", cancellation)
+}
+
+/// Files with the `extension` in all `dirs` (folders, files, globs and `!` exclusions like the
+/// path arguments of FRONTMATTER_DATA), a file in several of them is found once.
+#[tracing::instrument(level = "debug", skip_all, fields(dir = dirs.join(", ")))]
+pub(crate) fn find_files(
+    dirs: &[String],
+    extension: &str,
+    cancellation: &CancellationToken,
+) -> Result<(Vec<PathBuf>, Vec<FileError>), Box<dyn Error>> {
+    let paths = MarkdownPaths::new(dirs)?;
+    let mut entries = Vec::new();
+    for (root, glob) in &paths.roots {
+        let root_entries = if root.is_file() {
+            vec![Ok(root.clone())]
+        } else {
+            let ancestors: Vec<PathBuf> = fs::canonicalize(root).into_iter().collect();
+            walk_dir(root, extension, &ancestors, cancellation)
+        };
+        entries.extend(root_entries.into_iter().filter(|entry| {
+            let path = match entry {
+                Ok(path) => path.as_path(),
+                Err(error) => Path::new(&error.path),
+            };
+            paths.includes(root, glob.as_ref(), path)
+        }));
+    }
+    // Partial walks must not be used
+    cancellation.check()?;
+
+    let mut markdown_files = Vec::new();
+    let mut errors = Vec::new();
+    // Symlinked folders lead to the same file under several paths, only the first one is kept
+    let mut seen_files = HashSet::new();
+    for entry in entries {
+        match entry {
+            Ok(path) => {
+                let canonical = fs::canonicalize(&path).unwrap_or_else(|_| path.clone());
+                if seen_files.insert(canonical) {
+                    markdown_files.push(path);
                 }
             }
+            Err(error) => errors.push(error),
         }
     }
 
-    Ok(markdown_files)
+    tracing::debug!(files = markdown_files.len(), extension, "discovered files");
+    Ok((markdown_files, errors))
 }
 
-fn parse_files(files: Vec<PathBuf>) -> Result<HashMap<String, MarkdownFileInfo>, Box<dyn Error>> {
-    let matter = Matter::<YAML>::new();
+/// Files with the `extension` under `dir` (following symlinks), with subdirectories walked in
+/// parallel. Entries come in file name order, depth first, the same on every run. `ancestors` are
+/// the canonical paths of the directories above, to detect symlink loops.
+///
+/// Unreadable directories, broken symlinks and symlink loops are reported, unless the entry can
+/// not be a wanted file (other extensions).
+fn walk_dir(
+    dir: &Path,
+    extension: &str,
+    ancestors: &[PathBuf],
+    cancellation: &CancellationToken,
+) -> Vec<Result<PathBuf, FileError>> {
+    let file_error = |path: &Path, error: String| FileError {
+        path: path.display().to_string(),
+        error,
+    };
+    if cancellation.is_cancelled() {
+        return Vec::new();
+    }
+    let mut paths: Vec<PathBuf> = match fs::read_dir(dir) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .collect(),
+        Err(e) => return vec![Err(file_error(dir, format!("Could not read: {}", e)))],
+    };
+    paths.sort_by(|a, b| a.file_name().cmp(&b.file_name()));
+
+    paths
+        .par_iter()
+        .flat_map_iter(|path| {
+            let is_wanted = path.extension().is_some_and(|found| found == extension);
+            match fs::metadata(path) {
+                Ok(metadata) if metadata.is_dir() => {
+                    let canonical = fs::canonicalize(path).unwrap_or_else(|_| path.clone());
+                    if ancestors.contains(&canonical) {
+                        let error = format!("Symlink loop back to {}", canonical.display());
+                        return vec![Err(file_error(path, error))];
+                    }
+                    let mut ancestors = ancestors.to_vec();
+                    ancestors.push(canonical);
+                    walk_dir(path, extension, &ancestors, cancellation)
+                }
+                Ok(metadata) if metadata.is_file() && is_wanted => vec![Ok(path.clone())],
+                Ok(_) => Vec::new(),
+                Err(e) if is_wanted || path.extension().is_none() => {
+                    vec![Err(file_error(path, format!("Could not read: {}", e)))]
+                }
+                Err(_) => Vec::new(),
+            }
+        })
+        .collect()
+}
+
+/// Parsed markdown files by path
+type ParsedFiles = HashMap<String, MarkdownFileInfo>;
+
+#[tracing::instrument(level = "debug", skip_all, fields(files = files.len()))]
+fn parse_files(
+    files: Vec<PathBuf>,
+    max_file_size: Option<u64>,
+    cancellation: &CancellationToken,
+    progress: &Progress,
+) -> Result<(ParsedFiles, Vec<FileError>), Box<dyn Error>> {
+    let matter = Matter::<SplitOnly>::new();
+    let total = files.len();
+    let parsed = AtomicUsize::new(0);
 
     // Convert to parallel iterator and collect results
-    let results: HashMap<String, MarkdownFileInfo> = files
+    let parsed_files: Vec<(String, Result<MarkdownFileInfo, String>)> = files
         .par_iter()
         //.iter()
         .filter_map(|path| {
-            let mdf_info = parse_file(path, &matter).ok()?;
+            if cancellation.is_cancelled() {
+                return None;
+            }
+            let started = Instant::now();
+            let mdf_info = parse_file(path, &matter, max_file_size).map_err(|e| e.to_string());
+            if progress.is_enabled() {
+                progress.report(ProgressEvent::FileParseTime {
+                    path: path.display().to_string(),
+                    elapsed: started.elapsed(),
+                });
+            }
+            progress.report(ProgressEvent::FileParsed {
+                parsed: parsed.fetch_add(1, Ordering::Relaxed) + 1,
+                total,
+            });
             Some((path.display().to_string(), mdf_info))
         })
         .collect();
+    // Partial results must not end up in the cache
+    cancellation.check()?;
+
+    let mut results = HashMap::new();
+    let mut errors = Vec::new();
+    for (path, mdf_info) in parsed_files {
+        match mdf_info {
+            Ok(mdf_info) => {
+                results.insert(path, mdf_info);
+            }
+            Err(error) => errors.push(FileError { path, error }),
+        }
+    }
+    if !errors.is_empty() {
+        tracing::debug!(errors = errors.len(), "markdown files failed to parse");
+    }
 
-    Ok(add_link_paths(results))
+    Ok((add_link_paths(results), errors))
 }
 
 fn add_link_paths(
@@ -357,23 +1215,27 @@ fn find_matching_path(
     None
 }
 
-fn parse_file(path: &PathBuf, matter: &Matter<YAML>) -> Result<MarkdownFileInfo, Box<dyn Error>> {
-    let content = fs::read_to_string(path)?;
+fn parse_file(
+    path: &PathBuf,
+    matter: &Matter<SplitOnly>,
+    max_file_size: Option<u64>,
+) -> Result<MarkdownFileInfo, Box<dyn Error>> {
+    let content = read_markdown(path, max_file_size)?;
 
     // Extract frontmatter
-    let result = matter.parse(&content);
-    let mut frontmatter = result
-        .data
-        .as_ref()
-        .map(gray_matter_pod_to_pod)
-        .unwrap_or_else(Pod::new_hash);
-    let markdown_content = result.content;
+    let (frontmatter, markdown_content) = split_frontmatter(&normalize_content(&content), matter)?;
+    let mut frontmatter = match frontmatter {
+        Pod::Hash(hash) => Pod::Hash(hash),
+        Pod::Null => Pod::new_hash(),
+        _ => return Err("Invalid frontmatter: expected field: value pairs".into()),
+    };
 
     let file_data = get_file_info(path);
-    let _ = frontmatter.insert("file".to_string(), Pod::Hash(file_data.clone()));
 
     // Parse the rest of markdfown for title,code, links, and tasks
     let mut mdf_info = parse_markdown_content(&markdown_content, &file_data);
+    add_body_tags(&mut frontmatter, &mdf_info.body_tags);
+    let _ = frontmatter.insert("file".to_string(), Pod::Hash(file_data.clone()));
     mdf_info.modified = match file_data.get("modified") {
         Some(modified_pod) => modified_pod.to_string(),
         None => "".to_string(),
@@ -383,9 +1245,152 @@ fn parse_file(path: &PathBuf, matter: &Matter<YAML>) -> Result<MarkdownFileInfo,
     Ok(mdf_info)
 }
 
+// Tags in the text are tags of the note, after the frontmatter ones, like Obsidian has them
+fn add_body_tags(frontmatter: &mut Pod, body_tags: &[String]) {
+    let Pod::Hash(hash) = frontmatter else {
+        return;
+    };
+    if body_tags.is_empty() {
+        return;
+    }
+    let tags = hash.entry("tags".to_string()).or_insert(Pod::Null);
+    let mut all_tags = match std::mem::replace(tags, Pod::Null) {
+        Pod::Array(tags) => tags,
+        Pod::Null => vec![],
+        // `tags: project`
+        tag => vec![tag],
+    };
+    for tag in body_tags {
+        let tag = Pod::String(tag.clone());
+        if !all_tags.contains(&tag) {
+            all_tags.push(tag);
+        }
+    }
+    *tags = Pod::Array(all_tags);
+}
+
+/// Frontmatter of `content` and the markdown after it, as YAML, TOML or JSON, see
+/// [`toml_or_json_frontmatter`].
+fn split_frontmatter(content: &str, matter: &Matter<SplitOnly>) -> Result<(Pod, String), String> {
+    let invalid = |e: &dyn std::fmt::Display| format!("Invalid frontmatter: {}", e);
+    if let Some((format, range, markdown_start)) = toml_or_json_frontmatter(content) {
+        let value: serde_json::Value = match format {
+            FrontmatterFormat::Toml => toml::from_str(&content[range]).map_err(|e| invalid(&e))?,
+            FrontmatterFormat::Json => {
+                serde_json::from_str(&content[range]).map_err(|e| invalid(&e))?
+            }
+        };
+        return Ok((
+            toml_dates_to_strings(Pod::from_json_value(&value)),
+            content[markdown_start..].to_string(),
+        ));
+    }
+
+    let result = matter.parse(content);
+    let frontmatter = match result.data {
+        Some(_) => {
+            let docs = YamlLoader::load_from_str(&result.matter).map_err(|e| invalid(&e))?;
+            docs.first().map_or(Pod::Null, yaml_to_pod)
+        }
+        None => Pod::Null,
+    };
+    Ok((frontmatter, result.content))
+}
+
+#[derive(Debug, PartialEq)]
+pub(crate) enum FrontmatterFormat {
+    Toml,
+    Json,
+}
+
+/// TOML frontmatter between `+++` lines, or JSON frontmatter from a `{` line to a `}` line (like
+/// Hugo has them), that `content` starts with: its format, its range without the `+++` lines, and
+/// where the markdown after it starts. YAML frontmatter is split by gray_matter.
+pub(crate) fn toml_or_json_frontmatter(
+    content: &str,
+) -> Option<(FrontmatterFormat, Range<usize>, usize)> {
+    let start = content.len() - content.trim_start().len();
+    let mut lines = content[start..].split_inclusive('\n');
+    let first = lines.next()?;
+    let (format, closing) = match first.trim_end() {
+        "+++" => (FrontmatterFormat::Toml, "+++"),
+        "{" => (FrontmatterFormat::Json, "}"),
+        _ => return None,
+    };
+    let mut end = start + first.len();
+    for line in lines {
+        if line.trim_end() == closing {
+            let range = match format {
+                FrontmatterFormat::Toml => start + first.len()..end,
+                FrontmatterFormat::Json => start..end + line.len(),
+            };
+            return Some((format, range, end + line.len()));
+        }
+        end += line.len();
+    }
+    None
+}
+
+// TOML dates come through serde as a `{"$__toml_private_datetime": "1979-05-27"}` table, they are
+// strings like YAML dates are
+const TOML_DATETIME_KEY: &str = "$__toml_private_datetime";
+
+fn toml_dates_to_strings(pod: Pod) -> Pod {
+    match pod {
+        Pod::Hash(hash) if hash.len() == 1 && hash.contains_key(TOML_DATETIME_KEY) => {
+            hash.into_values().next().unwrap_or(Pod::Null)
+        }
+        Pod::Hash(hash) => Pod::Hash(
+            hash.into_iter()
+                .map(|(key, value)| (key, toml_dates_to_strings(value)))
+                .collect(),
+        ),
+        Pod::Array(array) => Pod::Array(array.into_iter().map(toml_dates_to_strings).collect()),
+        pod => pod,
+    }
+}
+
+/// Reads a file as text, unless it is bigger than `max_file_size` or looks like a binary file.
+fn read_markdown(path: &PathBuf, max_file_size: Option<u64>) -> Result<String, String> {
+    let read_error = |e: std::io::Error| format!("Could not read: {}", e);
+    if let Some(max_file_size) = max_file_size {
+        let size = fs::metadata(path).map_err(read_error)?.len();
+        if size > max_file_size {
+            return Err(format!(
+                "Skipped, {} bytes is over the {} bytes file size limit",
+                size, max_file_size
+            ));
+        }
+    }
+
+    let bytes = fs::read(path).map_err(read_error)?;
+    if bytes.iter().take(BINARY_SNIFF_LEN).any(|byte| *byte == 0) {
+        return Err("Skipped, looks like a binary file".to_string());
+    }
+    String::from_utf8(bytes).map_err(|_| "Not valid UTF-8".to_string())
+}
+
+/// Removes what keeps the frontmatter from being detected in notes from Windows or other apps: a
+/// BOM, CRLF line endings and blank lines before the opening `---` (or `+++`, `{`).
+fn normalize_content(content: &str) -> String {
+    let content = content
+        .strip_prefix('\u{feff}')
+        .unwrap_or(content)
+        .replace("\r\n", "\n");
+    let trimmed = content.trim_start();
+    if ["---", "+++\n", "{\n"]
+        .iter()
+        .any(|start| trimmed.starts_with(start))
+    {
+        trimmed.to_string()
+    } else {
+        content
+    }
+}
+
 fn parse_markdown_content(
     markdown_content: &str,
-    file_data: &HashMap<String, Pod>,
+    file_data: &IndexMap<String, Pod>,
 ) -> MarkdownFileInfo {
     // Parse markdown for code blocks, links, and tasks
     let parser = Parser::new_ext(
@@ -400,15 +1405,27 @@ fn parse_markdown_content(
         code_blocks: vec![],
         links: vec![],
         tasks: vec![],
+        paragraphs: vec![],
+        body_tags: vec![],
     };
 
     let mut in_title = false;
     let mut title_complete = false;
     let mut title_text = String::new();
 
+    let mut heading_level = None;
+    let mut heading_text = String::new();
+    let mut headings: Vec<(HeadingLevel, String)> = Vec::new();
+
+    // Paragraphs and list items being read, innermost last. Paragraphs of a list item are its text.
+    let mut blocks: Vec<(&str, usize, String)> = Vec::new();
+    let mut block_ord = 0;
+
     let mut in_code_block = false;
     let mut current_code = String::new();
     let mut current_code_lang = String::new();
+    let mut in_fenced_code_block = false;
+    let mut code_block_ord = 0;
 
     let mut in_link = false;
     let mut current_link = String::new();
@@ -422,42 +1439,92 @@ fn parse_markdown_content(
     let mut current_task = String::new();
     let mut task_checked = false;
 
+    // Text is split into several events, e.g. at `_`, tags are looked for in all of it
+    let mut tag_text = String::new();
+
     for event in parser {
+        if !matches!(event, Event::Text(_)) && !tag_text.is_empty() {
+            push_body_tags(&tag_text, &mut mdf_info.body_tags);
+            tag_text.clear();
+        }
         match event {
-            // Title
-            Event::Start(Tag::Heading { level, .. }) if !title_complete => {
-                if level == HeadingLevel::H1 {
+            // Title and headings
+            Event::Start(Tag::Heading { level, .. }) => {
+                heading_level = Some(level);
+                if !title_complete && level == HeadingLevel::H1 {
                     in_title = true;
                 }
             }
-            Event::End(TagEnd::Heading(_)) if !title_complete => {
-                if in_title {
-                    mdf_info.title.clone_from(&title_text);
-                    title_complete = true;
+            Event::End(TagEnd::Heading(_)) => {
+                if !title_complete {
+                    if in_title {
+                        mdf_info.title.clone_from(&title_text);
+                        title_complete = true;
+                    }
+                    in_title = false;
+                    title_text.clear();
+                }
+                if let Some(level) = heading_level.take() {
+                    headings.retain(|(parent_level, _)| *parent_level < level);
+                    headings.push((level, heading_text.trim().to_string()));
+                }
+                heading_text.clear();
+            }
+
+            // Paragraphs
+            Event::Start(Tag::Paragraph) => match blocks.last_mut() {
+                Some(("item", _, text)) => {
+                    if !text.is_empty() {
+                        text.push('\n');
+                    }
+                }
+                _ => {
+                    block_ord += 1;
+                    blocks.push(("paragraph", block_ord, String::new()));
+                }
+            },
+            Event::End(TagEnd::Paragraph) => {
+                if let Some(("paragraph", _, _)) = blocks.last() {
+                    if let Some((block_type, ord, text)) = blocks.pop() {
+                        mdf_info.paragraphs.extend(prepare_paragraph(
+                            block_type, ord, &text, &headings, file_data,
+                        ));
+                    }
+                }
+            }
+            Event::SoftBreak | Event::HardBreak => {
+                if let Some((_, _, text)) = blocks.last_mut() {
+                    text.push('\n');
+                }
+            }
+            Event::Code(code) => {
+                if let Some((_, _, text)) = blocks.last_mut() {
+                    text.push_str(&code);
                 }
-                in_title = false;
-                title_text.clear();
             }
 
             // Code blocks
             Event::Start(Tag::CodeBlock(kind)) => {
                 in_code_block = true;
-                if let pulldown_cmark::CodeBlockKind::Fenced(lang) = kind {
-                    current_code_lang = lang.to_string();
+                if let pulldown_cmark::CodeBlockKind::Fenced(info) = kind {
+                    in_fenced_code_block = true;
+                    // ```rust ignore is a rust block
+                    current_code_lang = info.split_whitespace().next().unwrap_or("").to_string();
                 }
             }
             Event::End(TagEnd::CodeBlock) => {
-                in_code_block = false;
-                if current_code_lang == "krafna" {
-                    mdf_info.code_blocks.push(
-                        current_code
-                            .chars()
-                            .map(|c| if c == '\n' { ' ' } else { c })
-                            .collect::<String>()
-                            .trim()
-                            .to_string(),
-                    )
+                if in_fenced_code_block {
+                    code_block_ord += 1;
+                    mdf_info.code_blocks.push(prepare_code_block(
+                        code_block_ord,
+                        &current_code,
+                        &current_code_lang,
+                        file_data,
+                    ));
                 }
+                in_code_block = false;
+                in_fenced_code_block = false;
+
                 current_code.clear();
                 current_code_lang.clear();
             }
@@ -467,18 +1534,16 @@ fn parse_markdown_content(
                 link_type,
                 dest_url: url,
                 ..
-            }) => {
-                if link_type == pulldown_cmark::LinkType::Inline
-                    || link_type == (pulldown_cmark::LinkType::WikiLink { has_pothole: false })
-                {
-                    in_link = true;
-                    current_link.push_str(&url);
-                    current_link_type = match link_type {
-                        pulldown_cmark::LinkType::Inline => "inline".to_string(),
-                        pulldown_cmark::LinkType::WikiLink { .. } => "wiki".to_string(),
-                        _ => "".to_string(),
-                    };
-                }
+            }) if link_type == pulldown_cmark::LinkType::Inline
+                || link_type == (pulldown_cmark::LinkType::WikiLink { has_pothole: false }) =>
+            {
+                in_link = true;
+                current_link.push_str(&url);
+                current_link_type = match link_type {
+                    pulldown_cmark::LinkType::Inline => "inline".to_string(),
+                    pulldown_cmark::LinkType::WikiLink { .. } => "wiki".to_string(),
+                    _ => "".to_string(),
+                };
             }
             Event::End(TagEnd::Link) => {
                 in_link = false;
@@ -512,8 +1577,16 @@ fn parse_markdown_content(
                 task_ord.push(0);
                 in_task = false;
             }
-            Event::Start(Tag::Item) => {}
+            Event::Start(Tag::Item) => {
+                block_ord += 1;
+                blocks.push(("item", block_ord, String::new()));
+            }
             Event::End(TagEnd::Item) => {
+                if let Some((block_type, ord, text)) = blocks.pop() {
+                    mdf_info.paragraphs.extend(prepare_paragraph(
+                        block_type, ord, &text, &headings, file_data,
+                    ));
+                }
                 if in_task {
                     mdf_info.tasks.push(prepare_task(
                         &current_task,
@@ -541,8 +1614,17 @@ fn parse_markdown_content(
                 if in_title {
                     title_text.push_str(&text);
                 }
+                if heading_level.is_some() {
+                    heading_text.push_str(&text);
+                }
+                if !in_code_block && !in_link {
+                    tag_text.push_str(&text);
+                }
                 if in_code_block {
                     current_code.push_str(&text);
+                } else if let (None, Some((_, _, block_text))) = (heading_level, blocks.last_mut())
+                {
+                    block_text.push_str(&text);
                 }
                 if in_link {
                     current_link_text.push_str(&text);
@@ -560,18 +1642,56 @@ fn parse_markdown_content(
             _ => {}
         }
     }
+    push_body_tags(&tag_text, &mut mdf_info.body_tags);
+    // List items end after their nested items
+    mdf_info
+        .paragraphs
+        .sort_by_key(|paragraph| match paragraph.nested_get("ord") {
+            Some(Pod::Integer(ord)) => *ord,
+            _ => 0,
+        });
 
     mdf_info
 }
 
+/// Byte ranges of the `[ ]`/`[x]` markers of the tasks in `markdown_content` (without the
+/// frontmatter), with the `ord` of their task as in MD_TASKS rows.
+pub(crate) fn task_markers(markdown_content: &str) -> Vec<(String, Range<usize>)> {
+    let parser = Parser::new_ext(
+        markdown_content,
+        Options::ENABLE_TASKLISTS | Options::ENABLE_WIKILINKS,
+    );
+
+    // Numbered like in parse_markdown_content
+    let mut task_ord: Vec<usize> = Vec::new();
+    let mut markers = Vec::new();
+    for (event, range) in parser.into_offset_iter() {
+        match event {
+            Event::Start(Tag::List(_)) => task_ord.push(0),
+            Event::End(TagEnd::List(_)) => {
+                task_ord.pop();
+            }
+            Event::TaskListMarker(_) => {
+                if let Some(n) = task_ord.last_mut() {
+                    *n += 1;
+                }
+                let ord: Vec<String> = task_ord.iter().map(|n| n.to_string()).collect();
+                markers.push((ord.join("."), range));
+            }
+            _ => {}
+        }
+    }
+    markers
+}
+
 fn prepare_link(
     link_ord: usize,
     current_link: &str,
     current_link_text: &str,
     current_link_type: &str,
-    file_data: &HashMap<String, Pod>,
+    file_data: &IndexMap<String, Pod>,
 ) -> Pod {
-    let mut link_hm = HashMap::new();
+    let mut link_hm = IndexMap::new();
 
     link_hm.insert("file".to_string(), Pod::Hash(file_data.clone()));
     link_hm.insert("ord".to_string(), Pod::Integer(link_ord as i64));
@@ -599,13 +1719,108 @@ fn prepare_link(
     Pod::Hash(link_hm)
 }
 
+fn prepare_code_block(
+    code_block_ord: usize,
+    current_code: &str,
+    current_code_lang: &str,
+    file_data: &IndexMap<String, Pod>,
+) -> Pod {
+    let mut code_block_hm = IndexMap::new();
+
+    code_block_hm.insert("file".to_string(), Pod::Hash(file_data.clone()));
+    code_block_hm.insert("ord".to_string(), Pod::Integer(code_block_ord as i64));
+    code_block_hm.insert(
+        "lang".to_string(),
+        Pod::String(current_code_lang.to_owned()),
+    );
+    code_block_hm.insert(
+        "content".to_string(),
+        Pod::String(current_code.trim_end_matches('\n').to_owned()),
+    );
+
+    Pod::Hash(code_block_hm)
+}
+
+// `#tag`s in `text` that are not in `tags` yet. As in Obsidian, a tag comes after a space (or at
+// the start), has letters, digits, `_`, `-` and `/` in it, and is not only digits.
+fn push_body_tags(text: &str, tags: &mut Vec<String>) {
+    let mut previous = ' ';
+    for (i, c) in text.char_indices() {
+        if c == '#' && previous.is_whitespace() {
+            let tag: String = text[i + 1..]
+                .chars()
+                .take_while(|c| c.is_alphanumeric() || matches!(c, '_' | '-' | '/'))
+                .collect();
+            let tag = tag.trim_end_matches('/');
+            if tag.chars().any(|c| c.is_alphabetic() || c == '_')
+                && !tags.iter().any(|known| known == tag)
+            {
+                tags.push(tag.to_string());
+            }
+        }
+        previous = c;
+    }
+}
+
+// A block without text, e.g. a list item that only has a nested list, is not a row
+fn prepare_paragraph(
+    block_type: &str,
+    block_ord: usize,
+    block_text: &str,
+    headings: &[(HeadingLevel, String)],
+    file_data: &IndexMap<String, Pod>,
+) -> Option<Pod> {
+    let (text, block_id) = split_block_id(block_text.trim());
+    if text.is_empty() && block_id.is_none() {
+        return None;
+    }
+    let mut paragraph_hm = IndexMap::new();
+
+    paragraph_hm.insert("file".to_string(), Pod::Hash(file_data.clone()));
+    paragraph_hm.insert("ord".to_string(), Pod::Integer(block_ord as i64));
+    paragraph_hm.insert("type".to_string(), Pod::String(block_type.to_owned()));
+    paragraph_hm.insert("text".to_string(), Pod::String(text.to_owned()));
+    paragraph_hm.insert(
+        "heading".to_string(),
+        headings
+            .last()
+            .map_or(Pod::Null, |(_, heading)| Pod::String(heading.clone())),
+    );
+    paragraph_hm.insert(
+        "headings".to_string(),
+        Pod::Array(
+            headings
+                .iter()
+                .map(|(_, heading)| Pod::String(heading.clone()))
+                .collect(),
+        ),
+    );
+    paragraph_hm.insert(
+        "block_id".to_string(),
+        block_id.map_or(Pod::Null, |id| Pod::String(id.to_owned())),
+    );
+
+    Some(Pod::Hash(paragraph_hm))
+}
+
+// `text ^abc-123` ends with the block id abc-123, the way Obsidian links to blocks
+fn split_block_id(text: &str) -> (&str, Option<&str>) {
+    let start = text.rfind(char::is_whitespace).map_or(0, |space| space + 1);
+    match text[start..].strip_prefix('^') {
+        Some(id) if !id.is_empty() && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') => {
+            (text[..start].trim_end(), Some(id))
+        }
+        _ => (text, None),
+    }
+}
+
 fn prepare_task(
     current_task: &str,
     task_checked: bool,
     task_ord: &[usize],
-    file_data: &HashMap<String, Pod>,
+    file_data: &IndexMap<String, Pod>,
 ) -> Pod {
-    let mut task_hm = HashMap::new();
+    let mut task_hm = IndexMap::new();
 
     task_hm.insert("file".to_string(), Pod::Hash(file_data.clone()));
     task_hm.insert(
@@ -627,35 +1842,57 @@ fn prepare_task(
     Pod::Hash(task_hm)
 }
 
-fn gray_matter_pod_to_pod(pod: &gray_matter::Pod) -> Pod {
-    match pod {
-        gray_matter::Pod::Null => Pod::Null,
-        gray_matter::Pod::String(s) => Pod::String(s.clone()),
-        gray_matter::Pod::Integer(i) => Pod::Integer(*i),
-        gray_matter::Pod::Float(f) => Pod::Float(*f),
-        gray_matter::Pod::Boolean(b) => Pod::Boolean(*b),
-        gray_matter::Pod::Array(arr) => {
-            Pod::Array(arr.iter().map(gray_matter_pod_to_pod).collect())
-        }
-        gray_matter::Pod::Hash(hm) => {
-            let mut new_hm = HashMap::new();
-            for (k, v) in hm {
-                new_hm.insert(k.clone(), gray_matter_pod_to_pod(v));
-            }
-            Pod::Hash(new_hm)
-        }
+/// gray_matter engine that only splits off the frontmatter, it is parsed by [`yaml_to_pod`] as
+/// gray_matter's own YAML engine loses the field order.
+struct SplitOnly;
+
+impl Engine for SplitOnly {
+    fn parse(_content: &str) -> gray_matter::Pod {
+        gray_matter::Pod::Null
+    }
+}
+
+// Converted like gray_matter does it, but hashes keep the order of the frontmatter
+fn yaml_to_pod(yaml: &Yaml) -> Pod {
+    match yaml {
+        Yaml::Real(val) => Pod::Float(val.parse().unwrap_or(0.0)),
+        Yaml::Integer(val) => Pod::Integer(*val),
+        Yaml::String(val) => Pod::String(val.clone()),
+        Yaml::Boolean(val) => Pod::Boolean(*val),
+        Yaml::Array(arr) => Pod::Array(arr.iter().map(yaml_to_pod).collect()),
+        Yaml::Hash(hash) => Pod::Hash(
+            hash.iter()
+                .filter_map(|(key, val)| {
+                    let key = match key {
+                        Yaml::String(s) | Yaml::Real(s) => s.clone(),
+                        Yaml::Boolean(b) => b.to_string(),
+                        Yaml::Integer(i) => i.to_string(),
+                        Yaml::Null => "null".to_string(),
+                        _ => return None,
+                    };
+                    Some((key, yaml_to_pod(val)))
+                })
+                .collect(),
+        ),
+        _ => Pod::Null,
     }
 }
 
-fn get_file_info(path: &PathBuf) -> HashMap<String, Pod> {
+pub(crate) fn get_file_info(path: &PathBuf) -> IndexMap<String, Pod> {
     // NOTE: potential colision with file defined values
-    let mut hash = HashMap::new();
+    let mut hash = IndexMap::new();
 
     let _ = hash.insert(
         "name".to_string(),
         Pod::String(path.file_name().unwrap().to_string_lossy().into_owned()),
     );
     let _ = hash.insert("path".to_string(), Pod::String(path.display().to_string()));
+    if let Some(folder) = path.parent() {
+        let _ = hash.insert(
+            "folder".to_string(),
+            Pod::String(folder.display().to_string()),
+        );
+    }
 
     if let Ok(metadata) = fs::metadata(path) {
         if let Ok(created_time) = metadata.created() {
@@ -680,3 +1917,449 @@ fn get_file_info(path: &PathBuf) -> HashMap<String, Pod> {
 
     hash
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_yaml_to_pod_keeps_frontmatter_order() {
+        let docs =
+            YamlLoader::load_from_str("zed: 1\ntitle: x\nalpha:\n  b: true\n  a: 2.5").unwrap();
+        let pod = yaml_to_pod(&docs[0]);
+
+        assert_eq!(
+            r#"{"zed":1,"title":"x","alpha":{"b":true,"a":2.5}}"#,
+            pod.to_untagged_json_string().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_task_markers_match_task_ords() {
+        let content = "- [ ] a\n  - [x] b\n  - c\n  - [ ] d\n\n1. [X] e\n";
+        let tasks = parse_markdown_content(content, &IndexMap::new()).tasks;
+
+        let markers = task_markers(content);
+
+        let ords: Vec<String> = tasks
+            .iter()
+            .filter_map(|task| task.nested_get("ord"))
+            .map(Pod::to_string)
+            .collect();
+        assert_eq!(vec!["1", "1.1", "1.2", "1"], ords);
+        assert_eq!(
+            ords,
+            markers
+                .iter()
+                .map(|(ord, _)| ord.clone())
+                .collect::<Vec<_>>()
+        );
+        let marked: Vec<&str> = markers.iter().map(|(_, r)| &content[r.clone()]).collect();
+        assert_eq!(vec!["[ ]", "[x]", "[ ]", "[X]"], marked);
+    }
+
+    #[test]
+    fn test_code_blocks_with_language() {
+        let content = "```bash\nls -a\ncd ..\n```\n\n    indented\n\n```rust ignore\nfn a() {}\n```\n\n```\nplain\n```\n";
+        let code_blocks = parse_markdown_content(content, &IndexMap::new()).code_blocks;
+
+        let fields = |code_block: &Pod| {
+            ["ord", "lang", "content"].map(|key| {
+                code_block
+                    .nested_get(key)
+                    .map(Pod::to_string)
+                    .unwrap_or_default()
+            })
+        };
+        assert_eq!(
+            vec![
+                ["1", "bash", "ls -a\ncd .."],
+                ["2", "rust", "fn a() {}"],
+                ["3", "", "plain"],
+            ],
+            code_blocks.iter().map(fields).collect::<Vec<_>>()
+        );
+        let rust_blocks = code_blocks_of(code_blocks, Some("RUST"));
+        assert_eq!(
+            vec![["2", "rust", "fn a() {}"]],
+            rust_blocks.iter().map(fields).collect::<Vec<_>>()
+        );
+
+        let path = |p: &str| FunctionArg::FieldValue(FieldValue::String(p.to_string()));
+        let args = [path("a"), path("b"), path("bash")];
+        let (paths, lang) = split_code_block_language(&args).unwrap();
+        assert_eq!(&args[..2], paths);
+        assert_eq!(Some("bash".to_string()), lang);
+        assert_eq!(
+            &args[..1],
+            source_path_arguments("MD_CODE_BLOCKS", &args[..1])
+        );
+        assert_eq!(&args[..], source_path_arguments("MD_TASKS", &args));
+    }
+
+    #[test]
+    fn test_fetch_code_snippets_of_language() {
+        let dir = std::env::temp_dir().join(format!("krafna-snippets-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("a.md"),
+            "```krafna\nSELECT title\nFROM FRONTMATTER_DATA('.')\n```\n\n```dataview\nLIST\nFROM \"work\"\n```\n",
+        )
+        .unwrap();
+
+        let snippets = code_snippets(
+            &dir.display().to_string(),
+            "dataview",
+            &MarkdownCache::new(None, false),
+        );
+        let _ = fs::remove_dir_all(&dir);
+
+        assert_eq!(vec!["LIST FROM \"work\""], snippets.unwrap());
+    }
+
+    #[test]
+    fn test_paragraphs_with_headings_and_block_ids() {
+        let content = "# A\n\nIntro with `code`\nand more ^intro\n\n## B\n\n- met Ana ^m-1\n  - follow up\n- [ ] ask Ana\n\n  about it\n\n```\nnot a paragraph\n```\n\n# C\n\n> quoted\n";
+        let paragraphs = parse_markdown_content(content, &IndexMap::new()).paragraphs;
+
+        let fields = |paragraph: &Pod| {
+            ["ord", "type", "text", "heading", "block_id"].map(|key| {
+                paragraph
+                    .nested_get(key)
+                    .map(Pod::to_string)
+                    .unwrap_or_default()
+            })
+        };
+        assert_eq!(
+            vec![
+                ["1", "paragraph", "Intro with code\nand more", "A", "intro"],
+                ["2", "item", "met Ana", "B", "m-1"],
+                ["3", "item", "follow up", "B", "NULL"],
+                ["4", "item", "ask Ana\nabout it", "B", "NULL"],
+                ["5", "paragraph", "quoted", "C", "NULL"],
+            ],
+            paragraphs.iter().map(fields).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            Some(&Pod::Array(vec![
+                Pod::String("A".to_string()),
+                Pod::String("B".to_string())
+            ])),
+            paragraphs[1].nested_get("headings")
+        );
+
+        assert_eq!(("a ^b c", None), split_block_id("a ^b c"));
+        assert_eq!(("a^b", None), split_block_id("a^b"));
+        assert_eq!(("", Some("b")), split_block_id("^b"));
+    }
+
+    #[test]
+    fn test_cache_files_are_deserialized_when_read() {
+        let path = std::env::temp_dir().join(format!("krafna-cache-{}", std::process::id()));
+        let mut mdf_info = parse_markdown_content("# A\n- [ ] task", &IndexMap::new());
+        mdf_info.modified = "2025-01-01T00:00:00+00:00".to_string();
+        let mut files = HashMap::new();
+        files.insert("a.md".to_string(), CachedFile::Parsed(mdf_info.clone()));
+
+        save_cache(&path, &files);
+        let mut loaded = load_cache(&path);
+        // Stored files are written back without being deserialized
+        save_cache(&path, &loaded);
+        let mut reloaded = load_cache(&path);
+        let _ = fs::remove_file(&path);
+
+        let cached_file = loaded.get_mut("a.md").unwrap();
+        assert!(matches!(cached_file, CachedFile::Stored { .. }));
+        assert_eq!("2025-01-01T00:00:00+00:00", cached_file.modified());
+        assert_eq!(&mdf_info, cached_file.materialize().unwrap());
+        assert!(matches!(cached_file, CachedFile::Parsed(_)));
+        assert_eq!(
+            &mdf_info,
+            reloaded.get_mut("a.md").unwrap().materialize().unwrap()
+        );
+
+        assert!(read_cache_index(Arc::new(vec![1, 2])).is_err());
+    }
+
+    #[test]
+    fn test_read_markdown_skips_big_and_binary_files() {
+        let dir = std::env::temp_dir().join(format!("krafna-read-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("a.md"), "# a").unwrap();
+        fs::write(dir.join("b.md"), "# b\0\u{1}").unwrap();
+
+        let results = [
+            read_markdown(&dir.join("a.md"), Some(3)),
+            read_markdown(&dir.join("a.md"), Some(2)),
+            read_markdown(&dir.join("a.md"), None),
+            read_markdown(&dir.join("b.md"), None),
+        ];
+        let _ = fs::remove_dir_all(&dir);
+
+        assert_eq!(Ok("# a".to_string()), results[0]);
+        assert_eq!(
+            Err("Skipped, 3 bytes is over the 2 bytes file size limit".to_string()),
+            results[1]
+        );
+        assert_eq!(Ok("# a".to_string()), results[2]);
+        assert_eq!(
+            Err("Skipped, looks like a binary file".to_string()),
+            results[3]
+        );
+    }
+
+    #[test]
+    fn test_normalize_content_exposes_frontmatter() {
+        let matter = Matter::<SplitOnly>::new();
+        for content in [
+            "\u{feff}---\ntitle: a\n---\n# a",
+            "---\r\ntitle: a\r\n---\r\n# a",
+            "\n  \n---\ntitle: a\n---\n# a",
+            "\u{feff}\r\n---\r\ntitle: a\r\n---\r\n# a",
+        ] {
+            let result = matter.parse(&normalize_content(content));
+            assert_eq!("title: a", result.matter, "{:?}", content);
+            assert_eq!("# a", result.content, "{:?}", content);
+        }
+
+        assert_eq!("\n# a\n", normalize_content("\u{feff}\r\n# a\r\n"));
+    }
+
+    #[test]
+    fn test_body_tags_are_added_to_tags() {
+        let content = "# A #heading\n\nOn #project/alpha with my_friend #snake_case, #123 and #2024-q1\nnot page#anchor, `#code`, [[#section]] or #\n#project\n\n```\n#code\n```\n";
+        let body_tags = parse_markdown_content(content, &IndexMap::new()).body_tags;
+        assert_eq!(
+            vec![
+                "heading",
+                "project/alpha",
+                "snake_case",
+                "2024-q1",
+                "project"
+            ],
+            body_tags
+        );
+
+        let tags = |frontmatter: &str| {
+            let mut frontmatter = yaml_to_pod(&YamlLoader::load_from_str(frontmatter).unwrap()[0]);
+            add_body_tags(&mut frontmatter, &body_tags);
+            frontmatter.to_json_value().to_string()
+        };
+        assert_eq!(
+            r#"{"tags":["project","old","heading","project/alpha","snake_case","2024-q1"]}"#,
+            tags("tags: [project, old]")
+        );
+        assert_eq!(
+            r#"{"tags":["solo","heading","project/alpha","snake_case","2024-q1","project"]}"#,
+            tags("tags: solo")
+        );
+        assert_eq!(
+            r#"{"title":"a","tags":["heading","project/alpha","snake_case","2024-q1","project"]}"#,
+            tags("title: a")
+        );
+    }
+
+    #[test]
+    fn test_toml_and_json_frontmatter() {
+        let matter = Matter::<SplitOnly>::new();
+        let split = |content: &str| {
+            let (frontmatter, markdown_content) =
+                split_frontmatter(&normalize_content(content), &matter).unwrap();
+            (frontmatter.to_json_value().to_string(), markdown_content)
+        };
+
+        assert_eq!(
+            (
+                r#"{"title":"a","created":"2024-01-02","tags":["x"],"meta":{"b":1}}"#.to_string(),
+                "# a\n".to_string()
+            ),
+            split("+++\r\ntitle = \"a\"\r\ncreated = 2024-01-02\r\ntags = [\"x\"]\r\n[meta]\r\nb = 1\r\n+++\r\n# a\r\n")
+        );
+        assert_eq!(
+            (
+                r#"{"title":"a","tags":["x"]}"#.to_string(),
+                "# a".to_string()
+            ),
+            split("\n{\n  \"title\": \"a\",\n  \"tags\": [\"x\"]\n}\n# a")
+        );
+        assert_eq!(
+            (r#"{"title":"a"}"#.to_string(), "# a".to_string()),
+            split("---\ntitle: a\n---\n# a")
+        );
+        // Not frontmatter without the closing line, or when `{` is not alone on its line
+        assert_eq!(
+            ("null".to_string(), "+++\na = 1".to_string()),
+            split("+++\na = 1\n")
+        );
+        assert_eq!(
+            ("null".to_string(), "{{date}}".to_string()),
+            split("{{date}}\n")
+        );
+
+        assert!(split_frontmatter("+++\na = \n+++\n", &matter)
+            .unwrap_err()
+            .starts_with("Invalid frontmatter"));
+        assert!(split_frontmatter("{\n\"a\": \n}\n", &matter)
+            .unwrap_err()
+            .starts_with("Invalid frontmatter"));
+    }
+
+    #[test]
+    fn test_get_markdown_files_of_several_paths() {
+        let dir = std::env::temp_dir().join(format!("krafna-several-{}", std::process::id()));
+        fs::create_dir_all(dir.join("projects")).unwrap();
+        fs::create_dir_all(dir.join("areas")).unwrap();
+        fs::write(dir.join("projects/a.md"), "# A").unwrap();
+        fs::write(dir.join("areas/b.md"), "# B").unwrap();
+        fs::write(dir.join("c.md"), "# C").unwrap();
+
+        let args: Vec<FunctionArg> = ["projects", "areas", "projects/a.md"]
+            .iter()
+            .map(|path| {
+                FunctionArg::FieldValue(FieldValue::String(dir.join(path).display().to_string()))
+            })
+            .collect();
+        let files = markdown_path_arguments(&args)
+            .and_then(|dirs| get_markdown_files(&dirs, &CancellationToken::default()))
+            .map(|(files, _)| files);
+        let _ = fs::remove_dir_all(&dir);
+
+        let names: Vec<String> = files
+            .unwrap()
+            .iter()
+            .map(|path| path.strip_prefix(&dir).unwrap().display().to_string())
+            .collect();
+        assert_eq!(vec!["projects/a.md", "areas/b.md"], names);
+        assert_eq!(vec!["."], markdown_path_arguments(&[]).unwrap());
+        assert!(markdown_path_arguments(&[FunctionArg::FieldName("a".to_string())]).is_err());
+    }
+
+    #[test]
+    fn test_get_markdown_files_globs_and_exclusions() {
+        let dir = std::env::temp_dir().join(format!("krafna-globs-{}", std::process::id()));
+        for path in [
+            "a.md",
+            "b.draft.md",
+            "templates/t.md",
+            "work/dailies/2024-01-01.md",
+            "work/dailies/notes.txt",
+            "home/dailies/2024-01-02.md",
+            "home/other/c.md",
+        ] {
+            fs::create_dir_all(dir.join(path).parent().unwrap()).unwrap();
+            fs::write(dir.join(path), "# note").unwrap();
+        }
+        let names = |paths: &[&str]| -> Vec<String> {
+            let paths: Vec<String> = paths
+                .iter()
+                .map(|path| match path.strip_prefix('!') {
+                    Some(_) => path.to_string(),
+                    None => dir.join(path).display().to_string(),
+                })
+                .collect();
+            let (files, _) = get_markdown_files(&paths, &CancellationToken::default()).unwrap();
+            files
+                .iter()
+                .map(|path| path.strip_prefix(&dir).unwrap().display().to_string())
+                .collect()
+        };
+
+        assert_eq!(
+            vec!["home/dailies/2024-01-02.md", "work/dailies/2024-01-01.md"],
+            names(&["**/dailies/*.md"])
+        );
+        assert_eq!(
+            vec!["a.md", "home/dailies/2024-01-02.md", "home/other/c.md"],
+            names(&[".", "!templates/**", "!*.draft.md", "!work"])
+        );
+        assert_eq!(vec!["a.md", "b.draft.md"], names(&["?.md", "*.draft.md"]));
+        let _ = fs::remove_dir_all(&dir);
+
+        let glob = glob_regex("**/dailies/*.md", false).unwrap();
+        assert!(glob.is_match("dailies/x.md"));
+        assert!(!glob.is_match("dailies/sub/x.md"));
+        let exclude = glob_regex("templates", true).unwrap();
+        assert!(exclude.is_match("templates/x.md"));
+        assert!(exclude.is_match("notes/templates/x.md"));
+        assert!(!exclude.is_match("templates2/x.md"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_get_markdown_files_skips_symlinked_duplicates() {
+        let dir = std::env::temp_dir().join(format!("krafna-symlinks-{}", std::process::id()));
+        let notes = dir.join("notes");
+        fs::create_dir_all(&notes).unwrap();
+        fs::write(notes.join("a.md"), "# A").unwrap();
+        fs::write(notes.join("b.md"), "# B").unwrap();
+        std::os::unix::fs::symlink(&notes, dir.join("linked")).unwrap();
+        std::os::unix::fs::symlink(notes.join("a.md"), dir.join("c.md")).unwrap();
+
+        let files = get_markdown_files(&[dir.display().to_string()], &CancellationToken::default())
+            .map(|(files, _)| files);
+        let _ = fs::remove_dir_all(&dir);
+
+        let names: Vec<String> = files
+            .unwrap()
+            .iter()
+            .map(|path| path.strip_prefix(&dir).unwrap().display().to_string())
+            .collect();
+        assert_eq!(vec!["c.md", "linked/b.md"], names);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_get_markdown_files_walks_in_name_order_and_stops_at_loops() {
+        let dir = std::env::temp_dir().join(format!("krafna-walk-{}", std::process::id()));
+        fs::create_dir_all(dir.join("b/d")).unwrap();
+        fs::create_dir_all(dir.join("c")).unwrap();
+        for file in ["a.md", "b/d/x.md", "b/e.md", "c/f.md", "z.md", "b/skip.txt"] {
+            fs::write(dir.join(file), "# x").unwrap();
+        }
+        std::os::unix::fs::symlink(&dir, dir.join("c/up")).unwrap();
+
+        let result =
+            get_markdown_files(&[dir.display().to_string()], &CancellationToken::default());
+        let _ = fs::remove_dir_all(&dir);
+
+        let (files, errors) = result.unwrap();
+        let names: Vec<String> = files
+            .iter()
+            .map(|path| path.strip_prefix(&dir).unwrap().display().to_string())
+            .collect();
+        assert_eq!(vec!["a.md", "b/d/x.md", "b/e.md", "c/f.md", "z.md"], names);
+        assert_eq!(
+            vec![FileError {
+                path: dir.join("c/up").display().to_string(),
+                error: format!(
+                    "Symlink loop back to {}",
+                    fs::canonicalize(std::env::temp_dir())
+                        .unwrap()
+                        .join(dir.file_name().unwrap())
+                        .display()
+                ),
+            }],
+            errors
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_get_markdown_files_reports_broken_symlinks() {
+        let dir = std::env::temp_dir().join(format!("krafna-broken-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("a.md"), "# A").unwrap();
+        std::os::unix::fs::symlink(dir.join("missing.md"), dir.join("b.md")).unwrap();
+        std::os::unix::fs::symlink(dir.join("missing.txt"), dir.join("c.txt")).unwrap();
+
+        let result =
+            get_markdown_files(&[dir.display().to_string()], &CancellationToken::default());
+        let _ = fs::remove_dir_all(&dir);
+
+        let (files, errors) = result.unwrap();
+        assert_eq!(vec![dir.join("a.md")], files);
+        assert_eq!(1, errors.len());
+        assert_eq!(dir.join("b.md").display().to_string(), errors[0].path);
+        assert!(errors[0].error.starts_with("Could not read: "));
+    }
+}
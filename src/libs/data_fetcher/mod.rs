@@ -1,19 +1,187 @@
+pub mod canvas_fetcher;
+pub mod csv_fetcher;
+pub mod json_fetcher;
 pub mod markdown_fetcher;
 pub mod pod;
+pub mod sqlite_fetcher;
+pub mod stdin_fetcher;
 
 // Re-export important items from submodules
 //pub use data_fetcher::fetch_data;
 
+use std::collections::BTreeMap;
 use std::error::Error;
+use std::path::PathBuf;
+#[cfg(feature = "async")]
+use std::{future::Future, pin::Pin};
 
+use crate::libs::cancellation::CancellationToken;
+use crate::libs::data_fetcher::markdown_fetcher::MarkdownCache;
 use crate::libs::data_fetcher::pod::Pod;
-use crate::libs::parser::Function;
+use crate::libs::parser::{Function, FunctionArg};
+use crate::libs::progress::Progress;
+use crate::libs::suggestion::with_suggestion;
 
+/// A data source that can be used in FROM, e.g. `FROM MY_API('some-arg')`.
+///
+/// Implement this to plug in a new source, then register it with
+/// [`Krafna::register_source`](crate::libs::engine::Krafna::register_source).
+pub trait SourceProvider: Send + Sync {
+    fn fetch(&self, args: &[FunctionArg]) -> Result<Vec<Pod>, Box<dyn Error>>;
+}
+
+impl<F> SourceProvider for F
+where
+    F: Fn(&[FunctionArg]) -> Result<Vec<Pod>, Box<dyn Error>> + Send + Sync,
+{
+    fn fetch(&self, args: &[FunctionArg]) -> Result<Vec<Pod>, Box<dyn Error>> {
+        self(args)
+    }
+}
+
+#[cfg(feature = "async")]
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// Async counterpart of [`SourceProvider`], for sources that do network or other async I/O
+/// (e.g. an HTTP fetcher). Register it with
+/// [`Krafna::register_async_source`](crate::libs::engine::Krafna::register_async_source).
+#[cfg(feature = "async")]
+pub trait AsyncSourceProvider: Send + Sync {
+    fn fetch<'a>(
+        &'a self,
+        args: &'a [FunctionArg],
+    ) -> BoxFuture<'a, Result<Vec<Pod>, Box<dyn Error + Send + Sync>>>;
+}
+
+/// Sources that [`fetch_data`] knows about. All of them read markdown files from a directory.
+pub const BUILTIN_SOURCES: [&str; 6] = [
+    "FRONTMATTER_DATA",
+    "MD_LINKS",
+    "MD_TASKS",
+    "MD_CODE_BLOCKS",
+    "MD_PARAGRAPHS",
+    "MD_BACKLINKS",
+];
+
+/// Sources that [`fetch_file_data`] knows about. They read data files (or stdin) instead of
+/// markdown files, so they have no per file changes and are not cached. `SQLITE_DATA` needs the
+/// `sqlite` feature, without it the source is an error.
+pub const FILE_SOURCES: [&str; 5] = [
+    "CSV_DATA",
+    "JSON_DATA",
+    "SQLITE_DATA",
+    "CANVAS_DATA",
+    "STDIN",
+];
+
+/// Rows by the path of the markdown file they come from.
+pub type FileRows = BTreeMap<String, Vec<Pod>>;
+
+/// Resolves a FROM function into rows. [`fetch_data`] is the built-in one.
+pub type DataFetcher<'a> = dyn Fn(&Function) -> Result<Vec<Pod>, Box<dyn Error>> + 'a;
+
+/// Rows of a built-in source: frontmatter of every file (`FRONTMATTER_DATA`), its links
+/// (`MD_LINKS`), its tasks (`MD_TASKS`), its fenced code blocks (`MD_CODE_BLOCKS`), its
+/// paragraphs and list items (`MD_PARAGRAPHS`) or the notes linking to it (`MD_BACKLINKS`), see
+/// [`BUILTIN_SOURCES`].
 pub fn fetch_data(from_function: &Function) -> Result<Vec<Pod>, Box<dyn Error>> {
+    fetch_data_with_cancellation(from_function, &CancellationToken::default())
+}
+
+pub fn fetch_data_with_cancellation(
+    from_function: &Function,
+    cancellation: &CancellationToken,
+) -> Result<Vec<Pod>, Box<dyn Error>> {
+    if FILE_SOURCES.contains(&from_function.name.to_uppercase().as_str()) {
+        return fetch_file_data(from_function);
+    }
+    fetch_builtin_data(
+        from_function,
+        &MarkdownCache::default(),
+        cancellation,
+        &Progress::default(),
+    )
+}
+
+/// Built-in sources, reading markdown files through `cache` and reporting to `progress`.
+pub fn fetch_builtin_data(
+    from_function: &Function,
+    cache: &MarkdownCache,
+    cancellation: &CancellationToken,
+    progress: &Progress,
+) -> Result<Vec<Pod>, Box<dyn Error>> {
+    let args = &from_function.args;
     match from_function.name.to_uppercase().as_str() {
-        "FRONTMATTER_DATA" => markdown_fetcher::fetch_frontmatter_data(&from_function.args),
-        "MD_LINKS" => markdown_fetcher::fetch_markdown_links(&from_function.args),
-        "MD_TASKS" => markdown_fetcher::fetch_markdown_tasks(&from_function.args),
-        _ => Err(format!("Unknown function: {}", from_function.name).into()),
+        "FRONTMATTER_DATA" => {
+            markdown_fetcher::fetch_frontmatter_data(args, cache, cancellation, progress)
+        }
+        "MD_LINKS" => markdown_fetcher::fetch_markdown_links(args, cache, cancellation, progress),
+        "MD_TASKS" => markdown_fetcher::fetch_markdown_tasks(args, cache, cancellation, progress),
+        "MD_CODE_BLOCKS" => {
+            markdown_fetcher::fetch_markdown_code_blocks(args, cache, cancellation, progress)
+        }
+        "MD_PARAGRAPHS" => {
+            markdown_fetcher::fetch_markdown_paragraphs(args, cache, cancellation, progress)
+        }
+        "MD_BACKLINKS" => {
+            markdown_fetcher::fetch_markdown_backlinks(args, cache, cancellation, progress)
+        }
+        name => Err(with_suggestion(
+            format!("Unknown function: {}", from_function.name),
+            name,
+            BUILTIN_SOURCES,
+        )
+        .into()),
     }
 }
+
+/// Rows of a data file source (`CSV_DATA`, `JSON_DATA`, `SQLITE_DATA`), of Obsidian canvases
+/// (`CANVAS_DATA`) or of stdin (`STDIN`), see [`FILE_SOURCES`].
+pub fn fetch_file_data(from_function: &Function) -> Result<Vec<Pod>, Box<dyn Error>> {
+    match from_function.name.to_uppercase().as_str() {
+        "CSV_DATA" => csv_fetcher::fetch_csv_data(&from_function.args),
+        "JSON_DATA" => json_fetcher::fetch_json_data(&from_function.args),
+        "SQLITE_DATA" => sqlite_fetcher::fetch_sqlite_data(&from_function.args),
+        "CANVAS_DATA" => canvas_fetcher::fetch_canvas_data(&from_function.args),
+        "STDIN" => stdin_fetcher::fetch_stdin_data(&from_function.args),
+        name => Err(with_suggestion(
+            format!("Unknown function: {}", from_function.name),
+            name,
+            FILE_SOURCES,
+        )
+        .into()),
+    }
+}
+
+/// Rows of the built-in `from_function` for just the `changed` files, by file path (removed files
+/// have none), to update earlier results without walking the directory again. `None` when the
+/// changes can not be applied per file, e.g. a directory was moved.
+pub fn fetch_builtin_changes(
+    from_function: &Function,
+    changed: &[PathBuf],
+    cache: &MarkdownCache,
+    progress: &Progress,
+) -> Result<Option<FileRows>, Box<dyn Error>> {
+    let name = from_function.name.to_uppercase();
+    if !BUILTIN_SOURCES.contains(&name.as_str()) {
+        return Err(with_suggestion(
+            format!("Unknown function: {}", from_function.name),
+            &name,
+            BUILTIN_SOURCES,
+        )
+        .into());
+    }
+    markdown_fetcher::fetch_changed_rows(&name, &from_function.args, changed, cache, progress)
+}
+
+/// Runs the built-in [`fetch_data`] on tokio's blocking pool, so walking and parsing a vault does
+/// not block the async runtime.
+#[cfg(feature = "async")]
+pub async fn fetch_data_async(
+    from_function: &Function,
+) -> Result<Vec<Pod>, Box<dyn Error + Send + Sync>> {
+    let from_function = from_function.clone();
+    tokio::task::spawn_blocking(move || fetch_data(&from_function).map_err(|e| e.to_string()))
+        .await?
+        .map_err(|e| e.into())
+}
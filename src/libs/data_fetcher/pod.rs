@@ -1,7 +1,6 @@
+use indexmap::IndexMap;
 use serde::{Deserialize, Serialize};
 
-use std::collections::HashMap;
-
 #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
 pub enum Pod {
     Null,
@@ -10,26 +9,50 @@ pub enum Pod {
     Float(f64),
     Boolean(bool),
     Array(Vec<Pod>),
-    Hash(HashMap<String, Pod>),
+    /// Keys keep their insertion order, so output is the same from run to run
+    Hash(IndexMap<String, Pod>),
 }
 
 impl Pod {
+    /// Gets the value at a dotted `key`, where every part can index into lists, e.g.
+    /// `projects[0].status` or `authors[-1]` (negative indices count from the end).
     pub fn nested_get(&self, key: &str) -> Option<&Pod> {
+        // Columns of grouped rows are keyed by their whole name, e.g. `file.folder` or `COUNT(*)`
+        if let Pod::Hash(hash) = self {
+            if let Some(value) = hash.get(key) {
+                return Some(value);
+            }
+        }
         let mut current = self;
         for subkey in key.split('.') {
-            match current {
-                Pod::Hash(hash) => match hash.get(subkey) {
-                    Some(pod) => current = pod,
-                    None => return None,
-                },
+            let (name, indices) = match subkey.split_once('[') {
+                Some((name, indices)) => (name, Some(indices)),
+                None => (subkey, None),
+            };
+            current = match current {
+                Pod::Hash(hash) => hash.get(name)?,
                 _ => return None,
+            };
+            for index in indices.into_iter().flat_map(|i| i.split('[')) {
+                let index = index.strip_suffix(']')?.parse::<i64>().ok()?;
+                current = match current {
+                    Pod::Array(array) => {
+                        let index = if index < 0 {
+                            array.len().checked_sub(index.unsigned_abs() as usize)?
+                        } else {
+                            index as usize
+                        };
+                        array.get(index)?
+                    }
+                    _ => return None,
+                };
             }
         }
         Some(current)
     }
 
     pub fn new_hash() -> Pod {
-        Pod::Hash(HashMap::new())
+        Pod::Hash(IndexMap::new())
     }
 
     pub fn new_array() -> Pod {
@@ -61,11 +84,48 @@ impl Pod {
     }
 
     pub fn to_untagged_json_string(&self) -> Result<String, serde_json::Error> {
-        serde_json::to_string(
-            &self
-                .to_gray_matter_pod()
-                .deserialize::<serde_json::Value>()?,
-        )
+        serde_json::to_string(&self.to_json_value())
+    }
+
+    /// Untagged JSON value, hash keys keep their order.
+    pub fn to_json_value(&self) -> serde_json::Value {
+        match self {
+            Pod::Null => serde_json::Value::Null,
+            Pod::String(s) => serde_json::Value::String(s.clone()),
+            Pod::Integer(i) => serde_json::Value::from(*i),
+            Pod::Float(f) => serde_json::Value::from(*f),
+            Pod::Boolean(b) => serde_json::Value::Bool(*b),
+            Pod::Array(array) => {
+                serde_json::Value::Array(array.iter().map(Pod::to_json_value).collect())
+            }
+            Pod::Hash(hash) => serde_json::Value::Object(
+                hash.iter()
+                    .map(|(k, v)| (k.clone(), v.to_json_value()))
+                    .collect(),
+            ),
+        }
+    }
+
+    /// Pod of a JSON value, object keys keep their order. Numbers that do not fit i64 are floats.
+    pub fn from_json_value(value: &serde_json::Value) -> Pod {
+        match value {
+            serde_json::Value::Null => Pod::Null,
+            serde_json::Value::Bool(b) => Pod::Boolean(*b),
+            serde_json::Value::Number(n) => match n.as_i64() {
+                Some(i) => Pod::Integer(i),
+                None => Pod::Float(n.as_f64().unwrap_or(f64::NAN)),
+            },
+            serde_json::Value::String(s) => Pod::String(s.clone()),
+            serde_json::Value::Array(array) => {
+                Pod::Array(array.iter().map(Pod::from_json_value).collect())
+            }
+            serde_json::Value::Object(object) => Pod::Hash(
+                object
+                    .iter()
+                    .map(|(k, v)| (k.clone(), Pod::from_json_value(v)))
+                    .collect(),
+            ),
+        }
     }
 
     // TODO: Figure out how to better deal with untagged so i don't have to do this crazy
@@ -123,7 +183,7 @@ impl Pod {
         }
     }
 
-    pub fn as_hashmap(&self) -> Option<HashMap<String, Pod>> {
+    pub fn as_hashmap(&self) -> Option<IndexMap<String, Pod>> {
         match *self {
             Pod::Hash(ref value) => Some(value.clone()),
             _ => None,
@@ -187,7 +247,7 @@ impl From<Pod> for Vec<Pod> {
     }
 }
 
-impl From<Pod> for HashMap<String, Pod> {
+impl From<Pod> for IndexMap<String, Pod> {
     fn from(val: Pod) -> Self {
         val.as_hashmap().unwrap()
     }
@@ -223,8 +283,8 @@ impl From<Vec<Pod>> for Pod {
     }
 }
 
-impl From<HashMap<String, Pod>> for Pod {
-    fn from(val: HashMap<String, Pod>) -> Self {
+impl From<IndexMap<String, Pod>> for Pod {
+    fn from(val: IndexMap<String, Pod>) -> Self {
         Pod::Hash(val)
     }
 }
@@ -0,0 +1,118 @@
+use std::error::Error;
+
+use crate::libs::data_fetcher::pod::Pod;
+use crate::libs::parser::{FieldValue, FunctionArg};
+
+/// Rows of the SQL query in `args` on the SQLite database in `args`, e.g. data of other apps
+/// (`SQLITE_DATA('~/Zotero/zotero.sqlite', 'SELECT key, dateAdded FROM items')`), with the
+/// column names of the query as fields. The database is opened read-only.
+pub fn fetch_sqlite_data(args: &[FunctionArg]) -> Result<Vec<Pod>, Box<dyn Error>> {
+    let (path, query) = match args {
+        [FunctionArg::FieldValue(FieldValue::String(path)), FunctionArg::FieldValue(FieldValue::String(query))] => {
+            (shellexpand::tilde(path).into_owned(), query)
+        }
+        _ => {
+            return Err(format!(
+                "SQLITE_DATA expects a database file path and a SELECT query, but found {:?}",
+                args
+            )
+            .into());
+        }
+    };
+
+    Ok(sqlite_rows(&path, query).map_err(|error| format!("{}: {}", path, error))?)
+}
+
+#[cfg(feature = "sqlite")]
+fn sqlite_rows(path: &str, query: &str) -> Result<Vec<Pod>, String> {
+    use rusqlite::{Connection, OpenFlags};
+
+    // Without SQLITE_OPEN_CREATE a missing file is an error instead of a new empty database
+    let connection = Connection::open_with_flags(
+        path,
+        OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+    )
+    .map_err(|error| format!("Could not open the database: {}", error))?;
+    query_to_pods(&connection, query)
+}
+
+#[cfg(not(feature = "sqlite"))]
+fn sqlite_rows(_path: &str, _query: &str) -> Result<Vec<Pod>, String> {
+    Err("SQLITE_DATA needs krafna built with the `sqlite` feature".to_string())
+}
+
+#[cfg(feature = "sqlite")]
+fn query_to_pods(connection: &rusqlite::Connection, query: &str) -> Result<Vec<Pod>, String> {
+    use indexmap::IndexMap;
+
+    let invalid = |error: rusqlite::Error| format!("Invalid query: {}", error);
+    let mut statement = connection.prepare(query).map_err(invalid)?;
+    let names: Vec<String> = statement
+        .column_names()
+        .into_iter()
+        .map(str::to_string)
+        .collect();
+
+    let rows = statement
+        .query_map([], |row| {
+            names
+                .iter()
+                .enumerate()
+                .map(|(index, name)| Ok((name.clone(), sqlite_value(row.get_ref(index)?))))
+                .collect::<Result<IndexMap<_, _>, _>>()
+                .map(Pod::Hash)
+        })
+        .map_err(invalid)?;
+    rows.collect::<Result<_, _>>().map_err(invalid)
+}
+
+// Blobs are hex, so they can be compared and printed like text
+#[cfg(feature = "sqlite")]
+fn sqlite_value(value: rusqlite::types::ValueRef) -> Pod {
+    use rusqlite::types::ValueRef;
+
+    match value {
+        ValueRef::Null => Pod::Null,
+        ValueRef::Integer(integer) => Pod::Integer(integer),
+        ValueRef::Real(float) => Pod::Float(float),
+        ValueRef::Text(text) => Pod::String(String::from_utf8_lossy(text).into_owned()),
+        ValueRef::Blob(blob) => Pod::String(blob.iter().map(|b| format!("{:02x}", b)).collect()),
+    }
+}
+
+#[cfg(all(test, feature = "sqlite"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_query_to_pods() {
+        let connection = rusqlite::Connection::open_in_memory().unwrap();
+        connection
+            .execute_batch(
+                "CREATE TABLE items (key TEXT, added INTEGER, score REAL, note TEXT, data BLOB);
+                 INSERT INTO items VALUES ('ABC', 20240101, 1.5, NULL, x'0aff');
+                 INSERT INTO items VALUES ('DEF', 20240102, NULL, 'read', NULL);",
+            )
+            .unwrap();
+
+        let rows: Vec<String> = query_to_pods(
+            &connection,
+            "SELECT key, added, score AS rating, note, data FROM items ORDER BY key",
+        )
+        .unwrap()
+        .iter()
+        .map(|row| row.to_json_value().to_string())
+        .collect();
+
+        assert_eq!(
+            vec![
+                r#"{"key":"ABC","added":20240101,"rating":1.5,"note":null,"data":"0aff"}"#,
+                r#"{"key":"DEF","added":20240102,"rating":null,"note":"read","data":null}"#,
+            ],
+            rows
+        );
+        assert!(query_to_pods(&connection, "SELECT nope FROM items")
+            .unwrap_err()
+            .starts_with("Invalid query"));
+    }
+}
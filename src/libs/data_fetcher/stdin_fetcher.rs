@@ -0,0 +1,153 @@
+use std::error::Error;
+use std::io::{IsTerminal, Read};
+use std::sync::OnceLock;
+
+use indexmap::IndexMap;
+
+use crate::libs::data_fetcher::csv_fetcher::typed_value;
+use crate::libs::data_fetcher::pod::Pod;
+use crate::libs::parser::FunctionArg;
+
+// Stdin can only be read once, queries that use STDIN() more than once (subqueries, UNION,
+// --check-fields) get the same rows every time
+static STDIN: OnceLock<Result<String, String>> = OnceLock::new();
+
+/// Rows piped into krafna, `some-tool | krafna "SELECT ... FROM STDIN()"`. Stdin is either JSON
+/// lines (one object per line), a JSON array of objects, or TSV with a header line.
+pub fn fetch_stdin_data(args: &[FunctionArg]) -> Result<Vec<Pod>, Box<dyn Error>> {
+    if !args.is_empty() {
+        return Err(format!("STDIN expects no arguments, but found {:?}", args).into());
+    }
+    let content = STDIN.get_or_init(|| {
+        let mut stdin = std::io::stdin();
+        if stdin.is_terminal() {
+            return Err("STDIN() reads rows piped into krafna, but nothing is piped".to_string());
+        }
+        let mut content = String::new();
+        stdin
+            .read_to_string(&mut content)
+            .map_err(|error| format!("Could not read stdin: {}", error))?;
+        Ok(content)
+    });
+
+    Ok(stdin_to_pods(content.as_ref().map_err(String::clone)?)?)
+}
+
+fn stdin_to_pods(content: &str) -> Result<Vec<Pod>, String> {
+    let content = content.strip_prefix('\u{feff}').unwrap_or(content);
+    match content.trim_start().chars().next() {
+        None => Ok(vec![]),
+        Some('[') => json_array_to_pods(content),
+        Some('{') => json_lines_to_pods(content),
+        Some(_) => Ok(tsv_to_pods(content)),
+    }
+}
+
+fn json_array_to_pods(content: &str) -> Result<Vec<Pod>, String> {
+    let value: serde_json::Value =
+        serde_json::from_str(content).map_err(|error| format!("Invalid JSON: {}", error))?;
+    let Pod::Array(rows) = Pod::from_json_value(&value) else {
+        unreachable!("JSON starting with '[' is an array");
+    };
+    rows.into_iter()
+        .enumerate()
+        .map(|(index, row)| match row {
+            Pod::Hash(_) => Ok(row),
+            _ => Err(format!("Item {} of the array is not an object", index)),
+        })
+        .collect()
+}
+
+fn json_lines_to_pods(content: &str) -> Result<Vec<Pod>, String> {
+    content
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(index, line)| {
+            let value: serde_json::Value = serde_json::from_str(line)
+                .map_err(|error| format!("Invalid JSON in line {}: {}", index + 1, error))?;
+            match Pod::from_json_value(&value) {
+                row @ Pod::Hash(_) => Ok(row),
+                _ => Err(format!("Line {} is not a JSON object", index + 1)),
+            }
+        })
+        .collect()
+}
+
+// Missing values at the end of a short line are null, values without a header name are dropped
+fn tsv_to_pods(content: &str) -> Vec<Pod> {
+    let mut lines = content
+        .lines()
+        .map(|line| line.strip_suffix('\r').unwrap_or(line))
+        .filter(|line| !line.is_empty());
+    let Some(header) = lines.next() else {
+        return vec![];
+    };
+    let names: Vec<&str> = header.split('\t').map(str::trim).collect();
+
+    lines
+        .map(|line| {
+            let mut values = line.split('\t');
+            let row = names
+                .iter()
+                .map(|name| {
+                    (
+                        name.to_string(),
+                        values.next().map_or(Pod::Null, typed_value),
+                    )
+                })
+                .collect::<IndexMap<_, _>>();
+            Pod::Hash(row)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn json_rows(content: &str) -> Result<Vec<String>, String> {
+        Ok(stdin_to_pods(content)?
+            .iter()
+            .map(|row| row.to_json_value().to_string())
+            .collect())
+    }
+
+    #[test]
+    fn test_stdin_formats() {
+        assert_eq!(
+            Ok(vec![
+                r#"{"name":"a","size":1}"#.to_string(),
+                r#"{"name":"b","tags":["x"]}"#.to_string(),
+            ]),
+            json_rows("{\"name\": \"a\", \"size\": 1}\n\n{\"name\": \"b\", \"tags\": [\"x\"]}\n")
+        );
+        assert_eq!(
+            Ok(vec![r#"{"name":"a"}"#.to_string()]),
+            json_rows("  [{\"name\": \"a\"}]")
+        );
+        assert_eq!(
+            Ok(vec![
+                r#"{"name":"a b","size":1.5,"done":true}"#.to_string(),
+                r#"{"name":"c","size":null,"done":null}"#.to_string(),
+            ]),
+            json_rows("name\tsize\tdone\r\na b\t1.5\ttrue\r\nc\t\n")
+        );
+        assert_eq!(Ok(vec![]), json_rows("\n"));
+    }
+
+    #[test]
+    fn test_stdin_errors_name_the_line() {
+        assert_eq!(
+            Err("Line 2 is not a JSON object".to_string()),
+            json_rows("{\"a\": 1}\n[1]\n")
+        );
+        assert!(json_rows("{\"a\": 1}\n{\"a\": \n")
+            .unwrap_err()
+            .starts_with("Invalid JSON in line 2"));
+        assert_eq!(
+            Err("Item 0 of the array is not an object".to_string()),
+            json_rows("[1]")
+        );
+    }
+}
@@ -0,0 +1,720 @@
+//! Obsidian dataview queries (DQL) translated to krafna queries, so existing dataview snippets
+//! can be run with `--dialect dataview` or moved over with `krafna translate`, e.g.
+//! `TABLE status, due FROM #project AND "work" WHERE status != "done" SORT due DESC` becomes
+//! `SELECT file.name, status, due FROM FRONTMATTER_DATA('~/notes/work') WHERE 'project' IN tags
+//! AND (status != 'done') ORDER BY due DESC`.
+//!
+//! Dataview queries are relative to the vault, so sources (`"folder"`) are read from below the
+//! vault dir given to [`translate_dataview`]. Parts of DQL without a krafna counterpart (FLATTEN,
+//! dataview's GROUP BY, `[[links]]` as sources, ...) are errors rather than being left
+//! out, so a translated query never returns different rows without saying so.
+
+use std::str::FromStr;
+
+use crate::libs::parser::Query;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Dialect {
+    #[default]
+    Krafna,
+    Dataview,
+}
+
+impl FromStr for Dialect {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "krafna" => Ok(Dialect::Krafna),
+            "dataview" => Ok(Dialect::Dataview),
+            _ => Err(format!(
+                "Unknown dialect '{}', expected krafna or dataview",
+                s
+            )),
+        }
+    }
+}
+
+impl Dialect {
+    /// Language of the code blocks holding queries of the dialect, the ones `--find` lists.
+    pub fn code_block_language(&self) -> &'static str {
+        match self {
+            Dialect::Krafna => "krafna",
+            Dialect::Dataview => "dataview",
+        }
+    }
+}
+
+/// Dataview's `file.` fields and the krafna fields they become. `file.name` of dataview has no
+/// `.md` extension, krafna's has.
+const FILE_FIELDS: [(&str, &str); 9] = [
+    ("file.name", "file.name"),
+    ("file.link", "file.name"),
+    ("file.path", "file.path"),
+    ("file.folder", "file.folder"),
+    ("file.ctime", "file.created"),
+    ("file.cday", "file.created"),
+    ("file.mtime", "file.modified"),
+    ("file.mday", "file.modified"),
+    ("file.tags", "tags"),
+];
+
+// Clauses that can follow the query type, in the order dataview allows them
+const CLAUSES: [&str; 6] = ["FROM", "WHERE", "SORT", "LIMIT", "GROUP", "FLATTEN"];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum QueryType {
+    Table,
+    List,
+    Task,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    /// Field names, numbers and keywords, e.g. `file.name`, `2.5` or `and`
+    Word(String),
+    /// Quoted text, without the quotes
+    Text(String),
+    /// `#tag`, without the `#`
+    Tag(String),
+    /// `[[note]]`, without the brackets
+    Link(String),
+    Symbol(String),
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Word(word) | Token::Symbol(word) => write!(f, "{}", word),
+            Token::Text(text) => write!(f, "\"{}\"", text),
+            Token::Tag(tag) => write!(f, "#{}", tag),
+            Token::Link(link) => write!(f, "[[{}]]", link),
+        }
+    }
+}
+
+/// Sources of a dataview FROM, e.g. `#project and ("work" or #urgent)`
+#[derive(Debug, Clone, PartialEq)]
+enum Source {
+    Folder(String),
+    Tag(String),
+    And(Box<Source>, Box<Source>),
+    Or(Box<Source>, Box<Source>),
+}
+
+/// The krafna query doing what the dataview query `dql` does, with folders of FROM read from
+/// below `vault`. TABLE and LIST select `file.name` (unless `WITHOUT ID`) and their fields from
+/// FRONTMATTER_DATA, TASK selects the tasks of MD_TASKS.
+pub fn translate_dataview(dql: &str, vault: &str) -> Result<String, String> {
+    let tokens = tokenize(dql)?;
+    let mut clauses = split_clauses(&tokens);
+    let (_, header) = clauses.remove(0);
+
+    let (query_type, fields) = translate_header(header)?;
+    let mut folder = None;
+    let mut conditions = Vec::new();
+    let mut order_by = Vec::new();
+    let mut limit: Option<String> = None;
+    for (clause, tokens) in clauses {
+        // Dataview runs its clauses in order, krafna always limits last
+        if limit.is_some() && matches!(clause.as_str(), "WHERE" | "SORT") {
+            return Err(format!(
+                "{} after LIMIT is not supported by krafna, LIMIT is applied last",
+                clause
+            ));
+        }
+        match clause.as_str() {
+            "FROM" => {
+                let mut tokens = tokens.iter().peekable();
+                let source = parse_source_or(&mut tokens)?;
+                if let Some(token) = tokens.next() {
+                    return Err(format!("Unexpected {} in FROM", token));
+                }
+                let (source_folder, condition) = split_source(source)?;
+                if query_type == QueryType::Task && condition.is_some() {
+                    return Err(
+                        "TASK FROM can only have folders, tasks have no tags in krafna".to_string(),
+                    );
+                }
+                folder = source_folder;
+                conditions.extend(condition);
+            }
+            "WHERE" => conditions.push(format!("({})", translate_expression(tokens, query_type)?)),
+            "SORT" => {
+                for sort_field in tokens.split(|token| *token == Token::Symbol(",".to_string())) {
+                    order_by.push(translate_sort(sort_field, query_type)?);
+                }
+            }
+            "LIMIT" => limit = Some(join_tokens(tokens)),
+            "GROUP" => {
+                return Err(
+                    "GROUP BY of dataview makes lists of rows, krafna's GROUP BY summarizes \
+                     them with aggregates (e.g. COUNT(*)), write the query in krafna instead"
+                        .to_string(),
+                )
+            }
+            _ => return Err(format!("{} is not supported by krafna", clause)),
+        }
+    }
+
+    let path = match folder {
+        Some(folder) if !folder.trim_matches('/').is_empty() => format!(
+            "{}/{}",
+            vault.trim_end_matches('/'),
+            folder.trim_matches('/')
+        ),
+        _ => vault.to_string(),
+    };
+    let source = match query_type {
+        QueryType::Task => "MD_TASKS",
+        _ => "FRONTMATTER_DATA",
+    };
+    let mut krafna = format!(
+        "SELECT {} FROM {}({})",
+        fields.join(", "),
+        source,
+        quote(&path)?
+    );
+    if !conditions.is_empty() {
+        krafna.push_str(&format!(" WHERE {}", conditions.join(" AND ")));
+    }
+    if !order_by.is_empty() {
+        krafna.push_str(&format!(" ORDER BY {}", order_by.join(", ")));
+    }
+    if let Some(limit) = limit {
+        krafna.push_str(&format!(" LIMIT {}", limit));
+    }
+
+    // Parsing makes sure the translation is a valid krafna query, and formats it
+    Ok(krafna
+        .parse::<Query>()
+        .map_err(|error| format!("Translated query {} is not valid: {}", krafna, error))?
+        .to_string())
+}
+
+fn tokenize(dql: &str) -> Result<Vec<Token>, String> {
+    let chars: Vec<char> = dql.chars().collect();
+    let mut tokens = Vec::new();
+    let mut index = 0;
+    let word_char = |c: char| c.is_alphanumeric() || c == '_' || c == '.';
+    let tag_char = |c: char| c.is_alphanumeric() || c == '_' || c == '-' || c == '/';
+    let take_while = |index: &mut usize, accept: &dyn Fn(char) -> bool| {
+        let start = *index;
+        while *index < chars.len() && accept(chars[*index]) {
+            *index += 1;
+        }
+        chars[start..*index].iter().collect::<String>()
+    };
+
+    while index < chars.len() {
+        let c = chars[index];
+        if c.is_whitespace() {
+            index += 1;
+        } else if c == '"' || c == '\'' {
+            let end = (index + 1..chars.len())
+                .find(|&end| chars[end] == c && chars[end - 1] != '\\')
+                .ok_or_else(|| format!("Missing closing {} of the text at {}", c, index))?;
+            let text: String = chars[index + 1..end].iter().collect();
+            tokens.push(Token::Text(
+                text.replace(&format!("\\{}", c), &c.to_string()),
+            ));
+            index = end + 1;
+        } else if c == '#' {
+            index += 1;
+            tokens.push(Token::Tag(take_while(&mut index, &tag_char)));
+        } else if chars[index..].starts_with(&['[', '[']) {
+            let end = (index + 2..chars.len())
+                .find(|&end| chars[end..].starts_with(&[']', ']']))
+                .ok_or_else(|| format!("Missing closing ]] of the link at {}", index))?;
+            tokens.push(Token::Link(chars[index + 2..end].iter().collect()));
+            index = end + 2;
+        } else if word_char(c) {
+            tokens.push(Token::Word(take_while(&mut index, &word_char)));
+        } else {
+            let two: String = chars[index..chars.len().min(index + 2)].iter().collect();
+            let symbol = match two.as_str() {
+                "!=" | "<=" | ">=" | "==" => two,
+                _ => c.to_string(),
+            };
+            index += symbol.chars().count();
+            tokens.push(Token::Symbol(symbol));
+        }
+    }
+
+    Ok(tokens)
+}
+
+// The query type with its fields, followed by the clauses, keyed by their (uppercase) keyword
+fn split_clauses(tokens: &[Token]) -> Vec<(String, &[Token])> {
+    let mut clauses = vec![(String::new(), 0)];
+    for (index, token) in tokens.iter().enumerate() {
+        if let Token::Word(word) = token {
+            let keyword = word.to_uppercase();
+            if CLAUSES.contains(&keyword.as_str()) {
+                clauses.push((keyword, index + 1));
+            }
+        }
+    }
+
+    let mut ends: Vec<usize> = clauses.iter().skip(1).map(|(_, start)| start - 1).collect();
+    ends.push(tokens.len());
+    clauses
+        .into_iter()
+        .zip(ends)
+        .map(|((clause, start), end)| match clause.as_str() {
+            // The BY of GROUP BY is not one of the fields
+            "GROUP" => (clause, &tokens[(start + 1).min(end)..end]),
+            _ => (clause, &tokens[start..end]),
+        })
+        .collect()
+}
+
+fn translate_header(tokens: &[Token]) -> Result<(QueryType, Vec<String>), String> {
+    let Some(Token::Word(query_type)) = tokens.first() else {
+        return Err("Expected TABLE, LIST or TASK at the start of the query".to_string());
+    };
+    let query_type = match query_type.to_uppercase().as_str() {
+        "TABLE" => QueryType::Table,
+        "LIST" => QueryType::List,
+        "TASK" => QueryType::Task,
+        "CALENDAR" => return Err("CALENDAR queries are not supported by krafna".to_string()),
+        _ => {
+            return Err(format!(
+                "Expected TABLE, LIST or TASK at the start of the query, found {}",
+                query_type
+            ))
+        }
+    };
+    let mut rest = &tokens[1..];
+    if query_type == QueryType::Task {
+        if let Some(token) = rest.first() {
+            return Err(format!("Unexpected {} after TASK", token));
+        }
+        return Ok((
+            query_type,
+            vec![
+                "file.name".to_string(),
+                "checked".to_string(),
+                "text".to_string(),
+            ],
+        ));
+    }
+
+    let mut fields = vec!["file.name".to_string()];
+    if let [Token::Word(without), Token::Word(id), after @ ..] = rest {
+        if without.eq_ignore_ascii_case("WITHOUT") && id.eq_ignore_ascii_case("ID") {
+            fields.clear();
+            rest = after;
+        }
+    }
+    if rest.is_empty() {
+        return Ok((query_type, fields));
+    }
+    for field in rest.split(|token| *token == Token::Symbol(",".to_string())) {
+        // Columns keep the name of their field, krafna has no AS
+        let field = match field {
+            [field @ .., Token::Word(r#as), Token::Text(_) | Token::Word(_)]
+                if r#as.eq_ignore_ascii_case("AS") =>
+            {
+                field
+            }
+            _ => field,
+        };
+        match field {
+            [Token::Word(name)] => fields.push(field_name(name, query_type)?),
+            _ => {
+                return Err(format!(
+                    "Only fields can be selected in krafna, not {}",
+                    join_tokens(field)
+                ))
+            }
+        }
+        if query_type == QueryType::List && fields.len() > 2 {
+            return Err("LIST can only have one field".to_string());
+        }
+    }
+
+    Ok((query_type, fields))
+}
+
+fn parse_source_or<'a, I>(tokens: &mut std::iter::Peekable<I>) -> Result<Source, String>
+where
+    I: Iterator<Item = &'a Token>,
+{
+    let mut source = parse_source_and(tokens)?;
+    while matches!(tokens.peek(), Some(Token::Word(or)) if or.eq_ignore_ascii_case("OR")) {
+        tokens.next();
+        source = Source::Or(Box::new(source), Box::new(parse_source_and(tokens)?));
+    }
+    Ok(source)
+}
+
+fn parse_source_and<'a, I>(tokens: &mut std::iter::Peekable<I>) -> Result<Source, String>
+where
+    I: Iterator<Item = &'a Token>,
+{
+    let mut source = parse_source(tokens)?;
+    while matches!(tokens.peek(), Some(Token::Word(and)) if and.eq_ignore_ascii_case("AND")) {
+        tokens.next();
+        source = Source::And(Box::new(source), Box::new(parse_source(tokens)?));
+    }
+    Ok(source)
+}
+
+fn parse_source<'a, I>(tokens: &mut std::iter::Peekable<I>) -> Result<Source, String>
+where
+    I: Iterator<Item = &'a Token>,
+{
+    match tokens.next() {
+        Some(Token::Tag(tag)) => Ok(Source::Tag(tag.clone())),
+        Some(Token::Text(folder)) => Ok(Source::Folder(folder.clone())),
+        Some(Token::Symbol(open)) if open == "(" => {
+            let source = parse_source_or(tokens)?;
+            match tokens.next() {
+                Some(Token::Symbol(close)) if close == ")" => Ok(source),
+                _ => Err("Missing ) in FROM".to_string()),
+            }
+        }
+        Some(Token::Symbol(not)) if not == "-" || not == "!" => {
+            Err("Excluding sources with - is not supported by krafna yet".to_string())
+        }
+        Some(Token::Link(link)) => Err(format!(
+            "Links as sources ([[{}]]) are not supported by krafna, query MD_LINKS instead",
+            link
+        )),
+        Some(token) => Err(format!("Unexpected {} in FROM", token)),
+        None => Err("Expected a #tag or \"folder\" in FROM".to_string()),
+    }
+}
+
+// The folder to read and the condition on tags of the other sources. krafna reads one folder,
+// so a folder can only be ANDed with the rest.
+fn split_source(source: Source) -> Result<(Option<String>, Option<String>), String> {
+    let mut parts = Vec::new();
+    let mut and_sources = vec![source];
+    while let Some(source) = and_sources.pop() {
+        match source {
+            Source::And(left, right) => and_sources.extend([*right, *left]),
+            source => parts.push(source),
+        }
+    }
+
+    let mut folder = None;
+    let mut conditions = Vec::new();
+    for part in parts {
+        match part {
+            Source::Folder(path) if folder.is_none() => folder = Some(path),
+            Source::Folder(_) => {
+                return Err("FROM can only have one folder, krafna reads one folder".to_string())
+            }
+            part => conditions.push(tag_condition(&part)?),
+        }
+    }
+    let condition = (!conditions.is_empty()).then(|| conditions.join(" AND "));
+    Ok((folder, condition))
+}
+
+fn tag_condition(source: &Source) -> Result<String, String> {
+    match source {
+        Source::Tag(tag) => Ok(format!("{} IN tags", quote(tag)?)),
+        Source::And(left, right) => Ok(format!(
+            "({} AND {})",
+            tag_condition(left)?,
+            tag_condition(right)?
+        )),
+        Source::Or(left, right) => Ok(format!(
+            "({} OR {})",
+            tag_condition(left)?,
+            tag_condition(right)?
+        )),
+        Source::Folder(_) => Err(
+            "Folders can only be combined with AND in FROM, krafna reads one folder".to_string(),
+        ),
+    }
+}
+
+fn translate_expression(tokens: &[Token], query_type: QueryType) -> Result<String, String> {
+    let mut parts = Vec::new();
+    let mut index = 0;
+    while index < tokens.len() {
+        let token = &tokens[index];
+        let next = tokens.get(index + 1);
+        match token {
+            Token::Word(name) if next == Some(&Token::Symbol("(".to_string())) => {
+                let end = closing_bracket(tokens, index + 1)?;
+                let args: Vec<String> = tokens[index + 2..end]
+                    .split(|token| *token == Token::Symbol(",".to_string()))
+                    .filter(|arg| !arg.is_empty())
+                    .map(|arg| translate_expression(arg, query_type))
+                    .collect::<Result<_, _>>()?;
+                parts.push(translate_function(name, &args)?);
+                index = end;
+            }
+            Token::Word(word) => parts.push(match word.to_uppercase().as_str() {
+                "AND" | "OR" => word.to_uppercase(),
+                "TRUE" | "FALSE" => word.to_lowercase(),
+                "NULL" => "NULL".to_string(),
+                _ if word.parse::<f64>().is_ok() => word.clone(),
+                _ => field_name(word, query_type)?,
+            }),
+            Token::Text(text) => parts.push(quote(text)?),
+            Token::Symbol(symbol) => parts.push(match symbol.as_str() {
+                "=" => "==".to_string(),
+                "&" => "AND".to_string(),
+                "|" => "OR".to_string(),
+                "!" => match next {
+                    // Missing fields are not true either, like in dataview
+                    Some(Token::Word(name))
+                        if tokens.get(index + 2) != Some(&Token::Symbol("(".to_string())) =>
+                    {
+                        index += 1;
+                        format!("{} != true", field_name(name, query_type)?)
+                    }
+                    _ => "NOT".to_string(),
+                },
+                "%" => return Err("% is not supported by krafna yet".to_string()),
+                _ => symbol.clone(),
+            }),
+            Token::Tag(tag) => {
+                return Err(format!(
+                    "Tags in WHERE are not supported, use {} IN tags",
+                    quote(tag)?
+                ))
+            }
+            Token::Link(link) => {
+                return Err(format!(
+                    "Links ([[{}]]) in WHERE are not supported by krafna",
+                    link
+                ))
+            }
+        }
+        index += 1;
+    }
+
+    Ok(parts.join(" "))
+}
+
+// Index of the `)` closing the `(` at `open`
+fn closing_bracket(tokens: &[Token], open: usize) -> Result<usize, String> {
+    let mut depth = 0;
+    for (index, token) in tokens.iter().enumerate().skip(open) {
+        match token {
+            Token::Symbol(open) if open == "(" => depth += 1,
+            Token::Symbol(close) if close == ")" => {
+                depth -= 1;
+                if depth == 0 {
+                    return Ok(index);
+                }
+            }
+            _ => {}
+        }
+    }
+    Err("Missing ) in WHERE".to_string())
+}
+
+fn translate_function(name: &str, args: &[String]) -> Result<String, String> {
+    let function = name.to_lowercase();
+    match (function.as_str(), args) {
+        // IN checks lists for the value and text for the substring, same as contains
+        ("contains", [haystack, needle]) => Ok(format!("{} IN {}", needle, haystack)),
+        ("startswith", [text, prefix]) => {
+            Ok(format!("{} REGEXP {}", text, regex(prefix, "^", "")?))
+        }
+        ("endswith", [text, suffix]) => Ok(format!("{} REGEXP {}", text, regex(suffix, "", "$")?)),
+        ("regexmatch", [pattern, text]) => Ok(format!(
+            "{} REGEXP {}",
+            text,
+            regex_pattern(pattern, "^(?:", ")$")?
+        )),
+        ("regextest", [pattern, text]) => Ok(format!("{} REGEXP {}", text, pattern)),
+        ("date", [date]) if matches!(date.as_str(), "today" | "now" | "tomorrow" | "yesterday") => {
+            Err(format!("date({}) is not supported by krafna yet", date))
+        }
+        ("date", [date]) => Ok(format!("DATE({})", date)),
+        ("contains" | "startswith" | "endswith" | "regexmatch" | "regextest" | "date", _) => {
+            Err(format!(
+                "{}() needs {} arguments",
+                name,
+                if function == "date" { 1 } else { 2 }
+            ))
+        }
+        _ => Err(format!("Function {}() is not supported by krafna", name)),
+    }
+}
+
+// Regex matching the translated text literal `text` as is, between `prefix` and `suffix`
+fn regex(text: &str, prefix: &str, suffix: &str) -> Result<String, String> {
+    match unquote(text) {
+        Some(text) => quote(&format!("{}{}{}", prefix, regex::escape(text), suffix)),
+        None => Err(format!("Expected a text to match, found {}", text)),
+    }
+}
+
+fn regex_pattern(pattern: &str, prefix: &str, suffix: &str) -> Result<String, String> {
+    match unquote(pattern) {
+        Some(pattern) => quote(&format!("{}{}{}", prefix, pattern, suffix)),
+        None => Err(format!("Expected a regex pattern, found {}", pattern)),
+    }
+}
+
+fn translate_sort(tokens: &[Token], query_type: QueryType) -> Result<String, String> {
+    let (field, direction) = match tokens {
+        [Token::Word(field)] => (field, "ASC"),
+        [Token::Word(field), Token::Word(direction)] => match direction.to_uppercase().as_str() {
+            "ASC" | "ASCENDING" => (field, "ASC"),
+            "DESC" | "DESCENDING" => (field, "DESC"),
+            _ => return Err(format!("Unexpected {} in SORT", direction)),
+        },
+        _ => {
+            return Err(format!(
+                "Only fields can be sorted by in krafna, not {}",
+                join_tokens(tokens)
+            ))
+        }
+    };
+    Ok(format!("{} {}", field_name(field, query_type)?, direction))
+}
+
+fn field_name(name: &str, query_type: QueryType) -> Result<String, String> {
+    if query_type == QueryType::Task {
+        match name {
+            "completed" | "checked" | "fullyCompleted" => return Ok("checked".to_string()),
+            "text" => return Ok("text".to_string()),
+            _ => {}
+        }
+    }
+    if !name.starts_with("file.") {
+        return Ok(name.to_string());
+    }
+    match FILE_FIELDS.iter().find(|(dataview, _)| *dataview == name) {
+        Some((_, krafna)) => Ok(krafna.to_string()),
+        None => Err(format!("Dataview field {} has no krafna counterpart", name)),
+    }
+}
+
+// Text in whichever quotes it does not contain, krafna strings have no escapes
+fn quote(text: &str) -> Result<String, String> {
+    if !text.contains('\'') {
+        Ok(format!("'{}'", text))
+    } else if !text.contains('"') {
+        Ok(format!("\"{}\"", text))
+    } else {
+        Err(format!(
+            "Text with both kinds of quotes is not supported: {}",
+            text
+        ))
+    }
+}
+
+fn unquote(text: &str) -> Option<&str> {
+    text.strip_prefix('\'')
+        .and_then(|text| text.strip_suffix('\''))
+        .or_else(|| {
+            text.strip_prefix('"')
+                .and_then(|text| text.strip_suffix('"'))
+        })
+}
+
+fn join_tokens(tokens: &[Token]) -> String {
+    tokens
+        .iter()
+        .map(Token::to_string)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_code_block_language() {
+        for (dialect, language) in [("krafna", "krafna"), ("DataView", "dataview")] {
+            assert_eq!(
+                language,
+                dialect.parse::<Dialect>().unwrap().code_block_language()
+            );
+        }
+    }
+
+    #[test]
+    fn test_translate_dataview() {
+        assert_eq!(
+            Ok("SELECT file.name, status, due FROM FRONTMATTER_DATA('~/notes/work') \
+                WHERE 'project' IN tags AND (status != 'done') ORDER BY due DESC"
+                .to_string()),
+            translate_dataview(
+                "TABLE status, due AS \"Due\" FROM #project and \"work\" WHERE status != \"done\" SORT due desc",
+                "~/notes/"
+            )
+        );
+        assert_eq!(
+            Ok("SELECT author FROM FRONTMATTER_DATA('~/notes') \
+                WHERE ('a' IN tags OR 'b/c' IN tags) AND ('rust' IN topics \
+                AND title REGEXP '^Week\\.' AND file.modified > DATE('2024-01-01'))"
+                .to_string()),
+            translate_dataview(
+                "LIST WITHOUT ID author FROM #a OR #b/c WHERE contains(topics, \"rust\") \
+                 and startswith(title, 'Week.') & file.mtime > date(\"2024-01-01\")",
+                "~/notes"
+            )
+        );
+        assert_eq!(
+            Ok(
+                "SELECT file.name, checked, text FROM MD_TASKS('~/notes/projects') \
+                WHERE (checked != true)"
+                    .to_string()
+            ),
+            translate_dataview("task from \"projects\" where !completed", "~/notes")
+        );
+        assert_eq!(
+            Ok("SELECT file.name FROM FRONTMATTER_DATA('~/notes') \
+                ORDER BY file.modified DESC LIMIT 5"
+                .to_string()),
+            translate_dataview("LIST SORT file.mtime DESC LIMIT 5", "~/notes")
+        );
+        assert_eq!(
+            Ok("SELECT file.name FROM FRONTMATTER_DATA('~/notes') \
+                WHERE (NOT (status == 'done' OR archived) AND NOT 'x' IN tags)"
+                .to_string()),
+            translate_dataview(
+                "LIST WHERE !(status = \"done\" | archived) AND !contains(tags, \"x\")",
+                "~/notes"
+            )
+        );
+    }
+
+    #[test]
+    fn test_translate_dataview_errors() {
+        let error = |dql: &str| translate_dataview(dql, "~/notes").unwrap_err();
+
+        assert_eq!(
+            "SORT after LIMIT is not supported by krafna, LIMIT is applied last",
+            error("LIST LIMIT 10 SORT file.name")
+        );
+        assert_eq!(
+            "Links as sources ([[Home]]) are not supported by krafna, query MD_LINKS instead",
+            error("LIST FROM [[Home]]")
+        );
+        assert_eq!(
+            "Folders can only be combined with AND in FROM, krafna reads one folder",
+            error("LIST FROM \"a\" OR #b")
+        );
+        assert_eq!(
+            "Only fields can be selected in krafna, not length ( file.tasks )",
+            error("TABLE length(file.tasks)")
+        );
+        assert_eq!(
+            "date(today) is not supported by krafna yet",
+            error("LIST WHERE due < date(today)")
+        );
+        assert_eq!(
+            "Dataview field file.outlinks has no krafna counterpart",
+            error("TABLE file.outlinks")
+        );
+        assert_eq!(
+            Err("Unknown dialect 'sql', expected krafna or dataview".to_string()),
+            "sql".parse::<Dialect>()
+        );
+    }
+}
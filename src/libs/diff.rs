@@ -0,0 +1,148 @@
+//! Differences between the results of two runs of a query, e.g. to report what became due since
+//! the last run.
+
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use indexmap::IndexMap;
+
+use crate::libs::data_fetcher::markdown_fetcher::cache_dir;
+use crate::libs::data_fetcher::pod::Pod;
+
+/// Fields that tell which row is which between two runs: the file a row comes from and, for
+/// links and tasks, its place in the file.
+pub const DIFF_KEY_FIELDS: [&str; 2] = ["file.path", "ord"];
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ResultDiff {
+    pub added: Vec<Pod>,
+    pub removed: Vec<Pod>,
+    /// Rows with the same key but different values, as (previous, current)
+    pub changed: Vec<(Pod, Pod)>,
+}
+
+impl ResultDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+/// Rows added, removed and changed from `previous` to `current`. Rows are matched by their
+/// [`DIFF_KEY_FIELDS`], rows without a `file.path` only by their values (so they are never
+/// changed, only added or removed).
+pub fn diff_results(previous: &[Pod], current: &[Pod]) -> ResultDiff {
+    let mut previous_by_key: IndexMap<String, Vec<&Pod>> = IndexMap::new();
+    for pod in previous {
+        previous_by_key.entry(row_key(pod)).or_default().push(pod);
+    }
+
+    let mut diff = ResultDiff::default();
+    for pod in current {
+        let previous_pod = previous_by_key
+            .get_mut(&row_key(pod))
+            .filter(|pods| !pods.is_empty())
+            .map(|pods| pods.remove(0));
+        match previous_pod {
+            Some(previous_pod) if previous_pod != pod => {
+                diff.changed.push((previous_pod.clone(), pod.clone()))
+            }
+            Some(_) => {}
+            None => diff.added.push(pod.clone()),
+        }
+    }
+    diff.removed = previous_by_key.into_values().flatten().cloned().collect();
+    diff
+}
+
+fn row_key(pod: &Pod) -> String {
+    match pod.nested_get(DIFF_KEY_FIELDS[0]) {
+        Some(path) => format!(
+            "{}#{}",
+            path,
+            pod.nested_get(DIFF_KEY_FIELDS[1])
+                .map(Pod::to_string)
+                .unwrap_or_default()
+        ),
+        None => pod.to_string(),
+    }
+}
+
+/// Where the last results of `query` are kept, in the OS cache dir. Equal queries (e.g. the
+/// [`Display`](std::fmt::Display) of a parsed query) share the file.
+pub fn snapshot_path(query: &str) -> Result<PathBuf, Box<dyn Error>> {
+    let dir = cache_dir()?.join("snapshots");
+    fs::create_dir_all(&dir)?;
+    Ok(dir.join(format!("{:016x}.json", fnv1a(query))))
+}
+
+/// Rows saved by [`save_snapshot`], `None` when there are none (e.g. on the first run).
+pub fn load_snapshot(path: &Path) -> Option<Vec<Pod>> {
+    let data = fs::read(path).ok()?;
+    serde_json::from_slice(&data).ok()
+}
+
+pub fn save_snapshot(path: &Path, rows: &[Pod]) -> Result<(), Box<dyn Error>> {
+    fs::write(path, serde_json::to_vec(rows)?)?;
+    Ok(())
+}
+
+// Stable between runs and Rust versions, unlike `DefaultHasher`
+fn fnv1a(text: &str) -> u64 {
+    text.bytes().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn task(path: &str, ord: &str, checked: bool) -> Pod {
+        let mut file = Pod::new_hash();
+        let _ = file.insert("path".to_string(), Pod::String(path.to_string()));
+        let mut pod = Pod::new_hash();
+        let _ = pod.insert("file".to_string(), file);
+        let _ = pod.insert("ord".to_string(), Pod::String(ord.to_string()));
+        let _ = pod.insert("checked".to_string(), Pod::Boolean(checked));
+        pod
+    }
+
+    #[test]
+    fn test_diff_results() {
+        let previous = vec![
+            task("a.md", "1", false),
+            task("a.md", "2", false),
+            task("b.md", "1", false),
+        ];
+        let current = vec![
+            task("a.md", "1", false),
+            task("a.md", "2", true),
+            task("c.md", "1", false),
+        ];
+
+        let diff = diff_results(&previous, &current);
+
+        assert_eq!(vec![task("c.md", "1", false)], diff.added);
+        assert_eq!(vec![task("b.md", "1", false)], diff.removed);
+        assert_eq!(
+            vec![(task("a.md", "2", false), task("a.md", "2", true))],
+            diff.changed
+        );
+        assert!(diff_results(&current, &current).is_empty());
+    }
+
+    #[test]
+    fn test_snapshot_round_trip() {
+        let path = std::env::temp_dir().join(format!("krafna-snapshot-{}", std::process::id()));
+        let rows = vec![task("a.md", "1", true)];
+
+        assert_eq!(None, load_snapshot(&path));
+        save_snapshot(&path, &rows).unwrap();
+        let loaded = load_snapshot(&path);
+        let _ = fs::remove_file(&path);
+
+        assert_eq!(Some(rows), loaded);
+        assert_ne!(fnv1a("SELECT a"), fnv1a("SELECT b"));
+    }
+}
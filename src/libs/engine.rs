@@ -0,0 +1,1725 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::num::NonZero;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use lru::LruCache;
+use once_cell::sync::Lazy;
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use regex::Regex;
+
+use crate::libs::cancellation::CancellationToken;
+use crate::libs::collation::Collation;
+use crate::libs::data_fetcher::markdown_fetcher::{
+    fingerprint_markdown_files, markdown_path_arguments, source_path_arguments, FileError,
+    MarkdownCache, DEFAULT_MAX_FILE_SIZE,
+};
+use crate::libs::data_fetcher::pod::Pod;
+#[cfg(feature = "async")]
+use crate::libs::data_fetcher::AsyncSourceProvider;
+use crate::libs::data_fetcher::{
+    fetch_builtin_changes, fetch_builtin_data, fetch_file_data, DataFetcher, FileRows,
+    SourceProvider, BUILTIN_SOURCES, FILE_SOURCES,
+};
+use crate::libs::executor::{
+    add_computed_fields, apply_field_aliases, execute_parsed_query, filter_rows, prepare_query,
+    resolve_subqueries, ExecutionContext, RandomSeed, RegexCache, UserFunction,
+    DEFAULT_REGEX_CACHE_SIZE,
+};
+use crate::libs::live_query::LiveQuery;
+use crate::libs::parser::{
+    Assignment, Definition, ExpressionElement, FieldValue, Function, FunctionArg, Query, Update,
+};
+use crate::libs::planner::{plan_query, strip_explain, QueryPlan};
+use crate::libs::prepared_query::PreparedQuery;
+use crate::libs::progress::{Progress, ProgressEvent};
+use crate::libs::suggestion::with_suggestion;
+use crate::libs::update::{update_frontmatter, update_tasks, FileUpdate, UpdateOptions};
+use crate::libs::validator::{validate_fields, FieldWarning};
+use crate::libs::with::With;
+
+/// Writes the assignments of an UPDATE to the files of the matching rows
+type UpdateFiles =
+    fn(&[Assignment], &[Pod], &UpdateOptions) -> Result<Vec<FileUpdate>, Box<dyn Error>>;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct KrafnaConfig {
+    /// Persist parsed markdown files between runs, so only modified files are parsed again
+    pub use_disk_cache: bool,
+    /// Where the markdown cache is stored, `None` uses the OS cache dir
+    pub cache_path: Option<PathBuf>,
+    /// How many compiled LIKE patterns are kept
+    pub regex_cache_size: usize,
+    /// Fail the query when a markdown file can not be parsed, instead of leaving it out
+    pub strict: bool,
+    /// Markdown files bigger than this many bytes are skipped (and reported), `None` reads files
+    /// of any size
+    pub max_file_size: Option<u64>,
+    /// Threads used to walk and parse markdown files, `None` uses rayon's global pool (one thread
+    /// per core)
+    pub threads: Option<usize>,
+    /// How many query results are kept, 0 turns the result cache off
+    pub result_cache_size: usize,
+    /// Fields added to every fetched row as (name, expression), e.g.
+    /// `("is_project", "'project' IN tags")`. Expressions are written like WHERE and can use the
+    /// fields computed before them.
+    pub computed_fields: Vec<(String, String)>,
+    /// Fields renamed in every fetched row as (alias, field), e.g. `("deadline", "due")`, so
+    /// notes that still use an old name can be queried by the new one. A row that has both keeps
+    /// the value of the field. Applied before the computed fields.
+    pub field_aliases: Vec<(String, String)>,
+    /// Conditions that queries can use in WHERE as `$name`, as (name, condition), e.g.
+    /// `("active", "status != 'done'")`. A condition can use the macros defined before it.
+    pub macros: Vec<(String, String)>,
+    /// Locale whose collation ORDER BY sorts strings by, e.g. `de`, instead of byte order. Needs
+    /// the `collation` feature, unless it is one of the
+    /// [`BUILTIN_COLLATIONS`](crate::libs::collation::BUILTIN_COLLATIONS), e.g. `natural`.
+    pub collation: Option<String>,
+    /// Directory built-in sources without a path read, e.g. `FROM #project` or
+    /// `FRONTMATTER_DATA()`. `None` reads the current directory.
+    pub vault: Option<String>,
+}
+
+impl Default for KrafnaConfig {
+    fn default() -> Self {
+        KrafnaConfig {
+            use_disk_cache: true,
+            cache_path: None,
+            regex_cache_size: DEFAULT_REGEX_CACHE_SIZE,
+            strict: false,
+            max_file_size: Some(DEFAULT_MAX_FILE_SIZE),
+            threads: None,
+            result_cache_size: DEFAULT_RESULT_CACHE_SIZE,
+            computed_fields: Vec::new(),
+            field_aliases: Vec::new(),
+            macros: Vec::new(),
+            collation: None,
+            vault: None,
+        }
+    }
+}
+
+pub const DEFAULT_RESULT_CACHE_SIZE: usize = 32;
+
+/// Results by the (bound) query text and the fingerprint of the markdown files it reads, so an
+/// entry is not used anymore as soon as one of the files changes.
+struct ResultCache {
+    results: Mutex<LruCache<(String, u64), CachedResult>>,
+}
+
+#[derive(Clone)]
+struct CachedResult {
+    results: (Vec<String>, Vec<Pod>),
+    /// Files that could not be parsed for these results, for [`Krafna::errors`]
+    errors: Vec<FileError>,
+}
+
+impl ResultCache {
+    fn new(size: usize) -> Self {
+        ResultCache {
+            results: Mutex::new(LruCache::new(
+                NonZero::new(size).unwrap_or(NonZero::<usize>::MIN),
+            )),
+        }
+    }
+
+    fn get(&self, key: &(String, u64)) -> Option<CachedResult> {
+        self.results.lock().ok()?.get(key).cloned()
+    }
+
+    fn put(&self, key: (String, u64), result: CachedResult) {
+        if let Ok(mut results) = self.results.lock() {
+            results.put(key, result);
+        }
+    }
+
+    fn clear(&self) {
+        if let Ok(mut results) = self.results.lock() {
+            results.clear();
+        }
+    }
+}
+
+/// Entry point for embedding krafna. Owns its configuration, the markdown and regex caches, and
+/// the sources and functions registered on top of the built-in ones (FRONTMATTER_DATA, MD_LINKS,
+/// MD_TASKS, MD_CODE_BLOCKS, MD_PARAGRAPHS, MD_BACKLINKS in FROM, DATE and DATEADD in WHERE).
+///
+/// Engines do not share any state, so several of them can be used independently. Clones share
+/// the caches, and a single engine can be used from many threads.
+#[derive(Clone)]
+pub struct Krafna {
+    config: KrafnaConfig,
+    markdown_cache: Arc<MarkdownCache>,
+    regex_cache: Arc<RegexCache>,
+    result_cache: Arc<ResultCache>,
+    /// Own pool when [`KrafnaConfig::threads`] is set
+    thread_pool: Option<Arc<ThreadPool>>,
+    /// Parsed [`KrafnaConfig::computed_fields`], or why one of them does not parse
+    computed_fields: Result<Arc<ComputedFields>, String>,
+    /// Checked [`KrafnaConfig::field_aliases`], or why one of them is invalid
+    field_aliases: Result<Arc<FieldAliases>, String>,
+    /// Parsed [`KrafnaConfig::macros`], or why one of them does not parse
+    macros: Result<Arc<Vec<Definition>>, String>,
+    /// Collation of [`KrafnaConfig::collation`], or why it is not available
+    collation: Result<Option<Collation>, String>,
+    functions: Arc<HashMap<String, Arc<UserFunction>>>,
+    progress: Progress,
+    sources: HashMap<String, Arc<dyn SourceProvider>>,
+    #[cfg(feature = "async")]
+    async_sources: HashMap<String, Arc<dyn AsyncSourceProvider>>,
+}
+
+impl Default for Krafna {
+    fn default() -> Self {
+        Krafna::with_config(KrafnaConfig::default())
+    }
+}
+
+impl Krafna {
+    pub fn new() -> Self {
+        Krafna::default()
+    }
+
+    pub fn with_config(config: KrafnaConfig) -> Self {
+        Krafna {
+            markdown_cache: Arc::new(
+                MarkdownCache::new(config.cache_path.clone(), config.use_disk_cache)
+                    .with_max_file_size(config.max_file_size),
+            ),
+            regex_cache: Arc::new(RegexCache::new(config.regex_cache_size)),
+            result_cache: Arc::new(ResultCache::new(config.result_cache_size)),
+            thread_pool: config.threads.and_then(|threads| {
+                ThreadPoolBuilder::new()
+                    .num_threads(threads)
+                    .build()
+                    .inspect_err(|error| {
+                        tracing::warn!(%error, "could not build thread pool, using the global one")
+                    })
+                    .ok()
+                    .map(Arc::new)
+            }),
+            computed_fields: parse_computed_fields(&config.computed_fields).map(Arc::new),
+            field_aliases: check_field_aliases(&config.field_aliases).map(Arc::new),
+            macros: parse_macros(&config.macros).map(Arc::new),
+            collation: config.collation.as_deref().map(Collation::new).transpose(),
+            functions: Arc::new(HashMap::new()),
+            progress: Progress::default(),
+            sources: HashMap::new(),
+            #[cfg(feature = "async")]
+            async_sources: HashMap::new(),
+            config,
+        }
+    }
+
+    pub fn config(&self) -> &KrafnaConfig {
+        &self.config
+    }
+
+    /// Markdown files the last query could not parse (e.g. broken YAML frontmatter). They are
+    /// left out of its results, or fail it with [`KrafnaConfig::strict`].
+    pub fn errors(&self) -> Vec<FileError> {
+        self.markdown_cache.errors()
+    }
+
+    /// Drops the in-memory markdown and result caches, files are read from the disk cache (or
+    /// parsed) again.
+    pub fn clear_cache(&self) {
+        self.markdown_cache.clear();
+        self.result_cache.clear();
+    }
+
+    /// Registers `function` under `name` (case insensitive), so it can be called in WHERE, e.g.
+    /// `WHERE SLUGIFY(title) == 'my-note'`. Built-in functions can not be overridden.
+    pub fn register_function<F>(&mut self, name: &str, function: F) -> &mut Self
+    where
+        F: Fn(&[FieldValue]) -> Result<FieldValue, String> + Send + Sync + 'static,
+    {
+        Arc::make_mut(&mut self.functions).insert(name.to_uppercase(), Arc::new(function));
+        self.result_cache.clear();
+        self
+    }
+
+    /// Sets a hook that gets [`ProgressEvent`]s (files discovered, files parsed, rows filtered,
+    /// stage and file parse times) while queries of this engine run.
+    pub fn on_progress<F>(&mut self, callback: F) -> &mut Self
+    where
+        F: Fn(&ProgressEvent) + Send + Sync + 'static,
+    {
+        self.progress = Progress::new(callback);
+        self
+    }
+
+    pub(crate) fn context(&self, cancellation: &CancellationToken) -> ExecutionContext {
+        ExecutionContext {
+            functions: Arc::clone(&self.functions),
+            regex_cache: Arc::clone(&self.regex_cache),
+            cancellation: cancellation.clone(),
+            progress: self.progress.clone(),
+            collation: self.collation().cloned(),
+            random_seed: RandomSeed::default(),
+        }
+    }
+
+    pub(crate) fn collation(&self) -> Option<&Collation> {
+        self.collation.as_ref().ok().and_then(Option::as_ref)
+    }
+
+    /// Registers `provider` under `name` (case insensitive). Registered sources take precedence
+    /// over the built-in ones, so a built-in source can be overridden as well.
+    pub fn register_source<P>(&mut self, name: &str, provider: P) -> &mut Self
+    where
+        P: SourceProvider + 'static,
+    {
+        self.sources.insert(name.to_uppercase(), Arc::new(provider));
+        self.result_cache.clear();
+        self
+    }
+
+    /// Registers an async `provider` under `name`. Async sources are only used by
+    /// [`Krafna::execute_query_async`], sync execution reports them as unknown.
+    #[cfg(feature = "async")]
+    pub fn register_async_source<P>(&mut self, name: &str, provider: P) -> &mut Self
+    where
+        P: AsyncSourceProvider + 'static,
+    {
+        self.async_sources
+            .insert(name.to_uppercase(), Arc::new(provider));
+        self
+    }
+
+    pub fn has_source(&self, name: &str) -> bool {
+        #[cfg(feature = "async")]
+        if self.async_sources.contains_key(&name.to_uppercase()) {
+            return true;
+        }
+        self.sources.contains_key(&name.to_uppercase())
+    }
+
+    pub fn prepare(
+        &self,
+        query: &str,
+        select: Option<String>,
+        from: Option<String>,
+        include_fields: Option<String>,
+    ) -> Result<PreparedQuery, Box<dyn Error>> {
+        Ok(PreparedQuery::from_query(self.prepare_query(
+            query,
+            select,
+            from,
+            include_fields,
+        )?))
+    }
+
+    // `prepare_query` with the macros of the config expanded
+    fn prepare_query(
+        &self,
+        query: &str,
+        select: Option<String>,
+        from: Option<String>,
+        include_fields: Option<String>,
+    ) -> Result<Query, Box<dyn Error>> {
+        let mut query = prepare_query(query, select, from, include_fields)?;
+        if let Some(from_function) = &mut query.from_function {
+            *from_function = self.in_vault(from_function);
+        }
+        let macros = self.macros.as_ref().map_err(String::clone)?;
+        Ok(query.expand_macros(macros)?)
+    }
+
+    // A built-in source or CANVAS_DATA without a path reads the vault
+    fn in_vault(&self, from_function: &Function) -> Function {
+        let name = from_function.name.to_uppercase();
+        match &self.config.vault {
+            Some(vault)
+                if from_function.args.is_empty()
+                    && !self.sources.contains_key(&name)
+                    && (BUILTIN_SOURCES.contains(&name.as_str()) || name == "CANVAS_DATA") =>
+            {
+                Function::new(
+                    from_function.name.clone(),
+                    vec![FunctionArg::FieldValue(FieldValue::String(vault.clone()))],
+                )
+            }
+            _ => from_function.clone(),
+        }
+    }
+
+    /// Executes `prepared` with `params` bound, fetching fresh data from this engine's sources.
+    pub fn execute_prepared(
+        &self,
+        prepared: &PreparedQuery,
+        params: &HashMap<String, FieldValue>,
+        cancellation: &CancellationToken,
+    ) -> Result<(Vec<String>, Vec<Pod>), Box<dyn Error>> {
+        self.execute_parsed(prepared.bind(params)?, cancellation)
+    }
+
+    /// Reports how `query` would be executed by this engine, without executing it. A leading
+    /// `EXPLAIN` is allowed.
+    pub fn plan(&self, query: &str) -> Result<QueryPlan, Box<dyn Error>> {
+        self.plan_with(query, None, None, None)
+    }
+
+    /// [`Krafna::plan`] with the SELECT/FROM overrides of [`Krafna::execute_query`].
+    pub fn plan_with(
+        &self,
+        query: &str,
+        select: Option<String>,
+        from: Option<String>,
+        include_fields: Option<String>,
+    ) -> Result<QueryPlan, Box<dyn Error>> {
+        let query = strip_explain(query).unwrap_or(query);
+        plan_query(
+            &self.prepare_query(query, select, from, include_fields)?,
+            &|name| self.has_source(name),
+            &self.markdown_cache,
+        )
+    }
+
+    /// Fetches the FROM source of `query` and reports fields it references that no fetched row
+    /// has (e.g. `file.nmae`), instead of the query silently returning no rows. Fetching goes
+    /// through the markdown cache, so executing the query afterwards does not parse files again.
+    pub fn check_fields(
+        &self,
+        query: &str,
+        select: Option<String>,
+        from: Option<String>,
+        include_fields: Option<String>,
+    ) -> Result<Vec<FieldWarning>, Box<dyn Error>> {
+        let query = self.prepare_query(query, select, from, include_fields)?;
+        let from_function = query
+            .from_function
+            .as_ref()
+            .ok_or("Query is missing FROM!")?;
+        let data = self.fetch_data(from_function)?;
+
+        Ok(validate_fields(&query, &data))
+    }
+
+    pub fn fetch_data(&self, from_function: &Function) -> Result<Vec<Pod>, Box<dyn Error>> {
+        self.fetch_data_with_cancellation(from_function, &CancellationToken::default())
+    }
+
+    pub fn fetch_data_with_cancellation(
+        &self,
+        from_function: &Function,
+        cancellation: &CancellationToken,
+    ) -> Result<Vec<Pod>, Box<dyn Error>> {
+        let mut data = match self.sources.get(&from_function.name.to_uppercase()) {
+            Some(provider) => provider.fetch(&from_function.args)?,
+            None if FILE_SOURCES.contains(&from_function.name.to_uppercase().as_str()) => {
+                fetch_file_data(from_function)?
+            }
+            None if !BUILTIN_SOURCES.contains(&from_function.name.to_uppercase().as_str()) => {
+                return Err(with_suggestion(
+                    format!("Unknown function: {}", from_function.name),
+                    &from_function.name,
+                    BUILTIN_SOURCES
+                        .into_iter()
+                        .chain(FILE_SOURCES)
+                        .chain(self.sources.keys().map(String::as_str)),
+                )
+                .into())
+            }
+            None => {
+                let from_function = &self.in_vault(from_function);
+                let fetch = || {
+                    fetch_builtin_data(
+                        from_function,
+                        &self.markdown_cache,
+                        cancellation,
+                        &self.progress,
+                    )
+                    .map_err(|error| error.to_string())
+                };
+                let data = match &self.thread_pool {
+                    Some(thread_pool) => thread_pool.install(fetch),
+                    None => fetch(),
+                }?;
+                self.check_strict()?;
+                data
+            }
+        };
+        self.apply_config_fields(&mut data)?;
+        Ok(data)
+    }
+
+    // Aliases first, so computed fields can use the new field names
+    fn apply_config_fields(&self, data: &mut [Pod]) -> Result<(), Box<dyn Error>> {
+        let aliases = self.field_aliases.as_ref().map_err(String::clone)?;
+        if !aliases.is_empty() {
+            apply_field_aliases(aliases, data);
+        }
+        let fields = self.computed_fields.as_ref().map_err(String::clone)?;
+        if !fields.is_empty() {
+            add_computed_fields(fields, data, &self.context(&CancellationToken::default()));
+        }
+        Ok(())
+    }
+
+    // Invalid config is reported before fetching, it is not an error of the source
+    fn check_config(&self) -> Result<(), Box<dyn Error>> {
+        self.field_aliases.as_ref().map_err(String::clone)?;
+        self.computed_fields.as_ref().map_err(String::clone)?;
+        self.collation.as_ref().map_err(String::clone)?;
+        Ok(())
+    }
+
+    /// Rows of `from_function` for just the `changed` files, by file path, to update earlier
+    /// results. `None` when they can not be updated per file (registered sources, data files,
+    /// moved directories), the whole source has to be fetched again then.
+    pub fn fetch_changes(
+        &self,
+        from_function: &Function,
+        changed: &[PathBuf],
+    ) -> Result<Option<FileRows>, Box<dyn Error>> {
+        let name = from_function.name.to_uppercase();
+        if self.sources.contains_key(&name) || FILE_SOURCES.contains(&name.as_str()) {
+            return Ok(None);
+        }
+        let fetch = || {
+            fetch_builtin_changes(from_function, changed, &self.markdown_cache, &self.progress)
+                .map_err(|error| error.to_string())
+        };
+        let mut changes = match &self.thread_pool {
+            Some(thread_pool) => thread_pool.install(fetch),
+            None => fetch(),
+        }?;
+        self.check_strict()?;
+        if let Some(changes) = &mut changes {
+            for rows in changes.values_mut() {
+                self.apply_config_fields(rows)?;
+            }
+        }
+        Ok(changes)
+    }
+
+    fn check_strict(&self) -> Result<(), Box<dyn Error>> {
+        let errors = self.errors();
+        if self.config.strict && !errors.is_empty() {
+            let errors: Vec<String> = errors.iter().map(FileError::to_string).collect();
+            return Err(format!(
+                "{} file(s) could not be parsed:\n  {}",
+                errors.len(),
+                errors.join("\n  ")
+            )
+            .into());
+        }
+        Ok(())
+    }
+
+    /// Executes `query` and keeps its results, so they can be updated as markdown files change
+    /// (watch mode) instead of executing the query again.
+    pub fn live_query(
+        &self,
+        query: &str,
+        select: Option<String>,
+        from: Option<String>,
+        include_fields: Option<String>,
+    ) -> Result<LiveQuery, Box<dyn Error>> {
+        self.check_config()?;
+        if With::split(query)?.is_some() {
+            return Err("WITH is not supported in watch mode yet".into());
+        }
+        LiveQuery::new(
+            self.clone(),
+            self.prepare_query(query, select, from, include_fields)?,
+        )
+    }
+
+    /// Executes an UPDATE `statement`, e.g.
+    /// `UPDATE FRONTMATTER_DATA('~/notes') SET status = 'archived' WHERE status == 'done'` or
+    /// `UPDATE MD_TASKS('~/notes/todo.md') SET checked = true WHERE ord == '1.2'`, and returns the
+    /// changes made to every file (or, with `options.dry_run`, that would be made).
+    pub fn execute_update(
+        &self,
+        statement: &str,
+        options: &UpdateOptions,
+    ) -> Result<Vec<FileUpdate>, Box<dyn Error>> {
+        let update: Update = statement.parse()?;
+        let macros = self.macros.as_ref().map_err(String::clone)?;
+        self.check_config()?;
+        let query = resolve_subqueries(update.to_query().expand_macros(macros)?, |subquery| {
+            self.execute_parsed(subquery, &CancellationToken::default())
+        })?;
+        let from_function = &update.from_function;
+        let name = from_function.name.to_uppercase();
+        let write = match name.as_str() {
+            _ if self.sources.contains_key(&name) => None,
+            "FRONTMATTER_DATA" => Some(update_frontmatter as UpdateFiles),
+            "MD_TASKS" => Some(update_tasks as UpdateFiles),
+            _ => None,
+        };
+        let Some(write) = write else {
+            return Err(format!(
+                "UPDATE is only supported for FRONTMATTER_DATA and MD_TASKS, not {}",
+                from_function.name
+            )
+            .into());
+        };
+
+        let mut rows = self
+            .fetch_data(from_function)
+            .map_err(|error| update.spans.locate(&error.to_string(), update.spans.from))?;
+        if let Some(error) = filter_rows(
+            &query.where_expression,
+            &query.spans,
+            &mut rows,
+            &self.context(&CancellationToken::default()),
+        )? {
+            return Err(error.into());
+        }
+
+        let updates = write(&update.assignments, &rows, options)?;
+        if !options.dry_run {
+            self.result_cache.clear();
+        }
+        Ok(updates)
+    }
+
+    pub fn execute_query(
+        &self,
+        query: &str,
+        select: Option<String>,
+        from: Option<String>,
+        include_fields: Option<String>,
+    ) -> Result<(Vec<String>, Vec<Pod>), Box<dyn Error>> {
+        self.execute_query_with_cancellation(
+            query,
+            select,
+            from,
+            include_fields,
+            &CancellationToken::default(),
+        )
+    }
+
+    /// Same as [`Krafna::execute_query`], but aborts as soon as `cancellation` is cancelled or
+    /// times out. Registered sources are only checked before and after fetching.
+    pub fn execute_query_with_cancellation(
+        &self,
+        query: &str,
+        select: Option<String>,
+        from: Option<String>,
+        include_fields: Option<String>,
+        cancellation: &CancellationToken,
+    ) -> Result<(Vec<String>, Vec<Pod>), Box<dyn Error>> {
+        self.execute_query_from(
+            query,
+            select,
+            from,
+            include_fields,
+            &|from_function| self.fetch_data_with_cancellation(from_function, cancellation),
+            cancellation,
+        )
+    }
+
+    // FROM of every query of a statement is fetched through `fetcher`, unless it reads a named
+    // query of WITH
+    fn execute_query_from(
+        &self,
+        query: &str,
+        select: Option<String>,
+        from: Option<String>,
+        include_fields: Option<String>,
+        fetcher: &DataFetcher,
+        cancellation: &CancellationToken,
+    ) -> Result<(Vec<String>, Vec<Pod>), Box<dyn Error>> {
+        // The SELECT/FROM/include overrides are for the main query, not for the named ones of WITH
+        With::execute_query(query, fetcher, |query, main, fetcher| {
+            let query = match main {
+                true => {
+                    self.prepare_query(query, select.clone(), from.clone(), include_fields.clone())
+                }
+                false => self.prepare_query(query, None, None, None),
+            }?;
+            self.execute_parsed_from(query, fetcher, cancellation)
+        })
+    }
+
+    fn execute_parsed(
+        &self,
+        query: Query,
+        cancellation: &CancellationToken,
+    ) -> Result<(Vec<String>, Vec<Pod>), Box<dyn Error>> {
+        self.execute_parsed_from(
+            query,
+            &|from_function| self.fetch_data_with_cancellation(from_function, cancellation),
+            cancellation,
+        )
+    }
+
+    // Results of built-in sources are cached, until one of the markdown files changes. FROM is
+    // fetched through `fetcher`, which knows the named queries of WITH.
+    fn execute_parsed_from(
+        &self,
+        query: Query,
+        fetcher: &DataFetcher,
+        cancellation: &CancellationToken,
+    ) -> Result<(Vec<String>, Vec<Pod>), Box<dyn Error>> {
+        self.check_config()?;
+        // Before the cache key, so it has the subquery results
+        let query = resolve_subqueries(query, |subquery| {
+            self.execute_parsed_from(subquery, fetcher, cancellation)
+        })?;
+        let key = self.result_cache_key(&query, cancellation)?;
+        if let Some(cached) = key.as_ref().and_then(|key| self.result_cache.get(key)) {
+            tracing::debug!("query results from the result cache");
+            self.markdown_cache.set_errors(cached.errors);
+            self.check_strict()?;
+            return Ok(cached.results);
+        }
+
+        let results = execute_parsed_query(query, fetcher, &self.context(cancellation))?;
+        if let Some(key) = key {
+            let cached = CachedResult {
+                results: results.clone(),
+                errors: self.errors(),
+            };
+            self.result_cache.put(key, cached);
+        }
+        Ok(results)
+    }
+
+    // `None` when results can not be cached: registered sources can not be fingerprinted, and
+    // invalid FROM arguments are left for the execution to report
+    fn result_cache_key(
+        &self,
+        query: &Query,
+        cancellation: &CancellationToken,
+    ) -> Result<Option<(String, u64)>, Box<dyn Error>> {
+        let Some(from_function) = &query.from_function else {
+            return Ok(None);
+        };
+        let name = from_function.name.to_uppercase();
+        if self.config.result_cache_size == 0
+            || self.sources.contains_key(&name)
+            || !BUILTIN_SOURCES.contains(&name.as_str())
+        {
+            return Ok(None);
+        }
+        let Ok(dir_paths) =
+            markdown_path_arguments(source_path_arguments(&name, &from_function.args))
+        else {
+            return Ok(None);
+        };
+        let query_string = query.to_string();
+        if depends_on_the_run(&query_string)
+            || self
+                .config
+                .computed_fields
+                .iter()
+                .any(|(_, expression)| depends_on_the_run(expression))
+        {
+            return Ok(None);
+        }
+
+        let fingerprint = || {
+            fingerprint_markdown_files(&dir_paths, cancellation).map_err(|error| error.to_string())
+        };
+        let fingerprint = match &self.thread_pool {
+            Some(thread_pool) => thread_pool.install(fingerprint),
+            None => fingerprint(),
+        }?;
+        Ok(Some((query_string, fingerprint)))
+    }
+}
+
+// RANDOM() without a seed is different on every run, TODAY(), NOW() and LAST_DAYS() with the clock
+fn depends_on_the_run(query: &str) -> bool {
+    static RUN_FUNCTIONS: Lazy<Regex> =
+        Lazy::new(|| Regex::new(r"(?i)\b(RANDOM\(\s*\)|(TODAY|NOW|LAST_DAYS)\()").unwrap());
+    RUN_FUNCTIONS.is_match(query)
+}
+
+type ComputedFields = Vec<(String, Vec<ExpressionElement>)>;
+
+type FieldAliases = Vec<(String, String)>;
+
+fn check_field_aliases(aliases: &[(String, String)]) -> Result<FieldAliases, String> {
+    for (alias, field) in aliases {
+        if alias.contains(['.', '[']) || field.contains(['.', '[']) {
+            return Err(format!(
+                "Field alias {} -> {} can not be nested, use names without '.' or '['",
+                alias, field
+            ));
+        }
+    }
+    Ok(aliases.to_vec())
+}
+
+fn parse_computed_fields(fields: &[(String, String)]) -> Result<ComputedFields, String> {
+    fields
+        .iter()
+        .map(|(name, expression)| {
+            if name.contains(['.', '[']) {
+                return Err(format!(
+                    "Computed field {} can not be nested, use a name without '.' or '['",
+                    name
+                ));
+            }
+            Query::parse_condition(expression)
+                .map(|expression| (name.clone(), expression))
+                .map_err(|error| format!("Computed field {}: {}", name, error))
+        })
+        .collect()
+}
+
+fn parse_macros(macros: &[(String, String)]) -> Result<Vec<Definition>, String> {
+    let mut definitions: Vec<Definition> = Vec::new();
+    for (name, condition) in macros {
+        let definition = Definition::new(name, condition)
+            .map_err(|error| format!("Macro ${}: {}", name, error))?;
+        definitions.push(definition.expand_macros(&definitions));
+    }
+    Ok(definitions)
+}
+
+#[cfg(feature = "async")]
+impl Krafna {
+    /// Async FROM resolution: async sources are awaited, sync and built-in sources run on tokio's
+    /// blocking pool.
+    pub async fn fetch_data_async(
+        &self,
+        from_function: &Function,
+    ) -> Result<Vec<Pod>, Box<dyn Error + Send + Sync>> {
+        let name = from_function.name.to_uppercase();
+        if let Some(provider) = self.async_sources.get(&name) {
+            let mut data = provider.fetch(&from_function.args).await?;
+            self.apply_config_fields(&mut data)
+                .map_err(|e| e.to_string())?;
+            return Ok(data);
+        }
+        let engine = self.clone();
+        let from_function = from_function.clone();
+        tokio::task::spawn_blocking(move || {
+            engine.fetch_data(&from_function).map_err(|e| e.to_string())
+        })
+        .await?
+        .map_err(|e| e.into())
+    }
+
+    /// Async version of [`Krafna::execute_query`], with WITH, UNION, subqueries and the result
+    /// cache like it. The query runs like the sync one (files are read with blocking I/O) on
+    /// tokio's blocking pool, and async sources are awaited from there. Dropping the future
+    /// cancels the query, it stops at the next cancellation check.
+    pub async fn execute_query_async(
+        &self,
+        query: &str,
+        select: Option<String>,
+        from: Option<String>,
+        include_fields: Option<String>,
+    ) -> Result<(Vec<String>, Vec<Pod>), Box<dyn Error + Send + Sync>> {
+        let engine = self.clone();
+        let query = query.to_string();
+        let runtime = tokio::runtime::Handle::current();
+        let cancellation = CancellationToken::new();
+        // A blocking task can not be aborted, so it is cancelled when the future is dropped
+        let _cancel_on_drop = cancellation.cancel_on_drop();
+        tokio::task::spawn_blocking(move || {
+            let fetcher = |from_function: &Function| -> Result<Vec<Pod>, Box<dyn Error>> {
+                cancellation.check()?;
+                match engine
+                    .async_sources
+                    .contains_key(&from_function.name.to_uppercase())
+                {
+                    true => runtime
+                        .block_on(engine.fetch_data_async(from_function))
+                        .map_err(|e| e.to_string().into()),
+                    false => engine.fetch_data_with_cancellation(from_function, &cancellation),
+                }
+            };
+            engine
+                .execute_query_from(
+                    &query,
+                    select,
+                    from,
+                    include_fields,
+                    &fetcher,
+                    &cancellation,
+                )
+                .map_err(|e| e.to_string())
+        })
+        .await?
+        .map_err(|e| e.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::libs::parser::FunctionArg;
+    use crate::libs::progress::Stage;
+
+    fn numbers_source(args: &[FunctionArg]) -> Result<Vec<Pod>, Box<dyn Error>> {
+        let count = match args.first() {
+            Some(FunctionArg::FieldValue(FieldValue::Integer(n))) => *n,
+            _ => return Err("Expected a number argument!".into()),
+        };
+
+        Ok((1..=count)
+            .map(|n| {
+                let mut pod = Pod::new_hash();
+                let _ = pod.insert("n".to_string(), Pod::Integer(n));
+                pod
+            })
+            .collect())
+    }
+
+    #[test]
+    fn test_register_source_is_used_in_from() {
+        let mut engine = Krafna::new();
+        engine.register_source("numbers", numbers_source);
+
+        let (fields, data) = engine
+            .execute_query("SELECT n FROM NUMBERS(5) WHERE n > 3", None, None, None)
+            .expect("Query should succeed");
+
+        assert_eq!(vec!["n".to_string()], fields);
+        assert_eq!(2, data.len());
+    }
+
+    #[test]
+    fn test_union_order_by_sorts_the_combined_rows() {
+        let mut engine = Krafna::new();
+        engine.register_source("NUMBERS", numbers_source);
+
+        let (fields, data) = engine
+            .execute_query(
+                "SELECT n FROM NUMBERS(2) UNION ALL SELECT n FROM NUMBERS(3) ORDER BY n DESC LIMIT 3",
+                None,
+                None,
+                None,
+            )
+            .expect("Query should succeed");
+
+        assert_eq!(vec!["n".to_string()], fields);
+        assert_eq!(
+            vec![r#"{"n":3}"#, r#"{"n":2}"#, r#"{"n":2}"#],
+            data.iter().map(Pod::to_string).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_register_source_case_insensitive() {
+        let mut engine = Krafna::new();
+        engine.register_source("My_Api", numbers_source);
+
+        assert!(engine.has_source("MY_API"));
+        assert!(engine.has_source("my_api"));
+        assert!(!engine.has_source("OTHER_API"));
+    }
+
+    #[cfg(feature = "async")]
+    struct AsyncNumbers;
+
+    #[cfg(feature = "async")]
+    impl AsyncSourceProvider for AsyncNumbers {
+        fn fetch<'a>(
+            &'a self,
+            args: &'a [FunctionArg],
+        ) -> crate::libs::data_fetcher::BoxFuture<'a, Result<Vec<Pod>, Box<dyn Error + Send + Sync>>>
+        {
+            Box::pin(async move { numbers_source(args).map_err(|e| e.to_string().into()) })
+        }
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn test_execute_query_async_with_async_source() {
+        let mut engine = Krafna::new();
+        engine.register_async_source("ASYNC_NUMBERS", AsyncNumbers);
+        engine.register_source("NUMBERS", numbers_source);
+
+        let (_, data) = engine
+            .execute_query_async(
+                "SELECT n FROM ASYNC_NUMBERS(4) WHERE n > 1",
+                None,
+                None,
+                None,
+            )
+            .await
+            .expect("Query should succeed");
+        assert_eq!(3, data.len());
+
+        let (_, data) = engine
+            .execute_query_async("SELECT n FROM NUMBERS(4) WHERE n > 1", None, None, None)
+            .await
+            .expect("Query should succeed");
+        assert_eq!(3, data.len());
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn test_execute_query_async_with_union_and_subqueries() {
+        let mut engine = Krafna::new();
+        engine.register_async_source("ASYNC_NUMBERS", AsyncNumbers);
+        engine.register_source("NUMBERS", numbers_source);
+        let numbers = |data: Vec<Pod>| -> Vec<String> { data.iter().map(Pod::to_string).collect() };
+
+        let (_, data) = engine
+            .execute_query_async(
+                "WITH big AS (SELECT n FROM ASYNC_NUMBERS(5) WHERE n > 3) \
+                 SELECT n FROM big UNION ALL SELECT n FROM NUMBERS(1)",
+                None,
+                None,
+                None,
+            )
+            .await
+            .expect("Query should succeed");
+        assert_eq!(
+            vec![r#"{"n":4}"#, r#"{"n":5}"#, r#"{"n":1}"#],
+            numbers(data)
+        );
+
+        let (_, data) = engine
+            .execute_query_async(
+                "SELECT n FROM NUMBERS(5) WHERE n IN (SELECT n FROM ASYNC_NUMBERS(2)) \
+                 AND EXISTS (SELECT n FROM ASYNC_NUMBERS(1))",
+                None,
+                None,
+                None,
+            )
+            .await
+            .expect("Query should succeed");
+        assert_eq!(vec![r#"{"n":1}"#, r#"{"n":2}"#], numbers(data));
+
+        let error = engine
+            .execute_query_async(
+                "WITH big AS (SELECT n FROM ASYNC_NUMBRS(5)) SELECT n FROM big",
+                None,
+                None,
+                None,
+            )
+            .await
+            .expect_err("Source should be unknown");
+        assert!(error
+            .to_string()
+            .starts_with("Error in WITH big: Unknown function: ASYNC_NUMBRS"));
+    }
+
+    // Async source that returns no rows once it is released
+    #[cfg(feature = "async")]
+    struct WaitingSource {
+        started: std::sync::mpsc::Sender<()>,
+        release: std::sync::Mutex<std::sync::mpsc::Receiver<()>>,
+    }
+
+    #[cfg(feature = "async")]
+    impl AsyncSourceProvider for WaitingSource {
+        fn fetch<'a>(
+            &'a self,
+            _args: &'a [FunctionArg],
+        ) -> crate::libs::data_fetcher::BoxFuture<'a, Result<Vec<Pod>, Box<dyn Error + Send + Sync>>>
+        {
+            Box::pin(async move {
+                let _ = self.started.send(());
+                let _ = self.release.lock().map(|release| release.recv());
+                Ok(Vec::new())
+            })
+        }
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn test_dropping_execute_query_async_cancels_the_query() {
+        use std::future::Future;
+        use std::sync::mpsc;
+
+        let (started, started_rx) = mpsc::channel();
+        let (release, release_rx) = mpsc::channel();
+        let (fetched, fetched_rx) = mpsc::channel();
+        let mut engine = Krafna::new();
+        engine.register_async_source(
+            "WAITING",
+            WaitingSource {
+                started,
+                release: std::sync::Mutex::new(release_rx),
+            },
+        );
+        engine.register_source("NUMBERS", move |args: &[FunctionArg]| {
+            let _ = fetched.send(());
+            numbers_source(args)
+        });
+
+        let mut future = Box::pin(engine.execute_query_async(
+            "SELECT n FROM WAITING() UNION ALL SELECT n FROM NUMBERS(1)",
+            None,
+            None,
+            None,
+        ));
+        let mut context = std::task::Context::from_waker(std::task::Waker::noop());
+        assert!(future.as_mut().poll(&mut context).is_pending());
+        started_rx.recv().unwrap();
+        drop(future);
+        release.send(()).unwrap();
+
+        assert_eq!(
+            Err(mpsc::RecvTimeoutError::Timeout),
+            fetched_rx.recv_timeout(std::time::Duration::from_millis(500))
+        );
+    }
+
+    #[test]
+    fn test_unknown_function_suggestions() {
+        let mut engine = Krafna::new();
+        engine.register_source("numbers", numbers_source);
+        engine.register_function("double", |_| Ok(FieldValue::Null));
+
+        let error = engine
+            .execute_query("SELECT n FROM NUMBRS(3)", None, None, None)
+            .expect_err("Source should be unknown");
+        assert!(error
+            .to_string()
+            .starts_with("Unknown function: NUMBRS, did you mean 'NUMBERS'?"));
+
+        let error = engine
+            .execute_query(
+                "SELECT n FROM NUMBERS(3) WHERE dubble(n) > 1",
+                None,
+                None,
+                None,
+            )
+            .expect_err("Function should be unknown");
+        assert!(error
+            .to_string()
+            .starts_with("Unknown function: dubble, did you mean 'DOUBLE'?"));
+    }
+
+    #[test]
+    fn test_check_fields() {
+        let mut engine = Krafna::new();
+        engine.register_source("numbers", numbers_source);
+
+        let warnings = engine
+            .check_fields("SELECT n, m FROM NUMBERS(3) WHERE nn > 1", None, None, None)
+            .expect("Check should succeed");
+
+        assert_eq!(
+            vec![
+                "Unknown field 'm', did you mean 'n'?".to_string(),
+                "Unknown field 'nn', did you mean 'n'?".to_string(),
+            ],
+            warnings.iter().map(|w| w.to_string()).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_execute_query_with_cancellation() {
+        let mut engine = Krafna::new();
+        engine.register_source("numbers", numbers_source);
+
+        let cancellation = CancellationToken::new();
+        cancellation.cancel();
+
+        let result = engine.execute_query_with_cancellation(
+            "SELECT n FROM NUMBERS(5) WHERE n > 3",
+            None,
+            None,
+            None,
+            &cancellation,
+        );
+        assert_eq!(
+            "Query was cancelled!",
+            result.expect_err("Query should be cancelled").to_string()
+        );
+    }
+
+    #[test]
+    fn test_execute_prepared() {
+        let mut engine = Krafna::new();
+        engine.register_source("numbers", numbers_source);
+
+        let prepared = engine
+            .prepare(
+                "SELECT n FROM NUMBERS(:count) WHERE n > 1",
+                None,
+                None,
+                None,
+            )
+            .unwrap();
+
+        for count in [3, 6] {
+            let mut params = HashMap::new();
+            params.insert("count".to_string(), FieldValue::Integer(count));
+            let (_, data) = engine
+                .execute_prepared(&prepared, &params, &CancellationToken::default())
+                .unwrap();
+            assert_eq!(count as usize - 1, data.len());
+        }
+    }
+
+    #[test]
+    fn test_register_function_is_used_in_where() {
+        let mut engine = Krafna::new();
+        engine
+            .register_source("numbers", numbers_source)
+            .register_function("double", |args| match args {
+                [FieldValue::Integer(n)] => Ok(FieldValue::Integer(n * 2)),
+                _ => Err("DOUBLE expects one number!".to_string()),
+            });
+
+        let (_, data) = engine
+            .execute_query(
+                "SELECT n FROM NUMBERS(5) WHERE double(n) > 6",
+                None,
+                None,
+                None,
+            )
+            .expect("Query should succeed");
+        assert_eq!(2, data.len());
+
+        // Other engines do not see it
+        let mut other = Krafna::new();
+        other.register_source("numbers", numbers_source);
+        let error = other
+            .execute_query(
+                "SELECT n FROM NUMBERS(5) WHERE double(n) > 6",
+                None,
+                None,
+                None,
+            )
+            .expect_err("Query should fail on unknown function");
+        assert!(error
+            .to_string()
+            .ends_with("at characters 31..40: `double(n)`"));
+    }
+
+    #[test]
+    fn test_engine_uses_its_own_markdown_cache() {
+        let dir = std::env::temp_dir().join(format!("krafna-engine-cache-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("a.md"), "---\ntitle: a\n---\n# a").unwrap();
+
+        let engine = Krafna::with_config(KrafnaConfig {
+            use_disk_cache: false,
+            ..KrafnaConfig::default()
+        });
+        assert!(!engine.config().use_disk_cache);
+        let query = format!("SELECT title FROM FRONTMATTER_DATA('{}')", dir.display());
+
+        assert!(!engine.plan(&query).unwrap().uses_cache);
+        let (_, data) = engine.execute_query(&query, None, None, None).unwrap();
+        assert_eq!(1, data.len());
+        assert!(engine.plan(&query).unwrap().uses_cache);
+
+        engine.clear_cache();
+        assert!(!engine.plan(&query).unwrap().uses_cache);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_on_progress_reports_files_and_rows() {
+        let dir =
+            std::env::temp_dir().join(format!("krafna-engine-progress-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("a.md"), "---\nn: 1\n---\n").unwrap();
+        std::fs::write(dir.join("b.md"), "---\nn: 2\n---\n").unwrap();
+
+        let events = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let events_clone = Arc::clone(&events);
+        let mut engine = Krafna::with_config(KrafnaConfig {
+            use_disk_cache: false,
+            ..KrafnaConfig::default()
+        });
+        engine.on_progress(move |event| events_clone.lock().unwrap().push(event.clone()));
+
+        let query = format!(
+            "SELECT n FROM FRONTMATTER_DATA('{}') WHERE n > 1",
+            dir.display()
+        );
+        let (_, data) = engine.execute_query(&query, None, None, None).unwrap();
+        assert_eq!(1, data.len());
+
+        let events = events.lock().unwrap();
+        assert_eq!(ProgressEvent::FilesDiscovered(2), events[0]);
+        assert!(events.contains(&ProgressEvent::FileParsed {
+            parsed: 2,
+            total: 2
+        }));
+        assert_eq!(
+            Some(&ProgressEvent::RowsFiltered {
+                filtered: 2,
+                total: 2
+            }),
+            events
+                .iter()
+                .rfind(|event| matches!(event, ProgressEvent::RowsFiltered { .. }))
+        );
+        let stages: Vec<(Stage, usize)> = events
+            .iter()
+            .filter_map(|event| match event {
+                ProgressEvent::StageFinished { stage, rows, .. } => Some((*stage, *rows)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            vec![
+                (Stage::Walk, 2),
+                (Stage::CacheLoad, 0),
+                (Stage::Parse, 2),
+                (Stage::Where, 1),
+                (Stage::OrderBy, 1),
+                (Stage::Select, 1),
+            ],
+            stages
+        );
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_errors_report_files_that_fail_to_parse() {
+        let dir = std::env::temp_dir().join(format!("krafna-engine-errors-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("a.md"), "---\ntitle: a\n---\n# a").unwrap();
+        std::fs::write(dir.join("b.md"), "---\ntitle: [b\n---\n# b").unwrap();
+        std::fs::write(dir.join("c.md"), b"\xff\xfe").unwrap();
+        let query = format!("SELECT title FROM FRONTMATTER_DATA('{}')", dir.display());
+
+        let engine = Krafna::with_config(KrafnaConfig {
+            use_disk_cache: false,
+            ..KrafnaConfig::default()
+        });
+        let (_, data) = engine.execute_query(&query, None, None, None).unwrap();
+        assert_eq!(1, data.len());
+        let errors = engine.errors();
+        assert_eq!(2, errors.len());
+        assert_eq!(dir.join("b.md").display().to_string(), errors[0].path);
+        assert!(errors[0].error.starts_with("Invalid frontmatter: "));
+        assert_eq!(
+            format!("{}: Not valid UTF-8", dir.join("c.md").display()),
+            errors[1].to_string()
+        );
+
+        let strict_engine = Krafna::with_config(KrafnaConfig {
+            use_disk_cache: false,
+            strict: true,
+            ..KrafnaConfig::default()
+        });
+        let error = strict_engine
+            .execute_query(&query, None, None, None)
+            .unwrap_err()
+            .to_string();
+        assert!(error.starts_with("2 file(s) could not be parsed:\n  "));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_threads_limit_the_parsing_pool() {
+        let dir =
+            std::env::temp_dir().join(format!("krafna-engine-threads-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("a.md"), "---\nn: 1\n---\n").unwrap();
+
+        let threads = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let threads_clone = Arc::clone(&threads);
+        let mut engine = Krafna::with_config(KrafnaConfig {
+            use_disk_cache: false,
+            threads: Some(2),
+            ..KrafnaConfig::default()
+        });
+        engine.on_progress(move |event| {
+            if let ProgressEvent::FileParsed { .. } = event {
+                threads_clone
+                    .lock()
+                    .unwrap()
+                    .push(rayon::current_num_threads());
+            }
+        });
+
+        let query = format!("SELECT n FROM FRONTMATTER_DATA('{}')", dir.display());
+        let (_, data) = engine.execute_query(&query, None, None, None).unwrap();
+        std::fs::remove_dir_all(dir).unwrap();
+
+        assert_eq!(1, data.len());
+        assert_eq!(vec![2], *threads.lock().unwrap());
+    }
+
+    #[test]
+    fn test_results_are_cached_until_a_file_changes() {
+        let dir =
+            std::env::temp_dir().join(format!("krafna-engine-result-cache-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("a.md"), "---\nn: 1\n---\n").unwrap();
+
+        let calls = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let calls_clone = Arc::clone(&calls);
+        let mut engine = Krafna::with_config(KrafnaConfig {
+            use_disk_cache: false,
+            ..KrafnaConfig::default()
+        });
+        engine.register_function("COUNTED", move |args| {
+            calls_clone.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            Ok(args[0].clone())
+        });
+        let query = format!(
+            "SELECT n FROM FRONTMATTER_DATA('{}') WHERE COUNTED(n) > 0",
+            dir.display()
+        );
+
+        let first = engine.execute_query(&query, None, None, None).unwrap();
+        let second = engine.execute_query(&query, None, None, None).unwrap();
+        let calls_before_change = calls.load(std::sync::atomic::Ordering::SeqCst);
+        std::fs::write(dir.join("a.md"), "---\nn: 22\n---\n").unwrap();
+        let (_, changed) = engine.execute_query(&query, None, None, None).unwrap();
+        std::fs::remove_dir_all(dir).unwrap();
+
+        assert_eq!(first, second);
+        assert_eq!(1, calls_before_change);
+        assert_eq!(2, calls.load(std::sync::atomic::Ordering::SeqCst));
+        assert_eq!(Some(&Pod::Integer(22)), changed[0].nested_get("n"));
+    }
+
+    #[test]
+    fn test_results_with_the_clock_are_not_cached() {
+        let dir = std::env::temp_dir().join(format!("krafna-engine-clock-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("a.md"), "---\nn: 1\n---\n").unwrap();
+
+        let calls = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let calls_clone = Arc::clone(&calls);
+        let mut engine = Krafna::with_config(KrafnaConfig {
+            use_disk_cache: false,
+            ..KrafnaConfig::default()
+        });
+        engine.register_function("COUNTED", move |args| {
+            calls_clone.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            Ok(args[0].clone())
+        });
+        let run = |condition: &str| {
+            let query = format!(
+                "SELECT n FROM FRONTMATTER_DATA('{}') WHERE COUNTED(n) > 0 AND {}",
+                dir.display(),
+                condition
+            );
+            let before = calls.load(std::sync::atomic::Ordering::SeqCst);
+            engine.execute_query(&query, None, None, None).unwrap();
+            engine.execute_query(&query, None, None, None).unwrap();
+            calls.load(std::sync::atomic::Ordering::SeqCst) - before
+        };
+
+        let executions = [
+            run("TODAY() <= NOW()"),
+            run("LAST_DAYS(7) < now()"),
+            run("RANDOM() < 2"),
+            run("RANDOM(42) < 2"),
+        ];
+        std::fs::remove_dir_all(dir).unwrap();
+
+        assert_eq!([2, 2, 2, 1], executions);
+    }
+
+    #[test]
+    fn test_execute_update_of_several_paths() {
+        let dir = std::env::temp_dir().join(format!("krafna-engine-paths-{}", std::process::id()));
+        for folder in ["a", "b", "c"] {
+            std::fs::create_dir_all(dir.join(folder)).unwrap();
+            std::fs::write(dir.join(folder).join("x.md"), "---\nstatus: done\n---\n").unwrap();
+        }
+        let engine = Krafna::with_config(KrafnaConfig {
+            use_disk_cache: false,
+            ..KrafnaConfig::default()
+        });
+        let from = format!(
+            "FRONTMATTER_DATA('{}', '{}')",
+            dir.join("a").display(),
+            dir.join("b").display()
+        );
+
+        let (_, selected) = engine
+            .execute_query(&format!("SELECT file.path FROM {}", from), None, None, None)
+            .unwrap();
+        let updates = engine.execute_update(
+            &format!("UPDATE {} SET status = 'archived'", from),
+            &UpdateOptions {
+                dry_run: true,
+                backup_dir: None,
+            },
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let selected: Vec<String> = selected
+            .iter()
+            .filter_map(|row| row.nested_get("file.path").map(Pod::to_string))
+            .collect();
+        let updated: Vec<String> = updates
+            .unwrap()
+            .iter()
+            .map(|update| update.path.display().to_string())
+            .collect();
+        assert_eq!(2, updated.len());
+        assert_eq!(selected, updated);
+    }
+
+    #[test]
+    fn test_execute_update_writes_matching_files() {
+        let dir = std::env::temp_dir().join(format!("krafna-engine-update-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("a.md"), "---\nstatus: done\n---\n# a").unwrap();
+        std::fs::write(dir.join("b.md"), "---\nstatus: open\n---\n# b").unwrap();
+        let engine = Krafna::with_config(KrafnaConfig {
+            use_disk_cache: false,
+            ..KrafnaConfig::default()
+        });
+        let options = UpdateOptions {
+            dry_run: false,
+            backup_dir: Some(dir.with_extension("backups")),
+        };
+        let query = format!("SELECT status FROM FRONTMATTER_DATA('{}')", dir.display());
+
+        let before = engine.execute_query(&query, None, None, None);
+        let updates = engine.execute_update(
+            &format!(
+                "UPDATE FRONTMATTER_DATA('{}') SET status = 'archived' WHERE status == 'done'",
+                dir.display()
+            ),
+            &options,
+        );
+        let after = engine.execute_query(&query, None, None, None);
+        let unsupported = engine.execute_update(
+            &format!("UPDATE MD_LINKS('{}') SET a = 1", dir.display()),
+            &options,
+        );
+        std::fs::remove_dir_all(dir.with_extension("backups")).unwrap();
+        std::fs::remove_dir_all(dir).unwrap();
+
+        let status = |results: (Vec<String>, Vec<Pod>)| -> Vec<String> {
+            results.1.iter().map(|row| row.to_string()).collect()
+        };
+        assert_eq!(
+            vec![r#"{"status":"done"}"#, r#"{"status":"open"}"#],
+            status(before.unwrap())
+        );
+        assert_eq!(1, updates.unwrap().len());
+        assert_eq!(
+            vec![r#"{"status":"archived"}"#, r#"{"status":"open"}"#],
+            status(after.unwrap())
+        );
+        assert_eq!(
+            "UPDATE is only supported for FRONTMATTER_DATA and MD_TASKS, not MD_LINKS",
+            unsupported.unwrap_err().to_string()
+        );
+    }
+
+    #[test]
+    fn test_tag_from_reads_the_vault() {
+        let dir = std::env::temp_dir().join(format!("krafna-engine-vault-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("a.md"), "---\ntags: [project, work]\n---\n").unwrap();
+        std::fs::write(dir.join("b.md"), "---\ntags: [project]\n---\n").unwrap();
+        std::fs::write(dir.join("c.md"), "---\ntitle: c\n---\n").unwrap();
+
+        let engine = Krafna::with_config(KrafnaConfig {
+            use_disk_cache: false,
+            vault: Some(dir.display().to_string()),
+            ..KrafnaConfig::default()
+        });
+        let names = |query: &str| -> Vec<String> {
+            let (_, rows) = engine.execute_query(query, None, None, None).unwrap();
+            rows.iter()
+                .filter_map(|row| row.nested_get("file.name")?.as_string())
+                .collect()
+        };
+
+        assert_eq!(
+            vec!["b.md"],
+            names("SELECT file.name FROM #project AND NOT #work")
+        );
+        assert_eq!(
+            vec!["a.md", "b.md", "c.md"],
+            names("SELECT file.name FROM FRONTMATTER_DATA() ORDER BY file.name")
+        );
+        assert_eq!(
+            Some(dir.display().to_string()),
+            engine
+                .plan("SELECT file.name FROM #work")
+                .unwrap()
+                .source_args
+                .first()
+                .map(|arg| arg.trim_matches('\'').to_string())
+        );
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_computed_fields_are_added_to_every_row() {
+        let config = |fields: &[(&str, &str)]| KrafnaConfig {
+            computed_fields: fields
+                .iter()
+                .map(|(name, expression)| (name.to_string(), expression.to_string()))
+                .collect(),
+            ..KrafnaConfig::default()
+        };
+        let mut engine = Krafna::with_config(config(&[
+            ("double", "n * 2"),
+            ("big", "double > 4"),
+            ("missing", "DATE(nope).year"),
+        ]));
+        engine.register_source("NUMBERS", numbers_source);
+        let broken = Krafna::with_config(config(&[("x", "n ==")]));
+
+        let (_, rows) = engine
+            .execute_query(
+                "SELECT n, double, big, missing FROM NUMBERS(3) WHERE big",
+                None,
+                None,
+                None,
+            )
+            .unwrap();
+        let error = broken
+            .execute_query("SELECT n FROM NUMBERS(3)", None, None, None)
+            .unwrap_err();
+
+        let rows: Vec<String> = rows.iter().map(Pod::to_string).collect();
+        assert_eq!(
+            vec![r#"{"n":3,"double":6,"big":true,"missing":null}"#],
+            rows
+        );
+        assert!(error
+            .to_string()
+            .starts_with("Computed field x: Error parsing WHERE"));
+    }
+
+    #[test]
+    fn test_field_aliases_rename_fields() {
+        let aliases = [("deadline", "due"), ("Created", "created"), ("a.b", "c")];
+        let config = |aliases: &[(&str, &str)]| KrafnaConfig {
+            field_aliases: aliases
+                .iter()
+                .map(|(alias, field)| (alias.to_string(), field.to_string()))
+                .collect(),
+            computed_fields: vec![("late".to_string(), "due > 2".to_string())],
+            ..KrafnaConfig::default()
+        };
+        let mut engine = Krafna::with_config(config(&aliases[..2]));
+        engine.register_source("NOTES", |_: &[FunctionArg]| {
+            let note = |fields: &[(&str, i64)]| {
+                let mut pod = Pod::new_hash();
+                for (name, value) in fields {
+                    let _ = pod.insert(name.to_string(), Pod::Integer(*value));
+                }
+                pod
+            };
+            Ok(vec![
+                note(&[("deadline", 1), ("Created", 5), ("n", 1)]),
+                note(&[("n", 2), ("due", 3)]),
+                note(&[("due", 4), ("deadline", 9), ("n", 3)]),
+            ])
+        });
+        let broken = Krafna::with_config(config(&aliases));
+
+        let (fields, rows) = engine
+            .execute_query("SELECT * FROM NOTES() WHERE due > 0", None, None, None)
+            .unwrap();
+        let error = broken
+            .execute_query("SELECT * FROM NOTES()", None, None, None)
+            .unwrap_err();
+
+        assert_eq!(vec!["due", "created", "n", "late"], fields);
+        let rows: Vec<String> = rows.iter().map(Pod::to_string).collect();
+        assert_eq!(
+            vec![
+                r#"{"due":1,"created":5,"n":1,"late":false}"#,
+                r#"{"n":2,"due":3,"late":true}"#,
+                r#"{"due":4,"n":3,"late":true}"#,
+            ],
+            rows
+        );
+        assert_eq!(
+            "Field alias a.b -> c can not be nested, use names without '.' or '['",
+            error.to_string()
+        );
+    }
+
+    #[test]
+    fn test_collation_orders_strings() {
+        let mut engine = Krafna::with_config(KrafnaConfig {
+            collation: Some("de".to_string()),
+            ..KrafnaConfig::default()
+        });
+        engine.register_source("WORDS", |_: &[FunctionArg]| {
+            Ok(["Zebra", "apple", "Ärger", "Apfel"]
+                .iter()
+                .map(|word| {
+                    let mut pod = Pod::new_hash();
+                    let _ = pod.insert("word".to_string(), Pod::String(word.to_string()));
+                    pod
+                })
+                .collect())
+        });
+
+        let result =
+            engine.execute_query("SELECT word FROM WORDS() ORDER BY word", None, None, None);
+
+        #[cfg(feature = "collation")]
+        {
+            let words: Vec<String> = result.unwrap().1.iter().map(Pod::to_string).collect();
+            assert_eq!(
+                vec![
+                    r#"{"word":"Apfel"}"#,
+                    r#"{"word":"apple"}"#,
+                    r#"{"word":"Ärger"}"#,
+                    r#"{"word":"Zebra"}"#,
+                ],
+                words
+            );
+        }
+        #[cfg(not(feature = "collation"))]
+        assert_eq!(
+            "Collation de needs krafna built with the `collation` feature",
+            result.unwrap_err().to_string()
+        );
+    }
+
+    #[test]
+    fn test_config_macros_are_expanded() {
+        let macros = [("big", "n > 2"), ("odd_big", "$big AND n // 2 * 2 != n")];
+        let mut engine = Krafna::with_config(KrafnaConfig {
+            macros: macros
+                .iter()
+                .map(|(name, condition)| (name.to_string(), condition.to_string()))
+                .collect(),
+            ..KrafnaConfig::default()
+        });
+        engine.register_source("NUMBERS", numbers_source);
+
+        let numbers = |query: &str| -> Result<Vec<String>, String> {
+            let (_, rows) = engine
+                .execute_query(query, None, None, None)
+                .map_err(|error| error.to_string())?;
+            Ok(rows.iter().map(Pod::to_string).collect())
+        };
+
+        assert_eq!(
+            Ok(vec![r#"{"n":3}"#.to_string(), r#"{"n":5}"#.to_string()]),
+            numbers("SELECT n FROM NUMBERS(6) WHERE $odd_big")
+        );
+        // Macros defined in the query take precedence
+        assert_eq!(
+            Ok(vec![r#"{"n":6}"#.to_string()]),
+            numbers("DEFINE big AS n > 5; SELECT n FROM NUMBERS(6) WHERE $big")
+        );
+        assert!(numbers("SELECT n FROM NUMBERS(6) WHERE $bg")
+            .unwrap_err()
+            .starts_with("Unknown macro: $bg, did you mean 'big'?"));
+    }
+
+    #[test]
+    fn test_engine_is_send_and_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<Krafna>();
+    }
+
+    #[test]
+    fn test_unknown_source_errors() {
+        let engine = Krafna::new();
+
+        assert!(engine
+            .execute_query("SELECT n FROM NOPE(5)", None, None, None)
+            .is_err());
+    }
+}
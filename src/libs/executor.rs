@@ -1,20 +1,30 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::error::Error;
+use std::hash::{BuildHasher, DefaultHasher, Hash, Hasher, RandomState};
 use std::num::NonZero;
-use std::sync::Mutex;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
 
-use chrono::{DateTime, Datelike, NaiveDate, NaiveDateTime, Utc};
+use chrono::{
+    DateTime, Datelike, Days, Local, NaiveDate, NaiveDateTime, NaiveTime, TimeDelta, Timelike, Utc,
+};
+use indexmap::{IndexMap, IndexSet};
 use lru::LruCache;
 use once_cell::sync::Lazy;
 use regex::Regex;
 
-use crate::libs::data_fetcher::fetch_data;
+use crate::libs::aggregate::group_rows;
+use crate::libs::cancellation::CancellationToken;
+use crate::libs::collation::Collation;
 use crate::libs::data_fetcher::pod::Pod;
+use crate::libs::data_fetcher::{fetch_data_with_cancellation, DataFetcher};
 use crate::libs::parser::{
     ExpressionElement, FieldValue, Function, FunctionArg, Operator, OrderByFieldOption,
-    OrderDirection, Query,
+    OrderDirection, Quantified, Quantifier, Query, QuerySpans, SELECT_ALL,
 };
-use crate::libs::PeekableDeque;
+use crate::libs::progress::{Progress, ProgressEvent, Stage, ROWS_REPORT_INTERVAL};
+use crate::libs::suggestion::with_suggestion;
+use crate::libs::with::With;
 
 pub fn execute_query(
     query: &str,
@@ -22,31 +32,113 @@ pub fn execute_query(
     from: Option<String>,
     include_fields: Option<String>,
 ) -> Result<(Vec<String>, Vec<Pod>), Box<dyn Error>> {
-    let mut query = match query.parse::<Query>() {
-        Ok(q) => q,
-        Err(error) => return Err(error.into()),
+    execute_query_with_cancellation(
+        query,
+        select,
+        from,
+        include_fields,
+        &CancellationToken::default(),
+    )
+}
+
+/// Same as [`execute_query`], but aborts with an error as soon as `cancellation` is cancelled or
+/// times out (checked while walking, parsing and per row in WHERE).
+pub fn execute_query_with_cancellation(
+    query: &str,
+    select: Option<String>,
+    from: Option<String>,
+    include_fields: Option<String>,
+    cancellation: &CancellationToken,
+) -> Result<(Vec<String>, Vec<Pod>), Box<dyn Error>> {
+    execute_query_with_fetcher(
+        query,
+        select,
+        from,
+        include_fields,
+        &|from_function| fetch_data_with_cancellation(from_function, cancellation),
+        &ExecutionContext::shared().with_cancellation(cancellation.clone()),
+    )
+}
+
+/// Same as [`execute_query`], but FROM is resolved through `fetcher` instead of the built-in
+/// sources. This is what [`Krafna`](crate::libs::engine::Krafna) uses for registered sources.
+pub fn execute_query_with_fetcher(
+    query: &str,
+    select: Option<String>,
+    from: Option<String>,
+    include_fields: Option<String>,
+    fetcher: &DataFetcher,
+    context: &ExecutionContext,
+) -> Result<(Vec<String>, Vec<Pod>), Box<dyn Error>> {
+    // The SELECT/FROM/include overrides are for the main query, not for the named ones of WITH
+    With::execute_query(query, fetcher, |query, main, fetcher| {
+        let query = match main {
+            true => prepare_query(query, select.clone(), from.clone(), include_fields.clone()),
+            false => prepare_query(query, None, None, None),
+        }?;
+        execute_parsed_query(query, fetcher, context)
+    })
+}
+
+/// Executes an already parsed (and bound) `query`, fetching FROM through `fetcher`.
+pub fn execute_parsed_query(
+    query: Query,
+    fetcher: &DataFetcher,
+    context: &ExecutionContext,
+) -> Result<(Vec<String>, Vec<Pod>), Box<dyn Error>> {
+    //println!("Parsed query: {:?}", query);
+    let query = resolve_subqueries(query, |subquery| {
+        execute_parsed_query(subquery, fetcher, context)
+    })?;
+    // FROM
+    let from_function = query
+        .from_function
+        .as_ref()
+        .ok_or("Query is missing FROM!")?;
+    let data = {
+        let _span = tracing::debug_span!("fetch", source = %from_function).entered();
+        let data = fetcher(from_function)
+            .map_err(|error| query.spans.locate(&error.to_string(), query.spans.from))?;
+        tracing::debug!(rows = data.len(), "fetched rows");
+        data
     };
 
+    execute_on_data(query, data, context)
+}
+
+/// Async version of [`execute_query`], runs the query with a default
+/// [`Krafna::execute_query_async`](crate::libs::engine::Krafna::execute_query_async).
+#[cfg(feature = "async")]
+pub async fn execute_query_async(
+    query: &str,
+    select: Option<String>,
+    from: Option<String>,
+    include_fields: Option<String>,
+) -> Result<(Vec<String>, Vec<Pod>), Box<dyn Error + Send + Sync>> {
+    crate::libs::engine::Krafna::new()
+        .execute_query_async(query, select, from, include_fields)
+        .await
+}
+
+/// Parses `query` and applies the SELECT/FROM/include overrides on top of it.
+#[tracing::instrument(level = "debug", name = "parse", skip_all)]
+pub fn prepare_query(
+    query: &str,
+    select: Option<String>,
+    from: Option<String>,
+    include_fields: Option<String>,
+) -> Result<Query, Box<dyn Error>> {
+    let mut query = query.parse::<Query>()?;
+
     // SELECT override if present
     if let Some(select_query) = select {
-        let mut peekable_select_query: PeekableDeque<char> =
-            PeekableDeque::from_iter(format!("SELECT {}", select_query).chars());
-        match Query::parse_select(&mut peekable_select_query) {
-            Ok(select_fields) => query.select_fields = select_fields,
-            Err(error) => {
-                return Err(format!(
-                    "Error parsing SELECT: {}, Query: \"{}\"",
-                    error, peekable_select_query
-                )
-                .into())
-            }
-        }
+        query.select_fields = Query::parse_select_fields(&select_query)?;
+        query.select_exclude = Vec::new();
+        query.spans.select = None;
     }
     // SELECT include/add fields to query SELECT fields
     if let Some(include_select_query) = include_fields {
-        let mut peekable_select_query: PeekableDeque<char> =
-            PeekableDeque::from_iter(format!("SELECT {}", include_select_query).chars());
-        match Query::parse_select(&mut peekable_select_query) {
+        match Query::parse_select_fields(&include_select_query) {
             Ok(select_fields) => {
                 // TODO: Should not filter duplicates, but only append "include_fields" that are not
                 // already in "select_fields"
@@ -55,102 +147,530 @@ pub fn execute_query(
             }
             Err(error) => {
                 if query.select_fields.is_empty() {
-                    return Err(format!(
-                        "Error parsing SELECT: {}, Query: \"{}\"",
-                        error, peekable_select_query
-                    )
-                    .into());
+                    return Err(error.into());
                 }
             }
         }
     }
 
     if let Some(from_query) = from {
-        let mut peekable_from_query: PeekableDeque<char> =
-            PeekableDeque::from_iter(format!("FROM {}", from_query).chars());
-        match Query::parse_from(&mut peekable_from_query) {
-            Ok(from_function) => query.from_function = Some(from_function),
-            Err(error) => {
-                return Err(format!(
-                    "Error parsing FROM: {}, Query: \"{}\"",
-                    error, peekable_from_query
-                )
-                .into())
+        query.from_function = Some(Query::parse_from_function(&from_query)?);
+        query.spans.from = None;
+    }
+
+    Ok(query)
+}
+
+/// Runs the subqueries in WHERE of `query` with `execute` and puts their results in their place:
+/// the values of their column after IN (e.g. `file.path IN (SELECT file.path FROM MD_TASKS(...))`),
+/// whether there are rows for EXISTS and the only value (NULL without rows) anywhere else.
+pub(crate) fn resolve_subqueries<F>(mut query: Query, execute: F) -> Result<Query, Box<dyn Error>>
+where
+    F: Fn(Query) -> Result<(Vec<String>, Vec<Pod>), Box<dyn Error>>,
+{
+    for index in 0..query.where_expression.len() {
+        let after_in = index > 0
+            && matches!(
+                query.where_expression[index - 1],
+                ExpressionElement::Operator(Operator::In | Operator::NotIn)
+            );
+        let span = query.spans.where_elements(index, index);
+        let located = |error: String| query.spans.locate(&error, span);
+        let value = match &query.where_expression[index] {
+            ExpressionElement::Subquery(subquery) => {
+                let (fields, rows) = execute((**subquery).clone())
+                    .map_err(|error| located(format!("Subquery failed: {}", error)))?;
+                let [field] = fields.as_slice() else {
+                    return Err(located(format!(
+                        "Subquery needs to select one field, but selects {}",
+                        fields.join(", ")
+                    ))
+                    .into());
+                };
+                let mut values: Vec<FieldValue> =
+                    rows.iter().map(|row| get_field_value(field, row)).collect();
+                match values.len() {
+                    _ if after_in => FieldValue::List(values),
+                    0 => FieldValue::Null,
+                    1 => values.remove(0),
+                    count => {
+                        return Err(located(format!(
+                            "Subquery returned {} rows, but one value is needed here (IN takes all of them)",
+                            count
+                        ))
+                        .into())
+                    }
+                }
             }
-        }
+            ExpressionElement::Exists(subquery) => {
+                let (_, rows) = execute((**subquery).clone())
+                    .map_err(|error| located(format!("Subquery failed: {}", error)))?;
+                FieldValue::Bool(!rows.is_empty())
+            }
+            _ => continue,
+        };
+        query.where_expression[index] = ExpressionElement::FieldValue(value);
     }
+    Ok(query)
+}
 
-    //println!("Parsed query: {:?}", query);
-    // FROM
-    let mut data = fetch_data(&query.from_function.unwrap())?;
+/// Runs WHERE, ORDER BY and SELECT of an already prepared `query` over fetched `data`.
+pub fn execute_on_data(
+    query: Query,
+    mut data: Vec<Pod>,
+    context: &ExecutionContext,
+) -> Result<(Vec<String>, Vec<Pod>), Box<dyn Error>> {
+    let progress = &context.progress;
+    // Before WHERE, so WHERE can use them
+    if !query.is_grouped() {
+        add_select_aliases(&query, &mut data, context)?;
+    }
     // WHERE
-    execute_where(&query.where_expression, &mut data)?;
+    let started = Instant::now();
+    tracing::debug_span!("where", rows = data.len())
+        .in_scope(|| execute_where(&query.where_expression, &query.spans, &mut data, context))?;
+    tracing::debug!(rows = data.len(), "filtered rows");
+    progress.stage_finished(Stage::Where, started, data.len());
+    // GROUP BY
+    query.check_grouping()?;
+    let started = Instant::now();
+    let order_by_fields = execute_group_by(&query, &mut data, context);
+    if query.is_grouped() {
+        progress.stage_finished(Stage::GroupBy, started, data.len());
+    }
+    // Before ORDER BY, so the columns of * follow the fetch order
+    let select_fields =
+        expand_select_all(query.select_fields.clone(), &query.select_exclude, &data);
+    // Before ORDER BY as well, so `ORDER BY 1` can sort by a SELECT function
+    execute_select_functions(&query, &mut data, context)?;
     // ORDER BY
-    execute_order_by(&query.order_by_fields, &mut data)?;
+    context.cancellation.check()?;
+    let started = Instant::now();
+    tracing::debug_span!("order_by", rows = data.len())
+        .in_scope(|| execute_order_by(&order_by_fields, &mut data, context))?;
+    progress.stage_finished(Stage::OrderBy, started, data.len());
+    // LIMIT and OFFSET
+    execute_limit(&query, &mut data);
     // SELECT
-    execute_select(&query.select_fields, &mut data);
+    let started = Instant::now();
+    tracing::debug_span!("select", rows = data.len())
+        .in_scope(|| execute_select(&select_fields, &mut data));
+    progress.stage_finished(Stage::Select, started, data.len());
+
+    Ok((select_fields, data))
+}
+
+/// Skips the first OFFSET rows of `data` and keeps at most LIMIT of the rest, of every distinct
+/// value of the `LIMIT ... PER` fields when the query has them.
+pub(crate) fn execute_limit(query: &Query, data: &mut Vec<Pod>) {
+    let offset = query.offset.unwrap_or_default();
+    if !query.limit_per.is_empty() {
+        let limit = query.limit.unwrap_or(usize::MAX);
+        let mut counts: HashMap<String, usize> = HashMap::new();
+        data.retain(|row| {
+            let values: Vec<FieldValue> = query
+                .limit_per
+                .iter()
+                .map(|field_name| get_field_value(field_name, row))
+                .collect();
+            let count = counts.entry(format!("{:?}", values)).or_default();
+            *count += 1;
+            *count > offset && *count - offset <= limit
+        });
+        return;
+    }
+    data.drain(..offset.min(data.len()));
+    if let Some(limit) = query.limit {
+        data.truncate(limit);
+    }
+}
+
+/// Replaces `*` with the top-level fields of `data`, in the order they first appear (first row
+/// first, keys in frontmatter order). Fields that are also selected explicitly, or in `exclude`,
+/// are not repeated.
+pub(crate) fn expand_select_all<'a, I>(
+    fields: Vec<String>,
+    exclude: &[String],
+    data: I,
+) -> Vec<String>
+where
+    I: IntoIterator<Item = &'a Pod> + Clone,
+{
+    if !fields.iter().any(|field| field == SELECT_ALL) {
+        return fields;
+    }
+
+    let mut expanded = IndexSet::new();
+    for field in &fields {
+        if field != SELECT_ALL {
+            expanded.insert(field.clone());
+            continue;
+        }
+        for pod in data.clone() {
+            if let Pod::Hash(hash) = pod {
+                for key in hash.keys() {
+                    if !fields.contains(key) && !exclude.contains(key) {
+                        expanded.insert(key.clone());
+                    }
+                }
+            }
+        }
+    }
+    expanded.into_iter().collect()
+}
+
+/// Number of rows for every value of `field_name` (in value order, rows without the field are
+/// counted under NULL), followed by the grand total with NULL as the value, like SQL's
+/// `GROUP BY ROLLUP(field_name)`. The columns are `field_name` and `count`.
+pub fn rollup_count(field_name: &str, data: &[Pod]) -> (Vec<String>, Vec<Pod>) {
+    let mut counts: BTreeMap<String, (Pod, i64)> = BTreeMap::new();
+    for pod in data {
+        let value = pod.nested_get(field_name).cloned().unwrap_or(Pod::Null);
+        counts.entry(value.to_string()).or_insert((value, 0)).1 += 1;
+    }
+
+    let row = |value: Pod, count: i64| {
+        // Nested like in the source rows, so `file.folder` is found under `file`
+        let mut keys = field_name.rsplit('.');
+        let mut value = value;
+        let top_level = keys.next_back().unwrap_or(field_name);
+        for key in keys {
+            let mut hash = Pod::new_hash();
+            let _ = hash.insert(key.to_string(), value);
+            value = hash;
+        }
+        let mut row = Pod::new_hash();
+        let _ = row.insert(top_level.to_string(), value);
+        let _ = row.insert("count".to_string(), Pod::Integer(count));
+        row
+    };
+    let mut rows: Vec<Pod> = counts
+        .into_values()
+        .map(|(value, count)| row(value, count))
+        .collect();
+    rows.push(row(Pod::Null, data.len() as i64));
 
-    Ok((query.select_fields, data))
+    (vec![field_name.to_string(), "count".to_string()], rows)
 }
 
-fn execute_select(fields: &[String], data: &mut Vec<Pod>) {
-    // TODO: implement * to select all values
-    // TODO: implement function calls in select
-    // TODO: implement AS in select
+/// Turns the last two columns of `data` into a matrix, like a spreadsheet's pivot table: the
+/// values of the second to last column become columns, holding the last column, and there is one
+/// row per combination of the other columns (a single row when there are none). E.g.
+/// `file.folder, status, COUNT(*)` becomes `file.folder, done, open, ...` with the counts per
+/// folder and status. Columns are in value order, combinations that have no row are left out of
+/// the rows.
+pub fn pivot(fields: &[String], data: &[Pod]) -> Result<(Vec<String>, Vec<Pod>), String> {
+    let [row_fields @ .., column_field, value_field] = fields else {
+        return Err(format!(
+            "Pivot needs at least 2 columns (columns and values, e.g. `SELECT file.folder, status, COUNT(*) ... GROUP BY file.folder, status`), but found {}",
+            fields.len()
+        ));
+    };
+
+    let mut columns: Vec<FieldValue> = Vec::new();
+    for pod in data {
+        let column = get_field_value(column_field, pod);
+        if !columns.contains(&column) {
+            columns.push(column);
+        }
+    }
+    columns.sort_by(|a, b| match (a, b) {
+        (FieldValue::Null, _) => std::cmp::Ordering::Less,
+        (_, FieldValue::Null) => std::cmp::Ordering::Greater,
+        _ => a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal),
+    });
+
+    let mut rows: IndexMap<String, Pod> = IndexMap::new();
+    for pod in data {
+        let values: Vec<Pod> = row_fields
+            .iter()
+            .map(|field| pod.nested_get(field).cloned().unwrap_or(Pod::Null))
+            .collect();
+        let row = rows.entry(format!("{:?}", values)).or_insert_with(|| {
+            let mut row = Pod::new_hash();
+            for (field, value) in row_fields.iter().zip(values.clone()) {
+                let _ = row.insert(field.clone(), value);
+            }
+            row
+        });
+
+        let column = pod
+            .nested_get(column_field)
+            .cloned()
+            .unwrap_or(Pod::Null)
+            .to_string();
+        if row.nested_get(&column).is_some() {
+            return Err(format!(
+                "Pivot found more than one {} for {} {}, group by the other columns to have one",
+                value_field, column_field, column
+            ));
+        }
+        let value = pod.nested_get(value_field).cloned().unwrap_or(Pod::Null);
+        let _ = row.insert(column, value);
+    }
+
+    let fields = row_fields
+        .iter()
+        .cloned()
+        .chain(
+            columns
+                .iter()
+                .map(|column| field_value_to_pod(column.clone()).to_string()),
+        )
+        .collect();
+    Ok((fields, rows.into_values().collect()))
+}
+
+/// Replaces `data` with one row per group when `query` is grouped. Returns the fields to sort
+/// the result by, which for groups are the ORDER BY fields followed by the GROUP BY fields.
+pub(crate) fn execute_group_by(
+    query: &Query,
+    data: &mut Vec<Pod>,
+    context: &ExecutionContext,
+) -> Vec<OrderByFieldOption> {
+    if !query.is_grouped() {
+        return query.order_by_fields.clone();
+    }
+
+    let _span = tracing::debug_span!("group_by", rows = data.len()).entered();
+    *data = group_rows(&query.group_by_fields, &query.aggregates(), data, context);
+    tracing::debug!(rows = data.len(), "grouped rows");
+    add_grouped_select_aliases(query, data);
+    // GROUP BY functions are columns of the groups, e.g. `DATE_TRUNC('week', created)`
+    let order_by_fields = query.order_by_fields.iter().map(|order_by| {
+        let group_function = query.group_by_fields.iter().find(|group_by| {
+            group_by.function.is_some()
+                && order_by.function.is_some()
+                && group_by
+                    .field_name
+                    .eq_ignore_ascii_case(&order_by.field_name)
+        });
+        match group_function {
+            Some(group_by) => OrderByFieldOption::new(
+                group_by.field_name.clone(),
+                order_by.order_direction.clone(),
+            ),
+            None => order_by.clone(),
+        }
+    });
+    let group_by_fields = query
+        .group_by_fields
+        .iter()
+        .map(|group_by| OrderByFieldOption::new(group_by.field_name.clone(), OrderDirection::ASC));
+    order_by_fields.chain(group_by_fields).collect()
+}
+
+/// Adds a column for every SELECT alias of an ungrouped `query` to the rows of `data`, e.g. `day`
+/// of `DATE(created) AS day`, with the value of its field or expression, so WHERE and ORDER BY
+/// can use it. Expressions are computed like in [`execute_select_functions`].
+pub(crate) fn add_select_aliases(
+    query: &Query,
+    data: &mut [Pod],
+    context: &ExecutionContext,
+) -> Result<(), String> {
+    let mut columns = Vec::new();
+    for (alias, field) in &query.select_aliases {
+        match Query::select_expression(field) {
+            Some(expression) => columns.push((alias, expression)),
+            None => {
+                for pod in data.iter_mut() {
+                    let value = pod.nested_get(field).cloned().unwrap_or(Pod::Null);
+                    let _ = pod.insert(alias.to_string(), value);
+                }
+            }
+        }
+    }
+    add_select_columns(query, &columns, data, context)
+}
+
+// Groups have their GROUP BY fields and aggregates as columns already, the aliases are copies
+fn add_grouped_select_aliases(query: &Query, data: &mut [Pod]) {
+    for pod in data {
+        for (alias, field) in &query.select_aliases {
+            let value = pod.nested_get(field).cloned().unwrap_or(Pod::Null);
+            let _ = pod.insert(alias.clone(), value);
+        }
+    }
+}
+
+/// Adds a column for every function and expression in SELECT of an ungrouped `query` to the
+/// rows of `data`, named by its query text, e.g. `DATE(created, '%Y-%m-%d')` or `n * 2`. Rows it
+/// fails for (e.g. without a date) are left without the column, like in WHERE it is an error
+/// when it fails for every row.
+pub(crate) fn execute_select_functions(
+    query: &Query,
+    data: &mut [Pod],
+    context: &ExecutionContext,
+) -> Result<(), String> {
+    // Functions of grouped rows are GROUP BY columns already
+    if query.is_grouped() {
+        return Ok(());
+    }
+    let columns: Vec<(&String, Vec<ExpressionElement>)> = query
+        .select_fields
+        .iter()
+        .filter_map(|field| Query::select_expression(field).map(|expression| (field, expression)))
+        .collect();
+    add_select_columns(query, &columns, data, context)
+}
+
+// Computes the `columns` for every row of `data`, errors point at SELECT
+fn add_select_columns(
+    query: &Query,
+    columns: &[(&String, Vec<ExpressionElement>)],
+    data: &mut [Pod],
+    context: &ExecutionContext,
+) -> Result<(), String> {
+    let located = |error: String| query.spans.locate(&error, query.spans.select);
+    for (_, expression) in columns {
+        for element in expression {
+            if let ExpressionElement::Function(function) = element {
+                check_function_name(function, context).map_err(located)?;
+            }
+        }
+    }
+    if columns.is_empty() || data.is_empty() {
+        return Ok(());
+    }
+
+    let spans = QuerySpans::default();
+    let repeated: Vec<Vec<&str>> = columns
+        .iter()
+        .map(|(_, expression)| repeated_fields(expression))
+        .collect();
+    let total = data.len();
+    let mut failed = vec![0; columns.len()];
+    let mut first_errors = vec![None; columns.len()];
+    for pod in data.iter_mut() {
+        for (index, ((name, expression), repeated)) in columns.iter().zip(&repeated).enumerate() {
+            // Rows it fails for are left without the column, like rows missing a plain field
+            match evaluate_expression_memoized(expression, repeated, &spans, pod, context) {
+                Ok(value) => {
+                    let _ = pod.insert(name.to_string(), field_value_to_pod(value));
+                }
+                Err(error) => {
+                    failed[index] += 1;
+                    first_errors[index].get_or_insert(error);
+                }
+            }
+        }
+    }
+
+    match first_errors
+        .into_iter()
+        .zip(failed)
+        .find_map(|(error, failed)| error.filter(|_| failed == total))
+    {
+        Some(error) => Err(located(error)),
+        None => Ok(()),
+    }
+}
+
+pub(crate) fn execute_select(fields: &[String], data: &mut [Pod]) {
     let check_fields: Vec<String> = fields
         .iter()
-        .map(|s| {
-            s.split_once('.')
-                .map_or(s.to_string(), |(before, _)| before.to_string())
-        })
+        .map(|s| s.split(['.', '[']).next().unwrap_or_default().to_string())
         .collect();
 
     for pod in data {
         if let Pod::Hash(ref mut hashmap) = *pod {
-            hashmap.retain(|k, _| check_fields.contains(k));
+            // Grouped rows have columns like `file.folder` or `COUNT(*)` under their whole name
+            hashmap.retain(|k, _| check_fields.contains(k) || fields.contains(k));
         }
     }
 }
 
-fn execute_order_by(fields: &Vec<OrderByFieldOption>, data: &mut [Pod]) -> Result<(), String> {
-    data.sort_by(|a, b| {
-        // TODO: add support for functions in order by
-        for orderby_field in fields {
-            let fv_a = get_field_value(&orderby_field.field_name, a);
-            let fv_b = get_field_value(&orderby_field.field_name, b);
+fn execute_order_by(
+    fields: &[OrderByFieldOption],
+    data: &mut [Pod],
+    context: &ExecutionContext,
+) -> Result<(), String> {
+    data.sort_by(|a, b| compare_rows(fields, a, b, context));
 
-            if matches!(fv_a, FieldValue::Null) && matches!(fv_b, FieldValue::Null) {
-                continue;
-            }
+    Ok(())
+}
 
-            let comparison: std::cmp::Ordering = if matches!(fv_a, FieldValue::Null) {
-                std::cmp::Ordering::Less
-            } else if matches!(fv_b, FieldValue::Null) {
-                std::cmp::Ordering::Greater
-            } else {
-                fv_a.partial_cmp(&fv_b).unwrap_or(std::cmp::Ordering::Equal)
-            };
+/// Order of rows `a` and `b` by the ORDER BY `fields`, NULLs first. Strings are compared by the
+/// collation of `context` when it has one.
+pub(crate) fn compare_rows(
+    fields: &[OrderByFieldOption],
+    a: &Pod,
+    b: &Pod,
+    context: &ExecutionContext,
+) -> std::cmp::Ordering {
+    let collation = context.collation.as_ref();
+    for orderby_field in fields {
+        let fv_a = get_order_by_value(orderby_field, a, context);
+        let fv_b = get_order_by_value(orderby_field, b, context);
+
+        if matches!(fv_a, FieldValue::Null) && matches!(fv_b, FieldValue::Null) {
+            continue;
+        }
 
-            if comparison.is_ne() {
-                if orderby_field.order_direction == OrderDirection::ASC {
-                    return comparison;
-                } else if comparison.is_lt() {
-                    return std::cmp::Ordering::Greater;
-                } else {
-                    return std::cmp::Ordering::Less;
+        let comparison: std::cmp::Ordering = if matches!(fv_a, FieldValue::Null) {
+            std::cmp::Ordering::Less
+        } else if matches!(fv_b, FieldValue::Null) {
+            std::cmp::Ordering::Greater
+        } else {
+            match (&fv_a, &fv_b, collation) {
+                (FieldValue::String(a), FieldValue::String(b), Some(collation)) => {
+                    collation.compare(a, b)
                 }
+                _ => fv_a.partial_cmp(&fv_b).unwrap_or(std::cmp::Ordering::Equal),
+            }
+        };
+
+        if comparison.is_ne() {
+            if orderby_field.order_direction == OrderDirection::ASC {
+                return comparison;
+            } else if comparison.is_lt() {
+                return std::cmp::Ordering::Greater;
+            } else {
+                return std::cmp::Ordering::Less;
             }
         }
+    }
 
-        std::cmp::Ordering::Equal
-    });
+    std::cmp::Ordering::Equal
+}
 
-    Ok(())
+// Rows a function fails on (e.g. missing fields) sort like NULL
+fn get_order_by_value(
+    orderby_field: &OrderByFieldOption,
+    data: &Pod,
+    context: &ExecutionContext,
+) -> FieldValue {
+    match &orderby_field.function {
+        Some(function) => execute_function(function, data, context).unwrap_or(FieldValue::Null),
+        None => get_field_value(&orderby_field.field_name, data),
+    }
 }
 
-fn execute_where(expression: &Vec<ExpressionElement>, data: &mut Vec<Pod>) -> Result<(), String> {
+fn execute_where(
+    expression: &[ExpressionElement],
+    spans: &QuerySpans,
+    data: &mut Vec<Pod>,
+    context: &ExecutionContext,
+) -> Result<(), String> {
+    // Failing on some rows is expected (e.g. missing fields), but failing on all of them means
+    // the query itself is wrong
+    match filter_rows(expression, spans, data, context)? {
+        Some(error) => Err(error),
+        None => Ok(()),
+    }
+}
+
+/// Keeps the rows of `data` that match `expression`. Rows that fail to evaluate are left out, the
+/// first of those errors is returned when all of the rows failed. A condition that is not a bool
+/// or NULL (e.g. `WHERE priority`) is an error right away.
+pub(crate) fn filter_rows(
+    expression: &[ExpressionElement],
+    spans: &QuerySpans,
+    data: &mut Vec<Pod>,
+    context: &ExecutionContext,
+) -> Result<Option<String>, String> {
     if expression.is_empty() || data.is_empty() {
-        return Ok(());
+        return Ok(None);
     }
 
     // Dry run to return an error if expression is invalid
@@ -158,53 +678,250 @@ fn execute_where(expression: &Vec<ExpressionElement>, data: &mut Vec<Pod>) -> Re
     // TODO: better error reporting, we want to filter as false pods that do not match the
     // expression, but we don't want to stop the execution if one pod fails to match the expression
 
-    data.retain(|pod| match evaluate_expression(expression, pod) {
-        Ok(FieldValue::Bool(bool)) => bool,
-        _ => false,
+    let repeated = repeated_fields(expression);
+    let total = data.len();
+    let mut filtered = 0;
+    let mut failed = 0;
+    let mut first_error = None;
+    // Cancelled, or stopped at a condition that is not a bool
+    let mut stopped = Ok(());
+    data.retain(|pod| {
+        if stopped.is_err() {
+            return false;
+        }
+        if let Err(error) = context.cancellation.check() {
+            stopped = Err(error);
+            return false;
+        }
+        filtered += 1;
+        if filtered % ROWS_REPORT_INTERVAL == 0 {
+            context
+                .progress
+                .report(ProgressEvent::RowsFiltered { filtered, total });
+        }
+        match evaluate_expression_memoized(expression, &repeated, spans, pod, context) {
+            Ok(FieldValue::Bool(bool)) => bool,
+            Ok(FieldValue::Null) => false,
+            Ok(value) => {
+                let error = format!("WHERE condition must be a bool or NULL, but is '{}'", value);
+                stopped = Err(spans.locate(&error, spans.where_clause));
+                false
+            }
+            Err(error) => {
+                failed += 1;
+                first_error.get_or_insert(error);
+                false
+            }
+        }
     });
+    if stopped.is_ok() && filtered % ROWS_REPORT_INTERVAL != 0 {
+        context
+            .progress
+            .report(ProgressEvent::RowsFiltered { filtered, total });
+    }
 
-    Ok(())
+    stopped?;
+
+    Ok(first_error.filter(|_| failed == total))
+}
+
+/// Renames the `alias` field of every row of `data` to `field`, in place so `*` keeps the
+/// frontmatter order. Rows that have both keep the value of `field`.
+pub(crate) fn apply_field_aliases(aliases: &[(String, String)], data: &mut [Pod]) {
+    for pod in data.iter_mut() {
+        let Pod::Hash(hash) = pod else {
+            continue;
+        };
+        for (alias, field) in aliases {
+            let Some((index, _, value)) = hash.shift_remove_full(alias) else {
+                continue;
+            };
+            if !hash.contains_key(field) {
+                hash.shift_insert(index, field.clone(), value);
+            }
+        }
+    }
+}
+
+/// Adds fields computed from every row of `data` to it, in order, so a field can use the ones
+/// before it. Rows a field fails to evaluate on (e.g. missing fields) get NULL.
+pub(crate) fn add_computed_fields(
+    fields: &[(String, Vec<ExpressionElement>)],
+    data: &mut [Pod],
+    context: &ExecutionContext,
+) {
+    let spans = QuerySpans::default();
+    let repeated: Vec<Vec<&str>> = fields
+        .iter()
+        .map(|(_, expression)| repeated_fields(expression))
+        .collect();
+    for pod in data.iter_mut() {
+        for ((name, expression), repeated) in fields.iter().zip(&repeated) {
+            let value = evaluate_expression_memoized(expression, repeated, &spans, pod, context)
+                .map_or(Pod::Null, field_value_to_pod);
+            let _ = pod.insert(name.clone(), value);
+        }
+    }
+}
+
+/// Names of the fields `expression` uses more than once (`created > X AND created < Y`), the
+/// only ones worth keeping after they are looked up in a row.
+fn repeated_fields(expression: &[ExpressionElement]) -> Vec<&str> {
+    let mut seen: Vec<&str> = Vec::new();
+    let mut repeated: Vec<&str> = Vec::new();
+    for element in expression {
+        if let ExpressionElement::FieldName(field_name) = element {
+            if !seen.contains(&field_name.as_str()) {
+                seen.push(field_name);
+            } else if !repeated.contains(&field_name.as_str()) {
+                repeated.push(field_name);
+            }
+        }
+    }
+    repeated
 }
 
 fn evaluate_expression(
-    expression: &Vec<ExpressionElement>,
+    expression: &[ExpressionElement],
+    spans: &QuerySpans,
+    data: &Pod,
+    context: &ExecutionContext,
+) -> Result<FieldValue, String> {
+    evaluate_expression_memoized(expression, &[], spans, data, context)
+}
+
+/// Evaluates `expression` on the row `data`, looking every field of `repeated` (see
+/// [`repeated_fields`]) up only once.
+fn evaluate_expression_memoized(
+    expression: &[ExpressionElement],
+    repeated: &[&str],
+    spans: &QuerySpans,
     data: &Pod,
+    context: &ExecutionContext,
 ) -> Result<FieldValue, String> {
     // Define operator precedence
+    // NOT (3) is between AND and the comparisons, so `NOT a == b AND c` is `(NOT (a == b)) AND c`
     let operator_precedence = |op: &Operator| match op {
         Operator::Or => 0,
         Operator::And => 1,
         Operator::In
+        | Operator::NotIn
         | Operator::Like
         | Operator::NotLike
+        | Operator::Regexp
+        | Operator::NotRegexp
         | Operator::Eq
         | Operator::Neq
         | Operator::Lt
         | Operator::Lte
         | Operator::Gt
-        | Operator::Gte => 2,
-        Operator::Plus | Operator::Minus => 3,
-        Operator::Multiply | Operator::Divide | Operator::FloorDivide => 4,
-        Operator::Power => 5,
+        | Operator::Gte => 3,
+        Operator::Plus | Operator::Minus => 4,
+        Operator::Multiply | Operator::Divide | Operator::FloorDivide => 5,
+        Operator::Power => 7,
+    };
+    // Unary minus binds tighter than everything but `**`, so `-a ** 2` is `-(a ** 2)`
+    let stack_precedence = |element: &ExpressionElement| match element {
+        ExpressionElement::Operator(op) => Some(operator_precedence(op)),
+        ExpressionElement::Negate => Some(6),
+        ExpressionElement::Not => Some(2),
+        _ => None,
     };
 
     let mut stack: Vec<ExpressionElement> = Vec::new();
     let mut queue: Vec<FieldValue> = Vec::new();
-
-    for element in expression {
+    // Which elements (first, last) every value on the queue was computed from, and where the
+    // opened brackets on the stack are, to point errors at the failing part of the expression
+    let mut ranges: Vec<(usize, usize)> = Vec::new();
+    let mut opened_brackets: Vec<usize> = Vec::new();
+    // Repeated fields already looked up in this row. Expressions use few fields, a Vec is faster
+    // than hashing.
+    let mut field_values: Vec<(&str, FieldValue)> = Vec::new();
+
+    for (index, element) in expression.iter().enumerate() {
         match element {
-            ExpressionElement::OpenedBracket => stack.push(ExpressionElement::OpenedBracket),
+            ExpressionElement::OpenedBracket => {
+                stack.push(ExpressionElement::OpenedBracket);
+                opened_brackets.push(index);
+            }
             ExpressionElement::FieldName(field_name) => {
-                queue.push(get_field_value(field_name, data))
+                let value = match field_values.iter().find(|(name, _)| name == field_name) {
+                    Some((_, value)) => value.clone(),
+                    None if repeated.contains(&field_name.as_str()) => {
+                        let value = get_field_value(field_name, data);
+                        field_values.push((field_name, value.clone()));
+                        value
+                    }
+                    None => get_field_value(field_name, data),
+                };
+                queue.push(value);
+                ranges.push((index, index));
+            }
+            ExpressionElement::FieldValue(field_value) => {
+                queue.push(field_value.clone());
+                ranges.push((index, index));
+            }
+            ExpressionElement::Function(func) => {
+                queue.push(
+                    execute_function(func, data, context).map_err(|error| {
+                        spans.locate(&error, spans.where_elements(index, index))
+                    })?,
+                );
+                ranges.push((index, index));
+            }
+            ExpressionElement::Quantified(quantified) => {
+                queue.push(
+                    evaluate_quantified(quantified, data, context).map_err(|error| {
+                        spans.locate(&error, spans.where_elements(index, index))
+                    })?,
+                );
+                ranges.push((index, index));
+            }
+            ExpressionElement::Parameter(name) => {
+                return Err(spans.locate(
+                    &format!("Parameter :{} is not bound!", name),
+                    spans.where_elements(index, index),
+                ))
+            }
+            ExpressionElement::Macro(name) => {
+                return Err(spans.locate(
+                    &format!("Unknown macro: ${}", name),
+                    spans.where_elements(index, index),
+                ))
+            }
+            ExpressionElement::Subquery(_) | ExpressionElement::Exists(_) => {
+                return Err(spans.locate(
+                    "Subqueries can only be used in WHERE, not in computed fields",
+                    spans.where_elements(index, index),
+                ))
+            }
+            // prefix operator, nothing to evaluate before it
+            ExpressionElement::Negate | ExpressionElement::Not => stack.push(element.clone()),
+            // postfix, applies to the function result right before it
+            ExpressionElement::Member(name) => {
+                let value = queue
+                    .pop()
+                    .ok_or("Expected operand on the queue, but found nothing!")?;
+                let first = ranges.pop().map_or(index, |(first, _)| first);
+                queue.push(
+                    get_member(&value, name).map_err(|error| {
+                        spans.locate(&error, spans.where_elements(first, index))
+                    })?,
+                );
+                ranges.push((first, index));
             }
-            ExpressionElement::FieldValue(field_value) => queue.push(field_value.clone()),
-            ExpressionElement::Function(func) => queue.push(execute_function(func, data)?),
             ExpressionElement::Operator(op) => {
                 // op goes on stack, but if stack has equal or higher priority operator on top, that one
                 // goes from stack to the "queue"
-                while let Some(ExpressionElement::Operator(last_op)) = stack.last() {
-                    if operator_precedence(last_op) >= operator_precedence(op) {
-                        evaluate_stack_operator(&mut stack, &mut queue)?;
+                while let Some(last_precedence) = stack.last().and_then(stack_precedence) {
+                    if last_precedence >= operator_precedence(op) {
+                        evaluate_located_stack_operator(
+                            &mut stack,
+                            &mut queue,
+                            &mut ranges,
+                            spans,
+                            context,
+                        )?;
                     } else {
                         break;
                     }
@@ -213,14 +930,24 @@ fn evaluate_expression(
             }
             ExpressionElement::ClosedBracket => {
                 while !matches!(stack.last(), Some(ExpressionElement::OpenedBracket)) {
-                    evaluate_stack_operator(&mut stack, &mut queue)?;
+                    evaluate_located_stack_operator(
+                        &mut stack,
+                        &mut queue,
+                        &mut ranges,
+                        spans,
+                        context,
+                    )?;
                 }
                 stack.pop();
+                // the value inside brackets was computed from the brackets too
+                if let (Some(opened), Some(range)) = (opened_brackets.pop(), ranges.last_mut()) {
+                    *range = (opened, index);
+                }
             }
         }
     }
     while stack.last().is_some() {
-        evaluate_stack_operator(&mut stack, &mut queue)?;
+        evaluate_located_stack_operator(&mut stack, &mut queue, &mut ranges, spans, context)?;
     }
 
     if queue.len() != 1 {
@@ -233,9 +960,70 @@ fn evaluate_expression(
     Ok(queue.pop().unwrap())
 }
 
+// Whether the comparison holds for any (or all) of the elements of the list
+fn evaluate_quantified(
+    quantified: &Quantified,
+    data: &Pod,
+    context: &ExecutionContext,
+) -> Result<FieldValue, String> {
+    let no_spans = QuerySpans::default();
+    let elements = match evaluate_expression(&quantified.list, &no_spans, data, context)? {
+        FieldValue::Null => return Ok(FieldValue::Null),
+        FieldValue::List(list) => list,
+        value => vec![value],
+    };
+    let value = evaluate_expression(&quantified.value, &no_spans, data, context)?;
+    // ANY is an OR of the comparisons and ALL an AND, so NULL comparisons count like in them
+    let (operator, empty) = match quantified.quantifier {
+        Quantifier::Any => (Operator::Or, false),
+        Quantifier::All => (Operator::And, true),
+    };
+    elements
+        .iter()
+        .try_fold(FieldValue::Bool(empty), |result, element| {
+            let matches = execute_operation(&quantified.operator, element, &value, context)?;
+            execute_operation(&operator, &result, &matches, context)
+        })
+}
+
+// evaluate_stack_operator that keeps `ranges` of the values on the queue in sync and points its
+// error at the operation that failed
+fn evaluate_located_stack_operator(
+    stack: &mut Vec<ExpressionElement>,
+    queue: &mut Vec<FieldValue>,
+    ranges: &mut Vec<(usize, usize)>,
+    spans: &QuerySpans,
+    context: &ExecutionContext,
+) -> Result<(), String> {
+    // negation keeps the range of its operand
+    if let Some(ExpressionElement::Negate | ExpressionElement::Not) = stack.last() {
+        let span = ranges
+            .last()
+            .and_then(|(first, last)| spans.where_elements(*first, *last));
+        return evaluate_stack_operator(stack, queue, context)
+            .map_err(|error| spans.locate(&error, span));
+    }
+
+    let range = match ranges.len() {
+        length if length >= 2 => Some((ranges[length - 2].0, ranges[length - 1].1)),
+        _ => None,
+    };
+    evaluate_stack_operator(stack, queue, context).map_err(|error| {
+        let span = range.and_then(|(first, last)| spans.where_elements(first, last));
+        spans.locate(&error, span)
+    })?;
+    if let Some(range) = range {
+        ranges.truncate(ranges.len() - 2);
+        ranges.push(range);
+    }
+
+    Ok(())
+}
+
 fn evaluate_stack_operator(
     stack: &mut Vec<ExpressionElement>,
     queue: &mut Vec<FieldValue>,
+    context: &ExecutionContext,
 ) -> Result<(), String> {
     let should_be_operator = stack.pop();
     match should_be_operator {
@@ -247,80 +1035,299 @@ fn evaluate_stack_operator(
                 .pop()
                 .ok_or("Expected operand on the queue, but found nothing!")?;
 
-            queue.push(execute_operation(&operator, &left, &right)?);
+            queue.push(execute_operation(&operator, &left, &right, context)?);
+        }
+        Some(ExpressionElement::Negate) => {
+            let value = queue
+                .pop()
+                .ok_or("Expected operand on the queue, but found nothing!")?;
+
+            queue.push(value.negate()?);
+        }
+        Some(ExpressionElement::Not) => match queue.pop() {
+            Some(FieldValue::Bool(value)) => queue.push(FieldValue::Bool(!value)),
+            // Like in SQL, NOT of a missing value is still missing
+            Some(FieldValue::Null) => queue.push(FieldValue::Null),
+            Some(_) => return Err("NOT operator expects operand to be bool!".to_string()),
+            None => return Err("Expected operand on the queue, but found nothing!".to_string()),
+        },
+        _ => {
+            return Err(format!(
+                "Expected operator on top of the stack, but found {:?}!",
+                should_be_operator
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+fn execute_operation(
+    op: &Operator,
+    left: &FieldValue,
+    right: &FieldValue,
+    context: &ExecutionContext,
+) -> Result<FieldValue, String> {
+    let null = |value: &FieldValue| matches!(value, FieldValue::Null);
+    let is_bool_or_null = |value: &FieldValue| null(value) || value.as_bool().is_some();
+    match op {
+        // get bools (or NULL, an unknown bool), return bool or NULL like in SQL: false AND NULL
+        // is false, true OR NULL is true and the rest with a NULL is NULL
+        Operator::And | Operator::Or if !is_bool_or_null(left) || !is_bool_or_null(right) => {
+            Err(format!("{} operator expects operands to be bools!", op))
+        }
+        Operator::And => Ok(match (left.as_bool(), right.as_bool()) {
+            (Some(false), _) | (_, Some(false)) => FieldValue::Bool(false),
+            (Some(true), Some(true)) => FieldValue::Bool(true),
+            _ => FieldValue::Null,
+        }),
+        Operator::Or => Ok(match (left.as_bool(), right.as_bool()) {
+            (Some(true), _) | (_, Some(true)) => FieldValue::Bool(true),
+            (Some(false), Some(false)) => FieldValue::Bool(false),
+            _ => FieldValue::Null,
+        }),
+
+        // == and != compare NULL like any other value, so `due == NULL` finds the rows without
+        // a due. IN and NOT IN with a missing list are NULL.
+        Operator::Eq => Ok(FieldValue::Bool(left == right)),
+        Operator::Neq => Ok(FieldValue::Bool(left != right)),
+        Operator::In | Operator::NotIn if null(right) => Ok(FieldValue::Null),
+        Operator::In => Ok(FieldValue::Bool(right.contains(left))),
+        Operator::NotIn => Ok(FieldValue::Bool(!right.contains(left))),
+        // the other comparisons and arithmetic with a NULL are NULL
+        _ if null(left) || null(right) => Ok(FieldValue::Null),
+
+        // get values, return bools
+        Operator::Like => Ok(FieldValue::Bool(context.regex_cache.is_match(left, right))),
+        Operator::NotLike => Ok(FieldValue::Bool(!context.regex_cache.is_match(left, right))),
+        Operator::Regexp => Ok(FieldValue::Bool(
+            context.regex_cache.regexp_match(left, right)?,
+        )),
+        Operator::NotRegexp => Ok(FieldValue::Bool(
+            !context.regex_cache.regexp_match(left, right)?,
+        )),
+        Operator::Lt | Operator::Lte | Operator::Gt | Operator::Gte => {
+            Ok(FieldValue::Bool(compare_values(op, left, right)))
+        }
+
+        // get values, return values
+        Operator::Plus | Operator::Minus if is_date_arithmetic(op, left, right) => {
+            execute_date_arithmetic(op, left, right)
+        }
+        Operator::Plus => left.add(right),
+        Operator::Minus => left.subtract(right),
+        Operator::Multiply => left.multiply(right),
+        Operator::Divide => left.divide(right),
+        Operator::Power => left.power(right),
+        Operator::FloorDivide => left.floor_divide(right),
+    }
+}
+
+// Date minus date, or date plus/minus a duration or number of days, where dates are strings
+// that parse as one
+fn is_date_arithmetic(op: &Operator, left: &FieldValue, right: &FieldValue) -> bool {
+    let is_date = |value: &FieldValue| match value {
+        FieldValue::String(date_str) => parse_naive_datetime(date_str, &None).is_ok(),
+        _ => false,
+    };
+    match (op, left, right) {
+        (Operator::Minus, FieldValue::String(_), FieldValue::String(_)) => {
+            is_date(left) && is_date(right)
+        }
+        (_, FieldValue::String(_), FieldValue::Duration(_)) => is_date(left),
+        (_, FieldValue::String(_), days) if days.is_number() => is_date(left),
+        (Operator::Plus, FieldValue::Duration(_), FieldValue::String(_)) => is_date(right),
+        (Operator::Plus, days, FieldValue::String(_)) if days.is_number() => is_date(right),
+        _ => false,
+    }
+}
+
+/// `due - TODAY()` is the duration between the dates, `due + DURATION('3d')` (or `-`) the date
+/// shifted by the duration, and `TODAY() - 7` the date 7 days earlier. Call only when
+/// [`is_date_arithmetic`].
+fn execute_date_arithmetic(
+    op: &Operator,
+    left: &FieldValue,
+    right: &FieldValue,
+) -> Result<FieldValue, String> {
+    let date = |value: &FieldValue| parse_naive_datetime(&value.to_string(), &None);
+    let seconds = |value: &FieldValue| match value {
+        FieldValue::Integer(days) => days.saturating_mul(86400),
+        FieldValue::Float(days) => (days * 86400.0).round() as i64,
+        FieldValue::Duration(seconds) => *seconds,
+        _ => 0,
+    };
+    let (date_value, seconds) = match (op, left, right) {
+        (_, FieldValue::String(_), FieldValue::String(_)) => {
+            return Ok(FieldValue::Duration(
+                (date(left)? - date(right)?).num_seconds(),
+            ))
+        }
+        (Operator::Minus, _, _) => (left, seconds(right).saturating_neg()),
+        (_, FieldValue::String(_), _) => (left, seconds(right)),
+        _ => (right, seconds(left)),
+    };
+    date(date_value)?
+        .checked_add_signed(TimeDelta::seconds(seconds))
+        .map(|date| FieldValue::String(date.format(DATE_FORMAT).to_string()))
+        .ok_or(format!(
+            "Date {} moved by {} is out of range",
+            date_value.to_query_string(),
+            FieldValue::Duration(seconds)
+        ))
+}
+
+/// `<`, `<=`, `>` and `>=` of two values. Strings that are both dates compare as dates, so
+/// `2024-03-01` is not before `2024-03-01T00:00:00` (what `TODAY()` returns).
+fn compare_values(op: &Operator, left: &FieldValue, right: &FieldValue) -> bool {
+    let dates = match (left, right) {
+        (FieldValue::String(a), FieldValue::String(b))
+            if a != b
+                && a.starts_with(|c: char| c.is_ascii_digit())
+                && b.starts_with(|c: char| c.is_ascii_digit()) =>
+        {
+            parse_naive_datetime(a, &None)
+                .ok()
+                .zip(parse_naive_datetime(b, &None).ok())
+        }
+        _ => None,
+    };
+    let ordering = match dates {
+        Some((a, b)) => a.partial_cmp(&b),
+        None => left.partial_cmp(right),
+    };
+    ordering.is_some_and(|ordering| match op {
+        Operator::Lt => ordering.is_lt(),
+        Operator::Lte => ordering.is_le(),
+        Operator::Gt => ordering.is_gt(),
+        _ => ordering.is_ge(),
+    })
+}
+
+/// Compiled LIKE and REGEXP patterns, so that a pattern is compiled once and not for every row.
+#[derive(Debug)]
+pub struct RegexCache {
+    cache: Mutex<LruCache<String, Regex>>,
+}
+
+impl Default for RegexCache {
+    fn default() -> Self {
+        RegexCache::new(DEFAULT_REGEX_CACHE_SIZE)
+    }
+}
+
+pub const DEFAULT_REGEX_CACHE_SIZE: usize = 100;
+
+impl RegexCache {
+    pub fn new(size: usize) -> Self {
+        RegexCache {
+            cache: Mutex::new(LruCache::new(
+                NonZero::new(size).unwrap_or(NonZero::<usize>::MIN),
+            )),
+        }
+    }
+
+    /// Whether `a` matches the regex `b`. Anything that is not two strings, or an invalid regex,
+    /// does not match.
+    fn is_match(&self, a: &FieldValue, b: &FieldValue) -> bool {
+        match (a, b) {
+            (FieldValue::String(a_str), FieldValue::String(b_str)) => {
+                self.matches(a_str, b_str).unwrap_or(false)
+            }
+            _ => false,
+        }
+    }
+
+    /// REGEXP: like [`RegexCache::is_match`], but the pattern can be written as `/pattern/flags`
+    /// (e.g. `/^draft/i`) and an invalid pattern is an error. A value that is not a string does
+    /// not match.
+    fn regexp_match(&self, a: &FieldValue, b: &FieldValue) -> Result<bool, String> {
+        let FieldValue::String(pattern) = b else {
+            return Err(format!("REGEXP expects a string pattern, got {}!", b));
+        };
+        let pattern = regexp_pattern(pattern);
+        match a {
+            FieldValue::String(a_str) => self
+                .matches(a_str, &pattern)
+                .map_err(|error| format!("Invalid REGEXP pattern: {}", error)),
+            _ => Ok(false),
         }
-        _ => {
-            return Err(format!(
-                "Expected operator on top of the stack, but found {:?}!",
-                should_be_operator
-            ));
+    }
+
+    fn matches(&self, text: &str, pattern: &str) -> Result<bool, regex::Error> {
+        let mut cache = self.cache.lock().unwrap();
+        if let Some(re) = cache.get(pattern) {
+            return Ok(re.is_match(text));
         }
+        let re = Regex::new(pattern)?;
+        let res = re.is_match(text);
+        cache.put(pattern.to_string(), re);
+        Ok(res)
     }
+}
 
-    Ok(())
+/// `/pattern/flags` -> `(?flags)pattern`. Patterns without flags after the closing `/` are used as
+/// they are, so `'/notes/'` still matches a literal `/notes/`.
+fn regexp_pattern(pattern: &str) -> String {
+    if let Some((body, flags)) = pattern
+        .strip_prefix('/')
+        .and_then(|rest| rest.rsplit_once('/'))
+    {
+        if !flags.is_empty() && flags.chars().all(|c| "imsxU".contains(c)) {
+            return format!("(?{}){}", flags, body);
+        }
+    }
+    pattern.to_string()
 }
 
-fn execute_operation(
-    op: &Operator,
-    left: &FieldValue,
-    right: &FieldValue,
-) -> Result<FieldValue, String> {
-    match op {
-        // get bools, return bool
-        Operator::And => match (left, right) {
-            (FieldValue::Bool(left), FieldValue::Bool(right)) => {
-                Ok(FieldValue::Bool(*left && *right))
-            }
-            _ => Err("AND operator expects operands to be bools!".to_string()),
-        },
-        Operator::Or => match (left, right) {
-            (FieldValue::Bool(left), FieldValue::Bool(right)) => {
-                Ok(FieldValue::Bool(*left || *right))
-            }
-            _ => Err("OR operator expects operands to be bools!".to_string()),
-        },
+/// A function that can be called in WHERE, registered with
+/// [`Krafna::register_function`](crate::libs::engine::Krafna::register_function). Arguments are
+/// already evaluated against the current row.
+pub type UserFunction = dyn Fn(&[FieldValue]) -> Result<FieldValue, String> + Send + Sync;
+
+/// State that query execution needs besides the query and the data: registered functions, the
+/// regex cache used by LIKE, the cancellation token and the progress hook.
+#[derive(Clone, Default)]
+pub struct ExecutionContext {
+    pub functions: Arc<HashMap<String, Arc<UserFunction>>>,
+    pub regex_cache: Arc<RegexCache>,
+    pub cancellation: CancellationToken,
+    pub progress: Progress,
+    /// How ORDER BY compares strings, byte order without one
+    pub collation: Option<Collation>,
+    pub random_seed: RandomSeed,
+}
 
-        // get values, return bools
-        Operator::Like => Ok(FieldValue::Bool(execute_operation_like(left, right))),
-        Operator::NotLike => Ok(FieldValue::Bool(!execute_operation_like(left, right))),
-        Operator::In => Ok(FieldValue::Bool(right.contains(left))),
-        Operator::Lt => Ok(FieldValue::Bool(left < right)),
-        Operator::Lte => Ok(FieldValue::Bool(left <= right)),
-        Operator::Gt => Ok(FieldValue::Bool(left > right)),
-        Operator::Gte => Ok(FieldValue::Bool(left >= right)),
-        Operator::Eq => Ok(FieldValue::Bool(left == right)),
-        Operator::Neq => Ok(FieldValue::Bool(left != right)),
+/// Seed of RANDOM() without arguments, a new one for every context so that every run of a query
+/// shuffles the rows differently
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RandomSeed(pub u64);
 
-        // get values, return values
-        Operator::Plus => left.add(right),
-        Operator::Minus => left.subtract(right),
-        Operator::Multiply => left.multiply(right),
-        Operator::Divide => left.divide(right),
-        Operator::Power => left.power(right),
-        Operator::FloorDivide => left.floor_divide(right),
+impl Default for RandomSeed {
+    fn default() -> Self {
+        RandomSeed(RandomState::new().hash_one("RANDOM"))
     }
 }
 
-static REGEX_CACHE: Lazy<Mutex<LruCache<String, Regex>>> =
-    once_cell::sync::Lazy::new(|| Mutex::new(LruCache::new(NonZero::new(100).unwrap())));
-fn execute_operation_like(a: &FieldValue, b: &FieldValue) -> bool {
-    match (a, b) {
-        (FieldValue::String(a_str), FieldValue::String(b_str)) => {
-            let mut cache = REGEX_CACHE.lock().unwrap();
-            match cache.get(b_str) {
-                Some(re) => re.is_match(a_str),
-                None => {
-                    if let Ok(re) = Regex::new(b_str) {
-                        let res = re.is_match(a_str);
-                        cache.put(b_str.clone(), re);
-                        res
-                    } else {
-                        false
-                    }
-                }
-            }
+static SHARED_REGEX_CACHE: Lazy<Arc<RegexCache>> = Lazy::new(|| Arc::new(RegexCache::default()));
+
+impl ExecutionContext {
+    /// Context used by the free functions (no registered functions), the regex cache is shared
+    /// between all of them.
+    pub fn shared() -> Self {
+        ExecutionContext {
+            functions: Arc::new(HashMap::new()),
+            regex_cache: Arc::clone(&SHARED_REGEX_CACHE),
+            cancellation: CancellationToken::default(),
+            progress: Progress::default(),
+            collation: None,
+            random_seed: RandomSeed::default(),
         }
-        _ => false,
+    }
+
+    pub fn with_cancellation(mut self, cancellation: CancellationToken) -> Self {
+        self.cancellation = cancellation;
+        self
     }
 }
 
@@ -330,8 +1337,8 @@ fn execute_operation_like(a: &FieldValue, b: &FieldValue) -> bool {
 pub fn get_field_value(field_name: &str, data: &Pod) -> FieldValue {
     match data.nested_get(field_name) {
         Some(Pod::String(str)) => FieldValue::String(str.clone()),
-        Some(Pod::Float(num)) => FieldValue::Number(*num),
-        Some(Pod::Integer(num)) => FieldValue::Number(*num as f64),
+        Some(Pod::Float(num)) => FieldValue::Float(*num),
+        Some(Pod::Integer(num)) => FieldValue::Integer(*num),
         Some(Pod::Boolean(bool)) => FieldValue::Bool(*bool),
         Some(Pod::Array(list)) => pod_array_to_field_value(list),
         Some(Pod::Hash(hash)) => pod_hash_to_field_value(hash),
@@ -339,14 +1346,26 @@ pub fn get_field_value(field_name: &str, data: &Pod) -> FieldValue {
     }
 }
 
+pub(crate) fn field_value_to_pod(value: FieldValue) -> Pod {
+    match value {
+        FieldValue::Null => Pod::Null,
+        FieldValue::String(str) => Pod::String(str),
+        FieldValue::Integer(num) => Pod::Integer(num),
+        FieldValue::Float(num) => Pod::Float(num),
+        FieldValue::Bool(bool) => Pod::Boolean(bool),
+        FieldValue::Duration(_) => Pod::String(value.to_string()),
+        FieldValue::List(list) => Pod::Array(list.into_iter().map(field_value_to_pod).collect()),
+    }
+}
+
 fn pod_array_to_field_value(list: &Vec<Pod>) -> FieldValue {
     let mut fv_list = Vec::new();
 
     for el in list {
         match el {
             Pod::String(str) => fv_list.push(FieldValue::String(str.clone())),
-            Pod::Float(num) => fv_list.push(FieldValue::Number(*num)),
-            Pod::Integer(num) => fv_list.push(FieldValue::Number(*num as f64)),
+            Pod::Float(num) => fv_list.push(FieldValue::Float(*num)),
+            Pod::Integer(num) => fv_list.push(FieldValue::Integer(*num)),
             Pod::Boolean(bool) => fv_list.push(FieldValue::Bool(*bool)),
             Pod::Array(list) => fv_list.push(pod_array_to_field_value(list)),
             Pod::Hash(hash) => fv_list.push(pod_hash_to_field_value(hash)),
@@ -357,7 +1376,7 @@ fn pod_array_to_field_value(list: &Vec<Pod>) -> FieldValue {
     FieldValue::List(fv_list)
 }
 
-fn pod_hash_to_field_value(hash: &HashMap<String, Pod>) -> FieldValue {
+fn pod_hash_to_field_value(hash: &IndexMap<String, Pod>) -> FieldValue {
     //match Pod::Hash(hash.clone()).deserialize::<serde_json::Value>() {
     match Pod::Hash(hash.clone()).to_untagged_json_string() {
         Ok(val) => FieldValue::String(val.to_string()),
@@ -368,101 +1387,595 @@ fn pod_hash_to_field_value(hash: &HashMap<String, Pod>) -> FieldValue {
 /***************************************************************************************************
 *************************************** EXECUTE functions ******************************************
 ***************************************************************************************************/
-fn execute_function(func: &Function, data: &Pod) -> Result<FieldValue, String> {
-    match func.name.to_uppercase().as_str() {
-        "DATEADD" => Ok(execute_function_date_add(func, data)?),
-        "DATE" => Ok(execute_function_date(func, data)?),
-        _ => Err(format!("TODO: Implement function execution: {:?}!", func)),
+/// Functions that can be called in WHERE without registering them
+pub const BUILTIN_FUNCTIONS: [&str; 14] = [
+    "DATE",
+    "DATEADD",
+    "DATE_TRUNC",
+    "SIMILARITY",
+    "DURATION",
+    "TODAY",
+    "NOW",
+    "LAST_DAYS",
+    "IF",
+    "TO_NUMBER",
+    "TO_STRING",
+    "TO_BOOL",
+    "TO_DATE",
+    "RANDOM",
+];
+
+pub(crate) fn execute_function(
+    func: &Function,
+    data: &Pod,
+    context: &ExecutionContext,
+) -> Result<FieldValue, String> {
+    let name = func.name.to_uppercase();
+    match name.as_str() {
+        "DATEADD" => execute_function_date_add(func, data, context),
+        "DATE" => execute_function_date(func, data, context),
+        "SIMILARITY" => execute_function_similarity(func, data, context),
+        "DATE_TRUNC" => execute_function_date_trunc(func, data, context),
+        "DURATION" => execute_function_duration(func, data, context),
+        "TODAY" | "NOW" => execute_function_now(func),
+        "LAST_DAYS" => execute_function_last_days(func, data, context),
+        "IF" => execute_function_if(func, data, context),
+        "TO_NUMBER" | "TO_STRING" | "TO_BOOL" => execute_function_cast(&name, func, data, context),
+        "TO_DATE" => execute_function_to_date(func, data, context),
+        "RANDOM" => execute_function_random(func, data, context),
+        _ => match context.functions.get(&name) {
+            Some(user_function) => {
+                let args = func
+                    .args
+                    .iter()
+                    .map(|arg| function_arg_value(arg, data, context))
+                    .collect::<Result<Vec<FieldValue>, String>>()?;
+                user_function(&args)
+            }
+            None => Err(unknown_function(func, context)),
+        },
+    }
+}
+
+fn check_function_name(func: &Function, context: &ExecutionContext) -> Result<(), String> {
+    let name = func.name.to_uppercase();
+    if BUILTIN_FUNCTIONS.contains(&name.as_str()) || context.functions.contains_key(&name) {
+        return Ok(());
     }
+    Err(unknown_function(func, context))
+}
+
+// Suggests the closest builtin or registered function
+fn unknown_function(func: &Function, context: &ExecutionContext) -> String {
+    with_suggestion(
+        format!("Unknown function: {}", func.name),
+        &func.name.to_uppercase(),
+        BUILTIN_FUNCTIONS
+            .into_iter()
+            .chain(context.functions.keys().map(String::as_str)),
+    )
 }
 
 const DATE_FORMAT: &str = "%Y-%m-%dT%H:%M:%S";
-fn execute_function_date_add(func: &Function, data: &Pod) -> Result<FieldValue, String> {
-    if func.args.len() != 3 && func.args.len() != 4 {
+
+fn function_arg_value(
+    arg: &FunctionArg,
+    data: &Pod,
+    context: &ExecutionContext,
+) -> Result<FieldValue, String> {
+    match arg {
+        FunctionArg::FieldName(field_name) => Ok(get_field_value(field_name, data)),
+        FunctionArg::FieldValue(field_value) => Ok(field_value.clone()),
+        FunctionArg::Parameter(name) => Err(format!("Parameter :{} is not bound!", name)),
+        FunctionArg::Expression(expression) => {
+            evaluate_expression(expression, &QuerySpans::default(), data, context)
+        }
+    }
+}
+
+/// Units DATE_TRUNC can truncate dates to
+pub const DATE_TRUNC_UNITS: [&str; 6] = ["year", "quarter", "month", "week", "day", "hour"];
+
+/// DATE_TRUNC(unit, date, <optional-format>): start of the year, quarter, month, week (starting on
+/// Monday), day or hour that `date` is in, e.g. `DATE_TRUNC('month', '2024-03-15')` is
+/// 2024-03-01. NULL when `date` is NULL.
+fn execute_function_date_trunc(
+    func: &Function,
+    data: &Pod,
+    context: &ExecutionContext,
+) -> Result<FieldValue, String> {
+    if func.args.len() != 2 && func.args.len() != 3 {
         return Err(format!(
-            "Function DATEADD expects 3 or 4 arguments, but found {}!",
+            "Function DATE_TRUNC expects 2 or 3 arguments, but found {}!",
             func.args.len()
         ));
     }
 
-    // FIRST ARGUMENT
-    let interval: String = match &func.args[0] {
-        FunctionArg::FieldName(field_name) => match get_field_value(field_name, data) {
-            FieldValue::String(interval) => interval,
-            _ => {
-                return Err(format!(
-                    "Function DATEADD expects first argument to be an interval, but found: {:?}",
-                    func.args[0]
-                ))
+    let unit = match function_arg_value(&func.args[0], data, context)? {
+        FieldValue::String(unit) if DATE_TRUNC_UNITS.contains(&unit.to_lowercase().as_str()) => {
+            unit.to_lowercase()
+        }
+        unit => {
+            return Err(with_suggestion(
+                format!(
+                    "Function DATE_TRUNC expects a unit ({}), but found: {}",
+                    DATE_TRUNC_UNITS.join(", "),
+                    unit.to_query_string()
+                ),
+                &unit.to_string().to_lowercase(),
+                DATE_TRUNC_UNITS,
+            ))
+        }
+    };
+    let date_str = match function_arg_value(&func.args[1], data, context)? {
+        FieldValue::String(date_str) => date_str,
+        FieldValue::Null => return Ok(FieldValue::Null),
+        date => {
+            return Err(format!(
+                "Function DATE_TRUNC expects second argument to be a date, but found: {}",
+                date.to_query_string()
+            ))
+        }
+    };
+    let format_str = match func
+        .args
+        .get(2)
+        .map(|arg| function_arg_value(arg, data, context))
+    {
+        Some(Ok(FieldValue::String(format_str))) => Some(format_str),
+        None | Some(Ok(FieldValue::Null)) => None,
+        Some(format) => {
+            return Err(format!(
+                "Function DATE_TRUNC expects third argument to be a format, but found: {}",
+                format?.to_query_string()
+            ))
+        }
+    };
+
+    let datetime = parse_naive_datetime(&date_str, &format_str).map_err(|_| {
+        format!(
+            "Function DATE_TRUNC did not succeed to parse {:?} into a date",
+            date_str
+        )
+    })?;
+    let date = datetime.date();
+    let start = match unit.as_str() {
+        "year" => NaiveDate::from_ymd_opt(date.year(), 1, 1),
+        "quarter" => NaiveDate::from_ymd_opt(date.year(), (date.month() - 1) / 3 * 3 + 1, 1),
+        "month" => NaiveDate::from_ymd_opt(date.year(), date.month(), 1),
+        "week" => date.checked_sub_days(Days::new(date.weekday().num_days_from_monday() as u64)),
+        _ => Some(date),
+    };
+    let hour = if unit == "hour" { datetime.hour() } else { 0 };
+    let start = start
+        .and_then(|start| start.and_hms_opt(hour, 0, 0))
+        .ok_or(format!(
+            "Function DATE_TRUNC can not truncate {:?}",
+            date_str
+        ))?;
+
+    Ok(FieldValue::String(start.format(DATE_FORMAT).to_string()))
+}
+
+/// DURATION(text): length of time written as numbers with units, e.g. `DURATION('3d')`,
+/// `DURATION('1w 2d 4h')` or `DURATION('-90 minutes')`. NULL when `text` is NULL.
+fn execute_function_duration(
+    func: &Function,
+    data: &Pod,
+    context: &ExecutionContext,
+) -> Result<FieldValue, String> {
+    let [text] = func.args.as_slice() else {
+        return Err(format!(
+            "Function DURATION expects 1 argument, but found {}!",
+            func.args.len()
+        ));
+    };
+    match function_arg_value(text, data, context)? {
+        FieldValue::String(text) => parse_duration(&text).map(FieldValue::Duration).ok_or(format!(
+            "Function DURATION expects a duration like '3d' or '1w 2h 30m' (units {}), but found: {:?}",
+            DURATION_UNIT_NAMES
+                .iter()
+                .map(|(names, _)| names[0])
+                .collect::<Vec<_>>()
+                .join(", "),
+            text
+        )),
+        FieldValue::Null => Ok(FieldValue::Null),
+        FieldValue::Duration(seconds) => Ok(FieldValue::Duration(seconds)),
+        text => Err(format!(
+            "Function DURATION expects a text like '3d', but found: {}",
+            text.to_query_string()
+        )),
+    }
+}
+
+/// Names of the units DURATION reads, with their length in seconds
+const DURATION_UNIT_NAMES: [(&[&str], f64); 5] = [
+    (&["w", "week", "weeks"], 604800.0),
+    (&["d", "day", "days"], 86400.0),
+    (&["h", "hour", "hours"], 3600.0),
+    (&["m", "min", "mins", "minute", "minutes"], 60.0),
+    (&["s", "sec", "secs", "second", "seconds"], 1.0),
+];
+
+// Seconds of `-1w 2.5d`, `None` when it is not a duration
+fn parse_duration(text: &str) -> Option<i64> {
+    let text = text.trim();
+    let (sign, mut rest) = match text.strip_prefix('-') {
+        Some(rest) => (-1.0, rest),
+        None => (1.0, text),
+    };
+    let mut seconds = 0.0;
+    let mut parts = 0;
+    while !rest.trim_start().is_empty() {
+        rest = rest.trim_start();
+        let number_end = rest
+            .find(|c: char| !c.is_ascii_digit() && c != '.')
+            .unwrap_or(rest.len());
+        let number: f64 = rest[..number_end].parse().ok()?;
+        rest = rest[number_end..].trim_start();
+        let unit_end = rest
+            .find(|c: char| !c.is_ascii_alphabetic())
+            .unwrap_or(rest.len());
+        let unit = rest[..unit_end].to_lowercase();
+        let (_, unit_seconds) = DURATION_UNIT_NAMES
+            .iter()
+            .find(|(names, _)| names.contains(&unit.as_str()))?;
+        seconds += number * unit_seconds;
+        parts += 1;
+        rest = &rest[unit_end..];
+    }
+    (parts > 0).then_some((sign * seconds).round() as i64)
+}
+
+/// TODAY(): the start of the current (local) day, NOW(): the current (local) date and time.
+fn execute_function_now(func: &Function) -> Result<FieldValue, String> {
+    if !func.args.is_empty() {
+        return Err(format!(
+            "Function {} expects no arguments, but found {}!",
+            func.name.to_uppercase(),
+            func.args.len()
+        ));
+    }
+    let now = Local::now().naive_local();
+    let now = match func.name.to_uppercase().as_str() {
+        "TODAY" => now.date().and_time(NaiveTime::MIN),
+        _ => now.with_nanosecond(0).unwrap_or(now),
+    };
+    Ok(FieldValue::String(now.format(DATE_FORMAT).to_string()))
+}
+
+/// LAST_DAYS(n): the start of the day `n` days ago, for `created >= LAST_DAYS(7)`. Same as
+/// `TODAY() - n`.
+fn execute_function_last_days(
+    func: &Function,
+    data: &Pod,
+    context: &ExecutionContext,
+) -> Result<FieldValue, String> {
+    let [days] = func.args.as_slice() else {
+        return Err(format!(
+            "Function LAST_DAYS expects 1 argument, but found {}!",
+            func.args.len()
+        ));
+    };
+    let days = match function_arg_value(days, data, context)? {
+        days if days.is_number() => days,
+        FieldValue::Null => return Ok(FieldValue::Null),
+        days => {
+            return Err(format!(
+                "Function LAST_DAYS expects a number of days, but found: {}",
+                days.to_query_string()
+            ))
+        }
+    };
+    let today = execute_function_now(&Function::new("TODAY".to_string(), Vec::new()))?;
+    execute_date_arithmetic(&Operator::Minus, &today, &days)
+}
+
+/// IF(condition, then, else): `then` when the condition is true, `else` when it is false or NULL.
+/// Only the returned argument is evaluated.
+fn execute_function_if(
+    func: &Function,
+    data: &Pod,
+    context: &ExecutionContext,
+) -> Result<FieldValue, String> {
+    let [condition, then, otherwise] = func.args.as_slice() else {
+        return Err(format!(
+            "Function IF expects 3 arguments, but found {}!",
+            func.args.len()
+        ));
+    };
+    match function_arg_value(condition, data, context)? {
+        FieldValue::Bool(true) => function_arg_value(then, data, context),
+        FieldValue::Bool(false) | FieldValue::Null => function_arg_value(otherwise, data, context),
+        condition => Err(format!(
+            "Function IF expects its condition to be a bool, but found: {}",
+            condition.to_query_string()
+        )),
+    }
+}
+
+/// TO_NUMBER(x), TO_STRING(x) and TO_BOOL(x): `x` converted to a number, string or bool, e.g.
+/// `TO_NUMBER('2024')` is 2024 and `TO_BOOL('yes')` is true. NULL when `x` can not be converted.
+fn execute_function_cast(
+    name: &str,
+    func: &Function,
+    data: &Pod,
+    context: &ExecutionContext,
+) -> Result<FieldValue, String> {
+    let [value] = func.args.as_slice() else {
+        return Err(format!(
+            "Function {} expects 1 argument, but found {}!",
+            name,
+            func.args.len()
+        ));
+    };
+    let value = function_arg_value(value, data, context)?;
+    Ok(match (name, value) {
+        (_, FieldValue::Null) => FieldValue::Null,
+        ("TO_NUMBER", value) if value.is_number() => value,
+        ("TO_NUMBER", FieldValue::String(text)) => {
+            let text = text.trim();
+            match text.parse::<i64>() {
+                Ok(number) => FieldValue::Integer(number),
+                Err(_) => text
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|number| number.is_finite())
+                    .map_or(FieldValue::Null, FieldValue::Float),
             }
+        }
+        ("TO_NUMBER", FieldValue::Bool(bool)) => FieldValue::Integer(bool as i64),
+        ("TO_STRING", FieldValue::List(_)) => FieldValue::Null,
+        ("TO_STRING", value) => FieldValue::String(value.to_string()),
+        ("TO_BOOL", FieldValue::Bool(bool)) => FieldValue::Bool(bool),
+        ("TO_BOOL", FieldValue::String(text)) => match text.trim().to_lowercase().as_str() {
+            "true" | "yes" | "y" | "on" | "1" => FieldValue::Bool(true),
+            "false" | "no" | "n" | "off" | "0" => FieldValue::Bool(false),
+            _ => FieldValue::Null,
         },
-        FunctionArg::FieldValue(FieldValue::String(interval)) => interval.clone(),
-        _ => {
+        ("TO_BOOL", value) if value.is_number() => FieldValue::Bool(value.as_f64() != Some(0.0)),
+        _ => FieldValue::Null,
+    })
+}
+
+/// TO_DATE(x, <optional-format>): `x` parsed as a date like DATE, but NULL instead of an error when
+/// it is not one, e.g. `TO_DATE(published, '%d.%m.%Y')`.
+fn execute_function_to_date(
+    func: &Function,
+    data: &Pod,
+    context: &ExecutionContext,
+) -> Result<FieldValue, String> {
+    if func.args.len() != 1 && func.args.len() != 2 {
+        return Err(format!(
+            "Function TO_DATE expects 1 or 2 arguments, but found {}!",
+            func.args.len()
+        ));
+    }
+    let text = match function_arg_value(&func.args[0], data, context)? {
+        FieldValue::Null | FieldValue::List(_) => return Ok(FieldValue::Null),
+        value => value.to_string(),
+    };
+    let format_str = match func
+        .args
+        .get(1)
+        .map(|arg| function_arg_value(arg, data, context))
+    {
+        Some(Ok(FieldValue::String(format_str))) => Some(format_str),
+        None | Some(Ok(FieldValue::Null)) => None,
+        Some(format) => {
             return Err(format!(
-                "Function DATEADD expects first argument to be an interval, but found: {:?}",
-                func.args[0]
+                "Function TO_DATE expects second argument to be a format, but found: {}",
+                format?.to_query_string()
             ))
         }
     };
 
-    // SECOND ARGUMENT
-    let number = match &func.args[1] {
-        FunctionArg::FieldName(field_name) => match get_field_value(field_name, data) {
-            FieldValue::Number(number) => number,
-            _ => {
-                return Err(format!(
-                    "Function DATEADD expects second argument to be a number, but found: {:?}",
-                    func.args[1]
-                ))
+    Ok(
+        parse_naive_datetime(text.trim(), &format_str).map_or(FieldValue::Null, |datetime| {
+            FieldValue::String(datetime.format(DATE_FORMAT).to_string())
+        }),
+    )
+}
+
+/// RANDOM(<optional-seed>): a number from 0 to 1 that stays the same for a row during a query, so
+/// `ORDER BY RANDOM()` shuffles the rows. With a seed, e.g. `RANDOM(TODAY())`, the numbers only
+/// change with the seed (and the row).
+fn execute_function_random(
+    func: &Function,
+    data: &Pod,
+    context: &ExecutionContext,
+) -> Result<FieldValue, String> {
+    let mut hasher = DefaultHasher::new();
+    match func.args.as_slice() {
+        [] => context.random_seed.0.hash(&mut hasher),
+        [seed] => function_arg_value(seed, data, context)?
+            .to_string()
+            .hash(&mut hasher),
+        args => {
+            return Err(format!(
+                "Function RANDOM expects 0 or 1 arguments, but found {}!",
+                args.len()
+            ))
+        }
+    }
+    hash_pod(data, &mut hasher);
+
+    // the 53 bits a float can hold exactly
+    Ok(FieldValue::Float(
+        (hasher.finish() >> 11) as f64 / (1u64 << 53) as f64,
+    ))
+}
+
+// Feeds every value of `pod` into `hasher`, so that different rows get different RANDOM() numbers
+fn hash_pod(pod: &Pod, hasher: &mut impl Hasher) {
+    std::mem::discriminant(pod).hash(hasher);
+    match pod {
+        Pod::Null => {}
+        Pod::String(text) => text.hash(hasher),
+        Pod::Integer(number) => number.hash(hasher),
+        Pod::Float(number) => number.to_bits().hash(hasher),
+        Pod::Boolean(bool) => bool.hash(hasher),
+        Pod::Array(list) => {
+            list.len().hash(hasher);
+            for item in list {
+                hash_pod(item, hasher);
             }
-        },
-        FunctionArg::FieldValue(FieldValue::Number(number)) => *number,
+        }
+        Pod::Hash(hash) => {
+            hash.len().hash(hasher);
+            for (key, value) in hash {
+                key.hash(hasher);
+                hash_pod(value, hasher);
+            }
+        }
+    }
+}
+
+/// SIMILARITY(a, b): trigram similarity of two strings, from 0 (no trigram in common) to 1 (same
+/// trigrams), ignoring case and punctuation. NULL when either is not a string.
+fn execute_function_similarity(
+    func: &Function,
+    data: &Pod,
+    context: &ExecutionContext,
+) -> Result<FieldValue, String> {
+    let [a, b] = func.args.as_slice() else {
+        return Err(format!(
+            "Function SIMILARITY expects 2 arguments, but found {}!",
+            func.args.len()
+        ));
+    };
+    match (
+        function_arg_value(a, data, context)?,
+        function_arg_value(b, data, context)?,
+    ) {
+        (FieldValue::String(a), FieldValue::String(b)) => {
+            Ok(FieldValue::Float(trigram_similarity(&a, &b)))
+        }
+        _ => Ok(FieldValue::Null),
+    }
+}
+
+/// Shared trigrams over all trigrams of both strings, like PostgreSQL's pg_trgm: every word is
+/// lowercased and padded with two spaces in front and one after, so short words and word starts
+/// count as well.
+pub fn trigram_similarity(a: &str, b: &str) -> f64 {
+    let trigrams = |text: &str| -> IndexSet<[char; 3]> {
+        let mut trigrams = IndexSet::new();
+        for word in text
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|word| !word.is_empty())
+        {
+            let padded: Vec<char> = "  "
+                .chars()
+                .chain(word.chars().flat_map(char::to_lowercase))
+                .chain(std::iter::once(' '))
+                .collect();
+            for window in padded.windows(3) {
+                trigrams.insert([window[0], window[1], window[2]]);
+            }
+        }
+        trigrams
+    };
+    let (a, b) = (trigrams(a), trigrams(b));
+    let shared = a.intersection(&b).count();
+    let total = a.len() + b.len() - shared;
+    if total == 0 {
+        return 0.0;
+    }
+    shared as f64 / total as f64
+}
+
+/// Component `name` (one of [`DATE_MEMBERS`](crate::libs::parser::DATE_MEMBERS)) of the date
+/// `value`. NULL stays NULL.
+fn get_member(value: &FieldValue, name: &str) -> Result<FieldValue, String> {
+    let date_str = match value {
+        FieldValue::String(date_str) => date_str,
+        FieldValue::Null => return Ok(FieldValue::Null),
         _ => {
             return Err(format!(
-                "Function DATEADD expects second argument to be a number, but found: {:?}",
-                func.args[1]
+                "Member .{} expects a date, but found: {}",
+                name,
+                value.to_query_string()
+            ))
+        }
+    };
+    let date = parse_naive_datetime(date_str, &None)
+        .map_err(|_| format!("Member .{} expects a date, but found: {:?}", name, date_str))?;
+
+    let component = match name {
+        "year" => date.year(),
+        "month" => date.month() as i32,
+        "day" => date.day() as i32,
+        "hour" => date.hour() as i32,
+        "minute" => date.minute() as i32,
+        "second" => date.second() as i32,
+        "weekday" => date.weekday().number_from_monday() as i32,
+        "week" => date.iso_week().week() as i32,
+        "dayofyear" => date.ordinal() as i32,
+        _ => return Err(format!("Unknown member .{}", name)),
+    };
+
+    Ok(FieldValue::Integer(component as i64))
+}
+fn execute_function_date_add(
+    func: &Function,
+    data: &Pod,
+    context: &ExecutionContext,
+) -> Result<FieldValue, String> {
+    if func.args.len() != 3 && func.args.len() != 4 {
+        return Err(format!(
+            "Function DATEADD expects 3 or 4 arguments, but found {}!",
+            func.args.len()
+        ));
+    }
+
+    // FIRST ARGUMENT
+    let interval = match function_arg_value(&func.args[0], data, context)? {
+        FieldValue::String(interval) => interval,
+        interval => {
+            return Err(format!(
+                "Function DATEADD expects first argument to be an interval, but found: {}",
+                interval.to_query_string()
+            ))
+        }
+    };
+
+    // SECOND ARGUMENT
+    let number = function_arg_value(&func.args[1], data, context)?;
+    let number = match number.as_f64() {
+        Some(number) => number,
+        None => {
+            return Err(format!(
+                "Function DATEADD expects second argument to be a number, but found: {}",
+                number.to_query_string()
             ))
         }
     };
 
     // THIRD ARGUMENT
-    let date_str = match &func.args[2] {
-        FunctionArg::FieldName(field_name) => match get_field_value(field_name, data) {
-            FieldValue::String(date_str) => date_str,
-            _ => {
-                return Err(format!(
-                    "Function DATEADD expects third argument to be a date, but found: {:?}",
-                    func.args[2]
-                ))
-            }
-        },
-        FunctionArg::FieldValue(FieldValue::String(date_str)) => date_str.clone(),
-        _ => {
+    let date_str = match function_arg_value(&func.args[2], data, context)? {
+        FieldValue::String(date_str) => date_str,
+        date => {
             return Err(format!(
-                "Function DATEADD expects third argument to be a date, but found: {:?}",
-                func.args[2]
+                "Function DATEADD expects third argument to be a date, but found: {}",
+                date.to_query_string()
             ))
         }
     };
 
     // FOURTH ARGUMENT
-    let format_str = match &func.args.get(3) {
-        Some(FunctionArg::FieldName(field_name)) => match get_field_value(field_name, data) {
-            FieldValue::String(format_str) => Some(format_str),
-            FieldValue::Null => None,
-            _ => {
-                return Err(format!(
-                    "Function DATEADD expects fourth argument to be a format, but found: {:?}",
-                    func.args[3]
-                ))
-            }
-        },
-        Some(FunctionArg::FieldValue(FieldValue::String(format_str))) => Some(format_str.clone()),
-        None => None,
-        _ => {
+    let format_str = match func
+        .args
+        .get(3)
+        .map(|arg| function_arg_value(arg, data, context))
+    {
+        Some(Ok(FieldValue::String(format_str))) => Some(format_str),
+        None | Some(Ok(FieldValue::Null)) => None,
+        Some(format) => {
             return Err(format!(
-                "Function DATEADD expects fourth argument to be a format, but found: {:?}",
-                func.args[3]
+                "Function DATEADD expects fourth argument to be a format, but found: {}",
+                format?.to_query_string()
             ))
         }
     };
@@ -516,7 +2029,11 @@ fn execute_function_date_add(func: &Function, data: &Pod) -> Result<FieldValue,
     ))
 }
 
-fn execute_function_date(func: &Function, data: &Pod) -> Result<FieldValue, String> {
+fn execute_function_date(
+    func: &Function,
+    data: &Pod,
+    context: &ExecutionContext,
+) -> Result<FieldValue, String> {
     if func.args.len() != 1 && func.args.len() != 2 {
         return Err(format!(
             "Function DATE expects 1 or 2 arguments, but found {}!",
@@ -525,43 +2042,28 @@ fn execute_function_date(func: &Function, data: &Pod) -> Result<FieldValue, Stri
     }
 
     // FIRST ARGUMENT
-    let date_str = match &func.args[0] {
-        FunctionArg::FieldName(field_name) => match get_field_value(field_name, data) {
-            FieldValue::String(date_str) => date_str,
-            _ => {
-                return Err(format!(
-                    "Function DATE expects first argument to be a date, but found: {:?}",
-                    func.args[0]
-                ))
-            }
-        },
-        FunctionArg::FieldValue(FieldValue::String(date_str)) => date_str.clone(),
-        _ => {
+    let date_str = match function_arg_value(&func.args[0], data, context)? {
+        FieldValue::String(date_str) => date_str,
+        date => {
             return Err(format!(
-                "Function DATE expects first argument to be a date, but found: {:?}",
-                func.args[0]
+                "Function DATE expects first argument to be a date, but found: {}",
+                date.to_query_string()
             ))
         }
     };
 
     // SECOND ARGUMENT
-    let format_str = match &func.args.get(1) {
-        Some(FunctionArg::FieldName(field_name)) => match get_field_value(field_name, data) {
-            FieldValue::String(format_str) => Some(format_str),
-            FieldValue::Null => None,
-            _ => {
-                return Err(format!(
-                    "Function DATE expects second argument to be a format, but found: {:?}",
-                    func.args[1]
-                ))
-            }
-        },
-        Some(FunctionArg::FieldValue(FieldValue::String(format_str))) => Some(format_str.clone()),
-        None => None,
-        _ => {
+    let format_str = match func
+        .args
+        .get(1)
+        .map(|arg| function_arg_value(arg, data, context))
+    {
+        Some(Ok(FieldValue::String(format_str))) => Some(format_str),
+        None | Some(Ok(FieldValue::Null)) => None,
+        Some(format) => {
             return Err(format!(
-                "Function DATE expects second argument to be a format, but found: {:?}",
-                func.args[1]
+                "Function DATE expects second argument to be a format, but found: {}",
+                format?.to_query_string()
             ))
         }
     };
@@ -619,6 +2121,7 @@ fn parse_naive_datetime(input: &str, format: &Option<String>) -> Result<NaiveDat
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::libs::serializer::pods_to_tsv;
 
     /***************************************************************************************************
      * TESTS for execute_select
@@ -675,6 +2178,114 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_rollup_count() {
+        let note = |folder: Option<&str>| {
+            let mut file = Pod::new_hash();
+            if let Some(folder) = folder {
+                let _ = file.insert("folder".to_string(), Pod::String(folder.to_string()));
+            }
+            let mut pod = Pod::new_hash();
+            let _ = pod.insert("file".to_string(), file);
+            pod
+        };
+        let data = vec![
+            note(Some("b")),
+            note(Some("a")),
+            note(None),
+            note(Some("b")),
+        ];
+
+        let (fields, rows) = rollup_count("file.folder", &data);
+
+        assert_eq!(vec!["file.folder", "count"], fields);
+        let rows: Vec<String> = rows.iter().map(Pod::to_string).collect();
+        assert_eq!(
+            vec![
+                r#"{"file":{"folder":null},"count":1}"#,
+                r#"{"file":{"folder":"a"},"count":1}"#,
+                r#"{"file":{"folder":"b"},"count":2}"#,
+                r#"{"file":{"folder":null},"count":4}"#,
+            ],
+            rows
+        );
+    }
+
+    #[test]
+    fn test_pivot() -> Result<(), String> {
+        let data: Vec<Pod> = [("b", "open", 1), ("a", "done", 2), ("b", "done", 3)]
+            .iter()
+            .map(|(folder, status, count)| {
+                let mut pod = Pod::new_hash();
+                let _ = pod.insert("file.folder".to_string(), Pod::String(folder.to_string()));
+                let _ = pod.insert("status".to_string(), Pod::String(status.to_string()));
+                let _ = pod.insert("COUNT(*)".to_string(), Pod::Integer(*count));
+                pod
+            })
+            .collect();
+        let fields: Vec<String> = ["file.folder", "status", "COUNT(*)"]
+            .iter()
+            .map(|field| field.to_string())
+            .collect();
+
+        let (pivot_fields, rows) = pivot(&fields, &data)?;
+
+        assert_eq!(vec!["file.folder", "done", "open"], pivot_fields);
+        let rows: Vec<String> = rows.iter().map(Pod::to_string).collect();
+        assert_eq!(
+            vec![
+                r#"{"file.folder":"b","open":1,"done":3}"#,
+                r#"{"file.folder":"a","done":2}"#,
+            ],
+            rows
+        );
+
+        // Without the folder there are two counts of done
+        assert_eq!(
+            Err("Pivot found more than one COUNT(*) for status done, group by the other columns to have one".to_string()),
+            pivot(&["x".to_string(), fields[1].clone(), fields[2].clone()], &data)
+        );
+        assert!(pivot(&fields[2..], &data).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_expand_select_all_keeps_first_seen_order() {
+        let mut pod1 = Pod::new_hash();
+        let _ = pod1.insert("zed".to_string(), Pod::Integer(1));
+        let _ = pod1.insert("title".to_string(), Pod::String("a".to_string()));
+        let mut pod2 = Pod::new_hash();
+        let _ = pod2.insert("alpha".to_string(), Pod::Integer(2));
+        let _ = pod2.insert("zed".to_string(), Pod::Integer(3));
+        let data = vec![pod1, pod2];
+
+        assert_eq!(
+            vec!["zed", "title", "alpha"],
+            expand_select_all(vec!["*".to_string()], &[], &data)
+        );
+        assert_eq!(
+            vec!["title", "zed", "alpha", "file.name"],
+            expand_select_all(
+                vec![
+                    "title".to_string(),
+                    "*".to_string(),
+                    "file.name".to_string()
+                ],
+                &[],
+                &data
+            )
+        );
+        assert_eq!(
+            vec!["title"],
+            expand_select_all(vec!["title".to_string()], &[], &data)
+        );
+        assert_eq!(
+            vec!["title", "alpha"],
+            expand_select_all(vec!["*".to_string()], &["zed".to_string()], &data)
+        );
+    }
+
     #[test]
     fn test_execute_select_retains_nested_field() {
         // Create sample Pod data with 3 fields
@@ -768,11 +2379,13 @@ mod tests {
         // Execute order by field2
         assert!(
             execute_order_by(
-                &vec![OrderByFieldOption {
+                &[OrderByFieldOption {
                     field_name: field2.clone(),
                     order_direction: OrderDirection::ASC,
+                    function: None,
                 }],
                 &mut data,
+                &ExecutionContext::default(),
             )
             .is_ok(),
             "Order by should be successful"
@@ -810,11 +2423,13 @@ mod tests {
         // Execute order by field2
         assert!(
             execute_order_by(
-                &vec![OrderByFieldOption {
+                &[OrderByFieldOption {
                     field_name: field2.clone(),
                     order_direction: OrderDirection::ASC,
+                    function: None,
                 }],
                 &mut data,
+                &ExecutionContext::default(),
             )
             .is_ok(),
             "Order by should be successful"
@@ -852,11 +2467,13 @@ mod tests {
         // Execute order by field2
         assert!(
             execute_order_by(
-                &vec![OrderByFieldOption {
+                &[OrderByFieldOption {
                     field_name: field2.clone(),
                     order_direction: OrderDirection::ASC,
+                    function: None,
                 }],
                 &mut data,
+                &ExecutionContext::default(),
             )
             .is_ok(),
             "Order by should be successful"
@@ -868,6 +2485,419 @@ mod tests {
         assert_eq!(pod1, data[1], "Second element should be pod1");
     }
 
+    #[test]
+    fn test_trigram_similarity() {
+        assert_eq!(1.0, trigram_similarity("Weekly Review", "weekly review!"));
+        assert_eq!(0.0, trigram_similarity("weekly", "month"));
+        assert_eq!(0.0, trigram_similarity("", ""));
+        // Word order does not matter, other words make it less similar
+        assert_eq!(1.0, trigram_similarity("review weekly", "weekly review"));
+        assert!(
+            trigram_similarity("Weekly review 2024-03", "weekly review")
+                < trigram_similarity("Weekly review", "weekly review")
+        );
+    }
+
+    #[test]
+    fn test_execute_order_by_similarity() -> Result<(), Box<dyn Error>> {
+        let data: Vec<Pod> = ["Daily log", "Weekly", "Review of week", "Weekly review"]
+            .iter()
+            .map(|title| {
+                let mut pod = Pod::new_hash();
+                let _ = pod.insert("title".to_string(), Pod::String(title.to_string()));
+                pod
+            })
+            .chain(std::iter::once(Pod::new_hash()))
+            .collect();
+        let query: Query =
+            "SELECT title ORDER BY SIMILARITY(title, 'weekly review') DESC, title".parse()?;
+
+        let (_, rows) = execute_on_data(query, data, &ExecutionContext::default())?;
+
+        // Rows without a title sort like NULL, last when descending
+        let titles: Vec<String> = rows.iter().map(|row| row.to_string()).collect();
+        assert_eq!(
+            vec![
+                r#"{"title":"Weekly review"}"#,
+                r#"{"title":"Review of week"}"#,
+                r#"{"title":"Weekly"}"#,
+                r#"{"title":"Daily log"}"#,
+                "{}",
+            ],
+            titles
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_execute_select_functions() -> Result<(), Box<dyn Error>> {
+        let data: Vec<Pod> = [
+            ("a", Some("2024-03-15")),
+            ("b", None),
+            ("c", Some("2024-01-02")),
+        ]
+        .iter()
+        .map(|(name, created)| {
+            let mut pod = Pod::new_hash();
+            let _ = pod.insert("name".to_string(), Pod::String(name.to_string()));
+            if let Some(created) = created {
+                let _ = pod.insert("created".to_string(), Pod::String(created.to_string()));
+            }
+            pod
+        })
+        .collect();
+        let query: Query =
+            "SELECT name, date_trunc('month', created), DATE(created, '%Y-%m-%d') ORDER BY 2 DESC"
+                .parse()?;
+
+        let (fields, rows) = execute_on_data(query, data.clone(), &ExecutionContext::default())?;
+
+        // Columns are named by the function, rows it fails for don't have the column (DATE_TRUNC
+        // of NULL is NULL, DATE of NULL fails)
+        assert_eq!(
+            vec![
+                "name",
+                "DATE_TRUNC('month', created)",
+                "DATE(created, '%Y-%m-%d')"
+            ],
+            fields
+        );
+        let rows: Vec<String> = rows.iter().map(|row| row.to_string()).collect();
+        assert_eq!(
+            vec![
+                r#"{"name":"a","DATE_TRUNC('month', created)":"2024-03-01T00:00:00","DATE(created, '%Y-%m-%d')":"2024-03-15T00:00:00"}"#,
+                r#"{"name":"c","DATE_TRUNC('month', created)":"2024-01-01T00:00:00","DATE(created, '%Y-%m-%d')":"2024-01-02T00:00:00"}"#,
+                r#"{"name":"b","DATE_TRUNC('month', created)":null}"#,
+            ],
+            rows
+        );
+        // so they print like a missing field, not as NULL
+        let query: Query = "SELECT name, DATE(created), created".parse()?;
+        let (fields, rows) = execute_on_data(query, data.clone(), &ExecutionContext::default())?;
+        let tsv = pods_to_tsv(fields, rows);
+        assert_eq!(Some("b\t\t"), tsv.lines().nth(2));
+
+        let error = execute_on_data(
+            "SELECT name, DATUM(created)".parse()?,
+            data,
+            &ExecutionContext::default(),
+        )
+        .unwrap_err();
+        assert!(error
+            .to_string()
+            .starts_with("Unknown function: DATUM, did you mean 'DATE'?"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_execute_select_expressions() -> Result<(), Box<dyn Error>> {
+        let data: Vec<Pod> = [("a", Some(3)), ("b", None)]
+            .iter()
+            .map(|(name, n)| {
+                let mut pod = Pod::new_hash();
+                let _ = pod.insert("name".to_string(), Pod::String(name.to_string()));
+                if let Some(n) = n {
+                    let _ = pod.insert("n".to_string(), Pod::Integer(*n));
+                    let _ =
+                        pod.insert("created".to_string(), Pod::String("2024-03-15".to_string()));
+                }
+                pod
+            })
+            .collect();
+        let query: Query =
+            "SELECT name, n*2, DATE(created).year AS year, n * n + 1 AS sq WHERE sq > 1".parse()?;
+
+        let (fields, rows) = execute_on_data(query, data.clone(), &ExecutionContext::default())?;
+
+        // Columns are named by their text, rows they fail for get NULL
+        assert_eq!(vec!["name", "n*2", "year", "sq"], fields);
+        let rows: Vec<String> = rows.iter().map(|row| row.to_string()).collect();
+        assert_eq!(vec![r#"{"name":"a","year":2024,"sq":10,"n*2":6}"#], rows);
+
+        // Failing for every row is an error, like in WHERE
+        for query in [
+            "SELECT name, IF('x' + 1 == 2, 1, 2) AS bad",
+            "SELECT name, 'x' + 1",
+        ] {
+            let error = execute_on_data(query.parse()?, data.clone(), &ExecutionContext::default())
+                .unwrap_err()
+                .to_string();
+            assert!(error.starts_with("Can't add String(\"x\")"), "{}", error);
+            assert!(error.ends_with(&format!("— at characters 0..{}: `{}`", query.len(), query)));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_execute_select_aliases() -> Result<(), Box<dyn Error>> {
+        let data: Vec<Pod> = [
+            ("a", "work", Some("2024-03-15")),
+            ("b", "home", None),
+            ("c", "work", Some("2024-01-02")),
+        ]
+        .iter()
+        .map(|(name, area, created)| {
+            let mut pod = Pod::new_hash();
+            let _ = pod.insert("name".to_string(), Pod::String(name.to_string()));
+            let _ = pod.insert("area".to_string(), Pod::String(area.to_string()));
+            if let Some(created) = created {
+                let _ = pod.insert("created".to_string(), Pod::String(created.to_string()));
+            }
+            pod
+        })
+        .collect();
+        let execute = |query: &str| -> Result<(Vec<String>, Vec<String>), Box<dyn Error>> {
+            let (fields, rows) =
+                execute_on_data(query.parse()?, data.clone(), &ExecutionContext::default())?;
+            Ok((fields, rows.iter().map(|row| row.to_string()).collect()))
+        };
+
+        // WHERE and ORDER BY can use the aliases
+        assert_eq!(
+            (
+                vec!["note".to_string(), "month".to_string()],
+                vec![
+                    r#"{"note":"c","month":"2024-01-01T00:00:00"}"#.to_string(),
+                    r#"{"note":"a","month":"2024-03-01T00:00:00"}"#.to_string(),
+                ]
+            ),
+            execute(
+                "SELECT name AS note, DATE_TRUNC('month', created) AS month \
+                 WHERE month != NULL ORDER BY month"
+            )?
+        );
+        // and grouped queries in ORDER BY
+        assert_eq!(
+            (
+                vec!["where".to_string(), "notes".to_string()],
+                vec![
+                    r#"{"where":"work","notes":2}"#.to_string(),
+                    r#"{"where":"home","notes":1}"#.to_string(),
+                ]
+            ),
+            execute("SELECT area AS where, COUNT(*) AS notes GROUP BY area ORDER BY notes DESC")?
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_resolve_subqueries() -> Result<(), Box<dyn Error>> {
+        // Paths of the files FROM F('<n>') returns, every row selects `path`
+        let execute = |query: Query| -> Result<(Vec<String>, Vec<Pod>), Box<dyn Error>> {
+            let Some(Function { args, .. }) = &query.from_function else {
+                return Err("no FROM".into());
+            };
+            let count = match args.first() {
+                Some(FunctionArg::FieldValue(FieldValue::String(count))) => count.parse()?,
+                _ => return Err("Unknown FROM".into()),
+            };
+            let rows = (0..count)
+                .map(|index| {
+                    let mut pod = Pod::new_hash();
+                    let _ = pod.insert("path".to_string(), Pod::String(format!("{}.md", index)));
+                    pod
+                })
+                .collect();
+            Ok((query.select_fields.clone(), rows))
+        };
+        let resolve = |query: &str| -> Result<String, Box<dyn Error>> {
+            Ok(resolve_subqueries(query.parse()?, execute)?.to_string())
+        };
+
+        assert_eq!(
+            "WHERE path IN ('0.md', '1.md') AND archived == false",
+            resolve("WHERE path IN (SELECT path FROM F('2')) AND archived == EXISTS (SELECT path FROM F('0'))")?
+        );
+        assert_eq!(
+            "WHERE path == '0.md' OR path == NULL",
+            resolve(
+                "WHERE path == (SELECT path FROM F('1')) OR path == (SELECT path FROM F('0'))"
+            )?
+        );
+        assert!(resolve("WHERE path == (SELECT path FROM F('3'))")
+            .unwrap_err()
+            .to_string()
+            .starts_with(
+                "Subquery returned 3 rows, but one value is needed here (IN takes all of them)"
+            ));
+        assert!(resolve("WHERE path IN (SELECT path, name FROM F('1'))")
+            .unwrap_err()
+            .to_string()
+            .starts_with("Subquery needs to select one field, but selects path, name"));
+        assert!(resolve("WHERE EXISTS (SELECT path FROM F(path))")
+            .unwrap_err()
+            .to_string()
+            .starts_with("Subquery failed: Unknown FROM"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_execute_limit_and_offset() -> Result<(), Box<dyn Error>> {
+        let data: Vec<Pod> = (1..=5)
+            .map(|sleep| {
+                let mut pod = Pod::new_hash();
+                let _ = pod.insert("sleep".to_string(), Pod::Integer(sleep));
+                pod
+            })
+            .collect();
+        let sleeps = |query: &str| -> Result<Vec<String>, Box<dyn Error>> {
+            let (_, rows) =
+                execute_on_data(query.parse()?, data.clone(), &ExecutionContext::default())?;
+            Ok(rows.iter().map(|row| row.to_string()).collect())
+        };
+
+        // Rows are cut after ordering, not in file order
+        assert_eq!(
+            vec![r#"{"sleep":4}"#, r#"{"sleep":3}"#],
+            sleeps("SELECT sleep ORDER BY sleep DESC LIMIT 2 OFFSET 1")?
+        );
+        assert_eq!(
+            vec![r#"{"sleep":5}"#],
+            sleeps("SELECT sleep WHERE sleep > 1 OFFSET 3")?
+        );
+        assert!(sleeps("SELECT sleep OFFSET 10")?.is_empty());
+        assert_eq!(5, sleeps("SELECT sleep LIMIT 10")?.len());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_execute_limit_per() -> Result<(), Box<dyn Error>> {
+        let data: Vec<Pod> = [
+            (1, Some("app")),
+            (2, Some("site")),
+            (3, Some("app")),
+            (4, None),
+            (5, Some("app")),
+            (6, None),
+        ]
+        .iter()
+        .map(|(sleep, project)| {
+            let mut pod = Pod::new_hash();
+            let _ = pod.insert("sleep".to_string(), Pod::Integer(*sleep));
+            if let Some(project) = project {
+                let _ = pod.insert("project".to_string(), Pod::String(project.to_string()));
+            }
+            pod
+        })
+        .collect();
+        let sleeps = |query: &str| -> Result<Vec<i64>, Box<dyn Error>> {
+            let (_, rows) =
+                execute_on_data(query.parse()?, data.clone(), &ExecutionContext::default())?;
+            Ok(rows
+                .iter()
+                .filter_map(|row| row.nested_get("sleep").and_then(Pod::as_i64))
+                .collect())
+        };
+
+        // Rows without the field are counted together
+        assert_eq!(
+            vec![6, 5, 4, 3, 2],
+            sleeps("SELECT sleep ORDER BY sleep DESC LIMIT 2 PER project")?
+        );
+        // OFFSET skips rows of every value as well
+        assert_eq!(
+            vec![3, 6],
+            sleeps("SELECT sleep ORDER BY sleep LIMIT 1 PER project OFFSET 1")?
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_execute_group_by() -> Result<(), Box<dyn Error>> {
+        let data: Vec<Pod> = [
+            ("a", "done", 7),
+            ("b", "open", 5),
+            ("c", "done", 9),
+            ("d", "late", 6),
+        ]
+        .iter()
+        .map(|(name, status, sleep)| {
+            let mut file = Pod::new_hash();
+            let _ = file.insert("name".to_string(), Pod::String(name.to_string()));
+            let mut pod = Pod::new_hash();
+            let _ = pod.insert("file".to_string(), file);
+            let _ = pod.insert("status".to_string(), Pod::String(status.to_string()));
+            let _ = pod.insert("sleep".to_string(), Pod::Integer(*sleep));
+            pod
+        })
+        .collect();
+        let context = ExecutionContext::default();
+
+        // Groups with the same count are in the order of their values
+        let query: Query =
+            "SELECT status, COUNT(*), MEDIAN(sleep) GROUP BY status ORDER BY COUNT(*) DESC"
+                .parse()?;
+        let (fields, rows) = execute_on_data(query, data.clone(), &context)?;
+        assert_eq!(vec!["status", "COUNT(*)", "MEDIAN(sleep)"], fields);
+        let rows: Vec<String> = rows.iter().map(|row| row.to_string()).collect();
+        assert_eq!(
+            vec![
+                r#"{"status":"done","COUNT(*)":2,"MEDIAN(sleep)":8.0}"#,
+                r#"{"status":"late","COUNT(*)":1,"MEDIAN(sleep)":6.0}"#,
+                r#"{"status":"open","COUNT(*)":1,"MEDIAN(sleep)":5.0}"#,
+            ],
+            rows
+        );
+
+        // GROUP BY a SELECT field by its position
+        let query: Query = "SELECT status, COUNT(*) GROUP BY 1 ORDER BY COUNT(*) DESC".parse()?;
+        let (_, rows) = execute_on_data(query, data.clone(), &context)?;
+        let rows: Vec<String> = rows.iter().map(|row| row.to_string()).collect();
+        assert_eq!(
+            vec![
+                r#"{"status":"done","COUNT(*)":2}"#,
+                r#"{"status":"late","COUNT(*)":1}"#,
+                r#"{"status":"open","COUNT(*)":1}"#,
+            ],
+            rows
+        );
+        assert!("SELECT status, COUNT(*) GROUP BY 4"
+            .parse::<Query>()
+            .is_err_and(|error| error.contains("Column position 4 is out of range")));
+
+        // Rows without a date are grouped under NULL, which sorts first
+        let query: Query = "SELECT date_trunc('month', created), COUNT(*) GROUP BY DATE_TRUNC('month', created) ORDER BY DATE_TRUNC('month', created) DESC".parse()?;
+        let mut dated = data.clone();
+        for (pod, created) in dated
+            .iter_mut()
+            .zip(["2024-03-30", "2024-02-01", "2024-03-02"])
+        {
+            let _ = pod.insert("created".to_string(), Pod::String(created.to_string()));
+        }
+        let (fields, rows) = execute_on_data(query, dated, &context)?;
+        assert_eq!(vec!["DATE_TRUNC('month', created)", "COUNT(*)"], fields);
+        let rows: Vec<String> = rows.iter().map(|row| row.to_string()).collect();
+        assert_eq!(
+            vec![
+                r#"{"DATE_TRUNC('month', created)":"2024-03-01T00:00:00","COUNT(*)":2}"#,
+                r#"{"DATE_TRUNC('month', created)":"2024-02-01T00:00:00","COUNT(*)":1}"#,
+                r#"{"DATE_TRUNC('month', created)":null,"COUNT(*)":1}"#,
+            ],
+            rows
+        );
+
+        // Without GROUP BY all of the rows are one group
+        let query: Query =
+            "SELECT file.name, AVG(sleep) WHERE sleep > 5 GROUP BY file.name".parse()?;
+        let (_, rows) = execute_on_data(query, data.clone(), &context)?;
+        assert_eq!(r#"{"file.name":"a","AVG(sleep)":7.0}"#, rows[0].to_string());
+        let query: Query = "SELECT STDDEV(sleep), MAX(file.name) WHERE sleep != 6".parse()?;
+        let (_, rows) = execute_on_data(query, data, &context)?;
+        assert_eq!(
+            r#"{"STDDEV(sleep)":2.0,"MAX(file.name)":"c"}"#,
+            rows[0].to_string()
+        );
+
+        Ok(())
+    }
+
     #[test]
     fn test_execute_order_by_desc() {
         // Create sample Pod data with 3 fields
@@ -894,11 +2924,13 @@ mod tests {
         // Execute order by field2
         assert!(
             execute_order_by(
-                &vec![OrderByFieldOption {
+                &[OrderByFieldOption {
                     field_name: field2.clone(),
                     order_direction: OrderDirection::DESC,
+                    function: None,
                 }],
                 &mut data,
+                &ExecutionContext::default(),
             )
             .is_ok(),
             "Order by should be successful"
@@ -945,17 +2977,20 @@ mod tests {
         // Execute order by field2
         assert!(
             execute_order_by(
-                &vec![
+                &[
                     OrderByFieldOption {
                         field_name: field2.clone(),
                         order_direction: OrderDirection::DESC,
+                        function: None,
                     },
                     OrderByFieldOption {
                         field_name: field1.clone(),
                         order_direction: OrderDirection::ASC,
+                        function: None,
                     }
                 ],
                 &mut data,
+                &ExecutionContext::default(),
             )
             .is_ok(),
             "Order by should be successful"
@@ -994,12 +3029,14 @@ mod tests {
         // Execute where field2 == "value2"
         assert!(
             execute_where(
-                &vec![
+                &[
                     ExpressionElement::FieldName(field2.clone()),
                     ExpressionElement::Operator(Operator::Eq),
                     ExpressionElement::FieldValue(FieldValue::String(field2_value.clone())),
                 ],
+                &QuerySpans::default(),
                 &mut data,
+                &ExecutionContext::default(),
             )
             .is_ok(),
             "Where should be successful"
@@ -1032,12 +3069,14 @@ mod tests {
         // Execute where field2 == "value2"
         assert!(
             execute_where(
-                &vec![
+                &[
                     ExpressionElement::FieldName(field2.clone()),
                     ExpressionElement::Operator(Operator::Eq),
                     ExpressionElement::FieldValue(FieldValue::String(field2_value.clone())),
                 ],
+                &QuerySpans::default(),
                 &mut data,
+                &ExecutionContext::default(),
             )
             .is_ok(),
             "Where should be successful"
@@ -1073,7 +3112,7 @@ mod tests {
         // Execute where field2 LIKE "val.*"
         assert!(
             execute_where(
-                &vec![
+                &[
                     ExpressionElement::Function(Function {
                         name: "DATE".to_string(),
                         args: vec![
@@ -1086,12 +3125,14 @@ mod tests {
                         name: "DATEADD".to_string(),
                         args: vec![
                             FunctionArg::FieldValue(FieldValue::String("YEAR".to_string())),
-                            FunctionArg::FieldValue(FieldValue::Number(1.0)),
+                            FunctionArg::FieldValue(FieldValue::Integer(1)),
                             FunctionArg::FieldValue(FieldValue::String(date_value))
                         ]
                     }),
                 ],
+                &QuerySpans::default(),
                 &mut data,
+                &ExecutionContext::default(),
             )
             .is_ok(),
             "Where should be successful"
@@ -1128,12 +3169,14 @@ mod tests {
         // Execute where field2 LIKE "val.*"
         assert!(
             execute_where(
-                &vec![
+                &[
                     ExpressionElement::FieldName(field2.clone()),
                     ExpressionElement::Operator(Operator::Like),
                     ExpressionElement::FieldValue(FieldValue::String("val.*".to_string())),
                 ],
+                &QuerySpans::default(),
                 &mut data,
+                &ExecutionContext::default(),
             )
             .is_ok(),
             "Where should be successful"
@@ -1198,26 +3241,28 @@ mod tests {
         // Execute where f1 == v4 or f2 == v1 and (f3 == v2 or f4 == v3)
         assert!(
             execute_where(
-                &vec![
+                &[
                     ExpressionElement::FieldName(field1.clone()),
                     ExpressionElement::Operator(Operator::Eq),
-                    ExpressionElement::FieldValue(FieldValue::Number(value4)),
+                    ExpressionElement::FieldValue(FieldValue::Float(value4)),
                     ExpressionElement::Operator(Operator::Or),
                     ExpressionElement::FieldName(field2.clone()),
                     ExpressionElement::Operator(Operator::Eq),
-                    ExpressionElement::FieldValue(FieldValue::Number(value1)),
+                    ExpressionElement::FieldValue(FieldValue::Float(value1)),
                     ExpressionElement::Operator(Operator::And),
                     ExpressionElement::OpenedBracket,
                     ExpressionElement::FieldName(field3.clone()),
                     ExpressionElement::Operator(Operator::Eq),
-                    ExpressionElement::FieldValue(FieldValue::Number(value2)),
+                    ExpressionElement::FieldValue(FieldValue::Float(value2)),
                     ExpressionElement::Operator(Operator::Or),
                     ExpressionElement::FieldName(field4.clone()),
                     ExpressionElement::Operator(Operator::Eq),
-                    ExpressionElement::FieldValue(FieldValue::Number(value3)),
+                    ExpressionElement::FieldValue(FieldValue::Float(value3)),
                     ExpressionElement::ClosedBracket,
                 ],
+                &QuerySpans::default(),
                 &mut data,
+                &ExecutionContext::default(),
             )
             .is_ok(),
             "Where should be successful"
@@ -1233,23 +3278,285 @@ mod tests {
     /***************************************************************************************************
      * TESTS for evaluate_expression
      * *************************************************************************************************/
+    #[test]
+    fn test_repeated_fields() -> Result<(), String> {
+        let query: Query =
+            "WHERE created > '2020' AND created < '2100' AND 'a' IN tags AND tags != created"
+                .parse()?;
+        assert_eq!(
+            vec!["created", "tags"],
+            repeated_fields(&query.where_expression)
+        );
+
+        let query: Query = "WHERE title == 'x' AND done".parse()?;
+        assert!(repeated_fields(&query.where_expression).is_empty());
+
+        Ok(())
+    }
+
     #[test]
     fn test_evaluate_expression() {
         let expression = vec![
-            ExpressionElement::FieldValue(FieldValue::Number(1.0)),
+            ExpressionElement::FieldValue(FieldValue::Integer(1)),
             ExpressionElement::Operator(Operator::Plus),
-            ExpressionElement::FieldValue(FieldValue::Number(2.0)),
+            ExpressionElement::FieldValue(FieldValue::Integer(2)),
             ExpressionElement::Operator(Operator::Multiply),
-            ExpressionElement::FieldValue(FieldValue::Number(3.0)),
+            ExpressionElement::FieldValue(FieldValue::Integer(3)),
             ExpressionElement::Operator(Operator::Eq),
-            ExpressionElement::FieldValue(FieldValue::Number(7.0)),
+            ExpressionElement::FieldValue(FieldValue::Integer(7)),
         ];
         let pod = Pod::new_hash();
 
         assert_eq!(
             Ok(FieldValue::Bool(true)),
-            evaluate_expression(&expression, &pod)
+            evaluate_expression(
+                &expression,
+                &QuerySpans::default(),
+                &pod,
+                &ExecutionContext::default()
+            )
+        );
+    }
+
+    #[test]
+    fn test_evaluate_expression_unary_minus() -> Result<(), String> {
+        let mut pod = Pod::new_hash();
+        pod.insert("balance".to_string(), Pod::Integer(-150))
+            .unwrap();
+        pod.insert("budget".to_string(), Pod::Integer(100)).unwrap();
+        pod.insert("title".to_string(), Pod::String("x".to_string()))
+            .unwrap();
+        let context = ExecutionContext::default();
+
+        for (condition, expected) in [
+            ("balance < -budget", FieldValue::Bool(true)),
+            ("-(balance + budget)", FieldValue::Integer(50)),
+            ("- -budget", FieldValue::Integer(100)),
+            ("-budget ** 2", FieldValue::Integer(-10000)),
+            ("2 ** -1", FieldValue::Float(0.5)),
+            ("-missing", FieldValue::Null),
+        ] {
+            let query: Query = format!("WHERE {}", condition).parse()?;
+            assert_eq!(
+                Ok(expected),
+                evaluate_expression(&query.where_expression, &query.spans, &pod, &context),
+                "{}",
+                condition
+            );
+        }
+
+        let query: Query = "WHERE a == -title".parse()?;
+        assert_eq!(
+            Err("Can not negate 'x'! — at characters 12..17: `title`".to_string()),
+            evaluate_expression(&query.where_expression, &query.spans, &pod, &context)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_evaluate_expression_not() -> Result<(), String> {
+        let mut pod = Pod::new_hash();
+        pod.insert("done".to_string(), Pod::Boolean(true)).unwrap();
+        pod.insert(
+            "tags".to_string(),
+            Pod::Array(vec![Pod::String("fleeting".to_string())]),
+        )
+        .unwrap();
+        pod.insert("sleep".to_string(), Pod::Integer(7)).unwrap();
+        let context = ExecutionContext::default();
+
+        for (condition, expected) in [
+            ("NOT ('fleeting' IN tags)", FieldValue::Bool(false)),
+            ("not 'permanent' IN tags", FieldValue::Bool(true)),
+            ("NOT done OR sleep > 5", FieldValue::Bool(true)),
+            ("NOT (done OR sleep > 5)", FieldValue::Bool(false)),
+            ("NOT sleep == 7 AND done", FieldValue::Bool(false)),
+            ("!(sleep < 5) AND NOT NOT done", FieldValue::Bool(true)),
+            ("NOT missing", FieldValue::Null),
+        ] {
+            let query: Query = format!("WHERE {}", condition).parse()?;
+            assert_eq!(
+                Ok(expected),
+                evaluate_expression(&query.where_expression, &query.spans, &pod, &context),
+                "{}",
+                condition
+            );
+        }
+
+        let query: Query = "WHERE done AND NOT sleep".parse()?;
+        assert_eq!(
+            Err(
+                "NOT operator expects operand to be bool! — at characters 19..24: `sleep`"
+                    .to_string()
+            ),
+            evaluate_expression(&query.where_expression, &query.spans, &pod, &context)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_evaluate_expression_null() -> Result<(), String> {
+        let mut pod = Pod::new_hash();
+        pod.insert("done".to_string(), Pod::Boolean(true)).unwrap();
+        pod.insert("sleep".to_string(), Pod::Integer(7)).unwrap();
+        let context = ExecutionContext::default();
+
+        for (condition, expected) in [
+            ("missing < 5", FieldValue::Null),
+            ("missing >= 5", FieldValue::Null),
+            ("missing LIKE 'a.*'", FieldValue::Null),
+            ("missing NOT LIKE 'a.*'", FieldValue::Null),
+            ("'a' IN missing", FieldValue::Null),
+            ("'a' NOT IN missing", FieldValue::Null),
+            ("sleep NOT IN (6, 8)", FieldValue::Bool(true)),
+            ("missing NOT IN (NULL, 'todo')", FieldValue::Bool(false)),
+            ("missing + 1 > 5", FieldValue::Null),
+            ("missing < 5 AND done", FieldValue::Null),
+            ("missing < 5 AND NOT done", FieldValue::Bool(false)),
+            ("missing < 5 OR done", FieldValue::Bool(true)),
+            ("missing < 5 OR NOT done", FieldValue::Null),
+            ("NOT (missing < 5) OR sleep == 7", FieldValue::Bool(true)),
+            (
+                "missing == NULL AND missing != 'done'",
+                FieldValue::Bool(true),
+            ),
+            ("missing IN (NULL, 'todo')", FieldValue::Bool(true)),
+        ] {
+            let query: Query = format!("WHERE {}", condition).parse()?;
+            assert_eq!(
+                Ok(expected),
+                evaluate_expression(&query.where_expression, &query.spans, &pod, &context),
+                "{}",
+                condition
+            );
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_evaluate_expression_date_members() -> Result<(), String> {
+        let mut pod = Pod::new_hash();
+        pod.insert(
+            "created".to_string(),
+            Pod::String("2024-03-07T10:20:30".to_string()),
+        )
+        .unwrap();
+        pod.insert("title".to_string(), Pod::String("x".to_string()))
+            .unwrap();
+        let context = ExecutionContext::default();
+
+        for (condition, expected) in [
+            ("DATE(created).year", 2024.0),
+            ("DATE(created).month", 3.0),
+            ("DATE(created).day", 7.0),
+            ("DATE(created).hour", 10.0),
+            ("DATE(created).minute", 20.0),
+            ("DATE(created).second", 30.0),
+            ("DATE(created).weekday", 4.0),
+            ("DATE(created).week", 10.0),
+            ("DATE(created).dayofyear", 67.0),
+            ("-DATEADD('YEAR', 1, created).year + 1", -2024.0),
+        ] {
+            let query: Query = format!("WHERE {}", condition).parse()?;
+            assert_eq!(
+                Ok(FieldValue::Float(expected)),
+                evaluate_expression(&query.where_expression, &query.spans, &pod, &context),
+                "{}",
+                condition
+            );
+        }
+
+        let query: Query = "WHERE a OR DATE(title).year".parse()?;
+        assert!(
+            evaluate_expression(&query.where_expression, &query.spans, &pod, &context)
+                .unwrap_err()
+                .starts_with("Function DATE")
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_evaluate_expression_error_points_at_fragment() -> Result<(), String> {
+        let query: Query = "WHERE a == 1 OR (created AND tags) OR DATE(b)".parse()?;
+        let mut pod = Pod::new_hash();
+        pod.insert("created".to_string(), Pod::String("2024-01-01".to_string()))
+            .unwrap();
+
+        assert_eq!(
+            Err("AND operator expects operands to be bools! — at characters 17..33: `created AND tags`".to_string()),
+            evaluate_expression(
+                &query.where_expression,
+                &query.spans,
+                &pod,
+                &ExecutionContext::default()
+            )
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_execute_where_fails_only_when_all_rows_fail() -> Result<(), String> {
+        let query: Query = "WHERE a AND b".parse()?;
+        let mut pod = Pod::new_hash();
+        pod.insert("a".to_string(), Pod::Boolean(true)).unwrap();
+        pod.insert("b".to_string(), Pod::Boolean(true)).unwrap();
+        let context = ExecutionContext::default();
+
+        let mut not_bool = Pod::new_hash();
+        not_bool
+            .insert("a".to_string(), Pod::String("yes".to_string()))
+            .unwrap();
+
+        // a missing field is NULL, that does not match but is not an error either
+        let mut data = vec![pod.clone(), not_bool.clone(), Pod::new_hash()];
+        execute_where(&query.where_expression, &query.spans, &mut data, &context)?;
+        assert_eq!(vec![pod], data);
+
+        let mut data = vec![not_bool];
+        assert_eq!(
+            Err(
+                "AND operator expects operands to be bools! — at characters 6..13: `a AND b`"
+                    .to_string()
+            ),
+            execute_where(&query.where_expression, &query.spans, &mut data, &context)
         );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_execute_where_non_bool_condition() -> Result<(), String> {
+        let query: Query = "WHERE priority".parse()?;
+        let context = ExecutionContext::default();
+        let mut with_priority = Pod::new_hash();
+        with_priority
+            .insert("priority".to_string(), Pod::Integer(2))
+            .unwrap();
+        let mut done = Pod::new_hash();
+        done.insert("priority".to_string(), Pod::Boolean(true))
+            .unwrap();
+
+        // NULL is false
+        let mut data = vec![done.clone(), Pod::new_hash()];
+        execute_where(&query.where_expression, &query.spans, &mut data, &context)?;
+        assert_eq!(vec![done.clone()], data);
+
+        // any other value is an error, even when other rows are bools
+        let mut data = vec![done, Pod::new_hash(), with_priority];
+        assert_eq!(
+            Err(
+                "WHERE condition must be a bool or NULL, but is '2' — at characters 0..14: `WHERE priority`"
+                    .to_string()
+            ),
+            execute_where(&query.where_expression, &query.spans, &mut data, &context)
+        );
+
+        Ok(())
     }
 
     /***************************************************************************************************
@@ -1260,7 +3567,9 @@ mod tests {
         let mut stack = vec![];
         let mut queue = vec![];
 
-        assert!(evaluate_stack_operator(&mut stack, &mut queue).is_err());
+        assert!(
+            evaluate_stack_operator(&mut stack, &mut queue, &ExecutionContext::default()).is_err()
+        );
         assert_eq!(0, stack.len(), "Stack should stay empty");
         assert_eq!(0, queue.len(), "Queue should stay empty");
     }
@@ -1268,9 +3577,11 @@ mod tests {
     #[test]
     fn test_evaluate_stack_operator_no_operator() {
         let mut stack = vec![ExpressionElement::OpenedBracket];
-        let mut queue = vec![FieldValue::Number(1.0), FieldValue::Number(2.0)];
+        let mut queue = vec![FieldValue::Integer(1), FieldValue::Integer(2)];
 
-        assert!(evaluate_stack_operator(&mut stack, &mut queue).is_err());
+        assert!(
+            evaluate_stack_operator(&mut stack, &mut queue, &ExecutionContext::default()).is_err()
+        );
         assert_eq!(0, stack.len(), "Stack should stay empty");
         assert_eq!(2, queue.len(), "Queue should have 2 elements");
     }
@@ -1281,9 +3592,11 @@ mod tests {
             ExpressionElement::OpenedBracket,
             ExpressionElement::Operator(Operator::Eq),
         ];
-        let mut queue = vec![FieldValue::Number(1.0), FieldValue::Number(2.0)];
+        let mut queue = vec![FieldValue::Integer(1), FieldValue::Integer(2)];
 
-        assert!(evaluate_stack_operator(&mut stack, &mut queue).is_ok());
+        assert!(
+            evaluate_stack_operator(&mut stack, &mut queue, &ExecutionContext::default()).is_ok()
+        );
 
         assert_eq!(1, stack.len(), "Stack should have 1 element");
         assert_eq!(
@@ -1305,7 +3618,9 @@ mod tests {
         let mut stack = vec![ExpressionElement::Operator(Operator::Eq)];
         let mut queue = vec![];
 
-        assert!(evaluate_stack_operator(&mut stack, &mut queue).is_err());
+        assert!(
+            evaluate_stack_operator(&mut stack, &mut queue, &ExecutionContext::default()).is_err()
+        );
         assert_eq!(0, stack.len(), "Stack should stay empty");
         assert_eq!(0, queue.len(), "Queue should be empty");
     }
@@ -1313,9 +3628,11 @@ mod tests {
     #[test]
     fn test_evaluate_stack_operator_one_operand() {
         let mut stack = vec![ExpressionElement::Operator(Operator::Eq)];
-        let mut queue = vec![FieldValue::Number(1.0)];
+        let mut queue = vec![FieldValue::Integer(1)];
 
-        assert!(evaluate_stack_operator(&mut stack, &mut queue).is_err());
+        assert!(
+            evaluate_stack_operator(&mut stack, &mut queue, &ExecutionContext::default()).is_err()
+        );
         assert_eq!(0, stack.len(), "Stack should stay empty");
         assert_eq!(0, queue.len(), "Queue should be empty");
     }
@@ -1330,7 +3647,8 @@ mod tests {
             execute_operation(
                 &Operator::And,
                 &FieldValue::Bool(true),
-                &FieldValue::Bool(true)
+                &FieldValue::Bool(true),
+                &ExecutionContext::default()
             )
         );
 
@@ -1339,7 +3657,8 @@ mod tests {
             execute_operation(
                 &Operator::And,
                 &FieldValue::Bool(true),
-                &FieldValue::Bool(false)
+                &FieldValue::Bool(false),
+                &ExecutionContext::default()
             )
         );
 
@@ -1348,7 +3667,8 @@ mod tests {
             execute_operation(
                 &Operator::And,
                 &FieldValue::Bool(false),
-                &FieldValue::Bool(true)
+                &FieldValue::Bool(true),
+                &ExecutionContext::default()
             )
         );
 
@@ -1357,7 +3677,8 @@ mod tests {
             execute_operation(
                 &Operator::And,
                 &FieldValue::Bool(false),
-                &FieldValue::Bool(false)
+                &FieldValue::Bool(false),
+                &ExecutionContext::default()
             )
         );
     }
@@ -1369,7 +3690,8 @@ mod tests {
             execute_operation(
                 &Operator::Or,
                 &FieldValue::Bool(true),
-                &FieldValue::Bool(true)
+                &FieldValue::Bool(true),
+                &ExecutionContext::default()
             )
         );
 
@@ -1378,7 +3700,8 @@ mod tests {
             execute_operation(
                 &Operator::Or,
                 &FieldValue::Bool(true),
-                &FieldValue::Bool(false)
+                &FieldValue::Bool(false),
+                &ExecutionContext::default()
             )
         );
 
@@ -1387,37 +3710,86 @@ mod tests {
             execute_operation(
                 &Operator::Or,
                 &FieldValue::Bool(false),
-                &FieldValue::Bool(true)
+                &FieldValue::Bool(true),
+                &ExecutionContext::default()
+            )
+        );
+
+        assert_eq!(
+            Ok(FieldValue::Bool(false)),
+            execute_operation(
+                &Operator::Or,
+                &FieldValue::Bool(false),
+                &FieldValue::Bool(false),
+                &ExecutionContext::default()
+            )
+        );
+    }
+
+    #[test]
+    fn test_execute_operation_like() {
+        assert_eq!(
+            Ok(FieldValue::Bool(true)),
+            execute_operation(
+                &Operator::Like,
+                &FieldValue::String("value".to_string()),
+                &FieldValue::String("val.*".to_string()),
+                &ExecutionContext::default()
+            )
+        );
+
+        assert_eq!(
+            Ok(FieldValue::Bool(false)),
+            execute_operation(
+                &Operator::Like,
+                &FieldValue::String("value".to_string()),
+                &FieldValue::String("[val.*".to_string()),
+                &ExecutionContext::default()
             )
         );
+    }
+
+    #[test]
+    fn test_execute_operation_regexp() {
+        let regexp = |op: Operator, value: &str, pattern: &str| {
+            execute_operation(
+                &op,
+                &FieldValue::String(value.to_string()),
+                &FieldValue::String(pattern.to_string()),
+                &ExecutionContext::default(),
+            )
+        };
 
+        assert_eq!(
+            Ok(FieldValue::Bool(true)),
+            regexp(Operator::Regexp, "Draft", "(?i)^draft")
+        );
+        assert_eq!(
+            Ok(FieldValue::Bool(true)),
+            regexp(Operator::Regexp, "Draft", "/^draft/i")
+        );
         assert_eq!(
             Ok(FieldValue::Bool(false)),
-            execute_operation(
-                &Operator::Or,
-                &FieldValue::Bool(false),
-                &FieldValue::Bool(false)
-            )
+            regexp(Operator::Regexp, "Draft", "^draft")
         );
-    }
-
-    #[test]
-    fn test_execute_operation_like() {
         assert_eq!(
             Ok(FieldValue::Bool(true)),
-            execute_operation(
-                &Operator::Like,
-                &FieldValue::String("value".to_string()),
-                &FieldValue::String("val.*".to_string())
-            )
+            regexp(Operator::Regexp, "a/notes/b", "/notes/")
         );
-
         assert_eq!(
-            Ok(FieldValue::Bool(false)),
+            Ok(FieldValue::Bool(true)),
+            regexp(Operator::NotRegexp, "Draft", "^draft")
+        );
+        assert!(regexp(Operator::Regexp, "value", "[val")
+            .unwrap_err()
+            .starts_with("Invalid REGEXP pattern: "));
+        assert_eq!(
+            Ok(FieldValue::Null),
             execute_operation(
-                &Operator::Like,
-                &FieldValue::String("value".to_string()),
-                &FieldValue::String("[val.*".to_string())
+                &Operator::Regexp,
+                &FieldValue::Null,
+                &FieldValue::String("x".to_string()),
+                &ExecutionContext::default()
             )
         );
     }
@@ -1429,7 +3801,8 @@ mod tests {
             execute_operation(
                 &Operator::NotLike,
                 &FieldValue::String("value".to_string()),
-                &FieldValue::String("val.*".to_string())
+                &FieldValue::String("val.*".to_string()),
+                &ExecutionContext::default()
             )
         );
     }
@@ -1442,9 +3815,10 @@ mod tests {
                 &Operator::In,
                 &FieldValue::String("value".to_string()),
                 &FieldValue::List(vec![
-                    FieldValue::Number(1.0),
+                    FieldValue::Integer(1),
                     FieldValue::String("value".to_string())
-                ])
+                ]),
+                &ExecutionContext::default()
             )
         );
 
@@ -1454,9 +3828,10 @@ mod tests {
                 &Operator::In,
                 &FieldValue::String("value".to_string()),
                 &FieldValue::List(vec![
-                    FieldValue::Number(1.0),
+                    FieldValue::Integer(1),
                     FieldValue::String("valu".to_string())
-                ])
+                ]),
+                &ExecutionContext::default()
             )
         );
     }
@@ -1469,6 +3844,7 @@ mod tests {
                 &Operator::In,
                 &FieldValue::String("lu".to_string()),
                 &FieldValue::String("value".to_string()),
+                &ExecutionContext::default()
             )
         );
 
@@ -1478,6 +3854,7 @@ mod tests {
                 &Operator::In,
                 &FieldValue::String("ul".to_string()),
                 &FieldValue::String("value".to_string()),
+                &ExecutionContext::default()
             )
         );
     }
@@ -1485,12 +3862,12 @@ mod tests {
     #[test]
     fn test_execute_operation_lt() {
         let smaller = [
-            FieldValue::Number(1.0),
+            FieldValue::Integer(1),
             FieldValue::String("aaa".to_string()),
             FieldValue::Bool(false),
         ];
         let greater = [
-            FieldValue::Number(2.0),
+            FieldValue::Integer(2),
             FieldValue::String("aab".to_string()),
             FieldValue::Bool(true),
         ];
@@ -1498,17 +3875,17 @@ mod tests {
         for (small, large) in smaller.iter().zip(greater.iter()) {
             assert_eq!(
                 Ok(FieldValue::Bool(true)),
-                execute_operation(&Operator::Lt, small, large,)
+                execute_operation(&Operator::Lt, small, large, &ExecutionContext::default())
             );
 
             assert_eq!(
                 Ok(FieldValue::Bool(false)),
-                execute_operation(&Operator::Lt, large, small,)
+                execute_operation(&Operator::Lt, large, small, &ExecutionContext::default())
             );
 
             assert_eq!(
                 Ok(FieldValue::Bool(false)),
-                execute_operation(&Operator::Lt, small, small,)
+                execute_operation(&Operator::Lt, small, small, &ExecutionContext::default())
             );
         }
     }
@@ -1516,12 +3893,12 @@ mod tests {
     #[test]
     fn test_execute_operation_lte() {
         let smaller = [
-            FieldValue::Number(1.0),
+            FieldValue::Integer(1),
             FieldValue::String("aaa".to_string()),
             FieldValue::Bool(false),
         ];
         let greater = [
-            FieldValue::Number(2.0),
+            FieldValue::Integer(2),
             FieldValue::String("aab".to_string()),
             FieldValue::Bool(true),
         ];
@@ -1529,17 +3906,17 @@ mod tests {
         for (small, large) in smaller.iter().zip(greater.iter()) {
             assert_eq!(
                 Ok(FieldValue::Bool(true)),
-                execute_operation(&Operator::Lte, small, large)
+                execute_operation(&Operator::Lte, small, large, &ExecutionContext::default())
             );
 
             assert_eq!(
                 Ok(FieldValue::Bool(false)),
-                execute_operation(&Operator::Lte, large, small)
+                execute_operation(&Operator::Lte, large, small, &ExecutionContext::default())
             );
 
             assert_eq!(
                 Ok(FieldValue::Bool(true)),
-                execute_operation(&Operator::Lte, small, small)
+                execute_operation(&Operator::Lte, small, small, &ExecutionContext::default())
             );
         }
     }
@@ -1547,12 +3924,12 @@ mod tests {
     #[test]
     fn test_execute_operation_gt() {
         let smaller = [
-            FieldValue::Number(1.0),
+            FieldValue::Integer(1),
             FieldValue::String("aaa".to_string()),
             FieldValue::Bool(false),
         ];
         let greater = [
-            FieldValue::Number(2.0),
+            FieldValue::Integer(2),
             FieldValue::String("aab".to_string()),
             FieldValue::Bool(true),
         ];
@@ -1560,17 +3937,17 @@ mod tests {
         for (small, large) in smaller.iter().zip(greater.iter()) {
             assert_eq!(
                 Ok(FieldValue::Bool(true)),
-                execute_operation(&Operator::Gt, large, small,)
+                execute_operation(&Operator::Gt, large, small, &ExecutionContext::default())
             );
 
             assert_eq!(
                 Ok(FieldValue::Bool(false)),
-                execute_operation(&Operator::Gt, small, large,)
+                execute_operation(&Operator::Gt, small, large, &ExecutionContext::default())
             );
 
             assert_eq!(
                 Ok(FieldValue::Bool(false)),
-                execute_operation(&Operator::Gt, small, small,)
+                execute_operation(&Operator::Gt, small, small, &ExecutionContext::default())
             );
         }
     }
@@ -1578,12 +3955,12 @@ mod tests {
     #[test]
     fn test_execute_operation_gte() {
         let smaller = [
-            FieldValue::Number(1.0),
+            FieldValue::Integer(1),
             FieldValue::String("aaa".to_string()),
             FieldValue::Bool(false),
         ];
         let greater = [
-            FieldValue::Number(2.0),
+            FieldValue::Integer(2),
             FieldValue::String("aab".to_string()),
             FieldValue::Bool(true),
         ];
@@ -1591,17 +3968,17 @@ mod tests {
         for (small, large) in smaller.iter().zip(greater.iter()) {
             assert_eq!(
                 Ok(FieldValue::Bool(true)),
-                execute_operation(&Operator::Gte, large, small,)
+                execute_operation(&Operator::Gte, large, small, &ExecutionContext::default())
             );
 
             assert_eq!(
                 Ok(FieldValue::Bool(false)),
-                execute_operation(&Operator::Gte, small, large,)
+                execute_operation(&Operator::Gte, small, large, &ExecutionContext::default())
             );
 
             assert_eq!(
                 Ok(FieldValue::Bool(true)),
-                execute_operation(&Operator::Gte, small, small,)
+                execute_operation(&Operator::Gte, small, small, &ExecutionContext::default())
             );
         }
     }
@@ -1609,12 +3986,12 @@ mod tests {
     #[test]
     fn test_execute_operation_eq() {
         let elements = [
-            FieldValue::Number(1.0),
+            FieldValue::Integer(1),
             FieldValue::String("value".to_string()),
             FieldValue::Bool(true),
         ];
         let different_elements = [
-            FieldValue::Number(2.0),
+            FieldValue::Integer(2),
             FieldValue::String("different value".to_string()),
             FieldValue::Bool(false),
         ];
@@ -1622,12 +3999,22 @@ mod tests {
         for (el, diff_el) in elements.iter().zip(different_elements.iter()) {
             assert_eq!(
                 Ok(FieldValue::Bool(true)),
-                execute_operation(&Operator::Eq, &el.clone(), &el.clone())
+                execute_operation(
+                    &Operator::Eq,
+                    &el.clone(),
+                    &el.clone(),
+                    &ExecutionContext::default()
+                )
             );
 
             assert_eq!(
                 Ok(FieldValue::Bool(false)),
-                execute_operation(&Operator::Eq, &el.clone(), diff_el)
+                execute_operation(
+                    &Operator::Eq,
+                    &el.clone(),
+                    diff_el,
+                    &ExecutionContext::default()
+                )
             );
         }
     }
@@ -1636,17 +4023,32 @@ mod tests {
     fn test_execute_operation_eq_null() {
         assert_eq!(
             Ok(FieldValue::Bool(true)),
-            execute_operation(&Operator::Eq, &FieldValue::Null, &FieldValue::Null)
+            execute_operation(
+                &Operator::Eq,
+                &FieldValue::Null,
+                &FieldValue::Null,
+                &ExecutionContext::default()
+            )
         );
 
         assert_eq!(
             Ok(FieldValue::Bool(false)),
-            execute_operation(&Operator::Eq, &FieldValue::Null, &FieldValue::Number(1.0))
+            execute_operation(
+                &Operator::Eq,
+                &FieldValue::Null,
+                &FieldValue::Integer(1),
+                &ExecutionContext::default()
+            )
         );
 
         assert_eq!(
             Ok(FieldValue::Bool(false)),
-            execute_operation(&Operator::Eq, &FieldValue::Number(1.0), &FieldValue::Null)
+            execute_operation(
+                &Operator::Eq,
+                &FieldValue::Integer(1),
+                &FieldValue::Null,
+                &ExecutionContext::default()
+            )
         );
     }
 
@@ -1657,13 +4059,14 @@ mod tests {
             execute_operation(
                 &Operator::Eq,
                 &FieldValue::List(vec![
-                    FieldValue::Number(1.0),
+                    FieldValue::Integer(1),
                     FieldValue::String("test".to_string())
                 ]),
                 &FieldValue::List(vec![
-                    FieldValue::Number(1.0),
+                    FieldValue::Integer(1),
                     FieldValue::String("test".to_string())
                 ]),
+                &ExecutionContext::default()
             )
         );
 
@@ -1672,13 +4075,14 @@ mod tests {
             execute_operation(
                 &Operator::Eq,
                 &FieldValue::List(vec![
-                    FieldValue::Number(1.0),
+                    FieldValue::Integer(1),
                     FieldValue::String("test".to_string())
                 ]),
                 &FieldValue::List(vec![
-                    FieldValue::Number(2.0),
+                    FieldValue::Integer(2),
                     FieldValue::String("test".to_string())
                 ]),
+                &ExecutionContext::default()
             )
         );
 
@@ -1687,13 +4091,14 @@ mod tests {
             execute_operation(
                 &Operator::Eq,
                 &FieldValue::List(vec![
-                    FieldValue::Number(1.0),
+                    FieldValue::Integer(1),
                     FieldValue::String("test".to_string())
                 ]),
                 &FieldValue::List(vec![
-                    FieldValue::Number(1.0),
+                    FieldValue::Integer(1),
                     FieldValue::String("bla".to_string())
                 ]),
+                &ExecutionContext::default()
             )
         );
     }
@@ -1701,12 +4106,12 @@ mod tests {
     #[test]
     fn test_execute_operation_neq() {
         let elements = [
-            FieldValue::Number(1.0),
+            FieldValue::Integer(1),
             FieldValue::String("value".to_string()),
             FieldValue::Bool(true),
         ];
         let different_elements = [
-            FieldValue::Number(2.0),
+            FieldValue::Integer(2),
             FieldValue::String("different value".to_string()),
             FieldValue::Bool(false),
         ];
@@ -1714,12 +4119,22 @@ mod tests {
         for (el, diff_el) in elements.iter().zip(different_elements.iter()) {
             assert_eq!(
                 Ok(FieldValue::Bool(false)),
-                execute_operation(&Operator::Neq, &el.clone(), &el.clone())
+                execute_operation(
+                    &Operator::Neq,
+                    &el.clone(),
+                    &el.clone(),
+                    &ExecutionContext::default()
+                )
             );
 
             assert_eq!(
                 Ok(FieldValue::Bool(true)),
-                execute_operation(&Operator::Neq, &el.clone(), diff_el)
+                execute_operation(
+                    &Operator::Neq,
+                    &el.clone(),
+                    diff_el,
+                    &ExecutionContext::default()
+                )
             );
         }
     }
@@ -1727,28 +4142,28 @@ mod tests {
     #[test]
     fn test_execute_operation_plus() {
         let elements = [
-            FieldValue::Number(1.0),
+            FieldValue::Integer(1),
             FieldValue::String("value".to_string()),
             FieldValue::List(vec![
-                FieldValue::Number(1.0),
+                FieldValue::Integer(1),
                 FieldValue::String("value".to_string()),
             ]),
         ];
         let different_elements = [
-            FieldValue::Number(2.0),
+            FieldValue::Integer(2),
             FieldValue::String("different value".to_string()),
             FieldValue::List(vec![
-                FieldValue::Number(2.0),
+                FieldValue::Integer(2),
                 FieldValue::String("different value".to_string()),
             ]),
         ];
         let results = [
-            FieldValue::Number(3.0),
+            FieldValue::Integer(3),
             FieldValue::String("valuedifferent value".to_string()),
             FieldValue::List(vec![
-                FieldValue::Number(1.0),
+                FieldValue::Integer(1),
                 FieldValue::String("value".to_string()),
-                FieldValue::Number(2.0),
+                FieldValue::Integer(2),
                 FieldValue::String("different value".to_string()),
             ]),
         ];
@@ -1760,14 +4175,20 @@ mod tests {
         {
             assert_eq!(
                 Ok(res.clone()),
-                execute_operation(&Operator::Plus, &el.clone(), diff_el)
+                execute_operation(
+                    &Operator::Plus,
+                    &el.clone(),
+                    diff_el,
+                    &ExecutionContext::default()
+                )
             );
         }
 
         assert!(execute_operation(
             &Operator::Plus,
             &FieldValue::Bool(true),
-            &FieldValue::Bool(false)
+            &FieldValue::Bool(false),
+            &ExecutionContext::default()
         )
         .is_err());
     }
@@ -1775,22 +4196,22 @@ mod tests {
     #[test]
     fn test_execute_operation_minus() {
         let elements = [
-            FieldValue::Number(1.0),
+            FieldValue::Integer(1),
             FieldValue::List(vec![
-                FieldValue::Number(1.0),
+                FieldValue::Integer(1),
                 FieldValue::String("value".to_string()),
             ]),
         ];
         let different_elements = [
-            FieldValue::Number(2.0),
+            FieldValue::Integer(2),
             FieldValue::List(vec![
-                FieldValue::Number(2.0),
+                FieldValue::Integer(2),
                 FieldValue::String("value".to_string()),
             ]),
         ];
         let results = [
-            FieldValue::Number(-1.0),
-            FieldValue::List(vec![FieldValue::Number(1.0)]),
+            FieldValue::Integer(-1),
+            FieldValue::List(vec![FieldValue::Integer(1)]),
         ];
 
         for ((el, diff_el), res) in elements
@@ -1800,14 +4221,20 @@ mod tests {
         {
             assert_eq!(
                 Ok(res.clone()),
-                execute_operation(&Operator::Minus, &el.clone(), diff_el)
+                execute_operation(
+                    &Operator::Minus,
+                    &el.clone(),
+                    diff_el,
+                    &ExecutionContext::default()
+                )
             );
         }
 
         assert!(execute_operation(
             &Operator::Minus,
             &FieldValue::Bool(true),
-            &FieldValue::Bool(false)
+            &FieldValue::Bool(false),
+            &ExecutionContext::default()
         )
         .is_err());
 
@@ -1815,6 +4242,7 @@ mod tests {
             &Operator::Minus,
             &FieldValue::String("value".to_string()),
             &FieldValue::String("value".to_string()),
+            &ExecutionContext::default()
         )
         .is_err());
     }
@@ -1822,11 +4250,12 @@ mod tests {
     #[test]
     fn test_execute_operation_multiply() {
         assert_eq!(
-            Ok(FieldValue::Number(2.0)),
+            Ok(FieldValue::Integer(2)),
             execute_operation(
                 &Operator::Multiply,
-                &FieldValue::Number(1.0),
-                &FieldValue::Number(2.0)
+                &FieldValue::Integer(1),
+                &FieldValue::Integer(2),
+                &ExecutionContext::default()
             )
         );
 
@@ -1834,24 +4263,31 @@ mod tests {
             FieldValue::String("value".to_string()),
             FieldValue::Bool(true),
             FieldValue::List(vec![
-                FieldValue::Number(1.0),
+                FieldValue::Integer(1),
                 FieldValue::String("value".to_string()),
             ]),
         ];
 
         for el in elements.iter() {
-            assert!(execute_operation(&Operator::Multiply, &el.clone(), &el.clone()).is_err());
+            assert!(execute_operation(
+                &Operator::Multiply,
+                &el.clone(),
+                &el.clone(),
+                &ExecutionContext::default()
+            )
+            .is_err());
         }
     }
 
     #[test]
     fn test_execute_operation_divide() {
         assert_eq!(
-            Ok(FieldValue::Number(2.5)),
+            Ok(FieldValue::Float(2.5)),
             execute_operation(
                 &Operator::Divide,
-                &FieldValue::Number(5.0),
-                &FieldValue::Number(2.0)
+                &FieldValue::Integer(5),
+                &FieldValue::Integer(2),
+                &ExecutionContext::default()
             )
         );
 
@@ -1859,24 +4295,31 @@ mod tests {
             FieldValue::String("value".to_string()),
             FieldValue::Bool(true),
             FieldValue::List(vec![
-                FieldValue::Number(1.0),
+                FieldValue::Integer(1),
                 FieldValue::String("value".to_string()),
             ]),
         ];
 
         for el in elements.iter() {
-            assert!(execute_operation(&Operator::Divide, &el.clone(), &el.clone()).is_err());
+            assert!(execute_operation(
+                &Operator::Divide,
+                &el.clone(),
+                &el.clone(),
+                &ExecutionContext::default()
+            )
+            .is_err());
         }
     }
 
     #[test]
     fn test_execute_operation_power() {
         assert_eq!(
-            Ok(FieldValue::Number(16.0)),
+            Ok(FieldValue::Integer(16)),
             execute_operation(
                 &Operator::Power,
-                &FieldValue::Number(4.0),
-                &FieldValue::Number(2.0)
+                &FieldValue::Integer(4),
+                &FieldValue::Integer(2),
+                &ExecutionContext::default()
             )
         );
 
@@ -1884,24 +4327,31 @@ mod tests {
             FieldValue::String("value".to_string()),
             FieldValue::Bool(true),
             FieldValue::List(vec![
-                FieldValue::Number(1.0),
+                FieldValue::Integer(1),
                 FieldValue::String("value".to_string()),
             ]),
         ];
 
         for el in elements.iter() {
-            assert!(execute_operation(&Operator::Power, &el.clone(), &el.clone()).is_err());
+            assert!(execute_operation(
+                &Operator::Power,
+                &el.clone(),
+                &el.clone(),
+                &ExecutionContext::default()
+            )
+            .is_err());
         }
     }
 
     #[test]
     fn test_execute_operation_floor_divide() {
         assert_eq!(
-            Ok(FieldValue::Number(2.0)),
+            Ok(FieldValue::Integer(2)),
             execute_operation(
                 &Operator::FloorDivide,
-                &FieldValue::Number(5.0),
-                &FieldValue::Number(2.0)
+                &FieldValue::Integer(5),
+                &FieldValue::Integer(2),
+                &ExecutionContext::default()
             )
         );
 
@@ -1909,16 +4359,80 @@ mod tests {
             FieldValue::String("value".to_string()),
             FieldValue::Bool(true),
             FieldValue::List(vec![
-                FieldValue::Number(1.0),
+                FieldValue::Integer(1),
                 FieldValue::String("value".to_string()),
             ]),
         ];
 
         for el in elements.iter() {
-            assert!(execute_operation(&Operator::FloorDivide, &el.clone(), &el.clone()).is_err());
+            assert!(execute_operation(
+                &Operator::FloorDivide,
+                &el.clone(),
+                &el.clone(),
+                &ExecutionContext::default()
+            )
+            .is_err());
         }
     }
 
+    #[test]
+    fn test_execute_operation_integer_and_float() {
+        let context = ExecutionContext::default();
+        let operation = |op: Operator, left: FieldValue, right: FieldValue| {
+            format!("{:?}", execute_operation(&op, &left, &right, &context))
+        };
+        let (int, float) = (FieldValue::Integer, FieldValue::Float);
+
+        // Integers stay integers, and keep their precision
+        assert_eq!(
+            "Ok(Integer(9007199254740993))",
+            operation(Operator::Plus, int(9007199254740992), int(1))
+        );
+        assert_eq!(
+            "Ok(Integer(6))",
+            operation(Operator::Multiply, int(2), int(3))
+        );
+        assert_eq!("Ok(Integer(8))", operation(Operator::Power, int(2), int(3)));
+        assert_eq!(
+            "Ok(Integer(-3))",
+            operation(Operator::FloorDivide, int(-5), int(2))
+        );
+        assert_eq!(
+            "Ok(Integer(-3))",
+            operation(Operator::FloorDivide, int(5), int(-2))
+        );
+        // Division, floats and overflows are floats
+        assert_eq!(
+            "Ok(Float(3.5))",
+            operation(Operator::Divide, int(7), int(2))
+        );
+        assert_eq!(
+            "Ok(Float(2.0))",
+            operation(Operator::Divide, int(4), int(2))
+        );
+        assert_eq!(
+            "Ok(Float(2.5))",
+            operation(Operator::Plus, int(2), float(0.5))
+        );
+        assert_eq!(
+            "Ok(Float(0.5))",
+            operation(Operator::Power, int(2), int(-1))
+        );
+        assert_eq!(
+            "Ok(Float(1.8446744073709552e19))",
+            operation(Operator::Multiply, int(i64::MAX), int(2))
+        );
+        // and compare with integers by their value
+        assert_eq!(
+            "Ok(Bool(true))",
+            operation(Operator::Eq, int(2), float(2.0))
+        );
+        assert_eq!(
+            "Ok(Bool(true))",
+            operation(Operator::Lt, int(2), float(2.5))
+        );
+    }
+
     /***************************************************************************************************
      * TESTS for get_field_value
      * *************************************************************************************************/
@@ -1929,10 +4443,7 @@ mod tests {
         let value = 1;
         let _ = pod.insert(key.clone(), value);
 
-        assert_eq!(
-            FieldValue::Number(value as f64),
-            get_field_value(&key, &pod)
-        );
+        assert_eq!(FieldValue::Float(value as f64), get_field_value(&key, &pod));
 
         assert_eq!(FieldValue::Null, get_field_value("b", &pod));
     }
@@ -1961,6 +4472,36 @@ mod tests {
         assert_eq!(None, pod.nested_get("a.c"));
     }
 
+    #[test]
+    fn test_get_nested_pod_list_index() {
+        let mut project = Pod::new_hash();
+        let _ = project.insert("status".to_string(), "done".to_string());
+        let mut pod = Pod::new_hash();
+        let _ = pod.insert(
+            "tags".to_string(),
+            vec![Pod::from("a".to_string()), Pod::from("b".to_string())],
+        );
+        let _ = pod.insert("projects".to_string(), vec![project]);
+        let _ = pod.insert(
+            "matrix".to_string(),
+            vec![Pod::Array(vec![Pod::Integer(7)])],
+        );
+
+        assert_eq!(Some(&Pod::from("a".to_string())), pod.nested_get("tags[0]"));
+        assert_eq!(
+            Some(&Pod::from("b".to_string())),
+            pod.nested_get("tags[-1]")
+        );
+        assert_eq!(
+            Some(&Pod::from("done".to_string())),
+            pod.nested_get("projects[0].status")
+        );
+        assert_eq!(Some(&Pod::Integer(7)), pod.nested_get("matrix[0][0]"));
+        assert_eq!(None, pod.nested_get("tags[2]"));
+        assert_eq!(None, pod.nested_get("tags[-3]"));
+        assert_eq!(None, pod.nested_get("projects[0][0]"));
+    }
+
     /***************************************************************************************************
      * TESTS for pod_array_to_field_value
      * *************************************************************************************************/
@@ -1974,16 +4515,16 @@ mod tests {
 
         assert_eq!(
             FieldValue::List(vec![
-                FieldValue::Number(value1 as f64),
-                FieldValue::Number(value2 as f64)
+                FieldValue::Float(value1 as f64),
+                FieldValue::Float(value2 as f64)
             ]),
             pod_array_to_field_value(&pod.as_vec().unwrap())
         );
 
         assert_ne!(
             FieldValue::List(vec![
-                FieldValue::Number(value1 as f64),
-                FieldValue::Number(value1 as f64)
+                FieldValue::Float(value1 as f64),
+                FieldValue::Float(value1 as f64)
             ]),
             pod_array_to_field_value(&pod.as_vec().unwrap())
         );
@@ -2019,8 +4560,8 @@ mod tests {
                         2,
                         "First item should be a list with 2 elements"
                     );
-                    assert_eq!(inner_list[0], FieldValue::Number(value1 as f64));
-                    assert_eq!(inner_list[1], FieldValue::Number(value2 as f64));
+                    assert_eq!(inner_list[0], FieldValue::Float(value1 as f64));
+                    assert_eq!(inner_list[1], FieldValue::Float(value2 as f64));
                 } else {
                     panic!("First item should be a list");
                 }
@@ -2094,6 +4635,19 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_pod_hash_to_field_value_keeps_key_order() {
+        let mut pod = Pod::new_hash();
+        let _ = pod.insert("zeta".to_string(), Pod::Integer(1));
+        let _ = pod.insert("alpha".to_string(), Pod::Integer(2));
+        let _ = pod.insert("mid".to_string(), Pod::Integer(3));
+
+        assert_eq!(
+            FieldValue::String(r#"{"zeta":1,"alpha":2,"mid":3}"#.to_string()),
+            pod_hash_to_field_value(&pod.as_hashmap().unwrap())
+        );
+    }
+
     /***************************************************************************************************
      * TESTS for execute_function
      * *************************************************************************************************/
@@ -2110,7 +4664,7 @@ mod tests {
 
         assert_eq!(
             Ok(FieldValue::String("2024-12-30T00:00:00".to_string())),
-            execute_function(&func, &pod)
+            execute_function(&func, &pod, &ExecutionContext::default())
         );
 
         assert!(execute_function(
@@ -2118,11 +4672,41 @@ mod tests {
                 name: "UNKNOWN".to_string(),
                 args: vec![],
             },
-            &pod
+            &pod,
+            &ExecutionContext::default()
         )
         .is_err());
     }
 
+    #[test]
+    fn test_execute_function_user_function() {
+        let mut pod = Pod::new_hash();
+        let _ = pod.insert("title".to_string(), Pod::String("My Note".to_string()));
+
+        let mut functions: HashMap<String, Arc<UserFunction>> = HashMap::new();
+        functions.insert(
+            "LOWER".to_string(),
+            Arc::new(|args: &[FieldValue]| match args {
+                [FieldValue::String(s)] => Ok(FieldValue::String(s.to_lowercase())),
+                _ => Err("LOWER expects one string!".to_string()),
+            }),
+        );
+        let context = ExecutionContext {
+            functions: Arc::new(functions),
+            ..ExecutionContext::default()
+        };
+
+        let func = Function {
+            name: "lower".to_string(),
+            args: vec![FunctionArg::FieldName("title".to_string())],
+        };
+        assert_eq!(
+            Ok(FieldValue::String("my note".to_string())),
+            execute_function(&func, &pod, &context)
+        );
+        assert!(execute_function(&func, &pod, &ExecutionContext::default()).is_err());
+    }
+
     /***************************************************************************************************
      * TESTS for execute_function_date_add
      * *************************************************************************************************/
@@ -2134,14 +4718,14 @@ mod tests {
             name: "DATEADD".to_string(),
             args: vec![
                 FunctionArg::FieldValue(FieldValue::String("YEAR".to_string())),
-                FunctionArg::FieldValue(FieldValue::Number(1.0)),
+                FunctionArg::FieldValue(FieldValue::Integer(1)),
                 FunctionArg::FieldValue(FieldValue::String("2024-12-30".to_string())),
             ],
         };
 
         assert_eq!(
             Ok(FieldValue::String("2025-12-30T00:00:00".to_string())),
-            execute_function_date_add(&func, &pod)
+            execute_function_date_add(&func, &pod, &ExecutionContext::default())
         );
     }
 
@@ -2163,7 +4747,7 @@ mod tests {
 
         assert_eq!(
             Ok(FieldValue::String("2025-12-30T00:00:00".to_string())),
-            execute_function_date_add(&func, &pod)
+            execute_function_date_add(&func, &pod, &ExecutionContext::default())
         );
     }
 
@@ -2187,7 +4771,7 @@ mod tests {
 
         assert_eq!(
             Ok(FieldValue::String("2025-12-30T00:00:00".to_string())),
-            execute_function_date_add(&func, &pod)
+            execute_function_date_add(&func, &pod, &ExecutionContext::default())
         );
     }
 
@@ -2197,13 +4781,13 @@ mod tests {
         let func = Function {
             name: "DATEADD".to_string(),
             args: vec![
-                FunctionArg::FieldValue(FieldValue::Number(1.0)),
-                FunctionArg::FieldValue(FieldValue::Number(1.0)),
+                FunctionArg::FieldValue(FieldValue::Integer(1)),
+                FunctionArg::FieldValue(FieldValue::Integer(1)),
                 FunctionArg::FieldValue(FieldValue::String("2024-12-30".to_string())),
             ],
         };
 
-        assert!(execute_function_date_add(&func, &pod).is_err());
+        assert!(execute_function_date_add(&func, &pod, &ExecutionContext::default()).is_err());
     }
 
     #[test]
@@ -2213,16 +4797,402 @@ mod tests {
             name: "DATEADD".to_string(),
             args: vec![
                 FunctionArg::FieldValue(FieldValue::String("INVALID".to_string())),
-                FunctionArg::FieldValue(FieldValue::Number(1.0)),
+                FunctionArg::FieldValue(FieldValue::Integer(1)),
                 FunctionArg::FieldValue(FieldValue::String("2024-12-30".to_string())),
             ],
         };
-        assert!(execute_function_date_add(&func, &pod).is_err());
+        assert!(execute_function_date_add(&func, &pod, &ExecutionContext::default()).is_err());
     }
 
     /***************************************************************************************************
      * TESTS for execute_function_date
      * *************************************************************************************************/
+    #[test]
+    fn test_execute_function_date_trunc() {
+        let mut pod = Pod::new_hash();
+        let _ = pod.insert(
+            "created".to_string(),
+            Pod::String("2024-08-15T13:45:00".to_string()),
+        );
+        let date_trunc = |unit: &str| {
+            let func = Function::new(
+                "DATE_TRUNC".to_string(),
+                vec![
+                    FunctionArg::FieldValue(FieldValue::String(unit.to_string())),
+                    FunctionArg::FieldName("created".to_string()),
+                ],
+            );
+            execute_function_date_trunc(&func, &pod, &ExecutionContext::default())
+        };
+        let date = |date: &str| Ok(FieldValue::String(date.to_string()));
+
+        assert_eq!(date("2024-01-01T00:00:00"), date_trunc("year"));
+        assert_eq!(date("2024-07-01T00:00:00"), date_trunc("quarter"));
+        assert_eq!(date("2024-08-01T00:00:00"), date_trunc("MONTH"));
+        // 2024-08-15 is a Thursday
+        assert_eq!(date("2024-08-12T00:00:00"), date_trunc("week"));
+        assert_eq!(date("2024-08-15T00:00:00"), date_trunc("day"));
+        assert_eq!(date("2024-08-15T13:00:00"), date_trunc("hour"));
+        assert_eq!(
+            Err("Function DATE_TRUNC expects a unit (year, quarter, month, week, day, hour), but found: 'weeks', did you mean 'week'?".to_string()),
+            date_trunc("weeks")
+        );
+
+        let func = Function::new(
+            "DATE_TRUNC".to_string(),
+            vec![
+                FunctionArg::FieldValue(FieldValue::String("week".to_string())),
+                FunctionArg::FieldName("missing".to_string()),
+            ],
+        );
+        assert_eq!(
+            Ok(FieldValue::Null),
+            execute_function_date_trunc(&func, &pod, &ExecutionContext::default())
+        );
+    }
+
+    #[test]
+    fn test_execute_function_duration() {
+        let duration = |text: &str| {
+            let func = Function::new(
+                "DURATION".to_string(),
+                vec![FunctionArg::FieldValue(FieldValue::String(
+                    text.to_string(),
+                ))],
+            );
+            execute_function_duration(&func, &Pod::new_hash(), &ExecutionContext::default())
+        };
+
+        assert_eq!(Ok(FieldValue::Duration(3 * 86400)), duration("3d"));
+        assert_eq!(
+            Ok(FieldValue::Duration(
+                604800 + 2 * 86400 + 4 * 3600 + 30 * 60
+            )),
+            duration("1w 2d 4h 30m")
+        );
+        assert_eq!(Ok(FieldValue::Duration(-5400)), duration("-90 Minutes"));
+        assert_eq!(Ok(FieldValue::Duration(36 * 3600)), duration("1.5days"));
+        for invalid in ["", "-", "3", "3 fortnights", "d3"] {
+            assert!(duration(invalid).is_err(), "{:?}", invalid);
+        }
+
+        assert_eq!("1d 12h", FieldValue::Duration(36 * 3600).to_string());
+        assert_eq!("-1m 5s", FieldValue::Duration(-65).to_string());
+        assert_eq!("0s", FieldValue::Duration(0).to_string());
+        assert_eq!(
+            "DURATION('3d')",
+            FieldValue::Duration(3 * 86400).to_query_string()
+        );
+    }
+
+    #[test]
+    fn test_execute_date_arithmetic() {
+        let context = ExecutionContext::default();
+        let date = |date: &str| FieldValue::String(date.to_string());
+        let days = |days: i64| FieldValue::Duration(days * 86400);
+        let operation = |op: Operator, left: &FieldValue, right: &FieldValue| {
+            execute_operation(&op, left, right, &context)
+        };
+
+        assert_eq!(
+            Ok(days(3)),
+            operation(Operator::Minus, &date("2024-03-04"), &date("2024-03-01"))
+        );
+        assert_eq!(
+            Ok(FieldValue::Duration(-(86400 + 3600))),
+            operation(
+                Operator::Minus,
+                &date("2024-02-29"),
+                &date("2024-03-01T01:00:00")
+            )
+        );
+        assert_eq!(
+            Ok(date("2024-03-01T00:00:00")),
+            operation(Operator::Plus, &date("2024-02-28"), &days(2))
+        );
+        assert_eq!(
+            Ok(date("2024-02-28T00:00:00")),
+            operation(Operator::Minus, &date("2024-03-01"), &days(2))
+        );
+        assert_eq!(
+            Ok(date("2024-03-03T00:00:00")),
+            operation(Operator::Plus, &days(2), &date("2024-03-01"))
+        );
+        assert_eq!(
+            Ok(FieldValue::Bool(true)),
+            operation(Operator::Lt, &days(2), &days(3))
+        );
+        assert_eq!(Ok(days(5)), operation(Operator::Plus, &days(2), &days(3)));
+        assert_eq!(
+            Ok(days(6)),
+            operation(Operator::Multiply, &FieldValue::Integer(3), &days(2))
+        );
+        assert_eq!(
+            Ok(FieldValue::Float(1.5)),
+            operation(Operator::Divide, &days(3), &days(2))
+        );
+        assert_eq!(Ok(days(-2)), days(2).negate());
+        // Strings that are not dates are still not numbers
+        assert!(operation(Operator::Minus, &date("soon"), &date("2024-03-01")).is_err());
+        assert!(operation(Operator::Plus, &date("soon"), &days(1)).is_err());
+        assert!(operation(Operator::Minus, &days(1), &date("2024-03-01")).is_err());
+    }
+
+    #[test]
+    fn test_execute_quantified() -> Result<(), String> {
+        let context = ExecutionContext::default();
+        let mut pod = Pod::new_hash();
+        let _ = pod.insert(
+            "tags".to_string(),
+            Pod::Array(vec![
+                Pod::String("work/krafna".to_string()),
+                Pod::String("home".to_string()),
+            ]),
+        );
+        let _ = pod.insert(
+            "scores".to_string(),
+            Pod::Array(vec![Pod::Integer(4), Pod::Float(5.5)]),
+        );
+        let _ = pod.insert("status".to_string(), Pod::String("done".to_string()));
+        let execute = |condition: &str| -> Result<FieldValue, String> {
+            let query: Query = format!("SELECT a FROM F('x') WHERE {}", condition).parse()?;
+            evaluate_expression(&query.where_expression, &query.spans, &pod, &context)
+        };
+
+        for (condition, expected) in [
+            ("ANY(tags) LIKE 'work/.*'", true),
+            ("ALL(tags) LIKE 'work/.*'", false),
+            ("ALL(tags) NOT LIKE '^school'", true),
+            ("ALL(scores) > 3", true),
+            ("ALL(scores) > 4", false),
+            ("ANY(scores) == 4", true),
+            ("ANY(scores + tags) == 'home'", true),
+            ("ANY(tags) IN ('home', 'garden') AND ALL(scores) < 10", true),
+            // A value that is not a list is a list of one
+            ("ANY(status) == 'done'", true),
+            ("ALL(status) != 'done'", false),
+        ] {
+            assert_eq!(
+                Ok(FieldValue::Bool(expected)),
+                execute(condition),
+                "{}",
+                condition
+            );
+        }
+        // and a missing field is NULL
+        assert_eq!(Ok(FieldValue::Null), execute("ANY(missing) == 'done'"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_execute_function_if() -> Result<(), String> {
+        let context = ExecutionContext::default();
+        let mut pod = Pod::new_hash();
+        let _ = pod.insert("status".to_string(), Pod::String("done".to_string()));
+        let _ = pod.insert("words".to_string(), Pod::Integer(120));
+        let execute = |query: &str| -> Result<FieldValue, String> {
+            let query: Query = format!("SELECT a FROM F('x') WHERE {}", query).parse()?;
+            match query.where_expression.as_slice() {
+                [ExpressionElement::Function(func)] => execute_function(func, &pod, &context),
+                _ => panic!("Expected a function, got {:?}", query.where_expression),
+            }
+        };
+
+        assert_eq!(
+            Ok(FieldValue::String("yes".to_string())),
+            execute("IF(status == 'done', 'yes', 'no')")
+        );
+        assert_eq!(
+            Ok(FieldValue::Integer(2)),
+            execute("IF(words > 500, words // 250 + 1, 2)")
+        );
+        // A missing field is NULL, so the else, and only the returned argument is evaluated
+        assert_eq!(
+            Ok(FieldValue::Float(60.0)),
+            execute("IF(missing, status * 2, words / 2)")
+        );
+        assert_eq!(
+            Ok(FieldValue::Bool(true)),
+            execute("IF(NOT (status IN ('done', 'dropped')), false, true)")
+        );
+        assert_eq!(
+            Err("Function IF expects its condition to be a bool, but found: 'done'".to_string()),
+            execute("IF(status, 1, 2)")
+        );
+        assert_eq!(
+            Err("Function IF expects 3 arguments, but found 2!".to_string()),
+            execute("IF(true, 1)")
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_execute_function_cast() -> Result<(), String> {
+        let context = ExecutionContext::default();
+        let mut pod = Pod::new_hash();
+        let _ = pod.insert("year".to_string(), Pod::String(" 2024 ".to_string()));
+        let _ = pod.insert("rating".to_string(), Pod::String("4.5".to_string()));
+        let _ = pod.insert("done".to_string(), Pod::String("Yes".to_string()));
+        let _ = pod.insert(
+            "published".to_string(),
+            Pod::String("15.03.2024".to_string()),
+        );
+        let execute = |query: &str| -> Result<FieldValue, String> {
+            let query: Query = format!("SELECT a FROM F('x') WHERE {}", query).parse()?;
+            match query.where_expression.as_slice() {
+                [ExpressionElement::Function(func)] => execute_function(func, &pod, &context),
+                _ => panic!("Expected a function, got {:?}", query.where_expression),
+            }
+        };
+        let string = |text: &str| Ok(FieldValue::String(text.to_string()));
+
+        for (query, expected) in [
+            ("TO_NUMBER(year)", Ok(FieldValue::Integer(2024))),
+            ("TO_NUMBER(rating)", Ok(FieldValue::Float(4.5))),
+            ("TO_NUMBER(7)", Ok(FieldValue::Integer(7))),
+            ("TO_NUMBER(true)", Ok(FieldValue::Integer(1))),
+            ("TO_NUMBER('seven')", Ok(FieldValue::Null)),
+            ("TO_NUMBER(missing)", Ok(FieldValue::Null)),
+            ("TO_STRING(2024)", string("2024")),
+            ("TO_STRING(2.5)", string("2.5")),
+            ("TO_STRING(DURATION('1d 2h'))", string("1d 2h")),
+            ("TO_STRING(missing)", Ok(FieldValue::Null)),
+            ("TO_BOOL(done)", Ok(FieldValue::Bool(true))),
+            ("TO_BOOL('off')", Ok(FieldValue::Bool(false))),
+            ("TO_BOOL(0)", Ok(FieldValue::Bool(false))),
+            ("TO_BOOL('maybe')", Ok(FieldValue::Null)),
+            (
+                "TO_DATE(published, '%d.%m.%Y')",
+                string("2024-03-15T00:00:00"),
+            ),
+            ("TO_DATE('2024-03-15')", string("2024-03-15T00:00:00")),
+            ("TO_DATE(published)", Ok(FieldValue::Null)),
+            (
+                "TO_NUMBER(year, 10)",
+                Err("Function TO_NUMBER expects 1 argument, but found 2!".to_string()),
+            ),
+        ] {
+            assert_eq!(expected, execute(query), "{}", query);
+        }
+        // a number is an integer or float, not only equal to it
+        assert!(matches!(
+            execute("TO_NUMBER('2.0')")?,
+            FieldValue::Float(number) if number == 2.0
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_execute_function_random() -> Result<(), String> {
+        let rows: Vec<Pod> = (0..20)
+            .map(|n| {
+                let mut pod = Pod::new_hash();
+                let _ = pod.insert("n".to_string(), Pod::Integer(n));
+                pod
+            })
+            .collect();
+        let random = |query: &str, pod: &Pod, context: &ExecutionContext| {
+            let query: Query = format!("SELECT a FROM F('x') WHERE {}", query).parse()?;
+            match query.where_expression.as_slice() {
+                [ExpressionElement::Function(func)] => execute_function(func, pod, context),
+                _ => panic!("Expected a function, got {:?}", query.where_expression),
+            }
+        };
+        let numbers = |query: &str, context: &ExecutionContext| {
+            rows.iter()
+                .map(|pod| random(query, pod, context))
+                .collect::<Result<Vec<FieldValue>, String>>()
+        };
+        let context = ExecutionContext::default();
+        let other_context = ExecutionContext::default();
+
+        // the same for a row during a query, so rows can be sorted by it
+        let first = numbers("RANDOM()", &context)?;
+        assert_eq!(first, numbers("random()", &context)?);
+        assert!(first
+            .iter()
+            .all(|number| matches!(number, FieldValue::Float(n) if (0.0..1.0).contains(n))));
+        assert!(first.windows(2).any(|pair| pair[0] > pair[1]));
+        assert_ne!(first, numbers("RANDOM()", &other_context)?);
+
+        // a seed gives the same numbers in every query
+        let seeded = numbers("RANDOM('2024-03-15')", &context)?;
+        assert_eq!(seeded, numbers("RANDOM('2024-03-15')", &other_context)?);
+        assert_ne!(seeded, numbers("RANDOM('2024-03-16')", &context)?);
+
+        assert_eq!(
+            Err("Function RANDOM expects 0 or 1 arguments, but found 2!".to_string()),
+            random("RANDOM(1, 2)", &rows[0], &context)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_execute_relative_dates() -> Result<(), String> {
+        let context = ExecutionContext::default();
+        let date = |date: &str| FieldValue::String(date.to_string());
+        let operation = |op: Operator, left: &FieldValue, right: &FieldValue| {
+            execute_operation(&op, left, right, &context)
+        };
+
+        // Numbers are days
+        assert_eq!(
+            Ok(date("2024-02-23T00:00:00")),
+            operation(
+                Operator::Minus,
+                &date("2024-03-01"),
+                &FieldValue::Integer(7)
+            )
+        );
+        assert_eq!(
+            Ok(date("2024-03-01T12:00:00")),
+            operation(Operator::Plus, &FieldValue::Float(0.5), &date("2024-03-01"))
+        );
+        // Dates in different formats compare as dates
+        for (op, expected) in [
+            (Operator::Lt, false),
+            (Operator::Lte, true),
+            (Operator::Gte, true),
+        ] {
+            assert_eq!(
+                Ok(FieldValue::Bool(expected)),
+                operation(op, &date("2024-03-01"), &date("2024-03-01T00:00:00"))
+            );
+        }
+        assert_eq!(
+            Ok(FieldValue::Bool(true)),
+            operation(
+                Operator::Gt,
+                &date("2024-03-01T09:00:00.5+00:00"),
+                &date("2024-03-01")
+            )
+        );
+        assert_eq!(
+            Ok(FieldValue::Bool(true)),
+            operation(Operator::Lt, &date("10"), &date("9"))
+        );
+
+        let today = execute_function(
+            &Function::new("TODAY".to_string(), Vec::new()),
+            &Pod::new_hash(),
+            &context,
+        )?;
+        let last_days = Function::new(
+            "LAST_DAYS".to_string(),
+            vec![FunctionArg::FieldValue(FieldValue::Integer(7))],
+        );
+        assert_eq!(
+            operation(Operator::Minus, &today, &FieldValue::Integer(7)),
+            execute_function(&last_days, &Pod::new_hash(), &context)
+        );
+
+        Ok(())
+    }
+
     #[test]
     fn test_execute_function_date() {
         let pod = Pod::new_hash();
@@ -2236,7 +5206,7 @@ mod tests {
 
         assert_eq!(
             Ok(FieldValue::String("2024-12-30T00:00:00".to_string())),
-            execute_function_date(&func, &pod)
+            execute_function_date(&func, &pod, &ExecutionContext::default())
         );
     }
 
@@ -2252,7 +5222,7 @@ mod tests {
 
         assert_eq!(
             Ok(FieldValue::String("2024-12-30T00:00:00".to_string())),
-            execute_function_date(&func, &pod)
+            execute_function_date(&func, &pod, &ExecutionContext::default())
         );
     }
 
@@ -2272,8 +5242,65 @@ mod tests {
 
         assert_eq!(
             Ok(FieldValue::String("2024-12-30T00:00:00".to_string())),
-            execute_function_date(&func, &pod)
+            execute_function_date(&func, &pod, &ExecutionContext::default())
+        );
+    }
+
+    #[test]
+    fn test_execute_function_date_expression_args() -> Result<(), String> {
+        let context = ExecutionContext::default();
+        let mut file = Pod::new_hash();
+        let _ = file.insert("created".to_string(), Pod::String("2024-12-30".to_string()));
+        let mut pod = Pod::new_hash();
+        let _ = pod.insert("file".to_string(), file);
+        let _ = pod.insert("priority".to_string(), Pod::Integer(2));
+        let execute = |query: &str| -> Result<FieldValue, String> {
+            let query: Query = format!("SELECT a FROM F('x') WHERE {}", query).parse()?;
+            match query.where_expression.as_slice() {
+                [ExpressionElement::Function(func)] => execute_function(func, &pod, &context),
+                _ => panic!("Expected a function, got {:?}", query.where_expression),
+            }
+        };
+        let date =
+            |date: NaiveDateTime| Ok(FieldValue::String(date.format(DATE_FORMAT).to_string()));
+        let today = Local::now().date_naive().and_time(NaiveTime::MIN);
+
+        assert_eq!(date(today), execute("DATE(TODAY())"));
+        assert_eq!(date(today), execute("DATE(TODAY(), '%Y-%m-%dT%H:%M:%S')"));
+        assert_eq!(
+            Ok(FieldValue::String("2024-12-30T00:00:00".to_string())),
+            execute("DATE(IF(priority > 1, file.created, NULL))")
+        );
+        assert_eq!(
+            date(today + TimeDelta::days(1)),
+            execute("DATEADD('day', 1, TODAY())")
+        );
+        assert_eq!(
+            Ok(FieldValue::String("2025-01-02T00:00:00".to_string())),
+            execute("DATEADD('day', priority + 1, file.created)")
+        );
+        assert_eq!(
+            Ok(FieldValue::String("2025-01-30T00:00:00".to_string())),
+            execute("DATEADD(IF(priority > 1, 'month', 'year'), 1, DATE(file.created))")
+        );
+        assert_eq!(
+            Err("Function DATE expects first argument to be a date, but found: 3".to_string()),
+            execute("DATE(priority + 1)")
+        );
+        assert_eq!(
+            Err(
+                "Function DATEADD expects second argument to be a number, but found: '2024-12-30'"
+                    .to_string()
+            ),
+            execute("DATEADD('day', file.created, TODAY())")
+        );
+        assert_eq!(
+            Err(
+                "Function DATEADD expects third argument to be a date, but found: NULL".to_string()
+            ),
+            execute("DATEADD('day', 1, file.missing)")
         );
+        Ok(())
     }
 
     /***************************************************************************************************
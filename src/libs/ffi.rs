@@ -0,0 +1,207 @@
+//! C interface, see `include/krafna.h`. Every string returned by krafna has to be released with
+//! [`krafna_free_string`].
+use std::cell::RefCell;
+use std::error::Error;
+use std::ffi::{c_char, CStr, CString};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+
+use crate::libs::data_fetcher::{fetch_data, DataFetcher};
+use crate::libs::executor::{execute_query_with_fetcher, ExecutionContext};
+use crate::libs::parser::{FieldValue, Function, FunctionArg};
+use crate::libs::serializer::pods_to_json;
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// Executes `query` and returns the result as a JSON array of objects, or NULL on error (see
+/// [`krafna_last_error`]). If `dir` is not NULL, it overrides FROM with `FRONTMATTER_DATA(dir)`.
+///
+/// # Safety
+///
+/// `query` has to be a valid NUL terminated string, `dir` has to be NULL or a valid NUL
+/// terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn krafna_execute_query(
+    query: *const c_char,
+    dir: *const c_char,
+) -> *mut c_char {
+    execute_query_with(query, dir, &fetch_data)
+}
+
+unsafe fn execute_query_with(
+    query: *const c_char,
+    dir: *const c_char,
+    fetch: &DataFetcher,
+) -> *mut c_char {
+    LAST_ERROR.with(|last_error| *last_error.borrow_mut() = None);
+
+    // Unwinding into the caller's code aborts it, a panic is reported like any other error
+    let result = panic::catch_unwind(AssertUnwindSafe(|| execute(query, dir, fetch)))
+        .unwrap_or_else(|panic| {
+            let message = panic
+                .downcast_ref::<&str>()
+                .map(|message| message.to_string())
+                .or_else(|| panic.downcast_ref::<String>().cloned())
+                .unwrap_or_default();
+            Err(format!("krafna panicked: {}", message).into())
+        });
+    match result {
+        Ok(json) => into_c_string(json),
+        Err(error) => {
+            LAST_ERROR.with(|last_error| *last_error.borrow_mut() = Some(error.to_string()));
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Error message of the last failed [`krafna_execute_query`] on this thread, or NULL if it
+/// succeeded.
+#[no_mangle]
+pub extern "C" fn krafna_last_error() -> *mut c_char {
+    LAST_ERROR.with(|last_error| match last_error.borrow().as_ref() {
+        Some(error) => into_c_string(error.clone()),
+        None => ptr::null_mut(),
+    })
+}
+
+/// Releases a string returned by krafna. NULL is ignored.
+///
+/// # Safety
+///
+/// `s` has to be NULL or a pointer returned by krafna that was not freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn krafna_free_string(s: *mut c_char) {
+    if !s.is_null() {
+        drop(CString::from_raw(s));
+    }
+}
+
+unsafe fn execute(
+    query: *const c_char,
+    dir: *const c_char,
+    fetch: &DataFetcher,
+) -> Result<String, Box<dyn Error>> {
+    if query.is_null() {
+        return Err("Query is NULL!".into());
+    }
+    let query = CStr::from_ptr(query).to_str()?;
+
+    let mut from = None;
+    if !dir.is_null() {
+        let dir = CStr::from_ptr(dir).to_str()?;
+        let function = Function {
+            name: "FRONTMATTER_DATA".to_string(),
+            args: vec![FunctionArg::FieldValue(FieldValue::String(dir.to_string()))],
+        };
+        from = Some(function.to_string());
+    }
+
+    let (fields, data) =
+        execute_query_with_fetcher(query, None, from, None, fetch, &ExecutionContext::shared())?;
+    Ok(pods_to_json(fields, data))
+}
+
+fn into_c_string(s: String) -> *mut c_char {
+    // JSON and error messages can not contain NUL, but don't panic across FFI if they ever do
+    CString::new(s.replace('\0', ""))
+        .map(CString::into_raw)
+        .unwrap_or(ptr::null_mut())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::libs::cancellation::CancellationToken;
+    use crate::libs::data_fetcher::fetch_builtin_data;
+    use crate::libs::data_fetcher::markdown_fetcher::MarkdownCache;
+    use crate::libs::data_fetcher::pod::Pod;
+    use crate::libs::progress::Progress;
+
+    unsafe fn take_string(s: *mut c_char) -> Option<String> {
+        if s.is_null() {
+            return None;
+        }
+        let result = CStr::from_ptr(s).to_str().unwrap().to_string();
+        krafna_free_string(s);
+        Some(result)
+    }
+
+    #[test]
+    fn test_execute_query_against_dir() {
+        let dir = std::env::temp_dir().join(format!("krafna-ffi-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("a.md"), "---\ntitle: hello\n---\n").unwrap();
+
+        let query = CString::new("SELECT title").unwrap();
+        let dir_c = CString::new(dir.display().to_string()).unwrap();
+        // Not persisted, so the test leaves nothing in the OS cache dir
+        let cache = MarkdownCache::new(None, false);
+        let fetch = |from_function: &Function| {
+            fetch_builtin_data(
+                from_function,
+                &cache,
+                &CancellationToken::default(),
+                &Progress::default(),
+            )
+        };
+        unsafe {
+            let json = take_string(execute_query_with(query.as_ptr(), dir_c.as_ptr(), &fetch));
+            assert_eq!(Some(r#"[{"title":"hello"}]"#.to_string()), json);
+            assert_eq!(None, take_string(krafna_last_error()));
+        }
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_execute_union_query() {
+        let query = CString::new(
+            "SELECT title FROM FRONTMATTER_DATA('a') UNION SELECT title FROM FRONTMATTER_DATA('b') ORDER BY title DESC",
+        )
+        .unwrap();
+        // Every source has one row titled by its argument
+        let fetch = |from_function: &Function| -> Result<Vec<Pod>, Box<dyn Error>> {
+            let title = match &from_function.args[0] {
+                FunctionArg::FieldValue(title) => title.to_string(),
+                arg => arg.to_string(),
+            };
+            let mut pod = Pod::new_hash();
+            let _ = pod.insert("title".to_string(), Pod::String(title));
+            Ok(vec![pod])
+        };
+        unsafe {
+            let json = take_string(execute_query_with(query.as_ptr(), ptr::null(), &fetch));
+            assert_eq!(Some(r#"[{"title":"b"},{"title":"a"}]"#.to_string()), json);
+        }
+    }
+
+    #[test]
+    fn test_execute_query_error() {
+        let query = CString::new("SELECT title").unwrap();
+        unsafe {
+            assert!(krafna_execute_query(query.as_ptr(), ptr::null()).is_null());
+            assert_eq!(
+                Some("Query is missing FROM!".to_string()),
+                take_string(krafna_last_error())
+            );
+
+            assert!(krafna_execute_query(ptr::null(), ptr::null()).is_null());
+            krafna_free_string(ptr::null_mut());
+        }
+    }
+
+    #[test]
+    fn test_panic_is_reported_as_error() {
+        let query = CString::new("SELECT title FROM FRONTMATTER_DATA('.')").unwrap();
+        let fetch = |_: &Function| -> Result<Vec<_>, Box<dyn Error>> { panic!("broken source") };
+        unsafe {
+            assert!(execute_query_with(query.as_ptr(), ptr::null(), &fetch).is_null());
+            assert_eq!(
+                Some("krafna panicked: broken source".to_string()),
+                take_string(krafna_last_error())
+            );
+        }
+    }
+}
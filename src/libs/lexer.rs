@@ -0,0 +1,657 @@
+//! Splits query text into [`Token`]s for the parser. Whitespace is not a token, but every token
+//! knows if there was whitespace in front of it, since the grammar cares about that in a few places
+//! (e.g. `FROM` has to be followed by whitespace, a function call can not have one before `(`).
+//! `--` and `# ` comments run until the end of the line and count as whitespace.
+
+use std::fmt::Display;
+
+use crate::libs::parser::Operator;
+
+/// Position of a token in the query text, in chars.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TokenKind {
+    /// Field names, function names, keywords, `true`/`false`/`NULL` and alphabetic operators
+    Identifier(String),
+    String(String),
+    /// Number without a decimal point that fits in 64 bits
+    Integer(i64),
+    /// Number with a decimal point, or too big for an integer
+    Number(f64),
+    /// `:name`
+    Parameter(String),
+    /// `$name`
+    Macro(String),
+    /// Non alphabetic operators, e.g. `<=` or `+`
+    Symbol(String),
+    OpenedBracket,
+    ClosedBracket,
+    Comma,
+    /// `;`, optionally ending a query
+    Semicolon,
+    /// Character that can not start any token, left for the parser to report in context
+    Unknown(char),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub span: Span,
+    /// Whether there was whitespace between the previous token (or start of the query) and this one
+    pub space_before: bool,
+}
+
+impl Token {
+    /// Whether the token is the identifier `keyword`, ignoring case.
+    pub fn is_keyword(&self, keyword: &str) -> bool {
+        matches!(&self.kind, TokenKind::Identifier(name) if name.eq_ignore_ascii_case(keyword))
+    }
+}
+
+impl TokenKind {
+    /// Value of an integer or a number token
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            TokenKind::Integer(num) => Some(*num as f64),
+            TokenKind::Number(num) => Some(*num),
+            _ => None,
+        }
+    }
+}
+
+impl Display for TokenKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TokenKind::Identifier(name) => write!(f, "{}", name),
+            TokenKind::String(str) => write!(f, "'{}'", str),
+            TokenKind::Integer(num) => write!(f, "{}", num),
+            TokenKind::Number(num) => write!(f, "{:?}", num),
+            TokenKind::Parameter(name) => write!(f, ":{}", name),
+            TokenKind::Macro(name) => write!(f, "${}", name),
+            TokenKind::Symbol(symbol) => write!(f, "{}", symbol),
+            TokenKind::OpenedBracket => write!(f, "("),
+            TokenKind::ClosedBracket => write!(f, ")"),
+            TokenKind::Comma => write!(f, ","),
+            TokenKind::Semicolon => write!(f, ";"),
+            TokenKind::Unknown(c) => write!(f, "{}", c),
+        }
+    }
+}
+
+impl Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.kind)
+    }
+}
+
+/// Error that stops tokenizing, `offset` is the char position it happened at.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LexError {
+    pub message: String,
+    pub offset: usize,
+}
+
+impl LexError {
+    fn new(message: String, offset: usize) -> Self {
+        LexError { message, offset }
+    }
+}
+
+pub fn tokenize(source: &str) -> Result<Vec<Token>, LexError> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut tokens = Vec::new();
+    let mut index = 0;
+    let mut space_before = false;
+
+    while let Some(&c) = chars.get(index) {
+        if c.is_whitespace() {
+            space_before = true;
+            index += 1;
+            continue;
+        }
+        if is_comment_start(&chars, index) {
+            while chars.get(index).is_some_and(|&c| c != '\n') {
+                index += 1;
+            }
+            space_before = true;
+            continue;
+        }
+
+        let start = index;
+        let kind = if is_name_start(c) {
+            TokenKind::Identifier(lex_name(&chars, &mut index)?)
+        } else if c.is_numeric() {
+            lex_number(&chars, &mut index)?
+        } else if c == '"' || c == '\'' {
+            TokenKind::String(lex_string(&chars, &mut index)?)
+        } else if c == ':' {
+            TokenKind::Parameter(lex_prefixed_name(&chars, &mut index, "parameter")?)
+        } else if c == '$' {
+            TokenKind::Macro(lex_prefixed_name(&chars, &mut index, "macro")?)
+        } else if c == '(' {
+            index += 1;
+            TokenKind::OpenedBracket
+        } else if c == ')' {
+            index += 1;
+            TokenKind::ClosedBracket
+        } else if c == ',' {
+            index += 1;
+            TokenKind::Comma
+        } else if c == ';' {
+            index += 1;
+            TokenKind::Semicolon
+        } else if let Some(symbol) = lex_symbol(&chars, &mut index) {
+            TokenKind::Symbol(symbol)
+        } else {
+            index += 1;
+            TokenKind::Unknown(c)
+        };
+
+        tokens.push(Token {
+            kind,
+            span: Span { start, end: index },
+            space_before,
+        });
+        space_before = false;
+    }
+
+    Ok(tokens)
+}
+
+fn is_name_start(c: char) -> bool {
+    c.is_alphabetic() || c == '_'
+}
+
+// Name right after a one char prefix, e.g. `:min` or `$active`
+fn lex_prefixed_name(chars: &[char], index: &mut usize, what: &str) -> Result<String, LexError> {
+    *index += 1;
+    match chars.get(*index) {
+        Some(&c) if is_name_start(c) => lex_name(chars, index),
+        Some(c) => Err(LexError::new(
+            format!(
+                "Invalid {} name: must start with letter or underscore, found: {}",
+                what, c
+            ),
+            *index,
+        )),
+        None => Err(LexError::new(
+            format!("Invalid {} name: nothing found", what),
+            *index,
+        )),
+    }
+}
+
+// `--`, or `#` followed by whitespace, so that `#tag` stays free for tags
+fn is_comment_start(chars: &[char], index: usize) -> bool {
+    match (chars[index], chars.get(index + 1)) {
+        ('-', Some('-')) => true,
+        ('#', next) => next.is_none_or(|c| c.is_whitespace()),
+        _ => false,
+    }
+}
+
+// Field names start with letter or underscore, can contain '-' and '.', but can't end with '.'.
+// List indices can follow any part of the name, e.g. `projects[0].status` or `authors[-1]`.
+fn lex_name(chars: &[char], index: &mut usize) -> Result<String, LexError> {
+    let mut name = String::new();
+    while let Some(&c) = chars.get(*index) {
+        if c == '[' && !name.ends_with('.') {
+            name.push_str(&lex_list_index(chars, index)?);
+            continue;
+        }
+        if !c.is_alphanumeric() && c != '_' && c != '-' && c != '.' {
+            break;
+        }
+        name.push(c);
+        *index += 1;
+    }
+
+    if name.ends_with('.') {
+        return Err(LexError::new(
+            "Field can't end with '.'!".to_string(),
+            *index - 1,
+        ));
+    }
+
+    Ok(name)
+}
+
+// `[0]` or `[-1]`, returned as written
+fn lex_list_index(chars: &[char], index: &mut usize) -> Result<String, LexError> {
+    let start = *index;
+    let mut list_index = String::from("[");
+    *index += 1;
+    if chars.get(*index) == Some(&'-') {
+        list_index.push('-');
+        *index += 1;
+    }
+    while let Some(&c) = chars.get(*index) {
+        if !c.is_ascii_digit() {
+            break;
+        }
+        list_index.push(c);
+        *index += 1;
+    }
+
+    if chars.get(*index) != Some(&']') || !list_index.ends_with(|c: char| c.is_ascii_digit()) {
+        return Err(LexError::new(
+            "List index must be a whole number in square brackets, e.g. tags[0]!".to_string(),
+            start,
+        ));
+    }
+    *index += 1;
+    list_index.push(']');
+
+    Ok(list_index)
+}
+
+// Minus is not part of the number, since the lexer can't know if it is an operator or a sign
+fn lex_number(chars: &[char], index: &mut usize) -> Result<TokenKind, LexError> {
+    let start = *index;
+    let mut number = String::new();
+    let mut has_decimal = false;
+    while let Some(&c) = chars.get(*index) {
+        if c == '.' {
+            if has_decimal {
+                return Err(LexError::new(
+                    "Can not have multiple decimal signs".to_string(),
+                    *index,
+                ));
+            }
+            has_decimal = true;
+        } else if !c.is_numeric() {
+            break;
+        }
+        number.push(c);
+        *index += 1;
+    }
+
+    if !has_decimal {
+        if let Ok(integer) = number.parse::<i64>() {
+            return Ok(TokenKind::Integer(integer));
+        }
+    }
+    number
+        .parse::<f64>()
+        .map(TokenKind::Number)
+        .map_err(|e| LexError::new(e.to_string(), start))
+}
+
+fn lex_string(chars: &[char], index: &mut usize) -> Result<String, LexError> {
+    let opened_quote = chars[*index];
+    *index += 1;
+
+    let mut str = String::new();
+    while let Some(&c) = chars.get(*index) {
+        *index += 1;
+        if c == opened_quote {
+            return Ok(str);
+        }
+        str.push(c);
+    }
+
+    Err(LexError::new(
+        format!("Query ended before string ({}) was closed!", str),
+        *index,
+    ))
+}
+
+// Longest non alphabetic operator at `index`
+fn lex_symbol(chars: &[char], index: &mut usize) -> Option<String> {
+    let operators = Operator::strings_hash();
+    for length in (1..=2).rev() {
+        let Some(candidate) = chars.get(*index..*index + length) else {
+            continue;
+        };
+        let candidate: String = candidate.iter().collect();
+        if !candidate.starts_with(char::is_alphabetic) && operators.contains(candidate.as_str()) {
+            *index += length;
+            return Some(candidate);
+        }
+    }
+
+    None
+}
+
+/// 1-based line and column of char `offset` in `source`.
+pub fn line_column(source: &str, offset: usize) -> (usize, usize) {
+    let consumed: Vec<char> = source.chars().take(offset).collect();
+    let line = consumed.iter().filter(|&&c| c == '\n').count() + 1;
+    let column = consumed.iter().rev().take_while(|&&c| c != '\n').count() + 1;
+    (line, column)
+}
+
+/// The line of char `offset` in `source`, with a caret under it, e.g.
+/// ```text
+///   |
+/// 2 | WHERE a == 1 && b
+///   |              ^
+/// ```
+pub fn caret_snippet(source: &str, offset: usize) -> String {
+    let (line, column) = line_column(source, offset);
+    let line_text = source.split('\n').nth(line - 1).unwrap_or_default();
+    let gutter = " ".repeat(line.to_string().len());
+    // Keep tabs so the caret lines up with the text above it
+    let padding: String = line_text
+        .chars()
+        .take(column - 1)
+        .map(|c| if c == '\t' { '\t' } else { ' ' })
+        .collect();
+
+    format!(
+        "{} |\n{} | {}\n{} | {}^",
+        gutter, line, line_text, gutter, padding
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kinds(source: &str) -> Result<Vec<TokenKind>, LexError> {
+        Ok(tokenize(source)?
+            .into_iter()
+            .map(|token| token.kind)
+            .collect())
+    }
+
+    #[test]
+    fn test_tokenize_query() -> Result<(), LexError> {
+        let tokens = tokenize("SELECT a FROM F('x') WHERE b.c >= -5 AND :d")?;
+
+        assert_eq!(
+            vec![
+                TokenKind::Identifier("SELECT".to_string()),
+                TokenKind::Identifier("a".to_string()),
+                TokenKind::Identifier("FROM".to_string()),
+                TokenKind::Identifier("F".to_string()),
+                TokenKind::OpenedBracket,
+                TokenKind::String("x".to_string()),
+                TokenKind::ClosedBracket,
+                TokenKind::Identifier("WHERE".to_string()),
+                TokenKind::Identifier("b.c".to_string()),
+                TokenKind::Symbol(">=".to_string()),
+                TokenKind::Symbol("-".to_string()),
+                TokenKind::Integer(5),
+                TokenKind::Identifier("AND".to_string()),
+                TokenKind::Parameter("d".to_string()),
+            ],
+            tokens
+                .iter()
+                .map(|token| token.kind.clone())
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(Span { start: 16, end: 19 }, tokens[5].span);
+        assert!(!tokens[4].space_before);
+        assert!(tokens[7].space_before);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_tokenize_whitespace() -> Result<(), LexError> {
+        let tokens = tokenize("  \t  \t\t\n  \t\n\n  a(b")?;
+
+        assert_eq!(3, tokens.len());
+        assert!(tokens[0].space_before);
+        assert!(!tokens[1].space_before);
+        assert!(!tokens[2].space_before);
+        assert_eq!(Vec::<Token>::new(), tokenize(" \t\n ")?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_tokenize_longest_symbol() -> Result<(), LexError> {
+        assert_eq!(
+            vec![
+                TokenKind::Symbol("**".to_string()),
+                TokenKind::Symbol("*".to_string()),
+                TokenKind::Symbol("<".to_string()),
+                TokenKind::Identifier("N".to_string()),
+                TokenKind::Symbol("//".to_string()),
+            ],
+            kinds("***<N//")?
+        );
+        assert_eq!(
+            vec![
+                TokenKind::Unknown('='),
+                TokenKind::Unknown('&'),
+                TokenKind::Symbol("!=".to_string()),
+            ],
+            kinds("=&!=")?
+        );
+
+        Ok(())
+    }
+
+    /////////////////////////////////////
+    // LEX NUMBER
+    /////////////////////////////////////
+    #[test]
+    fn test_tokenize_invalid_decimal_number() {
+        assert!(tokenize("5.3.2").is_err());
+    }
+
+    #[test]
+    fn test_tokenize_number_followed_by_name() -> Result<(), LexError> {
+        assert_eq!(
+            vec![
+                TokenKind::Number(543.21),
+                TokenKind::Identifier("a".to_string())
+            ],
+            kinds("543.21a")?
+        );
+        assert_eq!(
+            vec![
+                TokenKind::Integer(543),
+                TokenKind::Comma,
+                TokenKind::Integer(21),
+                TokenKind::Identifier("a".to_string())
+            ],
+            kinds("543,21a")?
+        );
+
+        Ok(())
+    }
+
+    /////////////////////////////////////
+    // LEX STRING
+    /////////////////////////////////////
+    #[test]
+    fn test_tokenize_string_with_single_quotes() -> Result<(), LexError> {
+        assert_eq!(
+            vec![TokenKind::String("test".to_string())],
+            kinds("'test'")?
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_tokenize_string_with_double_quotes() -> Result<(), LexError> {
+        assert_eq!(
+            vec![TokenKind::String("test".to_string())],
+            kinds("\"test\"")?
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_tokenize_valid_string_with_different_chars() -> Result<(), LexError> {
+        let str = "o oeuaoe 45646 ?$%^ ";
+
+        assert_eq!(
+            vec![TokenKind::String(str.to_string())],
+            kinds(&format!("'{}'", str))?
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_tokenize_string_without_closed_quote() {
+        assert_eq!(
+            Err(LexError::new(
+                "Query ended before string (test and field > 5) was closed!".to_string(),
+                19
+            )),
+            tokenize("'test and field > 5")
+        );
+    }
+
+    #[test]
+    fn test_tokenize_string_with_mixed_quotes() {
+        assert!(tokenize("'test\"").is_err());
+        assert!(tokenize("\"test'").is_err());
+        assert!(tokenize("test' and field > 5").is_err());
+    }
+
+    #[test]
+    fn test_tokenize_comments_and_semicolon() -> Result<(), LexError> {
+        let tokens = tokenize("a -- first\n-- second\n b; -- end")?;
+
+        assert_eq!(
+            vec![
+                TokenKind::Identifier("a".to_string()),
+                TokenKind::Identifier("b".to_string()),
+                TokenKind::Semicolon,
+            ],
+            tokens.iter().map(|t| t.kind.clone()).collect::<Vec<_>>()
+        );
+        assert!(tokens[1].space_before);
+        assert_eq!(Span { start: 23, end: 24 }, tokens[2].span);
+        assert_eq!(
+            vec![
+                TokenKind::Identifier("a".to_string()),
+                TokenKind::Symbol("-".to_string()),
+                TokenKind::Symbol("-".to_string()),
+                TokenKind::Integer(1),
+            ],
+            kinds("a - -1")?
+        );
+        assert_eq!(
+            vec![
+                TokenKind::Identifier("a".to_string()),
+                TokenKind::Identifier("b".to_string()),
+            ],
+            kinds("# a man can dream\na #\nb #\t-- end")?
+        );
+        assert_eq!(TokenKind::Unknown('#'), kinds("#tag")?[0]);
+
+        Ok(())
+    }
+
+    /////////////////////////////////////
+    // LEX FIELD NAME
+    /////////////////////////////////////
+    #[test]
+    fn test_tokenize_field_name_ends_with_dot() {
+        assert!(tokenize("test.").is_err());
+    }
+
+    #[test]
+    fn test_tokenize_field_name_with_dot_minus_and_underscore() -> Result<(), LexError> {
+        for field_name in ["te.st", "te-st", "_test", "test"] {
+            assert_eq!(
+                vec![TokenKind::Identifier(field_name.to_string())],
+                kinds(field_name)?
+            );
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_tokenize_field_name_with_list_index() -> Result<(), LexError> {
+        for field_name in ["tags[0]", "authors[-1]", "projects[0].status", "m[1][2]"] {
+            assert_eq!(
+                vec![TokenKind::Identifier(field_name.to_string())],
+                kinds(field_name)?
+            );
+        }
+        for (field_name, offset) in [("tags[a]", 4), ("tags[0", 4), ("tags[-]", 4), ("a[1][", 4)] {
+            assert_eq!(
+                Err(LexError::new(
+                    "List index must be a whole number in square brackets, e.g. tags[0]!"
+                        .to_string(),
+                    offset
+                )),
+                tokenize(field_name)
+            );
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_tokenize_field_name_invalid_start() -> Result<(), LexError> {
+        assert_eq!(TokenKind::Unknown('.'), kinds(".test")?[0]);
+        assert_eq!(TokenKind::Symbol("-".to_string()), kinds("-test")?[0]);
+        assert_eq!(TokenKind::Integer(5), kinds("5test")?[0]);
+
+        Ok(())
+    }
+
+    /////////////////////////////////////
+    // LEX PARAMETER AND MACRO
+    /////////////////////////////////////
+    #[test]
+    fn test_tokenize_parameter() -> Result<(), LexError> {
+        assert_eq!(
+            vec![TokenKind::Parameter("min".to_string())],
+            kinds(":min")?
+        );
+        assert!(tokenize(": min").is_err());
+        assert!(tokenize(":5").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_tokenize_macro() -> Result<(), LexError> {
+        assert_eq!(
+            vec![
+                TokenKind::Macro("active".to_string()),
+                TokenKind::Identifier("AND".to_string()),
+                TokenKind::Macro("due_soon".to_string()),
+            ],
+            kinds("$active AND $due_soon")?
+        );
+        assert_eq!(
+            Err(LexError::new(
+                "Invalid macro name: must start with letter or underscore, found: 1".to_string(),
+                1
+            )),
+            tokenize("$1")
+        );
+
+        Ok(())
+    }
+
+    /////////////////////////////////////
+    // ERROR POSITION
+    /////////////////////////////////////
+    #[test]
+    fn test_line_column_and_caret_snippet() {
+        let query = "SELECT a\nWHERE a == 1 && b";
+
+        assert_eq!((2, 14), line_column(query, 22));
+        assert_eq!(
+            "  |\n2 | WHERE a == 1 && b\n  |              ^",
+            caret_snippet(query, 22)
+        );
+    }
+
+    #[test]
+    fn test_caret_snippet_at_end() {
+        assert_eq!((1, 4), line_column("abc", 3));
+        assert_eq!("  |\n1 | abc\n  |    ^", caret_snippet("abc", 3));
+    }
+}
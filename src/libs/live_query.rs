@@ -0,0 +1,237 @@
+use std::error::Error;
+use std::path::PathBuf;
+
+use crate::libs::cancellation::CancellationToken;
+use crate::libs::data_fetcher::pod::Pod;
+use crate::libs::engine::Krafna;
+use crate::libs::executor::{
+    add_select_aliases, compare_rows, execute_group_by, execute_limit, execute_select,
+    execute_select_functions, expand_select_all, filter_rows,
+};
+use crate::libs::parser::{Function, Query};
+
+/// Results of a query that are kept up to date as markdown files change, e.g. in watch mode.
+/// Only the changed files are parsed and filtered again, and their rows are merged into the
+/// sorted results, instead of walking, filtering and sorting everything on every change.
+pub struct LiveQuery {
+    engine: Krafna,
+    query: Query,
+    /// Rows that passed WHERE, in ORDER BY order and with all of their fields
+    rows: Vec<Pod>,
+}
+
+impl LiveQuery {
+    pub(crate) fn new(engine: Krafna, query: Query) -> Result<Self, Box<dyn Error>> {
+        query.check_grouping()?;
+        // Their results would have to be kept up to date as well
+        if query.has_subqueries() {
+            return Err("Subqueries are not supported in watch mode yet".into());
+        }
+        let mut live_query = LiveQuery {
+            engine,
+            query,
+            rows: Vec::new(),
+        };
+        live_query.refresh()?;
+        Ok(live_query)
+    }
+
+    pub fn query(&self) -> &Query {
+        &self.query
+    }
+
+    /// Fetches, filters and sorts all of the rows again.
+    pub fn refresh(&mut self) -> Result<(), Box<dyn Error>> {
+        let from_function = self.source()?;
+        let mut rows = self.engine.fetch_data(from_function).map_err(|error| {
+            self.query
+                .spans
+                .locate(&error.to_string(), self.query.spans.from)
+        })?;
+        self.add_select_aliases(&mut rows)?;
+        if let Some(error) = filter_rows(
+            &self.query.where_expression,
+            &self.query.spans,
+            &mut rows,
+            &self.engine.context(&CancellationToken::default()),
+        )? {
+            return Err(error.into());
+        }
+        // Rows are fetched in path order, so ties are in path order as well
+        let context = self.engine.context(&CancellationToken::default());
+        execute_select_functions(&self.query, &mut rows, &context)?;
+        rows.sort_by(|a, b| compare_rows(&self.query.order_by_fields, a, b, &context));
+        self.rows = rows;
+        Ok(())
+    }
+
+    /// Updates the results for the `changed` files (created, modified or removed), as reported by
+    /// a file watcher. Changes that can not be applied per file (registered sources, moved
+    /// directories) fetch everything again.
+    pub fn update(&mut self, changed: &[PathBuf]) -> Result<(), Box<dyn Error>> {
+        let from_function = self.source()?;
+        let Some(changes) = self.engine.fetch_changes(from_function, changed)? else {
+            return self.refresh();
+        };
+        if changes.is_empty() {
+            return Ok(());
+        }
+
+        self.rows
+            .retain(|row| !changes.contains_key(row_path(row).unwrap_or_default()));
+        let mut rows: Vec<Pod> = changes.into_values().flatten().collect();
+        self.add_select_aliases(&mut rows)?;
+        // Failing on all of the changed rows is not an error, rows of other files still match
+        filter_rows(
+            &self.query.where_expression,
+            &self.query.spans,
+            &mut rows,
+            &self.engine.context(&CancellationToken::default()),
+        )?;
+        let context = self.engine.context(&CancellationToken::default());
+        execute_select_functions(&self.query, &mut rows, &context)?;
+        rows.sort_by(|a, b| compare_rows(&self.query.order_by_fields, a, b, &context));
+
+        let order_by = &self.query.order_by_fields;
+        self.rows = merge_sorted(std::mem::take(&mut self.rows), rows, |a, b| {
+            compare_rows(order_by, a, b, &context).then_with(|| row_path(a).cmp(&row_path(b)))
+        });
+        Ok(())
+    }
+
+    /// Current results with SELECT applied, the same as executing the query would return.
+    pub fn results(&self) -> (Vec<String>, Vec<Pod>) {
+        // `*` takes its columns in fetch order, which is path order
+        let mut fetch_order: Vec<&Pod> = self.rows.iter().collect();
+        fetch_order.sort_by_key(|row| row_path(row));
+        let fields = expand_select_all(
+            self.query.select_fields.clone(),
+            &self.query.select_exclude,
+            fetch_order,
+        );
+
+        let mut data = self.rows.clone();
+        // Groups change with every row, so they are computed from all of the rows
+        if self.query.is_grouped() {
+            let context = self.engine.context(&CancellationToken::default());
+            let order_by = execute_group_by(&self.query, &mut data, &context);
+            data.sort_by(|a, b| compare_rows(&order_by, a, b, &context));
+        }
+        execute_limit(&self.query, &mut data);
+        execute_select(&fields, &mut data);
+        (fields, data)
+    }
+
+    // Grouped queries get them with their groups
+    fn add_select_aliases(&self, rows: &mut [Pod]) -> Result<(), String> {
+        if self.query.is_grouped() {
+            return Ok(());
+        }
+        let context = self.engine.context(&CancellationToken::default());
+        add_select_aliases(&self.query, rows, &context)
+    }
+
+    fn source(&self) -> Result<&Function, Box<dyn Error>> {
+        Ok(self
+            .query
+            .from_function
+            .as_ref()
+            .ok_or("Query is missing FROM!")?)
+    }
+}
+
+// All built-in rows have the path of the file they come from
+fn row_path(row: &Pod) -> Option<&str> {
+    match row.nested_get("file.path") {
+        Some(Pod::String(path)) => Some(path),
+        _ => None,
+    }
+}
+
+// Both sides are sorted by `compare`, rows of `a` go first on ties
+fn merge_sorted<F>(a: Vec<Pod>, b: Vec<Pod>, compare: F) -> Vec<Pod>
+where
+    F: Fn(&Pod, &Pod) -> std::cmp::Ordering,
+{
+    let mut merged = Vec::with_capacity(a.len() + b.len());
+    let mut a = a.into_iter().peekable();
+    let mut b = b.into_iter().peekable();
+    loop {
+        let from_a = match (a.peek(), b.peek()) {
+            (Some(row_a), Some(row_b)) => compare(row_a, row_b).is_le(),
+            (Some(_), None) => true,
+            (None, Some(_)) => false,
+            (None, None) => break,
+        };
+        merged.extend(if from_a { a.next() } else { b.next() });
+    }
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::libs::engine::KrafnaConfig;
+
+    fn values(results: (Vec<String>, Vec<Pod>)) -> Vec<String> {
+        results.1.iter().map(|row| row.to_string()).collect()
+    }
+
+    #[test]
+    fn test_live_query_updates_only_changed_files() {
+        let dir = std::env::temp_dir().join(format!("krafna-live-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for (file, n) in [("a.md", 1), ("b.md", 3), ("c.md", 2)] {
+            fs::write(dir.join(file), format!("---\nn: {}\n---\n# {}", n, file)).unwrap();
+        }
+        let engine = Krafna::with_config(KrafnaConfig {
+            use_disk_cache: false,
+            ..KrafnaConfig::default()
+        });
+        let query = format!(
+            "SELECT n FROM FRONTMATTER_DATA('{}') WHERE n > 1 ORDER BY n DESC",
+            dir.display()
+        );
+
+        let mut live_query = engine.live_query(&query, None, None, None).unwrap();
+        let before = live_query.results();
+
+        fs::write(dir.join("a.md"), "---\nn: 5\n---\n# a").unwrap();
+        fs::remove_file(dir.join("b.md")).unwrap();
+        fs::write(dir.join("d.md"), "---\nn: 4\n---\n# d").unwrap();
+        let changed = ["a.md", "b.md", "d.md", "ignored.txt"].map(|file| dir.join(file));
+        let updated = live_query.update(&changed).map(|_| live_query.results());
+
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        fs::write(dir.join("sub/e.md"), "---\nn: 6\n---\n# e").unwrap();
+        let refreshed = live_query
+            .update(&[dir.join("sub")])
+            .map(|_| live_query.results());
+        let executed = engine.execute_query(&query, None, None, None);
+        let _ = fs::remove_dir_all(&dir);
+
+        assert_eq!(vec![r#"{"n":3}"#, r#"{"n":2}"#], values(before));
+        assert_eq!(
+            vec![r#"{"n":5}"#, r#"{"n":4}"#, r#"{"n":2}"#],
+            values(updated.unwrap())
+        );
+        let refreshed = refreshed.unwrap();
+        assert_eq!(
+            vec![r#"{"n":6}"#, r#"{"n":5}"#, r#"{"n":4}"#, r#"{"n":2}"#],
+            values(refreshed.clone())
+        );
+        assert_eq!(executed.unwrap(), refreshed);
+    }
+
+    #[test]
+    fn test_merge_sorted_keeps_order() {
+        let rows = |ns: &[i64]| -> Vec<Pod> { ns.iter().map(|n| Pod::Integer(*n)).collect() };
+        let merged = merge_sorted(rows(&[1, 3, 5]), rows(&[2, 3, 6]), |a, b| {
+            a.as_i64().cmp(&b.as_i64())
+        });
+
+        assert_eq!(rows(&[1, 2, 3, 3, 5, 6]), merged);
+    }
+}
@@ -1,10 +1,49 @@
+pub mod aggregate;
+pub mod cancellation;
+pub mod collation;
+pub mod config;
 pub mod data_fetcher;
+pub mod dataview;
+pub mod diff;
+pub mod engine;
 pub mod executor;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod lexer;
+pub mod live_query;
 pub mod parser;
 pub mod peekable_deque;
+pub mod planner;
+pub mod prepared_query;
+pub mod progress;
 pub mod serializer;
+pub mod suggestion;
+#[cfg(feature = "testing")]
+pub mod testing;
+pub mod union;
+pub mod update;
+pub mod validator;
+pub mod with;
 
 // Re-export important items from submodules
-pub use data_fetcher::fetch_data;
-pub use parser::{ExpressionElement, FieldValue, Function, FunctionArg, Query};
+pub use aggregate::Aggregate;
+pub use cancellation::CancellationToken;
+pub use collation::Collation;
+pub use config::ConfigFile;
+pub use data_fetcher::markdown_fetcher::FileError;
+pub use data_fetcher::{fetch_data, SourceProvider};
+pub use engine::{Krafna, KrafnaConfig};
+pub use lexer::Span;
+pub use live_query::LiveQuery;
+pub use parser::{
+    Assignment, Definition, ExpressionElement, FieldValue, Function, FunctionArg, GroupByField,
+    Query, QuerySpans, Update,
+};
 pub use peekable_deque::PeekableDeque;
+pub use planner::{plan, strip_explain, QueryPlan};
+pub use prepared_query::PreparedQuery;
+pub use progress::{Profile, Progress, ProgressEvent, Stage};
+pub use union::Union;
+pub use update::{FileUpdate, UpdateOptions};
+pub use validator::{validate_fields, FieldWarning};
+pub use with::With;
@@ -1,18 +1,45 @@
-// I wanted to try to do parsing in one go, but after trying, I'd say doing tokenisation first
-// would make for a nicer and cleaner code. If I'm bathered, might rewrite at some point.
-
 use core::f64;
 use hashbrown::HashSet;
 use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::HashMap;
 use std::{fmt::Display, str::FromStr};
 
+use crate::libs::aggregate::Aggregate;
+use crate::libs::lexer::{caret_snippet, line_column, tokenize, Span, Token, TokenKind};
 use crate::libs::peekable_deque::PeekableDeque;
+use crate::libs::suggestion::with_suggestion;
+
+/// First words of the clauses, suggested for misspelled keywords
+const CLAUSE_KEYWORDS: [&str; 7] = [
+    "SELECT", "FROM", "WHERE", "GROUP", "ORDER", "LIMIT", "OFFSET",
+];
+
+/// Keywords a statement starts with (after its DEFINEs)
+const STATEMENT_KEYWORDS: [&str; 4] = ["SELECT", "WITH", "EXPLAIN", "UPDATE"];
+
+/// SELECT field standing for every field of the rows, in the order they first appear
+pub const SELECT_ALL: &str = "*";
+
+/// Components of a date that can be accessed on a function result, e.g. `DATE(created).year`
+pub const DATE_MEMBERS: [&str; 9] = [
+    "year",
+    "month",
+    "day",
+    "hour",
+    "minute",
+    "second",
+    "weekday",
+    "week",
+    "dayofyear",
+];
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum Operator {
     And,
     Or,
     In,
+    NotIn,
     Lt,
     Lte,
     Gt,
@@ -21,6 +48,8 @@ pub enum Operator {
     Neq,
     Like,
     NotLike,
+    Regexp,
+    NotRegexp,
     Plus,
     Minus,
     Multiply,
@@ -30,10 +59,26 @@ pub enum Operator {
 }
 
 impl Operator {
+    /// Whether the operator compares its operands, giving a bool
+    pub fn is_comparison(&self) -> bool {
+        !matches!(
+            self,
+            Operator::And
+                | Operator::Or
+                | Operator::Plus
+                | Operator::Minus
+                | Operator::Multiply
+                | Operator::Divide
+                | Operator::Power
+                | Operator::FloorDivide
+        )
+    }
+
     const OPERATOR_MAP: phf::Map<&'static str, Operator> = phf::phf_map! {
         "AND" => Operator::And,
         "OR" => Operator::Or,
         "IN" => Operator::In,
+        "NOT IN" => Operator::NotIn,
         "<" => Operator::Lt,
         "<=" => Operator::Lte,
         ">" => Operator::Gt,
@@ -42,6 +87,8 @@ impl Operator {
         "!=" => Operator::Neq,
         "LIKE" => Operator::Like,
         "NOT LIKE" => Operator::NotLike,
+        "REGEXP" => Operator::Regexp,
+        "NOT REGEXP" => Operator::NotRegexp,
         "+" => Operator::Plus,
         "-" => Operator::Minus,
         "*" => Operator::Multiply,
@@ -73,6 +120,34 @@ impl FromStr for Operator {
     }
 }
 
+impl Display for Operator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let operator = match self {
+            Operator::And => "AND",
+            Operator::Or => "OR",
+            Operator::In => "IN",
+            Operator::NotIn => "NOT IN",
+            Operator::Lt => "<",
+            Operator::Lte => "<=",
+            Operator::Gt => ">",
+            Operator::Gte => ">=",
+            Operator::Eq => "==",
+            Operator::Neq => "!=",
+            Operator::Like => "LIKE",
+            Operator::NotLike => "NOT LIKE",
+            Operator::Regexp => "REGEXP",
+            Operator::NotRegexp => "NOT REGEXP",
+            Operator::Plus => "+",
+            Operator::Minus => "-",
+            Operator::Multiply => "*",
+            Operator::Divide => "/",
+            Operator::Power => "**",
+            Operator::FloorDivide => "//",
+        };
+        write!(f, "{}", operator)
+    }
+}
+
 #[derive(Debug, PartialEq, Clone)]
 pub enum ExpressionElement {
     OpenedBracket,
@@ -81,6 +156,63 @@ pub enum ExpressionElement {
     FieldName(String),
     FieldValue(FieldValue),
     Function(Function),
+    /// `:name` placeholder, replaced with a value by [`Query::bind`]
+    Parameter(String),
+    /// `$name` reference to a [`Definition`], replaced with its condition by
+    /// [`Query::expand_macros`]
+    Macro(String),
+    /// Unary minus, negates the operand that follows it, e.g. `-budget` or `-(a + b)`
+    Negate,
+    /// `NOT` (or `!`), negates the condition that follows it, e.g. `NOT ('fleeting' IN tags)` or
+    /// `!(done OR archived)`
+    Not,
+    /// `.name` right after a function call, one of [`DATE_MEMBERS`], e.g. `DATE(created).year`
+    Member(String),
+    /// `(SELECT ...)`, the values of its column after IN, its only value anywhere else. Runs
+    /// once before WHERE, see [`Query::has_subqueries`]
+    Subquery(Box<Query>),
+    /// `EXISTS (SELECT ...)`, whether the subquery has any rows
+    Exists(Box<Query>),
+    /// `ANY(tags) LIKE 'work/%'`, comparing every element of a list
+    Quantified(Box<Quantified>),
+}
+
+/// `ANY(list) op value` or `ALL(list) op value`: whether the comparison holds for any (or all)
+/// of the elements of the list. A value that is not a list is a list of itself, NULL is NULL.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Quantified {
+    pub quantifier: Quantifier,
+    pub list: Vec<ExpressionElement>,
+    pub operator: Operator,
+    pub value: Vec<ExpressionElement>,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Quantifier {
+    Any,
+    All,
+}
+
+impl Display for Quantifier {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Quantifier::Any => write!(f, "ANY"),
+            Quantifier::All => write!(f, "ALL"),
+        }
+    }
+}
+
+impl Display for Quantified {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}({}) {} {}",
+            self.quantifier,
+            expression_to_string(&self.list),
+            self.operator,
+            expression_to_string(&self.value)
+        )
+    }
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -93,6 +225,10 @@ pub struct Function {
 pub enum FunctionArg {
     FieldName(String),
     FieldValue(FieldValue),
+    Parameter(String),
+    /// Anything more than a single field, value or parameter, e.g. `status == 'done'` in
+    /// `IF(status == 'done', 'yes', 'no')`
+    Expression(Vec<ExpressionElement>),
 }
 
 impl Function {
@@ -101,17 +237,368 @@ impl Function {
     }
 }
 
-#[derive(Debug, PartialEq, PartialOrd, Clone, Serialize, Deserialize)]
+impl ExpressionElement {
+    pub fn field(name: &str) -> Self {
+        ExpressionElement::FieldName(name.to_string())
+    }
+
+    pub fn value(value: FieldValue) -> Self {
+        ExpressionElement::FieldValue(value)
+    }
+
+    pub fn string(value: &str) -> Self {
+        ExpressionElement::FieldValue(FieldValue::String(value.to_string()))
+    }
+
+    pub fn integer(value: i64) -> Self {
+        ExpressionElement::FieldValue(FieldValue::Integer(value))
+    }
+
+    pub fn float(value: f64) -> Self {
+        ExpressionElement::FieldValue(FieldValue::Float(value))
+    }
+
+    pub fn op(op: Operator) -> Self {
+        ExpressionElement::Operator(op)
+    }
+}
+
+/// Wraps `expression` in brackets if it has an OR outside of brackets, so it can be joined with
+/// other conditions by AND without changing its meaning.
+fn bracket_if_or(expression: Vec<ExpressionElement>) -> Vec<ExpressionElement> {
+    let mut depth = 0;
+    let has_top_level_or = expression.iter().any(|element| match element {
+        ExpressionElement::OpenedBracket => {
+            depth += 1;
+            false
+        }
+        ExpressionElement::ClosedBracket => {
+            depth -= 1;
+            false
+        }
+        ExpressionElement::Operator(Operator::Or) => depth == 0,
+        _ => false,
+    });
+    if !has_top_level_or {
+        return expression;
+    }
+
+    let mut bracketed = Vec::with_capacity(expression.len() + 2);
+    bracketed.push(ExpressionElement::OpenedBracket);
+    bracketed.extend(expression);
+    bracketed.push(ExpressionElement::ClosedBracket);
+    bracketed
+}
+
+// Whether `expression` is a single operand, or one pair of brackets around everything
+fn is_bracketed(expression: &[ExpressionElement]) -> bool {
+    if expression.len() == 1 {
+        return true;
+    }
+    let mut depth = 0;
+    for (index, element) in expression.iter().enumerate() {
+        match element {
+            ExpressionElement::OpenedBracket => depth += 1,
+            ExpressionElement::ClosedBracket => depth -= 1,
+            _ if index == 0 => return false,
+            _ => {}
+        }
+        if depth == 0 && index + 1 < expression.len() {
+            return false;
+        }
+    }
+    true
+}
+
+/// Replaces every `$name` in `expression` that one of `definitions` defines with its condition,
+/// other macros are left as they are. `spans` (one for every element, or none) are replaced
+/// with the spans the condition was parsed with, or with the span of `$name` when the condition
+/// does not come from the same source.
+fn expand_macros(
+    expression: &[ExpressionElement],
+    spans: &[Span],
+    definitions: &[Definition],
+) -> (Vec<ExpressionElement>, Vec<Span>) {
+    let mut expanded = Vec::with_capacity(expression.len());
+    let mut expanded_spans = Vec::with_capacity(spans.len());
+    for (index, element) in expression.iter().enumerate() {
+        let span = spans.get(index).copied();
+        let definition = match element {
+            ExpressionElement::Macro(name) => definitions.iter().find(|d| &d.name == name),
+            ExpressionElement::Subquery(query) | ExpressionElement::Exists(query) => {
+                let mut query = query.clone();
+                // Spans of the definitions are not in the subquery's source
+                let sub_definitions: Vec<Definition> = definitions
+                    .iter()
+                    .map(|d| Definition {
+                        spans: Vec::new(),
+                        ..d.clone()
+                    })
+                    .collect();
+                (query.where_expression, query.spans.where_expression) = expand_macros(
+                    &query.where_expression,
+                    &query.spans.where_expression,
+                    &sub_definitions,
+                );
+                expanded.push(match element {
+                    ExpressionElement::Exists(_) => ExpressionElement::Exists(query),
+                    _ => ExpressionElement::Subquery(query),
+                });
+                expanded_spans.extend(span);
+                continue;
+            }
+            // e.g. `IF($active, 'yes', 'no')`, the argument has no spans
+            ExpressionElement::Function(func) => {
+                let args = func.args.iter().map(|arg| match arg {
+                    FunctionArg::Expression(expression) => {
+                        FunctionArg::Expression(expand_macros(expression, &[], definitions).0)
+                    }
+                    _ => arg.clone(),
+                });
+                expanded.push(ExpressionElement::Function(Function::new(
+                    func.name.clone(),
+                    args.collect(),
+                )));
+                expanded_spans.extend(span);
+                continue;
+            }
+            _ => None,
+        };
+        let Some(definition) = definition else {
+            expanded.push(element.clone());
+            expanded_spans.extend(span);
+            continue;
+        };
+        expanded.extend(definition.condition.iter().cloned());
+        if let Some(span) = span {
+            match definition.spans.len() == definition.condition.len() {
+                true => expanded_spans.extend(definition.spans.iter().copied()),
+                false => expanded_spans.extend(definition.condition.iter().map(|_| span)),
+            }
+        }
+    }
+    (expanded, expanded_spans)
+}
+
+impl Display for ExpressionElement {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExpressionElement::OpenedBracket => write!(f, "("),
+            ExpressionElement::ClosedBracket => write!(f, ")"),
+            ExpressionElement::Operator(op) => write!(f, "{}", op),
+            ExpressionElement::FieldName(field_name) => write!(f, "{}", field_name),
+            ExpressionElement::FieldValue(fv) => write!(f, "{}", fv.to_query_string()),
+            ExpressionElement::Function(func) => write!(f, "{}", func),
+            ExpressionElement::Parameter(name) => write!(f, ":{}", name),
+            ExpressionElement::Macro(name) => write!(f, "${}", name),
+            ExpressionElement::Negate => write!(f, "-"),
+            ExpressionElement::Not => write!(f, "NOT"),
+            ExpressionElement::Member(name) => write!(f, ".{}", name),
+            ExpressionElement::Subquery(query) => write!(f, "({})", query),
+            ExpressionElement::Exists(query) => write!(f, "EXISTS ({})", query),
+            ExpressionElement::Quantified(quantified) => write!(f, "{}", quantified),
+        }
+    }
+}
+
+impl Display for Function {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let args: Vec<String> = self.args.iter().map(|arg| arg.to_string()).collect();
+        write!(f, "{}({})", self.name, args.join(", "))
+    }
+}
+
+impl Display for FunctionArg {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FunctionArg::FieldName(field_name) => write!(f, "{}", field_name),
+            FunctionArg::FieldValue(fv) => write!(f, "{}", fv.to_query_string()),
+            FunctionArg::Parameter(name) => write!(f, ":{}", name),
+            FunctionArg::Expression(expression) => {
+                write!(f, "{}", expression_to_string(expression))
+            }
+        }
+    }
+}
+
+/// Formats `expression` back into query text, e.g. `(a == 'b' OR c) AND d`.
+pub fn expression_to_string(expression: &[ExpressionElement]) -> String {
+    let mut result = String::new();
+    let mut after_negate = false;
+    for element in expression {
+        let text = element.to_string();
+        match element {
+            ExpressionElement::ClosedBracket | ExpressionElement::Member(_) => {}
+            _ if result.is_empty() || result.ends_with('(') => {}
+            // `--` would start a comment
+            _ if after_negate && !text.starts_with('-') => {}
+            _ => result.push(' '),
+        }
+        result.push_str(&text);
+        after_negate = *element == ExpressionElement::Negate;
+    }
+    result
+}
+
+// `:name` parameters of a function argument, also in the functions of an expression argument
+fn arg_parameters(arg: &FunctionArg) -> Vec<String> {
+    match arg {
+        FunctionArg::Parameter(name) => vec![name.clone()],
+        FunctionArg::Expression(expression) => expression_parameters(expression),
+        _ => Vec::new(),
+    }
+}
+
+// `:name` parameters of an expression, in its functions, subqueries and ANY/ALL too
+fn expression_parameters(expression: &[ExpressionElement]) -> Vec<String> {
+    expression
+        .iter()
+        .flat_map(|element| match element {
+            ExpressionElement::Parameter(name) => vec![name.clone()],
+            ExpressionElement::Function(func) => {
+                func.args.iter().flat_map(arg_parameters).collect()
+            }
+            ExpressionElement::Subquery(query) | ExpressionElement::Exists(query) => {
+                query.parameters()
+            }
+            ExpressionElement::Quantified(quantified) => {
+                let mut names = expression_parameters(&quantified.list);
+                names.extend(expression_parameters(&quantified.value));
+                names
+            }
+            _ => Vec::new(),
+        })
+        .collect()
+}
+
+fn bound_parameter(
+    name: &String,
+    params: &HashMap<String, FieldValue>,
+) -> Result<FieldValue, String> {
+    params
+        .get(name)
+        .cloned()
+        .ok_or(format!("Parameter :{} is not bound!", name))
+}
+
+// Function arguments with every `:name` parameter replaced by its value from `params`
+fn bind_args(
+    args: &[FunctionArg],
+    params: &HashMap<String, FieldValue>,
+) -> Result<Vec<FunctionArg>, String> {
+    args.iter()
+        .map(|arg| match arg {
+            FunctionArg::Parameter(name) => {
+                Ok(FunctionArg::FieldValue(bound_parameter(name, params)?))
+            }
+            FunctionArg::Expression(expression) => {
+                let mut expression = expression.clone();
+                bind_expression(&mut expression, params)?;
+                Ok(FunctionArg::Expression(expression))
+            }
+            _ => Ok(arg.clone()),
+        })
+        .collect()
+}
+
+// Replaces every `:name` parameter of an expression (and its functions, subqueries and ANY/ALL)
+// with its value from `params`
+fn bind_expression(
+    expression: &mut [ExpressionElement],
+    params: &HashMap<String, FieldValue>,
+) -> Result<(), String> {
+    for element in expression {
+        match element {
+            ExpressionElement::Parameter(name) => {
+                *element = ExpressionElement::FieldValue(bound_parameter(name, params)?)
+            }
+            ExpressionElement::Function(func) => func.args = bind_args(&func.args, params)?,
+            ExpressionElement::Subquery(query) | ExpressionElement::Exists(query) => {
+                **query = query.bind(params)?
+            }
+            ExpressionElement::Quantified(quantified) => {
+                bind_expression(&mut quantified.list, params)?;
+                bind_expression(&mut quantified.value, params)?;
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Like [`expression_to_string`], but every AND/OR outside of brackets starts a new line, indented
+/// by `indent`.
+fn expression_to_lines(expression: &[ExpressionElement], indent: &str) -> String {
+    let mut lines = Vec::new();
+    let mut line_start = 0;
+    let mut depth = 0;
+    for (index, element) in expression.iter().enumerate() {
+        match element {
+            ExpressionElement::OpenedBracket => depth += 1,
+            ExpressionElement::ClosedBracket => depth -= 1,
+            ExpressionElement::Operator(Operator::And | Operator::Or) if depth == 0 => {
+                lines.push(expression_to_string(&expression[line_start..index]));
+                line_start = index;
+            }
+            _ => {}
+        }
+    }
+    lines.push(expression_to_string(&expression[line_start..]));
+
+    lines.join(&format!("\n{}", indent))
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(untagged)]
 pub enum FieldValue {
-    List(Vec<FieldValue>), // TODO: implement parsing of lists in a query []
+    List(Vec<FieldValue>),
     String(String),
-    Number(f64),
+    // Integer before Float, so whole numbers deserialize as integers
+    Integer(i64),
+    Float(f64),
     Bool(bool),
+    /// Length of time in seconds, from `DURATION('3d')` or the difference of two dates
+    Duration(i64),
     Null,
 }
 
+// Integers and floats compare by their value, other variants only among themselves and in the
+// order they are declared in
+impl PartialEq for FieldValue {
+    fn eq(&self, other: &Self) -> bool {
+        self.partial_cmp(other) == Some(Ordering::Equal)
+    }
+}
+
+impl PartialOrd for FieldValue {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        match (self, other) {
+            (FieldValue::List(a), FieldValue::List(b)) => a.partial_cmp(b),
+            (FieldValue::String(a), FieldValue::String(b)) => a.partial_cmp(b),
+            (FieldValue::Integer(a), FieldValue::Integer(b)) => a.partial_cmp(b),
+            (FieldValue::Integer(a), FieldValue::Float(b)) => (*a as f64).partial_cmp(b),
+            (FieldValue::Float(a), FieldValue::Integer(b)) => a.partial_cmp(&(*b as f64)),
+            (FieldValue::Float(a), FieldValue::Float(b)) => a.partial_cmp(b),
+            (FieldValue::Bool(a), FieldValue::Bool(b)) => a.partial_cmp(b),
+            (FieldValue::Duration(a), FieldValue::Duration(b)) => a.partial_cmp(b),
+            (FieldValue::Null, FieldValue::Null) => Some(Ordering::Equal),
+            _ => self.rank().partial_cmp(&other.rank()),
+        }
+    }
+}
+
 impl FieldValue {
+    fn rank(&self) -> u8 {
+        match self {
+            FieldValue::List(_) => 0,
+            FieldValue::String(_) => 1,
+            FieldValue::Integer(_) | FieldValue::Float(_) => 2,
+            FieldValue::Bool(_) => 3,
+            FieldValue::Duration(_) => 4,
+            FieldValue::Null => 5,
+        }
+    }
+
     pub fn contains(&self, other: &Self) -> bool {
         match (self, other) {
             (FieldValue::List(list), _) => list.contains(other),
@@ -134,13 +621,26 @@ impl FieldValue {
         }
     }
 
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            FieldValue::Integer(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    /// Value of an integer or a float
     pub fn as_f64(&self) -> Option<f64> {
         match self {
-            FieldValue::Number(n) => Some(*n),
+            FieldValue::Integer(n) => Some(*n as f64),
+            FieldValue::Float(n) => Some(*n),
             _ => None,
         }
     }
 
+    pub fn is_number(&self) -> bool {
+        matches!(self, FieldValue::Integer(_) | FieldValue::Float(_))
+    }
+
     pub fn as_bool(&self) -> Option<bool> {
         match self {
             FieldValue::Bool(b) => Some(*b),
@@ -148,6 +648,40 @@ impl FieldValue {
         }
     }
 
+    /// Formats the value the way it would be written in a query (strings are quoted).
+    pub fn to_query_string(&self) -> String {
+        match self {
+            FieldValue::String(s) => {
+                if s.contains('\'') && !s.contains('"') {
+                    format!("\"{}\"", s)
+                } else {
+                    format!("'{}'", s)
+                }
+            }
+            FieldValue::List(list) => {
+                let elements: Vec<String> =
+                    list.iter().map(|item| item.to_query_string()).collect();
+                format!("({})", elements.join(", "))
+            }
+            FieldValue::Duration(_) => format!("DURATION('{}')", self),
+            // `3.0`, so it reads back as a float
+            FieldValue::Float(n) => format!("{:?}", n),
+            _ => self.to_string(),
+        }
+    }
+
+    pub fn negate(&self) -> Result<Self, String> {
+        match self {
+            FieldValue::Integer(n) => Ok(n
+                .checked_neg()
+                .map_or(FieldValue::Float(-(*n as f64)), FieldValue::Integer)),
+            FieldValue::Float(n) => Ok(FieldValue::Float(-n)),
+            FieldValue::Duration(seconds) => Ok(FieldValue::Duration(-seconds)),
+            FieldValue::Null => Ok(FieldValue::Null),
+            _ => Err(format!("Can not negate {}!", self.to_query_string())),
+        }
+    }
+
     pub fn add(&self, other: &Self) -> Result<Self, String> {
         match (self, other) {
             (FieldValue::List(list), FieldValue::List(other_list)) => {
@@ -158,8 +692,16 @@ impl FieldValue {
             (FieldValue::String(s), FieldValue::String(other_s)) => {
                 Ok(FieldValue::String(format!("{}{}", s, other_s)))
             }
-            (FieldValue::Number(n), FieldValue::Number(other_n)) => {
-                Ok(FieldValue::Number(n + other_n))
+            (FieldValue::Integer(n), FieldValue::Integer(other_n)) => {
+                Ok(integer_or_float(n.checked_add(*other_n), || {
+                    *n as f64 + *other_n as f64
+                }))
+            }
+            _ if self.is_number() && other.is_number() => {
+                Ok(FieldValue::Float(self.float() + other.float()))
+            }
+            (FieldValue::Duration(a), FieldValue::Duration(b)) => {
+                Ok(FieldValue::Duration(a.saturating_add(*b)))
             }
             _ => Err(format!("Can't add {:?} and {:?}", self, other)),
         }
@@ -172,8 +714,16 @@ impl FieldValue {
                 new_list.retain(|item| !other_list.contains(item));
                 Ok(FieldValue::List(new_list))
             }
-            (FieldValue::Number(n), FieldValue::Number(other_n)) => {
-                Ok(FieldValue::Number(n - other_n))
+            (FieldValue::Integer(n), FieldValue::Integer(other_n)) => {
+                Ok(integer_or_float(n.checked_sub(*other_n), || {
+                    *n as f64 - *other_n as f64
+                }))
+            }
+            _ if self.is_number() && other.is_number() => {
+                Ok(FieldValue::Float(self.float() - other.float()))
+            }
+            (FieldValue::Duration(a), FieldValue::Duration(b)) => {
+                Ok(FieldValue::Duration(a.saturating_sub(*b)))
             }
             _ => Err(format!("Can't substract {:?} and {:?}", self, other)),
         }
@@ -181,8 +731,20 @@ impl FieldValue {
 
     pub fn multiply(&self, other: &Self) -> Result<Self, String> {
         match (self, other) {
-            (FieldValue::Number(n), FieldValue::Number(other_n)) => {
-                Ok(FieldValue::Number(n * other_n))
+            (FieldValue::Integer(n), FieldValue::Integer(other_n)) => {
+                Ok(integer_or_float(n.checked_mul(*other_n), || {
+                    *n as f64 * *other_n as f64
+                }))
+            }
+            _ if self.is_number() && other.is_number() => {
+                Ok(FieldValue::Float(self.float() * other.float()))
+            }
+            (FieldValue::Duration(seconds), n) | (n, FieldValue::Duration(seconds))
+                if n.is_number() =>
+            {
+                Ok(FieldValue::Duration(
+                    (*seconds as f64 * n.float()).round() as i64
+                ))
             }
             _ => Err(format!("Can't multiply {:?} and {:?}", self, other)),
         }
@@ -190,11 +752,27 @@ impl FieldValue {
 
     pub fn divide(&self, other: &Self) -> Result<Self, String> {
         match (self, other) {
-            (FieldValue::Number(n), FieldValue::Number(other_n)) => {
-                if *other_n == 0.0 {
+            // Always a float, `7 / 2` is 3.5
+            _ if self.is_number() && other.is_number() => {
+                if other.float() == 0.0 {
+                    return Err("Division by zero!".to_string());
+                }
+                Ok(FieldValue::Float(self.float() / other.float()))
+            }
+            (FieldValue::Duration(seconds), n) if n.is_number() => {
+                if n.float() == 0.0 {
                     return Err("Division by zero!".to_string());
                 }
-                Ok(FieldValue::Number(n / other_n))
+                Ok(FieldValue::Duration(
+                    (*seconds as f64 / n.float()).round() as i64
+                ))
+            }
+            // How many times one fits in the other, e.g. `(due - TODAY()) / DURATION('1w')`
+            (FieldValue::Duration(seconds), FieldValue::Duration(other_seconds)) => {
+                if *other_seconds == 0 {
+                    return Err("Division by zero!".to_string());
+                }
+                Ok(FieldValue::Float(*seconds as f64 / *other_seconds as f64))
             }
             _ => Err(format!("Can't divide {:?} and {:?}", self, other)),
         }
@@ -202,8 +780,16 @@ impl FieldValue {
 
     pub fn power(&self, other: &Self) -> Result<Self, String> {
         match (self, other) {
-            (FieldValue::Number(n), FieldValue::Number(other_n)) => {
-                Ok(FieldValue::Number(n.powf(*other_n)))
+            (FieldValue::Integer(n), FieldValue::Integer(other_n)) if *other_n >= 0 => {
+                Ok(integer_or_float(
+                    u32::try_from(*other_n)
+                        .ok()
+                        .and_then(|exponent| n.checked_pow(exponent)),
+                    || (*n as f64).powf(*other_n as f64),
+                ))
+            }
+            _ if self.is_number() && other.is_number() => {
+                Ok(FieldValue::Float(self.float().powf(other.float())))
             }
             _ => Err(format!("Can't power {:?} and {:?}", self, other)),
         }
@@ -211,15 +797,38 @@ impl FieldValue {
 
     pub fn floor_divide(&self, other: &Self) -> Result<Self, String> {
         match (self, other) {
-            (FieldValue::Number(n), FieldValue::Number(other_n)) => {
-                if *other_n == 0.0 {
+            (FieldValue::Integer(n), FieldValue::Integer(other_n)) => {
+                if *other_n == 0 {
                     return Err("Division by zero!".to_string());
                 }
-                Ok(FieldValue::Number((n / other_n).floor()))
+                // Rounds down also for negative results, like floats do
+                let rounds_down = n.checked_rem(*other_n).is_some_and(|rest| rest != 0)
+                    && (*n < 0) != (*other_n < 0);
+                Ok(integer_or_float(
+                    n.checked_div(*other_n)
+                        .map(|quotient| quotient - rounds_down as i64),
+                    || (*n as f64 / *other_n as f64).floor(),
+                ))
+            }
+            _ if self.is_number() && other.is_number() => {
+                if other.float() == 0.0 {
+                    return Err("Division by zero!".to_string());
+                }
+                Ok(FieldValue::Float((self.float() / other.float()).floor()))
             }
             _ => Err(format!("Can't floor divide {:?} and {:?}", self, other)),
         }
     }
+
+    // Value of a number as a float, call only when `is_number`
+    fn float(&self) -> f64 {
+        self.as_f64().unwrap_or_default()
+    }
+}
+
+// Integer result of integer arithmetic, or the float one when it overflows
+fn integer_or_float(integer: Option<i64>, float: impl FnOnce() -> f64) -> FieldValue {
+    integer.map_or_else(|| FieldValue::Float(float()), FieldValue::Integer)
 }
 
 impl Display for FieldValue {
@@ -230,8 +839,10 @@ impl Display for FieldValue {
             match self {
                 FieldValue::Null => "NULL".to_string(),
                 FieldValue::String(s) => s.clone(),
-                FieldValue::Number(n) => n.to_string(),
+                FieldValue::Integer(n) => n.to_string(),
+                FieldValue::Float(n) => n.to_string(),
                 FieldValue::Bool(b) => b.to_string(),
+                FieldValue::Duration(seconds) => duration_to_string(*seconds),
                 FieldValue::List(list) => {
                     let elements: Vec<String> = list.iter().map(|item| item.to_string()).collect();
                     format!("[{}]", elements.join(", "))
@@ -241,10 +852,34 @@ impl Display for FieldValue {
     }
 }
 
-#[derive(Debug, PartialEq)]
+/// Units of durations, in seconds, from the largest, e.g. `3d 4h 30m`
+const DURATION_UNITS: [(&str, i64); 4] = [("d", 86400), ("h", 3600), ("m", 60), ("s", 1)];
+
+// `-3d 4h 30m`, without the units that are 0
+fn duration_to_string(seconds: i64) -> String {
+    let mut rest = seconds.unsigned_abs();
+    let mut parts = Vec::new();
+    for (unit, unit_seconds) in DURATION_UNITS {
+        let count = rest / unit_seconds as u64;
+        rest %= unit_seconds as u64;
+        if count > 0 {
+            parts.push(format!("{}{}", count, unit));
+        }
+    }
+    match (parts.is_empty(), seconds < 0) {
+        (true, _) => "0s".to_string(),
+        (false, true) => format!("-{}", parts.join(" ")),
+        (false, false) => parts.join(" "),
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
 pub struct OrderByFieldOption {
     pub field_name: String,
     pub order_direction: OrderDirection,
+    /// Function computing the sort key of every row, e.g. `SIMILARITY(title, 'weekly review')`,
+    /// `field_name` is its query text then
+    pub function: Option<Function>,
 }
 
 impl OrderByFieldOption {
@@ -252,743 +887,3673 @@ impl OrderByFieldOption {
         OrderByFieldOption {
             field_name,
             order_direction,
+            function: None,
+        }
+    }
+
+    pub fn by_function(function: Function, order_direction: OrderDirection) -> Self {
+        OrderByFieldOption {
+            field_name: function.to_string(),
+            order_direction,
+            function: Some(function),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct GroupByField {
+    pub field_name: String,
+    /// Function computing the group value of every row, e.g. `DATE_TRUNC('week', created)`,
+    /// `field_name` is its query text then, which is also how SELECT refers to it
+    pub function: Option<Function>,
+}
+
+impl GroupByField {
+    pub fn new(field_name: String) -> Self {
+        GroupByField {
+            field_name,
+            function: None,
+        }
+    }
+
+    pub fn by_function(function: Function) -> Self {
+        GroupByField {
+            field_name: function.to_string(),
+            function: Some(function),
         }
     }
 }
 
-#[derive(Debug, PartialEq)]
+impl Display for GroupByField {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.field_name)
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
 pub enum OrderDirection {
     ASC,
     DESC,
 }
 
-#[derive(Debug)]
+impl Display for OrderDirection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OrderDirection::ASC => write!(f, "ASC"),
+            OrderDirection::DESC => write!(f, "DESC"),
+        }
+    }
+}
+
+impl Display for OrderByFieldOption {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}", self.field_name, self.order_direction)
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Default)]
 pub struct Query {
-    pub select_fields: Vec<String>, // TODO: add suport for functions and AS
+    pub select_fields: Vec<String>,
+    /// SELECT fields named with AS, as (alias, field), e.g. `DATE(created) AS day` is
+    /// `("day", "DATE(created)")`. `select_fields` has the alias.
+    pub select_aliases: Vec<(String, String)>,
+    /// Top-level fields `*` leaves out, e.g. `SELECT * EXCLUDE (file, today)`
+    pub select_exclude: Vec<String>,
     pub from_function: Option<Function>,
     pub where_expression: Vec<ExpressionElement>,
+    pub group_by_fields: Vec<GroupByField>,
     pub order_by_fields: Vec<OrderByFieldOption>,
+    /// Rows kept after ORDER BY (and OFFSET), all of them when `None`
+    pub limit: Option<usize>,
+    /// Fields LIMIT keeps rows for every distinct value of (`LIMIT 3 PER project`), instead of
+    /// for the whole result
+    pub limit_per: Vec<String>,
+    /// Rows skipped after ORDER BY
+    pub offset: Option<usize>,
+    pub spans: QuerySpans,
+}
+
+/// Parsed SELECT clause, see [`Query::select_aliases`] and [`Query::select_exclude`]
+#[derive(Default)]
+struct SelectClause {
+    fields: Vec<String>,
+    aliases: Vec<(String, String)>,
+    exclude: Vec<String>,
+}
+
+/// Where the clauses and WHERE elements of a parsed query are in its source text, so errors can
+/// point at the fragment that caused them. Empty for queries built in code.
+#[derive(Debug, Clone, Default)]
+pub struct QuerySpans {
+    pub source: String,
+    pub select: Option<Span>,
+    pub from: Option<Span>,
+    pub where_clause: Option<Span>,
+    pub group_by: Option<Span>,
+    pub order_by: Option<Span>,
+    /// One for every element of `where_expression`
+    pub where_expression: Vec<Span>,
+}
+
+/// Spans don't change what the query does, so two queries that differ only in formatting are equal.
+impl PartialEq for QuerySpans {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+impl QuerySpans {
+    /// Source text of `span`.
+    pub fn fragment(&self, span: Span) -> String {
+        self.source
+            .chars()
+            .skip(span.start)
+            .take(span.end.saturating_sub(span.start))
+            .collect()
+    }
+
+    /// Span from WHERE element `first` to WHERE element `last` (inclusive), if they are known.
+    pub fn where_elements(&self, first: usize, last: usize) -> Option<Span> {
+        let start = self.where_expression.get(first)?.start;
+        let end = self.where_expression.get(last)?.end;
+        Some(Span { start, end })
+    }
+
+    /// Appends to `error` where in the query `span` is, e.g.
+    /// "AND operator expects operands to be bools! — at characters 41..57: `created AND tags`".
+    pub fn locate(&self, error: &str, span: Option<Span>) -> String {
+        match span {
+            Some(span) if span.end <= self.source.chars().count() => format!(
+                "{} — at characters {}..{}: `{}`",
+                error,
+                span.start,
+                span.end,
+                self.fragment(span)
+            ),
+            _ => error.to_string(),
+        }
+    }
 }
 
 impl FromStr for Query {
     type Err = String;
 
     fn from_str(query: &str) -> Result<Self, Self::Err> {
-        let mut peekable_query: PeekableDeque<char> = PeekableDeque::from_iter(query.chars());
-        Query::parse_whitespaces(&mut peekable_query);
-
-        let mut select_fields = Vec::new();
-        if let Some(&peeked_char) = peekable_query.peek() {
-            if peeked_char == 's' || peeked_char == 'S' {
-                select_fields = match Query::parse_select(&mut peekable_query) {
-                    Ok(sf) => sf,
-                    Err(error) => {
-                        return Err(format!(
-                            "Error parsing SELECT: {}, Query: \"{}\"",
-                            error, peekable_query
-                        ))
-                    }
-                };
-            }
+        let mut tokens = Query::tokens(query)?;
+        let mut spans = QuerySpans {
+            source: query.to_string(),
+            ..QuerySpans::default()
+        };
+        let definitions = Definition::parse_all(&mut tokens)
+            .map_err(|error| Query::error_at("DEFINE", &error, query, &tokens, &[]))?;
+        let statement_start = Query::next_start(&tokens);
+
+        let mut select = SelectClause::default();
+        if Query::starts_clause(&tokens, "SELECT") {
+            let start = Query::next_start(&tokens);
+            select = Query::parse_select(&mut tokens, query)
+                .map_err(|error| Query::error_at("SELECT", &error, query, &tokens, &[]))?;
+            spans.select = Some(Query::span_from(&tokens, start));
         }
 
-        // parse_SELECT parses whitespace after its fields
-
         let mut from_function = None;
-        if let Some(&peeked_char) = peekable_query.peek() {
-            if peeked_char == 'f' || peeked_char == 'F' {
-                from_function = match Query::parse_from(&mut peekable_query) {
-                    Ok(ft) => Some(ft),
-                    Err(error) => {
-                        return Err(format!(
-                            "Error parsing FROM: {}, Query: \"{}\"",
-                            error, peekable_query
-                        ))
-                    }
-                };
-            }
+        let mut from_tags = Vec::new();
+        if Query::starts_clause(&tokens, "FROM") {
+            let start = Query::next_start(&tokens);
+            let (function, tags) = Query::parse_from(&mut tokens)
+                .map_err(|error| Query::error_at("FROM", &error, query, &tokens, &[]))?;
+            from_function = Some(function);
+            from_tags = tags;
+            spans.from = Some(Query::span_from(&tokens, start));
         }
 
-        if !peekable_query.end() && from_function.is_some() {
-            if let Err(error) = Query::parse_mandatory_whitespace(&mut peekable_query) {
-                return Err(format!("{} Query: \"{}\"", error, peekable_query));
+        if !tokens.end() && !Query::at_semicolon(&tokens) && from_function.is_some() {
+            if let Err(error) = Query::parse_mandatory_whitespace(&tokens) {
+                return Err(Query::error_at(
+                    "FROM",
+                    &error,
+                    query,
+                    &tokens,
+                    &["WHERE", "GROUP BY", "ORDER BY", "LIMIT", "OFFSET"],
+                ));
             }
         }
-        Query::parse_whitespaces(&mut peekable_query);
 
         let mut where_expression = Vec::new();
-        if let Some(&peeked_char) = peekable_query.peek() {
-            if peeked_char == 'w' || peeked_char == 'W' {
-                where_expression = match Query::parse_where(&mut peekable_query) {
-                    Ok(we) => we,
-                    Err(error) => {
-                        return Err(format!(
-                            "Error parsing WHERE: {}, Query: \"{}\"",
-                            error, peekable_query
-                        ));
-                    }
-                };
-            }
+        if Query::starts_clause(&tokens, "WHERE") {
+            let start = Query::next_start(&tokens);
+            let (expression, expression_spans) = Query::parse_where(&mut tokens)
+                .map_err(|error| Query::error_at("WHERE", &error, query, &tokens, &[]))?;
+            (where_expression, spans.where_expression) =
+                expand_macros(&expression, &expression_spans, &definitions);
+            spans.where_clause = Some(Query::span_from(&tokens, start));
+        }
+        if !from_tags.is_empty() {
+            (where_expression, spans.where_expression) =
+                Query::and_from_tags(from_tags, where_expression, &spans);
         }
 
-        // in some cases where parses whitespace, in some not, so ORDER BY would technically work
-        // even without whitespace atm, but not a huge problem, so won't deal with it for now
-        //if !where_expression.is_empty() {
-        //    Query::parse_mandatory_whitespace(&mut peekable_query)?;
-        //    Query::parse_whitespaces(&mut peekable_query);
-        //}
-        Query::parse_whitespaces(&mut peekable_query);
+        let mut group_by_fields = Vec::new();
+        if Query::starts_clause(&tokens, "GROUP") {
+            let start = Query::next_start(&tokens);
+            group_by_fields = Query::parse_group_by(&mut tokens, &select)
+                .map_err(|error| Query::error_at("GROUP BY", &error, query, &tokens, &[]))?;
+            spans.group_by = Some(Query::span_from(&tokens, start));
+        }
 
         let mut order_by_fields = Vec::new();
-        if let Some(&peeked_char) = peekable_query.peek() {
-            if peeked_char == 'o' || peeked_char == 'O' {
-                order_by_fields = match Query::parse_order_by(&mut peekable_query) {
-                    Ok(ob) => ob,
-                    Err(error) => {
-                        return Err(format!(
-                            "Error parsing ORDER BY: {}, Query: \"{}\"",
-                            error, peekable_query
-                        ));
-                    }
-                };
+        if Query::starts_clause(&tokens, "ORDER") {
+            let start = Query::next_start(&tokens);
+            order_by_fields = Query::parse_order_by(&mut tokens, &select.fields)
+                .map_err(|error| Query::error_at("ORDER BY", &error, query, &tokens, &[]))?;
+            spans.order_by = Some(Query::span_from(&tokens, start));
+        }
+
+        let mut limit = None;
+        let mut limit_per = Vec::new();
+        if Query::starts_clause(&tokens, "LIMIT") {
+            limit = Some(
+                Query::parse_row_count(&mut tokens, "LIMIT")
+                    .map_err(|error| Query::error_at("LIMIT", &error, query, &tokens, &[]))?,
+            );
+            if Query::starts_clause(&tokens, "PER") {
+                limit_per = Query::parse_per(&mut tokens)
+                    .map_err(|error| Query::error_at("LIMIT", &error, query, &tokens, &[]))?;
             }
         }
 
-        //if let Some(&peeked_char) = peekable_query.peek() {
-        //    return Err(format!("Unexpected character: {}", peeked_char));
-        //}
+        let mut offset = None;
+        if Query::starts_clause(&tokens, "OFFSET") {
+            offset = Some(
+                Query::parse_row_count(&mut tokens, "OFFSET")
+                    .map_err(|error| Query::error_at("OFFSET", &error, query, &tokens, &[]))?,
+            );
+        }
 
-        Ok(Query::new(
-            select_fields,
-            from_function,
-            where_expression,
-            order_by_fields,
-        ))
-    }
-}
+        let ended_with_semicolon = Query::at_semicolon(&tokens);
+        if ended_with_semicolon {
+            tokens.next();
+        }
 
-impl Query {
-    pub fn new(
-        select_fields: Vec<String>,
-        from_function: Option<Function>,
-        where_expression: Vec<ExpressionElement>,
-        order_by_fields: Vec<OrderByFieldOption>,
-    ) -> Self {
-        Query {
-            select_fields,
-            from_function,
-            where_expression,
-            order_by_fields,
-        }
-    }
-
-    pub fn parse_select(peekable_query: &mut PeekableDeque<char>) -> Result<Vec<String>, String> {
-        match Query::parse_keyword(peekable_query, "SELECT", false) {
-            Ok(()) => {}
-            Err(error) => return Err(error),
+        if let Some(token) = tokens.peek() {
+            // Nothing was parsed, the statement itself does not start right
+            if token.span.start == statement_start && !ended_with_semicolon {
+                let mut error = Query::unexpected(token);
+                if let TokenKind::Identifier(name) = &token.kind {
+                    if !CLAUSE_KEYWORDS
+                        .iter()
+                        .any(|keyword| token.is_keyword(keyword))
+                    {
+                        error = with_suggestion(error, name, STATEMENT_KEYWORDS);
+                    }
+                }
+                return Err(Query::error_at(
+                    "query",
+                    &error,
+                    query,
+                    &tokens,
+                    &STATEMENT_KEYWORDS,
+                ));
+            }
+            // Everything that could have continued the query at this point
+            let mut expected = Vec::new();
+            if ended_with_semicolon {
+                return Err(Query::error_at(
+                    "query",
+                    &format!("{}, nothing can follow ';'", Query::unexpected(token)),
+                    query,
+                    &tokens,
+                    &expected,
+                ));
+            }
+            let after_order_by = limit.is_some() || offset.is_some();
+            if !where_expression.is_empty()
+                && group_by_fields.is_empty()
+                && order_by_fields.is_empty()
+                && !after_order_by
+            {
+                expected.push("an operator");
+            }
+            let clauses = [
+                (
+                    "FROM",
+                    from_function.is_none() && where_expression.is_empty(),
+                ),
+                (
+                    "WHERE",
+                    where_expression.is_empty() && group_by_fields.is_empty(),
+                ),
+                ("GROUP BY", group_by_fields.is_empty()),
+                ("ORDER BY", order_by_fields.is_empty()),
+            ];
+            if order_by_fields.is_empty() && !after_order_by {
+                expected.extend(
+                    clauses
+                        .iter()
+                        .filter(|(_, possible)| *possible)
+                        .map(|(clause, _)| *clause),
+                );
+            }
+            if !after_order_by {
+                expected.push("LIMIT");
+            }
+            if limit.is_some() && limit_per.is_empty() && offset.is_none() {
+                expected.push("PER");
+            }
+            if offset.is_none() {
+                expected.push("OFFSET");
+            }
+            let mut error = Query::unexpected(token);
+            if let TokenKind::Identifier(name) = &token.kind {
+                // A clause keyword here is out of order, not misspelled
+                if !CLAUSE_KEYWORDS
+                    .iter()
+                    .any(|keyword| token.is_keyword(keyword))
+                {
+                    error = with_suggestion(error, name, CLAUSE_KEYWORDS);
+                }
+            }
+            return Err(Query::error_at("query", &error, query, &tokens, &expected));
         }
-        Query::parse_mandatory_whitespace(peekable_query)?;
 
-        let mut select_fields: Vec<String> = Vec::new();
-
-        loop {
-            Query::parse_whitespaces(peekable_query);
-
-            match Query::parse_field_name(peekable_query) {
-                Ok(field_name) => select_fields.push(field_name),
-                Err(error) => return Err(error),
-            }
+        let query = Query {
+            spans,
+            select_aliases: select.aliases,
+            select_exclude: select.exclude,
+            group_by_fields,
+            limit,
+            limit_per,
+            offset,
+            ..Query::new(
+                select.fields,
+                from_function,
+                where_expression,
+                order_by_fields,
+            )
+        };
+        query.check_grouping()?;
 
-            Query::parse_whitespaces(peekable_query);
+        Ok(query)
+    }
+}
 
-            if let Some(&peeked_char) = peekable_query.peek() {
-                if peeked_char != ',' {
-                    break;
-                }
+/// Formats the query back into (single line) query text that parses into the same query. The
+/// alternate flag (`{:#}`) puts every clause on its own line, see [`Query::to_pretty_string`].
+impl Display for Query {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut clauses = Vec::new();
+        if !self.select_fields.is_empty() {
+            let select: Vec<String> = self
+                .select_fields
+                .iter()
+                .map(|field| match self.alias(field) {
+                    Some(aliased) => format!("{} AS {}", aliased, field),
+                    None if field == SELECT_ALL && !self.select_exclude.is_empty() => {
+                        format!("{} EXCLUDE ({})", field, self.select_exclude.join(", "))
+                    }
+                    None => field.clone(),
+                })
+                .collect();
+            clauses.push(format!("SELECT {}", select.join(", ")));
+        }
+        if let Some(from_function) = &self.from_function {
+            clauses.push(format!("FROM {}", from_function));
+        }
+        if !self.where_expression.is_empty() {
+            let where_expression = if f.alternate() {
+                expression_to_lines(&self.where_expression, "  ")
             } else {
-                break;
+                expression_to_string(&self.where_expression)
+            };
+            clauses.push(format!("WHERE {}", where_expression));
+        }
+        if !self.group_by_fields.is_empty() {
+            let group_by: Vec<String> = self
+                .group_by_fields
+                .iter()
+                .map(|group_by| group_by.to_string())
+                .collect();
+            clauses.push(format!("GROUP BY {}", group_by.join(", ")));
+        }
+        if !self.order_by_fields.is_empty() {
+            let order_by: Vec<String> = self
+                .order_by_fields
+                .iter()
+                .map(|ob| ob.to_string())
+                .collect();
+            clauses.push(format!("ORDER BY {}", order_by.join(", ")));
+        }
+        if let Some(limit) = self.limit {
+            match self.limit_per.is_empty() {
+                true => clauses.push(format!("LIMIT {}", limit)),
+                false => clauses.push(format!("LIMIT {} PER {}", limit, self.limit_per.join(", "))),
             }
+        }
+        if let Some(offset) = self.offset {
+            clauses.push(format!("OFFSET {}", offset));
+        }
+        let separator = if f.alternate() { "\n" } else { " " };
+        write!(f, "{}", clauses.join(separator))
+    }
+}
 
-            peekable_query.next();
+impl Query {
+    /// Formats a parse `error` of `clause` with the line/column of char `offset` in `source`, a
+    /// caret under it and, when known, what was `expected` there.
+    pub fn parse_error(
+        clause: &str,
+        error: &str,
+        source: &str,
+        offset: usize,
+        expected: &[&str],
+    ) -> String {
+        let (line, column) = line_column(source, offset);
+        let mut message = format!(
+            "Error parsing {}: {} (line {}, column {})\n{}",
+            clause,
+            error,
+            line,
+            column,
+            caret_snippet(source, offset)
+        );
+        if !expected.is_empty() {
+            message.push_str(&format!("\nExpected one of: {}", expected.join(", ")));
         }
+        message
+    }
 
-        Ok(select_fields)
+    // parse_error at the token the parser stopped at, or at the end of the query
+    fn error_at(
+        clause: &str,
+        error: &str,
+        source: &str,
+        tokens: &PeekableDeque<Token>,
+        expected: &[&str],
+    ) -> String {
+        let offset = match tokens.peek() {
+            Some(token) => token.span.start,
+            None => source.chars().count(),
+        };
+        Query::parse_error(clause, error, source, offset, expected)
     }
 
-    pub fn parse_from(peekable_query: &mut PeekableDeque<char>) -> Result<Function, String> {
-        match Query::parse_keyword(peekable_query, "FROM", false) {
-            Ok(()) => {}
-            Err(error) => return Err(error),
+    fn unexpected(token: &Token) -> String {
+        match token.kind {
+            TokenKind::Unknown(c) => format!("Unexpected character '{}'", c),
+            _ => format!("Unexpected token '{}'", token),
         }
+    }
 
-        Query::parse_mandatory_whitespace(peekable_query)?;
-        Query::parse_whitespaces(peekable_query);
+    fn tokens(source: &str) -> Result<PeekableDeque<Token>, String> {
+        tokenize(source)
+            .map(PeekableDeque::from_iter)
+            .map_err(|error| Query::parse_error("query", &error.message, source, error.offset, &[]))
+    }
 
-        Query::parse_function(peekable_query, None)
+    /// Canonical multi-line version of the query, e.g.
+    /// ```text
+    /// SELECT title, status
+    /// FROM FRONTMATTER_DATA('~/notes')
+    /// WHERE status == 'open'
+    ///   AND (priority > 2 OR 'urgent' IN tags)
+    /// ORDER BY priority DESC
+    /// ```
+    pub fn to_pretty_string(&self) -> String {
+        format!("{:#}", self)
     }
 
-    // call only when you expect WHERE should happen
-    fn parse_where(
-        peekable_query: &mut PeekableDeque<char>,
-    ) -> Result<Vec<ExpressionElement>, String> {
-        match Query::parse_keyword(peekable_query, "WHERE", false) {
-            Ok(()) => {}
-            Err(error) => return Err(error),
+    /// Parses a WHERE condition on its own, e.g. `"status == 'done' OR priority > 2"`.
+    pub fn parse_condition(condition: &str) -> Result<Vec<ExpressionElement>, String> {
+        let mut tokens = Query::tokens(condition)?;
+        let mut expression = Vec::new();
+        Query::parse_expression(&mut tokens, &mut expression, &mut Vec::new())
+            .map_err(|error| Query::error_at("WHERE", &error, condition, &tokens, &[]))?;
+        if let Some(token) = tokens.peek() {
+            return Err(Query::error_at(
+                "WHERE",
+                &Query::unexpected(token),
+                condition,
+                &tokens,
+                &["an operator"],
+            ));
         }
-        Query::parse_mandatory_whitespace(peekable_query)?;
-        Query::parse_whitespaces(peekable_query);
 
-        let mut where_expression: Vec<ExpressionElement> = Vec::new();
+        Ok(expression)
+    }
 
-        match Query::parse_expression(peekable_query, &mut where_expression) {
-            Ok(()) => {}
-            Err(error) => return Err(error),
+    /// Parses a comma separated list of fields as used after SELECT, e.g. `"title, tags"`.
+    pub fn parse_select_fields(fields: &str) -> Result<Vec<String>, String> {
+        let mut tokens = Query::tokens(fields)?;
+        let select_fields = Query::parse_field_names(&mut tokens, fields)
+            .map_err(|error| Query::error_at("SELECT", &error, fields, &tokens, &[]))?;
+        if let Some(token) = tokens.peek() {
+            return Err(Query::error_at(
+                "SELECT",
+                &Query::unexpected(token),
+                fields,
+                &tokens,
+                &[","],
+            ));
         }
 
-        Ok(where_expression)
+        Ok(select_fields)
     }
 
-    // call only when you expect ORDER BY should happen
-    fn parse_order_by(
-        peekable_query: &mut PeekableDeque<char>,
-    ) -> Result<Vec<OrderByFieldOption>, String> {
-        match Query::parse_keyword(peekable_query, "ORDER BY", false) {
-            Ok(()) => {}
-            Err(error) => return Err(error),
+    /// Parses a function as used after FROM, e.g. `"FRONTMATTER_DATA('~/notes')"`.
+    pub fn parse_from_function(from: &str) -> Result<Function, String> {
+        let mut tokens = Query::tokens(from)?;
+        let from_function = Query::parse_function(&mut tokens)
+            .map_err(|error| Query::error_at("FROM", &error, from, &tokens, &[]))?;
+        if let Some(token) = tokens.peek() {
+            return Err(Query::error_at(
+                "FROM",
+                &Query::unexpected(token),
+                from,
+                &tokens,
+                &[],
+            ));
         }
-        Query::parse_mandatory_whitespace(peekable_query)?;
 
-        let mut order_by_options = Vec::new();
+        Ok(from_function)
+    }
 
-        loop {
-            Query::parse_whitespaces(peekable_query);
+    pub fn select(mut self, field_name: &str) -> Self {
+        self.select_fields.push(field_name.to_string());
+        self
+    }
 
-            let field_name = match Query::parse_field_name(peekable_query) {
-                Ok(field_name) => field_name,
-                Err(error) => return Err(error),
-            };
-            Query::parse_whitespaces(peekable_query);
+    pub fn from(mut self, from_function: Function) -> Self {
+        self.from_function = Some(from_function);
+        self
+    }
 
-            let mut order_direction = OrderDirection::ASC;
-            if let Some(&peeked_char) = peekable_query.peek() {
-                if peeked_char != ',' {
-                    match Query::parse_sort_direction(peekable_query) {
-                        Ok(od) => order_direction = od,
-                        Err(error) => return Err(error),
-                    }
-                }
-            }
-            order_by_options.push(OrderByFieldOption::new(field_name, order_direction));
+    /// Adds `condition` to WHERE with AND, bracketing either side when needed.
+    pub fn and_where(mut self, condition: Vec<ExpressionElement>) -> Self {
+        self.clear_where_spans();
+        self.where_expression = if self.where_expression.is_empty() {
+            condition
+        } else {
+            let mut expression = bracket_if_or(std::mem::take(&mut self.where_expression));
+            expression.push(ExpressionElement::Operator(Operator::And));
+            expression.extend(bracket_if_or(condition));
+            expression
+        };
+        self
+    }
 
-            if let Some(&peeked_char) = peekable_query.peek() {
-                if peeked_char != ',' {
-                    break;
-                }
-                peekable_query.next();
-            } else {
-                break;
-            }
+    /// Adds `condition` to WHERE with OR.
+    pub fn or_where(mut self, condition: Vec<ExpressionElement>) -> Self {
+        self.clear_where_spans();
+        if !self.where_expression.is_empty() {
+            self.where_expression
+                .push(ExpressionElement::Operator(Operator::Or));
         }
+        self.where_expression.extend(condition);
+        self
+    }
 
-        Ok(order_by_options)
+    // WHERE built in code does not match the source text anymore
+    fn clear_where_spans(&mut self) {
+        self.spans.where_clause = None;
+        self.spans.where_expression.clear();
     }
 
-    fn parse_expression(
-        peekable_query: &mut PeekableDeque<char>,
-        expression_elements: &mut Vec<ExpressionElement>,
-    ) -> Result<(), String> {
-        if let Some(&peeked_char) = peekable_query.peek() {
-            if peeked_char == '(' {
-                match Query::parse_bracket_expression(peekable_query, expression_elements) {
-                    Ok(()) => {}
-                    Err(error) => return Err(error),
-                }
-            } else {
-                match Query::parse_no_bracket_expression(peekable_query, expression_elements) {
-                    Ok(()) => {}
-                    Err(error) => return Err(error),
-                }
-            }
-        } else {
-            return Err("Expected expression, but found nothing".to_string());
-        }
-        Query::parse_whitespaces(peekable_query);
+    pub fn group_by(mut self, field_name: &str) -> Self {
+        self.group_by_fields
+            .push(GroupByField::new(field_name.to_string()));
+        self
+    }
 
-        Ok(())
+    pub fn order_by(mut self, field_name: &str, order_direction: OrderDirection) -> Self {
+        self.order_by_fields.push(OrderByFieldOption::new(
+            field_name.to_string(),
+            order_direction,
+        ));
+        self
     }
 
-    fn parse_bracket_expression(
-        peekable_query: &mut PeekableDeque<char>,
-        expression_elements: &mut Vec<ExpressionElement>,
-    ) -> Result<(), String> {
-        if let Some(&peeked_char) = peekable_query.peek() {
-            if peeked_char != '(' {
-                return Err(format!("Expected a '(', but found: {}", peeked_char));
-            }
-        }
-        expression_elements.push(ExpressionElement::OpenedBracket);
-        peekable_query.next();
-        Query::parse_whitespaces(peekable_query);
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
 
-        match Query::parse_expression(peekable_query, expression_elements) {
-            Ok(()) => {}
-            Err(error) => return Err(error),
-        }
+    /// Keeps at most `limit` rows for every distinct value of `field_names`.
+    pub fn limit_per(mut self, limit: usize, field_names: &[&str]) -> Self {
+        self.limit = Some(limit);
+        self.limit_per = field_names.iter().map(|name| name.to_string()).collect();
+        self
+    }
+
+    pub fn offset(mut self, offset: usize) -> Self {
+        self.offset = Some(offset);
+        self
+    }
 
-        if let Some(&peeked_char) = peekable_query.peek() {
-            if peeked_char != ')' {
-                return Err(format!("Expected a ')', but found: {}", peeked_char));
+    /// Names of all `:name` parameters used in the query, in order of appearance, without duplicates.
+    pub fn parameters(&self) -> Vec<String> {
+        let mut names: Vec<String> = Vec::new();
+        let mut add = |name: &String| {
+            if !names.contains(name) {
+                names.push(name.clone());
             }
-        } else {
-            return Err("Expected a ')', but found nothing".to_string());
-        }
-        expression_elements.push(ExpressionElement::ClosedBracket);
-        peekable_query.next();
-        Query::parse_whitespaces(peekable_query);
+        };
 
-        match Query::try_parse_operator(peekable_query) {
-            Ok(op) => expression_elements.push(ExpressionElement::Operator(op)),
-            Err(_) => return Ok(()),
+        for arg in self.from_function.iter().flat_map(|func| func.args.iter()) {
+            arg_parameters(arg).iter().for_each(&mut add);
+        }
+        expression_parameters(&self.where_expression)
+            .iter()
+            .for_each(&mut add);
+        let group_by_args = self
+            .group_by_fields
+            .iter()
+            .flat_map(|group_by| group_by.function.iter().flat_map(|func| func.args.iter()));
+        let order_by_args = self
+            .order_by_fields
+            .iter()
+            .flat_map(|order_by| order_by.function.iter().flat_map(|func| func.args.iter()));
+        for arg in group_by_args.chain(order_by_args) {
+            arg_parameters(arg).iter().for_each(&mut add);
         }
-        Query::parse_whitespaces(peekable_query);
 
-        Query::parse_expression(peekable_query, expression_elements)
+        names
     }
 
-    fn parse_no_bracket_expression(
-        peekable_query: &mut PeekableDeque<char>,
-        expression_elements: &mut Vec<ExpressionElement>,
-    ) -> Result<(), String> {
-        match Query::parse_bool_field_name_or_function(peekable_query) {
-            Ok(field_name_or_function) => expression_elements.push(field_name_or_function),
-            Err(_) => match Query::parse_field_value(peekable_query) {
-                Ok(fv) => expression_elements.push(ExpressionElement::FieldValue(fv)),
-                Err(_) => return Err("No FieldValue, Function, nor FieldName found!".to_string()),
-            },
-        }
-        Query::parse_whitespaces(peekable_query);
+    /// Returns a copy of the query with every `:name` parameter replaced by its value from `params`.
+    pub fn bind(&self, params: &HashMap<String, FieldValue>) -> Result<Query, String> {
+        let bind_args = |args: &[FunctionArg]| bind_args(args, params);
 
-        loop {
-            // TODO: allow fields without operator for cases where field is boolean
-            match Query::try_parse_operator(peekable_query) {
-                Ok(op) => expression_elements.push(ExpressionElement::Operator(op)),
-                Err(_) => return Ok(()),
+        let mut query = self.clone();
+        if let Some(func) = &mut query.from_function {
+            func.args = bind_args(&func.args)?;
+        }
+        bind_expression(&mut query.where_expression, params)?;
+        for group_by in &mut query.group_by_fields {
+            if let Some(func) = &mut group_by.function {
+                func.args = bind_args(&func.args)?;
+                let field_name = func.to_string();
+                // SELECT refers to the function by its text
+                for select_field in &mut query.select_fields {
+                    if *select_field == group_by.field_name {
+                        select_field.clone_from(&field_name);
+                    }
+                }
+                group_by.field_name = field_name;
             }
-            Query::parse_whitespaces(peekable_query);
-
-            match Query::parse_expression(peekable_query, expression_elements) {
-                Ok(()) => {}
-                Err(error) => return Err(error),
+        }
+        for order_by in &mut query.order_by_fields {
+            if let Some(func) = &mut order_by.function {
+                func.args = bind_args(&func.args)?;
+                order_by.field_name = func.to_string();
             }
         }
+
+        Ok(query)
     }
 
-    fn try_parse_operator(peekable_query: &mut PeekableDeque<char>) -> Result<Operator, String> {
-        if let Some(&peeked_char) = peekable_query.peek() {
-            if !Operator::get_operator_first_chars().contains(peeked_char.to_ascii_uppercase()) {
-                return Err(format!("No operator starts with {}", peeked_char));
+    /// Names of all `$name` macros used in WHERE (and its subqueries), in order of appearance,
+    /// without duplicates.
+    pub fn macros(&self) -> Vec<String> {
+        let mut names: Vec<String> = Vec::new();
+        for element in &self.where_expression {
+            let nested = match element {
+                ExpressionElement::Macro(name) => vec![name.clone()],
+                ExpressionElement::Subquery(query) | ExpressionElement::Exists(query) => {
+                    query.macros()
+                }
+                _ => Vec::new(),
+            };
+            for name in nested {
+                if !names.contains(&name) {
+                    names.push(name);
+                }
             }
         }
+        names
+    }
+
+    /// Returns a copy of the query with every `$name` macro replaced by the condition of its
+    /// definition. Macros defined at the top of the query are already expanded when it is parsed.
+    pub fn expand_macros(&self, definitions: &[Definition]) -> Result<Query, String> {
+        let mut query = self.clone();
+        (query.where_expression, query.spans.where_expression) = expand_macros(
+            &self.where_expression,
+            &self.spans.where_expression,
+            definitions,
+        );
+
+        let unknown = query
+            .where_expression
+            .iter()
+            .position(|element| matches!(element, ExpressionElement::Macro(_)));
+        if let Some(index) = unknown {
+            let name = query.where_expression[index].to_string();
+            let error = with_suggestion(
+                format!("Unknown macro: {}", name),
+                &name[1..],
+                definitions.iter().map(|d| d.name.as_str()),
+            );
+            return Err(query
+                .spans
+                .locate(&error, query.spans.where_elements(index, index)));
+        }
 
-        let mut potential_opeartor = String::new();
-        let mut operator_candidate = None;
+        Ok(query)
+    }
 
-        while let Some(&peeked_char) = peekable_query.peek() {
-            potential_opeartor.push(peeked_char);
+    /// Whether rows are grouped into one row per group, which they are with GROUP BY or with
+    /// aggregates in SELECT.
+    pub fn is_grouped(&self) -> bool {
+        !self.group_by_fields.is_empty()
+            || self
+                .select_aliases
+                .iter()
+                .map(|(_, field)| field)
+                .chain(&self.select_fields)
+                .any(|field_name| field_name.parse::<Aggregate>().is_ok())
+    }
 
-            if let Ok(parsed_operator) = potential_opeartor.parse::<Operator>() {
-                operator_candidate = Some(parsed_operator);
-            } else if let Some(operator) = operator_candidate {
-                if potential_opeartor.chars().nth(0).unwrap().is_alphabetic()
-                    && !peeked_char.is_whitespace()
-                {
-                    peekable_query.back(potential_opeartor.len() - 1);
-                    return Err("Whitespace expected after alphabetic operator!".to_string());
+    /// The field or function a SELECT alias stands for, e.g. `DATE(created)` of `day` in
+    /// `SELECT DATE(created) AS day`.
+    pub fn alias(&self, field_name: &str) -> Option<&String> {
+        self.select_aliases
+            .iter()
+            .find(|(alias, _)| alias == field_name)
+            .map(|(_, field)| field)
+    }
+
+    /// Aggregates of SELECT and ORDER BY, without duplicates.
+    pub fn aggregates(&self) -> Vec<Aggregate> {
+        let order_by = self.order_by_fields.iter().map(|ob| &ob.field_name);
+        let select = self
+            .select_fields
+            .iter()
+            .map(|field_name| self.alias(field_name).unwrap_or(field_name));
+        let mut aggregates = Vec::new();
+        for field_name in select.chain(order_by) {
+            if let Ok(aggregate) = field_name.parse::<Aggregate>() {
+                if !aggregates.contains(&aggregate) {
+                    aggregates.push(aggregate);
                 }
-                return Ok(operator);
             }
+        }
+        aggregates
+    }
 
-            peekable_query.next();
+    /// Whether WHERE has `(SELECT ...)` or `EXISTS (SELECT ...)`. They are run once, before
+    /// WHERE, and replaced with their results (they can not refer to the rows of this query).
+    pub fn has_subqueries(&self) -> bool {
+        self.where_expression.iter().any(|element| {
+            matches!(
+                element,
+                ExpressionElement::Subquery(_) | ExpressionElement::Exists(_)
+            )
+        })
+    }
+
+    /// Checks that SELECT and ORDER BY of a grouped query only have GROUP BY fields and
+    /// aggregates, other fields have no single value in a group.
+    pub fn check_grouping(&self) -> Result<(), String> {
+        if !self.is_grouped() {
+            return Ok(());
         }
+        let is_grouped_field = |field_name: &String| {
+            self.group_by_fields
+                .iter()
+                .any(|group_by| &group_by.field_name == field_name)
+                || field_name.parse::<Aggregate>().is_ok()
+        };
 
-        if let Some(operator) = operator_candidate {
-            return Ok(operator);
+        for field_name in &self.select_fields {
+            let field_name = self.alias(field_name).unwrap_or(field_name);
+            let error = match field_name.as_str() {
+                SELECT_ALL => "SELECT * can not be used with GROUP BY or aggregates".to_string(),
+                _ if is_grouped_field(field_name) => continue,
+                _ => Query::ungrouped_field(field_name),
+            };
+            return Err(self.spans.locate(&error, self.spans.select));
         }
-        Err("Did not found operator!".to_string())
+        for order_by in &self.order_by_fields {
+            // aliases are checked in SELECT
+            if order_by.function.is_none()
+                && !is_grouped_field(&order_by.field_name)
+                && self.alias(&order_by.field_name).is_none()
+            {
+                let error = Query::ungrouped_field(&order_by.field_name);
+                return Err(self.spans.locate(&error, self.spans.order_by));
+            }
+        }
+
+        Ok(())
     }
 
-    fn parse_field_value(peekable_query: &mut PeekableDeque<char>) -> Result<FieldValue, String> {
-        if let Ok(str) = Query::parse_string(peekable_query) {
-            return Ok(FieldValue::String(str));
-        }
-        if let Ok(num) = Query::parse_number(peekable_query) {
-            return Ok(FieldValue::Number(num));
+    /// The expression a SELECT field computes, e.g. `DATE(created, '%Y-%m-%d')` or `n * 2`,
+    /// `None` for plain fields, `*` and aggregates.
+    pub fn select_expression(field_name: &str) -> Option<Vec<ExpressionElement>> {
+        if field_name == SELECT_ALL || field_name.parse::<Aggregate>().is_ok() {
+            return None;
         }
-        if let Ok(bv) = Query::parse_bool(peekable_query) {
-            return Ok(FieldValue::Bool(bv));
+        match Query::parse_condition(field_name).ok()?.as_slice() {
+            [ExpressionElement::FieldName(_)] => None,
+            expression => Some(expression.to_vec()),
         }
+    }
 
-        Err("No field value found!".to_string())
+    fn ungrouped_field(field_name: &str) -> String {
+        format!(
+            "Field {} is not in GROUP BY, group by it or use an aggregate, e.g. MAX({})",
+            field_name, field_name
+        )
     }
 
-    fn parse_string(peekable_query: &mut PeekableDeque<char>) -> Result<String, String> {
-        if let Some(&peeked_char) = peekable_query.peek() {
-            if peeked_char != '"' && peeked_char != '\'' {
-                return Err(format!(
-                    "Expected a quote symbol, but found: {}",
-                    peeked_char
-                ));
-            }
-        } else {
-            return Err("Expected a quote symbol, but found nothing!".to_string());
+    pub fn new(
+        select_fields: Vec<String>,
+        from_function: Option<Function>,
+        where_expression: Vec<ExpressionElement>,
+        order_by_fields: Vec<OrderByFieldOption>,
+    ) -> Self {
+        Query {
+            select_fields,
+            select_aliases: Vec::new(),
+            select_exclude: Vec::new(),
+            from_function,
+            where_expression,
+            group_by_fields: Vec::new(),
+            order_by_fields,
+            limit: None,
+            limit_per: Vec::new(),
+            offset: None,
+            spans: QuerySpans::default(),
         }
+    }
 
-        let opened_quote = *peekable_query.peek().unwrap();
-        peekable_query.next();
+    // Only the first token of the query can start a clause without whitespace before it
+    fn starts_clause(tokens: &PeekableDeque<Token>, keyword: &str) -> bool {
+        tokens.peek().is_some_and(|token| {
+            token.is_keyword(keyword) && (token.space_before || token.span.start == 0)
+        })
+    }
 
-        let mut str = String::new();
+    fn at_semicolon(tokens: &PeekableDeque<Token>) -> bool {
+        matches!(
+            tokens.peek().map(|token| &token.kind),
+            Some(TokenKind::Semicolon)
+        )
+    }
 
-        while let Some(&peeked_char) = peekable_query.peek() {
-            if peeked_char == opened_quote {
-                peekable_query.next();
-                return Ok(str);
-            }
-            str.push(peeked_char);
-            peekable_query.next();
-        }
+    // Start of the next token, call only when there is one
+    fn next_start(tokens: &PeekableDeque<Token>) -> usize {
+        tokens.peek().map_or(0, |token| token.span.start)
+    }
 
-        Err(format!("Query ended before string ({}) was closed!", str))
+    // From `start` to the end of the last consumed token
+    fn span_from(tokens: &PeekableDeque<Token>, start: usize) -> Span {
+        let end = tokens.previous().map_or(start, |token| token.span.end);
+        Span { start, end }
     }
 
-    fn parse_number(peekable_query: &mut PeekableDeque<char>) -> Result<f64, String> {
-        let mut number = String::new();
+    // Fields, the (alias, field) of the ones named with AS and the fields `*` excludes
+    fn parse_select(
+        tokens: &mut PeekableDeque<Token>,
+        source: &str,
+    ) -> Result<SelectClause, String> {
+        Query::parse_keyword(tokens, "SELECT")?;
+        Query::parse_mandatory_whitespace(tokens)?;
 
-        if let Some(&peeked_char) = peekable_query.peek() {
-            // First char can be minus or a number
-            if !peeked_char.is_numeric() && peeked_char != '-' {
-                return Err(format!("Number can not start with {}!", peeked_char));
+        let mut select = SelectClause::default();
+        loop {
+            let field = Query::parse_select_field(tokens, source)?;
+            if field == SELECT_ALL {
+                select.exclude.extend(Query::parse_exclude(tokens)?);
             }
-            number.push(peeked_char);
-            peekable_query.next();
-        } else {
-            return Err("Number expected. nothing found".to_string());
-        }
-
-        // if first char was -, then next one needs to be a number
-        if number.chars().nth(0).unwrap() == '-' {
-            if let Some(&peeked_char) = peekable_query.peek() {
-                if !peeked_char.is_numeric() {
-                    return Err(format!("Number can not start with {}!", peeked_char));
+            match Query::parse_alias(tokens, &field)? {
+                Some(alias) if select.fields.contains(&alias) => {
+                    return Err(format!("{} is in SELECT more than once", alias))
                 }
-                number.push(peeked_char);
-                peekable_query.next();
-            } else {
-                return Err("Number expected. nothing found".to_string());
+                Some(alias) => {
+                    select.fields.push(alias.clone());
+                    select.aliases.push((alias, field));
+                }
+                None => select.fields.push(field),
             }
-        }
-
-        let mut has_decimal = false;
-        while let Some(&peeked_char) = peekable_query.peek() {
-            if peeked_char == '.' {
-                if has_decimal {
-                    return Err("Can not have multiple decimal signs".to_string());
+            match tokens.peek().map(|token| &token.kind) {
+                Some(TokenKind::Comma) => {
+                    tokens.next();
                 }
-                has_decimal = true;
-            } else if !peeked_char.is_numeric() {
-                break;
+                _ => return Ok(select),
             }
-            number.push(peeked_char);
-            peekable_query.next();
         }
-
-        number.parse::<f64>().map_err(|e| e.to_string())
-    }
-
-    fn parse_bool(_peekable_query: &mut PeekableDeque<char>) -> Result<bool, String> {
-        Err("TODO: implement parse_bool".to_string())
     }
 
-    fn parse_bool_field_name_or_function(
-        peekable_query: &mut PeekableDeque<char>,
-    ) -> Result<ExpressionElement, String> {
-        let field_name = match Query::parse_field_name(peekable_query) {
-            Ok(field_name) => field_name,
-            Err(_) => return Err("No Function, nor FieldName found!".to_string()),
-        };
+    // `EXCLUDE (name, ...)` or `EXCLUDE name` after `*`
+    fn parse_exclude(tokens: &mut PeekableDeque<Token>) -> Result<Vec<String>, String> {
+        if !tokens
+            .peek()
+            .is_some_and(|token| token.is_keyword("EXCLUDE") && token.space_before)
+        {
+            return Ok(Vec::new());
+        }
+        tokens.next();
 
-        if let Some(&peeked_char) = peekable_query.peek() {
-            if peeked_char == '(' {
-                match Query::parse_function(peekable_query, Some(field_name)) {
-                    Ok(func) => return Ok(ExpressionElement::Function(func)),
-                    Err(error) => return Err(error),
+        let bracketed = matches!(
+            tokens.peek().map(|token| &token.kind),
+            Some(TokenKind::OpenedBracket)
+        );
+        if bracketed {
+            tokens.next();
+        } else {
+            Query::parse_mandatory_whitespace(tokens)?;
+        }
+        let mut exclude = Vec::new();
+        loop {
+            let field = Query::parse_field_name(tokens)?;
+            if field.contains(['.', '[']) {
+                return Err(format!(
+                    "EXCLUDE takes top-level fields, {} is nested",
+                    field
+                ));
+            }
+            exclude.push(field);
+            if !bracketed {
+                return Ok(exclude);
+            }
+            match tokens.peek().map(|token| &token.kind) {
+                Some(TokenKind::Comma) => {
+                    tokens.next();
+                }
+                Some(TokenKind::ClosedBracket) => {
+                    tokens.next();
+                    return Ok(exclude);
                 }
+                _ => return Err("Expected ',' or ')' in the EXCLUDE list".to_string()),
             }
         }
+    }
 
-        if let Ok(bool_value) = field_name.parse::<bool>() {
-            return Ok(ExpressionElement::FieldValue(FieldValue::Bool(bool_value)));
+    // `AS name` after a SELECT field
+    fn parse_alias(
+        tokens: &mut PeekableDeque<Token>,
+        field: &str,
+    ) -> Result<Option<String>, String> {
+        if !tokens
+            .peek()
+            .is_some_and(|token| token.is_keyword("AS") && token.space_before)
+        {
+            return Ok(None);
         }
+        tokens.next();
+        Query::parse_mandatory_whitespace(tokens)?;
+        if field == SELECT_ALL {
+            return Err("* can not be named with AS".to_string());
+        }
+        let alias = Query::parse_field_name(tokens)?;
+        if alias.contains(['.', '[']) {
+            return Err(format!(
+                "Alias {} can not be nested, use a name without '.' or '['",
+                alias
+            ));
+        }
+        Ok(Some(alias))
+    }
 
-        if field_name.to_uppercase() == "NULL" {
-            return Ok(ExpressionElement::FieldValue(FieldValue::Null));
+    fn parse_field_names(
+        tokens: &mut PeekableDeque<Token>,
+        source: &str,
+    ) -> Result<Vec<String>, String> {
+        let mut field_names = vec![Query::parse_select_field(tokens, source)?];
+        while let Some(TokenKind::Comma) = tokens.peek().map(|token| &token.kind) {
+            tokens.next();
+            field_names.push(Query::parse_select_field(tokens, source)?);
         }
 
-        Ok(ExpressionElement::FieldName(field_name))
+        Ok(field_names)
     }
 
-    fn parse_function(
-        peekable_query: &mut PeekableDeque<char>,
-        _func_name: Option<String>,
-    ) -> Result<Function, String> {
-        let func_name = match _func_name {
-            Some(_fn) => _fn,
-            None => {
-                // parse it
-                match Query::parse_field_name(peekable_query) {
-                    Ok(field_name) => field_name,
-                    Err(error) => return Err(error),
-                }
+    // Field name, aggregate or function (as their canonical text, e.g. `COUNT(*)`), `*` for all
+    // fields or any other expression, as its text in `source`, e.g. `n * 2` or
+    // `DATE(created).year`
+    fn parse_select_field(
+        tokens: &mut PeekableDeque<Token>,
+        source: &str,
+    ) -> Result<String, String> {
+        if let Some(TokenKind::Symbol(symbol)) = tokens.peek().map(|token| &token.kind) {
+            if symbol == SELECT_ALL {
+                tokens.next();
+                return Ok(SELECT_ALL.to_string());
             }
-        };
+        }
+        if Aggregate::starts(tokens) {
+            return Ok(Aggregate::parse(tokens)?.to_string());
+        }
 
-        let mut args = Vec::new();
+        let start = Query::next_start(tokens);
+        let mut expression = Vec::new();
+        Query::parse_expression(tokens, &mut expression, &mut Vec::new())?;
+        match expression.as_slice() {
+            [ExpressionElement::FieldName(field_name)] => Ok(field_name.clone()),
+            // Upper case, so GROUP BY matches it in any case
+            [ExpressionElement::Function(function)] => {
+                Ok(Function::new(function.name.to_uppercase(), function.args.clone()).to_string())
+            }
+            _ if expression.iter().any(|element| {
+                matches!(
+                    element,
+                    ExpressionElement::Subquery(_)
+                        | ExpressionElement::Exists(_)
+                        | ExpressionElement::Parameter(_)
+                        | ExpressionElement::Macro(_)
+                )
+            }) =>
+            {
+                Err(
+                    "Expressions in SELECT can not use subqueries, parameters or macros"
+                        .to_string(),
+                )
+            }
+            _ => {
+                let span = Query::span_from(tokens, start);
+                Ok(source
+                    .chars()
+                    .skip(span.start)
+                    .take(span.end - span.start)
+                    .collect())
+            }
+        }
+    }
 
-        if let Some(&peeked_char) = peekable_query.peek() {
-            if peeked_char == '(' {
-                peekable_query.next();
-            } else {
-                return Err(format!("Expected '(', but found {}", peeked_char));
+    // The source, and the condition on tags when it is a tag expression, e.g.
+    // `FROM #project AND (#work OR #home)`
+    fn parse_from(
+        tokens: &mut PeekableDeque<Token>,
+    ) -> Result<(Function, Vec<ExpressionElement>), String> {
+        Query::parse_keyword(tokens, "FROM")?;
+        Query::parse_mandatory_whitespace(tokens)?;
+
+        if Query::starts_tag_expression(tokens) {
+            // Notes of the vault, see `KrafnaConfig::vault`
+            let source = Function::new("FRONTMATTER_DATA".to_string(), Vec::new());
+            return Ok((source, Query::parse_tag_expression(tokens)?));
+        }
+        // A name from WITH, e.g. `FROM open_tasks`, is a source without arguments
+        let bare_name = matches!(
+            (tokens.peek().map(|t| &t.kind), tokens.peek_nth(1)),
+            (Some(TokenKind::Identifier(_)), next)
+                if next.is_none_or(|next| next.kind != TokenKind::OpenedBracket || next.space_before)
+        );
+        if bare_name {
+            let source = Function::new(Query::parse_field_name(tokens)?, Vec::new());
+            return Ok((source, Vec::new()));
+        }
+        Ok((Query::parse_function(tokens)?, Vec::new()))
+    }
+
+    // `#tag`, `NOT #tag` or `(#tag ...`
+    fn starts_tag_expression(tokens: &PeekableDeque<Token>) -> bool {
+        let mut index = 0;
+        while let Some(token) = tokens.peek_nth(index) {
+            match &token.kind {
+                TokenKind::Unknown('#') => return true,
+                TokenKind::OpenedBracket => index += 1,
+                _ if token.is_keyword("NOT") => index += 1,
+                _ => return false,
             }
-        } else {
-            return Err("Expected '(', but found nothing".to_string());
         }
+        false
+    }
 
-        let mut found_comma = false;
+    // Tags joined with AND, OR and NOT, as a WHERE condition on `tags`: `#a AND NOT #b` is
+    // `'a' IN tags AND NOT 'b' IN tags`
+    fn parse_tag_expression(
+        tokens: &mut PeekableDeque<Token>,
+    ) -> Result<Vec<ExpressionElement>, String> {
+        let mut expression = Vec::new();
+        let mut depth = 0;
         loop {
-            Query::parse_whitespaces(peekable_query);
-
-            if let Some(&peeked_char) = peekable_query.peek() {
-                if peeked_char == ')' {
-                    if found_comma {
-                        return Err("Can't have ')' after ','!".to_string());
+            loop {
+                match tokens.peek() {
+                    Some(token) if token.kind == TokenKind::OpenedBracket => {
+                        depth += 1;
+                        expression.push(ExpressionElement::OpenedBracket);
                     }
-                    peekable_query.next();
-                    break;
-                } else if !found_comma && !args.is_empty() {
-                    return Err(format!("Expected ',' or ')', but found {}", peeked_char));
+                    Some(token) if token.is_keyword("NOT") => {
+                        expression.push(ExpressionElement::Not);
+                    }
+                    _ => break,
                 }
+                tokens.next();
+            }
+            let tag = Query::parse_tag(tokens)?;
+            expression.extend([
+                ExpressionElement::string(&tag),
+                ExpressionElement::Operator(Operator::In),
+                ExpressionElement::field("tags"),
+            ]);
+            while depth > 0
+                && tokens
+                    .peek()
+                    .is_some_and(|token| token.kind == TokenKind::ClosedBracket)
+            {
+                tokens.next();
+                depth -= 1;
+                expression.push(ExpressionElement::ClosedBracket);
             }
 
-            // Try parse Bool or Field name, if not then filed value
-            match Query::parse_field_name(peekable_query) {
-                Ok(field_name) => {
-                    if let Ok(bool_value) = field_name.parse::<bool>() {
-                        args.push(FunctionArg::FieldValue(FieldValue::Bool(bool_value)));
-                    } else {
-                        args.push(FunctionArg::FieldName(field_name));
-                    }
-                }
-                Err(_) => match Query::parse_field_value(peekable_query) {
-                    Ok(fv) => args.push(FunctionArg::FieldValue(fv)),
-                    Err(error) => return Err(error),
-                },
+            let operator = match tokens.peek() {
+                Some(token) if token.is_keyword("AND") && token.space_before => Operator::And,
+                Some(token) if token.is_keyword("OR") && token.space_before => Operator::Or,
+                _ if depth > 0 => return Err("Tag expression is missing a closing ')'".to_string()),
+                _ => return Ok(expression),
             };
-
-            Query::parse_whitespaces(peekable_query);
-
-            found_comma = false;
-            if let Some(&peeked_char) = peekable_query.peek() {
-                if peeked_char == ',' {
-                    found_comma = true;
-                    peekable_query.next();
-                }
-            }
+            tokens.next();
+            Query::parse_mandatory_whitespace(tokens)?;
+            expression.push(ExpressionElement::Operator(operator));
         }
-
-        Ok(Function::new(func_name, args))
     }
 
-    fn parse_field_name(peekable_query: &mut PeekableDeque<char>) -> Result<String, String> {
-        let mut field_name = String::new();
-
-        if let Some(&peeked_char) = peekable_query.peek() {
-            // First char can be letter or underscore
-            if !peeked_char.is_alphabetic() && peeked_char != '_' {
-                return Err(format!("Field name expected. They must start with letter, underscore or a minus, found: {}", peeked_char));
+    // `#name`, names can have `/` and `-` in them, e.g. `#area/work` or `#to-do`
+    fn parse_tag(tokens: &mut PeekableDeque<Token>) -> Result<String, String> {
+        match tokens.peek() {
+            Some(token) if token.kind == TokenKind::Unknown('#') => {
+                tokens.next();
             }
-            field_name.push(peeked_char);
-        } else {
-            return Err("Field name expected. nothing found".to_string());
+            Some(token) => return Err(format!("Expected a #tag, but found {}", token)),
+            None => return Err("Expected a #tag, but found nothing".to_string()),
         }
-
-        let mut last_char = *peekable_query.peek().unwrap();
-        peekable_query.next();
-
-        while let Some(&peeked_char) = peekable_query.peek() {
-            if !peeked_char.is_alphanumeric()
-                && peeked_char != '_'
-                && peeked_char != '-'
-                && peeked_char != '.'
-            {
-                break;
+        let mut tag = String::new();
+        while let Some(token) = tokens.peek().filter(|token| !token.space_before) {
+            match &token.kind {
+                TokenKind::Identifier(name) => tag.push_str(name),
+                TokenKind::Integer(number) => tag.push_str(&number.to_string()),
+                TokenKind::Symbol(symbol) if symbol == "/" || symbol == "-" => tag.push_str(symbol),
+                _ => break,
             }
-            field_name.push(peeked_char);
-            last_char = peeked_char;
-            peekable_query.next();
+            tokens.next();
         }
-
-        if last_char == '.' {
-            return Err("Field can't end with '.'!".to_string());
+        if tag.is_empty() {
+            return Err("Expected a tag name after #".to_string());
+        }
+        Ok(tag)
+    }
+
+    // WHERE of a query with `FROM #tags`: the tag condition AND the WHERE of the query. The
+    // elements of the tag condition point at FROM.
+    fn and_from_tags(
+        tags: Vec<ExpressionElement>,
+        where_expression: Vec<ExpressionElement>,
+        spans: &QuerySpans,
+    ) -> (Vec<ExpressionElement>, Vec<Span>) {
+        let from = spans.from.unwrap_or_default();
+        let mut expression = bracket_if_or(tags);
+        let mut expression_spans = vec![from; expression.len()];
+        if where_expression.is_empty() {
+            return (expression, expression_spans);
         }
 
-        Ok(field_name)
+        expression.push(ExpressionElement::Operator(Operator::And));
+        expression_spans.push(from);
+        let length = where_expression.len();
+        let bracketed = bracket_if_or(where_expression);
+        let where_clause = spans.where_clause.unwrap_or_default();
+        if bracketed.len() > length {
+            expression_spans.push(where_clause);
+        }
+        expression_spans.extend(spans.where_expression.iter().copied());
+        if bracketed.len() > length {
+            expression_spans.push(where_clause);
+        }
+        expression.extend(bracketed);
+        (expression, expression_spans)
     }
 
-    fn parse_sort_direction(
-        peekable_query: &mut PeekableDeque<char>,
-    ) -> Result<OrderDirection, String> {
-        match Query::parse_keyword(peekable_query, "ASC", false) {
-            Ok(()) => Ok(OrderDirection::ASC),
-            Err(_) => match Query::parse_keyword(peekable_query, "DESC", false) {
-                Ok(()) => Ok(OrderDirection::DESC),
-                Err(_) => Err(format!("Expected ASC or DESC: {:?}!", peekable_query)),
-            },
+    // call only when you expect WHERE should happen
+    fn parse_where(
+        tokens: &mut PeekableDeque<Token>,
+    ) -> Result<(Vec<ExpressionElement>, Vec<Span>), String> {
+        Query::parse_keyword(tokens, "WHERE")?;
+        Query::parse_mandatory_whitespace(tokens)?;
+
+        let mut where_expression: Vec<ExpressionElement> = Vec::new();
+        let mut spans: Vec<Span> = Vec::new();
+        Query::parse_expression(tokens, &mut where_expression, &mut spans)?;
+
+        Ok((where_expression, spans))
+    }
+
+    // call only when you expect GROUP BY should happen
+    // A number instead of a field name is the position of a SELECT field, e.g. `GROUP BY 1`
+    fn parse_group_by(
+        tokens: &mut PeekableDeque<Token>,
+        select: &SelectClause,
+    ) -> Result<Vec<GroupByField>, String> {
+        Query::parse_keyword(tokens, "GROUP BY")?;
+        Query::parse_mandatory_whitespace(tokens)?;
+
+        let mut group_by_fields = Vec::new();
+        loop {
+            group_by_fields.push(match tokens.peek().and_then(|token| token.kind.as_f64()) {
+                Some(position) => {
+                    let field = Query::group_by_field_at(position, select)?;
+                    tokens.next();
+                    field
+                }
+                None => Query::parse_group_by_field(tokens)?,
+            });
+            match tokens.peek().map(|token| &token.kind) {
+                Some(TokenKind::Comma) => {
+                    tokens.next();
+                }
+                _ => return Ok(group_by_fields),
+            }
         }
     }
 
-    fn parse_keyword(
-        peekable_query: &mut PeekableDeque<char>,
-        keyword: &str,
-        case_sensitive: bool,
-    ) -> Result<(), String> {
-        let mut keyword_chars = keyword.chars();
-        let mut matched = String::new();
+    fn parse_group_by_field(tokens: &mut PeekableDeque<Token>) -> Result<GroupByField, String> {
+        Ok(match Query::is_function_call(tokens) {
+            true => GroupByField::by_function(Query::parse_key_function(tokens)?),
+            false => GroupByField::new(Query::parse_field_name(tokens)?),
+        })
+    }
+
+    // SELECT field at `position` as a GROUP BY key, an alias groups by what it stands for
+    fn group_by_field_at(position: f64, select: &SelectClause) -> Result<GroupByField, String> {
+        let field_name = Query::select_field_at(position, &select.fields, "GROUP BY")?;
+        let field_name = select
+            .aliases
+            .iter()
+            .find(|(alias, _)| *alias == field_name)
+            .map_or(field_name.clone(), |(_, field)| field.clone());
+        let mut tokens = Query::tokens(&field_name)?;
+        if Aggregate::starts(&tokens) {
+            return Err(format!(
+                "Column position {} is the aggregate {}, GROUP BY needs a field there",
+                position, field_name
+            ));
+        }
+        match Query::parse_group_by_field(&mut tokens) {
+            Ok(field) if tokens.peek().is_none() => Ok(field),
+            _ => Err(format!(
+                "Column position {} is {}, GROUP BY needs a field or function there",
+                position, field_name
+            )),
+        }
+    }
 
-        for expected_char in &mut keyword_chars {
-            if let Some(&peeked_char) = peekable_query.peek() {
-                matched.push(peeked_char);
+    // Function as a GROUP BY key, its name in upper case so SELECT matches it in any case
+    fn parse_key_function(tokens: &mut PeekableDeque<Token>) -> Result<Function, String> {
+        let mut function = Query::parse_function(tokens)?;
+        function.name = function.name.to_uppercase();
+        Ok(function)
+    }
 
-                let match_condition = if case_sensitive {
-                    peeked_char == expected_char
-                } else {
-                    peeked_char.to_ascii_lowercase() == expected_char.to_ascii_lowercase()
-                };
+    // call only when you expect ORDER BY should happen
+    // A number instead of a field name is the position of a SELECT field, e.g. `ORDER BY 2 DESC`
+    fn parse_order_by(
+        tokens: &mut PeekableDeque<Token>,
+        select_fields: &[String],
+    ) -> Result<Vec<OrderByFieldOption>, String> {
+        Query::parse_keyword(tokens, "ORDER BY")?;
+        Query::parse_mandatory_whitespace(tokens)?;
+
+        let mut order_by_options = Vec::new();
+
+        loop {
+            let mut function = None;
+            let field_name = match tokens.peek().and_then(|token| token.kind.as_f64()) {
+                Some(position) => {
+                    let field_name = Query::select_field_at(position, select_fields, "ORDER BY")?;
+                    tokens.next();
+                    field_name
+                }
+                // Aggregates sort by their column
+                _ if Aggregate::starts(tokens) => Aggregate::parse(tokens)?.to_string(),
+                _ if Query::is_function_call(tokens) => {
+                    function = Some(Query::parse_function(tokens)?);
+                    String::new()
+                }
+                _ => Query::parse_field_name(tokens)?,
+            };
+
+            let mut order_direction = OrderDirection::ASC;
+            let ends_field = Query::at_semicolon(tokens)
+                || Query::starts_clause(tokens, "LIMIT")
+                || Query::starts_clause(tokens, "OFFSET");
+            if let Some(token) = tokens.peek() {
+                if token.kind != TokenKind::Comma && !ends_field {
+                    order_direction = Query::parse_sort_direction(tokens)?;
+                }
+            }
+            order_by_options.push(match function {
+                Some(function) => OrderByFieldOption::by_function(function, order_direction),
+                None => OrderByFieldOption::new(field_name, order_direction),
+            });
+
+            match tokens.peek() {
+                Some(token) if token.kind == TokenKind::Comma => {
+                    tokens.next();
+                }
+                _ => break,
+            }
+        }
+
+        Ok(order_by_options)
+    }
+
+    // call only when you expect LIMIT or OFFSET (the `keyword`) should happen
+    fn parse_row_count(tokens: &mut PeekableDeque<Token>, keyword: &str) -> Result<usize, String> {
+        Query::parse_keyword(tokens, keyword)?;
+        Query::parse_mandatory_whitespace(tokens)?;
+
+        match tokens.peek().map(|token| &token.kind) {
+            Some(TokenKind::Integer(count)) if *count >= 0 => {
+                let count = *count as usize;
+                tokens.next();
+                Ok(count)
+            }
+            Some(_) => Err(format!(
+                "{} needs a whole number of rows, found '{}'",
+                keyword,
+                tokens.peek().map(Token::to_string).unwrap_or_default()
+            )),
+            None => Err(format!("{} needs a number of rows", keyword)),
+        }
+    }
+
+    // call only when you expect PER (after LIMIT) should happen
+    fn parse_per(tokens: &mut PeekableDeque<Token>) -> Result<Vec<String>, String> {
+        Query::parse_keyword(tokens, "PER")?;
+        Query::parse_mandatory_whitespace(tokens)?;
+
+        let mut field_names = vec![Query::parse_field_name(tokens)?];
+        while let Some(TokenKind::Comma) = tokens.peek().map(|token| &token.kind) {
+            tokens.next();
+            field_names.push(Query::parse_field_name(tokens)?);
+        }
+        Ok(field_names)
+    }
+
+    fn select_field_at(
+        position: f64,
+        select_fields: &[String],
+        clause: &str,
+    ) -> Result<String, String> {
+        if position.fract() != 0.0 || position < 1.0 || position > select_fields.len() as f64 {
+            return Err(format!(
+                "Column position {} is out of range, SELECT has {} field(s)",
+                position,
+                select_fields.len()
+            ));
+        }
+        match select_fields[position as usize - 1].as_str() {
+            SELECT_ALL => Err(format!(
+                "Column position {} is *, {} needs a field name there",
+                position, clause
+            )),
+            field_name => Ok(field_name.to_string()),
+        }
+    }
+
+    // operand (operator operand)*, `spans` gets the span of every element pushed
+    fn parse_expression(
+        tokens: &mut PeekableDeque<Token>,
+        expression_elements: &mut Vec<ExpressionElement>,
+        spans: &mut Vec<Span>,
+    ) -> Result<(), String> {
+        loop {
+            Query::parse_operand(tokens, expression_elements, spans)?;
+
+            // TODO: allow fields without operator for cases where field is boolean
+            let start = Query::next_start(tokens);
+            match Query::try_parse_operator(tokens) {
+                Ok(op) => {
+                    expression_elements.push(ExpressionElement::Operator(op));
+                    spans.push(Query::span_from(tokens, start));
+                }
+                Err(_) => return Ok(()),
+            }
+        }
+    }
 
-                if !match_condition {
+    fn parse_operand(
+        tokens: &mut PeekableDeque<Token>,
+        expression_elements: &mut Vec<ExpressionElement>,
+        spans: &mut Vec<Span>,
+    ) -> Result<(), String> {
+        let start = Query::next_start(tokens);
+        let after_in = matches!(
+            expression_elements.last(),
+            Some(ExpressionElement::Operator(Operator::In | Operator::NotIn))
+        );
+        match tokens.peek().map(|token| token.kind.clone()) {
+            Some(TokenKind::OpenedBracket) if after_in && Query::literal_list(tokens).is_some() => {
+                if let Some((list, length)) = Query::literal_list(tokens) {
+                    for _ in 0..length {
+                        tokens.next();
+                    }
+                    expression_elements.push(ExpressionElement::FieldValue(FieldValue::List(list)));
+                }
+            }
+            Some(TokenKind::OpenedBracket) if Query::starts_subquery(tokens, 0) => {
+                let subquery = Query::parse_subquery(tokens)?;
+                expression_elements.push(ExpressionElement::Subquery(Box::new(subquery)));
+            }
+            Some(TokenKind::Identifier(_))
+                if tokens
+                    .peek()
+                    .is_some_and(|token| token.is_keyword("EXISTS"))
+                    && Query::starts_subquery(tokens, 1) =>
+            {
+                tokens.next();
+                let subquery = Query::parse_subquery(tokens)?;
+                expression_elements.push(ExpressionElement::Exists(Box::new(subquery)));
+            }
+            Some(TokenKind::Identifier(_)) if Query::starts_quantified(tokens) => {
+                let quantified = Query::parse_quantified(tokens)?;
+                expression_elements.push(ExpressionElement::Quantified(Box::new(quantified)));
+            }
+            Some(TokenKind::OpenedBracket) if after_in => {
+                if let Some(offset) = Query::non_literal_in_list(tokens) {
+                    for _ in 0..offset {
+                        tokens.next();
+                    }
                     return Err(format!(
-                        "Expected {}, but instead found: '{}'!",
-                        keyword, matched
+                        "Lists after IN can only have values (strings, numbers, true, false or NULL), found {}",
+                        tokens.peek().map(Token::to_string).unwrap_or_default()
                     ));
                 }
-                peekable_query.next();
-            } else {
+                return Query::parse_bracket_expression(tokens, expression_elements, spans);
+            }
+            Some(TokenKind::OpenedBracket) => {
+                return Query::parse_bracket_expression(tokens, expression_elements, spans)
+            }
+            Some(TokenKind::Parameter(name)) => {
+                tokens.next();
+                expression_elements.push(ExpressionElement::Parameter(name));
+            }
+            Some(TokenKind::Macro(name)) => {
+                tokens.next();
+                expression_elements.push(ExpressionElement::Macro(name));
+            }
+            Some(kind)
+                if kind == TokenKind::Unknown('!')
+                    || tokens.peek().is_some_and(|token| token.is_keyword("NOT")) =>
+            {
+                tokens.next();
+                expression_elements.push(ExpressionElement::Not);
+                spans.push(Query::span_from(tokens, start));
+                return Query::parse_operand(tokens, expression_elements, spans);
+            }
+            Some(TokenKind::Identifier(name))
+                if name.parse::<Operator>().is_ok() && !Query::is_function_call(tokens) =>
+            {
+                return Err(Query::missing_operand(expression_elements, &name));
+            }
+            Some(TokenKind::Identifier(_)) => {
+                let (first, first_span) = (expression_elements.len(), spans.len());
+                let element = Query::parse_bool_field_name_or_function(tokens)?;
+                let is_function = matches!(element, ExpressionElement::Function(_));
+                let is_value = matches!(element, ExpressionElement::FieldValue(_));
+                expression_elements.push(element);
+                spans.push(Query::span_from(tokens, start));
+                if is_function {
+                    Query::parse_members(tokens, expression_elements, spans)?;
+                }
+                // `tags ANY LIKE 'work/.*'` is `ANY(tags) LIKE 'work/.*'`
+                if !is_value && Query::starts_postfix_quantified(tokens) {
+                    let list = expression_elements.split_off(first);
+                    spans.truncate(first_span);
+                    let quantifier = Query::parse_quantifier(tokens);
+                    let quantified = Query::parse_quantified_comparison(tokens, quantifier, list)?;
+                    expression_elements.push(ExpressionElement::Quantified(Box::new(quantified)));
+                    spans.push(Query::span_from(tokens, start));
+                }
+                return Ok(());
+            }
+            Some(TokenKind::Symbol(symbol))
+                if symbol == "-" && Query::literal_at(tokens, 0).is_none() =>
+            {
+                tokens.next();
+                expression_elements.push(ExpressionElement::Negate);
+                spans.push(Query::span_from(tokens, start));
+                return Query::parse_operand(tokens, expression_elements, spans);
+            }
+            Some(kind) => match Query::parse_field_value(tokens) {
+                Ok(fv) => expression_elements.push(ExpressionElement::FieldValue(fv)),
+                Err(_) => {
+                    return Err(Query::missing_operand(
+                        expression_elements,
+                        &kind.to_string(),
+                    ))
+                }
+            },
+            None => {
+                return Err(match expression_elements.last() {
+                    Some(ExpressionElement::Operator(op)) => {
+                        format!("Operator {} is missing its right operand", op)
+                    }
+                    _ => "Expected expression, but found nothing".to_string(),
+                })
+            }
+        }
+        spans.push(Query::span_from(tokens, start));
+
+        Ok(())
+    }
+
+    // Whether the n-th upcoming token opens the brackets of a subquery, `(SELECT ...`
+    fn starts_subquery(tokens: &PeekableDeque<Token>, n: usize) -> bool {
+        tokens
+            .peek_nth(n)
+            .is_some_and(|token| token.kind == TokenKind::OpenedBracket)
+            && tokens
+                .peek_nth(n + 1)
+                .is_some_and(|token| token.is_keyword("SELECT"))
+    }
+
+    // `(SELECT ...)`, parsed from its tokens put back together, so its spans are relative to
+    // the subquery
+    fn parse_subquery(tokens: &mut PeekableDeque<Token>) -> Result<Query, String> {
+        let mut source = String::new();
+        let mut depth = 0;
+        let mut n = 1;
+        loop {
+            let token = match tokens.peek_nth(n) {
+                Some(token) => token,
+                None => return Err("Subquery is missing its closing ')'".to_string()),
+            };
+            match token.kind {
+                TokenKind::OpenedBracket => depth += 1,
+                TokenKind::ClosedBracket if depth == 0 => break,
+                TokenKind::ClosedBracket => depth -= 1,
+                _ => {}
+            }
+            if token.space_before && n > 1 {
+                source.push(' ');
+            }
+            match &token.kind {
+                TokenKind::String(text) => {
+                    source.push_str(&FieldValue::String(text.clone()).to_query_string())
+                }
+                kind => source.push_str(&kind.to_string()),
+            }
+            n += 1;
+        }
+
+        let query = source.parse::<Query>().map_err(|error| {
+            // Only the message, the caret points at the subquery in the whole query
+            let message = error.lines().next().unwrap_or_default();
+            let message = message.rsplit_once(" (line ").map_or(message, |(m, _)| m);
+            format!(
+                "Subquery {}",
+                message.replacen("Error parsing", "error in", 1)
+            )
+        })?;
+        if query.from_function.is_none() {
+            return Err("Subquery needs a FROM".to_string());
+        }
+        for _ in 0..=n {
+            tokens.next();
+        }
+        Ok(query)
+    }
+
+    // Error for a token that can not start an operand, e.g. `WHERE AND done` or `WHERE a == == b`
+    // `ANY(` or `ALL(`
+    fn starts_quantified(tokens: &PeekableDeque<Token>) -> bool {
+        tokens
+            .peek()
+            .is_some_and(|token| token.is_keyword("ANY") || token.is_keyword("ALL"))
+            && Query::is_function_call(tokens)
+    }
+
+    // `ANY` or `ALL` after a field or function, followed by an operator, e.g. `tags ANY LIKE`
+    fn starts_postfix_quantified(tokens: &PeekableDeque<Token>) -> bool {
+        let quantifier = tokens.peek().is_some_and(|token| {
+            (token.is_keyword("ANY") || token.is_keyword("ALL")) && token.space_before
+        });
+        let operator = match tokens.peek_nth(1).map(|token| &token.kind) {
+            Some(TokenKind::Symbol(_)) => true,
+            Some(TokenKind::Identifier(name)) => {
+                name.parse::<Operator>().is_ok() || name.eq_ignore_ascii_case("NOT")
+            }
+            _ => false,
+        };
+        quantifier && operator
+    }
+
+    // call only when the next token is ANY or ALL
+    fn parse_quantifier(tokens: &mut PeekableDeque<Token>) -> Quantifier {
+        let quantifier = match tokens.peek() {
+            Some(token) if token.is_keyword("ALL") => Quantifier::All,
+            _ => Quantifier::Any,
+        };
+        tokens.next();
+        quantifier
+    }
+
+    // call only when `starts_quantified`, the value is a single operand, e.g. a field, a value,
+    // a function or brackets
+    fn parse_quantified(tokens: &mut PeekableDeque<Token>) -> Result<Quantified, String> {
+        let quantifier = Query::parse_quantifier(tokens);
+
+        let mut list = Vec::new();
+        Query::parse_bracket_expression(tokens, &mut list, &mut Vec::new())?;
+        // without the brackets of ANY(...)
+        list.pop();
+        list.remove(0);
+
+        Query::parse_quantified_comparison(tokens, quantifier, list)
+    }
+
+    // The comparison after the list of ANY or ALL, e.g. `LIKE 'work/.*'`
+    fn parse_quantified_comparison(
+        tokens: &mut PeekableDeque<Token>,
+        quantifier: Quantifier,
+        list: Vec<ExpressionElement>,
+    ) -> Result<Quantified, String> {
+        let found = tokens
+            .peek()
+            .map_or("nothing".to_string(), Token::to_string);
+        let operator = match Query::try_parse_operator(tokens) {
+            Ok(operator) if operator.is_comparison() => operator,
+            _ => {
                 return Err(format!(
-                    "Expected {}, but instead found: '{}'!",
-                    keyword, matched
+                    "{} needs a comparison after its list, e.g. {}(tags) == 'work', found {}",
+                    quantifier, quantifier, found
+                ))
+            }
+        };
+
+        // seeded with the operator so a literal list after IN is recognised
+        let mut value = vec![ExpressionElement::Operator(operator.clone())];
+        Query::parse_operand(tokens, &mut value, &mut Vec::new())?;
+        value.remove(0);
+
+        Ok(Quantified {
+            quantifier,
+            list,
+            operator,
+            value,
+        })
+    }
+
+    fn missing_operand(expression_elements: &[ExpressionElement], found: &str) -> String {
+        match (expression_elements.last(), found.parse::<Operator>()) {
+            (Some(ExpressionElement::Operator(op)), _) => {
+                format!(
+                    "Operator {} is missing its right operand, found {}",
+                    op, found
+                )
+            }
+            (_, Ok(op)) => format!("Operator {} is missing its left operand", op),
+            _ => format!("Expected a field, value or function, but found {}", found),
+        }
+    }
+
+    // `.year.month` right after a function call, without whitespace
+    fn parse_members(
+        tokens: &mut PeekableDeque<Token>,
+        expression_elements: &mut Vec<ExpressionElement>,
+        spans: &mut Vec<Span>,
+    ) -> Result<(), String> {
+        while let Some(Token {
+            kind: TokenKind::Unknown('.'),
+            space_before: false,
+            ..
+        }) = tokens.peek()
+        {
+            let start = Query::next_start(tokens);
+            tokens.next();
+            let name = match tokens.peek() {
+                Some(Token {
+                    kind: TokenKind::Identifier(name),
+                    space_before: false,
+                    ..
+                }) => name.to_lowercase(),
+                _ => return Err("Expected a member name after '.'".to_string()),
+            };
+            if !DATE_MEMBERS.contains(&name.as_str()) {
+                return Err(with_suggestion(
+                    format!("Unknown member .{}", name),
+                    &name,
+                    DATE_MEMBERS,
                 ));
             }
+            tokens.next();
+            expression_elements.push(ExpressionElement::Member(name));
+            spans.push(Query::span_from(tokens, start));
         }
 
         Ok(())
     }
 
-    fn parse_whitespaces(peekable_query: &mut PeekableDeque<char>) {
+    fn parse_bracket_expression(
+        tokens: &mut PeekableDeque<Token>,
+        expression_elements: &mut Vec<ExpressionElement>,
+        spans: &mut Vec<Span>,
+    ) -> Result<(), String> {
+        Query::parse_token(tokens, TokenKind::OpenedBracket)?;
+        expression_elements.push(ExpressionElement::OpenedBracket);
+        spans.push(
+            tokens
+                .previous()
+                .map(|token| token.span)
+                .unwrap_or_default(),
+        );
+
+        Query::parse_expression(tokens, expression_elements, spans)?;
+
+        Query::parse_token(tokens, TokenKind::ClosedBracket)?;
+        expression_elements.push(ExpressionElement::ClosedBracket);
+        spans.push(
+            tokens
+                .previous()
+                .map(|token| token.span)
+                .unwrap_or_default(),
+        );
+
+        Ok(())
+    }
+
+    fn try_parse_operator(tokens: &mut PeekableDeque<Token>) -> Result<Operator, String> {
+        let token = match tokens.peek() {
+            Some(token) => token,
+            None => return Err("Did not found operator!".to_string()),
+        };
+
+        match &token.kind {
+            TokenKind::Symbol(symbol) => {
+                let op = symbol.parse::<Operator>()?;
+                tokens.next();
+                Ok(op)
+            }
+            TokenKind::Identifier(name) => {
+                // NOT IN, NOT LIKE and NOT REGEXP are the only operators made of two words
+                let (operator, length) = match tokens.peek_nth(1) {
+                    Some(next) if token.is_keyword("NOT") && next.is_keyword("IN") => ("NOT IN", 2),
+                    Some(next) if token.is_keyword("NOT") && next.is_keyword("LIKE") => {
+                        ("NOT LIKE", 2)
+                    }
+                    Some(next) if token.is_keyword("NOT") && next.is_keyword("REGEXP") => {
+                        ("NOT REGEXP", 2)
+                    }
+                    _ => (name.as_str(), 1),
+                };
+                let op = operator.parse::<Operator>()?;
+                if let Some(next) = tokens.peek_nth(length) {
+                    if !next.space_before {
+                        return Err("Whitespace expected after alphabetic operator!".to_string());
+                    }
+                }
+                for _ in 0..length {
+                    tokens.next();
+                }
+                Ok(op)
+            }
+            _ => Err(format!("No operator starts with {}", token)),
+        }
+    }
+
+    fn parse_field_value(tokens: &mut PeekableDeque<Token>) -> Result<FieldValue, String> {
+        let field_value = match tokens.peek().map(|token| &token.kind) {
+            Some(TokenKind::String(str)) => FieldValue::String(str.clone()),
+            Some(TokenKind::Integer(num)) => FieldValue::Integer(*num),
+            Some(TokenKind::Number(num)) => FieldValue::Float(*num),
+            Some(TokenKind::Symbol(symbol)) if symbol == "-" => {
+                // Minus right in front of a number is its sign
+                match Query::literal_at(tokens, 0) {
+                    Some((num, 2)) => {
+                        tokens.next();
+                        num
+                    }
+                    _ => return Err("Number can not start with -!".to_string()),
+                }
+            }
+            Some(TokenKind::Identifier(name)) if name.eq_ignore_ascii_case("NULL") => {
+                FieldValue::Null
+            }
+            Some(TokenKind::Identifier(_)) => {
+                return Ok(FieldValue::Bool(Query::parse_bool(tokens)?))
+            }
+            _ => return Err("No field value found!".to_string()),
+        };
+        tokens.next();
+
+        Ok(field_value)
+    }
+
+    // `true` or `false`, in any case
+    fn parse_bool(tokens: &mut PeekableDeque<Token>) -> Result<bool, String> {
+        let bool_value = match tokens.peek() {
+            Some(Token {
+                kind: TokenKind::Identifier(name),
+                ..
+            }) => Query::bool_literal(name),
+            _ => None,
+        };
+        match (bool_value, tokens.peek()) {
+            (Some(bool_value), _) => {
+                tokens.next();
+                Ok(bool_value)
+            }
+            (None, Some(token)) => Err(format!("Expected true or false, but found {}", token)),
+            (None, None) => Err("Expected true or false, but found nothing".to_string()),
+        }
+    }
+
+    fn bool_literal(name: &str) -> Option<bool> {
+        match name {
+            _ if name.eq_ignore_ascii_case("true") => Some(true),
+            _ if name.eq_ignore_ascii_case("false") => Some(false),
+            _ => None,
+        }
+    }
+
+    // `('todo', 'doing', 3)` on the right of IN: the values and how many tokens they take
+    fn literal_list(tokens: &PeekableDeque<Token>) -> Option<(Vec<FieldValue>, usize)> {
+        let mut list = Vec::new();
+        let mut n = 1;
+        loop {
+            let (value, length) = Query::literal_at(tokens, n)?;
+            list.push(value);
+            n += length;
+            match tokens.peek_nth(n)?.kind {
+                TokenKind::Comma => n += 1,
+                TokenKind::ClosedBracket => return Some((list, n + 1)),
+                _ => return None,
+            }
+        }
+    }
+
+    // Position of the first item that is not a value, when the upcoming brackets are a list (have
+    // a comma outside of inner brackets), e.g. `b` in `('x', b)`
+    fn non_literal_in_list(tokens: &PeekableDeque<Token>) -> Option<usize> {
+        let mut depth = 0;
+        let mut item_start = Some(1);
+        let mut non_literal = None;
+        let mut is_list = false;
+        let mut n = 1;
         loop {
-            if let Some(&c) = peekable_query.peek() {
-                if !c.is_whitespace() {
-                    return;
+            match tokens.peek_nth(n)?.kind {
+                TokenKind::OpenedBracket => depth += 1,
+                TokenKind::ClosedBracket if depth == 0 => break,
+                TokenKind::ClosedBracket => depth -= 1,
+                TokenKind::Comma if depth == 0 => {
+                    is_list = true;
+                    item_start = Some(n + 1);
+                    n += 1;
+                    continue;
                 }
-                peekable_query.next();
-            } else {
-                return;
+                _ => {}
+            }
+            if let Some(start) = item_start.take() {
+                let is_literal = Query::literal_at(tokens, start).is_some_and(|(_, length)| {
+                    matches!(
+                        tokens.peek_nth(start + length).map(|token| &token.kind),
+                        Some(TokenKind::Comma | TokenKind::ClosedBracket)
+                    )
+                });
+                if !is_literal && non_literal.is_none() {
+                    non_literal = Some(start);
+                }
+            }
+            n += 1;
+        }
+        // A single bracketed expression, e.g. `a IN (b)`, is not a list
+        non_literal.filter(|_| is_list)
+    }
+
+    // Literal value starting at the n-th upcoming token and how many tokens it takes
+    fn literal_at(tokens: &PeekableDeque<Token>, n: usize) -> Option<(FieldValue, usize)> {
+        match &tokens.peek_nth(n)?.kind {
+            TokenKind::String(str) => Some((FieldValue::String(str.clone()), 1)),
+            TokenKind::Integer(num) => Some((FieldValue::Integer(*num), 1)),
+            TokenKind::Number(num) => Some((FieldValue::Float(*num), 1)),
+            TokenKind::Symbol(symbol) if symbol == "-" => match tokens.peek_nth(n + 1)? {
+                Token {
+                    kind: TokenKind::Integer(num),
+                    space_before: false,
+                    ..
+                } => Some((FieldValue::Integer(-num), 2)),
+                Token {
+                    kind: TokenKind::Number(num),
+                    space_before: false,
+                    ..
+                } => Some((FieldValue::Float(-num), 2)),
+                _ => None,
+            },
+            TokenKind::Identifier(name) => match Query::bool_literal(name) {
+                Some(bool_value) => Some((FieldValue::Bool(bool_value), 1)),
+                None if name.eq_ignore_ascii_case("NULL") => Some((FieldValue::Null, 1)),
+                None => None,
+            },
+            _ => None,
+        }
+    }
+
+    fn parse_bool_field_name_or_function(
+        tokens: &mut PeekableDeque<Token>,
+    ) -> Result<ExpressionElement, String> {
+        let field_name = match tokens.peek().map(|token| &token.kind) {
+            Some(TokenKind::Identifier(name)) => name.clone(),
+            _ => return Err("No Function, nor FieldName found!".to_string()),
+        };
+
+        if Query::is_function_call(tokens) {
+            return Ok(ExpressionElement::Function(Query::parse_function(tokens)?));
+        }
+        if let Ok(bool_value) = Query::parse_bool(tokens) {
+            return Ok(ExpressionElement::FieldValue(FieldValue::Bool(bool_value)));
+        }
+        tokens.next();
+
+        if field_name.to_uppercase() == "NULL" {
+            return Ok(ExpressionElement::FieldValue(FieldValue::Null));
+        }
+
+        Ok(ExpressionElement::FieldName(field_name))
+    }
+
+    // name immediately followed by '('
+    fn is_function_call(tokens: &PeekableDeque<Token>) -> bool {
+        matches!(
+            tokens.peek_nth(1),
+            Some(Token {
+                kind: TokenKind::OpenedBracket,
+                space_before: false,
+                ..
+            })
+        )
+    }
+
+    fn parse_function(tokens: &mut PeekableDeque<Token>) -> Result<Function, String> {
+        let func_name = Query::parse_field_name(tokens)?;
+
+        match tokens.peek() {
+            Some(token) if token.kind == TokenKind::OpenedBracket && !token.space_before => {
+                tokens.next();
+            }
+            Some(token) => return Err(format!("Expected '(', but found {}", token)),
+            None => return Err("Expected '(', but found nothing".to_string()),
+        }
+
+        let mut args = Vec::new();
+        if let Some(TokenKind::ClosedBracket) = tokens.peek().map(|token| &token.kind) {
+            tokens.next();
+            return Ok(Function::new(func_name, args));
+        }
+
+        loop {
+            args.push(Query::parse_function_arg(tokens)?);
+
+            match tokens.peek().map(|token| &token.kind) {
+                Some(TokenKind::Comma) => {
+                    tokens.next();
+                    if let Some(TokenKind::ClosedBracket) = tokens.peek().map(|token| &token.kind) {
+                        return Err("Can't have ')' after ','!".to_string());
+                    }
+                }
+                Some(TokenKind::ClosedBracket) => {
+                    tokens.next();
+                    break;
+                }
+                Some(kind) => return Err(format!("Expected ',' or ')', but found {}", kind)),
+                None => return Err("Expected ',' or ')', but found nothing".to_string()),
             }
         }
+
+        Ok(Function::new(func_name, args))
     }
 
-    fn parse_mandatory_whitespace(peekable_query: &mut PeekableDeque<char>) -> Result<(), String> {
-        // mandatory wihtespace
-        if let Some(&peeked_char) = peekable_query.peek() {
-            if !peeked_char.is_whitespace() {
-                return Err(format!("Expected whitespace, but found {}!", peeked_char));
+    fn parse_function_arg(tokens: &mut PeekableDeque<Token>) -> Result<FunctionArg, String> {
+        if !Query::is_single_arg(tokens) {
+            let mut expression = Vec::new();
+            Query::parse_expression(tokens, &mut expression, &mut Vec::new())?;
+            return Ok(FunctionArg::Expression(expression));
+        }
+        match tokens.peek().map(|token| token.kind.clone()) {
+            Some(TokenKind::Parameter(name)) => {
+                tokens.next();
+                Ok(FunctionArg::Parameter(name))
             }
-        } else {
-            return Err("Expected a whitespace, but fonud nothing!".to_string());
+            // Try parse Bool, NULL or Field name, if not then filed value
+            Some(TokenKind::Identifier(field_name)) => {
+                let value = Query::literal_at(tokens, 0).map(|(value, _)| value);
+                tokens.next();
+                match value {
+                    Some(value) => Ok(FunctionArg::FieldValue(value)),
+                    None => Ok(FunctionArg::FieldName(field_name)),
+                }
+            }
+            _ => Ok(FunctionArg::FieldValue(Query::parse_field_value(tokens)?)),
         }
+    }
+
+    // Whether the next function argument is a single field, value or parameter, ending at the
+    // next ',' or ')'
+    fn is_single_arg(tokens: &PeekableDeque<Token>) -> bool {
+        let length = match tokens.peek().map(|token| &token.kind) {
+            Some(TokenKind::Identifier(_)) if Query::is_function_call(tokens) => return false,
+            Some(TokenKind::Identifier(_) | TokenKind::Parameter(_)) => 1,
+            // Brackets, macros, `-field` or `!field`
+            Some(TokenKind::OpenedBracket | TokenKind::Macro(_)) => return false,
+            Some(TokenKind::Symbol(_)) if Query::literal_at(tokens, 0).is_none() => return false,
+            _ => match Query::literal_at(tokens, 0) {
+                Some((_, length)) => length,
+                None => return true,
+            },
+        };
+        matches!(
+            tokens.peek_nth(length).map(|token| &token.kind),
+            Some(TokenKind::Comma | TokenKind::ClosedBracket) | None
+        )
+    }
+
+    fn parse_field_name(tokens: &mut PeekableDeque<Token>) -> Result<String, String> {
+        match tokens.peek() {
+            Some(Token {
+                kind: TokenKind::Identifier(name),
+                ..
+            }) => {
+                let name = name.clone();
+                tokens.next();
+                Ok(name)
+            }
+            Some(token) => Err(format!(
+                "Field name expected. They must start with letter or underscore, found: {}",
+                token
+            )),
+            None => Err("Field name expected. nothing found".to_string()),
+        }
+    }
+
+    fn parse_sort_direction(tokens: &mut PeekableDeque<Token>) -> Result<OrderDirection, String> {
+        let order_direction = match tokens.peek() {
+            Some(token) if token.is_keyword("ASC") => OrderDirection::ASC,
+            Some(token) if token.is_keyword("DESC") => OrderDirection::DESC,
+            Some(token) => {
+                return Err(with_suggestion(
+                    format!("Expected ASC or DESC, but found {}", token),
+                    &token.to_string(),
+                    ["ASC", "DESC"],
+                ))
+            }
+            None => return Err("Expected ASC or DESC, but found nothing!".to_string()),
+        };
+        tokens.next();
+
+        Ok(order_direction)
+    }
+
+    // Keyword can be multiple words, e.g. "ORDER BY"
+    fn parse_keyword(tokens: &mut PeekableDeque<Token>, keyword: &str) -> Result<(), String> {
+        for word in keyword.split_whitespace() {
+            match tokens.peek() {
+                Some(token) if token.is_keyword(word) => {
+                    tokens.next();
+                }
+                Some(token) => {
+                    return Err(format!(
+                        "Expected {}, but instead found: '{}'!",
+                        keyword, token
+                    ))
+                }
+                None => return Err(format!("Expected {}, but found nothing!", keyword)),
+            }
+        }
+
+        Ok(())
+    }
+
+    fn parse_token(tokens: &mut PeekableDeque<Token>, kind: TokenKind) -> Result<(), String> {
+        match tokens.peek() {
+            Some(token) if token.kind == kind => {
+                tokens.next();
+                Ok(())
+            }
+            Some(token) => Err(format!("Expected a '{}', but found: {}", kind, token)),
+            None => Err(format!("Expected a '{}', but found nothing", kind)),
+        }
+    }
+
+    // Whitespace has to be between the last parsed token and the next one
+    fn parse_mandatory_whitespace(tokens: &PeekableDeque<Token>) -> Result<(), String> {
+        match tokens.peek() {
+            Some(token) if token.space_before => Ok(()),
+            Some(token) => Err(format!("Expected whitespace, but found {}!", token)),
+            None => Err("Expected a whitespace, but fonud nothing!".to_string()),
+        }
+    }
+}
+
+/// Named condition used in WHERE as `$name`, defined in front of a query with
+/// `DEFINE active AS (status != 'done' AND archived != true);` or in the config file.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Definition {
+    pub name: String,
+    /// In brackets (unless it is a single operand), so it means the same wherever it is used
+    pub condition: Vec<ExpressionElement>,
+    /// One for every element of `condition` when it was defined in the query, empty otherwise
+    pub spans: Vec<Span>,
+}
+
+impl Definition {
+    /// Definition of `$name`, e.g. `Definition::new("active", "status != 'done'")`.
+    pub fn new(name: &str, condition: &str) -> Result<Self, String> {
+        if !Definition::is_name(name) {
+            return Err(format!(
+                "Invalid macro name: {}, it must start with letter or underscore and can not contain '.' or '['",
+                name
+            ));
+        }
+        let condition = Query::parse_condition(condition)?;
+
+        Ok(Definition::bracketed(
+            name.to_string(),
+            condition,
+            Vec::new(),
+        ))
+    }
+
+    /// The definition with the `$name` macros of `definitions` in its condition expanded, e.g. to
+    /// use macros defined before it.
+    pub fn expand_macros(self, definitions: &[Definition]) -> Self {
+        let (condition, spans) = expand_macros(&self.condition, &self.spans, definitions);
+        Definition {
+            condition,
+            spans,
+            ..self
+        }
+    }
+
+    fn bracketed(name: String, condition: Vec<ExpressionElement>, spans: Vec<Span>) -> Self {
+        if is_bracketed(&condition) {
+            return Definition {
+                name,
+                condition,
+                spans,
+            };
+        }
+
+        let mut bracketed = Vec::with_capacity(condition.len() + 2);
+        bracketed.push(ExpressionElement::OpenedBracket);
+        bracketed.extend(condition);
+        bracketed.push(ExpressionElement::ClosedBracket);
+        let spans = match (spans.first(), spans.last()) {
+            (Some(first), Some(last)) => {
+                let whole = Span {
+                    start: first.start,
+                    end: last.end,
+                };
+                std::iter::once(whole)
+                    .chain(spans.iter().copied())
+                    .chain(std::iter::once(whole))
+                    .collect()
+            }
+            _ => Vec::new(),
+        };
+        Definition {
+            name,
+            condition: bracketed,
+            spans,
+        }
+    }
+
+    fn is_name(name: &str) -> bool {
+        name.starts_with(|c: char| c.is_alphabetic() || c == '_')
+            && name
+                .chars()
+                .all(|c| c.is_alphanumeric() || c == '_' || c == '-')
+    }
+
+    // `DEFINE name AS condition;` statements in front of a query, each of them can use the ones
+    // defined before it
+    fn parse_all(tokens: &mut PeekableDeque<Token>) -> Result<Vec<Definition>, String> {
+        let mut definitions: Vec<Definition> = Vec::new();
+        while Query::starts_clause(tokens, "DEFINE") {
+            Query::parse_keyword(tokens, "DEFINE")?;
+            Query::parse_mandatory_whitespace(tokens)?;
+            let name = match tokens.peek().map(|token| &token.kind) {
+                Some(TokenKind::Identifier(name)) if Definition::is_name(name) => name.clone(),
+                Some(kind) => return Err(format!("Expected a macro name, but found {}", kind)),
+                None => return Err("Expected a macro name, but found nothing".to_string()),
+            };
+            if definitions.iter().any(|definition| definition.name == name) {
+                return Err(format!("${} is already defined", name));
+            }
+            tokens.next();
+            Query::parse_mandatory_whitespace(tokens)?;
+            Query::parse_keyword(tokens, "AS")?;
+            Query::parse_mandatory_whitespace(tokens)?;
+
+            let mut condition = Vec::new();
+            let mut spans = Vec::new();
+            Query::parse_expression(tokens, &mut condition, &mut spans)?;
+            match tokens.peek() {
+                Some(token) if token.kind == TokenKind::Semicolon => {
+                    tokens.next();
+                }
+                Some(token) => {
+                    return Err(format!(
+                        "{}, a definition ends with ';'",
+                        Query::unexpected(token)
+                    ))
+                }
+                None => return Err("Expected ';' after the definition".to_string()),
+            }
+
+            let (condition, spans) = expand_macros(&condition, &spans, &definitions);
+            definitions.push(Definition::bracketed(name, condition, spans));
+        }
+
+        Ok(definitions)
+    }
+}
+
+impl Display for Definition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "DEFINE {} AS {}",
+            self.name,
+            expression_to_string(&self.condition)
+        )
+    }
+}
+
+/// `field = value` in the SET clause of an UPDATE statement.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Assignment {
+    pub field_name: String,
+    pub value: FieldValue,
+}
+
+impl Display for Assignment {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} = {}", self.field_name, self.value.to_query_string())
+    }
+}
+
+/// Statement that writes fields back to the markdown files of the rows that match WHERE, e.g.
+/// `UPDATE FRONTMATTER_DATA('~/notes') SET status = 'archived' WHERE status == 'done'`.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Update {
+    pub from_function: Function,
+    pub assignments: Vec<Assignment>,
+    pub where_expression: Vec<ExpressionElement>,
+    /// Only `from`, `where_clause` and `where_expression` are set
+    pub spans: QuerySpans,
+}
+
+impl Update {
+    /// Whether `statement` starts with UPDATE (after DEFINEs), so it should be parsed as an
+    /// [`Update`] instead of a [`Query`].
+    pub fn is_update(statement: &str) -> bool {
+        let Ok(tokens) = tokenize(statement) else {
+            return false;
+        };
+        let mut tokens = tokens.iter();
+        while let Some(token) = tokens.next() {
+            if !token.is_keyword("DEFINE") {
+                return token.is_keyword("UPDATE");
+            }
+            tokens.find(|token| token.kind == TokenKind::Semicolon);
+        }
+        false
+    }
+
+    /// Query that selects the rows the statement changes.
+    pub fn to_query(&self) -> Query {
+        Query {
+            spans: self.spans.clone(),
+            ..Query::new(
+                vec![SELECT_ALL.to_string()],
+                Some(self.from_function.clone()),
+                self.where_expression.clone(),
+                Vec::new(),
+            )
+        }
+    }
+
+    fn parse_set(tokens: &mut PeekableDeque<Token>) -> Result<Vec<Assignment>, String> {
+        Query::parse_keyword(tokens, "SET")?;
+        Query::parse_mandatory_whitespace(tokens)?;
+
+        let mut assignments = Vec::new();
+        loop {
+            let field_name = Query::parse_field_name(tokens)?;
+            // `=` is not an operator, so it is not a token of its own
+            match tokens.peek() {
+                Some(token) if token.kind == TokenKind::Unknown('=') => {
+                    tokens.next();
+                }
+                Some(token) => return Err(format!("Expected '=', but found {}", token)),
+                None => return Err("Expected '=', but found nothing".to_string()),
+            }
+            assignments.push(Assignment {
+                field_name,
+                value: Update::parse_value(tokens)?,
+            });
+
+            match tokens.peek() {
+                Some(token) if token.kind == TokenKind::Comma => {
+                    tokens.next();
+                }
+                _ => break,
+            }
+        }
+
+        Ok(assignments)
+    }
+
+    // Literal or a list of literals, e.g. `('work', 'archived')`
+    fn parse_value(tokens: &mut PeekableDeque<Token>) -> Result<FieldValue, String> {
+        let (value, length) = match tokens.peek().map(|token| &token.kind) {
+            Some(TokenKind::OpenedBracket) => Query::literal_list(tokens)
+                .map(|(list, length)| (FieldValue::List(list), length))
+                .ok_or("Expected a list of values, e.g. ('a', 'b')")?,
+            Some(_) => Query::literal_at(tokens, 0).ok_or_else(|| {
+                format!(
+                    "Expected a value, but found {}",
+                    tokens.peek().map(Token::to_string).unwrap_or_default()
+                )
+            })?,
+            None => return Err("Expected a value, but found nothing".to_string()),
+        };
+        for _ in 0..length {
+            tokens.next();
+        }
+
+        Ok(value)
+    }
+}
+
+impl FromStr for Update {
+    type Err = String;
+
+    fn from_str(statement: &str) -> Result<Self, Self::Err> {
+        let mut tokens = Query::tokens(statement)?;
+        let mut spans = QuerySpans {
+            source: statement.to_string(),
+            ..QuerySpans::default()
+        };
+        let definitions = Definition::parse_all(&mut tokens)
+            .map_err(|error| Query::error_at("DEFINE", &error, statement, &tokens, &[]))?;
+
+        let start = Query::next_start(&tokens);
+        Query::parse_keyword(&mut tokens, "UPDATE")
+            .and_then(|_| Query::parse_mandatory_whitespace(&tokens))
+            .map_err(|error| Query::error_at("UPDATE", &error, statement, &tokens, &[]))?;
+        let from_function = Query::parse_function(&mut tokens)
+            .map_err(|error| Query::error_at("UPDATE", &error, statement, &tokens, &[]))?;
+        spans.from = Some(Query::span_from(&tokens, start));
+
+        if !Query::starts_clause(&tokens, "SET") {
+            return Err(Query::error_at(
+                "UPDATE",
+                "Expected SET",
+                statement,
+                &tokens,
+                &["SET"],
+            ));
+        }
+        let assignments = Update::parse_set(&mut tokens)
+            .map_err(|error| Query::error_at("SET", &error, statement, &tokens, &[]))?;
+
+        let mut where_expression = Vec::new();
+        if Query::starts_clause(&tokens, "WHERE") {
+            let start = Query::next_start(&tokens);
+            let (expression, expression_spans) = Query::parse_where(&mut tokens)
+                .map_err(|error| Query::error_at("WHERE", &error, statement, &tokens, &[]))?;
+            (where_expression, spans.where_expression) =
+                expand_macros(&expression, &expression_spans, &definitions);
+            spans.where_clause = Some(Query::span_from(&tokens, start));
+        }
+
+        if Query::at_semicolon(&tokens) {
+            tokens.next();
+        }
+        if let Some(token) = tokens.peek() {
+            let expected: &[&str] = match where_expression.is_empty() {
+                true => &[",", "WHERE"],
+                false => &["an operator"],
+            };
+            return Err(Query::error_at(
+                "UPDATE",
+                &Query::unexpected(token),
+                statement,
+                &tokens,
+                expected,
+            ));
+        }
+
+        Ok(Update {
+            from_function,
+            assignments,
+            where_expression,
+            spans,
+        })
+    }
+}
+
+impl Display for Update {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let assignments: Vec<String> = self.assignments.iter().map(|a| a.to_string()).collect();
+        write!(
+            f,
+            "UPDATE {} SET {}",
+            self.from_function,
+            assignments.join(", ")
+        )?;
+        if !self.where_expression.is_empty() {
+            write!(f, " WHERE {}", expression_to_string(&self.where_expression))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokens(query: &str) -> PeekableDeque<Token> {
+        PeekableDeque::from_iter(tokenize(query).expect("Query should tokenize"))
+    }
+
+    fn peeked(tokens: &PeekableDeque<Token>) -> Option<String> {
+        tokens.peek().map(|token| token.to_string())
+    }
+
+    #[ignore = "TODO: implement this test"]
+    #[test]
+    fn parse_where() {}
+
+    #[ignore = "TODO: implement this test"]
+    #[test]
+    fn parse_from() {}
+
+    #[ignore = "TODO: implement this test"]
+    #[test]
+    fn parse_select() {}
+
+    /////////////////////////////////////
+    // BUILD AND DISPLAY QUERY
+    /////////////////////////////////////
+    #[test]
+    fn test_query_display_round_trip() -> Result<(), String> {
+        let text = "SELECT a, b.c FROM FRONTMATTER_DATA('~/notes') WHERE (a == 'x' OR b > 1) AND DATE(c) < :d ORDER BY a DESC, b ASC";
+        let query: Query = text.parse()?;
+
+        assert_eq!(text, query.to_string());
+        assert_eq!(query, query.to_string().parse::<Query>()?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_query_pretty_string() -> Result<(), String> {
+        let query: Query =
+            "select a,b from F('x') where a == 1 and (b or c) or d order by a desc".parse()?;
+
+        assert_eq!(
+            "SELECT a, b\nFROM F('x')\nWHERE a == 1\n  AND (b OR c)\n  OR d\nORDER BY a DESC",
+            query.to_pretty_string()
+        );
+        assert_eq!(query, query.to_pretty_string().parse::<Query>()?);
+        assert_eq!("WHERE a", "where a".parse::<Query>()?.to_pretty_string());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_query_builder() -> Result<(), String> {
+        let query = Query::default()
+            .select("title")
+            .from(Function::new(
+                "FRONTMATTER_DATA".to_string(),
+                vec![FunctionArg::FieldValue(FieldValue::String(
+                    "~/notes".to_string(),
+                ))],
+            ))
+            .and_where(vec![
+                ExpressionElement::field("status"),
+                ExpressionElement::op(Operator::Eq),
+                ExpressionElement::string("done"),
+            ])
+            .order_by("title", OrderDirection::DESC);
+
+        assert_eq!(
+            "SELECT title FROM FRONTMATTER_DATA('~/notes') WHERE status == 'done' ORDER BY title DESC",
+            query.to_string()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_query_and_where_brackets_or() -> Result<(), String> {
+        let query: Query = "SELECT a FROM F('x') WHERE a == 1 OR b == 2".parse()?;
+
+        let query = query
+            .and_where(Query::parse_condition("c == 3 OR d == 4")?)
+            .and_where(Query::parse_condition("e")?);
+
+        assert_eq!(
+            "SELECT a FROM F('x') WHERE (a == 1 OR b == 2) AND (c == 3 OR d == 4) AND e",
+            query.to_string()
+        );
+        assert_eq!(
+            "SELECT a FROM F('x') WHERE (a == 1 OR b == 2) AND (c == 3 OR d == 4) AND e OR f",
+            query
+                .or_where(vec![ExpressionElement::field("f")])
+                .to_string()
+        );
+        assert!(Query::parse_condition("a == ").is_err());
+
+        Ok(())
+    }
+
+    /////////////////////////////////////
+    // SPANS
+    /////////////////////////////////////
+    #[test]
+    fn test_query_spans() -> Result<(), String> {
+        let query: Query =
+            "SELECT a FROM F('x') WHERE (b AND -1) OR c NOT LIKE 'y' ORDER BY a".parse()?;
+
+        assert_eq!(
+            "SELECT a",
+            query.spans.fragment(query.spans.select.unwrap())
+        );
+        assert_eq!(
+            "FROM F('x')",
+            query.spans.fragment(query.spans.from.unwrap())
+        );
+        assert_eq!(
+            "WHERE (b AND -1) OR c NOT LIKE 'y'",
+            query.spans.fragment(query.spans.where_clause.unwrap())
+        );
+        assert_eq!(
+            "ORDER BY a",
+            query.spans.fragment(query.spans.order_by.unwrap())
+        );
+        assert_eq!(
+            vec!["(", "b", "AND", "-1", ")", "OR", "c", "NOT LIKE", "'y'"],
+            query
+                .spans
+                .where_expression
+                .iter()
+                .map(|span| query.spans.fragment(*span))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            "e — at characters 28..36: `b AND -1`",
+            query.spans.locate("e", query.spans.where_elements(1, 3))
+        );
+
+        // Spans are ignored when comparing and dropped when WHERE is changed in code
+        assert_eq!(
+            query,
+            query.to_string().replace(' ', "  ").parse::<Query>()?
+        );
+        let query = query.and_where(Query::parse_condition("d")?);
+        assert!(query.spans.where_expression.is_empty());
+        assert_eq!(
+            "e",
+            query.spans.locate("e", query.spans.where_elements(1, 3))
+        );
+
+        Ok(())
+    }
+
+    /////////////////////////////////////
+    // PARSE ERRORS
+    /////////////////////////////////////
+    #[test]
+    fn test_parse_error_has_line_column_and_caret() {
+        let error = "SELECT a FROM F('x')\nWHERE a == 1 && b"
+            .parse::<Query>()
+            .expect_err("Should fail on &&");
+
+        assert_eq!(
+            "Error parsing query: Unexpected character '&' (line 2, column 14)\n  |\n2 | WHERE a == 1 && b\n  |              ^\nExpected one of: an operator, GROUP BY, ORDER BY, LIMIT, OFFSET",
+            error
+        );
+    }
+
+    #[test]
+    fn test_parse_trailing_semicolon_and_comments() -> Result<(), String> {
+        let expected: Query = "SELECT a FROM F('x') WHERE a == 1 ORDER BY a DESC".parse()?;
+
+        for query in [
+            "SELECT a FROM F('x') WHERE a == 1 ORDER BY a DESC;",
+            "SELECT a FROM F('x') WHERE a == 1 ORDER BY a DESC ;  \n",
+            "SELECT a -- fields\nFROM F('x') -- source\nWHERE a == 1\nORDER BY a DESC; -- done",
+            "# latest first\nSELECT a FROM F('x') # a man can dream\nWHERE a == 1 ORDER BY a DESC #",
+        ] {
+            assert_eq!(expected, query.parse::<Query>()?);
+        }
+        assert_eq!(
+            Query::new(
+                vec![],
+                Some(Function::new(
+                    "F".to_string(),
+                    vec![FunctionArg::FieldValue(FieldValue::String("x".to_string()))]
+                )),
+                vec![],
+                vec![]
+            ),
+            "FROM F('x');".parse::<Query>()?
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_error_trailing_input() {
+        let error = "SELECT a FROM F('x') ORDER BY a DESC top 3"
+            .parse::<Query>()
+            .expect_err("Should fail on top");
+        assert!(
+            error.starts_with("Error parsing query: Unexpected token 'top' (line 1, column 38)"),
+            "{}",
+            error
+        );
+
+        let error = "SELECT a FROM F('x'); WHERE a == 1"
+            .parse::<Query>()
+            .expect_err("Should fail after ;");
+        assert_eq!(
+            "Error parsing query: Unexpected token 'WHERE', nothing can follow ';' (line 1, column 23)\n  |\n1 | SELECT a FROM F('x'); WHERE a == 1\n  |                       ^",
+            error
+        );
+    }
+
+    #[test]
+    fn test_parse_error_suggests_keyword() {
+        for (query, suggestion) in [
+            ("SELCT a FROM F('x')", "'SELCT', did you mean 'SELECT'?"),
+            (
+                "DEFINE o AS (a); WTIH t AS (SELECT a FROM F('x')) SELECT a FROM t",
+                "'WTIH', did you mean 'WITH'?",
+            ),
+            ("SELECT a FORM F('x')", "'FORM', did you mean 'FROM'?"),
+            (
+                "SELECT a FROM F('x') WHRE a",
+                "'WHRE', did you mean 'WHERE'?",
+            ),
+        ] {
+            let error = query.parse::<Query>().expect_err("Should fail on typo");
+            assert!(
+                error.starts_with(&format!(
+                    "Error parsing query: Unexpected token {}",
+                    suggestion
+                )),
+                "{}",
+                error
+            );
+        }
+
+        let error = "SELCT title FROM F('x')"
+            .parse::<Query>()
+            .expect_err("Should fail on SELCT");
+        assert!(
+            error.ends_with("Expected one of: SELECT, WITH, EXPLAIN, UPDATE"),
+            "{}",
+            error
+        );
+
+        let error = "SELECT a FROM F('x') ORDER BY a descc"
+            .parse::<Query>()
+            .expect_err("Should fail on descc");
+        assert!(
+            error.starts_with(
+                "Error parsing ORDER BY: Expected ASC or DESC, but found descc, did you mean 'DESC'?"
+            ),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn test_parse_unary_minus() -> Result<(), String> {
+        let query: Query = "WHERE balance < -budget AND -(a + b) > - -1 AND - -c".parse()?;
+
+        assert_eq!(
+            vec![
+                ExpressionElement::field("balance"),
+                ExpressionElement::op(Operator::Lt),
+                ExpressionElement::Negate,
+                ExpressionElement::field("budget"),
+                ExpressionElement::op(Operator::And),
+                ExpressionElement::Negate,
+                ExpressionElement::OpenedBracket,
+                ExpressionElement::field("a"),
+                ExpressionElement::op(Operator::Plus),
+                ExpressionElement::field("b"),
+                ExpressionElement::ClosedBracket,
+                ExpressionElement::op(Operator::Gt),
+                ExpressionElement::Negate,
+                ExpressionElement::integer(-1),
+                ExpressionElement::op(Operator::And),
+                ExpressionElement::Negate,
+                ExpressionElement::Negate,
+                ExpressionElement::field("c"),
+            ],
+            query.where_expression
+        );
+        assert_eq!(
+            "WHERE balance < -budget AND -(a + b) > - -1 AND - -c",
+            query.to_string()
+        );
+        assert_eq!(query, query.to_string().parse::<Query>()?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_not() -> Result<(), String> {
+        let query: Query = "WHERE NOT ('fleeting' IN tags) AND !(a OR not b)".parse()?;
+
+        assert_eq!(
+            vec![
+                ExpressionElement::Not,
+                ExpressionElement::OpenedBracket,
+                ExpressionElement::string("fleeting"),
+                ExpressionElement::op(Operator::In),
+                ExpressionElement::field("tags"),
+                ExpressionElement::ClosedBracket,
+                ExpressionElement::op(Operator::And),
+                ExpressionElement::Not,
+                ExpressionElement::OpenedBracket,
+                ExpressionElement::field("a"),
+                ExpressionElement::op(Operator::Or),
+                ExpressionElement::Not,
+                ExpressionElement::field("b"),
+                ExpressionElement::ClosedBracket,
+            ],
+            query.where_expression
+        );
+        assert_eq!(
+            "WHERE NOT ('fleeting' IN tags) AND NOT (a OR NOT b)",
+            query.to_string()
+        );
+        assert_eq!(query, query.to_string().parse::<Query>()?);
+        // Still the two word operators after an operand
+        assert_eq!(
+            vec![
+                ExpressionElement::field("title"),
+                ExpressionElement::op(Operator::NotLike),
+                ExpressionElement::string("x"),
+            ],
+            "WHERE title NOT LIKE 'x'"
+                .parse::<Query>()?
+                .where_expression
+        );
+        assert!("WHERE a AND NOT"
+            .parse::<Query>()
+            .unwrap_err()
+            .starts_with("Error parsing WHERE: Expected expression, but found nothing"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_subqueries() -> Result<(), String> {
+        let query: Query = "DEFINE open AS (checked != true); WHERE file.path IN (SELECT file.path FROM MD_TASKS('x') WHERE $open) AND EXISTS (SELECT a FROM F('y'))"
+            .parse()?;
+
+        let open_tasks: Query =
+            "SELECT file.path FROM MD_TASKS('x') WHERE (checked != true)".parse()?;
+        let exists: Query = "SELECT a FROM F('y')".parse()?;
+        assert_eq!(
+            vec![
+                ExpressionElement::field("file.path"),
+                ExpressionElement::op(Operator::In),
+                ExpressionElement::Subquery(Box::new(open_tasks)),
+                ExpressionElement::op(Operator::And),
+                ExpressionElement::Exists(Box::new(exists)),
+            ],
+            query.where_expression
+        );
+        assert_eq!(
+            "WHERE file.path IN (SELECT file.path FROM MD_TASKS('x') WHERE (checked != true)) AND EXISTS (SELECT a FROM F('y'))",
+            query.to_string()
+        );
+        assert!(query.has_subqueries());
+        assert_eq!(query, query.to_string().parse::<Query>()?);
+        // Macros of the config reach into subqueries too
+        let query: Query = "WHERE (SELECT MAX(sleep) FROM F('y') WHERE $open) > 5".parse()?;
+        assert_eq!(vec!["open".to_string()], query.macros());
+        assert_eq!(
+            "WHERE (SELECT MAX(sleep) FROM F('y') WHERE (checked != true)) > 5",
+            query
+                .expand_macros(&[Definition {
+                    name: "open".to_string(),
+                    condition: "WHERE (checked != true)".parse::<Query>()?.where_expression,
+                    spans: Vec::new(),
+                }])?
+                .to_string()
+        );
+        // Brackets are still brackets
+        assert!(!"WHERE (a OR b) AND c IN ('x')"
+            .parse::<Query>()?
+            .has_subqueries());
+
+        assert!("WHERE a IN (SELECT a WHERE b)"
+            .parse::<Query>()
+            .unwrap_err()
+            .contains("Subquery needs a FROM"));
+        assert!("WHERE a IN (SELECT a FROM F('x') WHERE)"
+            .parse::<Query>()
+            .unwrap_err()
+            .contains("Subquery error in WHERE"));
+        assert!("WHERE a IN (SELECT a FROM F('x')"
+            .parse::<Query>()
+            .unwrap_err()
+            .contains("Subquery is missing its closing ')'"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_quantified() -> Result<(), String> {
+        let query: Query =
+            "SELECT a FROM F('x') WHERE any(tags) LIKE 'work/.*' AND ALL(scores + other) >= (:min)"
+                .parse()?;
+
+        assert_eq!(
+            vec![
+                ExpressionElement::Quantified(Box::new(Quantified {
+                    quantifier: Quantifier::Any,
+                    list: vec![ExpressionElement::field("tags")],
+                    operator: Operator::Like,
+                    value: vec![ExpressionElement::string("work/.*")],
+                })),
+                ExpressionElement::op(Operator::And),
+                ExpressionElement::Quantified(Box::new(Quantified {
+                    quantifier: Quantifier::All,
+                    list: vec![
+                        ExpressionElement::field("scores"),
+                        ExpressionElement::op(Operator::Plus),
+                        ExpressionElement::field("other"),
+                    ],
+                    operator: Operator::Gte,
+                    value: vec![
+                        ExpressionElement::OpenedBracket,
+                        ExpressionElement::Parameter("min".to_string()),
+                        ExpressionElement::ClosedBracket,
+                    ],
+                })),
+            ],
+            query.where_expression
+        );
+        assert_eq!(3, query.spans.where_expression.len());
+        assert_eq!(
+            "ANY(tags) LIKE 'work/.*' AND ALL(scores + other) >= (:min)",
+            expression_to_string(&query.where_expression)
+        );
+        assert_eq!(vec!["min"], query.parameters());
+        let params = HashMap::from([("min".to_string(), FieldValue::Integer(3))]);
+        assert_eq!(
+            "ANY(tags) LIKE 'work/.*' AND ALL(scores + other) >= (3)",
+            expression_to_string(&query.bind(&params)?.where_expression)
+        );
+
+        // After a field or function ANY and ALL quantify it
+        let postfix: Query =
+            "SELECT a FROM F('x') WHERE tags ANY LIKE 'work/.*' AND scores ALL NOT IN (1, 2)"
+                .parse()?;
+        let prefix: Query =
+            "SELECT a FROM F('x') WHERE ANY(tags) LIKE 'work/.*' AND ALL(scores) NOT IN (1, 2)"
+                .parse()?;
+        assert_eq!(prefix.where_expression, postfix.where_expression);
+        assert_eq!(3, postfix.spans.where_expression.len());
+        assert_eq!(
+            "tags ANY LIKE 'work/.*'",
+            postfix
+                .spans
+                .fragment(postfix.spans.where_elements(0, 0).unwrap())
+        );
+
+        // Without brackets ANY and ALL are field names
+        let query: Query = "SELECT a FROM F('x') WHERE all == any".parse()?;
+        assert_eq!(
+            vec![
+                ExpressionElement::field("all"),
+                ExpressionElement::op(Operator::Eq),
+                ExpressionElement::field("any"),
+            ],
+            query.where_expression
+        );
+
+        for (query, error) in [
+            (
+                "ANY(tags) + 1",
+                "ANY needs a comparison after its list, e.g. ANY(tags) == 'work', found +",
+            ),
+            (
+                "ALL(tags)",
+                "ALL needs a comparison after its list, e.g. ALL(tags) == 'work', found nothing",
+            ),
+        ] {
+            let query = format!("SELECT a FROM F('x') WHERE {}", query);
+            match query.parse::<Query>() {
+                Ok(query) => panic!("Expected an error, got {:?}", query),
+                Err(e) => assert!(e.contains(error), "{}", e),
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_function_members() -> Result<(), String> {
+        let query: Query = "WHERE DATE(created).year == 2024 AND DATE(a).Month > 1".parse()?;
+
+        assert_eq!(
+            vec![
+                ExpressionElement::Function(Function::new(
+                    "DATE".to_string(),
+                    vec![FunctionArg::FieldName("created".to_string())]
+                )),
+                ExpressionElement::Member("year".to_string()),
+                ExpressionElement::op(Operator::Eq),
+                ExpressionElement::integer(2024),
+                ExpressionElement::op(Operator::And),
+                ExpressionElement::Function(Function::new(
+                    "DATE".to_string(),
+                    vec![FunctionArg::FieldName("a".to_string())]
+                )),
+                ExpressionElement::Member("month".to_string()),
+                ExpressionElement::op(Operator::Gt),
+                ExpressionElement::integer(1),
+            ],
+            query.where_expression
+        );
+        assert_eq!(
+            "WHERE DATE(created).year == 2024 AND DATE(a).month > 1",
+            query.to_string()
+        );
+
+        let error = "WHERE DATE(a).yeer == 1"
+            .parse::<Query>()
+            .expect_err("Member should be unknown");
+        assert!(
+            error.starts_with(
+                "Error parsing WHERE: Unknown member .yeer, did you mean 'year'? (line 1, column 15)"
+            ),
+            "{}",
+            error
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_in_literal_list() -> Result<(), String> {
+        let query: Query =
+            "WHERE status IN ('todo', \"doing\", -1, true, NULL) AND a IN (b)".parse()?;
+
+        assert_eq!(
+            vec![
+                ExpressionElement::field("status"),
+                ExpressionElement::op(Operator::In),
+                ExpressionElement::value(FieldValue::List(vec![
+                    FieldValue::String("todo".to_string()),
+                    FieldValue::String("doing".to_string()),
+                    FieldValue::Integer(-1),
+                    FieldValue::Bool(true),
+                    FieldValue::Null,
+                ])),
+                ExpressionElement::op(Operator::And),
+                ExpressionElement::field("a"),
+                ExpressionElement::op(Operator::In),
+                ExpressionElement::OpenedBracket,
+                ExpressionElement::field("b"),
+                ExpressionElement::ClosedBracket,
+            ],
+            query.where_expression
+        );
+        assert_eq!(
+            "WHERE status IN ('todo', 'doing', -1, true, NULL) AND a IN (b)",
+            query.to_string()
+        );
+        assert_eq!(query, query.to_string().parse::<Query>()?);
+
+        let error = "WHERE a IN ('x', b)"
+            .parse::<Query>()
+            .expect_err("Lists can only hold values");
+        assert!(
+            error.starts_with(
+                "Error parsing WHERE: Lists after IN can only have values (strings, numbers, true, false or NULL), found b (line 1, column 18)"
+            ),
+            "{}",
+            error
+        );
+        let error = "WHERE a IN (DATE(x), 'y')"
+            .parse::<Query>()
+            .expect_err("Lists can only hold values");
+        assert!(
+            error.contains("found DATE (line 1, column 13)"),
+            "{}",
+            error
+        );
+        assert_eq!(
+            Ok(vec![
+                ExpressionElement::field("a"),
+                ExpressionElement::op(Operator::In),
+                ExpressionElement::OpenedBracket,
+                ExpressionElement::field("b"),
+                ExpressionElement::op(Operator::Plus),
+                ExpressionElement::Function(Function::new(
+                    "F".to_string(),
+                    vec![
+                        FunctionArg::FieldName("c".to_string()),
+                        FunctionArg::FieldName("d".to_string()),
+                    ]
+                )),
+                ExpressionElement::ClosedBracket,
+            ]),
+            "WHERE a IN (b + F(c, d))"
+                .parse::<Query>()
+                .map(|query| query.where_expression)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_error_dangling_operator() {
+        for (condition, error, column) in [
+            ("tags ==", "Operator == is missing its right operand", 14),
+            (
+                "a == 1 AND",
+                "Operator AND is missing its right operand",
+                17,
+            ),
+            ("AND done", "Operator AND is missing its left operand", 7),
+            (
+                "a == == b",
+                "Operator == is missing its right operand, found ==",
+                12,
+            ),
+            (
+                "(a <) OR b",
+                "Operator < is missing its right operand, found )",
+                11,
+            ),
+            ("()", "Expected a field, value or function, but found )", 8),
+        ] {
+            let query = format!("WHERE {}", condition);
+            let result = query.parse::<Query>().expect_err("Should fail");
+            assert!(
+                result.starts_with(&format!(
+                    "Error parsing WHERE: {} (line 1, column {})",
+                    error, column
+                )),
+                "{}",
+                result
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_error_inside_clause() {
+        let error = "SELECT a FROM F('x') WHERE a == "
+            .parse::<Query>()
+            .expect_err("Should fail on missing operand");
+
+        assert!(error.starts_with("Error parsing WHERE: "));
+        assert!(error.contains("(line 1, column 33)"));
+        assert!(error.ends_with("  |                                 ^"));
+    }
+
+    #[test]
+    fn test_parse_error_from_lexer() {
+        let error = "SELECT a WHERE b == 'open"
+            .parse::<Query>()
+            .expect_err("Should fail on unclosed string");
+
+        assert_eq!(
+            "Error parsing query: Query ended before string (open) was closed! (line 1, column 26)\n  |\n1 | SELECT a WHERE b == 'open\n  |                          ^",
+            error
+        );
+    }
+
+    #[test]
+    fn test_parse_select_all() -> Result<(), String> {
+        let query: Query = "SELECT title, * FROM F('x') ORDER BY 1".parse()?;
+
+        assert_eq!(vec!["title", "*"], query.select_fields);
+        assert_eq!(
+            "SELECT title, * FROM F('x') ORDER BY title ASC",
+            query.to_string()
+        );
+        assert!("SELECT * FROM F('x') ORDER BY 1"
+            .parse::<Query>()
+            .unwrap_err()
+            .starts_with(
+                "Error parsing ORDER BY: Column position 1 is *, ORDER BY needs a field name there"
+            ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_select_aliases() -> Result<(), String> {
+        let query: Query =
+            "SELECT title AS name, date(created) as day, COUNT(*) AS notes, tags GROUP BY title, DATE(created), tags ORDER BY notes DESC"
+                .parse()?;
+
+        assert_eq!(vec!["name", "day", "notes", "tags"], query.select_fields);
+        assert_eq!(
+            vec![
+                ("name".to_string(), "title".to_string()),
+                ("day".to_string(), "DATE(created)".to_string()),
+                ("notes".to_string(), "COUNT(*)".to_string()),
+            ],
+            query.select_aliases
+        );
+        assert_eq!(Some(&"COUNT(*)".to_string()), query.alias("notes"));
+        assert_eq!(None, query.alias("tags"));
+        assert!(query.is_grouped());
+        assert_eq!(
+            "SELECT title AS name, DATE(created) AS day, COUNT(*) AS notes, tags GROUP BY title, DATE(created), tags ORDER BY notes DESC",
+            query.to_string()
+        );
+        assert_eq!(query, query.to_string().parse()?);
+
+        for (query, error) in [
+            ("SELECT * AS all", "* can not be named with AS"),
+            ("SELECT title AS a.b", "Alias a.b can not be nested"),
+            (
+                "SELECT title AS a, tags AS a",
+                "a is in SELECT more than once",
+            ),
+            (
+                "SELECT title AS t GROUP BY tags",
+                "Field title is not in GROUP BY",
+            ),
+        ] {
+            let result = query.parse::<Query>();
+            assert!(
+                result.as_ref().is_err_and(|e| e.contains(error)),
+                "{}: {:?}",
+                query,
+                result
+            );
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_from_tags() -> Result<(), String> {
+        let query: Query =
+            "SELECT title FROM (#a OR #area/work-2) AND NOT #c WHERE x OR y".parse()?;
+        assert_eq!(
+            Some(Function::new("FRONTMATTER_DATA".to_string(), Vec::new())),
+            query.from_function
+        );
+        assert_eq!(
+            "SELECT title FROM FRONTMATTER_DATA() WHERE ('a' IN tags OR 'area/work-2' IN tags) AND NOT 'c' IN tags AND (x OR y)",
+            query.to_string()
+        );
+        assert_eq!(
+            query.where_expression.len(),
+            query.spans.where_expression.len()
+        );
+        assert_eq!(query, query.to_string().parse()?);
+        assert_eq!(
+            "WHERE ('a' IN tags OR 'b' IN tags)",
+            "SELECT title FROM #a OR #b"
+                .parse::<Query>()?
+                .to_string()
+                .split(" FROM FRONTMATTER_DATA() ")
+                .nth(1)
+                .unwrap_or_default()
+        );
+
+        for (query, error) in [
+            ("SELECT title FROM #'a'", "Expected a tag name after #"),
+            ("SELECT title FROM (#a OR #b", "missing a closing ')'"),
+            ("SELECT title FROM #a AND b", "Expected a #tag, but found b"),
+        ] {
+            let result = query.parse::<Query>();
+            assert!(
+                result.as_ref().is_err_and(|e| e.contains(error)),
+                "{}: {:?}",
+                query,
+                result
+            );
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_select_exclude() -> Result<(), String> {
+        let query: Query =
+            "SELECT * EXCLUDE (file, now, today), file.name FROM FRONTMATTER_DATA('.')".parse()?;
+        assert_eq!(vec!["*", "file.name"], query.select_fields);
+        assert_eq!(vec!["file", "now", "today"], query.select_exclude);
+        assert_eq!(
+            "SELECT * EXCLUDE (file, now, today), file.name FROM FRONTMATTER_DATA('.')",
+            query.to_string()
+        );
+        assert_eq!(query, query.to_string().parse()?);
+
+        let query: Query = "SELECT * exclude file".parse()?;
+        assert_eq!(vec!["file"], query.select_exclude);
+
+        for (query, error) in [
+            (
+                "SELECT * EXCLUDE (file.name)",
+                "EXCLUDE takes top-level fields",
+            ),
+            ("SELECT * EXCLUDE (file today)", "Expected ',' or ')'"),
+            ("SELECT * EXCLUDE", "Expected"),
+        ] {
+            let result = query.parse::<Query>();
+            assert!(
+                result.as_ref().is_err_and(|e| e.contains(error)),
+                "{}: {:?}",
+                query,
+                result
+            );
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_select_fields_and_from_function() -> Result<(), String> {
+        assert_eq!(
+            vec!["a".to_string(), "b.c".to_string()],
+            Query::parse_select_fields("a, b.c")?
+        );
+        assert!(Query::parse_select_fields("a b").is_err());
+        assert_eq!(
+            Function::new(
+                "F".to_string(),
+                vec![FunctionArg::FieldValue(FieldValue::String("x".to_string()))]
+            ),
+            Query::parse_from_function("F('x')")?
+        );
+        assert!(Query::parse_from_function("F ('x')").is_err());
+        // Names of WITH have no arguments
+        assert_eq!(
+            Some(Function::new("open_tasks".to_string(), Vec::new())),
+            "SELECT a FROM open_tasks WHERE a"
+                .parse::<Query>()?
+                .from_function
+        );
+        assert!("SELECT a FROM F ('x')".parse::<Query>().is_err());
+
+        Ok(())
+    }
+
+    /////////////////////////////////////
+    // PARSE ORDER BY
+    /////////////////////////////////////
+    #[test]
+    fn test_parse_order_by_multiple_field() -> Result<(), String> {
+        let field1 = "field1".to_string();
+        let field2 = "field2".to_string();
+        let field3 = "field3".to_string();
+        let field4 = "field4".to_string();
+        let query = format!(
+            "order by {} desc, {}, {} asc, {}",
+            field1, field2, field3, field4
+        );
+        let mut tokens = tokens(&query);
+
+        match Query::parse_order_by(&mut tokens, &[]) {
+            Ok(obf) => assert_eq!(
+                vec![
+                    OrderByFieldOption::new(field1, OrderDirection::DESC),
+                    OrderByFieldOption::new(field2, OrderDirection::ASC),
+                    OrderByFieldOption::new(field3, OrderDirection::ASC),
+                    OrderByFieldOption::new(field4, OrderDirection::ASC),
+                ],
+                obf
+            ),
+            Err(error) => return Err(error),
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_order_by_one_field_with_direction() -> Result<(), String> {
+        let field1 = "field1".to_string();
+        let query = format!("order by {} desc", field1);
+        let mut tokens = tokens(&query);
+
+        match Query::parse_order_by(&mut tokens, &[]) {
+            Ok(obf) => assert_eq!(
+                vec![OrderByFieldOption::new(field1, OrderDirection::DESC)],
+                obf
+            ),
+            Err(error) => return Err(error),
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_order_by_column_position() -> Result<(), String> {
+        let query: Query = "SELECT a, b.c FROM F('x') ORDER BY 2 DESC, a".parse()?;
+
+        assert_eq!(
+            vec![
+                OrderByFieldOption::new("b.c".to_string(), OrderDirection::DESC),
+                OrderByFieldOption::new("a".to_string(), OrderDirection::ASC),
+            ],
+            query.order_by_fields
+        );
+
+        for (position, error) in [("3", "3"), ("0", "0"), ("1.5", "1.5")] {
+            let query = format!("SELECT a, b.c FROM F('x') ORDER BY {}", position);
+            match query.parse::<Query>() {
+                Ok(query) => panic!("Expected an error, got {:?}", query),
+                Err(e) => assert!(
+                    e.starts_with(&format!(
+                        "Error parsing ORDER BY: Column position {} is out of range, SELECT has 2 field(s) (line 1, column 36)",
+                        error
+                    )),
+                    "{}",
+                    e
+                ),
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_order_by_function() -> Result<(), String> {
+        let text = "SELECT title FROM F('x') ORDER BY SIMILARITY(title, :search) DESC, title ASC";
+        let query: Query = text.parse()?;
+
+        assert_eq!(
+            Some(Function::new(
+                "SIMILARITY".to_string(),
+                vec![
+                    FunctionArg::FieldName("title".to_string()),
+                    FunctionArg::Parameter("search".to_string()),
+                ],
+            )),
+            query.order_by_fields[0].function
+        );
+        assert_eq!(text, query.to_string());
+        assert_eq!(vec!["search".to_string()], query.parameters());
+
+        let params = HashMap::from([(
+            "search".to_string(),
+            FieldValue::String("weekly review".to_string()),
+        )]);
+        assert_eq!(
+            "SELECT title FROM F('x') ORDER BY SIMILARITY(title, 'weekly review') DESC, title ASC",
+            query.bind(&params)?.to_string()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_select_functions() -> Result<(), String> {
+        let query: Query = "SELECT file.name, date(created, '%Y-%m-%d') FROM F('x')".parse()?;
+
+        assert_eq!(
+            vec!["file.name", "DATE(created, '%Y-%m-%d')"],
+            query.select_fields
+        );
+        assert_eq!(
+            Some(vec![ExpressionElement::Function(Function::new(
+                "DATE".to_string(),
+                vec![
+                    FunctionArg::FieldName("created".to_string()),
+                    FunctionArg::FieldValue(FieldValue::String("%Y-%m-%d".to_string())),
+                ]
+            ))]),
+            Query::select_expression(&query.select_fields[1])
+        );
+        for field in ["file.name", "*", "COUNT(*)", "MAX(sleep)"] {
+            assert_eq!(None, Query::select_expression(field), "{}", field);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_select_expressions() -> Result<(), String> {
+        let query: Query =
+            "SELECT n*2, TODAY() - DATE(created) AS age, DATE(created).year, -n FROM F('x')"
+                .parse()?;
+
+        assert_eq!(
+            vec!["n*2", "age", "DATE(created).year", "-n"],
+            query.select_fields
+        );
+        assert_eq!(
+            vec![("age".to_string(), "TODAY() - DATE(created)".to_string())],
+            query.select_aliases
+        );
+        assert_eq!(
+            Some(vec![
+                ExpressionElement::field("n"),
+                ExpressionElement::op(Operator::Multiply),
+                ExpressionElement::FieldValue(FieldValue::Integer(2)),
+            ]),
+            Query::select_expression(&query.select_fields[0])
+        );
+        assert_eq!(
+            "SELECT n*2, TODAY() - DATE(created) AS age, DATE(created).year, -n FROM F('x')",
+            query.to_string()
+        );
+        assert_eq!(vec!["a", "b * 2"], Query::parse_select_fields("a, b * 2")?);
+
+        for query in [
+            "SELECT n * :k FROM F('x')",
+            "SELECT n + $m FROM F('x')",
+            "SELECT (SELECT a FROM G('y')) + 1 FROM F('x')",
+        ] {
+            let error = query.parse::<Query>().unwrap_err();
+            assert!(
+                error
+                    .contains("Expressions in SELECT can not use subqueries, parameters or macros"),
+                "{}",
+                error
+            );
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_expression_function_args() -> Result<(), String> {
+        let query: Query =
+            "SELECT IF(status=='done','yes',:no) FROM F('x') WHERE IF(-a > 1, DATE(b), (c)) == 'x'"
+                .parse()?;
+
+        assert_eq!(
+            vec!["IF(status == 'done', 'yes', :no)"],
+            query.select_fields
+        );
+        assert_eq!(
+            Some(vec![ExpressionElement::Function(Function::new(
+                "IF".to_string(),
+                vec![
+                    FunctionArg::Expression(vec![
+                        ExpressionElement::field("status"),
+                        ExpressionElement::op(Operator::Eq),
+                        ExpressionElement::string("done"),
+                    ]),
+                    FunctionArg::FieldValue(FieldValue::String("yes".to_string())),
+                    FunctionArg::Parameter("no".to_string()),
+                ]
+            ))]),
+            Query::select_expression(&query.select_fields[0])
+        );
+        assert_eq!(
+            "IF(-a > 1, DATE(b), (c)) == 'x'",
+            expression_to_string(&query.where_expression)
+        );
+
+        let query: Query = "SELECT a FROM F('x') WHERE IF(a == :min, DATE(:b), c)".parse()?;
+        assert_eq!(vec!["min", "b"], query.parameters());
+        let params = HashMap::from([
+            ("min".to_string(), FieldValue::Integer(1)),
+            (
+                "b".to_string(),
+                FieldValue::String("2024-03-01".to_string()),
+            ),
+        ]);
+        assert_eq!(
+            "IF(a == 1, DATE('2024-03-01'), c)",
+            expression_to_string(&query.bind(&params)?.where_expression)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_limit_and_offset() -> Result<(), String> {
+        let query: Query =
+            "SELECT file.name FROM F('x') ORDER BY created DESC LIMIT 5 offset 10;".parse()?;
+
+        assert_eq!((Some(5), Some(10)), (query.limit, query.offset));
+        assert_eq!(
+            "SELECT file.name FROM F('x') ORDER BY created DESC LIMIT 5 OFFSET 10",
+            query.to_string()
+        );
+        assert_eq!((None, Some(2)), {
+            let query: Query = "SELECT a FROM F('x') WHERE a > 1 OFFSET 2".parse()?;
+            (query.limit, query.offset)
+        });
+        assert_eq!((Some(1), None), {
+            let query: Query = "SELECT a FROM F('x') ORDER BY a LIMIT 1;".parse()?;
+            (query.limit, query.offset)
+        });
+        assert_eq!(
+            "SELECT a FROM F('x') LIMIT 3",
+            Query::new(
+                vec!["a".to_string()],
+                Some(Function::new(
+                    "F".to_string(),
+                    vec![FunctionArg::FieldValue(FieldValue::String("x".to_string()))]
+                )),
+                Vec::new(),
+                Vec::new()
+            )
+            .limit(3)
+            .to_string()
+        );
 
-        peekable_query.next();
+        let error = |query: &str| query.parse::<Query>().unwrap_err();
+        assert!(error("SELECT a FROM F('x') LIMIT 2.5")
+            .starts_with("Error parsing LIMIT: LIMIT needs a whole number of rows, found '2.5'"));
+        assert!(error("SELECT a FROM F('x') LIMIT all")
+            .starts_with("Error parsing LIMIT: LIMIT needs a whole number of rows, found 'all'"));
+        let out_of_order = error("SELECT a FROM F('x') OFFSET 1 LIMIT 2");
+        assert!(
+            out_of_order
+                .starts_with("Error parsing query: Unexpected token 'LIMIT' (line 1, column 31)"),
+            "{}",
+            out_of_order
+        );
+        assert!(!out_of_order.contains("Expected one of"));
+        assert!(error("SELECT a FROM F('x') LIMIT 1 ORDER BY a")
+            .ends_with("Expected one of: PER, OFFSET"));
 
         Ok(())
     }
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
 
-    #[ignore = "TODO: implement this test"]
     #[test]
-    fn parse_where() {}
+    fn test_parse_limit_per() -> Result<(), String> {
+        let query: Query =
+            "SELECT file.name FROM F('x') ORDER BY created DESC LIMIT 3 per project, file.folder OFFSET 1"
+                .parse()?;
 
-    #[ignore = "TODO: implement this test"]
-    #[test]
-    fn parse_from() {}
+        assert_eq!(
+            (Some(3), vec!["project", "file.folder"], Some(1)),
+            (
+                query.limit,
+                query
+                    .limit_per
+                    .iter()
+                    .map(String::as_str)
+                    .collect::<Vec<_>>(),
+                query.offset
+            )
+        );
+        assert_eq!(
+            "SELECT file.name FROM F('x') ORDER BY created DESC LIMIT 3 PER project, file.folder OFFSET 1",
+            query.to_string()
+        );
+        assert_eq!(
+            query,
+            "SELECT file.name FROM F('x') ORDER BY created DESC OFFSET 1"
+                .parse::<Query>()?
+                .limit_per(3, &["project", "file.folder"])
+        );
 
-    #[ignore = "TODO: implement this test"]
-    #[test]
-    fn parse_select() {}
+        let error = |query: &str| query.parse::<Query>().unwrap_err();
+        assert!(error("SELECT a FROM F('x') LIMIT 3 PER 'project'").starts_with(
+            "Error parsing LIMIT: Field name expected. They must start with letter or underscore"
+        ));
+        assert!(error("SELECT a FROM F('x') LIMIT 3 PER a,")
+            .starts_with("Error parsing LIMIT: Field name expected"));
 
-    #[ignore = "TODO: implement this test"]
-    #[test]
-    fn parse_bracket_expression() {}
+        Ok(())
+    }
 
-    #[ignore = "TODO: implement this test"]
     #[test]
-    fn parse_expression() {}
+    fn test_parse_group_by() -> Result<(), String> {
+        let query: Query =
+            "select status, count(*), percentile(words,90) from F('x') where a == 1 group by status order by Count(*) desc"
+                .parse()?;
 
-    /////////////////////////////////////
-    // PARSE ORDER BY
-    /////////////////////////////////////
-    #[test]
-    fn test_parse_order_by_multiple_field() -> Result<(), String> {
-        let field1 = "field1".to_string();
-        let field2 = "field2".to_string();
-        let field3 = "field3".to_string();
-        let field4 = "field4".to_string();
-        let query = format!(
-            "order by {} desc, {}, {} asc, {}",
-            field1, field2, field3, field4
+        assert_eq!(
+            vec![GroupByField::new("status".to_string())],
+            query.group_by_fields
         );
-        let mut peekable_query: PeekableDeque<char> = PeekableDeque::from_iter(query.chars());
-
-        match Query::parse_order_by(&mut peekable_query) {
-            Ok(obf) => assert_eq!(
+        assert_eq!(
+            vec![
+                Aggregate::Count(None),
+                Aggregate::Percentile("words".to_string(), 90.0)
+            ],
+            query.aggregates()
+        );
+        assert!(query.is_grouped());
+        assert_eq!(
+            "SELECT status, COUNT(*), PERCENTILE(words, 90) FROM F('x') WHERE a == 1 GROUP BY status ORDER BY COUNT(*) DESC",
+            query.to_string()
+        );
+        let query: Query = "SELECT date_trunc(:unit, created), COUNT(*) FROM F('x') GROUP BY Date_Trunc(:unit, created)".parse()?;
+        assert_eq!(
+            Some(Function::new(
+                "DATE_TRUNC".to_string(),
                 vec![
-                    OrderByFieldOption::new(field1, OrderDirection::DESC),
-                    OrderByFieldOption::new(field2, OrderDirection::ASC),
-                    OrderByFieldOption::new(field3, OrderDirection::ASC),
-                    OrderByFieldOption::new(field4, OrderDirection::ASC),
+                    FunctionArg::Parameter("unit".to_string()),
+                    FunctionArg::FieldName("created".to_string()),
                 ],
-                obf
-            ),
-            Err(error) => return Err(error),
-        }
+            )),
+            query.group_by_fields[0].function
+        );
+        assert_eq!(vec!["unit".to_string()], query.parameters());
+        let params = HashMap::from([("unit".to_string(), FieldValue::String("week".to_string()))]);
+        assert_eq!(
+            "SELECT DATE_TRUNC('week', created), COUNT(*) FROM F('x') GROUP BY DATE_TRUNC('week', created)",
+            query.bind(&params)?.to_string()
+        );
+        // Functions in SELECT of a grouped query have to be GROUP BY keys
+        assert!("SELECT DATE(created), COUNT(*) FROM F('x') GROUP BY status"
+            .parse::<Query>()
+            .unwrap_err()
+            .starts_with("Field DATE(created) is not in GROUP BY"));
 
-        Ok(())
-    }
+        assert!("SELECT AVG(words) FROM F('x')"
+            .parse::<Query>()?
+            .is_grouped());
+        assert!(!"SELECT count FROM F('x')".parse::<Query>()?.is_grouped());
 
-    #[test]
-    fn test_parse_order_by_one_field_with_direction() -> Result<(), String> {
-        let field1 = "field1".to_string();
-        let query = format!("order by {} desc", field1);
-        let mut peekable_query: PeekableDeque<char> = PeekableDeque::from_iter(query.chars());
+        assert_eq!(
+            Err("Field title is not in GROUP BY, group by it or use an aggregate, e.g. MAX(title) — at characters 0..20: `SELECT status, title`".to_string()),
+            "SELECT status, title FROM F('x') GROUP BY status".parse::<Query>()
+        );
+        assert_eq!(
+            Err("SELECT * can not be used with GROUP BY or aggregates — at characters 0..18: `SELECT *, COUNT(*)`".to_string()),
+            "SELECT *, COUNT(*) FROM F('x')".parse::<Query>()
+        );
+        assert!("SELECT status FROM F('x') GROUP BY status ORDER BY title"
+            .parse::<Query>()
+            .unwrap_err()
+            .ends_with("`ORDER BY title`"));
 
-        match Query::parse_order_by(&mut peekable_query) {
-            Ok(obf) => assert_eq!(
-                vec![OrderByFieldOption::new(field1, OrderDirection::DESC)],
-                obf
-            ),
-            Err(error) => return Err(error),
-        }
+        // A number is the position of a SELECT field, an alias groups by what it stands for
+        assert_eq!(
+            "SELECT tag, COUNT(*) FROM F('x') GROUP BY tag".parse::<Query>()?,
+            "SELECT tag, COUNT(*) FROM F('x') GROUP BY 1".parse::<Query>()?
+        );
+        let query: Query =
+            "SELECT date_trunc('month', created) AS month, COUNT(*) FROM F('x') GROUP BY 1"
+                .parse()?;
+        assert_eq!(
+            "DATE_TRUNC('month', created)",
+            query.group_by_fields[0].field_name
+        );
+        assert!("SELECT tag, COUNT(*) FROM F('x') GROUP BY 3"
+            .parse::<Query>()
+            .unwrap_err()
+            .starts_with(
+                "Error parsing GROUP BY: Column position 3 is out of range, SELECT has 2 field(s)"
+            ));
+        assert!("SELECT tag, COUNT(*) FROM F('x') GROUP BY 2"
+            .parse::<Query>()
+            .unwrap_err()
+            .contains("Column position 2 is the aggregate COUNT(*)"));
 
         Ok(())
     }
@@ -997,9 +4562,9 @@ mod tests {
     fn test_parse_order_by_one_field_no_direction() -> Result<(), String> {
         let field1 = "field1".to_string();
         let query = format!("order by {}", field1);
-        let mut peekable_query: PeekableDeque<char> = PeekableDeque::from_iter(query.chars());
+        let mut tokens = tokens(&query);
 
-        match Query::parse_order_by(&mut peekable_query) {
+        match Query::parse_order_by(&mut tokens, &[]) {
             Ok(obf) => assert_eq!(
                 vec![OrderByFieldOption::new(field1, OrderDirection::ASC)],
                 obf
@@ -1019,13 +4584,13 @@ mod tests {
         let arg1: f64 = 5.5;
         let arg2 = true;
         let query = format!("{}({} {}) ", func_name, arg1, arg2);
-        let mut peekable_query: PeekableDeque<char> = PeekableDeque::from_iter(query.chars());
+        let mut tokens = tokens(&query);
 
-        if Query::parse_function(&mut peekable_query, None).is_ok() {
-            return Err("It should fail due to trailing comma!".to_string());
+        if Query::parse_function(&mut tokens).is_ok() {
+            return Err("It should fail due to missing comma!".to_string());
         }
 
-        assert_eq!('t', *peekable_query.peek().unwrap());
+        assert_eq!(Some("true".to_string()), peeked(&tokens));
 
         Ok(())
     }
@@ -1035,13 +4600,13 @@ mod tests {
         let func_name = "test".to_string();
         let arg1: f64 = 5.5;
         let query = format!("{}({},) ", func_name, arg1);
-        let mut peekable_query: PeekableDeque<char> = PeekableDeque::from_iter(query.chars());
+        let mut tokens = tokens(&query);
 
-        if Query::parse_function(&mut peekable_query, None).is_ok() {
+        if Query::parse_function(&mut tokens).is_ok() {
             return Err("It should fail due to trailing comma!".to_string());
         }
 
-        assert_eq!(')', *peekable_query.peek().unwrap());
+        assert_eq!(Some(")".to_string()), peeked(&tokens));
 
         Ok(())
     }
@@ -1050,13 +4615,24 @@ mod tests {
     fn test_parse_function_with_comma_after_open_bracket() -> Result<(), String> {
         let func_name = "test".to_string();
         let query = format!("{}(,) ", func_name);
-        let mut peekable_query: PeekableDeque<char> = PeekableDeque::from_iter(query.chars());
+        let mut tokens = tokens(&query);
 
-        if Query::parse_function(&mut peekable_query, None).is_ok() {
+        if Query::parse_function(&mut tokens).is_ok() {
             return Err("It should fail due to trailing comma!".to_string());
         }
 
-        assert_eq!(',', *peekable_query.peek().unwrap());
+        assert_eq!(Some(",".to_string()), peeked(&tokens));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_function_with_space_before_bracket() -> Result<(), String> {
+        let mut tokens = tokens("test ()");
+
+        if Query::parse_function(&mut tokens).is_ok() {
+            return Err("It should fail due to space before '('!".to_string());
+        }
 
         Ok(())
     }
@@ -1071,14 +4647,14 @@ mod tests {
         let arg3 = true;
 
         let query = format!("{}({}  , {},{}) ", func_name, arg1, arg2, arg3);
-        let mut peekable_query: PeekableDeque<char> = PeekableDeque::from_iter(query.chars());
+        let mut tokens = tokens(&query);
 
-        match Query::parse_function(&mut peekable_query, None) {
+        match Query::parse_function(&mut tokens) {
             Ok(func) => assert_eq!(
                 Function::new(
                     func_name,
                     vec![
-                        FunctionArg::FieldValue(FieldValue::Number(arg1)),
+                        FunctionArg::FieldValue(FieldValue::Float(arg1)),
                         FunctionArg::FieldValue(FieldValue::String(arg2_str)),
                         FunctionArg::FieldValue(FieldValue::Bool(arg3))
                     ]
@@ -1088,7 +4664,7 @@ mod tests {
             Err(error) => return Err(error),
         }
 
-        assert_eq!(' ', *peekable_query.peek().unwrap());
+        assert!(tokens.end());
 
         Ok(())
     }
@@ -1098,9 +4674,9 @@ mod tests {
         let func_name = "test".to_string();
         let arg1 = "field".to_string();
         let query = format!("{}({}) ", func_name, arg1);
-        let mut peekable_query: PeekableDeque<char> = PeekableDeque::from_iter(query.chars());
+        let mut tokens = tokens(&query);
 
-        match Query::parse_function(&mut peekable_query, None) {
+        match Query::parse_function(&mut tokens) {
             Ok(func) => assert_eq!(
                 Function::new(func_name, vec![FunctionArg::FieldName(arg1)]),
                 func
@@ -1108,7 +4684,7 @@ mod tests {
             Err(error) => return Err(error),
         }
 
-        assert_eq!(' ', *peekable_query.peek().unwrap());
+        assert!(tokens.end());
 
         Ok(())
     }
@@ -1118,9 +4694,9 @@ mod tests {
         let func_name = "test".to_string();
         let arg = true;
         let query = format!("{}({}) ", func_name, arg);
-        let mut peekable_query: PeekableDeque<char> = PeekableDeque::from_iter(query.chars());
+        let mut tokens = tokens(&query);
 
-        match Query::parse_function(&mut peekable_query, None) {
+        match Query::parse_function(&mut tokens) {
             Ok(func) => assert_eq!(
                 Function::new(
                     func_name,
@@ -1131,7 +4707,7 @@ mod tests {
             Err(error) => return Err(error),
         }
 
-        assert_eq!(' ', *peekable_query.peek().unwrap());
+        assert!(tokens.end());
 
         Ok(())
     }
@@ -1141,9 +4717,9 @@ mod tests {
         let func_name = "test".to_string();
         let arg = "kifla".to_string();
         let query = format!("{}('{}') ", func_name, arg);
-        let mut peekable_query: PeekableDeque<char> = PeekableDeque::from_iter(query.chars());
+        let mut tokens = tokens(&query);
 
-        match Query::parse_function(&mut peekable_query, None) {
+        match Query::parse_function(&mut tokens) {
             Ok(func) => assert_eq!(
                 Function::new(
                     func_name,
@@ -1154,7 +4730,7 @@ mod tests {
             Err(error) => return Err(error),
         }
 
-        assert_eq!(' ', *peekable_query.peek().unwrap());
+        assert!(tokens.end());
 
         Ok(())
     }
@@ -1162,38 +4738,22 @@ mod tests {
     #[test]
     fn test_parse_function_with_name_one_num_arg() -> Result<(), String> {
         let func_name = "test".to_string();
-        let arg: f64 = 5.5;
+        let arg: f64 = -5.5;
         let query = format!("{}({}) ", func_name, arg);
-        let mut peekable_query: PeekableDeque<char> = PeekableDeque::from_iter(query.chars());
+        let mut tokens = tokens(&query);
 
-        match Query::parse_function(&mut peekable_query, None) {
+        match Query::parse_function(&mut tokens) {
             Ok(func) => assert_eq!(
                 Function::new(
                     func_name,
-                    vec![FunctionArg::FieldValue(FieldValue::Number(arg))]
+                    vec![FunctionArg::FieldValue(FieldValue::Float(arg))]
                 ),
                 func
             ),
             Err(error) => return Err(error),
         }
 
-        assert_eq!(' ', *peekable_query.peek().unwrap());
-
-        Ok(())
-    }
-
-    #[test]
-    fn test_parse_function_without_name_no_args() -> Result<(), String> {
-        let func_name = "test".to_string();
-        let query = "() ".to_string();
-        let mut peekable_query: PeekableDeque<char> = PeekableDeque::from_iter(query.chars());
-
-        match Query::parse_function(&mut peekable_query, Some(func_name.clone())) {
-            Ok(func) => assert_eq!(Function::new(func_name, Vec::new()), func),
-            Err(error) => return Err(error),
-        }
-
-        assert_eq!(' ', *peekable_query.peek().unwrap());
+        assert!(tokens.end());
 
         Ok(())
     }
@@ -1201,15 +4761,15 @@ mod tests {
     #[test]
     fn test_parse_function_with_name_no_args() -> Result<(), String> {
         let func_name = "test".to_string();
-        let query = format!("{}() ", func_name);
-        let mut peekable_query: PeekableDeque<char> = PeekableDeque::from_iter(query.chars());
+        let query = format!("{}() a", func_name);
+        let mut tokens = tokens(&query);
 
-        match Query::parse_function(&mut peekable_query, None) {
+        match Query::parse_function(&mut tokens) {
             Ok(func) => assert_eq!(Function::new(func_name, Vec::new()), func),
             Err(error) => return Err(error),
         }
 
-        assert_eq!(' ', *peekable_query.peek().unwrap());
+        assert_eq!(Some("a".to_string()), peeked(&tokens));
 
         Ok(())
     }
@@ -1217,35 +4777,18 @@ mod tests {
     /////////////////////////////////////
     // PARSE FIELD VALUE
     /////////////////////////////////////
-    #[ignore = "TODO: implement bool parsing"]
-    #[test]
-    fn test_parse_field_value_when_bool() -> Result<(), String> {
-        let bool_value = false;
-        let query = format!("{} ", bool_value);
-        let mut peekable_query: PeekableDeque<char> = PeekableDeque::from_iter(query.chars());
-
-        match Query::parse_field_value(&mut peekable_query) {
-            Ok(fv) => assert_eq!(FieldValue::Bool(bool_value), fv),
-            Err(error) => return Err(error),
-        }
-
-        assert_eq!(' ', *peekable_query.peek().unwrap());
-
-        Ok(())
-    }
-
     #[test]
     fn test_parse_field_value_when_string() -> Result<(), String> {
         let str = "test".to_string();
-        let query = format!("'{}' ", str);
-        let mut peekable_query: PeekableDeque<char> = PeekableDeque::from_iter(query.chars());
+        let query = format!("'{}' a", str);
+        let mut tokens = tokens(&query);
 
-        match Query::parse_field_value(&mut peekable_query) {
+        match Query::parse_field_value(&mut tokens) {
             Ok(fv) => assert_eq!(FieldValue::String(str), fv),
             Err(error) => return Err(error),
         }
 
-        assert_eq!(' ', *peekable_query.peek().unwrap());
+        assert_eq!(Some("a".to_string()), peeked(&tokens));
 
         Ok(())
     }
@@ -1253,421 +4796,482 @@ mod tests {
     #[test]
     fn test_parse_field_value_when_number() -> Result<(), String> {
         let num: f64 = 541.0;
-        let query = format!("{} ", num);
-        let mut peekable_query: PeekableDeque<char> = PeekableDeque::from_iter(query.chars());
+        let query = format!("{} a", num);
+        let mut tokens = tokens(&query);
 
-        match Query::parse_field_value(&mut peekable_query) {
-            Ok(fv) => assert_eq!(FieldValue::Number(num), fv),
+        match Query::parse_field_value(&mut tokens) {
+            Ok(fv) => assert_eq!(FieldValue::Float(num), fv),
             Err(error) => return Err(error),
         }
 
-        assert_eq!(' ', *peekable_query.peek().unwrap());
+        assert_eq!(Some("a".to_string()), peeked(&tokens));
 
         Ok(())
     }
 
-    /////////////////////////////////////
-    // PARSE NO BRACKET EXPRESSION
-    /////////////////////////////////////
     #[test]
-    fn test_parse_no_bracket_expression_with_operator() -> Result<(), String> {
-        let field_name = "kifla".to_string();
-        let bool_value = false;
-        let query = format!("{} and {}", field_name, bool_value);
-        let mut peekable_query: PeekableDeque<char> = PeekableDeque::from_iter(query.chars());
+    fn test_parse_field_value_when_field_name() -> Result<(), String> {
+        let mut tokens = tokens("test");
 
-        let mut expression_elements: Vec<ExpressionElement> = Vec::new();
+        if Query::parse_field_value(&mut tokens).is_ok() {
+            return Err("This should fail, because \"test\" is not a value".to_string());
+        }
 
-        assert_eq!(
-            Ok(()),
-            Query::parse_no_bracket_expression(&mut peekable_query, &mut expression_elements)
-        );
-        assert_eq!(
-            vec![
-                ExpressionElement::FieldName(field_name),
-                ExpressionElement::Operator(Operator::And),
-                ExpressionElement::FieldValue(FieldValue::Bool(bool_value))
-            ],
-            expression_elements
-        );
+        assert_eq!(Some("test".to_string()), peeked(&tokens));
 
         Ok(())
     }
 
     #[test]
-    fn test_parse_no_bracket_expression_when_field_name() -> Result<(), String> {
-        let field_name = "truea".to_string();
-        let query = format!("{} ", field_name);
-        let mut peekable_query: PeekableDeque<char> = PeekableDeque::from_iter(query.chars());
-
-        let mut expression_elements: Vec<ExpressionElement> = Vec::new();
+    fn test_parse_field_value_when_bool() -> Result<(), String> {
+        let mut tokens = tokens("FALSE True null a");
 
         assert_eq!(
-            Ok(()),
-            Query::parse_no_bracket_expression(&mut peekable_query, &mut expression_elements)
+            FieldValue::Bool(false),
+            Query::parse_field_value(&mut tokens)?
         );
         assert_eq!(
-            vec![ExpressionElement::FieldName(field_name)],
-            expression_elements
+            FieldValue::Bool(true),
+            Query::parse_field_value(&mut tokens)?
+        );
+        assert_eq!(FieldValue::Null, Query::parse_field_value(&mut tokens)?);
+        assert_eq!(
+            Err("Expected true or false, but found a".to_string()),
+            Query::parse_bool(&mut tokens)
         );
+        assert_eq!(Some("a".to_string()), peeked(&tokens));
 
         Ok(())
     }
 
     #[test]
-    fn test_parse_no_bracket_expression_when_func() -> Result<(), String> {
-        let func_name = "true".to_string();
-        let query = format!("{}() ", func_name);
-        let mut peekable_query: PeekableDeque<char> = PeekableDeque::from_iter(query.chars());
-
-        let mut expression_elements: Vec<ExpressionElement> = Vec::new();
+    fn test_parse_null() -> Result<(), String> {
+        let query: Query = "WHERE due != NULL AND DATEADD('day', 1, null) == Null".parse()?;
 
         assert_eq!(
-            Ok(()),
-            Query::parse_no_bracket_expression(&mut peekable_query, &mut expression_elements)
+            vec![
+                ExpressionElement::field("due"),
+                ExpressionElement::op(Operator::Neq),
+                ExpressionElement::FieldValue(FieldValue::Null),
+                ExpressionElement::op(Operator::And),
+                ExpressionElement::Function(Function::new(
+                    "DATEADD".to_string(),
+                    vec![
+                        FunctionArg::FieldValue(FieldValue::String("day".to_string())),
+                        FunctionArg::FieldValue(FieldValue::Integer(1)),
+                        FunctionArg::FieldValue(FieldValue::Null),
+                    ]
+                )),
+                ExpressionElement::op(Operator::Eq),
+                ExpressionElement::FieldValue(FieldValue::Null),
+            ],
+            query.where_expression
         );
         assert_eq!(
-            vec![ExpressionElement::Function(Function::new(
-                func_name,
-                Vec::new()
-            ))],
-            expression_elements
+            "WHERE due != NULL AND DATEADD('day', 1, NULL) == NULL",
+            query.to_string()
         );
 
         Ok(())
     }
 
     #[test]
-    fn test_parse_no_bracket_expression_when_bool() -> Result<(), String> {
-        let bool_value = false;
-        let query = format!("{} ", bool_value);
-        let mut peekable_query: PeekableDeque<char> = PeekableDeque::from_iter(query.chars());
-
-        let mut expression_elements: Vec<ExpressionElement> = Vec::new();
+    fn test_parse_bool_in_any_case() -> Result<(), String> {
+        let query: Query =
+            "WHERE checked == FALSE AND DATE(created, TRUE) OR a IN (True)".parse()?;
 
         assert_eq!(
-            Ok(()),
-            Query::parse_no_bracket_expression(&mut peekable_query, &mut expression_elements)
+            "WHERE checked == false AND DATE(created, true) OR a IN (true)",
+            query.to_string()
         );
+        // Only whole words
         assert_eq!(
-            vec![ExpressionElement::FieldValue(FieldValue::Bool(bool_value))],
-            expression_elements
+            vec![ExpressionElement::field("truely")],
+            "WHERE truely".parse::<Query>()?.where_expression
         );
 
         Ok(())
     }
 
+    /////////////////////////////////////
+    // PARSE NUMBER
+    /////////////////////////////////////
     #[test]
-    fn test_parse_no_bracket_expression_when_string() -> Result<(), String> {
-        let str = "test".to_string();
-        let query = format!("'{}' ", str);
-        let mut peekable_query: PeekableDeque<char> = PeekableDeque::from_iter(query.chars());
+    fn test_parse_invalid_negative_number() -> Result<(), String> {
+        let mut tokens = tokens("-test");
 
-        let mut expression_elements: Vec<ExpressionElement> = Vec::new();
+        if Query::parse_field_value(&mut tokens).is_ok() {
+            return Err("This should fail, because \"test\" is not a number".to_string());
+        }
 
-        assert_eq!(
-            Ok(()),
-            Query::parse_no_bracket_expression(&mut peekable_query, &mut expression_elements)
-        );
-        assert_eq!(
-            vec![ExpressionElement::FieldValue(FieldValue::String(str))],
-            expression_elements
-        );
+        assert_eq!(Some("-".to_string()), peeked(&tokens));
 
         Ok(())
     }
 
     #[test]
-    fn test_parse_no_bracket_expression_when_number() -> Result<(), String> {
-        let num: f64 = 541.0;
-        let query = format!("{} ", num);
-        let mut peekable_query: PeekableDeque<char> = PeekableDeque::from_iter(query.chars());
-
-        let mut expression_elements: Vec<ExpressionElement> = Vec::new();
+    fn test_parse_negative_number_with_space() -> Result<(), String> {
+        let mut tokens = tokens("- 5");
 
-        assert_eq!(
-            Ok(()),
-            Query::parse_no_bracket_expression(&mut peekable_query, &mut expression_elements)
-        );
-        assert_eq!(
-            vec![ExpressionElement::FieldValue(FieldValue::Number(num))],
-            expression_elements
-        );
+        if Query::parse_field_value(&mut tokens).is_ok() {
+            return Err("This should fail, because sign is separated from number".to_string());
+        }
 
         Ok(())
     }
 
-    /////////////////////////////////////
-    // PARSE BOOL FIELD NAME OR FUNCTION
-    /////////////////////////////////////
     #[test]
-    fn test_parse_bool_field_name_or_function_when_field_name() -> Result<(), String> {
-        let field_name = "truea".to_string();
-        let query = format!("{} ", field_name);
-        let mut peekable_query: PeekableDeque<char> = PeekableDeque::from_iter(query.chars());
+    fn test_parse_decimal_number_with_comma() -> Result<(), String> {
+        let num: f64 = 543.0;
+        let query = format!("{},21a", num);
+        let mut tokens = tokens(&query);
 
-        match Query::parse_bool_field_name_or_function(&mut peekable_query) {
-            Ok(_field_name) => assert_eq!(ExpressionElement::FieldName(field_name), _field_name),
+        match Query::parse_field_value(&mut tokens) {
+            Ok(fv) => assert_eq!(FieldValue::Float(num), fv),
             Err(error) => return Err(error),
         }
 
-        assert_eq!(' ', *peekable_query.peek().unwrap());
+        assert_eq!(Some(",".to_string()), peeked(&tokens));
 
         Ok(())
     }
 
     #[test]
-    fn test_parse_bool_field_name_or_function_when_function() -> Result<(), String> {
-        let func_name = "true".to_string();
-        let query = format!("{}() ", func_name);
-        let mut peekable_query: PeekableDeque<char> = PeekableDeque::from_iter(query.chars());
+    fn test_parse_decimal_number_with_dot() -> Result<(), String> {
+        let num: f64 = 543.21;
+        let query = format!("{}a", num);
+        let mut tokens = tokens(&query);
 
-        match Query::parse_bool_field_name_or_function(&mut peekable_query) {
-            Ok(_func) => assert_eq!(
-                ExpressionElement::Function(Function::new(func_name, Vec::new())),
-                _func
-            ),
+        match Query::parse_field_value(&mut tokens) {
+            Ok(fv) => assert_eq!(FieldValue::Float(num), fv),
             Err(error) => return Err(error),
         }
 
-        assert_eq!(' ', *peekable_query.peek().unwrap());
+        assert_eq!(Some("a".to_string()), peeked(&tokens));
 
         Ok(())
     }
 
     #[test]
-    fn test_parse_bool_field_name_or_function_when_false() -> Result<(), String> {
-        let bool_value = false;
-        let query = format!("{} ", bool_value);
-        let mut peekable_query: PeekableDeque<char> = PeekableDeque::from_iter(query.chars());
+    fn test_parse_negative_number() -> Result<(), String> {
+        let num: f64 = -543.0;
+        let query = format!("{}a", num);
+        let mut tokens = tokens(&query);
 
-        match Query::parse_bool_field_name_or_function(&mut peekable_query) {
-            Ok(_bool_value) => assert_eq!(
-                ExpressionElement::FieldValue(FieldValue::Bool(bool_value)),
-                _bool_value
-            ),
+        match Query::parse_field_value(&mut tokens) {
+            Ok(fv) => assert_eq!(FieldValue::Float(num), fv),
             Err(error) => return Err(error),
         }
 
-        assert_eq!(' ', *peekable_query.peek().unwrap());
+        assert_eq!(Some("a".to_string()), peeked(&tokens));
 
         Ok(())
     }
 
     #[test]
-    fn test_parse_bool_field_name_or_function_when_true() -> Result<(), String> {
-        let bool_value = true;
-        let query = format!("{} ", bool_value);
-        let mut peekable_query: PeekableDeque<char> = PeekableDeque::from_iter(query.chars());
+    fn test_parse_positive_number() -> Result<(), String> {
+        let num: f64 = 543.0;
+        let query = format!("{}a", num);
+        let mut tokens = tokens(&query);
 
-        match Query::parse_bool_field_name_or_function(&mut peekable_query) {
-            Ok(_bool_value) => assert_eq!(
-                ExpressionElement::FieldValue(FieldValue::Bool(bool_value)),
-                _bool_value
-            ),
+        match Query::parse_field_value(&mut tokens) {
+            Ok(fv) => assert_eq!(FieldValue::Float(num), fv),
             Err(error) => return Err(error),
         }
 
-        assert_eq!(' ', *peekable_query.peek().unwrap());
+        assert_eq!(Some("a".to_string()), peeked(&tokens));
 
         Ok(())
     }
 
-    /////////////////////////////////////
-    // PARSE NUMBER
-    /////////////////////////////////////
     #[test]
-    fn test_parse_invalid_decimal_number() -> Result<(), String> {
-        let query = "5.3.2".to_string();
-        let mut peekable_query: PeekableDeque<char> = PeekableDeque::from_iter(query.chars());
+    fn test_parse_integer_and_float() -> Result<(), String> {
+        let query: Query =
+            "SELECT a FROM F(3) WHERE a == 3 OR a == 3.0 OR a == -9007199254740993".parse()?;
 
-        if Query::parse_number(&mut peekable_query).is_ok() {
-            return Err("This should fail, because \"test\" is not a number".to_string());
-        }
+        assert_eq!(
+            "[FieldValue(Integer(3))]",
+            format!("{:?}", query.from_function.unwrap().args)
+        );
+        let literals: Vec<String> = query
+            .where_expression
+            .iter()
+            .filter_map(|element| match element {
+                ExpressionElement::FieldValue(value) => Some(format!("{:?}", value)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            vec!["Integer(3)", "Float(3.0)", "Integer(-9007199254740993)"],
+            literals
+        );
+        assert_eq!("3.0", FieldValue::Float(3.0).to_query_string());
+        assert_eq!("3", FieldValue::Integer(3).to_query_string());
 
         Ok(())
     }
 
+    /////////////////////////////////////
+    // PARSE EXPRESSION
+    /////////////////////////////////////
     #[test]
-    fn test_parse_invalid_negative_number() -> Result<(), String> {
-        let query = "-test".to_string();
-        let mut peekable_query: PeekableDeque<char> = PeekableDeque::from_iter(query.chars());
+    fn test_parse_expression_with_operator() -> Result<(), String> {
+        let field_name = "kifla".to_string();
+        let bool_value = false;
+        let query = format!("{} and {}", field_name, bool_value);
+        let mut tokens = tokens(&query);
 
-        if Query::parse_number(&mut peekable_query).is_ok() {
-            return Err("This should fail, because \"test\" is not a number".to_string());
-        }
+        let mut expression_elements: Vec<ExpressionElement> = Vec::new();
 
-        assert_eq!('t', *peekable_query.peek().unwrap());
+        assert_eq!(
+            Ok(()),
+            Query::parse_expression(&mut tokens, &mut expression_elements, &mut Vec::new())
+        );
+        assert_eq!(
+            vec![
+                ExpressionElement::FieldName(field_name),
+                ExpressionElement::Operator(Operator::And),
+                ExpressionElement::FieldValue(FieldValue::Bool(bool_value))
+            ],
+            expression_elements
+        );
 
         Ok(())
     }
 
     #[test]
-    fn test_parse_invalid_number() -> Result<(), String> {
-        let query = "test".to_string();
-        let mut peekable_query: PeekableDeque<char> = PeekableDeque::from_iter(query.chars());
+    fn test_parse_expression_with_brackets() -> Result<(), String> {
+        let mut tokens = tokens("(a or (b))+c - -1 order");
 
-        if Query::parse_number(&mut peekable_query).is_ok() {
-            return Err("This should fail, because \"test\" is not a number".to_string());
-        }
+        let mut expression_elements: Vec<ExpressionElement> = Vec::new();
 
-        assert_eq!('t', *peekable_query.peek().unwrap());
+        Query::parse_expression(&mut tokens, &mut expression_elements, &mut Vec::new())?;
+        assert_eq!(
+            vec![
+                ExpressionElement::OpenedBracket,
+                ExpressionElement::field("a"),
+                ExpressionElement::op(Operator::Or),
+                ExpressionElement::OpenedBracket,
+                ExpressionElement::field("b"),
+                ExpressionElement::ClosedBracket,
+                ExpressionElement::ClosedBracket,
+                ExpressionElement::op(Operator::Plus),
+                ExpressionElement::field("c"),
+                ExpressionElement::op(Operator::Minus),
+                ExpressionElement::integer(-1),
+            ],
+            expression_elements
+        );
+        assert_eq!(Some("order".to_string()), peeked(&tokens));
 
         Ok(())
     }
 
     #[test]
-    fn test_parse_decimal_number_with_comma() -> Result<(), String> {
-        let num: f64 = 543.0;
-        let query = format!("{},21a", num);
-        let mut peekable_query: PeekableDeque<char> = PeekableDeque::from_iter(query.chars());
-
-        match Query::parse_number(&mut peekable_query) {
-            Ok(_num) => assert_eq!(num, _num),
-            Err(error) => return Err(error),
-        }
+    fn test_parse_expression_with_unclosed_bracket() {
+        let mut tokens = tokens("(a or b");
 
-        assert_eq!(',', *peekable_query.peek().unwrap());
-
-        Ok(())
+        assert_eq!(
+            Err("Expected a ')', but found nothing".to_string()),
+            Query::parse_expression(&mut tokens, &mut Vec::new(), &mut Vec::new())
+        );
     }
 
     #[test]
-    fn test_parse_decimal_number_with_dot() -> Result<(), String> {
-        let num: f64 = 543.21;
-        let query = format!("{}a", num);
-        let mut peekable_query: PeekableDeque<char> = PeekableDeque::from_iter(query.chars());
+    fn test_parse_expression_when_field_name() -> Result<(), String> {
+        let field_name = "truea".to_string();
+        let query = format!("{} ", field_name);
+        let mut tokens = tokens(&query);
 
-        match Query::parse_number(&mut peekable_query) {
-            Ok(_num) => assert_eq!(num, _num),
-            Err(error) => return Err(error),
-        }
+        let mut expression_elements: Vec<ExpressionElement> = Vec::new();
 
-        assert_eq!('a', *peekable_query.peek().unwrap());
+        assert_eq!(
+            Ok(()),
+            Query::parse_expression(&mut tokens, &mut expression_elements, &mut Vec::new())
+        );
+        assert_eq!(
+            vec![ExpressionElement::FieldName(field_name)],
+            expression_elements
+        );
 
         Ok(())
     }
 
     #[test]
-    fn test_parse_negative_number() -> Result<(), String> {
-        let num: f64 = -543.0;
-        let query = format!("{}a", num);
-        let mut peekable_query: PeekableDeque<char> = PeekableDeque::from_iter(query.chars());
+    fn test_parse_expression_when_func() -> Result<(), String> {
+        let func_name = "true".to_string();
+        let query = format!("{}() ", func_name);
+        let mut tokens = tokens(&query);
 
-        match Query::parse_number(&mut peekable_query) {
-            Ok(_num) => assert_eq!(num, _num),
-            Err(error) => return Err(error),
-        }
+        let mut expression_elements: Vec<ExpressionElement> = Vec::new();
 
-        assert_eq!('a', *peekable_query.peek().unwrap());
+        assert_eq!(
+            Ok(()),
+            Query::parse_expression(&mut tokens, &mut expression_elements, &mut Vec::new())
+        );
+        assert_eq!(
+            vec![ExpressionElement::Function(Function::new(
+                func_name,
+                Vec::new()
+            ))],
+            expression_elements
+        );
 
         Ok(())
     }
 
     #[test]
-    fn test_parse_positive_number() -> Result<(), String> {
-        let num: f64 = 543.0;
-        let query = format!("{}a", num);
-        let mut peekable_query: PeekableDeque<char> = PeekableDeque::from_iter(query.chars());
+    fn test_parse_expression_when_bool() -> Result<(), String> {
+        let bool_value = false;
+        let query = format!("{} ", bool_value);
+        let mut tokens = tokens(&query);
 
-        match Query::parse_number(&mut peekable_query) {
-            Ok(_num) => assert_eq!(num, _num),
-            Err(error) => return Err(error),
-        }
+        let mut expression_elements: Vec<ExpressionElement> = Vec::new();
 
-        assert_eq!('a', *peekable_query.peek().unwrap());
+        assert_eq!(
+            Ok(()),
+            Query::parse_expression(&mut tokens, &mut expression_elements, &mut Vec::new())
+        );
+        assert_eq!(
+            vec![ExpressionElement::FieldValue(FieldValue::Bool(bool_value))],
+            expression_elements
+        );
 
         Ok(())
     }
 
-    /////////////////////////////////////
-    // PARSE STRING
-    /////////////////////////////////////
     #[test]
-    fn test_parse_string_without_opening_quote() -> Result<(), String> {
-        let query = "test' and field > 5".to_string();
-        let mut peekable_query: PeekableDeque<char> = PeekableDeque::from_iter(query.chars());
+    fn test_parse_expression_when_string() -> Result<(), String> {
+        let str = "test".to_string();
+        let query = format!("'{}' ", str);
+        let mut tokens = tokens(&query);
 
-        if Query::parse_string(&mut peekable_query).is_ok() {
-            return Err("This should fail, because string is not closed".to_string());
-        }
+        let mut expression_elements: Vec<ExpressionElement> = Vec::new();
+
+        assert_eq!(
+            Ok(()),
+            Query::parse_expression(&mut tokens, &mut expression_elements, &mut Vec::new())
+        );
+        assert_eq!(
+            vec![ExpressionElement::FieldValue(FieldValue::String(str))],
+            expression_elements
+        );
 
         Ok(())
     }
 
     #[test]
-    fn test_parse_string_without_closed_quote() -> Result<(), String> {
-        let query = "'test and field > 5".to_string();
-        let mut peekable_query: PeekableDeque<char> = PeekableDeque::from_iter(query.chars());
+    fn test_parse_expression_when_number() -> Result<(), String> {
+        let num: f64 = 541.0;
+        let query = format!("{} ", num);
+        let mut tokens = tokens(&query);
 
-        if Query::parse_string(&mut peekable_query).is_ok() {
-            return Err("This should fail, because string is not closed".to_string());
-        }
+        let mut expression_elements: Vec<ExpressionElement> = Vec::new();
+
+        assert_eq!(
+            Ok(()),
+            Query::parse_expression(&mut tokens, &mut expression_elements, &mut Vec::new())
+        );
+        assert_eq!(
+            vec![ExpressionElement::FieldValue(FieldValue::Float(num))],
+            expression_elements
+        );
 
         Ok(())
     }
 
     #[test]
-    fn test_parse_string_with_mixed_quotes2() -> Result<(), String> {
-        let query = "\"test'".to_string();
-        let mut peekable_query: PeekableDeque<char> = PeekableDeque::from_iter(query.chars());
+    fn test_parse_expression_when_parameter() -> Result<(), String> {
+        let mut tokens = tokens("field > :min");
 
-        if Query::parse_string(&mut peekable_query).is_ok() {
-            return Err("This should fail, because string is not closed".to_string());
-        }
+        let mut expression_elements: Vec<ExpressionElement> = Vec::new();
+
+        Query::parse_expression(&mut tokens, &mut expression_elements, &mut Vec::new())?;
+        assert_eq!(
+            vec![
+                ExpressionElement::FieldName("field".to_string()),
+                ExpressionElement::Operator(Operator::Gt),
+                ExpressionElement::Parameter("min".to_string()),
+            ],
+            expression_elements
+        );
 
         Ok(())
     }
 
+    /////////////////////////////////////
+    // PARSE BOOL FIELD NAME OR FUNCTION
+    /////////////////////////////////////
     #[test]
-    fn test_parse_string_with_mixed_quotes1() -> Result<(), String> {
-        let query = "'test\"".to_string();
-        let mut peekable_query: PeekableDeque<char> = PeekableDeque::from_iter(query.chars());
+    fn test_parse_bool_field_name_or_function_when_field_name() -> Result<(), String> {
+        let field_name = "truea".to_string();
+        let query = format!("{} a", field_name);
+        let mut tokens = tokens(&query);
 
-        if Query::parse_string(&mut peekable_query).is_ok() {
-            return Err("This should fail, because string is not closed".to_string());
+        match Query::parse_bool_field_name_or_function(&mut tokens) {
+            Ok(_field_name) => assert_eq!(ExpressionElement::FieldName(field_name), _field_name),
+            Err(error) => return Err(error),
         }
 
+        assert_eq!(Some("a".to_string()), peeked(&tokens));
+
         Ok(())
     }
 
     #[test]
-    fn test_parse_string_with_single_quotes() -> Result<(), String> {
-        let query = "'test'".to_string();
-        let mut peekable_query: PeekableDeque<char> = PeekableDeque::from_iter(query.chars());
+    fn test_parse_bool_field_name_or_function_when_function() -> Result<(), String> {
+        let func_name = "true".to_string();
+        let query = format!("{}() a", func_name);
+        let mut tokens = tokens(&query);
 
-        match Query::parse_string(&mut peekable_query) {
-            Ok(str) => assert_eq!("test", str),
+        match Query::parse_bool_field_name_or_function(&mut tokens) {
+            Ok(_func) => assert_eq!(
+                ExpressionElement::Function(Function::new(func_name, Vec::new())),
+                _func
+            ),
             Err(error) => return Err(error),
         }
 
+        assert_eq!(Some("a".to_string()), peeked(&tokens));
+
         Ok(())
     }
 
     #[test]
-    fn test_parse_string_with_double_quotes() -> Result<(), String> {
-        let query = "\"test\"".to_string();
-        let mut peekable_query: PeekableDeque<char> = PeekableDeque::from_iter(query.chars());
+    fn test_parse_bool_field_name_or_function_when_false() -> Result<(), String> {
+        let bool_value = false;
+        let query = format!("{} a", bool_value);
+        let mut tokens = tokens(&query);
 
-        match Query::parse_string(&mut peekable_query) {
-            Ok(str) => assert_eq!("test", str),
+        match Query::parse_bool_field_name_or_function(&mut tokens) {
+            Ok(_bool_value) => assert_eq!(
+                ExpressionElement::FieldValue(FieldValue::Bool(bool_value)),
+                _bool_value
+            ),
             Err(error) => return Err(error),
         }
 
+        assert_eq!(Some("a".to_string()), peeked(&tokens));
+
         Ok(())
     }
 
     #[test]
-    fn test_parse_valid_string_with_different_chars() -> Result<(), String> {
-        let str = "o oeuaoe 45646 ?$%^ ";
-        let query = format!("'{}'", str);
-        let mut peekable_query: PeekableDeque<char> = PeekableDeque::from_iter(query.chars());
+    fn test_parse_bool_field_name_or_function_when_true() -> Result<(), String> {
+        let bool_value = true;
+        let query = format!("{} a", bool_value);
+        let mut tokens = tokens(&query);
 
-        match Query::parse_string(&mut peekable_query) {
-            Ok(_str) => assert_eq!(str, _str),
+        match Query::parse_bool_field_name_or_function(&mut tokens) {
+            Ok(_bool_value) => assert_eq!(
+                ExpressionElement::FieldValue(FieldValue::Bool(bool_value)),
+                _bool_value
+            ),
             Err(error) => return Err(error),
         }
 
+        assert_eq!(Some("a".to_string()), peeked(&tokens));
+
         Ok(())
     }
 
@@ -1676,40 +5280,37 @@ mod tests {
     /////////////////////////////////////
     #[test]
     fn test_parse_existing_operator_with_space() -> Result<(), String> {
-        let operator = "AND ".to_string();
-        let mut peekable_query: PeekableDeque<char> = PeekableDeque::from_iter(operator.chars());
+        let mut tokens = tokens("AND a");
 
-        match Query::try_parse_operator(&mut peekable_query) {
+        match Query::try_parse_operator(&mut tokens) {
             Ok(op) => assert_eq!(Operator::And, op),
             Err(error) => return Err(error),
         }
 
-        assert_eq!(' ', *peekable_query.peek().unwrap());
+        assert_eq!(Some("a".to_string()), peeked(&tokens));
 
         Ok(())
     }
 
     #[test]
     fn test_parse_existing_operator_lowercase() -> Result<(), String> {
-        let operator = "and ".to_string();
-        let mut peekable_query: PeekableDeque<char> = PeekableDeque::from_iter(operator.chars());
+        let mut tokens = tokens("and a");
 
-        match Query::try_parse_operator(&mut peekable_query) {
+        match Query::try_parse_operator(&mut tokens) {
             Ok(op) => assert_eq!(Operator::And, op),
             Err(error) => return Err(error),
         }
 
-        assert_eq!(' ', *peekable_query.peek().unwrap());
+        assert_eq!(Some("a".to_string()), peeked(&tokens));
 
         Ok(())
     }
 
     #[test]
     fn test_parse_existing_operator_no_text() -> Result<(), String> {
-        let operator = "<=".to_string();
-        let mut peekable_query: PeekableDeque<char> = PeekableDeque::from_iter(operator.chars());
+        let mut tokens = tokens("<=");
 
-        match Query::try_parse_operator(&mut peekable_query) {
+        match Query::try_parse_operator(&mut tokens) {
             Ok(op) => assert_eq!(Operator::Lte, op),
             Err(error) => return Err(error),
         }
@@ -1719,23 +5320,9 @@ mod tests {
 
     #[test]
     fn test_parse_existing_operator_without_space() -> Result<(), String> {
-        let operator = "AND".to_string();
-        let mut peekable_query: PeekableDeque<char> = PeekableDeque::from_iter(operator.chars());
-
-        match Query::try_parse_operator(&mut peekable_query) {
-            Ok(op) => assert_eq!(Operator::And, op),
-            Err(error) => return Err(error),
-        }
+        let mut tokens = tokens("AND");
 
-        Ok(())
-    }
-
-    #[test]
-    fn test_parse_existing_operator() -> Result<(), String> {
-        let operator = "AND ".to_string();
-        let mut peekable_query: PeekableDeque<char> = PeekableDeque::from_iter(operator.chars());
-
-        match Query::try_parse_operator(&mut peekable_query) {
+        match Query::try_parse_operator(&mut tokens) {
             Ok(op) => assert_eq!(Operator::And, op),
             Err(error) => return Err(error),
         }
@@ -1744,56 +5331,54 @@ mod tests {
     }
 
     #[test]
-    fn test_parse_non_existing_long_alphabetic_operator() -> Result<(), String> {
-        let operator = "ANDN".to_string();
-        let mut peekable_query: PeekableDeque<char> = PeekableDeque::from_iter(operator.chars());
+    fn test_parse_alphabetic_operator_followed_by_bracket() -> Result<(), String> {
+        let mut tokens = tokens("AND(a)");
 
-        if Query::try_parse_operator(&mut peekable_query).is_ok() {
+        if Query::try_parse_operator(&mut tokens).is_ok() {
             return Err(
                 "Should fail because alphabetic operators require whitespace after them!"
                     .to_string(),
             );
         }
 
-        assert_eq!('A', *peekable_query.peek().unwrap());
+        assert_eq!(Some("AND".to_string()), peeked(&tokens));
 
         Ok(())
     }
 
     #[test]
-    fn test_parse_non_existing_long_non_alphabetic_operator() -> Result<(), String> {
-        let operator = "<N".to_string();
-        let mut peekable_query: PeekableDeque<char> = PeekableDeque::from_iter(operator.chars());
+    fn test_parse_non_existing_long_alphabetic_operator() -> Result<(), String> {
+        let mut tokens = tokens("ANDN");
 
-        match Query::try_parse_operator(&mut peekable_query) {
-            Ok(op) => assert_eq!(Operator::Lt, op),
-            Err(error) => return Err(error),
+        if Query::try_parse_operator(&mut tokens).is_ok() {
+            return Err("It should fail since there is no operator ANDN!".to_string());
         }
 
-        assert_eq!('N', *peekable_query.peek().unwrap());
+        assert_eq!(Some("ANDN".to_string()), peeked(&tokens));
 
         Ok(())
     }
 
     #[test]
-    fn test_parse_non_existing_short_operator_with_space() -> Result<(), String> {
-        let operator = "A ".to_string();
-        let mut peekable_query: PeekableDeque<char> = PeekableDeque::from_iter(operator.chars());
+    fn test_parse_non_existing_long_non_alphabetic_operator() -> Result<(), String> {
+        let mut tokens = tokens("<N");
 
-        if Query::try_parse_operator(&mut peekable_query).is_ok() {
-            return Err("It should fail since there is no operator ANN!".to_string());
+        match Query::try_parse_operator(&mut tokens) {
+            Ok(op) => assert_eq!(Operator::Lt, op),
+            Err(error) => return Err(error),
         }
 
+        assert_eq!(Some("N".to_string()), peeked(&tokens));
+
         Ok(())
     }
 
     #[test]
     fn test_parse_non_existing_short_operator() -> Result<(), String> {
-        let operator = "A".to_string();
-        let mut peekable_query: PeekableDeque<char> = PeekableDeque::from_iter(operator.chars());
+        let mut tokens = tokens("A ");
 
-        if Query::try_parse_operator(&mut peekable_query).is_ok() {
-            return Err("It should fail since there is no operator ANN!".to_string());
+        if Query::try_parse_operator(&mut tokens).is_ok() {
+            return Err("It should fail since there is no operator A!".to_string());
         }
 
         Ok(())
@@ -1801,10 +5386,9 @@ mod tests {
 
     #[test]
     fn test_parse_non_existing_operator() -> Result<(), String> {
-        let operator = "ANN".to_string();
-        let mut peekable_query: PeekableDeque<char> = PeekableDeque::from_iter(operator.chars());
+        let mut tokens = tokens("ANN");
 
-        if Query::try_parse_operator(&mut peekable_query).is_ok() {
+        if Query::try_parse_operator(&mut tokens).is_ok() {
             return Err("It should fail since there is no operator ANN!".to_string());
         }
 
@@ -1812,114 +5396,58 @@ mod tests {
     }
 
     #[test]
-    fn test_parse_non_existing_operator_different_first_char() -> Result<(), String> {
-        let operator = "BAN".to_string();
-        let mut peekable_query: PeekableDeque<char> = PeekableDeque::from_iter(operator.chars());
+    fn test_parse_non_existing_symbol_operator() -> Result<(), String> {
+        let mut tokens = tokens("= b");
 
-        if Query::try_parse_operator(&mut peekable_query).is_ok() {
-            return Err("It should fail since there is no operator NAN!".to_string());
+        if Query::try_parse_operator(&mut tokens).is_ok() {
+            return Err("It should fail since there is no operator =!".to_string());
         }
 
-        assert_eq!('B', *peekable_query.peek().unwrap());
+        assert_eq!(Some("=".to_string()), peeked(&tokens));
 
         Ok(())
     }
 
     #[test]
     fn test_parse_operator_not_like() -> Result<(), String> {
-        let operator = "NOT LIKE".to_string();
-        let mut peekable_query: PeekableDeque<char> = PeekableDeque::from_iter(operator.chars());
+        let mut tokens = tokens("NOT LIKE");
 
-        let op = Query::try_parse_operator(&mut peekable_query)?;
+        let op = Query::try_parse_operator(&mut tokens)?;
         assert_eq!(Operator::NotLike, op);
-
-        Ok(())
-    }
-
-    /////////////////////////////////////
-    // PARSE FIELD NAME
-    /////////////////////////////////////
-    #[test]
-    fn test_parse_field_name_ends_with_dot() -> Result<(), String> {
-        let field_name = "test.".to_string();
-        let mut peekable_query: PeekableDeque<char> = PeekableDeque::from_iter(field_name.chars());
-
-        if Query::parse_field_name(&mut peekable_query).is_ok() {
-            return Err("It should fail since field name can't end with a dot!".to_string());
-        }
-
-        Ok(())
-    }
-
-    #[test]
-    fn test_parse_field_name_contains_dot() -> Result<(), String> {
-        let field_name = "te.st".to_string();
-        let mut peekable_query: PeekableDeque<char> = PeekableDeque::from_iter(field_name.chars());
-
-        match Query::parse_field_name(&mut peekable_query) {
-            Ok(parsed_field_name) => assert_eq!(field_name, parsed_field_name),
-            Err(error) => return Err(error),
-        }
-
-        Ok(())
-    }
-
-    #[test]
-    fn test_parse_field_name_contains_minus() -> Result<(), String> {
-        let field_name = "te-st".to_string();
-        let mut peekable_query: PeekableDeque<char> = PeekableDeque::from_iter(field_name.chars());
-
-        match Query::parse_field_name(&mut peekable_query) {
-            Ok(parsed_field_name) => assert_eq!(field_name, parsed_field_name),
-            Err(error) => return Err(error),
-        }
-
-        Ok(())
-    }
-
-    #[test]
-    fn test_parse_field_name_start_with_dot() -> Result<(), String> {
-        let field_name = ".test".to_string();
-        let mut peekable_query: PeekableDeque<char> = PeekableDeque::from_iter(field_name.chars());
-
-        if Query::parse_field_name(&mut peekable_query).is_ok() {
-            return Err("It should fail since field name can't start with a dot!".to_string());
-        }
+        assert!(tokens.end());
 
         Ok(())
     }
 
     #[test]
-    fn test_parse_field_name_start_with_minus() -> Result<(), String> {
-        let field_name = "-test".to_string();
-        let mut peekable_query: PeekableDeque<char> = PeekableDeque::from_iter(field_name.chars());
-
-        if Query::parse_field_name(&mut peekable_query).is_ok() {
-            return Err("It should fail since field name can't start with a minus!".to_string());
+    fn test_parse_operator_regexp() -> Result<(), String> {
+        for (operator, expected) in [
+            ("REGEXP", Operator::Regexp),
+            ("not regexp", Operator::NotRegexp),
+            ("NOT IN", Operator::NotIn),
+        ] {
+            let mut tokens = tokens(operator);
+            assert_eq!(expected, Query::try_parse_operator(&mut tokens)?);
+            assert!(tokens.end());
         }
 
-        Ok(())
-    }
-
-    #[test]
-    fn test_parse_field_name_start_with_underscore() -> Result<(), String> {
-        let field_name = "_test".to_string();
-        let mut peekable_query: PeekableDeque<char> = PeekableDeque::from_iter(field_name.chars());
-
-        match Query::parse_field_name(&mut peekable_query) {
-            Ok(parsed_field_name) => assert_eq!(field_name, parsed_field_name),
-            Err(error) => return Err(error),
-        }
+        let query: Query = "WHERE title NOT REGEXP '/^draft/i'".parse()?;
+        assert_eq!("WHERE title NOT REGEXP '/^draft/i'", query.to_string());
+        let query: Query = "WHERE status NOT IN ('done', 'dropped')".parse()?;
+        assert_eq!("WHERE status NOT IN ('done', 'dropped')", query.to_string());
 
         Ok(())
     }
 
+    /////////////////////////////////////
+    // PARSE FIELD NAME
+    /////////////////////////////////////
     #[test]
     fn test_parse_field_name_basic() -> Result<(), String> {
-        let field_name = "test".to_string();
-        let mut peekable_query: PeekableDeque<char> = PeekableDeque::from_iter(field_name.chars());
+        let field_name = "te-st.a".to_string();
+        let mut tokens = tokens(&field_name);
 
-        match Query::parse_field_name(&mut peekable_query) {
+        match Query::parse_field_name(&mut tokens) {
             Ok(parsed_field_name) => assert_eq!(field_name, parsed_field_name),
             Err(error) => return Err(error),
         }
@@ -1929,10 +5457,9 @@ mod tests {
 
     #[test]
     fn test_parse_field_name_first_char_num() -> Result<(), String> {
-        let field_name = "5test".to_string();
-        let mut peekable_query: PeekableDeque<char> = PeekableDeque::from_iter(field_name.chars());
+        let mut tokens = tokens("5test");
 
-        if Query::parse_field_name(&mut peekable_query).is_ok() {
+        if Query::parse_field_name(&mut tokens).is_ok() {
             return Err("It should fail since field name can't start with a number!".to_string());
         }
 
@@ -1944,10 +5471,9 @@ mod tests {
     /////////////////////////////////////
     #[test]
     fn test_parse_sort_direction_desc() -> Result<(), String> {
-        let query = "desc".to_string();
-        let mut peekable_query: PeekableDeque<char> = PeekableDeque::from_iter(query.chars());
+        let mut tokens = tokens("desc");
 
-        match Query::parse_sort_direction(&mut peekable_query) {
+        match Query::parse_sort_direction(&mut tokens) {
             Ok(sd) => assert_eq!(OrderDirection::DESC, sd),
             Err(error) => return Err(error),
         }
@@ -1957,10 +5483,9 @@ mod tests {
 
     #[test]
     fn test_parse_sort_direction_asc() -> Result<(), String> {
-        let query = "asc".to_string();
-        let mut peekable_query: PeekableDeque<char> = PeekableDeque::from_iter(query.chars());
+        let mut tokens = tokens("asc");
 
-        match Query::parse_sort_direction(&mut peekable_query) {
+        match Query::parse_sort_direction(&mut tokens) {
             Ok(sd) => assert_eq!(OrderDirection::ASC, sd),
             Err(error) => return Err(error),
         }
@@ -1970,10 +5495,9 @@ mod tests {
 
     #[test]
     fn test_parse_sort_direction_non_existant() -> Result<(), String> {
-        let query = "invalid".to_string();
-        let mut peekable_query: PeekableDeque<char> = PeekableDeque::from_iter(query.chars());
+        let mut tokens = tokens("invalid");
 
-        if Query::parse_sort_direction(&mut peekable_query).is_ok() {
+        if Query::parse_sort_direction(&mut tokens).is_ok() {
             return Err("It should fail since there ASC or DESC are expected!".to_string());
         }
 
@@ -1985,126 +5509,187 @@ mod tests {
     /////////////////////////////////////
     #[test]
     fn test_parse_keyword_without_whitespace() -> Result<(), String> {
-        let keyword = "SELECT".to_string();
-        let query = format!("{}bla", keyword);
-        let mut peekable_query: PeekableDeque<char> = PeekableDeque::from_iter(query.chars());
+        let mut tokens = tokens("SELECTbla");
 
-        match Query::parse_keyword(&mut peekable_query, &keyword, true) {
-            Ok(()) => {}
-            Err(error) => return Err(error),
+        if Query::parse_keyword(&mut tokens, "SELECT").is_ok() {
+            return Err("It should fail since SELECTbla is one word!".to_string());
         }
 
-        assert_eq!('b', *peekable_query.peek().unwrap());
-
         Ok(())
     }
 
     #[test]
-    fn test_parse_keyword_case_sensitive() -> Result<(), String> {
-        let query = "SeLeCt ".to_string();
-        let keyword = "SELECT".to_string();
-        let mut peekable_query: PeekableDeque<char> = PeekableDeque::from_iter(query.chars());
+    fn test_parse_keyword_case_insensitive() -> Result<(), String> {
+        let mut tokens = tokens("SeLeCt a");
 
-        if Query::parse_keyword(&mut peekable_query, &keyword, true).is_ok() {
-            return Err(
-                "It should fail since there is no match if we take into account case sensitivity!"
-                    .to_string(),
-            );
+        match Query::parse_keyword(&mut tokens, "SELECT") {
+            Ok(()) => {}
+            Err(error) => return Err(error),
         }
 
-        assert_eq!('e', *peekable_query.peek().unwrap());
+        assert_eq!(Some("a".to_string()), peeked(&tokens));
 
         Ok(())
     }
 
     #[test]
-    fn test_parse_keyword_exact_case_sensitive() -> Result<(), String> {
-        let keyword = "SeLeCt".to_string();
-        let query = format!("{} ", keyword);
-        let mut peekable_query: PeekableDeque<char> = PeekableDeque::from_iter(query.chars());
+    fn test_parse_keyword_multiple_words() -> Result<(), String> {
+        let mut tokens = tokens("order  by a");
 
-        match Query::parse_keyword(&mut peekable_query, &keyword, true) {
-            Ok(()) => {}
-            Err(error) => return Err(error),
-        }
+        Query::parse_keyword(&mut tokens, "ORDER BY")?;
+        assert_eq!(Some("a".to_string()), peeked(&tokens));
 
-        assert_eq!(' ', *peekable_query.peek().unwrap());
+        if Query::parse_keyword(&mut self::tokens("order a"), "ORDER BY").is_ok() {
+            return Err("It should fail since BY is missing!".to_string());
+        }
 
         Ok(())
     }
 
+    /////////////////////////////////////
+    // PARSE MANDATORY WHITESPACE
+    /////////////////////////////////////
     #[test]
-    fn test_parse_keyword_exact_case_insensitive() -> Result<(), String> {
-        let query = "SELECT ".to_string();
-        let keyword = "SeLeCt".to_string();
-        let mut peekable_query: PeekableDeque<char> = PeekableDeque::from_iter(query.chars());
+    fn test_parse_mandatory_whitespaces_without_whitspace() -> Result<(), String> {
+        let mut tokens = tokens("a(b");
+        tokens.next();
 
-        match Query::parse_keyword(&mut peekable_query, &keyword, false) {
-            Ok(()) => {}
-            Err(error) => return Err(error),
+        if Query::parse_mandatory_whitespace(&tokens).is_ok() {
+            return Err("It should fail since there is whitespace is expected".to_string());
         }
 
-        assert_eq!(' ', *peekable_query.peek().unwrap());
-
         Ok(())
     }
 
     #[test]
-    fn test_parse_keyword_start_with_whitespace() -> Result<(), String> {
-        let keyword = "SELECT".to_string();
-        let query = format!("   {}", keyword);
-        let mut peekable_query: PeekableDeque<char> = PeekableDeque::from_iter(query.chars());
-
-        if Query::parse_keyword(&mut peekable_query, &keyword, false).is_ok() {
-            return Err("It should fail since it is supposed to expect the keywoard and it has empty space in the beginning!".to_string());
-        }
+    fn test_parse_mandatory_whitespaces_with_whitspace() {
+        let mut tokens = tokens("a (b");
+        tokens.next();
 
-        Ok(())
+        assert_eq!(Ok(()), Query::parse_mandatory_whitespace(&tokens));
+        assert_eq!(Some("(".to_string()), peeked(&tokens));
     }
 
     /////////////////////////////////////
-    // PARSE WHITESPACE
+    // PARSE UPDATE
     /////////////////////////////////////
     #[test]
-    fn test_parse_whitespaces_skip_whitspace() {
-        let query = "  \t  \t\t\n  \t\n\n  a".to_string();
-        let mut peekable_query: PeekableDeque<char> = PeekableDeque::from_iter(query.chars());
+    fn test_parse_update() -> Result<(), String> {
+        let update: Update =
+            "update FRONTMATTER_DATA('~/notes') SET status = 'archived', tags = ('a', -1), done = true WHERE status == 'done';"
+                .parse()?;
+
+        assert_eq!(
+            vec![
+                Assignment {
+                    field_name: "status".to_string(),
+                    value: FieldValue::String("archived".to_string()),
+                },
+                Assignment {
+                    field_name: "tags".to_string(),
+                    value: FieldValue::List(vec![
+                        FieldValue::String("a".to_string()),
+                        FieldValue::Integer(-1),
+                    ]),
+                },
+                Assignment {
+                    field_name: "done".to_string(),
+                    value: FieldValue::Bool(true),
+                },
+            ],
+            update.assignments
+        );
+        assert_eq!(
+            "UPDATE FRONTMATTER_DATA('~/notes') SET status = 'archived', tags = ('a', -1), done = true WHERE status == 'done'",
+            update.to_string()
+        );
+        assert_eq!(
+            "SELECT * FROM FRONTMATTER_DATA('~/notes') WHERE status == 'done'",
+            update.to_query().to_string()
+        );
+        assert!(Update::is_update("  -- archive\nUPDATE F('x') SET a = 1"));
+        assert!(!Update::is_update("SELECT a FROM F('x')"));
 
-        Query::parse_whitespaces(&mut peekable_query);
-        assert_eq!('a', *peekable_query.peek().unwrap());
+        Ok(())
     }
 
     #[test]
-    fn test_parse_whitespaces_nothing_to_skip() {
-        let query = "a  \t\t\n\n  ".to_string();
-        let mut peekable_query: PeekableDeque<char> = PeekableDeque::from_iter(query.chars());
+    fn test_parse_update_errors() {
+        let error = |statement: &str| statement.parse::<Update>().unwrap_err();
 
-        Query::parse_whitespaces(&mut peekable_query);
-        assert_eq!('a', *peekable_query.peek().unwrap());
+        assert!(error("UPDATE F('x') WHERE a == 1").contains("Expected SET"));
+        assert!(error("UPDATE F('x') SET a == 1").contains("Expected '=', but found =="));
+        assert!(error("UPDATE F('x') SET a = b").contains("Expected a value, but found b"));
+        assert!(error("UPDATE F('x') SET a = 1 b").contains("Expected one of: ,, WHERE"));
     }
 
     /////////////////////////////////////
-    // PARSE MANDATORY WHITESPACE
+    // DEFINE AND MACROS
     /////////////////////////////////////
     #[test]
-    fn test_parse_mandatory_whitespaces_without_whitspace() -> Result<(), String> {
-        let query = "b".to_string();
-        let mut peekable_query: PeekableDeque<char> = PeekableDeque::from_iter(query.chars());
+    fn test_parse_define_expands_macros() -> Result<(), String> {
+        let text = "DEFINE active AS status != 'done' OR urgent;\n\
+                    DEFINE due AS ($active AND due < today);\n\
+                    SELECT title FROM F('x') WHERE $due AND $later";
+        let query: Query = text.parse()?;
 
-        if Query::parse_mandatory_whitespace(&mut peekable_query).is_ok() {
-            return Err("It should fail since there is whitespace is expected".to_string());
-        }
-        assert_eq!('b', *peekable_query.peek().unwrap());
+        assert_eq!(
+            "SELECT title FROM F('x') WHERE ((status != 'done' OR urgent) AND due < today) AND $later",
+            query.to_string()
+        );
+        assert_eq!(vec!["later".to_string()], query.macros());
+        // Expanded elements point at the definition they come from
+        assert_eq!(
+            "status != 'done'",
+            query
+                .spans
+                .fragment(query.spans.where_elements(2, 4).unwrap())
+        );
+
+        let later = Definition::new("later", "NOT_BEFORE(today)")?;
+        assert_eq!(
+            "SELECT title FROM F('x') WHERE ((status != 'done' OR urgent) AND due < today) AND NOT_BEFORE(today)",
+            query.expand_macros(&[later])?.to_string()
+        );
+        let error = query.expand_macros(&[Definition::new("latter", "a")?]);
+        assert!(error
+            .unwrap_err()
+            .starts_with("Unknown macro: $later, did you mean 'latter'? — at characters"));
+
+        let query: Query = "DEFINE active AS (status != 'done' AND 'archive' NOT IN tags);\n\
+                            SELECT title FROM F('x') WHERE $active"
+            .parse()?;
+        assert_eq!(
+            "SELECT title FROM F('x') WHERE (status != 'done' AND 'archive' NOT IN tags)",
+            query.to_string()
+        );
+
+        let update: Update = "DEFINE a AS x == 1; UPDATE F('x') SET b = 2 WHERE $a".parse()?;
+        assert_eq!("(x == 1)", expression_to_string(&update.where_expression));
+        assert!(Update::is_update(
+            "DEFINE a AS x == 1; UPDATE F('x') SET b = 2"
+        ));
+        assert!(!Update::is_update("DEFINE a AS x == 1; SELECT b"));
 
         Ok(())
     }
 
     #[test]
-    fn test_parse_mandatory_whitespaces_with_whitspace() {
-        let query = " b".to_string();
-        let mut peekable_query: PeekableDeque<char> = PeekableDeque::from_iter(query.chars());
-
-        let _ = Query::parse_mandatory_whitespace(&mut peekable_query);
-        assert_eq!('b', *peekable_query.peek().unwrap());
+    fn test_parse_define_errors() {
+        let error = |query: &str| query.parse::<Query>().unwrap_err();
+
+        assert!(error("DEFINE a AS x == 1 SELECT b")
+            .contains("Unexpected token 'SELECT', a definition ends with ';'"));
+        assert!(error("DEFINE a AS x; DEFINE a AS y; SELECT b").contains("$a is already defined"));
+        assert!(error("DEFINE a.b AS x; SELECT b").contains("Expected a macro name, but found a.b"));
+        assert!(Definition::new("a[0]", "x").is_err());
+        assert_eq!(
+            "DEFINE a AS (x OR y)",
+            Definition::new("a", "x OR y").unwrap().to_string()
+        );
+        assert_eq!(
+            "DEFINE a AS x",
+            Definition::new("a", "x").unwrap().to_string()
+        );
     }
 }
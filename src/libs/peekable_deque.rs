@@ -12,6 +12,18 @@ impl<T: Display> PeekableDeque<T> {
         self.deque.get(self.index)
     }
 
+    // Method to peek n items past the next one, peek_nth(0) is the same as peek()
+    pub fn peek_nth(&self, n: usize) -> Option<&T> {
+        self.deque.get(self.index + n)
+    }
+
+    // Method to get the last item that was moved past
+    pub fn previous(&self) -> Option<&T> {
+        self.index
+            .checked_sub(1)
+            .and_then(|index| self.deque.get(index))
+    }
+
     pub fn back(&mut self, n: usize) {
         self.index = self.index.saturating_sub(n)
     }
@@ -0,0 +1,345 @@
+use std::error::Error;
+use std::fmt::Display;
+
+use crate::libs::data_fetcher::markdown_fetcher::{
+    count_markdown_files, markdown_path_arguments, source_path_arguments, MarkdownCache,
+};
+use crate::libs::data_fetcher::{BUILTIN_SOURCES, FILE_SOURCES};
+use crate::libs::lexer::tokenize;
+use crate::libs::parser::{expression_to_string, ExpressionElement, Operator, Query};
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum SourceKind {
+    /// One of the markdown sources from [`BUILTIN_SOURCES`]
+    Builtin,
+    /// Registered on the engine with `register_source`
+    Registered,
+    /// One of the data file sources from [`FILE_SOURCES`]
+    File,
+    Unknown,
+}
+
+/// Describes how a query will be executed, without executing it.
+#[derive(Debug, PartialEq, Clone)]
+pub struct QueryPlan {
+    /// The parsed query, with `--select`/`--from` overrides applied
+    pub query: Query,
+    pub source: Option<String>,
+    pub source_kind: SourceKind,
+    pub source_args: Vec<String>,
+    /// Filters applied while fetching, before rows are built: path globs and `!` exclusions of the
+    /// files to read, and the language of MD_CODE_BLOCKS
+    pub pushed_down_predicates: Vec<String>,
+    /// Predicates evaluated for every fetched row (top level AND conditions of WHERE)
+    pub row_predicates: Vec<String>,
+    /// Fields rows are grouped by, aggregates are computed per group
+    pub group_by: Vec<String>,
+    pub order_by: Vec<String>,
+    pub limit: Option<usize>,
+    /// Fields LIMIT and OFFSET count rows per distinct value of
+    pub limit_per: Vec<String>,
+    pub offset: Option<usize>,
+    pub select_fields: Vec<String>,
+    /// Number of files the source will read, when it is a directory based source
+    pub estimated_files: Option<usize>,
+    pub uses_cache: bool,
+}
+
+/// Query after a leading `EXPLAIN` keyword, `None` when `statement` does not start with it.
+pub fn strip_explain(statement: &str) -> Option<&str> {
+    let tokens = tokenize(statement).ok()?;
+    let mut tokens = tokens.iter();
+    if !tokens.next()?.is_keyword("EXPLAIN") {
+        return None;
+    }
+    // Spans count chars, not bytes
+    let start = tokens.next().map_or(statement.len(), |token| {
+        statement
+            .char_indices()
+            .nth(token.span.start)
+            .map_or(statement.len(), |(index, _)| index)
+    });
+    Some(&statement[start..])
+}
+
+/// Plans `query` against the built-in sources only. A leading `EXPLAIN` is allowed.
+pub fn plan(query: &str) -> Result<QueryPlan, Box<dyn Error>> {
+    let query = strip_explain(query).unwrap_or(query);
+    plan_query(
+        &query.parse::<Query>()?,
+        &|_| false,
+        &MarkdownCache::default(),
+    )
+}
+
+/// Plans an already parsed `query`. `is_registered` tells whether a FROM source name is provided
+/// by the caller (e.g. registered on the engine) instead of the built-ins.
+pub fn plan_query(
+    query: &Query,
+    is_registered: &dyn Fn(&str) -> bool,
+    cache: &MarkdownCache,
+) -> Result<QueryPlan, Box<dyn Error>> {
+    let mut plan = QueryPlan {
+        query: query.clone(),
+        source: None,
+        source_kind: SourceKind::Unknown,
+        source_args: Vec::new(),
+        pushed_down_predicates: Vec::new(),
+        row_predicates: split_conjunction(&query.where_expression)
+            .into_iter()
+            .map(expression_to_string)
+            .collect(),
+        group_by: query
+            .group_by_fields
+            .iter()
+            .map(|group_by| group_by.to_string())
+            .collect(),
+        order_by: query
+            .order_by_fields
+            .iter()
+            .map(|ob| format!("{} {:?}", ob.field_name, ob.order_direction))
+            .collect(),
+        limit: query.limit,
+        limit_per: query.limit_per.clone(),
+        offset: query.offset,
+        select_fields: query.select_fields.clone(),
+        estimated_files: None,
+        uses_cache: false,
+    };
+
+    let Some(from_function) = &query.from_function else {
+        return Ok(plan);
+    };
+    let name = from_function.name.to_uppercase();
+    plan.source_args = from_function.args.iter().map(|a| a.to_string()).collect();
+
+    if is_registered(&name) {
+        plan.source_kind = SourceKind::Registered;
+    } else if BUILTIN_SOURCES.contains(&name.as_str()) {
+        plan.source_kind = SourceKind::Builtin;
+        let path_args = source_path_arguments(&name, &from_function.args);
+        let dir_paths = markdown_path_arguments(path_args)?;
+        plan.pushed_down_predicates = dir_paths
+            .iter()
+            .filter_map(|path| path_filter(path))
+            .collect();
+        if let Some(lang) = from_function.args.get(path_args.len()) {
+            plan.pushed_down_predicates
+                .push(format!("lang == {}", lang));
+        }
+        plan.estimated_files = Some(count_markdown_files(&dir_paths)?);
+        plan.uses_cache = cache.exists();
+    } else if FILE_SOURCES.contains(&name.as_str()) {
+        plan.source_kind = SourceKind::File;
+    }
+    plan.source = Some(name);
+
+    Ok(plan)
+}
+
+// Files a path argument keeps, when it is not a plain folder or file
+fn path_filter(path: &str) -> Option<String> {
+    if let Some(pattern) = path.strip_prefix('!') {
+        return Some(format!("file.path NOT matching {}", pattern));
+    }
+    path.contains(['*', '?'])
+        .then(|| format!("file.path matching {}", path))
+}
+
+/// Splits `expression` on its top level ANDs. If there is a top level OR, the whole expression is
+/// one condition.
+fn split_conjunction(expression: &[ExpressionElement]) -> Vec<&[ExpressionElement]> {
+    if expression.is_empty() {
+        return Vec::new();
+    }
+
+    let mut depth = 0;
+    let mut split_points = Vec::new();
+    for (i, element) in expression.iter().enumerate() {
+        match element {
+            ExpressionElement::OpenedBracket => depth += 1,
+            ExpressionElement::ClosedBracket => depth -= 1,
+            ExpressionElement::Operator(Operator::Or) if depth == 0 => return vec![expression],
+            ExpressionElement::Operator(Operator::And) if depth == 0 => split_points.push(i),
+            _ => {}
+        }
+    }
+
+    let mut conditions = Vec::new();
+    let mut start = 0;
+    for split_point in split_points {
+        conditions.push(&expression[start..split_point]);
+        start = split_point + 1;
+    }
+    conditions.push(&expression[start..]);
+
+    conditions
+}
+
+impl Display for QueryPlan {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "QUERY:")?;
+        for line in self.query.to_pretty_string().lines() {
+            writeln!(f, "  {}", line)?;
+        }
+        match &self.source {
+            Some(source) => writeln!(
+                f,
+                "FROM: {}({}) [{:?}]",
+                source,
+                self.source_args.join(", "),
+                self.source_kind
+            )?,
+            None => writeln!(f, "FROM: -")?,
+        }
+        match self.estimated_files {
+            Some(files) => writeln!(f, "  estimated files: {}", files)?,
+            None => writeln!(f, "  estimated files: unknown")?,
+        }
+        writeln!(f, "  cache: {}", if self.uses_cache { "yes" } else { "no" })?;
+        writeln!(f, "PUSHED DOWN:")?;
+        if self.pushed_down_predicates.is_empty() {
+            writeln!(f, "  none, every fetched row is filtered")?;
+        }
+        for predicate in &self.pushed_down_predicates {
+            writeln!(f, "  {}", predicate)?;
+        }
+        writeln!(f, "WHERE (per row):")?;
+        for predicate in &self.row_predicates {
+            writeln!(f, "  {}", predicate)?;
+        }
+        if !self.group_by.is_empty() {
+            writeln!(f, "GROUP BY: {}", self.group_by.join(", "))?;
+        }
+        if !self.order_by.is_empty() {
+            writeln!(f, "ORDER BY: {}", self.order_by.join(", "))?;
+        }
+        if let Some(limit) = self.limit {
+            match self.limit_per.is_empty() {
+                true => writeln!(f, "LIMIT: {}", limit)?,
+                false => writeln!(f, "LIMIT: {} PER {}", limit, self.limit_per.join(", "))?,
+            }
+        }
+        if let Some(offset) = self.offset {
+            writeln!(f, "OFFSET: {}", offset)?;
+        }
+        write!(f, "SELECT: {}", self.select_fields.join(", "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plan_registered_source() {
+        let query: Query = "SELECT a, b FROM MY_API('x') WHERE a == 1 AND (b OR c) ORDER BY a desc"
+            .parse()
+            .unwrap();
+
+        let plan = plan_query(&query, &|name| name == "MY_API", &MarkdownCache::default()).unwrap();
+
+        assert_eq!(Some("MY_API".to_string()), plan.source);
+        assert_eq!(SourceKind::Registered, plan.source_kind);
+        assert_eq!(vec!["'x'".to_string()], plan.source_args);
+        assert_eq!(
+            vec!["a == 1".to_string(), "(b OR c)".to_string()],
+            plan.row_predicates
+        );
+        assert!(plan.pushed_down_predicates.is_empty());
+        assert_eq!(vec!["a DESC".to_string()], plan.order_by);
+        assert_eq!(None, plan.estimated_files);
+    }
+
+    #[test]
+    fn test_plan_unknown_source() {
+        let plan = plan("SELECT a FROM NOPE('x') WHERE a == 1 OR b == 2").unwrap();
+
+        assert_eq!(SourceKind::Unknown, plan.source_kind);
+        assert_eq!(vec!["a == 1 OR b == 2".to_string()], plan.row_predicates);
+    }
+
+    #[test]
+    fn test_plan_explain() {
+        assert_eq!(
+            Some("SELECT a FROM X('y')"),
+            strip_explain("explain  SELECT a FROM X('y')")
+        );
+        assert_eq!(
+            Some("SELECT a"),
+            strip_explain("-- why no rows?\nEXPLAIN SELECT a")
+        );
+        assert_eq!(
+            Some("SELECT a"),
+            strip_explain("# Größe ÄÄÄÄÄÄÄÄÄÄ\nEXPLAIN SELECT a")
+        );
+        assert_eq!(None, strip_explain("SELECT explain FROM X('y')"));
+
+        let plan = plan("EXPLAIN SELECT a FROM NOPE('x') WHERE a == 1").unwrap();
+        assert_eq!(
+            "SELECT a FROM NOPE('x') WHERE a == 1"
+                .parse::<Query>()
+                .unwrap(),
+            plan.query
+        );
+        assert!(plan
+            .to_string()
+            .starts_with("QUERY:\n  SELECT a\n  FROM NOPE('x')\n  WHERE a == 1\nFROM: NOPE('x')"));
+    }
+
+    #[test]
+    fn test_plan_without_order_by() {
+        let plan = plan("SELECT a FROM NOPE('x') WHERE a == 1 LIMIT 2").unwrap();
+
+        assert_eq!(
+            "QUERY:\n  SELECT a\n  FROM NOPE('x')\n  WHERE a == 1\n  LIMIT 2\n\
+             FROM: NOPE('x') [Unknown]\n  estimated files: unknown\n  cache: no\n\
+             PUSHED DOWN:\n  none, every fetched row is filtered\n\
+             WHERE (per row):\n  a == 1\n\
+             LIMIT: 2\n\
+             SELECT: a",
+            plan.to_string()
+        );
+    }
+
+    #[test]
+    fn test_plan_reports_fetch_filters() {
+        let plan = plan(
+            "SELECT a FROM MD_CODE_BLOCKS('tests/fixtures/vault/**/*.md', '!projects', 'bash')",
+        )
+        .unwrap();
+
+        assert_eq!(
+            vec![
+                "file.path matching tests/fixtures/vault/**/*.md".to_string(),
+                "file.path NOT matching projects".to_string(),
+                "lang == 'bash'".to_string(),
+            ],
+            plan.pushed_down_predicates
+        );
+        assert_eq!(Some(1), plan.estimated_files);
+        assert!(plan
+            .to_string()
+            .contains("PUSHED DOWN:\n  file.path matching"));
+    }
+
+    #[test]
+    fn test_plan_builtin_source_counts_files() {
+        let dir = std::env::temp_dir().join(format!("krafna-planner-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("a.md"), "# a").unwrap();
+        std::fs::write(dir.join("b.md"), "# b").unwrap();
+        std::fs::write(dir.join("c.txt"), "c").unwrap();
+
+        let plan = plan(&format!(
+            "SELECT a FROM FRONTMATTER_DATA('{}')",
+            dir.display()
+        ))
+        .unwrap();
+
+        assert_eq!(SourceKind::Builtin, plan.source_kind);
+        assert_eq!(Some(2), plan.estimated_files);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,146 @@
+use std::collections::HashMap;
+use std::error::Error;
+
+use crate::libs::data_fetcher::pod::Pod;
+use crate::libs::data_fetcher::{fetch_data_with_cancellation, DataFetcher};
+use crate::libs::executor::{
+    execute_on_data, execute_parsed_query, prepare_query, ExecutionContext,
+};
+use crate::libs::parser::{FieldValue, Query};
+
+/// A query that is parsed once and can be executed many times, e.g. on every tick of a watch
+/// loop. `:name` placeholders in WHERE and in function arguments (FROM included) are bound to
+/// values on every execution, and data is fetched again each time.
+#[derive(Debug, Clone)]
+pub struct PreparedQuery {
+    query: Query,
+    parameters: Vec<String>,
+}
+
+impl PreparedQuery {
+    pub fn new(
+        query: &str,
+        select: Option<String>,
+        from: Option<String>,
+        include_fields: Option<String>,
+    ) -> Result<Self, Box<dyn Error>> {
+        Ok(PreparedQuery::from_query(prepare_query(
+            query,
+            select,
+            from,
+            include_fields,
+        )?))
+    }
+
+    pub(crate) fn from_query(query: Query) -> Self {
+        let parameters = query.parameters();
+        PreparedQuery { query, parameters }
+    }
+
+    pub fn query(&self) -> &Query {
+        &self.query
+    }
+
+    /// Names of the `:name` parameters that need to be bound on execution.
+    pub fn parameters(&self) -> &[String] {
+        &self.parameters
+    }
+
+    pub fn bind(&self, params: &HashMap<String, FieldValue>) -> Result<Query, String> {
+        if self.parameters.is_empty() {
+            return Ok(self.query.clone());
+        }
+        self.query.bind(params)
+    }
+
+    /// Executes against the built-in sources.
+    pub fn execute(
+        &self,
+        params: &HashMap<String, FieldValue>,
+    ) -> Result<(Vec<String>, Vec<Pod>), Box<dyn Error>> {
+        let context = ExecutionContext::shared();
+        self.execute_with_fetcher(
+            params,
+            &|from_function| fetch_data_with_cancellation(from_function, &context.cancellation),
+            &context,
+        )
+    }
+
+    pub fn execute_with_fetcher(
+        &self,
+        params: &HashMap<String, FieldValue>,
+        fetcher: &DataFetcher,
+        context: &ExecutionContext,
+    ) -> Result<(Vec<String>, Vec<Pod>), Box<dyn Error>> {
+        execute_parsed_query(self.bind(params)?, fetcher, context)
+    }
+
+    /// Executes over already fetched `data`, ignoring FROM.
+    pub fn execute_on_data(
+        &self,
+        params: &HashMap<String, FieldValue>,
+        data: Vec<Pod>,
+    ) -> Result<(Vec<String>, Vec<Pod>), Box<dyn Error>> {
+        execute_on_data(self.bind(params)?, data, &ExecutionContext::shared())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pods() -> Vec<Pod> {
+        (1..=5)
+            .map(|n| {
+                let mut pod = Pod::new_hash();
+                let _ = pod.insert("n".to_string(), Pod::Integer(n));
+                pod
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_prepared_query_parameters() {
+        let prepared = PreparedQuery::new(
+            "SELECT n FROM FRONTMATTER_DATA(:dir) WHERE n > :min AND n < :max OR n == :min",
+            None,
+            None,
+            None,
+        )
+        .unwrap();
+
+        assert_eq!(
+            vec!["dir".to_string(), "min".to_string(), "max".to_string()],
+            prepared.parameters()
+        );
+    }
+
+    #[test]
+    fn test_prepared_query_executes_with_different_bindings() {
+        let prepared =
+            PreparedQuery::new("SELECT n FROM NUMBERS() WHERE n > :min", None, None, None).unwrap();
+
+        let mut params = HashMap::new();
+        params.insert("min".to_string(), FieldValue::Integer(3));
+        let (_, data) = prepared.execute_on_data(&params, pods()).unwrap();
+        assert_eq!(2, data.len());
+
+        params.insert("min".to_string(), FieldValue::Integer(1));
+        let (_, data) = prepared.execute_on_data(&params, pods()).unwrap();
+        assert_eq!(4, data.len());
+    }
+
+    #[test]
+    fn test_prepared_query_unbound_parameter() {
+        let prepared =
+            PreparedQuery::new("SELECT n FROM NUMBERS() WHERE n > :min", None, None, None).unwrap();
+
+        assert_eq!(
+            "Parameter :min is not bound!",
+            prepared
+                .execute_on_data(&HashMap::new(), pods())
+                .unwrap_err()
+                .to_string()
+        );
+    }
+}
@@ -0,0 +1,223 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// What a running query reports through [`Progress`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum ProgressEvent {
+    /// Markdown files found while walking the FROM directory
+    FilesDiscovered(usize),
+    /// Files that were not in the cache (or were modified) and are being parsed
+    FileParsed { parsed: usize, total: usize },
+    /// Rows evaluated by WHERE so far
+    RowsFiltered { filtered: usize, total: usize },
+    /// A stage of the query is done, with the rows (or files) it ended up with
+    StageFinished {
+        stage: Stage,
+        elapsed: Duration,
+        rows: usize,
+    },
+    /// How long parsing one markdown file took
+    FileParseTime { path: String, elapsed: Duration },
+}
+
+/// Stages of a query, in the order they run. Walk, cache load and parse only run for the
+/// markdown sources, GROUP BY only for grouped queries.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    /// Finding the markdown files of FROM, the rows are the files found
+    Walk,
+    /// Loading the cache and checking which files changed, the rows are the files used from it
+    CacheLoad,
+    /// Parsing the files that are not cached, the rows are the files parsed
+    Parse,
+    Where,
+    GroupBy,
+    OrderBy,
+    Select,
+}
+
+impl std::fmt::Display for Stage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Stage::Walk => "walk",
+            Stage::CacheLoad => "cache load",
+            Stage::Parse => "parse",
+            Stage::Where => "WHERE",
+            Stage::GroupBy => "GROUP BY",
+            Stage::OrderBy => "ORDER BY",
+            Stage::Select => "SELECT",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+pub type ProgressCallback = dyn Fn(&ProgressEvent) + Send + Sync;
+
+/// Optional hook that gets [`ProgressEvent`]s, e.g. to show a progress bar while a big vault is
+/// parsed for the first time. The default one does nothing. Can be called from many threads.
+#[derive(Clone, Default)]
+pub struct Progress {
+    callback: Option<Arc<ProgressCallback>>,
+}
+
+/// WHERE reports only every this many rows, so the callback does not slow down filtering.
+pub const ROWS_REPORT_INTERVAL: usize = 1000;
+
+impl Progress {
+    pub fn new<F>(callback: F) -> Self
+    where
+        F: Fn(&ProgressEvent) + Send + Sync + 'static,
+    {
+        Progress {
+            callback: Some(Arc::new(callback)),
+        }
+    }
+
+    pub fn report(&self, event: ProgressEvent) {
+        if let Some(callback) = &self.callback {
+            callback(&event);
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.callback.is_some()
+    }
+
+    /// Reports [`ProgressEvent::StageFinished`] for a `stage` that started at `started`.
+    pub fn stage_finished(&self, stage: Stage, started: Instant, rows: usize) {
+        if self.is_enabled() {
+            self.report(ProgressEvent::StageFinished {
+                stage,
+                elapsed: started.elapsed(),
+                rows,
+            });
+        }
+    }
+}
+
+/// Slowest files [`Profile`] keeps
+pub const SLOWEST_FILES: usize = 10;
+
+/// Time and rows of every stage of a query, and the slowest files to parse, collected from
+/// [`ProgressEvent`]s, e.g. `engine.on_progress(move |event| profile.lock().unwrap().record(event))`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Profile {
+    pub stages: Vec<(Stage, Duration, usize)>,
+    /// At most [`SLOWEST_FILES`], slowest first
+    pub slowest_files: Vec<(String, Duration)>,
+}
+
+impl Profile {
+    pub fn record(&mut self, event: &ProgressEvent) {
+        match event {
+            ProgressEvent::StageFinished {
+                stage,
+                elapsed,
+                rows,
+            } => self.stages.push((*stage, *elapsed, *rows)),
+            ProgressEvent::FileParseTime { path, elapsed } => {
+                let index = self
+                    .slowest_files
+                    .partition_point(|(_, slower)| slower >= elapsed);
+                if index < SLOWEST_FILES {
+                    self.slowest_files.insert(index, (path.clone(), *elapsed));
+                    self.slowest_files.truncate(SLOWEST_FILES);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+impl std::fmt::Display for Profile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let millis = |duration: &Duration| format!("{:.3}ms", duration.as_secs_f64() * 1000.0);
+        writeln!(f, "stage\ttime\trows")?;
+        for (stage, elapsed, rows) in &self.stages {
+            writeln!(f, "{}\t{}\t{}", stage, millis(elapsed), rows)?;
+        }
+        let total: Duration = self.stages.iter().map(|(_, elapsed, _)| *elapsed).sum();
+        write!(f, "total\t{}\t", millis(&total))?;
+        if !self.slowest_files.is_empty() {
+            write!(f, "\n\nslowest files to parse\ttime")?;
+            for (path, elapsed) in &self.slowest_files {
+                write!(f, "\n{}\t{}", path, millis(elapsed))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl std::fmt::Debug for Progress {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Progress")
+            .field("enabled", &self.is_enabled())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    #[test]
+    fn test_default_does_nothing() {
+        let progress = Progress::default();
+
+        assert!(!progress.is_enabled());
+        progress.report(ProgressEvent::FilesDiscovered(1));
+    }
+
+    #[test]
+    fn test_report_calls_callback() {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let events_clone = Arc::clone(&events);
+        let progress = Progress::new(move |event| events_clone.lock().unwrap().push(event.clone()));
+
+        progress.report(ProgressEvent::FilesDiscovered(3));
+        progress.clone().report(ProgressEvent::FileParsed {
+            parsed: 1,
+            total: 3,
+        });
+
+        assert_eq!(
+            vec![
+                ProgressEvent::FilesDiscovered(3),
+                ProgressEvent::FileParsed {
+                    parsed: 1,
+                    total: 3
+                }
+            ],
+            *events.lock().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_profile_records_stages_and_slowest_files() {
+        let mut profile = Profile::default();
+        for millis in 0..SLOWEST_FILES as u64 + 2 {
+            profile.record(&ProgressEvent::FileParseTime {
+                path: format!("{}.md", millis),
+                elapsed: Duration::from_millis(millis),
+            });
+        }
+        profile.record(&ProgressEvent::StageFinished {
+            stage: Stage::Where,
+            elapsed: Duration::from_millis(2),
+            rows: 5,
+        });
+        profile.record(&ProgressEvent::FilesDiscovered(3));
+
+        assert_eq!(
+            vec![(Stage::Where, Duration::from_millis(2), 5)],
+            profile.stages
+        );
+        assert_eq!(SLOWEST_FILES, profile.slowest_files.len());
+        assert_eq!("11.md", profile.slowest_files[0].0);
+        assert_eq!("2.md", profile.slowest_files[SLOWEST_FILES - 1].0);
+        assert!(profile
+            .to_string()
+            .starts_with("stage\ttime\trows\nWHERE\t2.000ms\t5\ntotal\t2.000ms\t\n\n"));
+    }
+}
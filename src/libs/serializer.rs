@@ -1,31 +1,47 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::str::FromStr;
+
+use chrono::{DateTime, NaiveDate, NaiveDateTime};
+use indexmap::IndexMap;
+
 use crate::libs::data_fetcher::pod::Pod;
+use crate::libs::diff::ResultDiff;
+use crate::libs::parser::Query;
 
 pub fn pods_to_json(field_names: Vec<String>, pods: Vec<Pod>) -> String {
     let json_values: Vec<String> = pods
         .into_iter()
-        .filter_map(|pod| {
-            let mut hash = Pod::new_hash();
-            for field_name in &field_names {
-                if let Some(nested_pod) = pod.nested_get(field_name) {
-                    let _ = hash.insert(field_name.clone(), nested_pod.clone());
-                }
-            }
-            hash.to_untagged_json_string().ok()
-        })
+        .filter_map(|pod| json_row(&field_names, &pod).to_untagged_json_string().ok())
         .collect();
 
     format!("[{}]", json_values.join(","))
 }
 
+// Row with just the `field_names`, keyed by the (dotted) name
+pub(crate) fn json_row(field_names: &[String], pod: &Pod) -> Pod {
+    let mut hash = Pod::new_hash();
+    for field_name in field_names {
+        if let Some(nested_pod) = pod.nested_get(field_name) {
+            let _ = hash.insert(field_name.clone(), nested_pod.clone());
+        }
+    }
+    hash
+}
+
 pub fn pods_to_tsv(field_names: Vec<String>, pods: Vec<Pod>) -> String {
     if pods.is_empty() {
         return String::new();
     }
 
-    // Build header row
+    // Build header row, `file.name` is `file_name`. Expressions keep their text, dots in their
+    // strings (`DATE(due, '%d.%m.%Y')`) are not paths.
     let header = field_names
         .iter()
-        .map(|s| s.replace('.', "_"))
+        .map(|s| match Query::select_expression(s) {
+            Some(_) => s.clone(),
+            None => s.replace('.', "_"),
+        })
         .collect::<Vec<String>>()
         .join("\t");
 
@@ -48,3 +64,980 @@ pub fn pods_to_tsv(field_names: Vec<String>, pods: Vec<Pod>) -> String {
     // Combine header and rows
     format!("{}\n{}", header, rows.join("\n"))
 }
+
+/// Results as NUON (Nushell object notation), a list of records with every field, e.g.
+/// `[{"file.name": "a.md", due: 2024-05-01, words: 120}]`. Numbers, booleans, lists and hashes
+/// keep their types and text that is a date (`2024-05-01`, `2024-05-01T10:00:00` or RFC 3339,
+/// like `file.created`) becomes a date, so `krafna ... --nuon | from nuon | where due < (date
+/// now)` works in Nushell. NULLs and missing fields are `null`.
+pub fn pods_to_nuon(field_names: Vec<String>, pods: Vec<Pod>) -> String {
+    let records: Vec<String> = pods
+        .iter()
+        .map(|pod| {
+            let fields: Vec<String> = field_names
+                .iter()
+                .map(|field_name| {
+                    let value = pod.nested_get(field_name).unwrap_or(&Pod::Null);
+                    format!("{}: {}", nuon_key(field_name), nuon_value(value))
+                })
+                .collect();
+            format!("{{{}}}", fields.join(", "))
+        })
+        .collect();
+
+    format!("[{}]", records.join(", "))
+}
+
+fn nuon_value(pod: &Pod) -> String {
+    match pod {
+        Pod::Null => "null".to_string(),
+        Pod::String(value) if is_date(value) => value.clone(),
+        Pod::String(value) => quoted(value),
+        Pod::Integer(value) => value.to_string(),
+        Pod::Float(value) if value.is_nan() => "NaN".to_string(),
+        Pod::Float(value) if value.is_infinite() => {
+            if *value > 0.0 { "inf" } else { "-inf" }.to_string()
+        }
+        // NUON floats need a decimal point, `1` would be an int
+        Pod::Float(value) if value.fract() == 0.0 && value.abs() < 1e15 => format!("{:.1}", value),
+        Pod::Float(value) => value.to_string(),
+        Pod::Boolean(value) => value.to_string(),
+        Pod::Array(array) => format!(
+            "[{}]",
+            array.iter().map(nuon_value).collect::<Vec<_>>().join(", ")
+        ),
+        Pod::Hash(hash) => format!(
+            "{{{}}}",
+            hash.iter()
+                .map(|(key, value)| format!("{}: {}", nuon_key(key), nuon_value(value)))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+    }
+}
+
+// Keys are bare words unless they have other characters than letters, digits, `_` and `-`
+fn nuon_key(key: &str) -> String {
+    let bare = !key.is_empty()
+        && !key.starts_with(|c: char| c.is_ascii_digit() || c == '-')
+        && key
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '_' || c == '-')
+        && !["true", "false", "null"].contains(&key);
+    match bare {
+        true => key.to_string(),
+        false => quoted(key),
+    }
+}
+
+fn quoted(text: &str) -> String {
+    serde_json::Value::String(text.to_string()).to_string()
+}
+
+// Only text that is exactly a date in one of the formats Nushell reads as a date literal
+fn is_date(text: &str) -> bool {
+    NaiveDate::parse_from_str(text, "%Y-%m-%d")
+        .is_ok_and(|date| date.format("%Y-%m-%d").to_string() == text)
+        || NaiveDateTime::parse_from_str(text, "%Y-%m-%dT%H:%M:%S")
+            .is_ok_and(|date| date.format("%Y-%m-%dT%H:%M:%S").to_string() == text)
+        || DateTime::parse_from_rfc3339(text).is_ok_and(|date| date.to_rfc3339() == text)
+}
+
+/// [`ResultDiff`] as TSV, with a `change` column first: `+` added, `-` removed and `~` changed
+/// (with the current values).
+pub fn diff_to_tsv(field_names: Vec<String>, diff: ResultDiff) -> String {
+    let mut rows = Vec::new();
+    let changes = [
+        ("+", diff.added),
+        ("-", diff.removed),
+        ("~", diff.changed.into_iter().map(|(_, pod)| pod).collect()),
+    ];
+    for (change, pods) in changes {
+        for mut pod in pods {
+            let _ = pod.insert("change".to_string(), Pod::String(change.to_string()));
+            rows.push(pod);
+        }
+    }
+
+    let mut field_names = field_names;
+    field_names.insert(0, "change".to_string());
+    pods_to_tsv(field_names, rows)
+}
+
+/// [`ResultDiff`] as a JSON object with `added`, `removed` and `changed` (`before`/`after`) rows.
+pub fn diff_to_json(field_names: Vec<String>, diff: ResultDiff) -> String {
+    let rows = |pods: Vec<Pod>| -> Vec<serde_json::Value> {
+        pods.iter()
+            .map(|pod| json_row(&field_names, pod).to_json_value())
+            .collect()
+    };
+    let changed: Vec<serde_json::Value> = diff
+        .changed
+        .iter()
+        .map(|(before, after)| {
+            serde_json::json!({
+                "before": json_row(&field_names, before).to_json_value(),
+                "after": json_row(&field_names, after).to_json_value(),
+            })
+        })
+        .collect();
+
+    serde_json::json!({
+        "added": rows(diff.added),
+        "removed": rows(diff.removed),
+        "changed": changed,
+    })
+    .to_string()
+}
+
+/// Dataview-style ways to show results, picked with `--view` or by starting the query with
+/// `TABLE`, `LIST` or `TASK`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum View {
+    /// A markdown table, see [`pods_to_markdown_table`]
+    Table,
+    /// Bullets, see [`pods_to_list`]
+    List,
+    /// Checkbox lines of the tasks under their file, see [`pods_to_tree`]
+    Task,
+}
+
+impl View {
+    /// The view a query starts with and the query after it, e.g. `LIST SELECT title FROM ...`
+    /// is `(Some(View::List), "SELECT title FROM ...")`. Like in dataview, TABLE and LIST can be
+    /// followed by their fields (or just FROM) instead of a SELECT, and then show `file.name`
+    /// first unless `WITHOUT ID` comes after them: `TABLE title FROM ...` is
+    /// `SELECT file.name, title FROM ...`.
+    pub fn split_keyword(query: &str) -> (Option<View>, String) {
+        let query = query.trim_start();
+        let end = query.find(char::is_whitespace).unwrap_or(query.len());
+        let view = match query[..end].parse() {
+            Ok(view) => view,
+            Err(_) => return (None, query.to_string()),
+        };
+        let rest = query[end..].trim_start();
+        if view == View::Task
+            || ["SELECT", "WITH", "DEFINE", "EXPLAIN"]
+                .iter()
+                .any(|keyword| strip_word(rest, keyword).is_some())
+        {
+            return (Some(view), rest.to_string());
+        }
+
+        let (id, rest) = match strip_word(rest, "WITHOUT").and_then(|rest| strip_word(rest, "ID")) {
+            Some(rest) => (None, rest),
+            None => (Some("file.name"), rest),
+        };
+        let query = match (id, strip_word(rest, "FROM")) {
+            (Some(id), Some(_)) => format!("SELECT {} {}", id, rest),
+            (Some(id), None) => format!("SELECT {}, {}", id, rest),
+            (None, _) => format!("SELECT {}", rest),
+        };
+        (Some(view), query)
+    }
+}
+
+// `text` after a leading (case insensitive) `word`, `None` when it does not start with it
+fn strip_word<'a>(text: &'a str, word: &str) -> Option<&'a str> {
+    let end = text
+        .find(|c: char| !c.is_alphanumeric() && c != '_')
+        .unwrap_or(text.len());
+    text[..end]
+        .eq_ignore_ascii_case(word)
+        .then(|| text[end..].trim_start())
+}
+
+impl FromStr for View {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "table" => Ok(View::Table),
+            "list" => Ok(View::List),
+            "task" => Ok(View::Task),
+            _ => Err(format!(
+                "Unknown view '{}', expected table, list or task",
+                s
+            )),
+        }
+    }
+}
+
+/// Results as a markdown table, with the field names as the header. NULLs and missing fields
+/// are empty cells.
+pub fn pods_to_markdown_table(field_names: Vec<String>, pods: Vec<Pod>) -> String {
+    if pods.is_empty() {
+        return String::new();
+    }
+
+    let row = |cells: Vec<String>| format!("| {} |", cells.join(" | "));
+    let mut lines = vec![
+        row(field_names.clone()),
+        row(vec!["---".to_string(); field_names.len()]),
+    ];
+    for pod in pods {
+        lines.push(row(field_names
+            .iter()
+            .map(|field_name| markdown_value(&pod, field_name).replace('|', "\\|"))
+            .collect()));
+    }
+    lines.join("\n")
+}
+
+/// Results as markdown bullets, `- first` or `- first: second, third` for more fields.
+pub fn pods_to_list(field_names: Vec<String>, pods: Vec<Pod>) -> String {
+    pods.into_iter()
+        .map(|pod| {
+            let mut values = field_names
+                .iter()
+                .map(|field_name| markdown_value(&pod, field_name));
+            let first = values.next().unwrap_or_default();
+            let rest: Vec<String> = values.collect();
+            match rest.is_empty() {
+                true => format!("- {}", first),
+                false => format!("- {}: {}", first, rest.join(", ")),
+            }
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+// Value as inline markdown text, empty for NULLs and missing fields
+fn markdown_value(pod: &Pod, field_name: &str) -> String {
+    match pod.nested_get(field_name) {
+        None | Some(Pod::Null) => String::new(),
+        Some(value) => value.to_string().replace('\n', " "),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ChartKind {
+    /// A bar next to every row, as long as its value relative to the biggest one
+    Bar,
+    /// One sparkline of the values of all rows, under them
+    Spark,
+}
+
+impl FromStr for ChartKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "bar" => Ok(ChartKind::Bar),
+            "spark" => Ok(ChartKind::Spark),
+            _ => Err(format!("Unknown chart '{}', expected bar or spark", s)),
+        }
+    }
+}
+
+/// Characters of a bar at the full width of 30 characters
+const BAR_WIDTH: usize = 30;
+// Eighths of a bar character, from empty to full
+const BAR_EIGHTHS: [char; 9] = [' ', '▏', '▎', '▍', '▌', '▋', '▊', '▉', '█'];
+const SPARK_LEVELS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// Results as TSV with a unicode chart of their numeric column, which has to be the only column
+/// with numbers (NULLs are left out of the chart).
+pub fn pods_to_chart(
+    field_names: Vec<String>,
+    pods: Vec<Pod>,
+    chart: ChartKind,
+) -> Result<String, String> {
+    if pods.is_empty() {
+        return Ok(String::new());
+    }
+    let numeric: Vec<&String> = field_names
+        .iter()
+        .filter(|field_name| {
+            let values = pods.iter().filter_map(|pod| pod.nested_get(field_name));
+            let mut values = values.filter(|value| **value != Pod::Null).peekable();
+            values.peek().is_some() && values.all(|value| number(value).is_some())
+        })
+        .collect();
+    let [field_name] = numeric.as_slice() else {
+        let numeric: Vec<&str> = numeric.iter().map(|name| name.as_str()).collect();
+        return Err(format!(
+            "Charts need results with one numeric column, found {}{}",
+            numeric.len(),
+            match numeric.is_empty() {
+                true => String::new(),
+                false => format!(": {}", numeric.join(", ")),
+            }
+        ));
+    };
+    let values: Vec<Option<f64>> = pods
+        .iter()
+        .map(|pod| pod.nested_get(field_name).and_then(number))
+        .collect();
+
+    let tsv = pods_to_tsv(field_names, pods);
+    Ok(match chart {
+        ChartKind::Bar => {
+            let max = values
+                .iter()
+                .flatten()
+                .fold(0.0, |max: f64, value| max.max(*value));
+            let mut lines = tsv.lines();
+            let header = lines.next().map(|header| format!("{}\tchart", header));
+            let rows = lines.zip(&values).map(|(line, value)| {
+                let bar = match value {
+                    Some(value) if max > 0.0 => bar(value / max),
+                    _ => String::new(),
+                };
+                format!("{}\t{}", line, bar)
+            });
+            header
+                .into_iter()
+                .chain(rows)
+                .collect::<Vec<String>>()
+                .join("\n")
+        }
+        ChartKind::Spark => format!("{}\n{}", tsv, sparkline(&values)),
+    })
+}
+
+fn number(pod: &Pod) -> Option<f64> {
+    match pod {
+        Pod::Integer(value) => Some(*value as f64),
+        Pod::Float(value) => Some(*value),
+        _ => None,
+    }
+}
+
+// Bar for a value between 0 and 1 of the full width, in eighths of a character
+fn bar(fraction: f64) -> String {
+    let eighths = (fraction.clamp(0.0, 1.0) * (BAR_WIDTH * 8) as f64).round() as usize;
+    let mut bar = BAR_EIGHTHS[8].to_string().repeat(eighths / 8);
+    let rest = eighths % 8;
+    if rest > 0 {
+        bar.push(BAR_EIGHTHS[rest]);
+    }
+    bar
+}
+
+/// One character per value from ▁ (smallest) to █ (biggest), a space for missing values.
+pub fn sparkline(values: &[Option<f64>]) -> String {
+    let present = values.iter().flatten();
+    let min = present
+        .clone()
+        .fold(f64::INFINITY, |min, value| min.min(*value));
+    let max = present.fold(f64::NEG_INFINITY, |max, value| max.max(*value));
+    values
+        .iter()
+        .map(|value| match value {
+            Some(_) if max == min => SPARK_LEVELS[SPARK_LEVELS.len() / 2],
+            Some(value) => {
+                let level = (value - min) / (max - min) * (SPARK_LEVELS.len() - 1) as f64;
+                SPARK_LEVELS[level.round() as usize]
+            }
+            None => ' ',
+        })
+        .collect()
+}
+
+/// Results as a Parquet file written to `writer`, one column per field (named like in JSON, e.g.
+/// `file.name`). Columns of only integers, numbers or booleans get that type, any other column
+/// is text: lists and hashes as JSON, dates in `DATE_FORMAT`. NULLs and missing fields are nulls.
+#[cfg(feature = "parquet")]
+pub fn pods_to_parquet<W: std::io::Write + Send>(
+    field_names: Vec<String>,
+    pods: Vec<Pod>,
+    writer: W,
+) -> Result<(), String> {
+    use std::sync::Arc;
+
+    use arrow_array::{Array, RecordBatch};
+    use arrow_schema::{Field, Schema};
+    use parquet::arrow::ArrowWriter;
+
+    let mut fields = Vec::new();
+    let mut columns = Vec::new();
+    for field_name in &field_names {
+        let values: Vec<Option<&Pod>> = pods
+            .iter()
+            .map(|pod| pod.nested_get(field_name).filter(|v| **v != Pod::Null))
+            .collect();
+        let column = parquet_column(&values);
+        fields.push(Field::new(field_name, column.data_type().clone(), true));
+        columns.push(column);
+    }
+    let schema = Arc::new(Schema::new(fields));
+    let batch = RecordBatch::try_new(schema.clone(), columns).map_err(|e| e.to_string())?;
+
+    let mut writer = ArrowWriter::try_new(writer, schema, None).map_err(|e| e.to_string())?;
+    writer.write(&batch).map_err(|e| e.to_string())?;
+    writer.close().map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+#[cfg(not(feature = "parquet"))]
+pub fn pods_to_parquet<W: std::io::Write + Send>(
+    _field_names: Vec<String>,
+    _pods: Vec<Pod>,
+    _writer: W,
+) -> Result<(), String> {
+    Err("Parquet output needs krafna built with the `parquet` feature".to_string())
+}
+
+// Column with the narrowest type that holds all `values`: integers, then numbers, then text
+#[cfg(feature = "parquet")]
+fn parquet_column(values: &[Option<&Pod>]) -> arrow_array::ArrayRef {
+    use std::sync::Arc;
+
+    use arrow_array::{BooleanArray, Float64Array, Int64Array, StringArray};
+
+    let present = || values.iter().flatten();
+    if present().next().is_none() {
+        return Arc::new(StringArray::new_null(values.len()));
+    }
+    if present().all(|v| matches!(v, Pod::Integer(_))) {
+        return Arc::new(Int64Array::from_iter(
+            values.iter().map(|v| v.and_then(Pod::as_i64)),
+        ));
+    }
+    if present().all(|v| matches!(v, Pod::Integer(_) | Pod::Float(_))) {
+        return Arc::new(Float64Array::from_iter(
+            values.iter().map(|v| v.and_then(number)),
+        ));
+    }
+    if present().all(|v| matches!(v, Pod::Boolean(_))) {
+        return Arc::new(BooleanArray::from_iter(
+            values.iter().map(|v| v.and_then(Pod::as_bool)),
+        ));
+    }
+    Arc::new(StringArray::from_iter(
+        values.iter().map(|v| v.map(Pod::to_string)),
+    ))
+}
+
+/// Fields a row needs to be an edge of the link graph: the linking file and the linked one (the
+/// resolved `path` of MD_LINKS rows).
+pub const GRAPH_FIELDS: [&str; 2] = ["file.path", "path"];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GraphFormat {
+    Dot,
+    GraphMl,
+    Json,
+}
+
+impl FromStr for GraphFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "dot" => Ok(GraphFormat::Dot),
+            "graphml" => Ok(GraphFormat::GraphMl),
+            "json" => Ok(GraphFormat::Json),
+            _ => Err(format!(
+                "Unknown graph format '{}', expected dot, graphml or json",
+                s
+            )),
+        }
+    }
+}
+
+/// Note graph of MD_LINKS rows, from `file.path` to the linked `path`. Rows without a resolved
+/// `path` (external and dangling links) are left out, several links between the same notes are
+/// one edge with a `weight`.
+pub fn pods_to_graph(pods: &[Pod], format: GraphFormat) -> String {
+    let mut nodes = IndexMap::new();
+    let mut edges: IndexMap<(String, String), usize> = IndexMap::new();
+    for pod in pods {
+        let (Some(Pod::String(source)), Some(Pod::String(target))) = (
+            pod.nested_get(GRAPH_FIELDS[0]),
+            pod.nested_get(GRAPH_FIELDS[1]),
+        ) else {
+            continue;
+        };
+        for path in [source, target] {
+            nodes
+                .entry(path.clone())
+                .or_insert_with(|| node_label(path));
+        }
+        *edges.entry((source.clone(), target.clone())).or_default() += 1;
+    }
+
+    match format {
+        GraphFormat::Dot => {
+            let mut dot = String::from("digraph notes {\n");
+            for (path, label) in &nodes {
+                dot.push_str(&format!("  {:?} [label={:?}];\n", path, label));
+            }
+            for ((source, target), weight) in &edges {
+                dot.push_str(&format!(
+                    "  {:?} -> {:?} [weight={}];\n",
+                    source, target, weight
+                ));
+            }
+            dot.push('}');
+            dot
+        }
+        GraphFormat::GraphMl => {
+            let mut graphml = String::from(concat!(
+                "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+                "<graphml xmlns=\"http://graphml.graphdrawing.org/xmlns\">\n",
+                "  <key id=\"label\" for=\"node\" attr.name=\"label\" attr.type=\"string\"/>\n",
+                "  <key id=\"weight\" for=\"edge\" attr.name=\"weight\" attr.type=\"int\"/>\n",
+                "  <graph id=\"notes\" edgedefault=\"directed\">\n",
+            ));
+            for (path, label) in &nodes {
+                graphml.push_str(&format!(
+                    "    <node id=\"{}\"><data key=\"label\">{}</data></node>\n",
+                    xml_escape(path),
+                    xml_escape(label)
+                ));
+            }
+            for ((source, target), weight) in &edges {
+                graphml.push_str(&format!(
+                    "    <edge source=\"{}\" target=\"{}\"><data key=\"weight\">{}</data></edge>\n",
+                    xml_escape(source),
+                    xml_escape(target),
+                    weight
+                ));
+            }
+            graphml.push_str("  </graph>\n</graphml>");
+            graphml
+        }
+        GraphFormat::Json => serde_json::json!({
+            "nodes": nodes
+                .iter()
+                .map(|(path, label)| serde_json::json!({ "id": path, "label": label }))
+                .collect::<Vec<_>>(),
+            "edges": edges
+                .iter()
+                .map(|((source, target), weight)| {
+                    serde_json::json!({ "source": source, "target": target, "weight": weight })
+                })
+                .collect::<Vec<_>>(),
+        })
+        .to_string(),
+    }
+}
+
+/// Fields a task row needs to find its place in the task tree and to be rendered as text.
+pub const TREE_FIELDS: [&str; 5] = ["file.path", "ord", "parent", "text", "checked"];
+
+/// MD_TASKS rows as indented markdown task lists under their file, subtasks under their parent
+/// task. Tasks whose parent is not in `pods` are shown at the top level, rows that are not tasks
+/// (no `ord`, `text` or `checked`) are an error.
+pub fn pods_to_tree(pods: Vec<Pod>) -> Result<String, String> {
+    let (roots, children) = task_tree(&pods)?;
+    let mut lines = Vec::new();
+    let mut current_file = None;
+    for root in roots {
+        let file = pods[root].nested_get("file.path");
+        if current_file != Some(file) {
+            lines.push(file.map(Pod::to_string).unwrap_or_default());
+            current_file = Some(file);
+        }
+        push_tree_lines(&pods, &children, root, 0, &mut lines);
+    }
+    Ok(lines.join("\n"))
+}
+
+fn push_tree_lines(
+    pods: &[Pod],
+    children: &[Vec<usize>],
+    index: usize,
+    depth: usize,
+    lines: &mut Vec<String>,
+) {
+    let checked = matches!(pods[index].nested_get("checked"), Some(Pod::Boolean(true)));
+    lines.push(format!(
+        "{}- [{}] {}",
+        "  ".repeat(depth),
+        if checked { 'x' } else { ' ' },
+        pods[index]
+            .nested_get("text")
+            .map(Pod::to_string)
+            .unwrap_or_default()
+    ));
+    for child in &children[index] {
+        push_tree_lines(pods, children, *child, depth + 1, lines);
+    }
+}
+
+/// Same as [`pods_to_tree`], as JSON: every task has its `field_names` and a `children` array.
+pub fn pods_to_tree_json(field_names: Vec<String>, pods: Vec<Pod>) -> Result<String, String> {
+    let (roots, children) = task_tree(&pods)?;
+    Ok(serde_json::Value::Array(
+        roots
+            .into_iter()
+            .map(|root| tree_json(&field_names, &pods, &children, root))
+            .collect(),
+    )
+    .to_string())
+}
+
+fn tree_json(
+    field_names: &[String],
+    pods: &[Pod],
+    children: &[Vec<usize>],
+    index: usize,
+) -> serde_json::Value {
+    let mut node = serde_json::Map::new();
+    for field_name in field_names {
+        if let Some(nested_pod) = pods[index].nested_get(field_name) {
+            node.insert(field_name.clone(), nested_pod.to_json_value());
+        }
+    }
+    node.insert(
+        "children".to_string(),
+        children[index]
+            .iter()
+            .map(|child| tree_json(field_names, pods, children, *child))
+            .collect(),
+    );
+    serde_json::Value::Object(node)
+}
+
+// Indices of the top-level tasks and the subtasks of every task, in the order of `pods`
+fn task_tree(pods: &[Pod]) -> Result<(Vec<usize>, Vec<Vec<usize>>), String> {
+    for pod in pods {
+        let missing: Vec<&str> = ["ord", "text", "checked"]
+            .into_iter()
+            .filter(|field| pod.nested_get(field).is_none())
+            .collect();
+        if !missing.is_empty() {
+            return Err(format!(
+                "Task trees need MD_TASKS results, found rows without {}",
+                missing.join(", ")
+            ));
+        }
+    }
+    let key = |pod: &Pod, field: &str| match (pod.nested_get("file.path"), pod.nested_get(field)) {
+        (Some(Pod::String(path)), Some(Pod::String(ord))) => Some((path.clone(), ord.clone())),
+        _ => None,
+    };
+    let indices: HashMap<(String, String), usize> = pods
+        .iter()
+        .enumerate()
+        .filter_map(|(index, pod)| Some((key(pod, "ord")?, index)))
+        .collect();
+
+    let mut roots = Vec::new();
+    let mut children = vec![Vec::new(); pods.len()];
+    for (index, pod) in pods.iter().enumerate() {
+        match key(pod, "parent").and_then(|parent| indices.get(&parent)) {
+            Some(parent) => children[*parent].push(index),
+            None => roots.push(index),
+        }
+    }
+    Ok((roots, children))
+}
+
+// Note name, `notes/My Note.md` -> `My Note`
+fn node_label(path: &str) -> String {
+    Path::new(path)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| path.to_string())
+}
+
+fn xml_escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn link(source: &str, target: Option<&str>) -> Pod {
+        let mut file = Pod::new_hash();
+        let _ = file.insert("path".to_string(), Pod::String(source.to_string()));
+        let mut pod = Pod::new_hash();
+        let _ = pod.insert("file".to_string(), file);
+        if let Some(target) = target {
+            let _ = pod.insert("path".to_string(), Pod::String(target.to_string()));
+        }
+        pod
+    }
+
+    fn task(path: &str, ord: &str, text: &str, checked: bool) -> Pod {
+        let mut file = Pod::new_hash();
+        let _ = file.insert("path".to_string(), Pod::String(path.to_string()));
+        let mut pod = Pod::new_hash();
+        let _ = pod.insert("file".to_string(), file);
+        let _ = pod.insert("text".to_string(), Pod::String(text.to_string()));
+        let _ = pod.insert("checked".to_string(), Pod::Boolean(checked));
+        let _ = pod.insert("ord".to_string(), Pod::String(ord.to_string()));
+        let parent = match ord.rsplit_once('.') {
+            Some((parent, _)) => Pod::String(parent.to_string()),
+            None => Pod::Null,
+        };
+        let _ = pod.insert("parent".to_string(), parent);
+        pod
+    }
+
+    #[test]
+    fn test_pods_to_chart() {
+        let pods: Vec<Pod> = [
+            ("a", Pod::Integer(4)),
+            ("b", Pod::Float(1.0)),
+            ("c", Pod::Null),
+        ]
+        .into_iter()
+        .map(|(name, words)| {
+            let mut pod = Pod::new_hash();
+            let _ = pod.insert("name".to_string(), Pod::String(name.to_string()));
+            let _ = pod.insert("words".to_string(), words);
+            pod
+        })
+        .collect();
+        let fields = vec!["name".to_string(), "words".to_string()];
+
+        let bars = pods_to_chart(fields.clone(), pods.clone(), ChartKind::Bar).unwrap();
+        let lines: Vec<&str> = bars.lines().collect();
+        assert_eq!("name\twords\tchart", lines[0]);
+        assert_eq!(format!("a\t4\t{}", "█".repeat(BAR_WIDTH)), lines[1]);
+        assert_eq!("b\t1\t███████▌", lines[2]);
+        assert_eq!("c\tNULL\t", lines[3]);
+
+        let spark = pods_to_chart(fields.clone(), pods.clone(), ChartKind::Spark).unwrap();
+        assert_eq!(Some("█▁ "), spark.lines().last());
+        assert_eq!(
+            "▁▅█▅",
+            sparkline(&[Some(-1.0), Some(2.0), Some(4.0), Some(2.0)])
+        );
+
+        assert_eq!(
+            Err("Charts need results with one numeric column, found 0".to_string()),
+            pods_to_chart(vec!["name".to_string()], pods, ChartKind::Bar)
+        );
+        assert_eq!("bar".parse(), Ok(ChartKind::Bar));
+        assert!("pie".parse::<ChartKind>().is_err());
+    }
+
+    #[cfg(feature = "parquet")]
+    #[test]
+    fn test_pods_to_parquet() {
+        use arrow_array::{Array, Float64Array, Int64Array, StringArray};
+        use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+        let rows = [
+            (
+                Pod::Integer(3),
+                Pod::Integer(1),
+                Pod::Array(vec![Pod::from(1)]),
+            ),
+            (Pod::Null, Pod::Float(2.5), Pod::String("b".to_string())),
+        ];
+        let pods: Vec<Pod> = rows
+            .into_iter()
+            .map(|(count, sleep, tags)| {
+                let mut pod = Pod::new_hash();
+                let _ = pod.insert("count".to_string(), count);
+                let _ = pod.insert("sleep".to_string(), sleep);
+                let _ = pod.insert("tags".to_string(), tags);
+                pod
+            })
+            .collect();
+        let fields = ["count", "sleep", "tags", "file.name"]
+            .map(String::from)
+            .to_vec();
+        let path = std::env::temp_dir().join(format!("krafna-parquet-{}", std::process::id()));
+
+        pods_to_parquet(fields, pods, std::fs::File::create(&path).unwrap()).unwrap();
+        let batch = ParquetRecordBatchReaderBuilder::try_new(std::fs::File::open(&path).unwrap())
+            .unwrap()
+            .build()
+            .unwrap()
+            .next()
+            .unwrap()
+            .unwrap();
+        let _ = std::fs::remove_file(&path);
+
+        let column = |name: &str| batch.column_by_name(name).unwrap().clone();
+        let count = column("count");
+        let count = count.as_any().downcast_ref::<Int64Array>().unwrap();
+        assert_eq!((3, true), (count.value(0), count.is_null(1)));
+        let sleep = column("sleep");
+        let sleep = sleep.as_any().downcast_ref::<Float64Array>().unwrap();
+        assert_eq!((1.0, 2.5), (sleep.value(0), sleep.value(1)));
+        let tags = column("tags");
+        let tags = tags.as_any().downcast_ref::<StringArray>().unwrap();
+        assert_eq!(("[1]", "b"), (tags.value(0), tags.value(1)));
+        assert_eq!(2, column("file.name").null_count());
+    }
+
+    #[test]
+    fn test_pods_to_markdown_table_and_list() {
+        let pods: Vec<Pod> = [
+            ("a.md", Pod::String("x | y".to_string())),
+            ("b.md", Pod::Null),
+        ]
+        .into_iter()
+        .map(|(name, status)| {
+            let mut pod = Pod::new_hash();
+            let _ = pod.insert("status".to_string(), status);
+            let _ = pod.insert("file".to_string(), Pod::new_hash());
+            if let Pod::Hash(hash) = &mut pod {
+                let _ = hash["file"].insert("name".to_string(), name.to_string());
+            }
+            pod
+        })
+        .collect();
+        let fields = vec!["file.name".to_string(), "status".to_string()];
+
+        assert_eq!(
+            "| file.name | status |\n| --- | --- |\n| a.md | x \\| y |\n| b.md |  |",
+            pods_to_markdown_table(fields.clone(), pods.clone())
+        );
+        assert_eq!(
+            "- a.md: x | y\n- b.md: ",
+            pods_to_list(fields, pods.clone())
+        );
+        assert_eq!(
+            "- a.md\n- b.md",
+            pods_to_list(vec!["file.name".to_string()], pods)
+        );
+        assert_eq!(String::new(), pods_to_markdown_table(vec![], vec![]));
+
+        assert_eq!(
+            (Some(View::List), "SELECT title FROM F('x')".to_string()),
+            View::split_keyword("  list\n SELECT title FROM F('x')")
+        );
+        assert_eq!(
+            (None, "SELECT list FROM F('x')".to_string()),
+            View::split_keyword("SELECT list FROM F('x')")
+        );
+        for (query, expected) in [
+            ("LIST FROM F('x')", "SELECT file.name FROM F('x')"),
+            (
+                "list status FROM F('x')",
+                "SELECT file.name, status FROM F('x')",
+            ),
+            (
+                "TABLE title, status FROM F('x') WHERE a",
+                "SELECT file.name, title, status FROM F('x') WHERE a",
+            ),
+            (
+                "TABLE WITHOUT ID title FROM F('x')",
+                "SELECT title FROM F('x')",
+            ),
+            ("TASK FROM MD_TASKS('x')", "FROM MD_TASKS('x')"),
+        ] {
+            assert_eq!(expected, View::split_keyword(query).1, "{}", query);
+        }
+    }
+
+    #[test]
+    fn test_pods_to_nuon() {
+        let mut pod = Pod::new_hash();
+        let mut file = Pod::new_hash();
+        let _ = file.insert("name".to_string(), "a \"b\".md".to_string());
+        let _ = file.insert(
+            "created".to_string(),
+            "2025-01-01T00:00:00+00:00".to_string(),
+        );
+        let _ = pod.insert("file".to_string(), file);
+        let _ = pod.insert("due".to_string(), "2024-05-01".to_string());
+        let _ = pod.insert("title".to_string(), "2024-5-1".to_string());
+        let _ = pod.insert("words".to_string(), Pod::Float(120.0));
+        let _ = pod.insert(
+            "tags".to_string(),
+            Pod::Array(vec![Pod::from(1), Pod::from(true)]),
+        );
+        let fields = [
+            "file.name",
+            "file.created",
+            "due",
+            "title",
+            "words",
+            "tags",
+            "status",
+        ]
+        .map(String::from)
+        .to_vec();
+
+        assert_eq!(
+            "[{\"file.name\": \"a \\\"b\\\".md\", \"file.created\": 2025-01-01T00:00:00+00:00, \
+             due: 2024-05-01, title: \"2024-5-1\", words: 120.0, tags: [1, true], status: null}]",
+            pods_to_nuon(fields, vec![pod])
+        );
+        assert_eq!("[]", pods_to_nuon(vec!["a".to_string()], vec![]));
+    }
+
+    #[test]
+    fn test_pods_to_tsv_header() {
+        let mut pod = Pod::new_hash();
+        let _ = pod.insert("title".to_string(), Pod::String("a".to_string()));
+        let fields = vec![
+            "file.name".to_string(),
+            "title".to_string(),
+            "TO_DATE('01.02.2024', '%d.%m.%Y')".to_string(),
+            "AVG(file.size)".to_string(),
+        ];
+
+        assert_eq!(
+            "file_name\ttitle\tTO_DATE('01.02.2024', '%d.%m.%Y')\tAVG(file_size)\n\ta\t\t",
+            pods_to_tsv(fields, vec![pod])
+        );
+    }
+
+    #[test]
+    fn test_pods_to_tree() {
+        let pods = vec![
+            task("a.md", "1", "one", false),
+            task("a.md", "1.1", "one one", true),
+            task("a.md", "1.1.1", "deep", false),
+            task("a.md", "2.1", "orphan", false),
+            task("b.md", "1", "other", true),
+        ];
+
+        assert_eq!(
+            "a.md\n- [ ] one\n  - [x] one one\n    - [ ] deep\n- [ ] orphan\nb.md\n- [x] other",
+            pods_to_tree(pods.clone()).unwrap()
+        );
+        assert_eq!(
+            r#"[{"text":"one","children":[{"text":"one one","children":[{"text":"deep","children":[]}]}]},{"text":"orphan","children":[]},{"text":"other","children":[]}]"#,
+            pods_to_tree_json(vec!["text".to_string()], pods).unwrap()
+        );
+
+        let mut note = Pod::new_hash();
+        let _ = note.insert("title".to_string(), Pod::String("Note".to_string()));
+        let error = Err(
+            "Task trees need MD_TASKS results, found rows without ord, text, checked".to_string(),
+        );
+        assert_eq!(error, pods_to_tree(vec![note.clone()]));
+        assert_eq!(error, pods_to_tree_json(vec![], vec![note]));
+        assert_eq!(Ok(String::new()), pods_to_tree(vec![]));
+    }
+
+    #[test]
+    fn test_pods_to_graph() {
+        let pods = [
+            link("n/a.md", Some("n/b.md")),
+            link("n/a.md", Some("n/b.md")),
+            link("n/b.md", Some("n/\"c\".md")),
+            link("n/b.md", None),
+        ];
+
+        assert_eq!(
+            concat!(
+                "digraph notes {\n",
+                "  \"n/a.md\" [label=\"a\"];\n",
+                "  \"n/b.md\" [label=\"b\"];\n",
+                "  \"n/\\\"c\\\".md\" [label=\"\\\"c\\\"\"];\n",
+                "  \"n/a.md\" -> \"n/b.md\" [weight=2];\n",
+                "  \"n/b.md\" -> \"n/\\\"c\\\".md\" [weight=1];\n",
+                "}"
+            ),
+            pods_to_graph(&pods, GraphFormat::Dot)
+        );
+        assert!(pods_to_graph(&pods, GraphFormat::GraphMl).contains(
+            "<edge source=\"n/b.md\" target=\"n/&quot;c&quot;.md\"><data key=\"weight\">1</data></edge>"
+        ));
+        assert_eq!(
+            r#"{"nodes":[{"id":"n/a.md","label":"a"},{"id":"n/b.md","label":"b"},{"id":"n/\"c\".md","label":"\"c\""}],"edges":[{"source":"n/a.md","target":"n/b.md","weight":2},{"source":"n/b.md","target":"n/\"c\".md","weight":1}]}"#,
+            pods_to_graph(&pods, GraphFormat::Json)
+        );
+        assert_eq!(Ok(GraphFormat::GraphMl), "GraphML".parse());
+        assert!("svg".parse::<GraphFormat>().is_err());
+    }
+}
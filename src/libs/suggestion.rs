@@ -0,0 +1,83 @@
+//! "Did you mean" suggestions for misspelled keywords, functions and fields.
+
+/// Candidate closest to `word` (ignoring case), if it is close enough to be a likely typo.
+pub fn closest_match<'a, I>(word: &str, candidates: I) -> Option<&'a str>
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    let word = word.to_lowercase();
+    let max_distance = (word.chars().count() / 2).clamp(1, 3);
+    candidates
+        .into_iter()
+        .map(|candidate| (edit_distance(&word, &candidate.to_lowercase()), candidate))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)
+}
+
+/// `message`, followed by `, did you mean 'x'?` when one of `candidates` is close to `word`.
+pub fn with_suggestion<'a, I>(message: String, word: &str, candidates: I) -> String
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    match closest_match(word, candidates) {
+        Some(suggestion) => format!("{}, did you mean '{}'?", message, suggestion),
+        None => message,
+    }
+}
+
+/// Levenshtein distance, counted in chars.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, a_char) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a_char != *b_char);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_edit_distance() {
+        assert_eq!(0, edit_distance("name", "name"));
+        assert_eq!(2, edit_distance("nmae", "name"));
+        assert_eq!(1, edit_distance("tag", "tags"));
+        assert_eq!(3, edit_distance("", "abc"));
+    }
+
+    #[test]
+    fn test_closest_match() {
+        let candidates = ["FRONTMATTER_DATA", "MD_LINKS", "MD_TASKS"];
+
+        assert_eq!(
+            Some("FRONTMATTER_DATA"),
+            closest_match("frontmater_data", candidates)
+        );
+        assert_eq!(Some("MD_TASKS"), closest_match("MD_TASK", candidates));
+        assert_eq!(None, closest_match("SOMETHING", candidates));
+    }
+
+    #[test]
+    fn test_with_suggestion() {
+        assert_eq!(
+            "Unexpected token 'SELCT', did you mean 'SELECT'?",
+            with_suggestion(
+                "Unexpected token 'SELCT'".to_string(),
+                "SELCT",
+                ["SELECT", "FROM"]
+            )
+        );
+        assert_eq!(
+            "Unexpected token 'x'",
+            with_suggestion("Unexpected token 'x'".to_string(), "x", ["SELECT"])
+        );
+    }
+}
@@ -0,0 +1,161 @@
+//! Golden-result helpers for tests of code built on krafna (and krafna's own integration tests):
+//! query results are rendered as stable text and compared with a stored snapshot file.
+//!
+//! Run the tests with `KRAFNA_UPDATE_SNAPSHOTS=1` to write (or rewrite) the snapshot files
+//! instead of comparing against them.
+
+use std::fs;
+use std::path::Path;
+
+use crate::libs::data_fetcher::pod::Pod;
+use crate::libs::serializer::json_row;
+
+/// Environment variable that makes [`assert_snapshot`] write snapshot files.
+pub const UPDATE_SNAPSHOTS_ENV: &str = "KRAFNA_UPDATE_SNAPSHOTS";
+
+/// `file.*` fields that differ from machine to machine, they are replaced with `<time>`.
+const TIME_FIELDS: [&str; 3] = ["created", "accessed", "modified"];
+
+/// `fields` and `rows` (as a query returns them) as text that only changes when the results do:
+/// a `# fields:` line, then one JSON object per row with the fields in SELECT order. File times
+/// are replaced with `<time>`, and paths under `root` are made relative to it (`.` for `root` itself), so snapshots can
+/// be compared on any machine.
+pub fn snapshot_string(fields: &[String], rows: &[Pod], root: Option<&Path>) -> String {
+    let root = root.map(|root| root.display().to_string());
+    let mut lines = vec![format!("# fields: {}", fields.join(", "))];
+    for row in rows {
+        let mut row = normalize(row, root.as_deref());
+        if let Pod::Hash(hash) = &mut row {
+            if let Some(Pod::Hash(file)) = hash.get_mut("file") {
+                for field in TIME_FIELDS {
+                    if let Some(value) = file.get_mut(field) {
+                        *value = Pod::String("<time>".to_string());
+                    }
+                }
+            }
+        }
+        lines.push(json_row(fields, &row).to_string());
+    }
+    lines.join("\n") + "\n"
+}
+
+fn normalize(pod: &Pod, root: Option<&str>) -> Pod {
+    match pod {
+        Pod::String(value) => match root.and_then(|root| value.strip_prefix(root)) {
+            Some("") => Pod::String(".".to_string()),
+            Some(relative) if relative.starts_with('/') => Pod::String(relative[1..].to_string()),
+            _ => pod.clone(),
+        },
+        Pod::Array(array) => Pod::Array(array.iter().map(|pod| normalize(pod, root)).collect()),
+        Pod::Hash(hash) => Pod::Hash(
+            hash.iter()
+                .map(|(key, value)| (key.clone(), normalize(value, root)))
+                .collect(),
+        ),
+        _ => pod.clone(),
+    }
+}
+
+/// Compares `actual` with the snapshot at `path`, the error shows the first line that differs.
+pub fn check_snapshot(path: &Path, actual: &str) -> Result<(), String> {
+    let expected = fs::read_to_string(path).map_err(|e| {
+        format!(
+            "Could not read snapshot {}: {} (run with {}=1 to create it)",
+            path.display(),
+            e,
+            UPDATE_SNAPSHOTS_ENV
+        )
+    })?;
+    if expected == actual {
+        return Ok(());
+    }
+
+    let mut expected_lines = expected.lines();
+    let mut actual_lines = actual.lines();
+    for line in 1.. {
+        match (expected_lines.next(), actual_lines.next()) {
+            (Some(expected), Some(actual)) if expected == actual => continue,
+            (None, None) => break,
+            (expected, actual) => {
+                return Err(format!(
+                    "Snapshot {} differs at line {}:\n  expected: {}\n  actual:   {}",
+                    path.display(),
+                    line,
+                    expected.unwrap_or("<end>"),
+                    actual.unwrap_or("<end>")
+                ))
+            }
+        }
+    }
+    Err(format!(
+        "Snapshot {} differs in line endings",
+        path.display()
+    ))
+}
+
+/// Asserts that query results match the snapshot at `path` (see [`snapshot_string`]), or writes
+/// them there when [`UPDATE_SNAPSHOTS_ENV`] is set.
+#[track_caller]
+pub fn assert_snapshot(
+    path: impl AsRef<Path>,
+    fields: &[String],
+    rows: &[Pod],
+    root: Option<&Path>,
+) {
+    let path = path.as_ref();
+    let actual = snapshot_string(fields, rows, root);
+    if std::env::var_os(UPDATE_SNAPSHOTS_ENV).is_some() {
+        if let Some(parent) = path.parent() {
+            let _ = fs::create_dir_all(parent);
+        }
+        if let Err(error) = fs::write(path, &actual) {
+            panic!("Could not write snapshot {}: {}", path.display(), error);
+        }
+        return;
+    }
+    if let Err(error) = check_snapshot(path, &actual) {
+        panic!("{}", error);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_snapshot_string_is_machine_independent() {
+        let mut file = Pod::new_hash();
+        let _ = file.insert("path".to_string(), Pod::String("/vault/a.md".to_string()));
+        let _ = file.insert("modified".to_string(), Pod::String("2025".to_string()));
+        let mut row = Pod::new_hash();
+        let _ = row.insert("title".to_string(), Pod::String("A".to_string()));
+        let _ = row.insert("file".to_string(), file);
+        let fields = vec!["title".to_string(), "file".to_string()];
+
+        let snapshot = snapshot_string(&fields, &[row], Some(Path::new("/vault")));
+
+        assert_eq!(
+            "# fields: title, file\n{\"title\":\"A\",\"file\":{\"path\":\"a.md\",\"modified\":\"<time>\"}}\n",
+            snapshot
+        );
+    }
+
+    #[test]
+    fn test_check_snapshot_reports_first_difference() {
+        let path = std::env::temp_dir().join(format!("krafna-golden-{}", std::process::id()));
+        fs::write(&path, "# fields: a\n{\"a\":1}\n").unwrap();
+
+        let same = check_snapshot(&path, "# fields: a\n{\"a\":1}\n");
+        let different = check_snapshot(&path, "# fields: a\n{\"a\":2}\n");
+        let _ = fs::remove_file(&path);
+
+        assert_eq!(Ok(()), same);
+        assert_eq!(
+            format!(
+                "Snapshot {} differs at line 2:\n  expected: {{\"a\":1}}\n  actual:   {{\"a\":2}}",
+                path.display()
+            ),
+            different.unwrap_err()
+        );
+    }
+}
@@ -0,0 +1,318 @@
+//! `UNION` / `UNION ALL` of queries, e.g. `SELECT file.name, title FROM FRONTMATTER_DATA('~/inbox')
+//! UNION ALL SELECT file.name, text FROM MD_TASKS('~/projects') ORDER BY title`. Every query runs
+//! on its own (with its own WHERE) and their rows are concatenated in query order under the column
+//! names of the first query. Like in SQL, ORDER BY, LIMIT and OFFSET after the last query sort and
+//! cut the combined rows. UNION leaves out duplicate rows, UNION ALL keeps them.
+
+use std::collections::HashSet;
+use std::error::Error;
+
+use crate::libs::data_fetcher::pod::Pod;
+use crate::libs::lexer::{tokenize, TokenKind};
+
+/// Queries of a UNION, as query text, and for every query after the first whether it was joined
+/// with UNION ALL.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Union {
+    pub first: String,
+    pub rest: Vec<(bool, String)>,
+    /// ORDER BY, LIMIT and OFFSET after the last query, e.g. `ORDER BY title LIMIT 10`, they are
+    /// applied to the combined rows
+    pub order_by: Option<String>,
+}
+
+impl Union {
+    /// Splits `query` at every UNION outside of brackets, `None` when there is none.
+    /// `DEFINE`s in front of the first query are used by all of them, ORDER BY, LIMIT and OFFSET
+    /// at the end of the last one go to [`Union::order_by`].
+    pub fn split(query: &str) -> Result<Option<Union>, String> {
+        let tokens = tokenize(query).map_err(|error| error.message)?;
+        let chars: Vec<char> = query.chars().collect();
+        let text = |start: usize, end: usize| chars[start..end].iter().collect::<String>();
+
+        let mut depth = 0;
+        let mut select_start = None;
+        // (char where UNION starts, char where the next query starts, ALL)
+        let mut unions = Vec::new();
+        // Char where ORDER BY, LIMIT or OFFSET of the last query starts
+        let mut order_by = None;
+        let mut index = 0;
+        while let Some(token) = tokens.get(index) {
+            match token.kind {
+                TokenKind::OpenedBracket => depth += 1,
+                TokenKind::ClosedBracket => depth -= 1,
+                _ if depth != 0 => {}
+                _ if token.is_keyword("SELECT") && select_start.is_none() => {
+                    select_start = Some(token.span.start)
+                }
+                _ if token.is_keyword("UNION") => {
+                    order_by = None;
+                    let all = tokens.get(index + 1).is_some_and(|t| t.is_keyword("ALL"));
+                    if all {
+                        index += 1;
+                    }
+                    let next = tokens.get(index + 1).map(|t| t.span.start);
+                    unions.push((token.span.start, next.unwrap_or(chars.len()), all));
+                }
+                _ if order_by.is_none()
+                    && ((token.is_keyword("ORDER")
+                        && tokens.get(index + 1).is_some_and(|t| t.is_keyword("BY")))
+                        || token.is_keyword("LIMIT")
+                        || token.is_keyword("OFFSET")) =>
+                {
+                    order_by = Some(token.span.start)
+                }
+                _ => {}
+            }
+            index += 1;
+        }
+        if unions.is_empty() {
+            return Ok(None);
+        }
+
+        let definitions = text(0, select_start.unwrap_or_default());
+        let mut rest = Vec::new();
+        let last_end = order_by.unwrap_or(chars.len());
+        for (position, (_, start, all)) in unions.iter().enumerate() {
+            let end = unions.get(position + 1).map_or(last_end, |next| next.0);
+            let part = text(*start, end);
+            if part.trim().is_empty() {
+                return Err(format!(
+                    "UNION{} needs a query after it",
+                    if *all { " ALL" } else { "" }
+                ));
+            }
+            rest.push((*all, format!("{}{}", definitions, part.trim())));
+        }
+        Ok(Some(Union {
+            first: text(0, unions[0].0).trim().to_string(),
+            rest,
+            order_by: order_by.map(|start| text(start, chars.len()).trim().to_string()),
+        }))
+    }
+
+    /// Runs `query` with `execute`, every query of it on its own and their results combined
+    /// when it is a UNION. `order` sorts and cuts the combined rows, see [`Union::execute`].
+    pub fn execute_query<F, G>(
+        query: &str,
+        execute: F,
+        order: G,
+    ) -> Result<(Vec<String>, Vec<Pod>), Box<dyn Error>>
+    where
+        F: Fn(&str) -> Result<(Vec<String>, Vec<Pod>), Box<dyn Error>>,
+        G: Fn(&str, Vec<Pod>) -> Result<Vec<Pod>, Box<dyn Error>>,
+    {
+        match Union::split(query)? {
+            Some(union) => union.execute(execute, order),
+            None => execute(query),
+        }
+    }
+
+    /// Runs every query with `execute` and combines their results. When there is an
+    /// [`Union::order_by`], `order` gets it with the combined rows and returns them sorted and cut.
+    pub fn execute<F, G>(
+        &self,
+        execute: F,
+        order: G,
+    ) -> Result<(Vec<String>, Vec<Pod>), Box<dyn Error>>
+    where
+        F: Fn(&str) -> Result<(Vec<String>, Vec<Pod>), Box<dyn Error>>,
+        G: Fn(&str, Vec<Pod>) -> Result<Vec<Pod>, Box<dyn Error>>,
+    {
+        let (fields, rows) = execute(&self.first)?;
+        let mut rows = union_rows(&fields, &fields, rows);
+        for (all, query) in &self.rest {
+            let (next_fields, next_rows) = execute(query)?;
+            if next_fields.len() != fields.len() {
+                return Err(format!(
+                    "Queries of a UNION need the same number of columns, but {} has {} and {} has {}",
+                    fields.join(", "),
+                    fields.len(),
+                    next_fields.join(", "),
+                    next_fields.len()
+                )
+                .into());
+            }
+            rows.extend(union_rows(&fields, &next_fields, next_rows));
+            if !all {
+                rows = distinct_rows(&fields, rows);
+            }
+        }
+        if let Some(order_by) = &self.order_by {
+            rows = order(order_by, rows)?;
+        }
+        Ok((fields, rows))
+    }
+}
+
+// Rows with the values of `from` under the names of `fields`, by position
+fn union_rows(fields: &[String], from: &[String], rows: Vec<Pod>) -> Vec<Pod> {
+    rows.into_iter()
+        .map(|row| {
+            let mut union_row = Pod::new_hash();
+            for (field, from) in fields.iter().zip(from) {
+                let value = row.nested_get(from).cloned().unwrap_or(Pod::Null);
+                let _ = union_row.insert(field.clone(), value);
+            }
+            union_row
+        })
+        .collect()
+}
+
+// First of every set of rows with the same values
+fn distinct_rows(fields: &[String], rows: Vec<Pod>) -> Vec<Pod> {
+    let mut seen = HashSet::new();
+    rows.into_iter()
+        .filter(|row| {
+            let values: Vec<Option<&Pod>> =
+                fields.iter().map(|field| row.nested_get(field)).collect();
+            seen.insert(format!("{:?}", values))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(fields: &[(&str, Pod)]) -> Pod {
+        let mut row = Pod::new_hash();
+        for (field, value) in fields {
+            let _ = row.insert(field.to_string(), value.clone());
+        }
+        row
+    }
+
+    #[test]
+    fn test_split_union() -> Result<(), String> {
+        assert_eq!(
+            None,
+            Union::split("SELECT a FROM F('x') WHERE a IN ('union')")?
+        );
+        assert_eq!(
+            Some(Union {
+                first: "SELECT a FROM F('x')".to_string(),
+                rest: vec![
+                    (true, "SELECT b FROM G('y') WHERE (b)".to_string()),
+                    (false, "SELECT c FROM F('z')".to_string()),
+                ],
+                order_by: None,
+            }),
+            Union::split("SELECT a FROM F('x') UNION ALL SELECT b FROM G('y') WHERE (b)\nunion SELECT c FROM F('z')")?
+        );
+        assert_eq!(
+            Some(Union {
+                first: "DEFINE open AS (status != 'done'); SELECT a FROM F('x') WHERE $open"
+                    .to_string(),
+                rest: vec![(
+                    false,
+                    "DEFINE open AS (status != 'done'); SELECT b FROM G('y') WHERE $open"
+                        .to_string()
+                )],
+                order_by: None,
+            }),
+            Union::split(
+                "DEFINE open AS (status != 'done'); SELECT a FROM F('x') WHERE $open UNION SELECT b FROM G('y') WHERE $open"
+            )?
+        );
+        assert_eq!(
+            Some(Union {
+                first: "SELECT a FROM F('x') ORDER BY a".to_string(),
+                rest: vec![(true, "SELECT b FROM G('y') WHERE b IN (SELECT c FROM H('z') LIMIT 1)".to_string())],
+                order_by: Some("ORDER BY a DESC LIMIT 2".to_string()),
+            }),
+            Union::split("SELECT a FROM F('x') ORDER BY a UNION ALL SELECT b FROM G('y') WHERE b IN (SELECT c FROM H('z') LIMIT 1) ORDER BY a DESC LIMIT 2")?
+        );
+        assert_eq!(
+            Err("UNION ALL needs a query after it".to_string()),
+            Union::split("SELECT a FROM F('x') UNION ALL")
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_union_execute() -> Result<(), Box<dyn Error>> {
+        let execute = |query: &str| -> Result<(Vec<String>, Vec<Pod>), Box<dyn Error>> {
+            Ok(match query {
+                "a" => (
+                    vec!["file.name".to_string(), "title".to_string()],
+                    vec![
+                        row(&[
+                            ("file", row(&[("name", Pod::String("a.md".to_string()))])),
+                            ("title", Pod::String("A".to_string())),
+                        ]),
+                        row(&[("title", Pod::String("B".to_string()))]),
+                    ],
+                ),
+                "b" => (
+                    vec!["file.name".to_string(), "text".to_string()],
+                    vec![row(&[
+                        ("file", row(&[("name", Pod::String("a.md".to_string()))])),
+                        ("text", Pod::String("A".to_string())),
+                    ])],
+                ),
+                _ => (vec!["x".to_string()], Vec::new()),
+            })
+        };
+        // Sorts by title, descending
+        let order = |order_by: &str, mut rows: Vec<Pod>| -> Result<Vec<Pod>, Box<dyn Error>> {
+            assert_eq!("ORDER BY title DESC", order_by);
+            rows.sort_by_key(|row| std::cmp::Reverse(row.nested_get("title").map(Pod::to_string)));
+            Ok(rows)
+        };
+        let union = |rest: Vec<(bool, &str)>| Union {
+            first: "a".to_string(),
+            rest: rest
+                .into_iter()
+                .map(|(all, query)| (all, query.to_string()))
+                .collect(),
+            order_by: None,
+        };
+        let rows = |rows: Vec<Pod>| -> Vec<String> { rows.iter().map(Pod::to_string).collect() };
+
+        let (fields, all) = union(vec![(true, "b")]).execute(execute, order)?;
+        assert_eq!(vec!["file.name", "title"], fields);
+        assert_eq!(
+            vec![
+                r#"{"file.name":"a.md","title":"A"}"#,
+                r#"{"file.name":null,"title":"B"}"#,
+                r#"{"file.name":"a.md","title":"A"}"#,
+            ],
+            rows(all)
+        );
+        let (_, distinct) = union(vec![(false, "b")]).execute(execute, order)?;
+        assert_eq!(
+            vec![
+                r#"{"file.name":"a.md","title":"A"}"#,
+                r#"{"file.name":null,"title":"B"}"#,
+            ],
+            rows(distinct)
+        );
+        assert_eq!(
+            "Queries of a UNION need the same number of columns, but file.name, title has 2 and x has 1",
+            union(vec![(true, "c")]).execute(execute, order).unwrap_err().to_string()
+        );
+
+        let (fields, combined) = Union::execute_query("a UNION ALL b", execute, order)?;
+        assert_eq!(vec!["file.name", "title"], fields);
+        assert_eq!(3, combined.len());
+        let (_, sorted) = Union {
+            order_by: Some("ORDER BY title DESC".to_string()),
+            ..union(vec![(true, "b")])
+        }
+        .execute(execute, order)?;
+        assert_eq!(
+            vec![
+                r#"{"file.name":null,"title":"B"}"#,
+                r#"{"file.name":"a.md","title":"A"}"#,
+                r#"{"file.name":"a.md","title":"A"}"#,
+            ],
+            rows(sorted)
+        );
+        let (fields, _) = Union::execute_query("b", execute, order)?;
+        assert_eq!(vec!["file.name", "text"], fields);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,765 @@
+//! Writes UPDATE statements back into markdown files. Only the lines of the changed fields are
+//! rewritten, the rest of the file (comments, formatting, field order) stays as it is.
+
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fmt::Display;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use yaml_rust2::YamlLoader;
+
+use crate::libs::data_fetcher::markdown_fetcher::{
+    cache_dir, task_markers, toml_or_json_frontmatter, FrontmatterFormat,
+};
+use crate::libs::data_fetcher::pod::Pod;
+use crate::libs::parser::{Assignment, FieldValue};
+
+/// Fields that every row has, they can not be written to a file.
+const FILE_FIELD: &str = "file";
+
+/// The only field of a task that can be set.
+const CHECKED_FIELD: &str = "checked";
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct UpdateOptions {
+    /// Only report what would change, without writing any file
+    pub dry_run: bool,
+    /// Where the original files are copied to before they are written, `None` uses a new
+    /// directory in the OS cache dir for every update
+    pub backup_dir: Option<PathBuf>,
+}
+
+/// Lines of a file replaced by an update.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LineChange {
+    /// Line the change starts at in the original file, 1-based
+    pub line: usize,
+    pub removed: Vec<String>,
+    pub added: Vec<String>,
+}
+
+/// Changes made (or, in a dry run, that would be made) to one file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileUpdate {
+    pub path: PathBuf,
+    pub changes: Vec<LineChange>,
+    /// Copy of the original file, `None` in a dry run
+    pub backup: Option<PathBuf>,
+}
+
+/// Every change as a hunk, e.g.
+/// ```text
+/// /notes/a.md:3
+/// - status: open
+/// + status: archived
+/// ```
+impl Display for FileUpdate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut hunks = Vec::new();
+        for change in &self.changes {
+            let mut hunk = vec![format!("{}:{}", self.path.display(), change.line)];
+            hunk.extend(change.removed.iter().map(|line| format!("- {}", line)));
+            hunk.extend(change.added.iter().map(|line| format!("+ {}", line)));
+            hunks.push(hunk.join("\n"));
+        }
+        write!(f, "{}", hunks.join("\n"))
+    }
+}
+
+/// Sets the `assignments` in the frontmatter of the files of `rows`. Nothing is written unless
+/// every file can be updated, and every file is backed up before it is written. When backing up
+/// or writing a file fails, the files written before it are restored from their backups. Files
+/// that already have the values are left out.
+pub fn update_frontmatter(
+    assignments: &[Assignment],
+    rows: &[Pod],
+    options: &UpdateOptions,
+) -> Result<Vec<FileUpdate>, Box<dyn Error>> {
+    check_assignments(assignments)?;
+
+    update_files(rows, options, |content, _| {
+        set_frontmatter_fields(content, assignments)
+    })
+}
+
+/// Checks or unchecks the tasks of `rows` (MD_TASKS rows), the only field that can be set is
+/// `checked`. Files are written like in [`update_frontmatter`].
+pub fn update_tasks(
+    assignments: &[Assignment],
+    rows: &[Pod],
+    options: &UpdateOptions,
+) -> Result<Vec<FileUpdate>, Box<dyn Error>> {
+    let checked = match assignments {
+        [Assignment {
+            field_name,
+            value: FieldValue::Bool(checked),
+        }] if field_name == CHECKED_FIELD => *checked,
+        _ => {
+            return Err(format!(
+                "Only {} = true or {} = false can be set on tasks",
+                CHECKED_FIELD, CHECKED_FIELD
+            )
+            .into())
+        }
+    };
+
+    update_files(rows, options, |content, rows| {
+        let ords: Vec<String> = rows
+            .iter()
+            .filter_map(|row| row.nested_get("ord").map(Pod::to_string))
+            .collect();
+        Ok(set_tasks_checked(content, &ords, checked))
+    })
+}
+
+// Edits the files of `rows` with `edit(content, rows of the file)`, backing them up first
+fn update_files<F>(
+    rows: &[Pod],
+    options: &UpdateOptions,
+    edit: F,
+) -> Result<Vec<FileUpdate>, Box<dyn Error>>
+where
+    F: Fn(&str, &[&Pod]) -> Result<(String, Vec<LineChange>), String>,
+{
+    let mut rows_by_path: BTreeMap<PathBuf, Vec<&Pod>> = BTreeMap::new();
+    for row in rows {
+        if let Some(Pod::String(path)) = row.nested_get("file.path") {
+            rows_by_path
+                .entry(PathBuf::from(path))
+                .or_default()
+                .push(row);
+        }
+    }
+
+    let mut updates = Vec::new();
+    for (path, rows) in rows_by_path {
+        let content = fs::read_to_string(&path)
+            .map_err(|error| format!("Could not read {}: {}", path.display(), error))?;
+        let (content, changes) = edit(&content, &rows)
+            .map_err(|error| format!("Could not update {}: {}", path.display(), error))?;
+        if !changes.is_empty() {
+            updates.push((path, content, changes));
+        }
+    }
+    if options.dry_run {
+        return Ok(updates
+            .into_iter()
+            .map(|(path, _, changes)| FileUpdate {
+                path,
+                changes,
+                backup: None,
+            })
+            .collect());
+    }
+
+    let backup_dir = match &options.backup_dir {
+        Some(backup_dir) => backup_dir.clone(),
+        None => default_backup_dir()?,
+    };
+    // Two files backed up to the same path would leave only the last one
+    let mut backed_up: HashMap<PathBuf, &Path> = HashMap::new();
+    for (path, _, _) in &updates {
+        if let Some(other) = backed_up.insert(backup_name(path), path) {
+            return Err(format!(
+                "{} and {} would be backed up to the same file, nothing was written",
+                other.display(),
+                path.display()
+            )
+            .into());
+        }
+    }
+
+    let mut written = Vec::new();
+    for (path, content, changes) in updates {
+        let backup = backup_dir.join(backup_name(&path));
+        let result = back_up(&path, &backup).and_then(|()| {
+            fs::write(&path, content)
+                .map_err(|error| format!("Could not write {}: {}", path.display(), error))
+        });
+        if let Err(error) = result {
+            return Err(restore_backups(error, &written).into());
+        }
+        written.push(FileUpdate {
+            path,
+            changes,
+            backup: Some(backup),
+        });
+    }
+    Ok(written)
+}
+
+fn back_up(path: &Path, backup: &Path) -> Result<(), String> {
+    let error = |error: std::io::Error| format!("Could not back up {}: {}", path.display(), error);
+    if let Some(parent) = backup.parent() {
+        fs::create_dir_all(parent).map_err(error)?;
+    }
+    fs::copy(path, backup).map_err(error)?;
+    Ok(())
+}
+
+// Copies the `written` files back from their backups after `error`, so a failed update leaves
+// the files as they were
+fn restore_backups(error: String, written: &[FileUpdate]) -> String {
+    let not_restored: Vec<String> = written
+        .iter()
+        .filter_map(|update| {
+            let backup = update.backup.as_ref()?;
+            fs::copy(backup, &update.path)
+                .err()
+                .map(|_| format!("{} (backup in {})", update.path.display(), backup.display()))
+        })
+        .collect();
+    match not_restored.is_empty() {
+        true => format!("{}, no file was changed", error),
+        false => format!("{}, could not restore {}", error, not_restored.join(", ")),
+    }
+}
+
+fn check_assignments(assignments: &[Assignment]) -> Result<(), String> {
+    for (i, assignment) in assignments.iter().enumerate() {
+        let field = &assignment.field_name;
+        if field.contains(['.', '[']) {
+            return Err(format!(
+                "Only top level frontmatter fields can be set, not {}",
+                field
+            ));
+        }
+        if field == FILE_FIELD {
+            return Err(format!("{} fields can not be set", FILE_FIELD));
+        }
+        if assignments[..i]
+            .iter()
+            .any(|other| &other.field_name == field)
+        {
+            return Err(format!("{} is set more than once", field));
+        }
+    }
+    Ok(())
+}
+
+// Backups keep the whole path of the file, so files with the same name do not collide
+fn backup_name(path: &Path) -> PathBuf {
+    let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    path.components()
+        .filter_map(|component| match component {
+            Component::Normal(part) => Some(part),
+            _ => None,
+        })
+        .collect()
+}
+
+/// New directory in the OS cache dir, named after the current time, used for backups when
+/// [`UpdateOptions::backup_dir`] is not set.
+pub fn default_backup_dir() -> Result<PathBuf, Box<dyn Error>> {
+    let millis = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis();
+    Ok(cache_dir()?.join("backups").join(millis.to_string()))
+}
+
+/// Sets the `assignments` in the frontmatter of markdown `content`, creating the frontmatter when
+/// there is none. NULL removes the field. Returns the new content and which lines changed.
+pub fn set_frontmatter_fields(
+    content: &str,
+    assignments: &[Assignment],
+) -> Result<(String, Vec<LineChange>), String> {
+    let (bom, body) = match content.strip_prefix('\u{feff}') {
+        Some(body) => ("\u{feff}", body),
+        None => ("", content),
+    };
+    // Fields are written as YAML, into TOML or JSON they would break the frontmatter
+    match toml_or_json_frontmatter(body) {
+        Some((FrontmatterFormat::Toml, _, _)) => {
+            return Err("UPDATE only writes YAML frontmatter, not TOML (+++)".to_string())
+        }
+        Some((FrontmatterFormat::Json, _, _)) => {
+            return Err("UPDATE only writes YAML frontmatter, not JSON ({)".to_string())
+        }
+        None => {}
+    }
+    let newline = if body.contains("\r\n") { "\r\n" } else { "\n" };
+    let mut lines: Vec<String> = body
+        .split('\n')
+        .map(|line| line.strip_suffix('\r').unwrap_or(line).to_string())
+        .collect();
+
+    // (start, end, new lines) in the original lines, they never overlap
+    let mut edits: Vec<(usize, usize, Vec<String>)> = Vec::new();
+    match frontmatter_lines(&lines)? {
+        Some((open, close)) => {
+            let mut missing = Vec::new();
+            for assignment in assignments {
+                let field_lines = yaml_field(assignment);
+                match find_field(&lines, open + 1, close, &assignment.field_name) {
+                    Some((start, end)) if lines[start..end] != field_lines[..] => {
+                        edits.push((start, end, field_lines))
+                    }
+                    Some(_) => {}
+                    None => missing.extend(field_lines),
+                }
+            }
+            if !missing.is_empty() {
+                edits.push((close, close, missing));
+            }
+        }
+        None => {
+            let fields: Vec<String> = assignments.iter().flat_map(yaml_field).collect();
+            if !fields.is_empty() {
+                let mut frontmatter = vec!["---".to_string()];
+                frontmatter.extend(fields);
+                frontmatter.push("---".to_string());
+                edits.push((0, 0, frontmatter));
+            }
+        }
+    }
+    edits.sort_by_key(|(start, _, _)| *start);
+
+    let mut changes = Vec::new();
+    for (start, end, added) in edits.into_iter().rev() {
+        let removed: Vec<String> = lines.splice(start..end, added.clone()).collect();
+        changes.push(LineChange {
+            line: start + 1,
+            removed,
+            added,
+        });
+    }
+    changes.reverse();
+
+    if let Some((open, close)) = frontmatter_lines(&lines)? {
+        YamlLoader::load_from_str(&lines[open + 1..close].join("\n"))
+            .map_err(|error| format!("Updated frontmatter is not valid YAML: {}", error))?;
+    }
+    Ok((format!("{}{}", bom, lines.join(newline)), changes))
+}
+
+/// Sets the `[ ]`/`[x]` marker of the tasks with the given `ords` in markdown `content`. Returns
+/// the new content and which lines changed.
+pub fn set_tasks_checked(
+    content: &str,
+    ords: &[String],
+    checked: bool,
+) -> (String, Vec<LineChange>) {
+    // Tasks are numbered in the markdown after the frontmatter
+    let bom = if content.starts_with('\u{feff}') {
+        "\u{feff}".len()
+    } else {
+        0
+    };
+    let lines: Vec<String> = content[bom..].split('\n').map(str::to_string).collect();
+    let body_start = match frontmatter_lines(&lines) {
+        Ok(Some((_, close))) => {
+            bom + lines[..=close]
+                .iter()
+                .map(|line| line.len() + 1)
+                .sum::<usize>()
+        }
+        _ => toml_or_json_frontmatter(&content[bom..])
+            .map_or(bom, |(_, _, markdown_start)| bom + markdown_start),
+    };
+    let body_start = body_start.min(content.len());
+
+    let marker = if checked { "[x]" } else { "[ ]" };
+    let mut updated = content.to_string();
+    let mut changes = Vec::new();
+    for (ord, range) in task_markers(&content[body_start..]) {
+        let current = &content[body_start + range.start..body_start + range.end];
+        if !ords.contains(&ord) || (current != "[ ]") == checked {
+            continue;
+        }
+        let start = body_start + range.start;
+        updated.replace_range(start..body_start + range.end, marker);
+
+        let line_start = content[..start].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = content[start..]
+            .find('\n')
+            .map_or(content.len(), |i| start + i);
+        let line = content[line_start..line_end].trim_end_matches('\r');
+        changes.push(LineChange {
+            line: content[..start].matches('\n').count() + 1,
+            removed: vec![line.to_string()],
+            added: vec![line.replacen(current, marker, 1)],
+        });
+    }
+    (updated, changes)
+}
+
+// Lines of the opening and closing `---`, blank lines can come before the frontmatter
+fn frontmatter_lines(lines: &[String]) -> Result<Option<(usize, usize)>, String> {
+    let Some(open) = lines.iter().position(|line| !line.trim().is_empty()) else {
+        return Ok(None);
+    };
+    if lines[open].trim_end() != "---" {
+        return Ok(None);
+    }
+    match lines[open + 1..]
+        .iter()
+        .position(|line| matches!(line.trim_end(), "---" | "..."))
+    {
+        Some(length) => Ok(Some((open, open + 1 + length))),
+        None => Err("Frontmatter is not closed with ---".to_string()),
+    }
+}
+
+// Lines of a top level `field:` and its (indented or list) value, without trailing blank lines
+fn find_field(lines: &[String], from: usize, to: usize, field: &str) -> Option<(usize, usize)> {
+    let start = (from..to).find(|&i| line_key(&lines[i]) == Some(field))?;
+    let mut end = start + 1;
+    while end < to && (lines[end].starts_with([' ', '\t', '-']) || lines[end].trim().is_empty()) {
+        end += 1;
+    }
+    while end > start + 1 && lines[end - 1].trim().is_empty() {
+        end -= 1;
+    }
+    Some((start, end))
+}
+
+fn line_key(line: &str) -> Option<&str> {
+    if line.starts_with([' ', '\t', '#', '-']) {
+        return None;
+    }
+    let (key, value) = line.split_once(':')?;
+    if !value.is_empty() && !value.starts_with([' ', '\t']) {
+        return None;
+    }
+    let key = key.trim_end();
+    Some(
+        key.strip_prefix('"')
+            .and_then(|key| key.strip_suffix('"'))
+            .or_else(|| {
+                key.strip_prefix('\'')
+                    .and_then(|key| key.strip_suffix('\''))
+            })
+            .unwrap_or(key),
+    )
+}
+
+// Lists are written as block lists, the way Obsidian writes them
+fn yaml_field(assignment: &Assignment) -> Vec<String> {
+    let field = &assignment.field_name;
+    match &assignment.value {
+        FieldValue::Null => Vec::new(),
+        FieldValue::List(list) if !list.is_empty() => {
+            let mut lines = vec![format!("{}:", field)];
+            lines.extend(list.iter().map(|item| format!("  - {}", yaml_value(item))));
+            lines
+        }
+        value => vec![format!("{}: {}", field, yaml_value(value))],
+    }
+}
+
+fn yaml_value(value: &FieldValue) -> String {
+    match value {
+        FieldValue::Null => "null".to_string(),
+        FieldValue::Bool(b) => b.to_string(),
+        FieldValue::Integer(n) => n.to_string(),
+        // `2.0`, so it stays a float when read back
+        FieldValue::Float(n) => format!("{:?}", n),
+        FieldValue::String(s) => yaml_string(s),
+        FieldValue::Duration(_) => yaml_string(&value.to_string()),
+        FieldValue::List(list) => {
+            let items: Vec<String> = list.iter().map(yaml_value).collect();
+            format!("[{}]", items.join(", "))
+        }
+    }
+}
+
+// Plain when YAML reads it back as the same string, double quoted otherwise
+fn yaml_string(s: &str) -> String {
+    let plain = s.starts_with(|c: char| c.is_alphanumeric() || c == '_')
+        && s == s.trim_end()
+        && s.chars()
+            .all(|c| c.is_alphanumeric() || matches!(c, ' ' | '_' | '-' | '.' | '/'))
+        && !matches!(
+            s.to_lowercase().as_str(),
+            "true" | "false" | "yes" | "no" | "on" | "off" | "null" | "nan" | "inf"
+        )
+        // Numbers like `12`, `1e3` or `0x1A` are not read back as strings
+        && YamlLoader::load_from_str(&format!("v: {}", s))
+            .is_ok_and(|yaml| yaml[0]["v"].as_str() == Some(s));
+    if plain {
+        return s.to_string();
+    }
+    let escaped = s
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+        .replace('\t', "\\t");
+    format!("\"{}\"", escaped)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set(field_name: &str, value: FieldValue) -> Assignment {
+        Assignment {
+            field_name: field_name.to_string(),
+            value,
+        }
+    }
+
+    #[test]
+    fn test_set_frontmatter_fields_keeps_the_rest_of_the_file() {
+        let content = "---\r\n# comment\r\nstatus: open\r\ntags:\r\n  - a\r\n  - b\r\n\r\ntitle: x\r\n---\r\n# Note\r\n";
+        let assignments = [
+            set(
+                "tags",
+                FieldValue::List(vec![FieldValue::String("work".to_string())]),
+            ),
+            set("status", FieldValue::String("archived".to_string())),
+            set("title", FieldValue::Null),
+            set("priority", FieldValue::Integer(2)),
+        ];
+
+        let (updated, changes) = set_frontmatter_fields(content, &assignments).unwrap();
+
+        assert_eq!(
+            "---\r\n# comment\r\nstatus: archived\r\ntags:\r\n  - work\r\n\r\npriority: 2\r\n---\r\n# Note\r\n",
+            updated
+        );
+        assert_eq!(
+            vec![3, 4, 8, 9],
+            changes.iter().map(|c| c.line).collect::<Vec<_>>()
+        );
+        assert_eq!(vec!["  - a", "  - b"], changes[1].removed[1..]);
+    }
+
+    #[test]
+    fn test_set_frontmatter_fields_creates_frontmatter() {
+        let assignments = [set("done", FieldValue::Bool(true))];
+
+        let (updated, changes) = set_frontmatter_fields("# Note\n", &assignments).unwrap();
+        let (unchanged, no_changes) = set_frontmatter_fields(&updated, &assignments).unwrap();
+
+        assert_eq!("---\ndone: true\n---\n# Note\n", updated);
+        assert_eq!(1, changes.len());
+        assert_eq!(updated, unchanged);
+        assert!(no_changes.is_empty());
+    }
+
+    #[test]
+    fn test_toml_and_json_frontmatter_are_not_written() {
+        for (content, error) in [
+            ("+++\ntitle = \"a\"\n+++\n# Note\n", "not TOML (+++)"),
+            ("{\n\"title\": \"a\"\n}\n# Note\n", "not JSON ({)"),
+        ] {
+            let result = set_frontmatter_fields(content, &[set("done", FieldValue::Bool(true))]);
+            assert_eq!(
+                Err(format!("UPDATE only writes YAML frontmatter, {}", error)),
+                result
+            );
+        }
+
+        // Tasks are numbered after the frontmatter, as MD_TASKS has them
+        let (updated, _) = set_tasks_checked(
+            "+++\ntodo = \"\"\"\n- [ ] not a task\n\"\"\"\n+++\n- [ ] a\n",
+            &["1".to_string()],
+            true,
+        );
+        assert_eq!(
+            "+++\ntodo = \"\"\"\n- [ ] not a task\n\"\"\"\n+++\n- [x] a\n",
+            updated
+        );
+    }
+
+    #[test]
+    fn test_yaml_string_reads_back_the_same() {
+        for s in [
+            "plain text",
+            "true",
+            "12",
+            "0x1A",
+            "0o17",
+            "1e3",
+            "yes",
+            "null",
+            "~",
+            "a: b",
+            "it's \"quoted\"",
+            "#tag",
+            "",
+        ] {
+            let yaml = YamlLoader::load_from_str(&format!("v: {}", yaml_string(s))).unwrap();
+            assert_eq!(Some(s), yaml[0]["v"].as_str(), "{}", s);
+        }
+    }
+
+    #[test]
+    fn test_update_frontmatter_backs_up_files() {
+        let dir = std::env::temp_dir().join(format!("krafna-update-{}", std::process::id()));
+        let backup_dir = dir.join("backups");
+        fs::create_dir_all(dir.join("notes")).unwrap();
+        let note = dir.join("notes/a.md");
+        fs::write(&note, "---\nstatus: open\n---\n").unwrap();
+        let mut file = Pod::new_hash();
+        let _ = file.insert("path".to_string(), Pod::String(note.display().to_string()));
+        let mut row = Pod::new_hash();
+        let _ = row.insert("file".to_string(), file);
+        let assignments = [set("status", FieldValue::String("done".to_string()))];
+
+        let dry_run = update_frontmatter(
+            &assignments,
+            &[row.clone()],
+            &UpdateOptions {
+                dry_run: true,
+                backup_dir: Some(backup_dir.clone()),
+            },
+        );
+        let after_dry_run = fs::read_to_string(&note).unwrap();
+        let updated = update_frontmatter(
+            &assignments,
+            &[row],
+            &UpdateOptions {
+                dry_run: false,
+                backup_dir: Some(backup_dir.clone()),
+            },
+        );
+        let after = fs::read_to_string(&note).unwrap();
+        let backup = fs::read_to_string(backup_dir.join(backup_name(&note)));
+        let _ = fs::remove_dir_all(&dir);
+
+        let dry_run = dry_run.unwrap();
+        assert_eq!(
+            format!("{}:2\n- status: open\n+ status: done", note.display()),
+            dry_run[0].to_string()
+        );
+        assert_eq!("---\nstatus: open\n---\n", after_dry_run);
+        assert_eq!(dry_run[0].changes, updated.unwrap()[0].changes);
+        assert_eq!("---\nstatus: done\n---\n", after);
+        assert_eq!("---\nstatus: open\n---\n", backup.unwrap());
+    }
+
+    #[test]
+    fn test_update_frontmatter_backs_up_files_with_the_same_name() {
+        let dir = std::env::temp_dir().join(format!("krafna-update-same-{}", std::process::id()));
+        let backup_dir = dir.join("backups");
+        let mut rows = Vec::new();
+        let mut notes = Vec::new();
+        for folder in ["a", "b"] {
+            fs::create_dir_all(dir.join(folder)).unwrap();
+            let note = dir.join(folder).join("x.md");
+            fs::write(&note, format!("---\nstatus: {}\n---\n", folder)).unwrap();
+            let mut file = Pod::new_hash();
+            let _ = file.insert("path".to_string(), Pod::String(note.display().to_string()));
+            let mut row = Pod::new_hash();
+            let _ = row.insert("file".to_string(), file);
+            rows.push(row);
+            notes.push(note);
+        }
+
+        let updated = update_frontmatter(
+            &[set("status", FieldValue::String("done".to_string()))],
+            &rows,
+            &UpdateOptions {
+                dry_run: false,
+                backup_dir: Some(backup_dir.clone()),
+            },
+        );
+        let backups: Vec<_> = notes
+            .iter()
+            .map(|note| fs::read_to_string(backup_dir.join(backup_name(note))))
+            .collect();
+        let _ = fs::remove_dir_all(&dir);
+
+        assert_eq!(2, updated.unwrap().len());
+        assert_eq!("---\nstatus: a\n---\n", backups[0].as_ref().unwrap());
+        assert_eq!("---\nstatus: b\n---\n", backups[1].as_ref().unwrap());
+    }
+
+    #[test]
+    fn test_update_frontmatter_restores_written_files_on_error() {
+        let dir =
+            std::env::temp_dir().join(format!("krafna-update-restore-{}", std::process::id()));
+        let backup_dir = dir.join("backups");
+        fs::create_dir_all(&dir).unwrap();
+        let mut rows = Vec::new();
+        for name in ["a.md", "b.md"] {
+            fs::write(dir.join(name), "---\nstatus: open\n---\n").unwrap();
+            let mut file = Pod::new_hash();
+            let _ = file.insert(
+                "path".to_string(),
+                Pod::String(dir.join(name).display().to_string()),
+            );
+            let mut row = Pod::new_hash();
+            let _ = row.insert("file".to_string(), file);
+            rows.push(row);
+        }
+        // b.md can not be backed up, as a folder is in the way
+        fs::create_dir_all(backup_dir.join(backup_name(&dir.join("b.md")))).unwrap();
+
+        let updated = update_frontmatter(
+            &[set("status", FieldValue::String("done".to_string()))],
+            &rows,
+            &UpdateOptions {
+                dry_run: false,
+                backup_dir: Some(backup_dir),
+            },
+        );
+        let a = fs::read_to_string(dir.join("a.md"));
+        let b = fs::read_to_string(dir.join("b.md"));
+        let _ = fs::remove_dir_all(&dir);
+
+        let error = updated.unwrap_err().to_string();
+        assert!(error.starts_with("Could not back up"), "{}", error);
+        assert!(error.ends_with(", no file was changed"), "{}", error);
+        assert_eq!("---\nstatus: open\n---\n", a.unwrap());
+        assert_eq!("---\nstatus: open\n---\n", b.unwrap());
+    }
+
+    #[test]
+    fn test_backup_name_keeps_the_whole_path() {
+        assert_eq!(
+            PathBuf::from("vault/a/x.md"),
+            backup_name(Path::new("/vault/a/x.md"))
+        );
+        assert_ne!(
+            backup_name(Path::new("/vault/a/x.md")),
+            backup_name(Path::new("/vault/b/x.md"))
+        );
+    }
+
+    #[test]
+    fn test_update_frontmatter_rejects_nested_fields() {
+        let assignments = [set("file.name", FieldValue::Null)];
+
+        assert!(update_frontmatter(&assignments, &[], &UpdateOptions::default()).is_err());
+    }
+
+    #[test]
+    fn test_set_tasks_checked_flips_markers() {
+        let content = "---\ntodo:\n  - [ ] not a task\n---\n- [ ] a\r\n  - [ ] b\r\n  - [x] c\r\n";
+        let ords = ["1.1".to_string(), "1.2".to_string()];
+
+        let (checked, changes) = set_tasks_checked(content, &ords, true);
+        let (unchecked, _) = set_tasks_checked(&checked, &ords[1..], false);
+
+        assert_eq!(
+            "---\ntodo:\n  - [ ] not a task\n---\n- [ ] a\r\n  - [x] b\r\n  - [x] c\r\n",
+            checked
+        );
+        assert_eq!(
+            vec![LineChange {
+                line: 6,
+                removed: vec!["  - [ ] b".to_string()],
+                added: vec!["  - [x] b".to_string()],
+            }],
+            changes
+        );
+        assert_eq!(
+            "---\ntodo:\n  - [ ] not a task\n---\n- [ ] a\r\n  - [x] b\r\n  - [ ] c\r\n",
+            unchecked
+        );
+    }
+
+    #[test]
+    fn test_update_tasks_sets_only_checked() {
+        let assignments = [set("text", FieldValue::String("a".to_string()))];
+
+        assert_eq!(
+            "Only checked = true or checked = false can be set on tasks",
+            update_tasks(&assignments, &[], &UpdateOptions::default())
+                .unwrap_err()
+                .to_string()
+        );
+    }
+}
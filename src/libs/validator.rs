@@ -0,0 +1,244 @@
+use std::collections::BTreeSet;
+use std::fmt::Display;
+
+use crate::libs::aggregate::Aggregate;
+use crate::libs::data_fetcher::pod::Pod;
+use crate::libs::parser::{ExpressionElement, Function, FunctionArg, Query, SELECT_ALL};
+use crate::libs::suggestion::closest_match;
+
+/// Field referenced by a query that none of the fetched rows has, most likely a typo.
+#[derive(Debug, PartialEq, Clone)]
+pub struct FieldWarning {
+    pub field: String,
+    /// Closest known field, if there is one that is close enough
+    pub suggestion: Option<String>,
+}
+
+impl Display for FieldWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Unknown field '{}'", self.field)?;
+        if let Some(suggestion) = &self.suggestion {
+            write!(f, ", did you mean '{}'?", suggestion)?;
+        }
+        Ok(())
+    }
+}
+
+/// Checks the fields referenced in SELECT, WHERE, GROUP BY and ORDER BY of `query` against the fields found
+/// in `data`. Nothing is reported when there is no data, as there is nothing to compare against.
+pub fn validate_fields(query: &Query, data: &[Pod]) -> Vec<FieldWarning> {
+    if data.is_empty() {
+        return Vec::new();
+    }
+
+    let known = known_fields(data);
+    referenced_fields(query)
+        .into_iter()
+        .filter(|field| !known.contains(&without_list_indices(field)))
+        .map(|field| FieldWarning {
+            suggestion: closest_match(&field, known.iter().map(String::as_str)).map(str::to_string),
+            field,
+        })
+        .collect()
+}
+
+/// Field names used in `query`, in order of first appearance.
+pub fn referenced_fields(query: &Query) -> Vec<String> {
+    let mut fields: Vec<String> = query
+        .select_fields
+        .iter()
+        .flat_map(|field| {
+            let field = query.alias(field).unwrap_or(field);
+            match Query::select_expression(field) {
+                Some(expression) => expression_fields(&expression),
+                None => aggregated_field(field).into_iter().collect(),
+            }
+        })
+        .collect();
+    fields.extend(query.select_exclude.iter().cloned());
+    fields.extend(expression_fields(&query.where_expression));
+    for group_by in &query.group_by_fields {
+        match &group_by.function {
+            Some(function) => fields.extend(function_fields(function)),
+            None => fields.push(group_by.field_name.clone()),
+        }
+    }
+    for order_by in &query.order_by_fields {
+        match &order_by.function {
+            Some(function) => fields.extend(function_fields(function)),
+            None => fields.extend(aggregated_field(&order_by.field_name)),
+        }
+    }
+
+    let mut seen = BTreeSet::new();
+    // aliases are columns of the query itself
+    fields.retain(|field| {
+        field != SELECT_ALL && query.alias(field).is_none() && seen.insert(field.clone())
+    });
+    fields
+}
+
+// Fields of a WHERE expression, also the ones in its function arguments
+fn expression_fields(expression: &[ExpressionElement]) -> Vec<String> {
+    expression
+        .iter()
+        .flat_map(|element| match element {
+            ExpressionElement::FieldName(field) => vec![field.clone()],
+            ExpressionElement::Function(function) => function_fields(function),
+            ExpressionElement::Quantified(quantified) => {
+                let mut fields = expression_fields(&quantified.list);
+                fields.extend(expression_fields(&quantified.value));
+                fields
+            }
+            _ => Vec::new(),
+        })
+        .collect()
+}
+
+fn function_fields(function: &Function) -> Vec<String> {
+    function
+        .args
+        .iter()
+        .flat_map(|arg| match arg {
+            FunctionArg::FieldName(field) => vec![field.clone()],
+            FunctionArg::Expression(expression) => expression_fields(expression),
+            _ => Vec::new(),
+        })
+        .collect()
+}
+
+// Field of an aggregate column (none for `COUNT(*)`), other names are fields themselves
+fn aggregated_field(name: &str) -> Option<String> {
+    match name.parse::<Aggregate>() {
+        Ok(aggregate) => aggregate.field_name().map(str::to_string),
+        Err(_) => Some(name.to_string()),
+    }
+}
+
+/// Every (dotted) path that leads to a value in at least one of the rows.
+pub fn known_fields(data: &[Pod]) -> BTreeSet<String> {
+    let mut fields = BTreeSet::new();
+    for pod in data {
+        collect_paths(pod, "", &mut fields);
+    }
+    fields
+}
+
+// List elements share the path of the list, indices are left out
+fn collect_paths(pod: &Pod, prefix: &str, fields: &mut BTreeSet<String>) {
+    match pod {
+        Pod::Hash(hash) => {
+            for (key, value) in hash {
+                let path = if prefix.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{}.{}", prefix, key)
+                };
+                collect_paths(value, &path, fields);
+                fields.insert(path);
+            }
+        }
+        Pod::Array(array) => {
+            for item in array {
+                collect_paths(item, prefix, fields);
+            }
+        }
+        _ => {}
+    }
+}
+
+// `projects[0].status` -> `projects.status`
+fn without_list_indices(field: &str) -> String {
+    let mut result = String::new();
+    let mut in_index = false;
+    for c in field.chars() {
+        match c {
+            '[' => in_index = true,
+            ']' => in_index = false,
+            _ if !in_index => result.push(c),
+            _ => {}
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn note(name: &str, tags: &[&str]) -> Pod {
+        let mut file = Pod::new_hash();
+        let _ = file.insert("name".to_string(), Pod::String(name.to_string()));
+        let mut pod = Pod::new_hash();
+        let _ = pod.insert("file".to_string(), file);
+        let _ = pod.insert(
+            "tags".to_string(),
+            Pod::Array(tags.iter().map(|t| Pod::String(t.to_string())).collect()),
+        );
+        pod
+    }
+
+    #[test]
+    fn test_validate_fields_suggests_closest_field() {
+        let query: Query =
+            "SELECT file.nmae FROM F('x') WHERE 'a' IN tags AND tags[-1] == 'b' AND DATE(crated) > 1 ORDER BY file.name"
+                .parse()
+                .unwrap();
+
+        let warnings = validate_fields(&query, &[note("a", &["a"]), note("b", &[])]);
+
+        assert_eq!(
+            vec![
+                FieldWarning {
+                    field: "file.nmae".to_string(),
+                    suggestion: Some("file.name".to_string()),
+                },
+                FieldWarning {
+                    field: "crated".to_string(),
+                    suggestion: None,
+                },
+            ],
+            warnings
+        );
+        assert_eq!(
+            "Unknown field 'file.nmae', did you mean 'file.name'?",
+            warnings[0].to_string()
+        );
+        assert_eq!("Unknown field 'crated'", warnings[1].to_string());
+    }
+
+    #[test]
+    fn test_validate_fields_of_aggregates() {
+        let query: Query =
+            "SELECT file.name, COUNT(*), MAX(tag) FROM F('x') GROUP BY file.name ORDER BY COUNT(*)"
+                .parse()
+                .unwrap();
+
+        assert_eq!(
+            vec![FieldWarning {
+                field: "tag".to_string(),
+                suggestion: Some("tags".to_string()),
+            }],
+            validate_fields(&query, &[note("a", &["a"])])
+        );
+    }
+
+    #[test]
+    fn test_validate_fields_of_select_expressions() {
+        let query: Query = "SELECT file.name, tags[0] + 1, DATE(crated).year AS year FROM F('x')"
+            .parse()
+            .unwrap();
+
+        assert_eq!(
+            vec!["file.name", "tags[0]", "crated"],
+            referenced_fields(&query)
+        );
+    }
+
+    #[test]
+    fn test_validate_fields_without_data() {
+        let query: Query = "SELECT nope FROM F('x')".parse().unwrap();
+
+        assert!(validate_fields(&query, &[]).is_empty());
+    }
+}
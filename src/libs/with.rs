@@ -0,0 +1,333 @@
+//! `WITH` named queries (common table expressions), e.g. `WITH open AS (SELECT file.path FROM
+//! MD_TASKS('~/vault') WHERE checked != true) SELECT file.path, COUNT(*) FROM open GROUP BY
+//! file.path`. Every named query runs once, in order, and later ones and the main query read its
+//! rows with `FROM <name>`, like from any other source.
+
+use std::collections::HashMap;
+use std::error::Error;
+
+use crate::libs::data_fetcher::pod::Pod;
+use crate::libs::data_fetcher::DataFetcher;
+use crate::libs::lexer::{tokenize, Token, TokenKind};
+use crate::libs::parser::Function;
+use crate::libs::union::Union;
+
+/// Name the combined rows of a UNION are read from by its ORDER BY.
+const UNION_TABLE: &str = "UNION_ROWS";
+
+/// Named queries of a WITH as query text, in order, and the main query after them.
+#[derive(Debug, PartialEq, Clone)]
+pub struct With {
+    pub tables: Vec<(String, String)>,
+    pub query: String,
+}
+
+impl With {
+    /// Splits a query starting with WITH (after its `DEFINE`s) into its named queries and the
+    /// main query, `None` when it does not start with WITH.
+    /// `DEFINE`s in front of WITH are used by all of them.
+    pub fn split(query: &str) -> Result<Option<With>, String> {
+        let tokens = tokenize(query).map_err(|error| error.message)?;
+        let chars: Vec<char> = query.chars().collect();
+        let text = |start: usize, end: usize| chars[start..end].iter().collect::<String>();
+
+        // WITH is the first token of a statement, only DEFINEs can come before it
+        let mut depth = 0;
+        let mut statement_start = true;
+        let mut with = None;
+        for (index, token) in tokens.iter().enumerate() {
+            match token.kind {
+                TokenKind::OpenedBracket => depth += 1,
+                TokenKind::ClosedBracket => depth -= 1,
+                TokenKind::Semicolon if depth == 0 => {
+                    statement_start = true;
+                    continue;
+                }
+                _ if depth == 0 && statement_start && token.is_keyword("WITH") => {
+                    with = Some(index);
+                    break;
+                }
+                _ => {}
+            }
+            statement_start = false;
+        }
+        let Some(with) = with else {
+            return Ok(None);
+        };
+
+        let definitions = text(0, tokens[with].span.start);
+        let mut tables: Vec<(String, String)> = Vec::new();
+        let mut index = with + 1;
+        loop {
+            let name = match tokens.get(index).map(|t| &t.kind) {
+                Some(TokenKind::Identifier(name)) => name.clone(),
+                _ => {
+                    return Err(format!(
+                        "Expected a name after WITH, found {}",
+                        found(&tokens, index)
+                    ))
+                }
+            };
+            if tables
+                .iter()
+                .any(|(table, _)| table.eq_ignore_ascii_case(&name))
+            {
+                return Err(format!("WITH defines {} twice", name));
+            }
+            if !tokens.get(index + 1).is_some_and(|t| t.is_keyword("AS")) {
+                return Err(format!(
+                    "Expected AS after {}, found {}",
+                    name,
+                    found(&tokens, index + 1)
+                ));
+            }
+            if tokens.get(index + 2).map(|t| &t.kind) != Some(&TokenKind::OpenedBracket) {
+                return Err(format!(
+                    "Expected '(' after {} AS, found {}",
+                    name,
+                    found(&tokens, index + 2)
+                ));
+            }
+            let start = index + 3;
+            let mut end = start;
+            let mut depth = 0;
+            loop {
+                match tokens.get(end).map(|t| &t.kind) {
+                    None => return Err(format!("WITH {} is missing its closing ')'", name)),
+                    Some(TokenKind::ClosedBracket) if depth == 0 => break,
+                    Some(TokenKind::ClosedBracket) => depth -= 1,
+                    Some(TokenKind::OpenedBracket) => depth += 1,
+                    _ => {}
+                }
+                end += 1;
+            }
+            if start == end {
+                return Err(format!("WITH {} needs a query in its brackets", name));
+            }
+            let table = text(tokens[start].span.start, tokens[end].span.start);
+            tables.push((name, format!("{}{}", definitions, table.trim())));
+
+            index = end + 1;
+            match tokens.get(index).map(|t| &t.kind) {
+                Some(TokenKind::Comma) => index += 1,
+                Some(_) => break,
+                None => return Err("WITH needs a query after its named queries".to_string()),
+            }
+        }
+
+        Ok(Some(With {
+            tables,
+            query: format!(
+                "{}{}",
+                definitions,
+                text(tokens[index].span.start, chars.len()).trim()
+            ),
+        }))
+    }
+
+    /// Runs `query` with its named queries (when it starts with WITH) and UNIONs, one SELECT at a
+    /// time with `execute`. It gets the SELECT, whether it is (part of) the main query and not a
+    /// named one, and the fetcher for its FROM.
+    pub fn execute_query<F>(
+        query: &str,
+        fetcher: &DataFetcher,
+        execute: F,
+    ) -> Result<(Vec<String>, Vec<Pod>), Box<dyn Error>>
+    where
+        F: Fn(&str, bool, &DataFetcher) -> Result<(Vec<String>, Vec<Pod>), Box<dyn Error>>,
+    {
+        // ORDER BY of a UNION runs as a query over the combined rows
+        let execute = |query: &str, main: bool, fetcher: &DataFetcher| {
+            Union::execute_query(
+                query,
+                |query| execute(query, main, fetcher),
+                |order_by, rows| {
+                    let query = format!("SELECT * FROM {} {}", UNION_TABLE, order_by);
+                    let rows = HashMap::from([(UNION_TABLE.to_string(), rows)]);
+                    let (_, rows) = execute(&query, false, &|from_function| {
+                        fetch(&rows, fetcher, from_function)
+                    })?;
+                    Ok(rows)
+                },
+            )
+        };
+        match With::split(query)? {
+            Some(with) => with.execute(
+                fetcher,
+                |query, fetcher| execute(query, false, fetcher),
+                |query, fetcher| execute(query, true, fetcher),
+            ),
+            None => execute(query, true, fetcher),
+        }
+    }
+
+    /// Runs the named queries in order with `execute_table`, and then the main query with
+    /// `execute_query`. Both get a fetcher that reads `FROM <name>` from the named queries run
+    /// so far, and everything else from `fetcher`.
+    pub fn execute<F, G>(
+        &self,
+        fetcher: &DataFetcher,
+        execute_table: F,
+        execute_query: G,
+    ) -> Result<(Vec<String>, Vec<Pod>), Box<dyn Error>>
+    where
+        F: Fn(&str, &DataFetcher) -> Result<(Vec<String>, Vec<Pod>), Box<dyn Error>>,
+        G: Fn(&str, &DataFetcher) -> Result<(Vec<String>, Vec<Pod>), Box<dyn Error>>,
+    {
+        let mut rows: HashMap<String, Vec<Pod>> = HashMap::new();
+        for (name, query) in &self.tables {
+            let (_, table) =
+                execute_table(query, &|from_function| fetch(&rows, fetcher, from_function))
+                    .map_err(|error| format!("Error in WITH {}: {}", name, error))?;
+            rows.insert(name.to_uppercase(), table);
+        }
+        execute_query(&self.query, &|from_function| {
+            fetch(&rows, fetcher, from_function)
+        })
+    }
+}
+
+// Rows of the named query `FROM <name>` reads, or whatever `fetcher` returns for other sources
+fn fetch(
+    rows: &HashMap<String, Vec<Pod>>,
+    fetcher: &DataFetcher,
+    from_function: &Function,
+) -> Result<Vec<Pod>, Box<dyn Error>> {
+    match rows.get(&from_function.name.to_uppercase()) {
+        Some(rows) if from_function.args.is_empty() => Ok(rows.clone()),
+        _ => fetcher(from_function),
+    }
+}
+
+fn found(tokens: &[Token], index: usize) -> String {
+    tokens
+        .get(index)
+        .map_or("nothing".to_string(), |token| token.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use super::*;
+
+    #[test]
+    fn test_split_with() -> Result<(), String> {
+        assert_eq!(None, With::split("SELECT a FROM F('x') WHERE with")?);
+        assert_eq!(
+            Some(With {
+                tables: vec![
+                    ("open".to_string(), "SELECT a FROM F('x') WHERE (b)".to_string()),
+                    ("done".to_string(), "SELECT a FROM open".to_string()),
+                ],
+                query: "SELECT a FROM done".to_string(),
+            }),
+            With::split("WITH open AS (SELECT a FROM F('x') WHERE (b)),\n  done AS ( SELECT a FROM open ) SELECT a FROM done")?
+        );
+        assert_eq!(
+            Some(With {
+                tables: vec![(
+                    "open".to_string(),
+                    "DEFINE o AS (b); SELECT a FROM F('x') WHERE $o".to_string()
+                )],
+                query: "DEFINE o AS (b); SELECT a FROM open WHERE $o".to_string(),
+            }),
+            With::split("DEFINE o AS (b); with open as (SELECT a FROM F('x') WHERE $o) SELECT a FROM open WHERE $o")?
+        );
+
+        assert_eq!(
+            Err("Expected AS after open, found (".to_string()),
+            With::split("WITH open (SELECT a FROM F('x')) SELECT a FROM open")
+        );
+        assert_eq!(
+            Err("WITH open is missing its closing ')'".to_string()),
+            With::split("WITH open AS (SELECT a FROM F('x') SELECT a FROM open")
+        );
+        assert_eq!(
+            Err("WITH needs a query after its named queries".to_string()),
+            With::split("WITH open AS (SELECT a FROM F('x'))")
+        );
+        assert_eq!(
+            Err("WITH defines OPEN twice".to_string()),
+            With::split("WITH open AS (SELECT a FROM F('x')), OPEN AS (SELECT a FROM F('y')) SELECT a FROM open")
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_with_execute() -> Result<(), Box<dyn Error>> {
+        let row = |value: i64| {
+            let mut row = Pod::new_hash();
+            let _ = row.insert("n".to_string(), Pod::Integer(value));
+            row
+        };
+        // F() has 1, 2 and 3, a named query keeps the rows of its FROM over its number
+        let fetcher = |from_function: &Function| -> Result<Vec<Pod>, Box<dyn Error>> {
+            match from_function.name.as_str() {
+                "F" => Ok((1..=3).map(row).collect()),
+                name => Err(format!("Unknown function: {}", name).into()),
+            }
+        };
+        let execute = |query: &str,
+                       fetcher: &DataFetcher|
+         -> Result<(Vec<String>, Vec<Pod>), Box<dyn Error>> {
+            let (from, min) = query.split_once(' ').unwrap_or((query, "0"));
+            let rows = fetcher(&Function::new(from.to_string(), Vec::new()))?
+                .into_iter()
+                .filter(|row| {
+                    row.nested_get("n").and_then(|n| n.as_i64()) > Some(min.parse().unwrap())
+                })
+                .collect();
+            Ok((vec!["n".to_string()], rows))
+        };
+        let with = |tables: &[(&str, &str)], query: &str| With {
+            tables: tables
+                .iter()
+                .map(|(name, query)| (name.to_string(), query.to_string()))
+                .collect(),
+            query: query.to_string(),
+        };
+
+        let (_, rows) = with(&[("big", "F 1"), ("bigger", "BIG 2")], "bigger")
+            .execute(&fetcher, execute, execute)?;
+        assert_eq!(vec![row(3)], rows);
+        let (_, rows) = with(&[("big", "F 1")], "F").execute(&fetcher, execute, execute)?;
+        assert_eq!(3, rows.len());
+        assert_eq!(
+            "Error in WITH bigger: Unknown function: small",
+            with(&[("big", "F 1"), ("bigger", "small")], "bigger")
+                .execute(&fetcher, execute, execute)
+                .unwrap_err()
+                .to_string()
+        );
+
+        // The main query is run a SELECT at a time, also when it is a UNION
+        let runs = RefCell::new(Vec::new());
+        let (_, rows) = With::execute_query(
+            "WITH big AS (F 1) big UNION ALL F 2",
+            &fetcher,
+            |query, main, fetcher| {
+                runs.borrow_mut().push((query.to_string(), main));
+                execute(query, fetcher)
+            },
+        )?;
+        assert_eq!(vec![row(2), row(3), row(3)], rows);
+        assert_eq!(
+            vec![
+                ("F 1".to_string(), false),
+                ("big".to_string(), true),
+                ("F 2".to_string(), true)
+            ],
+            runs.take()
+        );
+        let (_, rows) = With::execute_query("F 2", &fetcher, |query, main, fetcher| {
+            runs.borrow_mut().push((query.to_string(), main));
+            execute(query, fetcher)
+        })?;
+        assert_eq!(vec![row(3)], rows);
+        assert_eq!(vec![("F 2".to_string(), true)], runs.take());
+
+        Ok(())
+    }
+}
@@ -1,30 +1,59 @@
-use std::error::Error;
+use std::collections::HashMap;
+use std::fs;
+use std::io::{IsTerminal, Read};
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::Duration;
 
-use clap::{Parser, ValueHint};
+use clap::{Parser, Subcommand, ValueHint};
+use notify::{Event, RecursiveMode, Watcher};
+use tracing_subscriber::fmt::format::FmtSpan;
+use tracing_subscriber::EnvFilter;
 
-use krafna::libs::data_fetcher::markdown_fetcher::fetch_code_snippets;
-use krafna::libs::executor::execute_query;
-use krafna::libs::serializer::{pods_to_json, pods_to_tsv};
+use krafna::libs::data_fetcher::markdown_fetcher::{
+    fetch_code_snippets, markdown_roots, source_path_arguments,
+};
+use krafna::libs::data_fetcher::pod::Pod;
+use krafna::libs::dataview::{translate_dataview, Dialect};
+use krafna::libs::diff::{
+    diff_results, load_snapshot, save_snapshot, snapshot_path, DIFF_KEY_FIELDS,
+};
+use krafna::libs::executor::{pivot, rollup_count};
+use krafna::libs::serializer::{
+    diff_to_json, diff_to_tsv, pods_to_chart, pods_to_graph, pods_to_json, pods_to_list,
+    pods_to_markdown_table, pods_to_nuon, pods_to_parquet, pods_to_tree, pods_to_tree_json,
+    pods_to_tsv, ChartKind, GraphFormat, View, GRAPH_FIELDS, TREE_FIELDS,
+};
+use krafna::libs::update::default_backup_dir;
+use krafna::libs::{
+    strip_explain, ConfigFile, FieldValue, Profile, ProgressEvent, Query, Union, Update,
+    UpdateOptions, With,
+};
+use krafna::{CancellationToken, Krafna, KrafnaConfig};
 
-#[derive(Parser, Debug)]
+#[derive(Parser, Debug, Clone)]
 #[command(name = "krafna")]
 #[command(about = "Obsidian `dataview` alternative.", long_about = None)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// The query to execute
     #[arg(value_hint = ValueHint::Other)]
     query: Option<String>,
 
     /// OVERRIDES SELECT fields with "field1,field2"
-    #[arg(long)]
+    #[arg(long, global = true)]
     select: Option<String>,
 
     /// From option in case you are implementing querying for specific FROM that you don't want to
     /// specify every time. This OVERRIDES the FROM part of the query!
-    #[arg(long, value_hint = ValueHint::Other)]
+    #[arg(long, global = true, value_hint = ValueHint::Other)]
     from: Option<String>,
 
     /// include SELECT fields with "field1,field2"
-    #[arg(long)]
+    #[arg(long, global = true)]
     include_fields: Option<String>,
 
     /// Find option to find all krafna snippets within a dir
@@ -32,56 +61,840 @@ struct Args {
     find: Option<String>,
 
     /// Output results in JSON format
-    #[arg(long)]
+    #[arg(long, global = true)]
     json: bool,
+
+    /// Output results as NUON, records with typed values (dates too) for Nushell's `from nuon`
+    #[arg(long, global = true, conflicts_with_all = ["json", "graph", "tree", "diff"])]
+    nuon: bool,
+
+    /// Output the links of an MD_LINKS query as a note graph: dot, graphml or json
+    #[arg(long, global = true, value_name = "FORMAT", conflicts_with = "json")]
+    graph: Option<GraphFormat>,
+
+    /// Output the tasks of an MD_TASKS query as an indented hierarchy (nested `children` with
+    /// --json)
+    #[arg(long, global = true, conflicts_with = "graph")]
+    tree: bool,
+
+    /// Output the number of results in every folder, followed by the total (NULL folder)
+    #[arg(long, global = true, conflicts_with_all = ["graph", "tree"])]
+    group_by_folder: bool,
+
+    /// Output only the rows added (+), removed (-) or changed (~) since the last run of the query
+    #[arg(long, global = true, conflicts_with_all = ["graph", "tree", "group_by_folder", "watch"])]
+    diff: bool,
+
+    /// Output the last two columns as a matrix: values of the second to last column become
+    /// columns holding the last one, e.g. counts of `GROUP BY file.folder, status` per status
+    #[arg(long, global = true, conflicts_with_all = ["graph", "tree", "group_by_folder", "diff"])]
+    pivot: bool,
+
+    /// Output a unicode chart of the (only) numeric column of the results: bar (next to every
+    /// row) or spark (a sparkline under them)
+    #[arg(long, global = true, value_name = "KIND", conflicts_with_all = ["json", "nuon", "graph", "tree", "diff"])]
+    chart: Option<ChartKind>,
+
+    /// Show the results the way dataview does: table (markdown), list (bullets) or task (checkbox
+    /// lines under their file), the same as starting the query with TABLE, LIST or TASK
+    #[arg(long, global = true, value_name = "VIEW", conflicts_with_all = ["json", "nuon", "graph", "tree", "chart", "diff"])]
+    view: Option<View>,
+
+    /// Write the results to the given Parquet file instead of printing them, e.g. for pandas or
+    /// polars (needs krafna built with the `parquet` feature)
+    #[arg(long, global = true, value_name = "FILE", value_hint = ValueHint::FilePath, conflicts_with_all = ["json", "nuon", "graph", "tree", "diff", "chart", "view"])]
+    parquet: Option<PathBuf>,
+
+    /// Abort the query if it runs longer than the given number of seconds
+    #[arg(long, global = true)]
+    timeout: Option<f64>,
+
+    /// Show progress of parsing markdown files on stderr
+    #[arg(long, global = true)]
+    progress: bool,
+
+    /// Print the time and rows of every stage of the query (walk, cache load, parse, WHERE, ORDER
+    /// BY, SELECT) and the slowest files to parse on stderr
+    #[arg(long, global = true, conflicts_with = "watch")]
+    profile: bool,
+
+    /// Warn on stderr about queried fields that none of the fetched rows has (e.g. typos)
+    #[arg(long, global = true)]
+    check_fields: bool,
+
+    /// Fail when a markdown file can not be parsed, instead of leaving it out with a warning
+    #[arg(long, global = true)]
+    strict: bool,
+
+    /// Skip (with a warning) markdown files bigger than the given number of megabytes, 0 for no
+    /// limit
+    #[arg(long, global = true, default_value_t = 10)]
+    max_file_size: u64,
+
+    /// Number of threads used to read markdown files, defaults to one per core
+    #[arg(long, global = true)]
+    threads: Option<usize>,
+
+    /// Keep running and print the results again whenever markdown files of FROM change
+    #[arg(long, global = true)]
+    watch: bool,
+
+    /// Print the changes an UPDATE statement would make, without writing any file
+    #[arg(long, global = true)]
+    dry_run: bool,
+
+    /// Language of the query (and of the snippets --find looks for): krafna or dataview (DQL,
+    /// e.g. `TABLE status FROM #project WHERE due`, translated to krafna, see `krafna translate`)
+    #[arg(long, global = true, value_name = "DIALECT", default_value = "krafna")]
+    dialect: Dialect,
+
+    /// Directory `FROM #tag` queries read and the folders of dataview queries are in (default:
+    /// `vault` of the config file, or the current directory)
+    #[arg(long, global = true, value_hint = ValueHint::DirPath)]
+    vault: Option<String>,
+
+    /// YAML config file to use instead of config.yaml in the OS config dir
+    #[arg(long, global = true, value_hint = ValueHint::FilePath)]
+    config: Option<PathBuf>,
 }
 
-fn main() -> Result<(), Box<dyn Error>> {
-    let args = Args::parse();
+#[derive(Subcommand, Debug, Clone)]
+enum Command {
+    /// Print a canonical, indented version of the query
+    Fmt {
+        /// The query to format, read from stdin when omitted
+        #[arg(value_hint = ValueHint::Other)]
+        query: Option<String>,
+    },
+    /// Print the krafna query doing what a dataview (DQL) query does, folders are read from below
+    /// --vault
+    Translate {
+        /// The dataview query to translate, read from stdin when omitted
+        #[arg(value_hint = ValueHint::Other)]
+        query: Option<String>,
+    },
+    /// Run a query saved under `queries` in the config file, list the saved queries without NAME
+    Run {
+        /// Name of the saved query
+        name: Option<String>,
 
-    match args.query {
-        Some(query) => do_query(
-            &query,
-            args.select,
-            args.from,
-            args.include_fields,
-            args.json,
-        ),
+        /// Value of a `:name` parameter of the query, e.g. `--param vault=~/notes`
+        #[arg(long = "param", short, value_name = "NAME=VALUE", value_parser = parse_param)]
+        params: Vec<(String, FieldValue)>,
+    },
+}
+
+fn main() -> ExitCode {
+    // RUST_LOG=krafna=debug shows the time spent in every stage of the query
+    tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::from_default_env())
+        .with_span_events(FmtSpan::CLOSE)
+        .with_writer(std::io::stderr)
+        .init();
+
+    let mut args = Args::parse();
+
+    match &args.command {
+        Some(Command::Fmt { query }) => return format_query(query.clone()),
+        Some(Command::Translate { query }) => return translate_query(query.clone(), &vault(&args)),
+        Some(Command::Run { name, params }) => {
+            return run_saved_query(name.as_deref(), params, &args)
+        }
+        None => {}
+    }
+
+    let query = match (args.query.clone(), args.dialect) {
+        (Some(dql), Dialect::Dataview) => match translate_dataview(&dql, &vault(&args)) {
+            Ok(query) => {
+                // Results of dataview's TABLE, LIST and TASK look the same as in Obsidian
+                args.view = args.view.or(View::split_keyword(&dql).0);
+                Some(query)
+            }
+            Err(error) => {
+                eprintln!("Error: {}", error);
+                return ExitCode::FAILURE;
+            }
+        },
+        (query, _) => query,
+    };
+    match &query {
+        Some(query) if Update::is_update(query) => return do_update(query, &args),
+        Some(query) => return do_query(query, &args),
         None => {
-            if let Some(find) = args.find {
-                find_files(&find, args.json);
+            if let Some(find) = &args.find {
+                find_files(find, args.dialect, args.json);
             } else {
                 print_help();
             }
         }
     }
 
-    Ok(())
+    ExitCode::SUCCESS
+}
+
+fn run_saved_query(name: Option<&str>, params: &[(String, FieldValue)], args: &Args) -> ExitCode {
+    let config_file = match load_config_file(args) {
+        Ok(config_file) => config_file,
+        Err(error) => {
+            eprintln!("Error: {}", error);
+            return ExitCode::FAILURE;
+        }
+    };
+    let Some(name) = name else {
+        for (name, query) in &config_file.queries {
+            println!("{}\t{}", name, query);
+        }
+        return ExitCode::SUCCESS;
+    };
+
+    let query = config_file
+        .query(name)
+        .and_then(|query| bind_params(query, params));
+    match query {
+        Ok(query) if Update::is_update(&query) => do_update(&query, args),
+        Ok(query) => do_query(&query, args),
+        Err(error) => {
+            eprintln!("Error: {}", error);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+// Parameters are bound into the query text, so every mode (--watch, --diff, ...) can run it
+fn bind_params(query: &str, params: &[(String, FieldValue)]) -> Result<String, String> {
+    if Update::is_update(query) {
+        return match params.is_empty() {
+            true => Ok(query.to_string()),
+            false => Err("Parameters are only supported in SELECT queries".to_string()),
+        };
+    }
+    let parsed: Query = query.parse()?;
+    let parameters = parsed.parameters();
+    if parameters.is_empty() && params.is_empty() {
+        return Ok(query.to_string());
+    }
+    if let Some((name, _)) = params.iter().find(|(name, _)| !parameters.contains(name)) {
+        return Err(format!(
+            "Unknown parameter :{}, the query has: {}",
+            name,
+            parameters
+                .iter()
+                .map(|name| format!(":{}", name))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ));
+    }
+    Ok(parsed
+        .bind(&params.iter().cloned().collect::<HashMap<_, _>>())?
+        .to_string())
+}
+
+// `name=value`, values that look like numbers or booleans are ones
+fn parse_param(param: &str) -> Result<(String, FieldValue), String> {
+    let (name, value) = param
+        .split_once('=')
+        .ok_or("expected NAME=VALUE, e.g. vault=~/notes")?;
+    let value = match value {
+        "true" => FieldValue::Bool(true),
+        "false" => FieldValue::Bool(false),
+        _ => match (value.parse::<i64>(), value.parse::<f64>()) {
+            (Ok(integer), _) => FieldValue::Integer(integer),
+            (_, Ok(number)) => FieldValue::Float(number),
+            _ => FieldValue::String(value.to_string()),
+        },
+    };
+    Ok((name.trim_start_matches(':').to_string(), value))
+}
+
+fn load_config_file(args: &Args) -> Result<ConfigFile, Box<dyn std::error::Error>> {
+    match &args.config {
+        Some(path) => ConfigFile::load(path),
+        None => match ConfigFile::default_path().filter(|path| path.is_file()) {
+            Some(path) => ConfigFile::load(&path),
+            None => Ok(ConfigFile::default()),
+        },
+    }
+}
+
+// --vault, `vault` of the config file or the current directory
+fn vault(args: &Args) -> String {
+    args.vault
+        .clone()
+        .or_else(|| load_config_file(args).ok()?.vault)
+        .unwrap_or_else(|| ".".to_string())
+}
+
+// The config file is optional, unless it is given with --config
+fn engine_config(args: &Args) -> Result<KrafnaConfig, Box<dyn std::error::Error>> {
+    let config = KrafnaConfig {
+        strict: args.strict,
+        max_file_size: match args.max_file_size {
+            0 => None,
+            megabytes => Some(megabytes.saturating_mul(1024 * 1024)),
+        },
+        threads: args.threads,
+        vault: args.vault.clone(),
+        // One query per run, there is nothing to reuse results for
+        result_cache_size: 0,
+        ..KrafnaConfig::default()
+    };
+    Ok(load_config_file(args)?.apply(config))
+}
+
+// Fails when the query does, so scripts (e.g. CI with --strict) can check the exit status
+fn do_query(query: &str, args: &Args) -> ExitCode {
+    // A leading TABLE, LIST or TASK picks the view, unless --view is given
+    let (view, query) = View::split_keyword(query);
+    let query = query.as_str();
+    let args = &Args {
+        view: args.view.or(view),
+        ..args.clone()
+    };
+    let include_fields = include_fields(args);
+    let cancellation = match args.timeout {
+        Some(seconds) => match Duration::try_from_secs_f64(seconds) {
+            Ok(duration) => CancellationToken::with_timeout(duration),
+            Err(error) => {
+                eprintln!("Error: Invalid timeout {}: {}", seconds, error);
+                return ExitCode::FAILURE;
+            }
+        },
+        None => CancellationToken::new(),
+    };
+
+    let mut engine = match engine_config(args) {
+        Ok(config) => Krafna::with_config(config),
+        Err(error) => {
+            eprintln!("Error: {}", error);
+            return ExitCode::FAILURE;
+        }
+    };
+    let profile = Arc::new(Mutex::new(Profile::default()));
+    if args.progress || args.profile {
+        let (show_progress, profile) = (args.progress, Arc::clone(&profile));
+        engine.on_progress(move |event| match event {
+            ProgressEvent::FileParsed { parsed, total } if show_progress => {
+                eprint!("\rParsing markdown files: {}/{}", parsed, total);
+                if parsed == total {
+                    eprintln!();
+                }
+            }
+            ProgressEvent::StageFinished { .. } | ProgressEvent::FileParseTime { .. } => {
+                if let Ok(mut profile) = profile.lock() {
+                    profile.record(event);
+                }
+            }
+            _ => {}
+        });
+    }
+
+    // EXPLAIN prints how the query would run instead of running it
+    if strip_explain(query).is_some() {
+        return match engine.plan_with(
+            query,
+            args.select.clone(),
+            args.from.clone(),
+            include_fields,
+        ) {
+            Ok(plan) => {
+                println!("{}", plan);
+                ExitCode::SUCCESS
+            }
+            Err(error) => {
+                eprintln!("Error: {}", error);
+                ExitCode::FAILURE
+            }
+        };
+    }
+
+    if args.check_fields {
+        match engine.check_fields(
+            query,
+            args.select.clone(),
+            args.from.clone(),
+            include_fields.clone(),
+        ) {
+            Ok(warnings) => {
+                for warning in warnings {
+                    eprintln!("Warning: {}", warning);
+                }
+            }
+            Err(error) => {
+                eprintln!("Error: {}", error);
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+
+    if args.watch {
+        return watch_query(query, args, include_fields, &engine);
+    }
+
+    let results = engine.execute_query_with_cancellation(
+        query,
+        args.select.clone(),
+        args.from.clone(),
+        include_fields,
+        &cancellation,
+    );
+    let exit_code = match results {
+        Ok(results) if args.diff => {
+            let exit_code = print_diff(&engine, query, args, results);
+            print_file_errors(&engine);
+            exit_code
+        }
+        Ok(results) => {
+            let exit_code = print_results(results, args);
+            print_file_errors(&engine);
+            exit_code
+        }
+        Err(error) => {
+            eprintln!("Error: {}", error);
+            ExitCode::FAILURE
+        }
+    };
+    if args.profile {
+        if let Ok(profile) = profile.lock() {
+            eprintln!("{}", profile);
+        }
+    }
+    exit_code
 }
 
-fn do_query(
+// Files are backed up to the cache dir before they are written
+fn do_update(statement: &str, args: &Args) -> ExitCode {
+    let engine = match engine_config(args) {
+        Ok(config) => Krafna::with_config(config),
+        Err(error) => {
+            eprintln!("Error: {}", error);
+            return ExitCode::FAILURE;
+        }
+    };
+    let backup_dir = match default_backup_dir() {
+        Ok(backup_dir) => backup_dir,
+        Err(error) => {
+            eprintln!("Error: Could not create a directory for backups: {}", error);
+            return ExitCode::FAILURE;
+        }
+    };
+    let options = UpdateOptions {
+        dry_run: args.dry_run,
+        backup_dir: Some(backup_dir.clone()),
+    };
+
+    match engine.execute_update(statement, &options) {
+        Ok(updates) => {
+            let hunks: Vec<String> = updates.iter().map(|update| update.to_string()).collect();
+            if !hunks.is_empty() {
+                println!("{}", hunks.join("\n\n"));
+            }
+            if args.dry_run {
+                eprintln!("Would update {} file(s)", updates.len());
+            } else if updates.is_empty() {
+                eprintln!("Updated 0 file(s)");
+            } else {
+                eprintln!(
+                    "Updated {} file(s), originals are in {}",
+                    updates.len(),
+                    backup_dir.display()
+                );
+            }
+            print_file_errors(&engine);
+            ExitCode::SUCCESS
+        }
+        Err(error) => {
+            eprintln!("Error: {}", error);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+// Changes of a single save usually come as several events, they are handled together
+const WATCH_DEBOUNCE: Duration = Duration::from_millis(100);
+
+// Only the changed files are parsed and filtered again on every change, see `LiveQuery`
+fn watch_query(
     query: &str,
-    select_fields: Option<String>,
-    from: Option<String>,
+    args: &Args,
     include_fields: Option<String>,
-    to_json: bool,
-) {
-    match execute_query(query, select_fields, from, include_fields) {
-        Ok((fields, res)) => {
-            if to_json {
-                let json = pods_to_json(fields, res);
-                println!("{}", json);
-            } else {
-                let tsv = pods_to_tsv(fields, res);
-                println!("{}", tsv);
+    engine: &Krafna,
+) -> ExitCode {
+    let mut live_query = match engine.live_query(
+        query,
+        args.select.clone(),
+        args.from.clone(),
+        include_fields,
+    ) {
+        Ok(live_query) => live_query,
+        Err(error) => {
+            eprintln!("Error: {}", error);
+            return ExitCode::FAILURE;
+        }
+    };
+    let dirs = match live_query
+        .query()
+        .from_function
+        .as_ref()
+        .map(|from_function| {
+            markdown_roots(source_path_arguments(
+                &from_function.name,
+                &from_function.args,
+            ))
+        }) {
+        Some(Ok(dirs)) => dirs,
+        Some(Err(error)) => {
+            eprintln!("Error: {}", error);
+            return ExitCode::FAILURE;
+        }
+        None => {
+            eprintln!("Error: Query is missing FROM!");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let (sender, receiver) = mpsc::channel();
+    let watcher = notify::recommended_watcher(sender).and_then(|mut watcher| {
+        for dir in &dirs {
+            watcher.watch(dir, RecursiveMode::Recursive)?;
+        }
+        Ok(watcher)
+    });
+    let _watcher = match watcher {
+        Ok(watcher) => watcher,
+        Err(error) => {
+            let dirs: Vec<String> = dirs.iter().map(|dir| dir.display().to_string()).collect();
+            eprintln!("Error: Could not watch {}: {}", dirs.join(", "), error);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    if print_results(live_query.results(), args) == ExitCode::FAILURE {
+        return ExitCode::FAILURE;
+    }
+    print_file_errors(engine);
+    while let Ok(event) = receiver.recv() {
+        let mut changed = changed_paths(event);
+        while let Ok(event) = receiver.recv_timeout(WATCH_DEBOUNCE) {
+            changed.extend(changed_paths(event));
+        }
+        if changed.is_empty() {
+            continue;
+        }
+
+        match live_query.update(&changed) {
+            Ok(()) => {
+                if std::io::stdout().is_terminal() {
+                    print!("\x1b[2J\x1b[H");
+                }
+                print_results(live_query.results(), args);
+                print_file_errors(engine);
+            }
+            Err(error) => eprintln!("Error: {}", error),
+        }
+    }
+
+    ExitCode::SUCCESS
+}
+
+fn changed_paths(event: notify::Result<Event>) -> Vec<PathBuf> {
+    match event {
+        // Reading files is reported too, parsing them must not trigger another update
+        Ok(event) if event.kind.is_access() => Vec::new(),
+        Ok(event) => event.paths,
+        Err(error) => {
+            eprintln!("Warning: {}", error);
+            Vec::new()
+        }
+    }
+}
+
+// The snapshot of the previous run is replaced with the current results
+fn print_diff(
+    engine: &Krafna,
+    query: &str,
+    args: &Args,
+    (fields, res): (Vec<String>, Vec<Pod>),
+) -> ExitCode {
+    let snapshot = snapshot_key(engine, query, args).and_then(|key| snapshot_path(&key));
+    let printed = hide_diff_keys(engine, query, args, fields);
+    let (snapshot, fields) = match (snapshot, printed) {
+        (Ok(snapshot), Ok(fields)) => (snapshot, fields),
+        (Err(error), _) | (_, Err(error)) => {
+            eprintln!("Error: {}", error);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let previous = load_snapshot(&snapshot).unwrap_or_default();
+    let diff = diff_results(&previous, &res);
+    if args.json {
+        println!("{}", diff_to_json(fields, diff));
+    } else {
+        println!("{}", diff_to_tsv(fields, diff));
+    }
+
+    match save_snapshot(&snapshot, &res) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("Error: Could not save the results for --diff: {}", error);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+// Every SELECT of the statement (named queries of WITH, queries of a UNION) prepared like when
+// the statement runs, in the order they run, with whether it is (part of) the main query.
+// Nothing is fetched.
+fn prepared_parts(
+    engine: &Krafna,
+    query: &str,
+    args: &Args,
+    include_fields: Option<String>,
+) -> Result<Vec<(bool, Query)>, Box<dyn std::error::Error>> {
+    let parts = Mutex::new(Vec::new());
+    With::execute_query(query, &|_| Ok(Vec::new()), |query, main, _| {
+        let prepared = match main {
+            true => engine.prepare(
+                query,
+                args.select.clone(),
+                args.from.clone(),
+                include_fields.clone(),
+            ),
+            false => engine.prepare(query, None, None, None),
+        }?;
+        if let Ok(mut parts) = parts.lock() {
+            parts.push((main, prepared.query().clone()));
+        }
+        Ok((Vec::new(), Vec::new()))
+    })?;
+    Ok(parts.into_inner().unwrap_or_default())
+}
+
+fn snapshot_key(
+    engine: &Krafna,
+    query: &str,
+    args: &Args,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let parts = prepared_parts(engine, query, args, include_fields(args))?;
+    let parts: Vec<String> = parts.iter().map(|(_, query)| query.to_string()).collect();
+    Ok(parts.join("\n"))
+}
+
+// DIFF_KEY_FIELDS are only added to match rows, they are printed when the query selects them
+// (`ord` with `*` as well, `file.path` is in the `file` column of `*`)
+fn hide_diff_keys(
+    engine: &Krafna,
+    query: &str,
+    args: &Args,
+    fields: Vec<String>,
+) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let parts = prepared_parts(engine, query, args, args.include_fields.clone())?;
+    let selected = parts
+        .iter()
+        .find(|(main, _)| *main)
+        .map(|(_, query)| query.select_fields.clone())
+        .unwrap_or_default();
+    let is_selected = |field: &str| {
+        selected.iter().any(|selected| selected == field)
+            || (!field.contains('.') && selected.iter().any(|selected| selected == "*"))
+    };
+    Ok(fields
+        .into_iter()
+        .filter(|field| !DIFF_KEY_FIELDS.contains(&field.as_str()) || is_selected(field))
+        .collect())
+}
+
+const FOLDER_FIELD: &str = "file.folder";
+
+// Graph edges, task trees, folder counts and diffs need some fields, whatever the query selects
+fn include_fields(args: &Args) -> Option<String> {
+    let required: &[&str] = if args.graph.is_some() {
+        &GRAPH_FIELDS
+    } else if args.tree || args.view == Some(View::Task) {
+        &TREE_FIELDS
+    } else if args.group_by_folder {
+        &[FOLDER_FIELD]
+    } else if args.diff {
+        &DIFF_KEY_FIELDS
+    } else {
+        return args.include_fields.clone();
+    };
+    let mut fields: Vec<&str> = args.include_fields.iter().map(String::as_str).collect();
+    fields.extend(required);
+    Some(fields.join(","))
+}
+
+fn print_results((fields, res): (Vec<String>, Vec<Pod>), args: &Args) -> ExitCode {
+    let (fields, res) = match (args.group_by_folder, args.pivot) {
+        (true, _) => rollup_count(FOLDER_FIELD, &res),
+        (_, true) => match pivot(&fields, &res) {
+            Ok(pivoted) => pivoted,
+            Err(error) => {
+                eprintln!("Error: {}", error);
+                return ExitCode::FAILURE;
+            }
+        },
+        _ => (fields, res),
+    };
+    if let Some(format) = args.graph {
+        println!("{}", pods_to_graph(&res, format));
+    } else if args.tree {
+        let tree = match args.json {
+            true => pods_to_tree_json(fields, res),
+            false => pods_to_tree(res),
+        };
+        match tree {
+            Ok(tree) => println!("{}", tree),
+            Err(error) => {
+                eprintln!("Error: {}", error);
+                return ExitCode::FAILURE;
+            }
+        }
+    } else if args.json {
+        let json = pods_to_json(fields, res);
+        println!("{}", json);
+    } else if args.nuon {
+        println!("{}", pods_to_nuon(fields, res));
+    } else if let Some(path) = &args.parquet {
+        let mut parquet = Vec::new();
+        let written = pods_to_parquet(fields, res, &mut parquet).and_then(|_| {
+            fs::write(path, parquet)
+                .map_err(|error| format!("Could not write {}: {}", path.display(), error))
+        });
+        if let Err(error) = written {
+            eprintln!("Error: {}", error);
+            return ExitCode::FAILURE;
+        }
+    } else if let Some(chart) = args.chart {
+        match pods_to_chart(fields, res, chart) {
+            Ok(chart) => println!("{}", chart),
+            Err(error) => {
+                eprintln!("Error: {}", error);
+                return ExitCode::FAILURE;
+            }
+        }
+    } else if let Some(view) = args.view {
+        let text = match view {
+            View::Table => Ok(pods_to_markdown_table(fields, res)),
+            View::List => Ok(pods_to_list(fields, res)),
+            View::Task => pods_to_tree(res),
+        };
+        match text {
+            Ok(text) => println!("{}", text),
+            Err(error) => {
+                eprintln!("Error: {}", error);
+                return ExitCode::FAILURE;
+            }
+        }
+    } else {
+        let tsv = pods_to_tsv(fields, res);
+        println!("{}", tsv);
+    }
+    ExitCode::SUCCESS
+}
+
+fn print_file_errors(engine: &Krafna) {
+    let errors = engine.errors();
+    if errors.is_empty() {
+        return;
+    }
+    eprintln!(
+        "Warning: {} file(s) could not be parsed and were skipped:",
+        errors.len()
+    );
+    for error in errors {
+        eprintln!("  {}", error);
+    }
+}
+
+fn format_query(query: Option<String>) -> ExitCode {
+    let Some(query) = query_or_stdin(query) else {
+        return ExitCode::FAILURE;
+    };
+
+    match format_statement(&query) {
+        Ok(query) => {
+            println!("{}", query);
+            ExitCode::SUCCESS
+        }
+        Err(error) => {
+            eprintln!("Error: {}", error);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+// Every query of EXPLAIN, WITH and UNION statements formatted on its own, UPDATE on one line
+fn format_statement(statement: &str) -> Result<String, Box<dyn std::error::Error>> {
+    if let Some(statement) = strip_explain(statement) {
+        return Ok(format!("EXPLAIN\n{}", format_statement(statement)?));
+    }
+    if Update::is_update(statement) {
+        return Ok(statement.parse::<Update>()?.to_string());
+    }
+    if let Some(with) = With::split(statement)? {
+        let mut tables = Vec::new();
+        for (name, table) in &with.tables {
+            let table = format_statement(table)?.replace('\n', "\n  ");
+            tables.push(format!("{} AS (\n  {}\n)", name, table));
+        }
+        return Ok(format!(
+            "WITH {}\n{}",
+            tables.join(",\n"),
+            format_statement(&with.query)?
+        ));
+    }
+    if let Some(union) = Union::split(statement)? {
+        let mut parts = vec![union.first.parse::<Query>()?.to_pretty_string()];
+        for (all, query) in &union.rest {
+            parts.push(if *all { "UNION ALL" } else { "UNION" }.to_string());
+            parts.push(query.parse::<Query>()?.to_pretty_string());
+        }
+        // Only the ORDER BY, LIMIT and OFFSET clauses are printed
+        if let Some(order_by) = &union.order_by {
+            let mut query: Query = format!("SELECT * FROM UNION_ROWS {}", order_by).parse()?;
+            query.select_fields.clear();
+            query.from_function = None;
+            parts.push(query.to_pretty_string());
+        }
+        return Ok(parts.join("\n"));
+    }
+    Ok(statement.parse::<Query>()?.to_pretty_string())
+}
+
+fn translate_query(query: Option<String>, vault: &str) -> ExitCode {
+    let Some(query) = query_or_stdin(query) else {
+        return ExitCode::FAILURE;
+    };
+
+    match translate_dataview(&query, vault) {
+        Ok(query) => {
+            println!("{}", query);
+            ExitCode::SUCCESS
+        }
+        Err(error) => {
+            eprintln!("Error: {}", error);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn query_or_stdin(query: Option<String>) -> Option<String> {
+    match query {
+        Some(query) => Some(query),
+        None => {
+            let mut query = String::new();
+            if let Err(error) = std::io::stdin().read_to_string(&mut query) {
+                eprintln!("Error: {}", error);
+                return None;
             }
+            Some(query)
         }
-        Err(error) => eprintln!("Error: {}", error),
     }
 }
 
-fn find_files(dir: &String, to_json: bool) {
-    match fetch_code_snippets(dir, "krafna".to_string()) {
+fn find_files(dir: &str, dialect: Dialect, to_json: bool) {
+    match fetch_code_snippets(dir, dialect.code_block_language().to_string()) {
         Ok(snippets) => {
             if to_json {
                 println!(
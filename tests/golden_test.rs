@@ -0,0 +1,163 @@
+#![cfg(feature = "testing")]
+
+use std::path::{Path, PathBuf};
+
+use krafna::libs::testing::assert_snapshot;
+use krafna::{Krafna, KrafnaConfig};
+
+fn vault() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/vault")
+}
+
+fn assert_query_snapshot(name: &str, query: &str) {
+    let engine = Krafna::with_config(KrafnaConfig {
+        use_disk_cache: false,
+        ..KrafnaConfig::default()
+    });
+    let query = query.replace("VAULT", &vault().display().to_string());
+    let (fields, rows) = engine
+        .execute_query(&query, None, None, None)
+        .expect("Query should succeed");
+
+    assert_snapshot(
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/snapshots")
+            .join(format!("{}.snap", name)),
+        &fields,
+        &rows,
+        Some(&vault()),
+    );
+}
+
+#[test]
+fn test_frontmatter_where_order_by() {
+    assert_query_snapshot(
+        "frontmatter_where_order_by",
+        "SELECT title, priority, file.path FROM FRONTMATTER_DATA('VAULT') WHERE 'project' IN tags OR priority > 1 ORDER BY priority DESC",
+    );
+}
+
+#[test]
+fn test_select_all() {
+    assert_query_snapshot("select_all", "SELECT * FROM FRONTMATTER_DATA('VAULT')");
+}
+
+#[test]
+fn test_open_tasks() {
+    assert_query_snapshot(
+        "open_tasks",
+        "SELECT file.name, ord, parent, text FROM MD_TASKS('VAULT') WHERE checked == false",
+    );
+}
+
+#[test]
+fn test_tasks_of_one_file() {
+    assert_query_snapshot(
+        "tasks_of_one_file",
+        "SELECT file.name, ord, text, checked FROM MD_TASKS('VAULT/projects/roadmap.md')",
+    );
+}
+
+#[test]
+fn test_bash_code_blocks() {
+    assert_query_snapshot(
+        "bash_code_blocks",
+        "SELECT file.name, ord, lang, content FROM MD_CODE_BLOCKS('VAULT', 'bash') ORDER BY file.name",
+    );
+}
+
+#[test]
+fn test_paragraphs_mentioning() {
+    assert_query_snapshot(
+        "paragraphs_mentioning",
+        "SELECT file.name, heading, type, text FROM MD_PARAGRAPHS('VAULT') WHERE text REGEXP '(?i)roadmap|release' ORDER BY file.name, ord",
+    );
+}
+
+#[test]
+fn test_csv_hours_per_note() {
+    assert_query_snapshot(
+        "csv_hours_per_note",
+        "SELECT project, SUM(hours) AS hours FROM CSV_DATA('VAULT/exports/time.csv') WHERE project IN (SELECT title FROM FRONTMATTER_DATA('VAULT')) GROUP BY project ORDER BY project",
+    );
+}
+
+#[test]
+fn test_json_open_items() {
+    assert_query_snapshot(
+        "json_open_items",
+        "SELECT content, project, labels FROM JSON_DATA('VAULT/exports/todoist.json', 'items') WHERE checked == false ORDER BY priority DESC",
+    );
+}
+
+#[test]
+fn test_body_tags() {
+    assert_query_snapshot(
+        "body_tags",
+        "SELECT title, tags FROM FRONTMATTER_DATA('VAULT') WHERE 'project/alpha' IN tags",
+    );
+}
+
+#[test]
+fn test_links_of_project_notes() {
+    assert_query_snapshot(
+        "links_of_project_notes",
+        "SELECT file.name, frontmatter.status, url, path FROM MD_LINKS('VAULT') WHERE 'project' IN frontmatter.tags",
+    );
+}
+
+#[test]
+fn test_backlinks() {
+    assert_query_snapshot(
+        "backlinks",
+        "SELECT file.name, backlink_count, backlinks FROM MD_BACKLINKS('VAULT') ORDER BY file.name",
+    );
+}
+
+#[test]
+fn test_canvas() {
+    assert_query_snapshot(
+        "canvas",
+        "SELECT file.name, type, id, text, path, label, group, from, to FROM CANVAS_DATA('VAULT') ORDER BY ord",
+    );
+}
+
+#[test]
+fn test_links() {
+    assert_query_snapshot(
+        "links",
+        "SELECT file.name, text, url, path, external FROM MD_LINKS('VAULT')",
+    );
+}
+
+#[test]
+fn test_most_linked_notes() {
+    assert_query_snapshot(
+        "most_linked_notes",
+        "SELECT path, COUNT(*) AS links FROM MD_LINKS('VAULT') WHERE external == false GROUP BY path ORDER BY links DESC, path",
+    );
+}
+
+#[test]
+fn test_union_inbox() {
+    assert_query_snapshot(
+        "union_inbox",
+        "SELECT file.path, title FROM FRONTMATTER_DATA('VAULT') WHERE 'todo' IN tags UNION ALL SELECT file.path, text FROM MD_TASKS('VAULT') WHERE checked == false",
+    );
+}
+
+#[test]
+fn test_subquery_open_tasks() {
+    assert_query_snapshot(
+        "subquery_open_tasks",
+        "SELECT file.path, title FROM FRONTMATTER_DATA('VAULT') WHERE file.path IN (SELECT file.path FROM MD_TASKS('VAULT') WHERE checked != true) ORDER BY file.path",
+    );
+}
+
+#[test]
+fn test_with_open_tasks_per_file() {
+    assert_query_snapshot(
+        "with_open_tasks_per_file",
+        "WITH open AS (SELECT file.name, text FROM MD_TASKS('VAULT') WHERE checked != true) SELECT file.name, COUNT(*) FROM open GROUP BY file.name ORDER BY file.name",
+    );
+}